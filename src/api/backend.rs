@@ -0,0 +1,354 @@
+//! The torrent client files are served from.
+//!
+//! [`TorrentClient`] is what the filesystem needs from a client: listing
+//! torrents with their stats and pieces, reading file data, and adding,
+//! pausing and removing torrents. [`RqbitClient`] implements it over rqbit's
+//! HTTP API and [`TransmissionClient`] over Transmission's RPC, chosen by
+//! `api_backend`.
+
+use crate::api::client::{create_api_client, RqbitClient};
+use crate::api::memory::MemoryBudget;
+use crate::api::streaming::StreamManagerStats;
+use crate::api::transmission::TransmissionClient;
+use crate::api::types::{
+    AddTorrentResponse, ListTorrentsResult, PieceBitfield, TorrentInfo, TorrentStats,
+};
+use crate::api::{ReadPriority, RetryBudget};
+use crate::config::{ApiBackend, Config};
+use crate::metrics::Metrics;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A torrent client, as the filesystem uses it. Torrents and their files
+/// are identified by the client's numeric ids and file indexes.
+pub trait TorrentClient: Send + Sync {
+    /// Lists the torrents with their files.
+    fn list_torrents(&self) -> BoxFuture<'_, Result<ListTorrentsResult>>;
+
+    /// Lists the torrents, reusing a listing no older than `max_age`.
+    fn list_torrents_newer_than(
+        &self,
+        max_age: Duration,
+    ) -> BoxFuture<'_, Result<ListTorrentsResult>> {
+        let _ = max_age;
+        self.list_torrents()
+    }
+
+    fn get_torrent(&self, id: u64) -> BoxFuture<'_, Result<TorrentInfo>>;
+
+    fn get_torrent_stats(&self, id: u64) -> BoxFuture<'_, Result<TorrentStats>>;
+
+    /// The stats of a torrent as the client reports them.
+    fn get_torrent_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<serde_json::Value>>;
+
+    /// The stats of a torrent's peers as the client reports them.
+    fn get_peer_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<serde_json::Value>>;
+
+    fn get_piece_bitfield(&self, id: u64) -> BoxFuture<'_, Result<PieceBitfield>>;
+
+    /// Whether every piece holding a byte range of the torrent is
+    /// downloaded.
+    fn check_range_available(
+        &self,
+        torrent_id: u64,
+        offset: u64,
+        size: u64,
+        piece_length: u64,
+    ) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            let bitfield = self.get_piece_bitfield(torrent_id).await?;
+            Ok(bitfield.has_piece_range(offset, size, piece_length))
+        })
+    }
+
+    fn add_torrent_magnet<'a>(
+        &'a self,
+        magnet_link: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>>;
+
+    fn add_torrent_url<'a>(
+        &'a self,
+        torrent_url: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>>;
+
+    /// Adds a torrent from the contents of its `.torrent` file.
+    fn add_torrent_file(&self, contents: Bytes) -> BoxFuture<'_, Result<AddTorrentResponse>>;
+
+    fn pause_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>>;
+
+    fn start_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>>;
+
+    /// Removes a torrent, keeping its files.
+    fn forget_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>>;
+
+    /// Removes a torrent and deletes its files.
+    fn delete_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>>;
+
+    /// Whether the client answers.
+    fn health_check(&self) -> BoxFuture<'_, Result<bool>>;
+
+    /// Reads `size` bytes at `offset` of a file, waiting for them to be
+    /// downloaded. Returns less only at the end of the file.
+    fn read_file_streaming(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> BoxFuture<'_, Result<Bytes>>;
+
+    /// Reads like [`read_file_streaming()`](Self::read_file_streaming),
+    /// returning what arrived by `deadline` as a short read.
+    fn read_file_until(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        let _ = deadline;
+        self.read_file_streaming(torrent_id, file_idx, offset, size)
+    }
+
+    /// Reads like [`read_file_streaming()`](Self::read_file_streaming),
+    /// checking the pieces read against the torrent's hashes. Clients that
+    /// only serve pieces they checked themselves read as usual.
+    fn read_file_verified(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        self.read_file_streaming(torrent_id, file_idx, offset, size)
+    }
+
+    /// Reads the inclusive byte `range` of a file, or all of it, once a slot
+    /// of `priority` is free. Clients without read slots read right away.
+    fn read_file_with_priority(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        range: Option<(u64, u64)>,
+        priority: ReadPriority,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        let _ = priority;
+        let (offset, size) = range.map_or((0, usize::MAX), |(start, end)| {
+            (start, end.saturating_sub(start).saturating_add(1) as usize)
+        });
+        self.read_file_streaming(torrent_id, file_idx, offset, size)
+    }
+
+    /// Overrides how far ahead reads of a file opened from now on download
+    /// (`None` restores `readahead_size`).
+    fn set_prefetch_size(&self, torrent_id: u64, file_idx: usize, size: Option<usize>) {
+        let _ = (torrent_id, file_idx, size);
+    }
+
+    /// Closes what is kept open to read a file. Returns whether anything
+    /// was.
+    fn close_stream(&self, torrent_id: u64, file_idx: usize) -> BoxFuture<'_, bool> {
+        let _ = (torrent_id, file_idx);
+        Box::pin(async { false })
+    }
+
+    /// Drops connections and caches, all made again on demand. Returns the
+    /// number of streams closed.
+    fn release_idle_resources(&self) -> BoxFuture<'_, usize> {
+        Box::pin(async { 0 })
+    }
+
+    /// Memory budget of `max_memory_bytes`, shared by the client's buffers
+    /// and the filesystem's caches.
+    fn memory(&self) -> &Arc<MemoryBudget>;
+
+    fn stream_stats(&self) -> BoxFuture<'_, StreamManagerStats> {
+        Box::pin(async { StreamManagerStats::default() })
+    }
+}
+
+impl TorrentClient for RqbitClient {
+    fn list_torrents(&self) -> BoxFuture<'_, Result<ListTorrentsResult>> {
+        Box::pin(RqbitClient::list_torrents(self))
+    }
+
+    fn list_torrents_newer_than(
+        &self,
+        max_age: Duration,
+    ) -> BoxFuture<'_, Result<ListTorrentsResult>> {
+        Box::pin(RqbitClient::list_torrents_newer_than(self, max_age))
+    }
+
+    fn get_torrent(&self, id: u64) -> BoxFuture<'_, Result<TorrentInfo>> {
+        Box::pin(RqbitClient::get_torrent(self, id))
+    }
+
+    fn get_torrent_stats(&self, id: u64) -> BoxFuture<'_, Result<TorrentStats>> {
+        Box::pin(RqbitClient::get_torrent_stats(self, id))
+    }
+
+    fn get_torrent_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<serde_json::Value>> {
+        Box::pin(RqbitClient::get_torrent_stats_raw(self, id))
+    }
+
+    fn get_peer_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<serde_json::Value>> {
+        Box::pin(RqbitClient::get_peer_stats_raw(self, id))
+    }
+
+    fn get_piece_bitfield(&self, id: u64) -> BoxFuture<'_, Result<PieceBitfield>> {
+        Box::pin(RqbitClient::get_piece_bitfield(self, id))
+    }
+
+    fn check_range_available(
+        &self,
+        torrent_id: u64,
+        offset: u64,
+        size: u64,
+        piece_length: u64,
+    ) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(RqbitClient::check_range_available(
+            self,
+            torrent_id,
+            offset,
+            size,
+            piece_length,
+        ))
+    }
+
+    fn add_torrent_magnet<'a>(
+        &'a self,
+        magnet_link: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>> {
+        Box::pin(RqbitClient::add_torrent_magnet(self, magnet_link))
+    }
+
+    fn add_torrent_url<'a>(
+        &'a self,
+        torrent_url: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>> {
+        Box::pin(RqbitClient::add_torrent_url(self, torrent_url))
+    }
+
+    fn add_torrent_file(&self, contents: Bytes) -> BoxFuture<'_, Result<AddTorrentResponse>> {
+        Box::pin(RqbitClient::add_torrent_file(self, contents))
+    }
+
+    fn pause_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(RqbitClient::pause_torrent(self, id))
+    }
+
+    fn start_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(RqbitClient::start_torrent(self, id))
+    }
+
+    fn forget_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(RqbitClient::forget_torrent(self, id))
+    }
+
+    fn delete_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(RqbitClient::delete_torrent(self, id))
+    }
+
+    fn health_check(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(RqbitClient::health_check(self))
+    }
+
+    fn read_file_streaming(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        Box::pin(RqbitClient::read_file_streaming(
+            self, torrent_id, file_idx, offset, size,
+        ))
+    }
+
+    fn read_file_until(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        Box::pin(RqbitClient::read_file_until(
+            self, torrent_id, file_idx, offset, size, deadline,
+        ))
+    }
+
+    fn read_file_verified(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        Box::pin(RqbitClient::read_file_verified(
+            self, torrent_id, file_idx, offset, size,
+        ))
+    }
+
+    fn read_file_with_priority(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        range: Option<(u64, u64)>,
+        priority: ReadPriority,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        Box::pin(RqbitClient::read_file_with_priority(
+            self, torrent_id, file_idx, range, priority,
+        ))
+    }
+
+    fn set_prefetch_size(&self, torrent_id: u64, file_idx: usize, size: Option<usize>) {
+        RqbitClient::set_prefetch_size(self, torrent_id, file_idx, size)
+    }
+
+    fn close_stream(&self, torrent_id: u64, file_idx: usize) -> BoxFuture<'_, bool> {
+        Box::pin(RqbitClient::close_stream(self, torrent_id, file_idx))
+    }
+
+    fn release_idle_resources(&self) -> BoxFuture<'_, usize> {
+        Box::pin(RqbitClient::release_idle_resources(self))
+    }
+
+    fn memory(&self) -> &Arc<MemoryBudget> {
+        RqbitClient::memory(self)
+    }
+
+    fn stream_stats(&self) -> BoxFuture<'_, StreamManagerStats> {
+        Box::pin(RqbitClient::stream_stats(self))
+    }
+}
+
+/// Creates the client of a mount's `api_backend`, with its stream, retry
+/// and login settings.
+pub fn create_client(config: &Config, metrics: Arc<Metrics>) -> Result<Arc<dyn TorrentClient>> {
+    match config.api_backend {
+        ApiBackend::Rqbit => {
+            let mut api_client = create_api_client(
+                &config.api_url,
+                config.api_username.as_deref(),
+                config.api_password.as_deref(),
+                Some(metrics),
+            )
+            .context("API client creation failed")?
+            .with_stream_config(config.stream_config())
+            .with_retry_budget(RetryBudget::new(config.retry_budget))
+            .with_lazy_details_threshold(config.lazy_details_threshold);
+            if let Some(session) = config.session_auth()? {
+                api_client = api_client.with_session(session);
+            }
+            Ok(Arc::new(api_client))
+        }
+        ApiBackend::Transmission => Ok(Arc::new(
+            TransmissionClient::from_config(config).context("API client creation failed")?,
+        )),
+    }
+}
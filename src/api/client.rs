@@ -1,28 +1,59 @@
-use crate::api::streaming::PersistentStreamManager;
+use crate::api::memory::MemoryBudget;
+use crate::api::metainfo::PieceHashes;
+use crate::api::request_id;
+use crate::api::retry::{jittered, RetryBudget};
+use crate::api::scheduler::ReadPriority;
+use crate::api::session::SessionAuth;
+use crate::api::streaming::{PersistentStreamManager, StreamConfig};
 use crate::api::types::*;
 use crate::error::RqbitFuseError;
 use crate::metrics::Metrics;
 use anyhow::{Context, Result};
 use bytes::Bytes;
+use dashmap::DashMap;
 use reqwest::{Client, StatusCode};
 
 use futures::stream::StreamExt;
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::time::sleep;
 use tracing::{debug, error, info, instrument, trace, warn};
 
+/// Returns a client builder using the TLS backend selected by the `rustls`
+/// and `native-tls` features. rustls wins when both are enabled.
+pub(super) fn http_client_builder() -> reqwest::ClientBuilder {
+    let builder = Client::builder();
+    #[cfg(feature = "rustls")]
+    let builder = builder.use_rustls_tls();
+    #[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+    let builder = builder.use_native_tls();
+    builder
+}
+
+/// Torrent details fetched at once while listing torrents.
+const DETAIL_FETCH_CONCURRENCY: usize = 16;
+
 /// HTTP client for interacting with rqbit server
 pub struct RqbitClient {
     client: Client,
     base_url: String,
     max_retries: u32,
     retry_delay: Duration,
+    /// Retries left across all requests of this client.
+    retry_budget: Arc<RetryBudget>,
     stream_manager: PersistentStreamManager,
     auth_credentials: Option<(String, String)>,
+    /// Gateway session used instead of Basic auth.
+    session: Option<Arc<SessionAuth>>,
     list_torrents_cache: Arc<RwLock<Option<(Instant, ListTorrentsResult)>>>,
     list_torrents_cache_ttl: Duration,
+    /// Torrents listed with at least this many files skip the details
+    /// request (0 = never).
+    lazy_details_threshold: usize,
+    /// Piece hashes of torrents read with verification, from their metainfo.
+    piece_hashes: DashMap<u64, Arc<PieceHashes>>,
     metrics: Option<Arc<Metrics>>,
 }
 
@@ -49,19 +80,28 @@ impl RqbitClient {
         metrics: Option<Arc<Metrics>>,
     ) -> Result<Self> {
         // Validate URL at construction time (fail fast on invalid URL)
-        let _ = reqwest::Url::parse(&base_url)
+        let url = reqwest::Url::parse(&base_url)
             .map_err(|e| RqbitFuseError::IoError(format!("Invalid URL: {}", e)))?;
+        if url.scheme() == "https" && cfg!(not(any(feature = "rustls", feature = "native-tls"))) {
+            return Err(RqbitFuseError::InvalidArgument(format!(
+                "{} needs TLS, but rqbit-fuse was built without the `rustls` or `native-tls` feature",
+                base_url
+            ))
+            .into());
+        }
 
-        let client = Client::builder()
+        let client = http_client_builder()
             .timeout(Duration::from_secs(60))
             .pool_max_idle_per_host(10)
             .build()
             .map_err(|e| RqbitFuseError::IoError(format!("Failed to create HTTP client: {}", e)))?;
 
-        let stream_manager = PersistentStreamManager::new(
+        let stream_manager = PersistentStreamManager::with_config(
             client.clone(),
             base_url.clone(),
             auth_credentials.clone(),
+            StreamConfig::default(),
+            metrics.clone(),
         );
 
         Ok(Self {
@@ -69,16 +109,61 @@ impl RqbitClient {
             base_url,
             max_retries,
             retry_delay,
+            retry_budget: Arc::new(RetryBudget::default()),
             stream_manager,
             auth_credentials,
+            session: None,
             list_torrents_cache: Arc::new(RwLock::new(None)),
             list_torrents_cache_ttl: Duration::from_secs(30),
+            lazy_details_threshold: 0,
+            piece_hashes: DashMap::new(),
             metrics,
         })
     }
 
-    fn create_auth_header(&self) -> Option<String> {
-        super::create_auth_header(self.auth_credentials.as_ref())
+    /// Replace the persistent stream settings (idle timeout, stream limit, ...)
+    pub fn with_stream_config(mut self, config: StreamConfig) -> Self {
+        self.stream_manager = PersistentStreamManager::with_config(
+            self.client.clone(),
+            self.base_url.clone(),
+            self.auth_credentials.clone(),
+            config,
+            self.metrics.clone(),
+        );
+        self.stream_manager.set_session(self.session.clone());
+        self
+    }
+
+    /// Authenticate through a gateway's login form and session cookies
+    /// instead of Basic auth.
+    pub fn with_session(mut self, session: SessionAuth) -> Self {
+        let session = Arc::new(session);
+        self.auth_credentials = None;
+        self.stream_manager.set_session(Some(Arc::clone(&session)));
+        self.session = Some(session);
+        self
+    }
+
+    /// Replace the retry budget shared by all requests (see `RetryBudget`).
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Arc::new(budget);
+        self
+    }
+
+    /// List torrents the server reports with at least `threshold` files
+    /// from their summary alone, leaving the file list to `get_torrent`
+    /// (0 = always fetch details).
+    pub fn with_lazy_details_threshold(mut self, threshold: usize) -> Self {
+        self.lazy_details_threshold = threshold;
+        self
+    }
+
+    /// Adds the credentials to a request.
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request.headers(super::auth_headers(
+            self.auth_credentials.as_ref(),
+            self.session.as_deref(),
+        ))
     }
 
     async fn invalidate_list_torrents_cache(&self) {
@@ -94,6 +179,30 @@ impl RqbitClient {
         endpoint: &str,
         operation: F,
     ) -> Result<reqwest::Response>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+    {
+        let Some(session) = &self.session else {
+            return self.execute_with_backoff(endpoint, &operation).await;
+        };
+        session.ensure_login().await?;
+        let cookie = session.cookie();
+        let response = self.execute_with_backoff(endpoint, &operation).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        // The session expired; the operation picks up the new cookie
+        debug!("{}: session rejected, logging in again", endpoint);
+        session.refresh(cookie.as_deref()).await?;
+        self.execute_with_backoff(endpoint, &operation).await
+    }
+
+    async fn execute_with_backoff<F, Fut>(
+        &self,
+        endpoint: &str,
+        operation: F,
+    ) -> Result<reqwest::Response>
     where
         F: Fn() -> Fut,
         Fut: std::future::Future<Output = reqwest::Result<reqwest::Response>>,
@@ -107,11 +216,20 @@ impl RqbitClient {
                     let should_retry = (status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS)
                         && attempt < self.max_retries;
 
-                    if should_retry {
+                    if should_retry && self.retry_budget.try_withdraw() {
                         warn!("{}: {} error, retry {}/{}", endpoint, status.as_u16(), attempt + 1, self.max_retries);
-                        sleep(self.retry_delay * (attempt + 1)).await;
+                        sleep(jittered(self.retry_delay * (attempt + 1))).await;
                         continue;
                     }
+                    if should_retry {
+                        debug!(
+                            "{}: {} error, retry budget exhausted",
+                            endpoint,
+                            status.as_u16()
+                        );
+                    } else if status.is_success() {
+                        self.retry_budget.deposit();
+                    }
 
                     return Ok(response);
                 }
@@ -119,9 +237,12 @@ impl RqbitClient {
                     let api_error: RqbitFuseError = e.into();
                     last_error = Some(api_error.clone());
 
-                    if api_error.is_transient() && attempt < self.max_retries {
+                    if api_error.is_transient()
+                        && attempt < self.max_retries
+                        && self.retry_budget.try_withdraw()
+                    {
                         warn!("{}: retry {}/{}: {}", endpoint, attempt + 1, self.max_retries, api_error);
-                        sleep(self.retry_delay * (attempt + 1)).await;
+                        sleep(jittered(self.retry_delay * (attempt + 1))).await;
                     } else {
                         return Err(api_error.into());
                     }
@@ -176,11 +297,7 @@ impl RqbitClient {
     ) -> Result<T> {
         let response = self
             .execute_with_retry(endpoint, || {
-                let mut req = self.client.get(url);
-                if let Some(auth_header) = self.create_auth_header() {
-                    req = req.header("Authorization", auth_header);
-                }
-                req.send()
+                self.authorize(request_id::tag(self.client.get(url))).send()
             })
             .await?;
         let response = self.check_response(response).await?;
@@ -195,11 +312,8 @@ impl RqbitClient {
     ) -> Result<T> {
         let response = self
             .execute_with_retry(endpoint, || {
-                let mut req = self.client.post(url).json(body);
-                if let Some(auth_header) = self.create_auth_header() {
-                    req = req.header("Authorization", auth_header);
-                }
-                req.send()
+                self.authorize(request_id::tag(self.client.post(url).json(body)))
+                    .send()
             })
             .await?;
         let response = self.check_response(response).await?;
@@ -208,11 +322,18 @@ impl RqbitClient {
 
     #[instrument(skip(self), fields(api_op = "list_torrents"))]
     pub async fn list_torrents(&self) -> Result<ListTorrentsResult> {
+        self.list_torrents_newer_than(self.list_torrents_cache_ttl)
+            .await
+    }
+
+    /// Like [`list_torrents`](Self::list_torrents), but only reuses a cached
+    /// list younger than `max_age`.
+    pub async fn list_torrents_newer_than(&self, max_age: Duration) -> Result<ListTorrentsResult> {
         // Check cache first
         {
             let cache = self.list_torrents_cache.read().await;
             if let Some((cached_at, cached_result)) = cache.as_ref() {
-                if cached_at.elapsed() < self.list_torrents_cache_ttl {
+                if cached_at.elapsed() < max_age.min(self.list_torrents_cache_ttl) {
                     debug!("list_torrents: cache hit");
                     if let Some(metrics) = &self.metrics {
                         metrics.record_cache_hit();
@@ -227,29 +348,26 @@ impl RqbitClient {
             metrics.record_cache_miss();
         }
         debug!("list_torrents: cache miss or expired, fetching fresh data");
-        let url = format!("{}/torrents", self.base_url);
-
-        let response = self
-            .execute_with_retry("/torrents", || {
-                let mut req = self.client.get(&url);
-                if let Some(auth_header) = self.create_auth_header() {
-                    req = req.header("Authorization", auth_header);
-                }
-                req.send()
-            })
-            .await?;
+        let summaries = self.list_torrent_summaries().await?;
 
-        let response = self.check_response(response).await?;
-        let data: TorrentListResponse = response.json().await?;
-
-        // Fetch full details for each torrent since /torrents doesn't include files
+        // Fetch full details for each torrent since /torrents doesn't include
+        // files, several at a time, keeping the list order
         let mut result = ListTorrentsResult {
-            torrents: Vec::with_capacity(data.torrents.len()),
+            torrents: Vec::with_capacity(summaries.len()),
             errors: Vec::new(),
         };
 
-        for basic_info in data.torrents {
-            match self.get_torrent(basic_info.id).await {
+        let mut details = futures::stream::iter(summaries)
+            .map(|basic_info| async move {
+                let details = match self.summary_only(&basic_info) {
+                    Some(info) => Ok(info),
+                    None => self.get_torrent(basic_info.id).await,
+                };
+                (basic_info, details)
+            })
+            .buffered(DETAIL_FETCH_CONCURRENCY);
+        while let Some((basic_info, details)) = details.next().await {
+            match details {
                 Ok(full_info) => {
                     result.torrents.push(full_info);
                 }
@@ -293,6 +411,64 @@ impl RqbitClient {
         Ok(result)
     }
 
+    /// A torrent with too many files to fetch its details at discovery,
+    /// built from its list entry when that reports the file count and size.
+    fn summary_only(&self, summary: &TorrentSummary) -> Option<TorrentInfo> {
+        let file_count = summary.file_count?;
+        if self.lazy_details_threshold == 0 || file_count < self.lazy_details_threshold {
+            return None;
+        }
+        Some(TorrentInfo {
+            id: summary.id,
+            info_hash: summary.info_hash.clone(),
+            name: summary.name.clone(),
+            output_folder: summary.output_folder.clone(),
+            file_count: Some(file_count),
+            files: Vec::new(),
+            piece_length: None,
+            tags: None,
+            deferred_size: Some(summary.total_bytes?),
+        })
+    }
+
+    /// Fetches every page of `/torrents`. A response without `total` or
+    /// `next_cursor` is the whole list.
+    async fn list_torrent_summaries(&self) -> Result<Vec<TorrentSummary>> {
+        let base_url = format!("{}/torrents", self.base_url);
+        let mut url = base_url.clone();
+        let mut torrents = Vec::new();
+        let mut seen = HashSet::new();
+        let mut received = 0u64;
+
+        loop {
+            let page: TorrentListResponse = self.get_json("/torrents", &url).await?;
+            let page_len = page.torrents.len();
+            received += page_len as u64;
+
+            // Offset pages shift when torrents are added or removed meanwhile
+            let before = torrents.len();
+            torrents.extend(page.torrents.into_iter().filter(|t| seen.insert(t.id)));
+            if torrents.len() == before {
+                break;
+            }
+
+            let next = match (page.next_cursor, page.total) {
+                (Some(cursor), _) if !cursor.is_empty() => vec![("cursor", cursor)],
+                (_, Some(total)) if received < total => vec![
+                    ("offset", received.to_string()),
+                    ("limit", page_len.to_string()),
+                ],
+                _ => break,
+            };
+            url = reqwest::Url::parse_with_params(&base_url, &next)
+                .context("invalid torrent list URL")?
+                .to_string();
+            debug!(url = %url, received = received, "Fetching next torrent list page");
+        }
+
+        Ok(torrents)
+    }
+
     #[instrument(skip(self), fields(api_op = "get_torrent", id))]
     pub async fn get_torrent(&self, id: u64) -> Result<TorrentInfo> {
         let url = format!("{}/torrents/{}", self.base_url, id);
@@ -351,6 +527,29 @@ impl RqbitClient {
         Ok(result)
     }
 
+    /// Adds a torrent from the contents of a `.torrent` file.
+    #[instrument(skip(self, contents), fields(api_op = "add_torrent_file", len = contents.len()))]
+    pub async fn add_torrent_file(&self, contents: Bytes) -> Result<AddTorrentResponse> {
+        let url = format!("{}/torrents", self.base_url);
+
+        trace!("Adding torrent from a torrent file");
+
+        let response = self
+            .execute_with_retry("/torrents", || {
+                let request = self
+                    .client
+                    .post(&url)
+                    .header("Content-Type", "application/x-bittorrent")
+                    .body(contents.clone());
+                self.authorize(request_id::tag(request)).send()
+            })
+            .await?;
+        let result: AddTorrentResponse = self.check_response(response).await?.json().await?;
+        debug!(api_op = "add_torrent_file", id = result.id, info_hash = %result.info_hash);
+        self.invalidate_list_torrents_cache().await;
+        Ok(result)
+    }
+
     #[instrument(skip(self), fields(api_op = "get_torrent_stats", id))]
     pub async fn get_torrent_stats(&self, id: u64) -> Result<TorrentStats> {
         let url = format!("{}/torrents/{}/stats/v1", self.base_url, id);
@@ -380,6 +579,40 @@ impl RqbitClient {
         }
     }
 
+    /// Returns the stats of a torrent as rqbit sent them, including the
+    /// fields [`TorrentStats`] doesn't model.
+    #[instrument(skip(self), fields(api_op = "get_torrent_stats_raw", id))]
+    pub async fn get_torrent_stats_raw(&self, id: u64) -> Result<serde_json::Value> {
+        let url = format!("{}/torrents/{}/stats/v1", self.base_url, id);
+        let endpoint = format!("/torrents/{}/stats", id);
+
+        self.get_json(&endpoint, &url)
+            .await
+            .map_err(|e| match e.downcast_ref::<RqbitFuseError>() {
+                Some(RqbitFuseError::ApiError { status: 404, .. }) => {
+                    RqbitFuseError::NotFound(format!("torrent {}", id)).into()
+                }
+                _ => e,
+            })
+    }
+
+    /// Returns the stats of a torrent's live peers as rqbit sent them,
+    /// keyed by peer address.
+    #[instrument(skip(self), fields(api_op = "get_peer_stats_raw", id))]
+    pub async fn get_peer_stats_raw(&self, id: u64) -> Result<serde_json::Value> {
+        let url = format!("{}/torrents/{}/peer_stats", self.base_url, id);
+        let endpoint = format!("/torrents/{}/peer_stats", id);
+
+        self.get_json(&endpoint, &url)
+            .await
+            .map_err(|e| match e.downcast_ref::<RqbitFuseError>() {
+                Some(RqbitFuseError::ApiError { status: 404, .. }) => {
+                    RqbitFuseError::NotFound(format!("torrent {}", id)).into()
+                }
+                _ => e,
+            })
+    }
+
     #[instrument(skip(self), fields(api_op = "get_piece_bitfield", id))]
     pub async fn get_piece_bitfield(&self, id: u64) -> Result<PieceBitfield> {
         let url = format!("{}/torrents/{}/haves", self.base_url, id);
@@ -387,14 +620,9 @@ impl RqbitClient {
 
         let response = self
             .execute_with_retry(&endpoint, || {
-                let mut req = self
-                    .client
-                    .get(&url)
+                let req = request_id::tag(self.client.get(&url))
                     .header("Accept", "application/octet-stream");
-                if let Some(auth_header) = self.create_auth_header() {
-                    req = req.header("Authorization", auth_header);
-                }
-                req.send()
+                self.authorize(req).send()
             })
             .await?;
 
@@ -420,6 +648,38 @@ impl RqbitClient {
         }
     }
 
+    /// Fetch the `.torrent` metainfo file of a torrent.
+    #[instrument(skip(self), fields(api_op = "get_metainfo", id))]
+    pub async fn get_metainfo(&self, id: u64) -> Result<Bytes> {
+        let url = format!("{}/torrents/{}/metadata", self.base_url, id);
+        let endpoint = format!("/torrents/{}/metadata", id);
+
+        let response = self
+            .execute_with_retry(&endpoint, || {
+                self.authorize(request_id::tag(self.client.get(&url)))
+                    .send()
+            })
+            .await?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND => {
+                Err(RqbitFuseError::NotFound(format!("torrent {}", id)).into())
+            }
+            _ => Ok(self.check_response(response).await?.bytes().await?),
+        }
+    }
+
+    /// Piece hashes of a torrent, fetched once and kept until it is forgotten.
+    async fn piece_hashes(&self, id: u64) -> Result<Arc<PieceHashes>> {
+        if let Some(hashes) = self.piece_hashes.get(&id) {
+            return Ok(Arc::clone(&hashes));
+        }
+        let metainfo = self.get_metainfo(id).await?;
+        let hashes = Arc::new(PieceHashes::parse(&metainfo)?);
+        self.piece_hashes.insert(id, Arc::clone(&hashes));
+        Ok(hashes)
+    }
+
     /// Check if a byte range is fully available (all pieces downloaded).
     #[instrument(
         skip(self),
@@ -453,25 +713,40 @@ impl RqbitClient {
     // File Operations
     // =========================================================================
 
-    #[instrument(skip(self), fields(api_op = "read_file", torrent_id, file_idx, range = ?range))]
+    /// Reads a file range an application is waiting for.
     pub async fn read_file(
         &self,
         torrent_id: u64,
         file_idx: usize,
         range: Option<(u64, u64)>,
     ) -> Result<Bytes> {
+        self.read_file_with_priority(torrent_id, file_idx, range, ReadPriority::Interactive)
+            .await
+    }
+
+    /// Reads a file range once a slot of the given priority is free.
+    /// Background reads yield to reads applications are waiting for.
+    #[instrument(skip(self), fields(api_op = "read_file", torrent_id, file_idx, range = ?range))]
+    pub async fn read_file_with_priority(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        range: Option<(u64, u64)>,
+        priority: ReadPriority,
+    ) -> Result<Bytes> {
+        let _permit = self.stream_manager.scheduler().acquire(priority).await;
+        let _in_flight = self
+            .stream_manager
+            .memory()
+            .charge(range.map_or(0, |(start, end)| end.saturating_sub(start) + 1));
+
         let url = format!(
             "{}/torrents/{}/stream/{}",
             self.base_url, torrent_id, file_idx
         );
         let endpoint = format!("/torrents/{}/stream/{}", torrent_id, file_idx);
 
-        let mut request = self.client.get(&url);
-
-        // Add Authorization header if credentials are configured
-        if let Some(auth_header) = self.create_auth_header() {
-            request = request.header("Authorization", auth_header);
-        }
+        let mut request = request_id::tag(self.client.get(&url));
 
         // Add Range header if specified
         if let Some((start, end)) = range {
@@ -497,7 +772,7 @@ impl RqbitClient {
             .execute_with_retry(&endpoint, move || {
                 // This unwrap is safe because we validated the request can be cloned above.
                 // GET requests with no body can always be cloned.
-                request.try_clone().unwrap().send()
+                self.authorize(request.try_clone().unwrap()).send()
             })
             .await?;
 
@@ -538,8 +813,9 @@ impl RqbitClient {
                 let response = self.check_response(response).await?;
 
                 // Stream the response and apply byte limit if needed
+                // Chunks are kept as slices and joined once at the end
                 let mut stream = response.bytes_stream();
-                let mut result = Vec::new();
+                let mut parts: Vec<Bytes> = Vec::new();
                 let mut total_read = 0usize;
                 // If server returned full file for a range request, we need to:
                 // 1. Skip bytes to reach the requested start offset
@@ -563,19 +839,19 @@ impl RqbitClient {
                             continue;
                         } else {
                             // Partial skip - take remaining bytes from this chunk
-                            let remaining = &chunk[bytes_to_skip..];
+                            let remaining = chunk.slice(bytes_to_skip..);
                             bytes_to_skip = 0;
 
                             if let Some(limit) = limit {
                                 let to_take = remaining.len().min(limit.saturating_sub(total_read));
-                                result.extend_from_slice(&remaining[..to_take]);
+                                parts.push(remaining.slice(..to_take));
                                 total_read += to_take;
                                 if total_read >= limit {
                                     break;
                                 }
                             } else {
-                                result.extend_from_slice(remaining);
                                 total_read += remaining.len();
+                                parts.push(remaining);
                             }
                             continue;
                         }
@@ -588,15 +864,15 @@ impl RqbitClient {
                             break;
                         }
                         let to_take = chunk.len().min(remaining);
-                        result.extend_from_slice(&chunk[..to_take]);
+                        parts.push(chunk.slice(..to_take));
                         total_read += to_take;
                     } else {
-                        result.extend_from_slice(&chunk);
                         total_read += chunk.len();
+                        parts.push(chunk);
                     }
                 }
 
-                Ok(Bytes::from(result))
+                Ok(crate::api::streaming::concat(parts))
             }
         }
     }
@@ -618,6 +894,86 @@ impl RqbitClient {
             .await
     }
 
+    /// Read file data like `read_file_streaming`, returning what arrived by
+    /// `deadline` as a short read. Fails with `TimedOut` if nothing did.
+    #[instrument(
+        skip(self),
+        fields(api_op = "read_file_until", torrent_id, file_idx, offset, size)
+    )]
+    pub async fn read_file_until(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> Result<Bytes> {
+        self.stream_manager
+            .read_until(torrent_id, file_idx, offset, size, deadline)
+            .await
+    }
+
+    /// Read file data like `read_file_streaming`, widened to whole pieces
+    /// whose hashes are checked against the torrent's metainfo. On a
+    /// mismatch the file's stream and its buffered data are dropped.
+    ///
+    /// Returns the data from `offset` to the end of the last piece read,
+    /// which may be more than `size` bytes.
+    #[instrument(
+        skip(self),
+        fields(api_op = "read_file_verified", torrent_id, file_idx, offset, size)
+    )]
+    pub async fn read_file_verified(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> Result<Bytes> {
+        let hashes = self.piece_hashes(torrent_id).await?;
+        let (start, end) = hashes.piece_aligned(file_idx, offset, size as u64);
+        let data = self
+            .stream_manager
+            .read(torrent_id, file_idx, start, (end - start) as usize)
+            .await?;
+
+        if let Err(e) = hashes.verify(file_idx, start, &data) {
+            warn!(torrent_id, file_idx, error = %e, "Discarding data that failed piece verification");
+            self.stream_manager.close_stream(torrent_id, file_idx).await;
+            return Err(e.into());
+        }
+
+        let skip = ((offset - start) as usize).min(data.len());
+        Ok(data.slice(skip..))
+    }
+
+    /// Overrides how far ahead streams of a file opened from now on download
+    /// (`None` restores `readahead_size`)
+    pub fn set_prefetch_size(&self, torrent_id: u64, file_idx: usize, size: Option<usize>) {
+        self.stream_manager
+            .set_prefetch_size(torrent_id, file_idx, size);
+    }
+
+    /// Close the persistent stream for a file, if one is open
+    pub async fn close_stream(&self, torrent_id: u64, file_idx: usize) -> bool {
+        self.stream_manager.close_stream(torrent_id, file_idx).await
+    }
+
+    /// Close every persistent stream and drop the cached torrent list and
+    /// piece hashes, all fetched again on demand. Returns the number of
+    /// streams closed.
+    pub async fn release_idle_resources(&self) -> usize {
+        self.invalidate_list_torrents_cache().await;
+        self.piece_hashes.clear();
+        self.stream_manager.close_all().await
+    }
+
+    /// Memory budget of `max_memory_bytes`, shared by streams, reads and
+    /// the filesystem's caches
+    pub fn memory(&self) -> &Arc<MemoryBudget> {
+        self.stream_manager.memory()
+    }
+
     /// Get statistics about the persistent stream manager
     pub async fn stream_stats(&self) -> crate::api::streaming::StreamManagerStats {
         self.stream_manager.stats().await
@@ -635,7 +991,10 @@ impl RqbitClient {
         trace!("Executing {} on torrent {}", action, id);
 
         let response = self
-            .execute_with_retry(&endpoint, || self.client.post(&url).send())
+            .execute_with_retry(&endpoint, || {
+                self.authorize(request_id::tag(self.client.post(&url)))
+                    .send()
+            })
             .await?;
 
         match response.status() {
@@ -671,11 +1030,13 @@ impl RqbitClient {
 
     /// Remove torrent from session (keep files)
     pub async fn forget_torrent(&self, id: u64) -> Result<()> {
+        self.piece_hashes.remove(&id);
         self.torrent_action(id, "forget").await
     }
 
     /// Remove torrent from session and delete files
     pub async fn delete_torrent(&self, id: u64) -> Result<()> {
+        self.piece_hashes.remove(&id);
         self.torrent_action(id, "delete").await
     }
 
@@ -685,7 +1046,7 @@ impl RqbitClient {
         let url = format!("{}/torrents", self.base_url);
 
         // Use a shorter timeout for health checks (5 seconds)
-        let health_client = Client::builder()
+        let health_client = http_client_builder()
             .timeout(Duration::from_secs(5))
             .pool_max_idle_per_host(1)
             .build()
@@ -776,6 +1137,17 @@ pub fn create_api_client(
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_https_needs_tls_backend() {
+        let client = RqbitClient::new("https://seedbox.example.com:3030".to_string());
+        let has_tls = cfg!(any(feature = "rustls", feature = "native-tls"));
+        assert_eq!(client.is_ok(), has_tls);
+        if let Err(e) = client {
+            assert!(e.to_string().contains("without the `rustls`"), "{}", e);
+        }
+        assert!(RqbitClient::new("http://127.0.0.1:3030".to_string()).is_ok());
+    }
+
     #[test]
     fn test_piece_bitfield() {
         // Create bitfield with pieces 0, 1, 3 downloaded (binary: 1011)
@@ -901,7 +1273,7 @@ mod tests {
     // Mocked HTTP Response Tests
     // =========================================================================
 
-    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::matchers::{body_bytes, body_json, header, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[tokio::test]
@@ -965,6 +1337,57 @@ mod tests {
         mock_server.verify().await;
     }
 
+    #[tokio::test]
+    async fn test_session_is_renewed_when_rejected() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(204).insert_header("Set-Cookie", "sid=1"))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(204).insert_header("Set-Cookie", "sid=2"))
+            .mount(&mock_server)
+            .await;
+        // The gateway has expired the first session
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .and(header("cookie", "sid=2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": 1,
+                "info_hash": "abc123",
+                "name": "Test Torrent",
+                "output_folder": "/downloads",
+                "files": []
+            })))
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let session = SessionAuth::new(
+            format!("{}/login", mock_server.uri()),
+            "alice".to_string(),
+            "secret".to_string(),
+            None,
+        )
+        .unwrap();
+        let client = create_api_client(&mock_server.uri(), Some("alice"), Some("secret"), None)
+            .unwrap()
+            .with_session(session);
+
+        let torrent = client.get_torrent(1).await.unwrap();
+        assert_eq!(torrent.name, "Test Torrent");
+        mock_server.verify().await;
+    }
+
     #[tokio::test]
     async fn test_list_torrents_empty() {
         let mock_server = MockServer::start().await;
@@ -987,6 +1410,180 @@ mod tests {
         mock_server.verify().await;
     }
 
+    async fn mount_torrent_details(mock_server: &MockServer, ids: std::ops::RangeInclusive<u64>) {
+        for id in ids {
+            Mock::given(method("GET"))
+                .and(path(format!("/torrents/{}", id)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "id": id,
+                    "info_hash": format!("hash{}", id),
+                    "name": format!("Torrent {}", id),
+                    "output_folder": "/downloads",
+                    "files": [],
+                    "piece_length": 1048576
+                })))
+                .mount(mock_server)
+                .await;
+        }
+    }
+
+    fn summaries(ids: std::ops::RangeInclusive<u64>) -> Vec<serde_json::Value> {
+        ids.map(|id| {
+            serde_json::json!({
+                "id": id,
+                "info_hash": format!("hash{}", id),
+                "name": format!("Torrent {}", id),
+                "output_folder": "/downloads"
+            })
+        })
+        .collect()
+    }
+
+    #[tokio::test]
+    async fn test_list_torrents_fetches_details_concurrently() {
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"torrents": summaries(1..=32)})),
+            )
+            .mount(&mock_server)
+            .await;
+        for id in 1..=32u64 {
+            Mock::given(method("GET"))
+                .and(path(format!("/torrents/{}", id)))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(summaries(id..=id)[0].clone())
+                        .set_delay(Duration::from_millis(200)),
+                )
+                .mount(&mock_server)
+                .await;
+        }
+
+        // 6.4s one at a time
+        let started = Instant::now();
+        let result = client.list_torrents().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(3));
+        let ids: Vec<u64> = result.torrents.iter().map(|t| t.id).collect();
+        assert_eq!(ids, (1..=32).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_large_torrents_listed_from_summary() {
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri())
+            .unwrap()
+            .with_lazy_details_threshold(1000);
+
+        let mut large = summaries(1..=1)[0].clone();
+        large["file_count"] = 50000.into();
+        large["total_bytes"] = 123456789.into();
+        let mut small = summaries(2..=2)[0].clone();
+        small["file_count"] = 3.into();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"torrents": [large, small]})),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        mount_torrent_details(&mock_server, 2..=2).await;
+
+        let result = client.list_torrents().await.unwrap();
+        assert!(result.errors.is_empty());
+        assert_eq!(result.torrents[0].file_count, Some(50000));
+        assert_eq!(result.torrents[0].deferred_size, Some(123456789));
+        assert!(result.torrents[0].files.is_empty());
+        assert_eq!(result.torrents[1].deferred_size, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_torrents_offset_pagination() {
+        use wiremock::matchers::{query_param, query_param_is_missing};
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .and(query_param_is_missing("offset"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"torrents": summaries(1..=2), "total": 5})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .and(query_param("offset", "2"))
+            .and(query_param("limit", "2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"torrents": summaries(3..=4), "total": 5})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Overlaps the previous page, as if a torrent was removed meanwhile
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .and(query_param("offset", "4"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"torrents": summaries(4..=5), "total": 5})),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        mount_torrent_details(&mock_server, 1..=5).await;
+
+        let result = client.list_torrents().await.unwrap();
+        let ids: Vec<u64> = result.torrents.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        mock_server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_torrents_cursor_pagination() {
+        use wiremock::matchers::{query_param, query_param_is_missing};
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .and(query_param_is_missing("cursor"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(
+                    serde_json::json!({"torrents": summaries(1..=2), "next": "page 2"}),
+                ),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .and(query_param("cursor", "page 2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({"torrents": summaries(3..=3), "next_cursor": null}),
+            ))
+            .mount(&mock_server)
+            .await;
+        mount_torrent_details(&mock_server, 1..=3).await;
+
+        let result = client.list_torrents().await.unwrap();
+        assert_eq!(result.torrents.len(), 3);
+    }
+
     #[tokio::test]
     async fn test_list_torrents_partial_failure() {
         let mock_server = MockServer::start().await;
@@ -1080,6 +1677,28 @@ mod tests {
         assert_eq!(torrent.name, "Test Torrent");
     }
 
+    #[tokio::test]
+    async fn test_requests_carry_trace_id() {
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .and(header(request_id::HEADER, "0123456789abcdef"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![1u8; 10]))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let data = request_id::scope(
+            "0123456789abcdef".to_string(),
+            client.read_file(1, 0, Some((0, 9))),
+        )
+        .await
+        .unwrap();
+        assert_eq!(data.len(), 10);
+    }
+
     #[tokio::test]
     async fn test_get_torrent_not_found() {
         let mock_server = MockServer::start().await;
@@ -1157,6 +1776,30 @@ mod tests {
         assert_eq!(result.info_hash, "def456");
     }
 
+    #[tokio::test]
+    async fn test_add_torrent_file_success() {
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("POST"))
+            .and(path("/torrents"))
+            .and(header("content-type", "application/x-bittorrent"))
+            .and(body_bytes(b"d4:infodee".to_vec()))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "id": 44, "info_hash": "fed789" })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let result = client
+            .add_torrent_file(Bytes::from_static(b"d4:infodee"))
+            .await
+            .unwrap();
+        assert_eq!(result.id, 44);
+        assert_eq!(result.info_hash, "fed789");
+    }
+
     #[tokio::test]
     async fn test_get_torrent_stats_success() {
         let mock_server = MockServer::start().await;
@@ -1234,6 +1877,33 @@ mod tests {
         assert!(stats.live.is_none());
     }
 
+    #[tokio::test]
+    async fn test_get_peer_stats_raw() {
+        let mock_server = MockServer::start().await;
+        let client = RqbitClient::new(mock_server.uri()).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/peer_stats"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "peers": {"10.0.0.2:6881": {"state": "live", "counters": {"fetched_bytes": 4096}}}
+            })))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/2/peer_stats"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let peers = client.get_peer_stats_raw(1).await.unwrap();
+        assert_eq!(peers["peers"]["10.0.0.2:6881"]["state"], "live");
+        let err = client.get_peer_stats_raw(2).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RqbitFuseError>(),
+            Some(RqbitFuseError::NotFound(_))
+        ));
+    }
+
     #[tokio::test]
     async fn test_get_piece_bitfield_success() {
         let mock_server = MockServer::start().await;
@@ -1522,6 +2192,27 @@ mod tests {
         assert!(result.torrents.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_retry_budget_shared_across_requests() {
+        let mock_server = MockServer::start().await;
+        let client =
+            RqbitClient::with_config(mock_server.uri(), 3, Duration::from_millis(10), None, None)
+                .unwrap()
+                .with_retry_budget(RetryBudget::new(2));
+
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(4)
+            .mount(&mock_server)
+            .await;
+
+        // The first request spends the whole budget on two retries, the
+        // second fails without retrying
+        assert!(client.get_torrent(1).await.is_err());
+        assert!(client.get_torrent(1).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_api_error_response() {
         let mock_server = MockServer::start().await;
@@ -0,0 +1,171 @@
+//! Accounting of the memory held by stream buffers, read-ahead caches and
+//! in-flight reads against `max_memory_bytes`, so a small device sheds
+//! buffers instead of being OOM-killed.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Bytes held by buffers and reads, against an optional cap.
+///
+/// Charges never fail: the cap is enforced by sizing optional buffers to the
+/// [`headroom`](Self::headroom) and shrinking caches once usage passes the
+/// high-water mark, while reads an application waits on always proceed.
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    /// 0 = no cap.
+    limit: u64,
+    used: AtomicU64,
+    /// Whether the system is short of memory, whatever the usage
+    squeezed: AtomicBool,
+}
+
+/// Bytes charged to a [`MemoryBudget`]; released on drop.
+#[derive(Debug)]
+pub struct MemoryCharge {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    /// A budget of `limit` bytes (0 = unlimited).
+    pub fn new(limit: u64) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            used: AtomicU64::new(0),
+            squeezed: AtomicBool::new(false),
+        })
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Bytes left below the cap; `u64::MAX` without one, 0 while squeezed.
+    pub fn headroom(&self) -> u64 {
+        if self.is_squeezed() {
+            return 0;
+        }
+        if self.limit == 0 {
+            return u64::MAX;
+        }
+        self.limit.saturating_sub(self.used())
+    }
+
+    /// Whether usage passed seven eighths of the cap, or the budget is
+    /// squeezed: caches shrink and new readahead is throttled.
+    pub fn under_pressure(&self) -> bool {
+        self.is_squeezed() || (self.limit > 0 && self.used() >= self.limit - self.limit / 8)
+    }
+
+    pub fn is_squeezed(&self) -> bool {
+        self.squeezed.load(Ordering::Relaxed)
+    }
+
+    /// Marks the system as short of memory, e.g. from pressure stall
+    /// information, so the budget acts as if full. Returns the previous
+    /// state.
+    pub fn set_squeezed(&self, squeezed: bool) -> bool {
+        self.squeezed.swap(squeezed, Ordering::Relaxed)
+    }
+
+    /// Adds `bytes` to the usage, even past the cap.
+    pub fn reserve(&self, bytes: u64) {
+        self.used.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Removes `bytes` added by [`reserve`](Self::reserve).
+    pub fn release(&self, bytes: u64) {
+        let _ = self
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                Some(used.saturating_sub(bytes))
+            });
+    }
+
+    /// Charges `bytes`, even past the cap.
+    pub fn charge(self: &Arc<Self>, bytes: u64) -> MemoryCharge {
+        self.reserve(bytes);
+        MemoryCharge {
+            budget: Arc::clone(self),
+            bytes,
+        }
+    }
+
+    /// Charges as much of `bytes` as fits below the cap.
+    pub fn charge_up_to(self: &Arc<Self>, bytes: u64) -> MemoryCharge {
+        let mut charge = self.charge(0);
+        charge.resize_up_to(bytes);
+        charge
+    }
+}
+
+impl MemoryCharge {
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Changes the charge to `bytes`, even past the cap.
+    pub fn resize(&mut self, bytes: u64) {
+        if bytes > self.bytes {
+            self.budget.reserve(bytes - self.bytes);
+        } else {
+            self.budget.release(self.bytes - bytes);
+        }
+        self.bytes = bytes;
+    }
+
+    /// Changes the charge to `bytes`, growing only as far as the cap allows.
+    /// Returns the new charge.
+    pub fn resize_up_to(&mut self, bytes: u64) -> u64 {
+        let allowed = if bytes > self.bytes {
+            self.bytes + (bytes - self.bytes).min(self.budget.headroom())
+        } else {
+            bytes
+        };
+        self.resize(allowed);
+        self.bytes
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charges_stop_at_the_cap() {
+        let budget = MemoryBudget::new(1000);
+        let held = budget.charge(600);
+        assert!(!budget.under_pressure());
+
+        let mut window = budget.charge_up_to(800);
+        assert_eq!(window.bytes(), 400);
+        assert_eq!(budget.headroom(), 0);
+        assert!(budget.under_pressure());
+
+        drop(held);
+        assert_eq!(window.resize_up_to(500), 500);
+        assert_eq!(window.resize_up_to(100), 100);
+        assert_eq!(budget.used(), 100);
+        drop(window);
+        assert_eq!(budget.used(), 0);
+
+        let unlimited = MemoryBudget::new(0);
+        assert_eq!(unlimited.charge_up_to(1 << 40).bytes(), 1 << 40);
+        assert!(!unlimited.under_pressure());
+
+        // Squeezed, even an uncapped budget grants nothing
+        unlimited.set_squeezed(true);
+        assert!(unlimited.under_pressure());
+        assert_eq!(unlimited.charge_up_to(100).bytes(), 0);
+    }
+}
@@ -0,0 +1,475 @@
+//! Piece hashes from a torrent's metainfo, used to verify data read over HTTP.
+
+use crate::error::RqbitFuseError;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+/// BitTorrent v2 hashes files in blocks of this size.
+const V2_BLOCK_SIZE: usize = 16 * 1024;
+
+/// Deepest nesting accepted in a metainfo file, so a hostile one can't
+/// overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// A decoded bencode value borrowing from the metainfo bytes.
+#[derive(Debug)]
+enum Value<'a> {
+    Int(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Dict(BTreeMap<&'a [u8], Value<'a>>),
+}
+
+impl<'a> Value<'a> {
+    fn get(&self, key: &str) -> Option<&Value<'a>> {
+        match self {
+            Value::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn get_bytes(&self, key: &[u8]) -> Option<&'a [u8]> {
+        match self {
+            Value::Dict(dict) => dict.get(key).and_then(Value::as_bytes),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Int(n) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(reason: &str) -> RqbitFuseError {
+    RqbitFuseError::ParseError(format!("invalid metainfo: {}", reason))
+}
+
+/// Decodes the bencode value starting at `pos`, returning it and the
+/// position after it.
+fn decode(input: &[u8], pos: usize, depth: usize) -> Result<(Value<'_>, usize), RqbitFuseError> {
+    if depth > MAX_DEPTH {
+        return Err(invalid("nested too deeply"));
+    }
+    match input.get(pos) {
+        Some(b'i') => {
+            let end = find(input, pos + 1, b'e')?;
+            let n = parse_int(&input[pos + 1..end])?;
+            Ok((Value::Int(n), end + 1))
+        }
+        Some(b'l') => {
+            let mut items = Vec::new();
+            let mut pos = pos + 1;
+            while input.get(pos) != Some(&b'e') {
+                let (item, next) = decode(input, pos, depth + 1)?;
+                items.push(item);
+                pos = next;
+            }
+            Ok((Value::List(items), pos + 1))
+        }
+        Some(b'd') => {
+            let mut dict = BTreeMap::new();
+            let mut pos = pos + 1;
+            while input.get(pos) != Some(&b'e') {
+                let (key, next) = decode(input, pos, depth + 1)?;
+                let key = key
+                    .as_bytes()
+                    .ok_or_else(|| invalid("dictionary key is not a string"))?;
+                let (value, next) = decode(input, next, depth + 1)?;
+                dict.insert(key, value);
+                pos = next;
+            }
+            Ok((Value::Dict(dict), pos + 1))
+        }
+        Some(b'0'..=b'9') => {
+            let colon = find(input, pos, b':')?;
+            let len = usize::try_from(parse_int(&input[pos..colon])?)
+                .map_err(|_| invalid("negative string length"))?;
+            let start = colon + 1;
+            let end = start
+                .checked_add(len)
+                .filter(|&end| end <= input.len())
+                .ok_or_else(|| invalid("string runs past the end"))?;
+            Ok((Value::Bytes(&input[start..end]), end))
+        }
+        Some(_) => Err(invalid("unexpected byte")),
+        None => Err(invalid("truncated")),
+    }
+}
+
+fn find(input: &[u8], from: usize, byte: u8) -> Result<usize, RqbitFuseError> {
+    input[from.min(input.len())..]
+        .iter()
+        .position(|&b| b == byte)
+        .map(|i| from + i)
+        .ok_or_else(|| invalid("truncated"))
+}
+
+fn parse_int(digits: &[u8]) -> Result<i64, RqbitFuseError> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| invalid("bad integer"))
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Root of the v2 merkle tree over `data`'s 16KiB blocks, padded with zero
+/// hashes to `leaves` (a power of two).
+fn merkle_root(data: &[u8], leaves: usize) -> [u8; 32] {
+    let mut layer: Vec<[u8; 32]> = data.chunks(V2_BLOCK_SIZE).map(|b| sha256(&[b])).collect();
+    layer.resize(leaves.max(1), [0; 32]);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| sha256(&[&pair[0], &pair[1]]))
+            .collect();
+    }
+    layer[0]
+}
+
+/// A file of a v2 torrent with its piece hashes.
+#[derive(Debug)]
+struct V2File {
+    length: u64,
+    /// The file's piece layer, or just its pieces root when it fits in one
+    /// piece.
+    hashes: Vec<[u8; 32]>,
+}
+
+#[derive(Debug)]
+enum Layout {
+    /// v1: SHA-1 of pieces laid over the concatenated files, which may span
+    /// file boundaries.
+    V1 {
+        pieces: Vec<[u8; 20]>,
+        /// Start offset in the torrent and length of each file.
+        files: Vec<(u64, u64)>,
+        total_length: u64,
+    },
+    /// v2: SHA-256 merkle hashes of pieces aligned to each file.
+    V2 { files: Vec<V2File> },
+}
+
+/// Piece hashes of a torrent and the layout needed to locate each piece
+/// within its files.
+#[derive(Debug)]
+pub struct PieceHashes {
+    piece_length: u64,
+    layout: Layout,
+}
+
+impl PieceHashes {
+    /// Parses the piece hashes of a `.torrent` file. v1 hashes are used
+    /// when present (hybrid torrents), since rqbit indexes files in v1
+    /// order.
+    pub fn parse(torrent: &[u8]) -> Result<Self, RqbitFuseError> {
+        let (root, _) = decode(torrent, 0, 0)?;
+        let info = root.get("info").ok_or_else(|| invalid("missing info"))?;
+        let piece_length = info
+            .get("piece length")
+            .and_then(Value::as_u64)
+            .filter(|&len| len > 0)
+            .ok_or_else(|| invalid("missing piece length"))?;
+
+        let layout = match info.get("pieces").and_then(Value::as_bytes) {
+            Some(pieces) => Self::parse_v1(info, pieces)?,
+            None => Self::parse_v2(&root, info, piece_length)?,
+        };
+        Ok(Self {
+            piece_length,
+            layout,
+        })
+    }
+
+    fn parse_v1(info: &Value<'_>, pieces: &[u8]) -> Result<Layout, RqbitFuseError> {
+        if !pieces.len().is_multiple_of(20) {
+            return Err(invalid("pieces is not a multiple of 20 bytes"));
+        }
+        let lengths = match info.get("files") {
+            Some(Value::List(files)) => files
+                .iter()
+                .map(|file| file.get("length").and_then(Value::as_u64))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid("file without length"))?,
+            _ => vec![info
+                .get("length")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| invalid("missing length"))?],
+        };
+
+        let mut files = Vec::with_capacity(lengths.len());
+        let mut total_length = 0u64;
+        for length in lengths {
+            files.push((total_length, length));
+            total_length = total_length.saturating_add(length);
+        }
+        Ok(Layout::V1 {
+            pieces: pieces
+                .chunks_exact(20)
+                .map(|hash| hash.try_into().expect("20-byte chunk"))
+                .collect(),
+            files,
+            total_length,
+        })
+    }
+
+    fn parse_v2(
+        root: &Value<'_>,
+        info: &Value<'_>,
+        piece_length: u64,
+    ) -> Result<Layout, RqbitFuseError> {
+        if !piece_length.is_power_of_two() || piece_length < V2_BLOCK_SIZE as u64 {
+            return Err(invalid(
+                "v2 piece length must be a power of two of at least 16KiB",
+            ));
+        }
+        let tree = info
+            .get("file tree")
+            .ok_or_else(|| invalid("missing pieces and file tree"))?;
+        let layers = root.get("piece layers");
+
+        let mut files = Vec::new();
+        let mut stack = vec![tree];
+        while let Some(node) = stack.pop() {
+            let Value::Dict(children) = node else {
+                return Err(invalid("file tree entry is not a dictionary"));
+            };
+            if let Some(file) = children.get(&b""[..]) {
+                let length = file
+                    .get("length")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| invalid("file without length"))?;
+                let hashes = match file.get("pieces root").and_then(Value::as_bytes) {
+                    _ if length == 0 => Vec::new(),
+                    Some(pieces_root) if length <= piece_length => vec![hash32(pieces_root)?],
+                    Some(pieces_root) => layers
+                        .and_then(|layers| layers.get_bytes(pieces_root))
+                        .ok_or_else(|| invalid("missing piece layer"))?
+                        .chunks(32)
+                        .map(hash32)
+                        .collect::<Result<_, _>>()?,
+                    None => return Err(invalid("file without pieces root")),
+                };
+                files.push(V2File { length, hashes });
+                continue;
+            }
+            // Visit children in key order, as they were bencoded
+            stack.extend(children.values().rev());
+        }
+        Ok(Layout::V2 { files })
+    }
+
+    /// The range of file `file_index`, widened from `offset..offset + len`
+    /// to whole pieces as far as they lie within the file.
+    pub fn piece_aligned(&self, file_index: usize, offset: u64, len: u64) -> (u64, u64) {
+        let end = offset.saturating_add(len);
+        let (file_start, file_length) = match &self.layout {
+            Layout::V1 { files, .. } => match files.get(file_index) {
+                Some(&file) => file,
+                None => return (offset, end),
+            },
+            Layout::V2 { files } => match files.get(file_index) {
+                Some(file) => (0, file.length),
+                None => return (offset, end),
+            },
+        };
+        let pl = self.piece_length;
+        let start = (file_start + offset) / pl * pl;
+        let aligned_end = (file_start + end).div_ceil(pl).saturating_mul(pl);
+        (
+            start.max(file_start) - file_start,
+            aligned_end.min(file_start + file_length) - file_start,
+        )
+    }
+
+    /// Checks every piece lying entirely within `data`, read from `offset`
+    /// of file `file_index`. Pieces only partly covered are skipped.
+    pub fn verify(
+        &self,
+        file_index: usize,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(), RqbitFuseError> {
+        let pl = self.piece_length;
+        match &self.layout {
+            Layout::V1 {
+                pieces,
+                files,
+                total_length,
+            } => {
+                let Some(&(file_start, _)) = files.get(file_index) else {
+                    return Ok(());
+                };
+                let start = file_start + offset;
+                let end = start + data.len() as u64;
+                let mut piece = start.div_ceil(pl);
+                while piece * pl < *total_length {
+                    let piece_start = piece * pl;
+                    let piece_end = (piece_start + pl).min(*total_length);
+                    if piece_end > end {
+                        break;
+                    }
+                    let bytes = &data[(piece_start - start) as usize..(piece_end - start) as usize];
+                    let expected = pieces
+                        .get(piece as usize)
+                        .ok_or_else(|| invalid("too few pieces"))?;
+                    if Sha1::digest(bytes).as_slice() != expected {
+                        return Err(mismatch(piece, file_index));
+                    }
+                    piece += 1;
+                }
+            }
+            Layout::V2 { files } => {
+                let Some(file) = files.get(file_index) else {
+                    return Ok(());
+                };
+                let end = offset + data.len() as u64;
+                let mut piece = offset.div_ceil(pl);
+                while piece * pl < file.length {
+                    let piece_start = piece * pl;
+                    let piece_end = (piece_start + pl).min(file.length);
+                    if piece_end > end {
+                        break;
+                    }
+                    let bytes =
+                        &data[(piece_start - offset) as usize..(piece_end - offset) as usize];
+                    // A file of one piece is hashed up to its own size only
+                    let leaves = if file.length <= pl {
+                        bytes.len().div_ceil(V2_BLOCK_SIZE).next_power_of_two()
+                    } else {
+                        (pl as usize) / V2_BLOCK_SIZE
+                    };
+                    let expected = file
+                        .hashes
+                        .get(piece as usize)
+                        .ok_or_else(|| invalid("too few pieces"))?;
+                    if &merkle_root(bytes, leaves) != expected {
+                        return Err(mismatch(piece, file_index));
+                    }
+                    piece += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn hash32(bytes: &[u8]) -> Result<[u8; 32], RqbitFuseError> {
+    bytes
+        .try_into()
+        .map_err(|_| invalid("SHA-256 hash is not 32 bytes"))
+}
+
+fn mismatch(piece: u64, file_index: usize) -> RqbitFuseError {
+    RqbitFuseError::IoError(format!(
+        "piece {} of file {} failed hash verification",
+        piece, file_index
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bstr(s: &[u8]) -> Vec<u8> {
+        let mut out = format!("{}:", s.len()).into_bytes();
+        out.extend_from_slice(s);
+        out
+    }
+
+    /// A v1 torrent of two files, `a` (5 bytes) and `b` (7 bytes), in
+    /// 4-byte pieces.
+    fn v1_torrent(content: &[u8]) -> Vec<u8> {
+        let pieces: Vec<u8> = content
+            .chunks(4)
+            .flat_map(|p| Sha1::digest(p).to_vec())
+            .collect();
+        let mut t = b"d4:infod5:filesl".to_vec();
+        t.extend_from_slice(b"d6:lengthi5e4:pathl1:aeed6:lengthi7e4:pathl1:beee");
+        t.extend_from_slice(b"4:name1:t12:piece lengthi4e6:pieces");
+        t.extend(bstr(&pieces));
+        t.extend_from_slice(b"ee");
+        t
+    }
+
+    #[test]
+    fn test_v1_verifies_whole_pieces() {
+        let content = b"aaaaabbbbbbb";
+        let hashes = PieceHashes::parse(&v1_torrent(content)).unwrap();
+
+        // File b starts at torrent offset 5, inside piece 1
+        assert_eq!(hashes.piece_aligned(1, 0, 1), (0, 3));
+        assert_eq!(hashes.piece_aligned(1, 4, 1), (3, 7));
+        assert!(hashes.verify(1, 3, b"bbbb").is_ok());
+        assert!(hashes.verify(1, 3, b"bxbb").is_err());
+        // Piece 1 spans both files and can't be checked from file b alone
+        assert!(hashes.verify(1, 0, b"xxx").is_ok());
+        assert!(hashes.verify(0, 0, b"aaaa").is_ok());
+        assert!(hashes.verify(0, 0, b"aaab").is_err());
+    }
+
+    #[test]
+    fn test_v2_verifies_merkle_pieces() {
+        let piece_length = 2 * V2_BLOCK_SIZE;
+        let big: Vec<u8> = (0..piece_length + 100).map(|i| i as u8).collect();
+        let small = b"small file".to_vec();
+
+        let layer = [
+            merkle_root(&big[..piece_length], 2),
+            merkle_root(&big[piece_length..], 2),
+        ]
+        .concat();
+        let big_root = sha256(&[&layer[..32], &layer[32..]]);
+        let small_root = merkle_root(&small, 1);
+
+        let mut t = b"d4:infod9:file treed".to_vec();
+        for (name, len, root) in [("a", big.len(), big_root), ("b", small.len(), small_root)] {
+            t.extend(bstr(name.as_bytes()));
+            t.extend_from_slice(format!("d0:d6:lengthi{}e11:pieces root", len).as_bytes());
+            t.extend(bstr(&root));
+            t.extend_from_slice(b"ee");
+        }
+        t.extend_from_slice(
+            format!("e12:piece lengthi{}ee12:piece layersd", piece_length).as_bytes(),
+        );
+        t.extend(bstr(&big_root));
+        t.extend(bstr(&layer));
+        t.extend_from_slice(b"ee");
+
+        let hashes = PieceHashes::parse(&t).unwrap();
+        assert!(hashes.verify(0, 0, &big).is_ok());
+        assert!(hashes
+            .verify(0, piece_length as u64, &big[piece_length..])
+            .is_ok());
+        assert!(hashes.verify(1, 0, &small).is_ok());
+
+        let mut corrupt = big.clone();
+        corrupt[piece_length + 1] ^= 1;
+        assert!(hashes.verify(0, 0, &corrupt).is_err());
+        assert!(hashes.verify(0, 0, &corrupt[..piece_length]).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_malformed_metainfo() {
+        assert!(PieceHashes::parse(b"d4:info").is_err());
+        assert!(PieceHashes::parse(b"d4:infod6:lengthi1eee").is_err());
+        assert!(PieceHashes::parse(&[b'l'; 1000]).is_err());
+    }
+}
@@ -1,12 +1,25 @@
-//! HTTP client for rqbit API and torrent streaming.
+//! Clients of the torrent client files are served from: rqbit's HTTP API,
+//! with torrent streaming, or Transmission's RPC.
 
 use base64::Engine;
 
+pub mod backend;
 pub mod client;
+pub mod memory;
+pub mod metainfo;
+pub mod request_id;
+pub mod retry;
+pub mod scheduler;
+pub mod session;
 pub mod streaming;
+pub mod transmission;
 pub mod types;
 
+pub use backend::{create_client, TorrentClient};
 pub use client::create_api_client;
+pub use retry::RetryBudget;
+pub use scheduler::{ReadPriority, ReadScheduler};
+pub use session::SessionAuth;
 pub use streaming::{PersistentStreamManager, StreamManagerStats};
 pub use types::{ListTorrentsResult, TorrentInfo, TorrentSummary};
 
@@ -21,3 +34,22 @@ pub fn create_auth_header(auth_credentials: Option<&(String, String)>) -> Option
         format!("Basic {}", encoded)
     })
 }
+
+/// Credentials of a request: the Basic auth header, or the cookie of a
+/// gateway session once logged in.
+pub(crate) fn auth_headers(
+    auth_credentials: Option<&(String, String)>,
+    session: Option<&SessionAuth>,
+) -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) = create_auth_header(auth_credentials).and_then(|v| v.parse().ok()) {
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+    if let Some(value) = session
+        .and_then(SessionAuth::cookie)
+        .and_then(|v| v.parse().ok())
+    {
+        headers.insert(reqwest::header::COOKIE, value);
+    }
+    headers
+}
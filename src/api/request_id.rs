@@ -0,0 +1,118 @@
+//! Trace ids following a FUSE operation through the worker queue and the
+//! HTTP requests it makes, sent to rqbit as `X-Request-Id`.
+
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// Header carrying the trace id on requests to rqbit.
+pub const HEADER: &str = "X-Request-Id";
+
+tokio::task_local! {
+    static TASK_ID: String;
+}
+
+thread_local! {
+    static THREAD_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Returns a new trace id: a random per-process prefix and a counter, so
+/// ids stay unique across restarts writing to the same logs.
+pub fn generate() -> String {
+    static PREFIX: OnceLock<u32> = OnceLock::new();
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let prefix = PREFIX.get_or_init(|| RandomState::new().build_hasher().finish() as u32);
+    format!(
+        "{:08x}{:08x}",
+        prefix,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// The trace id of the running task or, outside of one, of the FUSE
+/// operation on this thread.
+pub fn current() -> Option<String> {
+    TASK_ID
+        .try_with(Clone::clone)
+        .ok()
+        .or_else(|| THREAD_ID.with(|id| id.borrow().clone()))
+}
+
+/// Starts a trace for the FUSE operation `op` running on this thread.
+///
+/// Log lines are tagged with the id through a `fuse_op` span until the
+/// returned guard is dropped, and requests handed to the worker carry it.
+pub fn begin(op: &'static str) -> TraceGuard {
+    let id = generate();
+    let span = tracing::info_span!("fuse_op", op, trace_id = %id).entered();
+    let previous = THREAD_ID.with(|current| current.borrow_mut().replace(id));
+    TraceGuard {
+        previous,
+        _span: span,
+    }
+}
+
+/// Ends the trace started by [`begin`] when dropped.
+pub struct TraceGuard {
+    previous: Option<String>,
+    _span: tracing::span::EnteredSpan,
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        THREAD_ID.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Runs `future` with `id` as its trace id.
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    TASK_ID.scope(id, future).await
+}
+
+/// Adds the current trace id, if any, to a request.
+pub fn tag(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current() {
+        Some(id) => request.header(HEADER, id),
+        None => request,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_unique() {
+        let a = generate();
+        let b = generate();
+        assert_ne!(a, b);
+        assert_eq!(a.len(), 16);
+        assert_eq!(a[..8], b[..8]);
+    }
+
+    #[test]
+    fn test_begin_sets_thread_id_until_dropped() {
+        assert_eq!(current(), None);
+        {
+            let _outer = begin("read");
+            let outer = current().unwrap();
+            {
+                let _inner = begin("read");
+                assert_ne!(current().unwrap(), outer);
+            }
+            assert_eq!(current().unwrap(), outer);
+        }
+        assert_eq!(current(), None);
+    }
+
+    #[tokio::test]
+    async fn test_scope_sets_task_id() {
+        let id = scope("abc".to_string(), async { current() }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+        assert_eq!(current(), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! Retry budget shared by every request of a client, so an outage doesn't
+//! multiply into a retry storm once hundreds of reads are failing at once.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Tokens are counted in thousandths, so successes can earn fractions.
+const SCALE: u64 = 1000;
+
+/// Fraction of a retry earned back by each successful request.
+const REFILL_PER_SUCCESS: u64 = SCALE / 10;
+
+/// A token bucket limiting retries across all in-flight requests.
+///
+/// Each retry spends a token and each successful request earns back a tenth
+/// of one, so in steady state retries add at most about 10% to the load on
+/// rqbit. While rqbit is down the bucket drains after `capacity` retries and
+/// further failures are returned immediately instead of each request
+/// running its own backoff.
+#[derive(Debug)]
+pub struct RetryBudget {
+    capacity: u64,
+    balance: AtomicU64,
+}
+
+impl RetryBudget {
+    /// Creates a full budget of `capacity` retries (0 = never retry).
+    pub fn new(capacity: u32) -> Self {
+        let capacity = capacity as u64 * SCALE;
+        Self {
+            capacity,
+            balance: AtomicU64::new(capacity),
+        }
+    }
+
+    /// Spends a token for a retry. Returns false when the budget is empty.
+    pub fn try_withdraw(&self) -> bool {
+        self.balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |balance| {
+                balance.checked_sub(SCALE)
+            })
+            .is_ok()
+    }
+
+    /// Earns back part of a token for a successful request.
+    pub fn deposit(&self) {
+        let _ = self
+            .balance
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |balance| {
+                (balance < self.capacity).then(|| (balance + REFILL_PER_SUCCESS).min(self.capacity))
+            });
+    }
+
+    /// Retries currently available.
+    pub fn available(&self) -> f64 {
+        self.balance.load(Ordering::Acquire) as f64 / SCALE as f64
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+/// `delay` plus up to half of it again at random, so requests that failed
+/// together don't all retry at the same instant.
+pub fn jittered(delay: Duration) -> Duration {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default(),
+    );
+    let fraction = (hasher.finish() % 1000) as u32;
+    delay + delay / 2 * fraction / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_drains_and_refills() {
+        let budget = RetryBudget::new(2);
+        assert!(budget.try_withdraw());
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+
+        // Ten successes earn back one retry
+        for _ in 0..10 {
+            budget.deposit();
+        }
+        assert!(budget.try_withdraw());
+        assert!(!budget.try_withdraw());
+
+        // Never more than the capacity
+        for _ in 0..100 {
+            budget.deposit();
+        }
+        assert_eq!(budget.available(), 2.0);
+    }
+
+    #[test]
+    fn test_zero_budget_never_retries() {
+        let budget = RetryBudget::new(0);
+        budget.deposit();
+        assert!(!budget.try_withdraw());
+    }
+
+    #[test]
+    fn test_jitter_stays_within_half_the_delay() {
+        let delay = Duration::from_millis(100);
+        for _ in 0..100 {
+            let jittered = jittered(delay);
+            assert!(jittered >= delay && jittered <= delay * 3 / 2);
+        }
+    }
+}
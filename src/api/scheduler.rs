@@ -0,0 +1,178 @@
+//! Admission of upstream reads by priority, so cache warming never holds up
+//! reads an application is waiting for.
+
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Who a read is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReadPriority {
+    /// A read an application is blocked on.
+    Interactive,
+    /// Prefetch and readahead that only warms caches.
+    Background,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    running: usize,
+    interactive_waiting: usize,
+}
+
+/// Limits concurrent upstream reads, admitting interactive reads first.
+///
+/// Background reads wait while any interactive read is queued and never take
+/// the last free slot, so an interactive read doesn't queue behind a batch of
+/// prefetches.
+#[derive(Debug)]
+pub struct ReadScheduler {
+    max_concurrent: usize,
+    state: Mutex<State>,
+    released: Notify,
+}
+
+/// A slot held for one read; released on drop.
+#[derive(Debug)]
+pub struct ReadPermit {
+    scheduler: Arc<ReadScheduler>,
+}
+
+/// Counts a queued interactive read until it is admitted or cancelled.
+struct Waiting<'a>(&'a ReadScheduler);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().interactive_waiting -= 1;
+        self.0.released.notify_waiters();
+    }
+}
+
+impl ReadScheduler {
+    /// Creates a scheduler admitting up to `max_concurrent` reads (at least one).
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent: max_concurrent.max(1),
+            state: Mutex::new(State::default()),
+            released: Notify::new(),
+        }
+    }
+
+    /// Waits for a slot. Queued interactive reads are admitted before any
+    /// background read.
+    pub async fn acquire(self: &Arc<Self>, priority: ReadPriority) -> ReadPermit {
+        let mut waiting = None;
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            // Register before checking, so a release in between isn't missed
+            released.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if self.admits(&state, priority) {
+                    state.running += 1;
+                    if let Some(waiting) = waiting.take() {
+                        std::mem::forget(waiting);
+                        state.interactive_waiting -= 1;
+                    }
+                    return ReadPermit {
+                        scheduler: Arc::clone(self),
+                    };
+                }
+                if priority == ReadPriority::Interactive && waiting.is_none() {
+                    state.interactive_waiting += 1;
+                    waiting = Some(Waiting(self));
+                }
+            }
+            released.await;
+        }
+    }
+
+    fn admits(&self, state: &State, priority: ReadPriority) -> bool {
+        match priority {
+            ReadPriority::Interactive => state.running < self.max_concurrent,
+            ReadPriority::Background => {
+                let limit = (self.max_concurrent - 1).max(1);
+                state.interactive_waiting == 0 && state.running < limit
+            }
+        }
+    }
+
+    /// Number of reads currently admitted.
+    pub fn running(&self) -> usize {
+        self.state.lock().unwrap().running
+    }
+
+    /// Number of interactive reads waiting for a slot.
+    pub fn interactive_waiting(&self) -> usize {
+        self.state.lock().unwrap().interactive_waiting
+    }
+}
+
+impl Drop for ReadPermit {
+    fn drop(&mut self) {
+        self.scheduler.state.lock().unwrap().running -= 1;
+        self.scheduler.released.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    async fn pending<F: std::future::Future>(future: F) -> bool {
+        tokio::time::timeout(Duration::from_millis(20), future)
+            .await
+            .is_err()
+    }
+
+    #[tokio::test]
+    async fn test_background_leaves_a_slot_for_interactive() {
+        let scheduler = Arc::new(ReadScheduler::new(2));
+        let _background = scheduler.acquire(ReadPriority::Background).await;
+        assert!(pending(scheduler.acquire(ReadPriority::Background)).await);
+
+        let _interactive = scheduler.acquire(ReadPriority::Interactive).await;
+        assert_eq!(scheduler.running(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_queued_interactive_read_goes_first() {
+        let scheduler = Arc::new(ReadScheduler::new(1));
+        let held = scheduler.acquire(ReadPriority::Interactive).await;
+
+        let interactive = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move { scheduler.acquire(ReadPriority::Interactive).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(scheduler.interactive_waiting(), 1);
+        let background = tokio::spawn({
+            let scheduler = Arc::clone(&scheduler);
+            async move { scheduler.acquire(ReadPriority::Background).await }
+        });
+
+        drop(held);
+        let permit = interactive.await.unwrap();
+        assert_eq!(scheduler.interactive_waiting(), 0);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!background.is_finished());
+
+        drop(permit);
+        background.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_interactive_read_releases_background() {
+        let scheduler = Arc::new(ReadScheduler::new(2));
+        let held: Vec<_> = vec![
+            scheduler.acquire(ReadPriority::Interactive).await,
+            scheduler.acquire(ReadPriority::Interactive).await,
+        ];
+        assert!(pending(scheduler.acquire(ReadPriority::Interactive)).await);
+        assert_eq!(scheduler.interactive_waiting(), 0);
+
+        drop(held);
+        let _background = scheduler.acquire(ReadPriority::Background).await;
+    }
+}
@@ -0,0 +1,229 @@
+//! Session cookies for rqbit behind an authenticating gateway.
+//!
+//! Some deployments put rqbit behind a proxy that accepts a login form and
+//! answers with a session cookie instead of checking Basic auth on every
+//! request. [`SessionAuth`] posts the credentials to the login URL, sends
+//! the cookies it got with every request, and logs in again when a request
+//! is rejected with 401. The cookies can be kept in a file so a restart
+//! reuses the session instead of logging in again.
+
+use crate::error::RqbitFuseError;
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderValue, SET_COOKIE};
+use reqwest::Client;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Logs in to a gateway and holds the session cookies it issued.
+#[derive(Debug)]
+pub struct SessionAuth {
+    login_url: String,
+    username: String,
+    password: String,
+    /// File the cookies are kept in across restarts.
+    cookie_jar: Option<PathBuf>,
+    /// `Cookie` header of the current session.
+    cookie: RwLock<Option<String>>,
+    /// One login at a time; the others wait for its cookies.
+    login: tokio::sync::Mutex<()>,
+    /// Doesn't follow redirects, which carry the cookies of a login form.
+    client: Client,
+}
+
+impl SessionAuth {
+    /// Creates a session, reusing the cookies saved in `cookie_jar`.
+    pub fn new(
+        login_url: String,
+        username: String,
+        password: String,
+        cookie_jar: Option<PathBuf>,
+    ) -> Result<Self> {
+        let client = super::client::http_client_builder()
+            .timeout(Duration::from_secs(30))
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| RqbitFuseError::IoError(format!("Failed to create HTTP client: {}", e)))?;
+        let cookie = cookie_jar.as_ref().and_then(|path| {
+            let saved = std::fs::read_to_string(path).ok()?;
+            let cookie = saved.lines().collect::<Vec<_>>().join("; ");
+            (!cookie.is_empty()).then_some(cookie)
+        });
+        Ok(Self {
+            login_url,
+            username,
+            password,
+            cookie_jar,
+            cookie: RwLock::new(cookie),
+            login: tokio::sync::Mutex::new(()),
+            client,
+        })
+    }
+
+    /// The `Cookie` header to send, once logged in.
+    pub fn cookie(&self) -> Option<String> {
+        self.cookie.read().unwrap().clone()
+    }
+
+    /// Logs in unless a session is already open.
+    pub async fn ensure_login(&self) -> Result<()> {
+        if self.cookie().is_some() {
+            return Ok(());
+        }
+        self.refresh(None).await
+    }
+
+    /// Logs in again after a request sent with `stale` was rejected. Does
+    /// nothing if another request has logged in since.
+    pub async fn refresh(&self, stale: Option<&str>) -> Result<()> {
+        let _login = self.login.lock().await;
+        let current = self.cookie();
+        if current.is_some() && current.as_deref() != stale {
+            return Ok(());
+        }
+
+        let response = self
+            .client
+            .post(&self.login_url)
+            .form(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(RqbitFuseError::from)
+            .with_context(|| format!("login to {} failed", self.login_url))?;
+        let status = response.status();
+        let cookies: Vec<String> = response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(cookie_pair)
+            .collect();
+        if !(status.is_success() || status.is_redirection()) || cookies.is_empty() {
+            *self.cookie.write().unwrap() = None;
+            return Err(RqbitFuseError::PermissionDenied(format!(
+                "login to {} failed: HTTP {}{}",
+                self.login_url,
+                status,
+                if cookies.is_empty() {
+                    ", no session cookie"
+                } else {
+                    ""
+                }
+            ))
+            .into());
+        }
+
+        info!("Logged in to {}", self.login_url);
+        *self.cookie.write().unwrap() = Some(cookies.join("; "));
+        if let Some(path) = &self.cookie_jar {
+            if let Err(e) = save_cookies(path, &cookies) {
+                warn!(
+                    "Failed to save session cookies to {}: {:#}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `name=value` of a `Set-Cookie` header, without its attributes.
+fn cookie_pair(header: &HeaderValue) -> Option<String> {
+    let pair = header.to_str().ok()?.split(';').next()?.trim();
+    pair.contains('=').then(|| pair.to_string())
+}
+
+/// Writes the cookies one per line, readable by the owner only.
+fn save_cookies(path: &std::path::Path, cookies: &[String]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    for cookie in cookies {
+        writeln!(file, "{}", cookie)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_login_saves_and_reuses_cookies() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .and(body_string_contains("username=alice"))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("Location", "/")
+                    .append_header("Set-Cookie", "sid=abc; Path=/; HttpOnly")
+                    .append_header("Set-Cookie", "csrf=x1"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let jar = dir.path().join("cookies");
+        let login_url = format!("{}/login", server.uri());
+        let session = SessionAuth::new(
+            login_url.clone(),
+            "alice".to_string(),
+            "secret".to_string(),
+            Some(jar.clone()),
+        )
+        .unwrap();
+        assert_eq!(session.cookie(), None);
+        session.ensure_login().await.unwrap();
+        assert_eq!(session.cookie().as_deref(), Some("sid=abc; csrf=x1"));
+        // Already logged in, and a refresh for an older cookie is a no-op
+        session.ensure_login().await.unwrap();
+        session.refresh(Some("sid=old")).await.unwrap();
+
+        let restarted = SessionAuth::new(
+            login_url,
+            "alice".to_string(),
+            "secret".to_string(),
+            Some(jar),
+        )
+        .unwrap();
+        assert_eq!(restarted.cookie().as_deref(), Some("sid=abc; csrf=x1"));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_login() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&server)
+            .await;
+
+        let session = SessionAuth::new(
+            format!("{}/login", server.uri()),
+            "alice".to_string(),
+            "wrong".to_string(),
+            None,
+        )
+        .unwrap();
+        let err = session.ensure_login().await.unwrap_err();
+        assert!(err.to_string().contains("HTTP 403"), "{}", err);
+        assert_eq!(session.cookie(), None);
+    }
+}
@@ -1,19 +1,79 @@
+use crate::api::memory::{MemoryBudget, MemoryCharge};
+use crate::api::request_id;
+use crate::api::scheduler::{ReadPriority, ReadScheduler};
+use crate::api::session::SessionAuth;
 use crate::error::RqbitFuseError;
+use crate::metrics::Metrics;
 use anyhow::{Context, Result};
 use bytes::{Bytes, BytesMut};
+use dashmap::DashMap;
 use futures::stream::StreamExt;
 use reqwest::{Client, StatusCode};
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::task::JoinHandle;
 use tracing::{debug, trace};
 
 const MAX_SEEK_FORWARD: u64 = 10 * 1024 * 1024; // 10MB
-const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(10);
 const SKIP_YIELD_INTERVAL: u64 = 1024 * 1024; // 1MB
+/// Consecutive non-sequential reads after which access counts as random
+const RANDOM_ACCESS_SEEKS: u32 = 3;
+/// Smallest prefetch window chosen from measured rates
+const MIN_AUTO_PREFETCH: usize = 1024 * 1024; // 1MB
+/// Longest gap between reads counted towards the consumption rate, so a
+/// paused player doesn't shrink its window
+const MAX_READ_GAP: Duration = Duration::from_secs(5);
+/// Measured seconds after which older rate samples lose half their weight
+const RATE_WINDOW_SECS: f64 = 10.0;
+/// Measured seconds needed before a rate is trusted
+const MIN_RATE_SAMPLE_SECS: f64 = 0.5;
+
+/// Tunables for persistent streams
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    /// How long an unused stream is kept open
+    pub idle_timeout: Duration,
+    /// Maximum number of concurrent streams
+    pub max_streams: usize,
+    /// Maximum number of concurrent streams for a single torrent (0 = no limit)
+    pub max_streams_per_torrent: usize,
+    /// Bytes kept per stream for serving short backward seeks
+    pub seek_back_buffer_size: usize,
+    /// Bytes fetched ahead of the reader in the background (0 = no prefetch)
+    pub prefetch_size: usize,
+    /// Seconds of reading the prefetch window is resized to hold, from the
+    /// measured download and consumption rates (zero = fixed `prefetch_size`)
+    pub prefetch_buffer: Duration,
+    /// Largest prefetch window chosen from measured rates
+    pub max_prefetch_size: usize,
+    /// Maximum number of upstream reads in flight at once
+    pub max_concurrent_reads: usize,
+    /// Cap on the bytes held by stream buffers, read-ahead caches and
+    /// in-flight reads (0 = no cap)
+    pub max_memory: u64,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(30),
+            max_streams: 50,
+            max_streams_per_torrent: 4,
+            seek_back_buffer_size: 2 * 1024 * 1024, // 2MB
+            prefetch_size: 32 * 1024 * 1024,        // 32MB
+            prefetch_buffer: Duration::ZERO,
+            max_prefetch_size: 256 * 1024 * 1024, // 256MB
+            max_concurrent_reads: 10,
+            max_memory: 0,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct StreamKey {
@@ -23,12 +83,285 @@ struct StreamKey {
 
 type ByteStream = Pin<Box<dyn futures::Stream<Item = reqwest::Result<Bytes>> + Send>>;
 
+/// Bytes per second over recent activity. Samples lose half their weight
+/// every `RATE_WINDOW_SECS` of measured time.
+#[derive(Debug, Default)]
+struct RateMeter {
+    bytes: f64,
+    secs: f64,
+}
+
+impl RateMeter {
+    fn record(&mut self, bytes: usize, elapsed: Duration) {
+        self.bytes += bytes as f64;
+        self.secs += elapsed.as_secs_f64();
+        if self.secs > RATE_WINDOW_SECS {
+            self.bytes /= 2.0;
+            self.secs /= 2.0;
+        }
+    }
+
+    fn rate(&self) -> Option<f64> {
+        (self.secs >= MIN_RATE_SAMPLE_SECS).then(|| self.bytes / self.secs)
+    }
+}
+
+/// How many bytes a prefetching stream may buffer ahead of the reader.
+/// The window can be resized while the transfer runs.
+struct PrefetchBudget {
+    permits: Semaphore,
+    size: AtomicUsize,
+    /// Permits still to be removed after shrinking below what is lent out
+    debt: AtomicUsize,
+    /// Download rate, not counting time spent waiting for the reader
+    download: std::sync::Mutex<RateMeter>,
+}
+
+impl PrefetchBudget {
+    fn new(size: usize) -> Arc<Self> {
+        let size = size.clamp(1, u32::MAX as usize);
+        Arc::new(Self {
+            permits: Semaphore::new(size),
+            size: AtomicUsize::new(size),
+            debt: AtomicUsize::new(0),
+            download: std::sync::Mutex::new(RateMeter::default()),
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    fn download_rate(&self) -> Option<f64> {
+        self.download.lock().unwrap().rate()
+    }
+
+    /// Returns permits taken by the transfer, paying off shrink debt first.
+    fn release(&self, permits: usize) {
+        let mut paid = 0;
+        let _ = self
+            .debt
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| {
+                paid = debt.min(permits);
+                Some(debt - paid)
+            });
+        self.permits.add_permits(permits - paid);
+    }
+
+    fn resize(&self, size: usize) {
+        let size = size.clamp(1, u32::MAX as usize);
+        let old = self.size.swap(size, Ordering::AcqRel);
+        if size > old {
+            let mut paid = 0;
+            let _ = self
+                .debt
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |debt| {
+                    paid = debt.min(size - old);
+                    Some(debt - paid)
+                });
+            self.permits.add_permits(size - old - paid);
+        } else if size < old {
+            let forgotten = self.permits.forget_permits(old - size);
+            self.debt
+                .fetch_add(old - size - forgotten, Ordering::AcqRel);
+        }
+    }
+}
+
+/// Sizes a stream's prefetch window to hold `buffer` worth of reading.
+#[derive(Debug, Clone, Copy)]
+struct Autoscale {
+    buffer: Duration,
+    max: usize,
+}
+
+impl Autoscale {
+    /// The window for the measured rates: what the reader consumes in
+    /// `buffer`, but no more than the link can download in that time.
+    fn window(&self, consumption: Option<f64>, download: Option<f64>) -> Option<usize> {
+        let rate = match (consumption?, download) {
+            (consumption, Some(download)) => consumption.min(download),
+            (consumption, None) => consumption,
+        };
+        let window = (rate * self.buffer.as_secs_f64()) as usize;
+        Some(window.clamp(MIN_AUTO_PREFETCH.min(self.max), self.max))
+    }
+}
+
+/// Pulls an HTTP body in a background task so the next chunks are already
+/// downloading while the current one is handed to the kernel.
+///
+/// At most the budget's window is buffered ahead of the reader. Dropping the
+/// stream aborts the transfer.
+struct PrefetchStream {
+    rx: mpsc::UnboundedReceiver<(reqwest::Result<Bytes>, u32)>,
+    budget: Arc<PrefetchBudget>,
+    task: JoinHandle<()>,
+}
+
+impl PrefetchStream {
+    fn spawn(mut inner: ByteStream, budget: Arc<PrefetchBudget>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let permits = Arc::clone(&budget);
+        let task = tokio::spawn(async move {
+            loop {
+                let started = Instant::now();
+                let Some(item) = inner.next().await else {
+                    break;
+                };
+                let len = item.as_ref().map_or(0, |chunk| chunk.len());
+                permits
+                    .download
+                    .lock()
+                    .unwrap()
+                    .record(len, started.elapsed());
+                let needed = len.min(permits.size()) as u32;
+                let Ok(permit) = permits.permits.acquire_many(needed).await else {
+                    break;
+                };
+                // The reader returns the permits once it takes the chunk
+                permit.forget();
+                if tx.send((item, needed)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { rx, budget, task }
+    }
+}
+
+impl futures::Stream for PrefetchStream {
+    type Item = reqwest::Result<Bytes>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx).map(|item| {
+            item.map(|(chunk, permits)| {
+                self.budget.release(permits as usize);
+                chunk
+            })
+        })
+    }
+}
+
+impl Drop for PrefetchStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The most recently consumed bytes of a stream, so short backward seeks
+/// can be served from memory instead of opening a new HTTP stream.
+struct SeekBackBuffer {
+    chunks: VecDeque<Bytes>,
+    len: usize,
+    capacity: usize,
+    /// Charge for `len`, once the stream is accounted
+    charge: Option<MemoryCharge>,
+}
+
+impl SeekBackBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            len: 0,
+            capacity,
+            charge: None,
+        }
+    }
+
+    /// Record bytes that were just consumed from the stream
+    fn push(&mut self, data: Bytes) {
+        if self.capacity == 0 || data.is_empty() {
+            return;
+        }
+        self.len += data.len();
+        self.chunks.push_back(data);
+
+        while self.len > self.capacity {
+            let excess = self.len - self.capacity;
+            let front = self.chunks.front_mut().expect("buffer has data");
+            if front.len() <= excess {
+                self.len -= front.len();
+                self.chunks.pop_front();
+            } else {
+                *front = front.slice(excess..);
+                self.len -= excess;
+            }
+        }
+        if let Some(charge) = &mut self.charge {
+            charge.resize(self.len as u64);
+        }
+    }
+
+    /// Drop the buffered bytes
+    fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+        if let Some(charge) = &mut self.charge {
+            charge.resize(0);
+        }
+    }
+
+    /// Up to `len` bytes starting `back` bytes before the end of the buffer,
+    /// as slices of the buffered chunks
+    fn slices_from(&self, back: usize, len: usize) -> Vec<Bytes> {
+        let mut skip = self.len - back;
+        let mut remaining = len;
+        let mut slices = Vec::new();
+        for chunk in &self.chunks {
+            if remaining == 0 {
+                break;
+            }
+            if skip >= chunk.len() {
+                skip -= chunk.len();
+                continue;
+            }
+            let n = (chunk.len() - skip).min(remaining);
+            slices.push(chunk.slice(skip..skip + n));
+            remaining -= n;
+            skip = 0;
+        }
+        slices
+    }
+}
+
+/// Joins chunk slices into one buffer. A single slice is returned as is, so
+/// reads within one received chunk are never copied.
+pub(crate) fn concat(mut parts: Vec<Bytes>) -> Bytes {
+    if parts.len() <= 1 {
+        return parts.pop().unwrap_or_default();
+    }
+    let mut buffer = BytesMut::with_capacity(parts.iter().map(Bytes::len).sum());
+    for part in &parts {
+        buffer.extend_from_slice(part);
+    }
+    buffer.freeze()
+}
+
 struct PersistentStream {
     stream: ByteStream,
     current_position: u64,
     last_access: Instant,
     is_valid: bool,
     pending_buffer: Option<Bytes>,
+    history: SeekBackBuffer,
+    /// Whether the body is downloaded ahead of the reader
+    prefetching: bool,
+    /// Window of the background download, while prefetching
+    prefetch: Option<Arc<PrefetchBudget>>,
+    /// Charge for the prefetch window, once the stream is accounted
+    window: Option<MemoryCharge>,
+    /// Resizes the prefetch window from measured rates
+    autoscale: Option<Autoscale>,
+    /// Rate at which the reader takes data
+    consumption: RateMeter,
+    /// Consecutive reads that did not start at the current position
+    seeks: u32,
 }
 
 impl PersistentStream {
@@ -38,18 +371,17 @@ impl PersistentStream {
         torrent_id: u64,
         file_idx: usize,
         start_offset: u64,
-        auth_header: Option<&str>,
+        auth_headers: reqwest::header::HeaderMap,
+        config: &StreamConfig,
     ) -> Result<Self> {
         let url = format!("{}/torrents/{}/stream/{}", base_url, torrent_id, file_idx);
 
         trace!("Creating stream for {}/{}", torrent_id, file_idx);
 
         let range_header = format!("bytes={}-", start_offset);
-        let mut request = client.get(&url).header("Range", range_header);
-
-        if let Some(auth) = auth_header {
-            request = request.header("Authorization", auth);
-        }
+        let request = request_id::tag(client.get(&url))
+            .header("Range", range_header)
+            .headers(auth_headers);
 
         let response = request
             .send()
@@ -58,6 +390,13 @@ impl PersistentStream {
 
         let status = response.status();
 
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(RqbitFuseError::PermissionDenied(format!(
+                "Failed to create stream: HTTP {}",
+                status
+            ))
+            .into());
+        }
         if !status.is_success() && status != StatusCode::PARTIAL_CONTENT {
             return Err(RqbitFuseError::IoError(format!(
                 "Failed to create stream: HTTP {}",
@@ -88,7 +427,16 @@ impl PersistentStream {
             );
         }
 
-        let stream: ByteStream = Box::pin(response.bytes_stream());
+        let mut stream: ByteStream = Box::pin(response.bytes_stream());
+        let prefetch =
+            (config.prefetch_size > 0).then(|| PrefetchBudget::new(config.prefetch_size));
+        if let Some(budget) = &prefetch {
+            stream = Box::pin(PrefetchStream::spawn(stream, Arc::clone(budget)));
+        }
+        let autoscale = (!config.prefetch_buffer.is_zero()).then_some(Autoscale {
+            buffer: config.prefetch_buffer,
+            max: config.max_prefetch_size,
+        });
 
         let mut persistent_stream = Self {
             stream,
@@ -96,6 +444,13 @@ impl PersistentStream {
             last_access: Instant::now(),
             is_valid: true,
             pending_buffer: None,
+            history: SeekBackBuffer::new(config.seek_back_buffer_size),
+            prefetching: prefetch.is_some(),
+            prefetch,
+            window: None,
+            autoscale,
+            consumption: RateMeter::default(),
+            seeks: 0,
         };
 
         if is_full_response {
@@ -105,54 +460,113 @@ impl PersistentStream {
         Ok(persistent_stream)
     }
 
-    /// Read bytes from the current position
-    async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+    /// Read up to `size` bytes from the current position. With a
+    /// `deadline`, returns the bytes received by then, possibly none,
+    /// instead of waiting for the rest.
+    async fn read(&mut self, size: usize, deadline: Option<Instant>) -> Result<Bytes> {
         if !self.is_valid {
             return Err(anyhow::anyhow!("Stream is no longer valid"));
         }
 
+        let mut parts = Vec::new();
         let mut bytes_read = 0;
-
-        if let Some(ref pending) = self.pending_buffer {
-            let pending_len = pending.len();
-            if pending_len > 0 {
-                let to_copy = pending_len.min(buf.len());
-                buf[..to_copy].copy_from_slice(&pending[..to_copy]);
-                bytes_read += to_copy;
-                self.current_position += to_copy as u64;
-
-                if to_copy < pending_len {
-                    self.pending_buffer = Some(pending.slice(to_copy..));
-                } else {
-                    self.pending_buffer = None;
+        while bytes_read < size {
+            let next = match deadline {
+                // A pending `next_slice` loses no data when dropped
+                Some(deadline) => {
+                    let next = self.next_slice(size - bytes_read);
+                    match tokio::time::timeout_at(deadline.into(), next).await {
+                        Ok(next) => next?,
+                        Err(_) => break,
+                    }
                 }
+                None => self.next_slice(size - bytes_read).await?,
+            };
+            match next {
+                Some(part) if part.is_empty() => {}
+                Some(part) => {
+                    bytes_read += part.len();
+                    parts.push(part);
+                }
+                None => break,
             }
         }
 
-        while bytes_read < buf.len() {
-            match self.stream.next().await {
-                Some(Ok(chunk)) => {
-                    let remaining = buf.len() - bytes_read;
-                    let to_copy = chunk.len().min(remaining);
-                    buf[bytes_read..bytes_read + to_copy].copy_from_slice(&chunk[..to_copy]);
-                    bytes_read += to_copy;
-                    self.current_position += to_copy as u64;
+        let now = Instant::now();
+        self.consumption.record(
+            bytes_read,
+            now.duration_since(self.last_access).min(MAX_READ_GAP),
+        );
+        self.last_access = now;
+        self.autoscale_prefetch();
+        Ok(concat(parts))
+    }
 
-                    self.buffer_leftover(chunk, to_copy);
-                    if self.pending_buffer.is_some() {
-                        break;
-                    }
-                }
+    /// Resizes the prefetch window to the measured rates, ignoring changes
+    /// under an eighth of the window.
+    fn autoscale_prefetch(&mut self) {
+        let (Some(prefetch), Some(autoscale)) = (&self.prefetch, self.autoscale) else {
+            return;
+        };
+        let Some(window) = autoscale.window(self.consumption.rate(), prefetch.download_rate())
+        else {
+            return;
+        };
+        let window = match &mut self.window {
+            Some(charge) => charge.resize_up_to(window as u64) as usize,
+            None => window,
+        };
+        let current = prefetch.size();
+        if window.abs_diff(current) > current / 8 {
+            trace!(
+                "Resizing prefetch window from {} to {} bytes",
+                current,
+                window
+            );
+            prefetch.resize(window);
+        }
+    }
+
+    /// Charges the stream's buffers to `memory`: the prefetch `window`,
+    /// sized by the caller, and the seek-back buffer.
+    fn charge_to(&mut self, memory: &Arc<MemoryBudget>, window: MemoryCharge) {
+        self.window = Some(window);
+        self.history.charge = Some(memory.charge(self.history.len as u64));
+    }
+
+    /// Frees what the stream holds for later reads: the seek-back buffer
+    /// and all but one chunk of the prefetch window.
+    fn shrink(&mut self) {
+        self.history.clear();
+        if let Some(prefetch) = &self.prefetch {
+            prefetch.resize(1);
+        }
+        if let Some(window) = &mut self.window {
+            window.resize(0);
+        }
+    }
+
+    /// Take up to `max` bytes at the current position, as a slice of the
+    /// pending or next received chunk. Returns None at the end of the body.
+    async fn next_slice(&mut self, max: usize) -> Result<Option<Bytes>> {
+        let chunk = match self.pending_buffer.take() {
+            Some(pending) => pending,
+            None => match self.stream.next().await {
+                Some(Ok(chunk)) => chunk,
                 Some(Err(e)) => {
                     self.is_valid = false;
                     return Err(anyhow::anyhow!("Stream error: {}", e));
                 }
-                None => break,
-            }
-        }
+                None => return Ok(None),
+            },
+        };
 
-        self.last_access = Instant::now();
-        Ok(bytes_read)
+        let taken = chunk.len().min(max);
+        let part = chunk.slice(..taken);
+        self.current_position += taken as u64;
+        self.history.push(part.clone());
+        self.buffer_leftover(chunk, taken);
+        Ok(Some(part))
     }
 
     async fn skip(&mut self, bytes_to_skip: u64) -> Result<u64> {
@@ -171,6 +585,7 @@ impl PersistentStream {
                     skipped += to_skip as u64;
                     self.current_position += to_skip as u64;
                     bytes_since_yield += to_skip as u64;
+                    self.history.push(chunk.slice(..to_skip));
 
                     self.buffer_leftover(chunk, to_skip);
                     if self.pending_buffer.is_some() {
@@ -207,14 +622,36 @@ impl PersistentStream {
             let gap = offset - self.current_position;
             gap <= MAX_SEEK_FORWARD
         } else {
-            // Can't seek backward
-            false
+            // Can seek backward as far as the seek-back buffer reaches
+            self.current_position - offset <= self.history.len as u64
         }
     }
 
+    /// Up to `size` already-consumed bytes at `offset` (behind the current
+    /// position) from the seek-back buffer.
+    fn read_behind(&mut self, offset: u64, size: usize) -> Vec<Bytes> {
+        let back = (self.current_position - offset) as usize;
+        self.last_access = Instant::now();
+        self.history.slices_from(back, back.min(size))
+    }
+
+    /// Track the access pattern for a read at `offset`.
+    fn record_access(&mut self, offset: u64) {
+        if offset == self.current_position {
+            self.seeks = 0;
+        } else {
+            self.seeks = self.seeks.saturating_add(1);
+        }
+    }
+
+    /// Whether recent reads jump around instead of following the stream
+    fn is_random_access(&self) -> bool {
+        self.seeks >= RANDOM_ACCESS_SEEKS
+    }
+
     /// Check if the stream has been idle too long
-    fn is_idle(&self) -> bool {
-        self.last_access.elapsed() > STREAM_IDLE_TIMEOUT
+    fn is_idle(&self, idle_timeout: Duration) -> bool {
+        self.last_access.elapsed() > idle_timeout
     }
 
     /// Consume bytes from pending buffer, returns bytes consumed
@@ -222,6 +659,7 @@ impl PersistentStream {
         if let Some(ref mut pending) = self.pending_buffer {
             let to_consume = pending.len().min(bytes_needed);
             self.current_position += to_consume as u64;
+            self.history.push(pending.slice(..to_consume));
 
             if to_consume < pending.len() {
                 *pending = pending.slice(to_consume..);
@@ -254,8 +692,24 @@ pub struct PersistentStreamManager {
     cleanup_handle: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
     /// Optional authentication credentials for HTTP Basic Auth
     auth_credentials: Option<(String, String)>,
-    /// Maximum number of concurrent streams allowed
-    max_streams: usize,
+    /// Gateway session used instead of Basic auth
+    session: Option<Arc<SessionAuth>>,
+    /// Stream limits and timeouts
+    config: StreamConfig,
+    /// Prefetch sizes of files opened with a readahead profile
+    prefetch_sizes: DashMap<StreamKey, usize>,
+    /// Admits upstream reads, interactive ones first
+    scheduler: Arc<ReadScheduler>,
+    /// Memory held by streams, reads and the filesystem's caches
+    memory: Arc<MemoryBudget>,
+    /// Records streams opened, reused and expired
+    metrics: Option<Arc<Metrics>>,
+    /// Streams opened by this manager
+    streams_opened: AtomicU64,
+    /// Reads served by an already-open stream
+    streams_reused: AtomicU64,
+    /// Bytes returned to callers
+    bytes_streamed: AtomicU64,
 }
 
 impl PersistentStreamManager {
@@ -274,6 +728,21 @@ impl PersistentStreamManager {
         base_url: String,
         auth_credentials: Option<(String, String)>,
         max_streams: usize,
+    ) -> Self {
+        let config = StreamConfig {
+            max_streams,
+            ..Default::default()
+        };
+        Self::with_config(client, base_url, auth_credentials, config, None)
+    }
+
+    /// Create a new stream manager with custom limits and optional metrics
+    pub fn with_config(
+        client: Client,
+        base_url: String,
+        auth_credentials: Option<(String, String)>,
+        config: StreamConfig,
+        metrics: Option<Arc<Metrics>>,
     ) -> Self {
         let streams: Arc<Mutex<HashMap<StreamKey, PersistentStream>>> =
             Arc::new(Mutex::new(HashMap::new()));
@@ -286,7 +755,15 @@ impl PersistentStreamManager {
             streams: Arc::clone(&streams),
             cleanup_handle: Arc::clone(&cleanup_handle),
             auth_credentials,
-            max_streams,
+            session: None,
+            scheduler: Arc::new(ReadScheduler::new(config.max_concurrent_reads)),
+            memory: MemoryBudget::new(config.max_memory),
+            config,
+            prefetch_sizes: DashMap::new(),
+            metrics,
+            streams_opened: AtomicU64::new(0),
+            streams_reused: AtomicU64::new(0),
+            bytes_streamed: AtomicU64::new(0),
         };
 
         // Start cleanup task
@@ -295,8 +772,56 @@ impl PersistentStreamManager {
         manager
     }
 
-    fn create_auth_header(&self) -> Option<String> {
-        super::create_auth_header(self.auth_credentials.as_ref())
+    /// Authenticate streams with the cookies of a gateway session instead
+    /// of Basic auth.
+    pub fn set_session(&mut self, session: Option<Arc<SessionAuth>>) {
+        if session.is_some() {
+            self.auth_credentials = None;
+        }
+        self.session = session;
+    }
+
+    fn auth_headers(&self) -> reqwest::header::HeaderMap {
+        super::auth_headers(self.auth_credentials.as_ref(), self.session.as_deref())
+    }
+
+    /// Opens a stream, logging in to the gateway first and again if it
+    /// rejects the session.
+    async fn open_stream(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        config: &StreamConfig,
+    ) -> Result<PersistentStream> {
+        let open = || {
+            PersistentStream::new(
+                &self.client,
+                &self.base_url,
+                torrent_id,
+                file_idx,
+                offset,
+                self.auth_headers(),
+                config,
+            )
+        };
+        let Some(session) = &self.session else {
+            return open().await;
+        };
+        session.ensure_login().await?;
+        let cookie = session.cookie();
+        match open().await {
+            Err(e)
+                if matches!(
+                    e.downcast_ref::<RqbitFuseError>(),
+                    Some(RqbitFuseError::PermissionDenied(_))
+                ) =>
+            {
+                session.refresh(cookie.as_deref()).await?;
+                open().await
+            }
+            result => result,
+        }
     }
 
     /// Start background task to clean up idle streams
@@ -312,9 +837,16 @@ impl PersistentStreamManager {
             return;
         }
 
+        let idle_timeout = self.config.idle_timeout;
+        let metrics = self.metrics.clone();
+
         // Spawn the cleanup task
         let cleanup_task = tokio::spawn(async move {
-            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            let mut interval = tokio::time::interval(
+                CLEANUP_INTERVAL
+                    .min(idle_timeout)
+                    .max(Duration::from_millis(10)),
+            );
 
             loop {
                 interval.tick().await;
@@ -323,7 +855,7 @@ impl PersistentStreamManager {
                 let before_count = streams_guard.len();
 
                 streams_guard.retain(|key, stream| {
-                    let should_keep = !stream.is_idle();
+                    let should_keep = !stream.is_idle(idle_timeout);
                     if !should_keep {
                         trace!(
                             "Removing idle stream for {}/{}",
@@ -336,6 +868,9 @@ impl PersistentStreamManager {
 
                 let after_count = streams_guard.len();
                 if before_count != after_count {
+                    if let Some(metrics) = &metrics {
+                        metrics.record_streams_expired((before_count - after_count) as u64);
+                    }
                     debug!(
                         stream_op = "cleanup",
                         removed = before_count - after_count,
@@ -354,6 +889,34 @@ impl PersistentStreamManager {
         });
     }
 
+    /// Admits upstream reads, shared with reads made outside of streams.
+    pub fn scheduler(&self) -> &Arc<ReadScheduler> {
+        &self.scheduler
+    }
+
+    /// Memory budget shared with reads made outside of streams and with the
+    /// filesystem's caches.
+    pub fn memory(&self) -> &Arc<MemoryBudget> {
+        &self.memory
+    }
+
+    /// Overrides the configured prefetch size for streams of a file opened
+    /// from now on (`None` restores the default).
+    pub fn set_prefetch_size(&self, torrent_id: u64, file_idx: usize, size: Option<usize>) {
+        let key = StreamKey {
+            torrent_id,
+            file_idx,
+        };
+        match size {
+            Some(size) => {
+                self.prefetch_sizes.insert(key, size);
+            }
+            None => {
+                self.prefetch_sizes.remove(&key);
+            }
+        }
+    }
+
     /// Read data from a file, using a persistent stream if possible
     pub async fn read(
         &self,
@@ -361,15 +924,83 @@ impl PersistentStreamManager {
         file_idx: usize,
         offset: u64,
         size: usize,
+    ) -> Result<Bytes> {
+        self.read_range(torrent_id, file_idx, offset, size, None)
+            .await
+    }
+
+    /// Like `read`, but returns the data received by `deadline` as a short
+    /// read instead of waiting for the whole range. Fails with `TimedOut`
+    /// if none arrived.
+    pub async fn read_until(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> Result<Bytes> {
+        let data = self
+            .read_range(torrent_id, file_idx, offset, size, Some(deadline))
+            .await?;
+        if data.is_empty() && size > 0 && Instant::now() >= deadline {
+            return Err(RqbitFuseError::TimedOut(
+                "no data arrived before the read deadline".to_string(),
+            )
+            .into());
+        }
+        Ok(data)
+    }
+
+    async fn read_range(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Option<Instant>,
     ) -> Result<Bytes> {
         let key = StreamKey {
             torrent_id,
             file_idx,
         };
 
+        // Stream reads are what applications wait on
+        let _permit = self.scheduler.acquire(ReadPriority::Interactive).await;
+        let _in_flight = self.memory.charge(size as u64);
+
         // Try to use existing stream first, holding lock for entire check-and-act
         let mut streams = self.streams.lock().await;
 
+        // Near the memory cap, the other streams give up their buffers
+        if self.memory.under_pressure() {
+            debug!(
+                stream_op = "memory_pressure",
+                used = self.memory.used(),
+                limit = self.memory.limit()
+            );
+            for (_, stream) in streams.iter_mut().filter(|(k, _)| **k != key) {
+                stream.shrink();
+            }
+        }
+
+        // Once reads turn random, prefetched data would mostly be thrown away:
+        // drop the stream to abort its transfer and continue without prefetch
+        let mut seeks = 0;
+        if let Some(stream) = streams.get_mut(&key) {
+            stream.record_access(offset);
+            seeks = stream.seeks;
+            if stream.prefetching && stream.is_random_access() {
+                debug!(
+                    stream_op = "readahead_cancelled",
+                    torrent_id = torrent_id,
+                    file_idx = file_idx,
+                    seeks = seeks
+                );
+                streams.remove(&key);
+            }
+        }
+
         let can_use_existing = if let Some(stream) = streams.get(&key) {
             stream.can_read_at(offset)
         } else {
@@ -390,6 +1021,27 @@ impl PersistentStreamManager {
                 offset,
                 size
             );
+            self.streams_reused.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_stream_reused();
+            }
+
+            // Short backward seek: serve what we can from the seek-back buffer
+            if offset < stream.current_position {
+                trace!(
+                    "Serving backward seek of {} bytes from buffer",
+                    stream.current_position - offset
+                );
+                let mut parts = stream.read_behind(offset, size);
+                let behind: usize = parts.iter().map(Bytes::len).sum();
+                if behind < size {
+                    parts.push(stream.read(size - behind, deadline).await?);
+                }
+                let data = concat(parts);
+                self.bytes_streamed
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+                return Ok(data);
+            }
 
             // If we need to seek forward a bit, do it
             if offset > stream.current_position {
@@ -400,22 +1052,46 @@ impl PersistentStreamManager {
 
             // Read while still holding lock, then release
             let result = self
-                .read_from_stream(stream, size, torrent_id, file_idx)
+                .read_from_stream(stream, size, deadline, torrent_id, file_idx)
                 .await;
             drop(streams); // Release lock before returning
             result
         } else {
             // Check if we're at the stream limit before creating a new stream
             let current_count = streams.len();
-            if current_count >= self.max_streams {
+            if current_count >= self.config.max_streams {
                 // At limit - return an error indicating resource exhaustion
                 // The caller should handle this and possibly retry after closing other streams
                 return Err(anyhow::anyhow!(
                     "Maximum number of open streams ({}) exceeded",
-                    self.max_streams
+                    self.config.max_streams
                 ));
             }
 
+            // Make room under the per-torrent cap by closing the torrent's
+            // least recently used streams
+            let cap = self.config.max_streams_per_torrent;
+            if cap > 0 {
+                streams.remove(&key);
+                let mut same_torrent: Vec<_> = streams
+                    .iter()
+                    .filter(|(k, _)| k.torrent_id == torrent_id)
+                    .map(|(k, s)| (*k, s.last_access))
+                    .collect();
+                if same_torrent.len() >= cap {
+                    same_torrent.sort_by_key(|(_, last_access)| *last_access);
+                    for (evict, _) in &same_torrent[..=same_torrent.len() - cap] {
+                        debug!(
+                            stream_op = "evicted",
+                            torrent_id = torrent_id,
+                            file_idx = evict.file_idx,
+                            reason = "per_torrent_limit"
+                        );
+                        streams.remove(evict);
+                    }
+                }
+            }
+
             // Drop the lock before creating a new stream (creation is async and may block)
             drop(streams);
 
@@ -428,19 +1104,50 @@ impl PersistentStreamManager {
                 size
             );
 
-            let auth_header = self.create_auth_header();
-            let mut new_stream = PersistentStream::new(
-                &self.client,
-                &self.base_url,
-                torrent_id,
-                file_idx,
-                offset,
-                auth_header.as_deref(),
-            )
-            .await?;
+            let override_size = if seeks >= RANDOM_ACCESS_SEEKS {
+                Some(0)
+            } else {
+                self.prefetch_sizes.get(&key).map(|size| *size)
+            };
+            // Prefetch only as far as the memory cap allows
+            let wanted = override_size.unwrap_or(self.config.prefetch_size);
+            let window = self.memory.charge_up_to(wanted as u64);
+            let prefetch_size = window.bytes() as usize;
+            if prefetch_size < wanted {
+                debug!(
+                    stream_op = "readahead_throttled",
+                    torrent_id = torrent_id,
+                    file_idx = file_idx,
+                    wanted = wanted,
+                    granted = prefetch_size
+                );
+            }
+            let file_config;
+            let config = if override_size.is_some() || prefetch_size < wanted {
+                file_config = StreamConfig {
+                    prefetch_size,
+                    prefetch_buffer: match override_size {
+                        Some(_) => Duration::ZERO,
+                        None => self.config.prefetch_buffer,
+                    },
+                    ..self.config.clone()
+                };
+                &file_config
+            } else {
+                &self.config
+            };
+            let mut new_stream = self
+                .open_stream(torrent_id, file_idx, offset, config)
+                .await?;
+            new_stream.charge_to(&self.memory, window);
+            new_stream.seeks = seeks;
+            self.streams_opened.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = &self.metrics {
+                metrics.record_stream_opened();
+            }
 
             let result = self
-                .read_from_stream(&mut new_stream, size, torrent_id, file_idx)
+                .read_from_stream(&mut new_stream, size, deadline, torrent_id, file_idx)
                 .await?;
 
             // Store the stream for future use
@@ -451,13 +1158,52 @@ impl PersistentStreamManager {
         }
     }
 
+    /// Close the stream for a file, e.g. once its last handle is released.
+    /// Returns true if a stream was open.
+    pub async fn close_stream(&self, torrent_id: u64, file_idx: usize) -> bool {
+        let key = StreamKey {
+            torrent_id,
+            file_idx,
+        };
+        let removed = self.streams.lock().await.remove(&key).is_some();
+        if removed {
+            debug!(
+                stream_op = "closed",
+                torrent_id = torrent_id,
+                file_idx = file_idx
+            );
+        }
+        removed
+    }
+
+    /// Close every open stream. Returns how many were open.
+    pub async fn close_all(&self) -> usize {
+        let closed = self.streams.lock().await.drain().count();
+        if closed > 0 {
+            debug!(stream_op = "closed_all", count = closed);
+        }
+        closed
+    }
+
     /// Get statistics about active streams
     pub async fn stats(&self) -> StreamManagerStats {
         let streams = self.streams.lock().await;
+        let opened = self.streams_opened.load(Ordering::Relaxed);
+        let reused = self.streams_reused.load(Ordering::Relaxed);
         StreamManagerStats {
             active_streams: streams.len(),
-            max_streams: self.max_streams,
+            max_streams: self.config.max_streams,
             total_bytes_streaming: streams.values().map(|s| s.current_position).sum(),
+            streams_opened: opened,
+            streams_reused: reused,
+            bytes_streamed: self.bytes_streamed.load(Ordering::Relaxed),
+            reuse_ratio: if opened + reused > 0 {
+                reused as f64 / (opened + reused) as f64
+            } else {
+                0.0
+            },
+            memory_used: self.memory.used(),
+            memory_limit: self.memory.limit(),
         }
     }
 
@@ -466,18 +1212,17 @@ impl PersistentStreamManager {
         &self,
         stream: &mut PersistentStream,
         size: usize,
+        deadline: Option<Instant>,
         torrent_id: u64,
         file_idx: usize,
     ) -> Result<Bytes> {
-        // Use BytesMut to avoid zeroing overhead - allocates but doesn't initialize
-        let mut buffer = BytesMut::new();
-        buffer.resize(size, 0);
-        let bytes_read = stream.read(&mut buffer).await?;
-        buffer.truncate(bytes_read);
+        let data = stream.read(size, deadline).await?;
+        self.bytes_streamed
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
 
-        trace!("Read {} bytes from {}/{}", bytes_read, torrent_id, file_idx);
+        trace!("Read {} bytes from {}/{}", data.len(), torrent_id, file_idx);
 
-        Ok(buffer.freeze())
+        Ok(data)
     }
 }
 
@@ -493,11 +1238,24 @@ impl Drop for PersistentStreamManager {
 }
 
 /// Statistics about the stream manager
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StreamManagerStats {
     pub active_streams: usize,
     pub max_streams: usize,
+    /// Sum of the current positions of the open streams
     pub total_bytes_streaming: u64,
+    pub streams_opened: u64,
+    pub streams_reused: u64,
+    /// Bytes returned to readers since startup
+    pub bytes_streamed: u64,
+    /// Fraction of reads served by an already-open stream
+    pub reuse_ratio: f64,
+    /// Bytes held by stream buffers, read-ahead caches and in-flight reads
+    #[serde(default)]
+    pub memory_used: u64,
+    /// `max_memory_bytes` (0 = no cap)
+    #[serde(default)]
+    pub memory_limit: u64,
 }
 
 #[cfg(test)]
@@ -704,11 +1462,17 @@ mod tests {
             last_access: Instant::now(),
             is_valid: false, // Start as invalid
             pending_buffer: None,
+            history: SeekBackBuffer::new(0),
+            prefetching: false,
+            prefetch: None,
+            window: None,
+            autoscale: None,
+            consumption: RateMeter::default(),
+            seeks: 0,
         };
 
         // Try to read from invalid stream
-        let mut buffer = vec![0u8; 100];
-        let result = persistent_stream.read(&mut buffer).await;
+        let result = persistent_stream.read(100, None).await;
 
         assert!(
             result.is_err(),
@@ -722,4 +1486,411 @@ mod tests {
             error_msg
         );
     }
+
+    #[tokio::test]
+    async fn test_read_within_chunk_is_not_copied() {
+        let chunk = Bytes::from(vec![7u8; 64]);
+        let chunks: Vec<reqwest::Result<Bytes>> =
+            vec![Ok(chunk.clone()), Ok(Bytes::from_static(b"tail"))];
+        let mut persistent_stream = PersistentStream {
+            stream: Box::pin(futures::stream::iter(chunks)),
+            current_position: 0,
+            last_access: Instant::now(),
+            is_valid: true,
+            pending_buffer: None,
+            history: SeekBackBuffer::new(0),
+            prefetching: false,
+            prefetch: None,
+            window: None,
+            autoscale: None,
+            consumption: RateMeter::default(),
+            seeks: 0,
+        };
+
+        let first = persistent_stream.read(32, None).await.unwrap();
+        assert_eq!(first.as_ptr(), chunk.as_ptr());
+        let second = persistent_stream.read(32, None).await.unwrap();
+        assert_eq!(second.as_ptr(), chunk[32..].as_ptr());
+
+        // Spanning chunks joins them
+        let mut persistent_stream = PersistentStream {
+            stream: Box::pin(futures::stream::iter(vec![
+                Ok(Bytes::from_static(b"abc")),
+                Ok(Bytes::from_static(b"def")),
+            ])),
+            ..persistent_stream
+        };
+        assert_eq!(
+            persistent_stream.read(5, None).await.unwrap(),
+            &b"abcde"[..]
+        );
+        assert_eq!(persistent_stream.current_position, 64 + 5);
+        assert_eq!(persistent_stream.read(5, None).await.unwrap(), &b"f"[..]);
+    }
+
+    #[tokio::test]
+    async fn test_read_returns_prefix_at_deadline() {
+        // One chunk arrives, then the body stalls
+        let chunks = futures::stream::iter(vec![Ok(Bytes::from_static(b"abc"))])
+            .chain(futures::stream::pending());
+        let mut persistent_stream = PersistentStream {
+            stream: Box::pin(chunks),
+            current_position: 0,
+            last_access: Instant::now(),
+            is_valid: true,
+            pending_buffer: None,
+            history: SeekBackBuffer::new(0),
+            prefetching: false,
+            prefetch: None,
+            window: None,
+            autoscale: None,
+            consumption: RateMeter::default(),
+            seeks: 0,
+        };
+
+        let deadline = Instant::now() + Duration::from_millis(50);
+        let data = persistent_stream.read(10, Some(deadline)).await.unwrap();
+        assert_eq!(data, &b"abc"[..]);
+        assert_eq!(persistent_stream.current_position, 3);
+        assert!(persistent_stream.is_valid);
+
+        // Nothing more arrives before the next deadline
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(persistent_stream
+            .read(10, Some(deadline))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_seek_back_buffer_keeps_most_recent_bytes() {
+        let mut buffer = SeekBackBuffer::new(10);
+        buffer.push(Bytes::from_static(b"abcdef"));
+        buffer.push(Bytes::from_static(b"ghijkl"));
+        assert_eq!(buffer.len, 10);
+
+        assert_eq!(concat(buffer.slices_from(10, 10)), &b"cdefghijkl"[..]);
+        assert_eq!(concat(buffer.slices_from(4, 3)), &b"ijk"[..]);
+    }
+
+    /// Test short backward seeks are served without a new HTTP request
+    #[tokio::test]
+    async fn test_backward_seek_served_from_buffer() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let (mock_server, manager) = setup_mock_server().await;
+        let content: Vec<u8> = (0..20000u32).map(|i| (i % 251) as u8).collect();
+
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(content.clone()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let first = manager.read(1, 0, 0, 8000).await.unwrap();
+        assert_eq!(&first[..], &content[..8000]);
+
+        // Seek back 3000 bytes and read across the current position
+        let back = manager.read(1, 0, 5000, 4000).await.unwrap();
+        assert_eq!(&back[..], &content[5000..9000]);
+
+        // Sequential reading continues on the same stream
+        let next = manager.read(1, 0, 9000, 1000).await.unwrap();
+        assert_eq!(&next[..], &content[9000..10000]);
+
+        mock_server.verify().await;
+    }
+
+    /// Test idle streams expire after the configured timeout and are counted
+    #[tokio::test]
+    async fn test_idle_streams_expire() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        let metrics = Arc::new(Metrics::new());
+        let manager = PersistentStreamManager::with_config(
+            Client::new(),
+            mock_server.uri(),
+            None,
+            StreamConfig {
+                idle_timeout: Duration::from_millis(50),
+                ..Default::default()
+            },
+            Some(Arc::clone(&metrics)),
+        );
+
+        manager.read(1, 0, 0, 100).await.unwrap();
+        manager.read(1, 0, 100, 100).await.unwrap();
+        assert_eq!(manager.stats().await.active_streams, 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(manager.stats().await.active_streams, 0);
+
+        let stats = manager.stats().await;
+        assert_eq!(stats.bytes_streamed, 200);
+        assert_eq!(stats.reuse_ratio, 0.5);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.streams_opened, 1);
+        assert_eq!(snapshot.streams_reused, 1);
+        assert_eq!(snapshot.streams_expired, 1);
+    }
+
+    #[tokio::test]
+    async fn test_close_stream() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let (mock_server, manager) = setup_mock_server().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        manager.read(1, 0, 0, 100).await.unwrap();
+        assert!(manager.close_stream(1, 0).await);
+        assert!(!manager.close_stream(1, 0).await);
+        assert_eq!(manager.stats().await.active_streams, 0);
+    }
+
+    #[tokio::test]
+    async fn test_per_torrent_stream_limit() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        let manager = PersistentStreamManager::with_config(
+            Client::new(),
+            mock_server.uri(),
+            None,
+            StreamConfig {
+                max_streams_per_torrent: 2,
+                ..Default::default()
+            },
+            None,
+        );
+
+        for file_idx in 0..5 {
+            manager.read(1, file_idx, 0, 100).await.unwrap();
+        }
+        manager.read(2, 0, 0, 100).await.unwrap();
+        assert_eq!(manager.stats().await.active_streams, 3);
+
+        // The most recently used streams of torrent 1 are kept
+        let streams = manager.streams.lock().await;
+        let mut kept: Vec<_> = streams
+            .keys()
+            .filter(|k| k.torrent_id == 1)
+            .map(|k| k.file_idx)
+            .collect();
+        kept.sort_unstable();
+        assert_eq!(kept, vec![3, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_stream_is_bounded() {
+        let chunks: Vec<reqwest::Result<Bytes>> =
+            (0..10).map(|_| Ok(Bytes::from(vec![0u8; 100]))).collect();
+        let mut stream = PrefetchStream::spawn(
+            Box::pin(futures::stream::iter(chunks)),
+            PrefetchBudget::new(250),
+        );
+
+        // The pump stops once the budget is used up
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(stream.rx.len(), 2);
+
+        let mut total = 0;
+        while let Some(chunk) = stream.next().await {
+            total += chunk.unwrap().len();
+        }
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn test_prefetch_budget_resize() {
+        let budget = PrefetchBudget::new(100);
+        budget.permits.try_acquire_many(80).unwrap().forget();
+
+        // Shrinking below what is lent out is paid off as permits return
+        budget.resize(50);
+        assert_eq!(budget.permits.available_permits(), 0);
+        budget.release(80);
+        assert_eq!(budget.permits.available_permits(), 50);
+
+        budget.resize(200);
+        assert_eq!(budget.permits.available_permits(), 200);
+        assert_eq!(budget.size(), 200);
+    }
+
+    #[test]
+    fn test_autoscale_window() {
+        let autoscale = Autoscale {
+            buffer: Duration::from_secs(10),
+            max: 256 * 1024 * 1024,
+        };
+        let mb = 1024.0 * 1024.0;
+
+        // Nothing measured yet: keep the configured window
+        assert_eq!(autoscale.window(None, Some(100.0 * mb)), None);
+        // Fast link: sized to the reader
+        assert_eq!(
+            autoscale.window(Some(5.0 * mb), Some(100.0 * mb)),
+            Some(50 * 1024 * 1024)
+        );
+        // Slow link: no more than it can download in time
+        assert_eq!(
+            autoscale.window(Some(5.0 * mb), Some(2.0 * mb)),
+            Some(20 * 1024 * 1024)
+        );
+        // Clamped at both ends
+        assert_eq!(autoscale.window(Some(1.0), None), Some(MIN_AUTO_PREFETCH));
+        assert_eq!(
+            autoscale.window(Some(1000.0 * mb), None),
+            Some(256 * 1024 * 1024)
+        );
+    }
+
+    #[test]
+    fn test_rate_meter() {
+        let mut meter = RateMeter::default();
+        meter.record(1000, Duration::from_millis(100));
+        assert_eq!(meter.rate(), None);
+        meter.record(1000, Duration::from_millis(900));
+        assert_eq!(meter.rate(), Some(2000.0));
+
+        // Old samples fade out
+        for _ in 0..10 {
+            meter.record(10_000, Duration::from_secs(2));
+        }
+        assert!(meter.rate().unwrap() > 4900.0);
+    }
+
+    #[tokio::test]
+    async fn test_random_access_cancels_prefetch() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let (mock_server, manager) = setup_mock_server().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        let key = StreamKey {
+            torrent_id: 1,
+            file_idx: 0,
+        };
+        manager.read(1, 0, 0, 100).await.unwrap();
+        assert!(manager.streams.lock().await[&key].prefetching);
+
+        // Sequential reads keep prefetching
+        manager.read(1, 0, 100, 100).await.unwrap();
+        assert!(manager.streams.lock().await[&key].prefetching);
+
+        for offset in [500, 300, 800] {
+            manager.read(1, 0, offset, 50).await.unwrap();
+        }
+        let streams = manager.streams.lock().await;
+        assert!(!streams[&key].prefetching);
+        assert!(streams[&key].is_random_access());
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_size_override() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let (mock_server, manager) = setup_mock_server().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+
+        let key = StreamKey {
+            torrent_id: 1,
+            file_idx: 0,
+        };
+        manager.set_prefetch_size(1, 0, Some(0));
+        manager.read(1, 0, 0, 100).await.unwrap();
+        assert!(!manager.streams.lock().await[&key].prefetching);
+
+        // Restoring the default applies to the next stream
+        manager.set_prefetch_size(1, 0, None);
+        manager.close_stream(1, 0).await;
+        manager.read(1, 0, 0, 100).await.unwrap();
+        assert!(manager.streams.lock().await[&key].prefetching);
+    }
+
+    #[tokio::test]
+    async fn test_memory_cap_throttles_and_shrinks_streams() {
+        use wiremock::matchers::{method, path_regex};
+        use wiremock::{Mock, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path_regex("^/torrents/1/stream/[0-9]+$"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1000]))
+            .mount(&mock_server)
+            .await;
+        let config = StreamConfig {
+            prefetch_size: 600,
+            max_memory: 1000,
+            ..Default::default()
+        };
+        let manager = PersistentStreamManager::with_config(
+            Client::new(),
+            mock_server.uri(),
+            None,
+            config,
+            None,
+        );
+        let key = |file_idx| StreamKey {
+            torrent_id: 1,
+            file_idx,
+        };
+
+        // The first stream gets its full window and keeps what it read
+        manager.read(1, 0, 0, 100).await.unwrap();
+        assert_eq!(manager.memory().used(), 700);
+
+        // The second only gets what is left below the cap
+        manager.read(1, 1, 0, 100).await.unwrap();
+        {
+            let streams = manager.streams.lock().await;
+            assert_eq!(streams[&key(1)].prefetch.as_ref().unwrap().size(), 200);
+        }
+        assert!(manager.memory().under_pressure());
+
+        // Past the high-water mark, the other streams give up their buffers
+        manager.read(1, 2, 0, 100).await.unwrap();
+        let streams = manager.streams.lock().await;
+        assert_eq!(streams[&key(0)].history.len, 0);
+        assert_eq!(streams[&key(0)].prefetch.as_ref().unwrap().size(), 1);
+        assert!(manager.memory().used() <= 1000);
+        drop(streams);
+
+        manager.close_stream(1, 0).await;
+        manager.close_stream(1, 1).await;
+        manager.close_stream(1, 2).await;
+        assert_eq!(manager.memory().used(), 0);
+    }
 }
@@ -0,0 +1,910 @@
+//! Transmission as the torrent client, through its RPC.
+//!
+//! Torrents are listed, added and controlled with calls to the RPC endpoint
+//! at `api_url`, e.g. `http://127.0.0.1:9091/transmission/rpc`. The RPC has
+//! no way to read file data, so files are read from Transmission's download
+//! directory: this backend only works with Transmission running on the same
+//! host (or the download directory mounted at the same path on this one). A
+//! read of pieces Transmission doesn't have yet checks them again every
+//! [`PIECE_POLL_INTERVAL`] until they are downloaded or `read_timeout`
+//! passes. Reads return at most [`MAX_READ_SIZE`] bytes, charged to the
+//! memory budget while they are read.
+
+use crate::api::backend::TorrentClient;
+use crate::api::memory::MemoryBudget;
+use crate::api::types::{
+    AddTorrentResponse, FileInfo, ListTorrentsResult, LiveStats, PieceBitfield, Speed, TorrentInfo,
+    TorrentSnapshot, TorrentStats,
+};
+use crate::config::Config;
+use crate::error::RqbitFuseError;
+use anyhow::Result;
+use base64::Engine;
+use bytes::Bytes;
+use dashmap::{DashMap, DashSet};
+use futures::future::BoxFuture;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{debug, trace, warn};
+
+/// Header carrying the session id Transmission requires against CSRF.
+const SESSION_ID_HEADER: &str = "X-Transmission-Session-Id";
+
+/// How often a read waiting for pieces checks them again.
+pub const PIECE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Most bytes one read returns, so that reads of whole files (like
+/// prefetches) don't hold the whole file in memory.
+pub const MAX_READ_SIZE: usize = 4 * 1024 * 1024;
+
+/// How long a read waits for pieces unless set with
+/// [`TransmissionClient::with_read_timeout`].
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fields of a torrent listing it with its files.
+const TORRENT_FIELDS: &[&str] = &[
+    "id",
+    "hashString",
+    "name",
+    "downloadDir",
+    "files",
+    "pieceSize",
+    "labels",
+];
+
+/// Fields of a torrent's stats.
+const STATS_FIELDS: &[&str] = &[
+    "status",
+    "error",
+    "errorString",
+    "percentDone",
+    "haveValid",
+    "totalSize",
+    "uploadedEver",
+    "rateDownload",
+    "rateUpload",
+    "peersConnected",
+    "eta",
+    "fileStats",
+];
+
+/// Reply to an RPC call.
+#[derive(Debug, Deserialize)]
+struct RpcReply {
+    result: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// A torrent as `torrent-get` returns [`TORRENT_FIELDS`].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcTorrent {
+    id: u64,
+    hash_string: String,
+    name: String,
+    download_dir: String,
+    #[serde(default)]
+    files: Vec<RpcFile>,
+    #[serde(default)]
+    piece_size: u64,
+    #[serde(default)]
+    labels: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcFile {
+    /// Path below the download directory, starting with the torrent's
+    /// directory in multi-file torrents.
+    name: String,
+    length: u64,
+}
+
+/// A torrent's stats as `torrent-get` returns [`STATS_FIELDS`].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcStats {
+    status: i64,
+    error: i64,
+    error_string: String,
+    percent_done: f64,
+    have_valid: u64,
+    total_size: u64,
+    uploaded_ever: u64,
+    rate_download: u64,
+    rate_upload: u64,
+    peers_connected: u64,
+    #[serde(default)]
+    file_stats: Vec<RpcFileStats>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcFileStats {
+    bytes_completed: u64,
+}
+
+/// A torrent `torrent-add` added, or found already added.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RpcAdded {
+    id: u64,
+    hash_string: String,
+}
+
+/// Where a torrent's files are on disk, and the pieces they lie in.
+#[derive(Debug)]
+struct FileLayout {
+    piece_length: u64,
+    /// Path, offset in the torrent and length of each file.
+    files: Vec<(PathBuf, u64, u64)>,
+}
+
+impl RpcTorrent {
+    fn info(self) -> TorrentInfo {
+        // Files of multi-file torrents are listed below the torrent's name
+        let prefix = format!("{}/", self.name);
+        let files: Vec<FileInfo> = self
+            .files
+            .into_iter()
+            .map(|file| {
+                let path = file.name.strip_prefix(&prefix).unwrap_or(&file.name);
+                let components: Vec<String> = path.split('/').map(str::to_string).collect();
+                FileInfo {
+                    name: components.last().cloned().unwrap_or_default(),
+                    length: file.length,
+                    components,
+                }
+            })
+            .collect();
+        TorrentInfo {
+            id: self.id,
+            info_hash: self.hash_string,
+            name: self.name,
+            output_folder: self.download_dir,
+            file_count: Some(files.len()),
+            files,
+            piece_length: Some(self.piece_size).filter(|&len| len > 0),
+            tags: Some(self.labels),
+            deferred_size: None,
+        }
+    }
+
+    fn layout(&self) -> FileLayout {
+        let dir = Path::new(&self.download_dir);
+        let mut offset = 0u64;
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                let start = offset;
+                offset = offset.saturating_add(file.length);
+                (dir.join(&file.name), start, file.length)
+            })
+            .collect();
+        FileLayout {
+            piece_length: self.piece_size,
+            files,
+        }
+    }
+}
+
+impl RpcStats {
+    /// The stats in rqbit's terms: only local errors fail the torrent,
+    /// tracker errors don't.
+    fn stats(self) -> TorrentStats {
+        let state = match (self.error, self.status) {
+            (3, _) => "error",
+            (_, 0) => "paused",
+            (_, 1 | 2) => "initializing",
+            _ => "live",
+        };
+        let speed = |bytes_per_sec: u64| {
+            let mbps = bytes_per_sec as f64 / (1024.0 * 1024.0);
+            Speed {
+                mbps,
+                human_readable: format!("{:.2} MiB/s", mbps),
+            }
+        };
+        let live = (state == "live").then(|| LiveStats {
+            snapshot: TorrentSnapshot {
+                downloaded_and_checked_bytes: self.have_valid,
+                uploaded_bytes: Some(self.uploaded_ever),
+                remaining_bytes: Some(self.total_size.saturating_sub(self.have_valid)),
+                peer_stats: Some(json!({ "live": self.peers_connected })),
+                ..Default::default()
+            },
+            average_piece_download_time: None,
+            download_speed: speed(self.rate_download),
+            upload_speed: speed(self.rate_upload),
+            time_remaining: None,
+        });
+        TorrentStats {
+            state: state.to_string(),
+            file_progress: self
+                .file_stats
+                .iter()
+                .map(|file| file.bytes_completed)
+                .collect(),
+            error: (self.error == 3).then_some(self.error_string),
+            progress_bytes: self.have_valid,
+            uploaded_bytes: self.uploaded_ever,
+            total_bytes: self.total_size,
+            finished: self.percent_done >= 1.0,
+            live,
+        }
+    }
+}
+
+/// Client of Transmission's RPC.
+#[derive(Debug)]
+pub struct TransmissionClient {
+    client: Client,
+    rpc_url: String,
+    auth_credentials: Option<(String, String)>,
+    /// Session id of the last `409 Conflict`, sent with every call.
+    session_id: RwLock<Option<String>>,
+    memory: Arc<MemoryBudget>,
+    /// How long a read waits for its pieces to be downloaded.
+    read_timeout: Duration,
+    /// File layouts of the torrents read from, fetched on first read.
+    layouts: DashMap<u64, Arc<FileLayout>>,
+    /// Torrents found complete, whose reads don't wait for pieces.
+    complete: DashSet<u64>,
+}
+
+impl TransmissionClient {
+    pub fn new(
+        rpc_url: String,
+        auth_credentials: Option<(String, String)>,
+        max_memory: u64,
+    ) -> Result<Self> {
+        reqwest::Url::parse(&rpc_url)
+            .map_err(|e| RqbitFuseError::IoError(format!("Invalid URL: {}", e)))?;
+        let client = super::client::http_client_builder()
+            .timeout(Duration::from_secs(60))
+            .build()
+            .map_err(|e| RqbitFuseError::IoError(format!("Failed to create HTTP client: {}", e)))?;
+        Ok(Self {
+            client,
+            rpc_url,
+            auth_credentials,
+            session_id: RwLock::new(None),
+            memory: MemoryBudget::new(max_memory),
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            layouts: DashMap::new(),
+            complete: DashSet::new(),
+        })
+    }
+
+    /// The client of a mount's `api_url` and credentials.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let auth_credentials = config.api_username.clone().zip(config.api_password.clone());
+        Ok(Self::new(
+            config.api_url.clone(),
+            auth_credentials,
+            config.max_memory_bytes,
+        )?
+        .with_read_timeout(Duration::from_secs(config.read_timeout)))
+    }
+
+    /// Replace how long reads wait for their pieces to be downloaded.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Calls an RPC method, getting a new session id and calling again
+    /// when Transmission rejects the current one.
+    async fn call(&self, method: &str, arguments: Value) -> Result<Value> {
+        let body = json!({ "method": method, "arguments": arguments });
+        for _ in 0..2 {
+            let mut request = self.client.post(&self.rpc_url).json(&body);
+            if let Some((username, password)) = &self.auth_credentials {
+                request = request.basic_auth(username, Some(password));
+            }
+            if let Some(session_id) = self.session_id.read().unwrap().as_deref() {
+                request = request.header(SESSION_ID_HEADER, session_id);
+            }
+            let response = request.send().await.map_err(RqbitFuseError::from)?;
+
+            match response.status() {
+                StatusCode::CONFLICT => {
+                    let session_id = response
+                        .headers()
+                        .get(SESSION_ID_HEADER)
+                        .and_then(|value| value.to_str().ok())
+                        .ok_or_else(|| RqbitFuseError::ApiError {
+                            status: 409,
+                            message: "no session id in the reply".to_string(),
+                        })?;
+                    debug!("New Transmission session id");
+                    *self.session_id.write().unwrap() = Some(session_id.to_string());
+                    continue;
+                }
+                StatusCode::UNAUTHORIZED => {
+                    return Err(RqbitFuseError::PermissionDenied(
+                        "Authentication failed: Invalid credentials".to_string(),
+                    )
+                    .into());
+                }
+                status if !status.is_success() => {
+                    return Err(RqbitFuseError::ApiError {
+                        status: status.as_u16(),
+                        message: response.text().await.unwrap_or_default(),
+                    }
+                    .into());
+                }
+                _ => {}
+            }
+
+            let reply: RpcReply = response
+                .json()
+                .await
+                .map_err(|e| RqbitFuseError::ParseError(format!("{}: {}", method, e)))?;
+            if reply.result != "success" {
+                // Transmission reports bad arguments, like a corrupt
+                // torrent file, in the result
+                return Err(RqbitFuseError::ApiError {
+                    status: 400,
+                    message: reply.result,
+                }
+                .into());
+            }
+            trace!("{} succeeded", method);
+            return Ok(reply.arguments);
+        }
+        Err(RqbitFuseError::ApiError {
+            status: 409,
+            message: "session id rejected".to_string(),
+        }
+        .into())
+    }
+
+    /// The `fields` of every torrent, or of torrent `id`.
+    async fn torrent_get(&self, id: Option<u64>, fields: &[&str]) -> Result<Vec<Value>> {
+        let mut arguments = json!({ "fields": fields });
+        if let Some(id) = id {
+            arguments["ids"] = json!([id]);
+        }
+        let mut reply = self.call("torrent-get", arguments).await?;
+        serde_json::from_value(reply["torrents"].take())
+            .map_err(|e| RqbitFuseError::ParseError(format!("torrent-get: {}", e)).into())
+    }
+
+    /// The `fields` of torrent `id`.
+    async fn torrent(&self, id: u64, fields: &[&str]) -> Result<Value> {
+        self.torrent_get(Some(id), fields)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| RqbitFuseError::NotFound(format!("torrent {}", id)).into())
+    }
+
+    /// Calls `method` on torrent `id`.
+    async fn torrent_action(&self, id: u64, method: &str, extra: Value) -> Result<()> {
+        let mut arguments = json!({ "ids": [id] });
+        if let (Some(arguments), Value::Object(extra)) = (arguments.as_object_mut(), extra) {
+            arguments.extend(extra);
+        }
+        self.call(method, arguments).await?;
+        Ok(())
+    }
+
+    async fn add(&self, arguments: Value) -> Result<AddTorrentResponse> {
+        let mut reply = self.call("torrent-add", arguments).await?;
+        let added = match reply["torrent-added"].take() {
+            Value::Null => reply["torrent-duplicate"].take(),
+            added => added,
+        };
+        let added: RpcAdded = serde_json::from_value(added)
+            .map_err(|e| RqbitFuseError::ParseError(format!("torrent-add: {}", e)))?;
+        Ok(AddTorrentResponse {
+            id: added.id,
+            info_hash: added.hash_string,
+        })
+    }
+
+    async fn layout(&self, torrent_id: u64) -> Result<Arc<FileLayout>> {
+        if let Some(layout) = self.layouts.get(&torrent_id) {
+            return Ok(Arc::clone(&layout));
+        }
+        let torrent: RpcTorrent =
+            serde_json::from_value(self.torrent(torrent_id, TORRENT_FIELDS).await?)
+                .map_err(|e| RqbitFuseError::ParseError(format!("torrent-get: {}", e)))?;
+        let layout = Arc::new(torrent.layout());
+        // Without metadata there is nothing to read yet
+        if !layout.files.is_empty() && layout.piece_length > 0 {
+            self.layouts.insert(torrent_id, Arc::clone(&layout));
+        }
+        Ok(layout)
+    }
+
+    /// Waits until the pieces holding `size` bytes at `offset` of a torrent
+    /// are downloaded, failing with `TimedOut` at `deadline`. Fails right
+    /// away while the piece size is unknown.
+    async fn wait_for_pieces(
+        &self,
+        torrent_id: u64,
+        piece_length: u64,
+        offset: u64,
+        size: u64,
+        deadline: Instant,
+    ) -> Result<()> {
+        while !self.complete.contains(&torrent_id) {
+            let bitfield = self.get_piece_bitfield(torrent_id).await?;
+            if bitfield.is_complete() {
+                self.complete.insert(torrent_id);
+                break;
+            }
+            if piece_length == 0 {
+                return Err(RqbitFuseError::NotReady(format!(
+                    "piece size of torrent {} is unknown",
+                    torrent_id
+                ))
+                .into());
+            }
+            if bitfield.has_piece_range(offset, size, piece_length) {
+                break;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RqbitFuseError::TimedOut(format!(
+                    "pieces at {} of torrent {} not downloaded",
+                    offset, torrent_id
+                ))
+                .into());
+            }
+            trace!(torrent_id, offset, size, "Waiting for pieces");
+            tokio::time::sleep(PIECE_POLL_INTERVAL.min(deadline - now)).await;
+        }
+        Ok(())
+    }
+
+    /// Reads up to [`MAX_READ_SIZE`] bytes at `offset` of a file, waiting
+    /// for its pieces until `deadline`.
+    async fn read(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> Result<Bytes> {
+        let layout = self.layout(torrent_id).await?;
+        let Some((path, file_offset, length)) = layout.files.get(file_idx) else {
+            return Err(RqbitFuseError::NotFound(format!(
+                "file {} of torrent {}",
+                file_idx, torrent_id
+            ))
+            .into());
+        };
+        if offset >= *length || size == 0 {
+            return Ok(Bytes::new());
+        }
+        let size = size.min((length - offset) as usize).min(MAX_READ_SIZE);
+        self.wait_for_pieces(
+            torrent_id,
+            layout.piece_length,
+            file_offset + offset,
+            size as u64,
+            deadline,
+        )
+        .await?;
+        let _in_flight = self.memory.charge(size as u64);
+        let data = read_at(path.clone(), offset, size).await;
+        if data.is_err() {
+            // Transmission may have moved the torrent's files
+            self.layouts.remove(&torrent_id);
+        }
+        data
+    }
+
+    /// Forgets what was cached about a torrent.
+    fn forget_cached(&self, torrent_id: u64) {
+        self.layouts.remove(&torrent_id);
+        self.complete.remove(&torrent_id);
+    }
+}
+
+/// Reads up to `size` bytes at `offset` of a file. Transmission may name
+/// files it hasn't finished with a `.part` suffix.
+async fn read_at(path: PathBuf, offset: u64, size: usize) -> Result<Bytes> {
+    use std::os::unix::fs::FileExt;
+
+    let display = path.display().to_string();
+    let read = tokio::task::spawn_blocking(move || -> std::io::Result<Bytes> {
+        let file = match std::fs::File::open(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut part = path.into_os_string();
+                part.push(".part");
+                std::fs::File::open(part)?
+            }
+            file => file?,
+        };
+        let mut buf = vec![0u8; size];
+        let mut filled = 0;
+        while filled < size {
+            match file.read_at(&mut buf[filled..], offset + filled as u64) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        buf.truncate(filled);
+        Ok(Bytes::from(buf))
+    });
+    match read.await {
+        Ok(Ok(data)) => Ok(data),
+        Ok(Err(e)) => Err(RqbitFuseError::IoError(format!("{}: {}", display, e)).into()),
+        Err(e) => Err(RqbitFuseError::IoError(format!("{}: {}", display, e)).into()),
+    }
+}
+
+impl TorrentClient for TransmissionClient {
+    fn list_torrents(&self) -> BoxFuture<'_, Result<ListTorrentsResult>> {
+        Box::pin(async move {
+            let torrents = self.torrent_get(None, TORRENT_FIELDS).await?;
+            let mut result = ListTorrentsResult {
+                torrents: Vec::with_capacity(torrents.len()),
+                errors: Vec::new(),
+            };
+            for torrent in torrents {
+                match serde_json::from_value::<RpcTorrent>(torrent.clone()) {
+                    Ok(torrent) => result.torrents.push(torrent.info()),
+                    Err(e) => {
+                        warn!("Skipping torrent Transmission listed: {}", e);
+                        result.errors.push((
+                            torrent["id"].as_u64().unwrap_or(0),
+                            torrent["name"].as_str().unwrap_or_default().to_string(),
+                            RqbitFuseError::ParseError(e.to_string()),
+                        ));
+                    }
+                }
+            }
+            Ok(result)
+        })
+    }
+
+    fn get_torrent(&self, id: u64) -> BoxFuture<'_, Result<TorrentInfo>> {
+        Box::pin(async move {
+            let torrent: RpcTorrent =
+                serde_json::from_value(self.torrent(id, TORRENT_FIELDS).await?)
+                    .map_err(|e| RqbitFuseError::ParseError(format!("torrent-get: {}", e)))?;
+            Ok(torrent.info())
+        })
+    }
+
+    fn get_torrent_stats(&self, id: u64) -> BoxFuture<'_, Result<TorrentStats>> {
+        Box::pin(async move {
+            let stats: RpcStats = serde_json::from_value(self.torrent(id, STATS_FIELDS).await?)
+                .map_err(|e| RqbitFuseError::ParseError(format!("torrent-get: {}", e)))?;
+            Ok(stats.stats())
+        })
+    }
+
+    fn get_torrent_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<Value>> {
+        Box::pin(self.torrent(id, STATS_FIELDS))
+    }
+
+    fn get_peer_stats_raw(&self, id: u64) -> BoxFuture<'_, Result<Value>> {
+        Box::pin(async move {
+            let mut torrent = self.torrent(id, &["peers"]).await?;
+            let Value::Array(list) = torrent["peers"].take() else {
+                return Ok(json!({ "peers": {} }));
+            };
+            // Keyed by address like rqbit's
+            let peers: serde_json::Map<String, Value> = list
+                .into_iter()
+                .map(|peer| {
+                    let address = format!(
+                        "{}:{}",
+                        peer["address"].as_str().unwrap_or_default(),
+                        peer["port"]
+                    );
+                    (address, peer)
+                })
+                .collect();
+            Ok(json!({ "peers": peers }))
+        })
+    }
+
+    fn get_piece_bitfield(&self, id: u64) -> BoxFuture<'_, Result<PieceBitfield>> {
+        Box::pin(async move {
+            let torrent = self.torrent(id, &["pieces", "pieceCount"]).await?;
+            let pieces = torrent["pieces"].as_str().unwrap_or_default();
+            let bits = base64::engine::general_purpose::STANDARD
+                .decode(pieces)
+                .map_err(|e| RqbitFuseError::ParseError(format!("pieces: {}", e)))?;
+            Ok(PieceBitfield {
+                // Transmission puts the first piece in the high bit
+                bits: bits.into_iter().map(u8::reverse_bits).collect(),
+                num_pieces: torrent["pieceCount"].as_u64().unwrap_or(0) as usize,
+            })
+        })
+    }
+
+    fn add_torrent_magnet<'a>(
+        &'a self,
+        magnet_link: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>> {
+        Box::pin(self.add(json!({ "filename": magnet_link })))
+    }
+
+    fn add_torrent_url<'a>(
+        &'a self,
+        torrent_url: &'a str,
+    ) -> BoxFuture<'a, Result<AddTorrentResponse>> {
+        Box::pin(self.add(json!({ "filename": torrent_url })))
+    }
+
+    fn add_torrent_file(&self, contents: Bytes) -> BoxFuture<'_, Result<AddTorrentResponse>> {
+        let metainfo = base64::engine::general_purpose::STANDARD.encode(&contents);
+        Box::pin(self.add(json!({ "metainfo": metainfo })))
+    }
+
+    fn pause_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.torrent_action(id, "torrent-stop", Value::Null))
+    }
+
+    fn start_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(self.torrent_action(id, "torrent-start", Value::Null))
+    }
+
+    fn forget_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        self.forget_cached(id);
+        Box::pin(self.torrent_action(id, "torrent-remove", json!({ "delete-local-data": false })))
+    }
+
+    fn delete_torrent(&self, id: u64) -> BoxFuture<'_, Result<()>> {
+        self.forget_cached(id);
+        Box::pin(self.torrent_action(id, "torrent-remove", json!({ "delete-local-data": true })))
+    }
+
+    fn health_check(&self) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            let check = tokio::time::timeout(
+                Duration::from_secs(5),
+                self.call("session-get", json!({ "fields": ["version"] })),
+            );
+            match check.await {
+                Ok(Ok(_)) => Ok(true),
+                Ok(Err(e)) => {
+                    warn!("Health check failed: {:#}", e);
+                    Ok(false)
+                }
+                Err(_) => {
+                    warn!("Health check timed out");
+                    Ok(false)
+                }
+            }
+        })
+    }
+
+    fn read_file_streaming(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        let deadline = Instant::now() + self.read_timeout;
+        Box::pin(self.read(torrent_id, file_idx, offset, size, deadline))
+    }
+
+    fn read_file_until(
+        &self,
+        torrent_id: u64,
+        file_idx: usize,
+        offset: u64,
+        size: usize,
+        deadline: Instant,
+    ) -> BoxFuture<'_, Result<Bytes>> {
+        Box::pin(self.read(torrent_id, file_idx, offset, size, deadline))
+    }
+
+    fn release_idle_resources(&self) -> BoxFuture<'_, usize> {
+        self.layouts.clear();
+        self.complete.clear();
+        Box::pin(async { 0 })
+    }
+
+    fn memory(&self) -> &Arc<MemoryBudget> {
+        &self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn album(download_dir: &str) -> Value {
+        json!({
+            "id": 7,
+            "hashString": "abc123",
+            "name": "album",
+            "downloadDir": download_dir,
+            "files": [
+                { "name": "album/a.txt", "length": 10 },
+                { "name": "album/cd2/b.txt", "length": 6 },
+            ],
+            "pieceSize": 8,
+            "labels": ["music"],
+            "pieces": base64::engine::general_purpose::STANDARD.encode([0b1100_0000u8]),
+            "pieceCount": 2,
+            "status": 4,
+            "error": 0,
+            "errorString": "",
+            "percentDone": 1.0,
+            "haveValid": 16,
+            "totalSize": 16,
+            "uploadedEver": 3,
+            "rateDownload": 1048576,
+            "rateUpload": 0,
+            "peersConnected": 2,
+            "fileStats": [{ "bytesCompleted": 10 }, { "bytesCompleted": 6 }],
+        })
+    }
+
+    async fn mock_torrent_get(server: &MockServer, torrent: Value) {
+        Mock::given(method("POST"))
+            .and(path("/transmission/rpc"))
+            .and(body_string_contains("torrent-get"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": "success",
+                "arguments": { "torrents": [torrent] },
+            })))
+            .mount(server)
+            .await;
+    }
+
+    fn client(server: &MockServer) -> TransmissionClient {
+        let rpc_url = format!("{}/transmission/rpc", server.uri());
+        TransmissionClient::new(rpc_url, None, 64 * 1024 * 1024).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_id_handshake() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(header(SESSION_ID_HEADER, "sid1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "result": "success",
+                "arguments": { "version": "4.0.5" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(409).insert_header(SESSION_ID_HEADER, "sid1"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert!(TorrentClient::health_check(&client(&server)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_torrent_mapping() {
+        let server = MockServer::start().await;
+        mock_torrent_get(&server, album("/downloads")).await;
+        let client = client(&server);
+
+        let list = client.list_torrents().await.unwrap();
+        assert!(list.errors.is_empty());
+        let info = &list.torrents[0];
+        assert_eq!((info.id, info.info_hash.as_str()), (7, "abc123"));
+        assert_eq!(info.output_folder, "/downloads");
+        assert_eq!(info.piece_length, Some(8));
+        assert_eq!(info.files[0].components, vec!["a.txt"]);
+        assert_eq!(info.files[1].components, vec!["cd2", "b.txt"]);
+        assert_eq!(info.files[1].name, "b.txt");
+        assert_eq!(info.tags, Some(vec!["music".to_string()]));
+
+        let stats = client.get_torrent_stats(7).await.unwrap();
+        assert_eq!(stats.state, "live");
+        assert!(stats.finished);
+        assert_eq!(stats.file_progress, vec![10, 6]);
+        assert_eq!(stats.live.unwrap().download_speed.mbps, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_piece_bitfield_order() {
+        let server = MockServer::start().await;
+        let mut torrent = album("/downloads");
+        torrent["pieces"] =
+            json!(base64::engine::general_purpose::STANDARD.encode([0b1000_0000u8]));
+        mock_torrent_get(&server, torrent).await;
+
+        let bitfield = client(&server).get_piece_bitfield(7).await.unwrap();
+        assert!(bitfield.has_piece(0));
+        assert!(!bitfield.has_piece(1));
+        assert!(!bitfield.is_complete());
+    }
+
+    #[tokio::test]
+    async fn test_read_from_download_dir() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("album/cd2")).unwrap();
+        std::fs::write(dir.path().join("album/a.txt"), b"0123456789").unwrap();
+        // Not finished yet, so still named with the suffix
+        std::fs::write(dir.path().join("album/cd2/b.txt.part"), b"abcdef").unwrap();
+
+        let server = MockServer::start().await;
+        mock_torrent_get(&server, album(dir.path().to_str().unwrap())).await;
+        let client = client(&server);
+
+        let data = client.read_file_streaming(7, 0, 2, 3).await.unwrap();
+        assert_eq!(&data[..], b"234");
+        // Reads past the end of the file are cut short
+        let data = client.read_file_streaming(7, 1, 4, 100).await.unwrap();
+        assert_eq!(&data[..], b"ef");
+        assert!(client
+            .read_file_streaming(7, 1, 6, 1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_bounded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(dir.path().join("album")).unwrap();
+        std::fs::write(dir.path().join("album/a.txt"), vec![1u8; MAX_READ_SIZE + 1]).unwrap();
+
+        let server = MockServer::start().await;
+        let mut torrent = album(dir.path().to_str().unwrap());
+        torrent["files"][0]["length"] = json!(MAX_READ_SIZE + 1);
+        mock_torrent_get(&server, torrent).await;
+        let client = client(&server);
+
+        // A read of the whole file returns at most one chunk
+        let data = client
+            .read_file_streaming(7, 0, 0, usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(data.len(), MAX_READ_SIZE);
+        assert_eq!(client.memory().used(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_waits_until_timeout() {
+        let server = MockServer::start().await;
+        let mut torrent = album("/downloads");
+        torrent["pieces"] =
+            json!(base64::engine::general_purpose::STANDARD.encode([0b1000_0000u8]));
+        mock_torrent_get(&server, torrent).await;
+        let client = client(&server).with_read_timeout(Duration::from_millis(100));
+
+        let err = client.read_file_streaming(7, 1, 0, 6).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RqbitFuseError>(),
+            Some(RqbitFuseError::TimedOut(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_unknown_piece_size() {
+        let server = MockServer::start().await;
+        let mut torrent = album("/downloads");
+        torrent["pieces"] =
+            json!(base64::engine::general_purpose::STANDARD.encode([0b1000_0000u8]));
+        torrent["pieceSize"] = json!(0);
+        mock_torrent_get(&server, torrent).await;
+        // Fails right away rather than waiting for the read timeout
+        let client = client(&server).with_read_timeout(Duration::from_secs(3600));
+
+        let err = client.read_file_streaming(7, 0, 0, 1).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<RqbitFuseError>(),
+            Some(RqbitFuseError::NotReady(_))
+        ));
+    }
+}
@@ -1,23 +1,45 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 // DataUnavailableReason and ApiError have been moved to crate::error::RqbitFuseError
 // Re-export for backward compatibility: pub use crate::error::RqbitFuseError as ApiError;
 
+/// Deserializes `null` as the type's default. rqbit reports `null` names
+/// and files for magnets still waiting for metadata.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 /// Torrent summary from list endpoint.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TorrentSummary {
     pub id: u64,
     #[serde(rename = "info_hash")]
     pub info_hash: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
     #[serde(rename = "output_folder")]
     pub output_folder: String,
+    /// Number of files, when the server includes it in the list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<usize>,
+    /// Total size, when the server includes it in the list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
 }
 
-/// Response from listing torrents.
+/// Response from listing torrents. Servers that page the list report the
+/// total count (offset paging) or the next page's cursor.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TorrentListResponse {
     pub torrents: Vec<TorrentSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    #[serde(default, alias = "next", skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 /// Full torrent information.
@@ -26,14 +48,24 @@ pub struct TorrentInfo {
     pub id: u64,
     #[serde(rename = "info_hash")]
     pub info_hash: String,
+    #[serde(default, deserialize_with = "null_as_default")]
     pub name: String,
     #[serde(rename = "output_folder")]
     pub output_folder: String,
     #[serde(rename = "file_count")]
     pub file_count: Option<usize>,
+    /// Empty while a magnet is waiting for metadata.
+    #[serde(default, deserialize_with = "null_as_default")]
     pub files: Vec<FileInfo>,
     #[serde(rename = "piece_length")]
     pub piece_length: Option<u64>,
+    /// Tags/labels, when the server supports them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Total size of a torrent listed from its summary alone; its `files`
+    /// are left empty until fetched with `get_torrent`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deferred_size: Option<u64>,
 }
 
 /// File information.
@@ -269,6 +301,24 @@ mod tests {
     use super::*;
     use rstest::rstest;
 
+    #[test]
+    fn test_torrent_info_awaiting_metadata() {
+        let info: TorrentInfo = serde_json::from_str(
+            r#"{"id": 3, "info_hash": "abc", "name": null, "output_folder": "/dl",
+                "file_count": null, "files": null, "piece_length": null}"#,
+        )
+        .unwrap();
+        assert!(info.name.is_empty());
+        assert!(info.files.is_empty());
+
+        let info: TorrentInfo = serde_json::from_str(
+            r#"{"id": 3, "info_hash": "abc", "output_folder": "/dl", "file_count": null,
+                "piece_length": null}"#,
+        )
+        .unwrap();
+        assert!(info.files.is_empty());
+    }
+
     #[rstest]
     // Complete bitfield (0b11111111) - all 8 pieces available
     #[case(vec![0b11111111], 8, 0, 100, 100, true)] // First piece
@@ -0,0 +1,266 @@
+//! autofs integration.
+//!
+//! `rqbit-fuse autofs-map` prints an automount(8) map with one entry per
+//! torrent and, given a key, serves as an executable map. Entries use the
+//! `rqbit-fuse` filesystem type, so mount(8) runs `mount.rqbit-fuse` (a
+//! symlink to this binary) with the torrent as the source. The helper starts
+//! a background mount process and returns once the mount is up; when autofs
+//! unmounts an idle mount, that process exits.
+
+use crate::api::types::TorrentInfo;
+use anyhow::{bail, Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Filesystem type used in map entries; mount(8) runs `mount.<type>`.
+pub const FSTYPE: &str = "rqbit-fuse";
+/// Map key and mount source for the whole rqbit namespace.
+pub const ALL_TORRENTS: &str = "all";
+
+/// Returns true when the process was started as `mount.rqbit-fuse`.
+pub fn invoked_as_mount_helper() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .and_then(|argv0| argv0.file_name().map(|name| name.to_os_string()))
+        .is_some_and(|name| name == format!("mount.{}", FSTYPE).as_str())
+}
+
+/// Map key for a torrent: its name with characters autofs treats specially
+/// replaced by `_`.
+pub fn map_key(torrent: &TorrentInfo) -> String {
+    let name = if torrent.name.is_empty() {
+        &torrent.info_hash
+    } else {
+        &torrent.name
+    };
+    name.chars()
+        .map(|c| {
+            if c.is_whitespace() || c.is_control() || "/\\\"'&:*#".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Options and location of a map entry mounting `source`. automount mounts
+/// as root, so entries allow other users in.
+pub fn map_entry(source: &str) -> String {
+    format!("-fstype={},ro,nosuid,nodev,allow_other :{}", FSTYPE, source)
+}
+
+/// Renders a complete map: the whole namespace under `all`, then one
+/// entry per torrent mounting it by info hash.
+pub fn render_map(torrents: &[TorrentInfo]) -> String {
+    let mut map = format!("{}\t{}\n", ALL_TORRENTS, map_entry(ALL_TORRENTS));
+    for torrent in torrents {
+        map.push_str(&format!(
+            "{}\t{}\n",
+            map_key(torrent),
+            map_entry(&torrent.info_hash)
+        ));
+    }
+    map
+}
+
+/// Looks up one key as automount does for executable maps. Keys match the
+/// map key, the torrent ID or the info hash.
+pub fn lookup(torrents: &[TorrentInfo], key: &str) -> Option<String> {
+    if key == ALL_TORRENTS {
+        return Some(map_entry(ALL_TORRENTS));
+    }
+    torrents
+        .iter()
+        .find(|t| {
+            map_key(t) == key || t.id.to_string() == key || t.info_hash.eq_ignore_ascii_case(key)
+        })
+        .map(|t| map_entry(&t.info_hash))
+}
+
+/// Arguments mount(8) passes to `mount.rqbit-fuse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelperArgs {
+    pub source: String,
+    pub mount_point: PathBuf,
+    pub options: Vec<String>,
+}
+
+impl HelperArgs {
+    /// Parses `source dir [-sfnv] [-o options] [-t type] [-N namespace]`.
+    pub fn parse<I: IntoIterator<Item = OsString>>(args: I) -> Result<Self> {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let arg = arg
+                .into_string()
+                .map_err(|arg| anyhow::anyhow!("invalid argument {:?}", arg))?;
+            match arg.as_str() {
+                "-o" => {
+                    let value = args.next().context("-o needs a value")?;
+                    options.extend(
+                        value
+                            .to_string_lossy()
+                            .split(',')
+                            .filter(|o| !o.is_empty())
+                            .map(str::to_string),
+                    );
+                }
+                "-t" | "-N" => {
+                    args.next();
+                }
+                flag if flag.starts_with('-') => {}
+                _ => positional.push(arg),
+            }
+        }
+        let [source, mount_point] = <[String; 2]>::try_from(positional)
+            .map_err(|_| anyhow::anyhow!("usage: mount.{} SOURCE DIR [-o OPTIONS]", FSTYPE))?;
+        Ok(Self {
+            source,
+            mount_point: PathBuf::from(mount_point),
+            options,
+        })
+    }
+
+    /// Arguments for the `rqbit-fuse mount` process serving the mount.
+    ///
+    /// `config=` and `api_url=` options are passed on, as are `allow_other`,
+    /// `allow_root` and `default_permissions`; generic mount options such as
+    /// `ro` are implied.
+    pub fn mount_args(&self) -> Result<Vec<OsString>> {
+        let mut args: Vec<OsString> = vec![
+            "mount".into(),
+            "--mount-point".into(),
+            self.mount_point.clone().into(),
+        ];
+        if self.source != ALL_TORRENTS {
+            args.extend(["--torrent".into(), self.source.clone().into()]);
+        }
+        for option in &self.options {
+            match option.split_once('=') {
+                Some(("config", path)) => args.extend(["--config".into(), path.into()]),
+                Some(("api_url", url)) => args.extend(["--api-url".into(), url.into()]),
+                Some((key, _)) => bail!("unsupported mount option {}", key),
+                None => match option.as_str() {
+                    "allow_other" => args.push("--allow-other".into()),
+                    "allow_root" => args.push("--allow-root".into()),
+                    "default_permissions" => args.push("--default-permissions".into()),
+                    "ro" | "nosuid" | "nodev" | "noexec" | "noatime" | "defaults" | "auto"
+                    | "noauto" | "_netdev" => {}
+                    _ => bail!("unsupported mount option {}", option),
+                },
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Waits for `mount_point` to become a mount while `exited` reports the
+/// serving process is still running.
+pub async fn wait_for_mount(
+    mount_point: &Path,
+    timeout: std::time::Duration,
+    mut exited: impl FnMut() -> Result<bool>,
+) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    while !crate::mount::is_mounted(mount_point) {
+        if exited()? {
+            bail!(
+                "rqbit-fuse exited before {} was mounted",
+                mount_point.display()
+            );
+        }
+        if tokio::time::Instant::now() >= deadline {
+            bail!("timed out waiting for {} to mount", mount_point.display());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(id: u64, name: &str, info_hash: &str) -> TorrentInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "info_hash": info_hash,
+            "name": name,
+            "output_folder": "/downloads",
+            "files": [],
+            "piece_length": 1048576
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_and_lookup_map() {
+        let torrents = [torrent(1, "Big Buck Bunny", "aaaa"), torrent(2, "", "bbbb")];
+
+        assert_eq!(
+            render_map(&torrents),
+            "all\t-fstype=rqbit-fuse,ro,nosuid,nodev,allow_other :all\n\
+             Big_Buck_Bunny\t-fstype=rqbit-fuse,ro,nosuid,nodev,allow_other :aaaa\n\
+             bbbb\t-fstype=rqbit-fuse,ro,nosuid,nodev,allow_other :bbbb\n"
+        );
+
+        let entry = Some(map_entry("aaaa"));
+        assert_eq!(lookup(&torrents, "Big_Buck_Bunny"), entry);
+        assert_eq!(lookup(&torrents, "1"), entry);
+        assert_eq!(lookup(&torrents, "AAAA"), entry);
+        assert_eq!(lookup(&torrents, "all"), Some(map_entry("all")));
+        assert_eq!(lookup(&torrents, "missing"), None);
+    }
+
+    #[test]
+    fn test_helper_args() {
+        let args = HelperArgs::parse(
+            [
+                "aaaa",
+                "/mnt/auto/movie",
+                "-n",
+                "-o",
+                "ro,allow_other,default_permissions,config=/etc/rq.toml",
+            ]
+            .map(OsString::from),
+        )
+        .unwrap();
+        assert_eq!(args.source, "aaaa");
+        assert_eq!(args.mount_point, PathBuf::from("/mnt/auto/movie"));
+        assert_eq!(
+            args.mount_args().unwrap(),
+            [
+                "mount",
+                "--mount-point",
+                "/mnt/auto/movie",
+                "--torrent",
+                "aaaa",
+                "--allow-other",
+                "--default-permissions",
+                "--config",
+                "/etc/rq.toml"
+            ]
+            .map(OsString::from)
+        );
+
+        let all = HelperArgs::parse(["all", "/mnt/auto/all"].map(OsString::from)).unwrap();
+        assert!(!all.mount_args().unwrap().contains(&"--torrent".into()));
+    }
+
+    #[rstest::rstest]
+    #[case(&["only-source"])]
+    #[case(&["a", "b", "c"])]
+    #[case(&["a", "b", "-o"])]
+    fn test_helper_args_invalid(#[case] args: &[&str]) {
+        assert!(HelperArgs::parse(args.iter().map(OsString::from)).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_option() {
+        let args = HelperArgs::parse(["a", "/m", "-o", "rw"].map(OsString::from)).unwrap();
+        assert!(args.mount_args().is_err());
+    }
+}
@@ -0,0 +1,273 @@
+//! `rqbit-fuse bench`: read throughput and latency of a file in a mount.
+//!
+//! Each block size gets its own region of the file, read sequentially twice:
+//! cold, after asking the kernel to drop its cached pages, then warm, when
+//! reads should be served from the page cache and rqbit-fuse's caches. A
+//! random pass then reads blocks spread over the whole file. Comparing runs
+//! before and after a tuning change shows its effect.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Block sizes measured when none are given.
+pub const DEFAULT_BLOCK_SIZES: [u64; 3] = [4 << 10, 64 << 10, 1 << 20];
+/// Bytes read sequentially per block size when not given.
+pub const DEFAULT_SIZE: u64 = 64 << 20;
+/// Random reads per block size when not given.
+pub const DEFAULT_RANDOM_READS: usize = 64;
+
+/// What to measure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchOptions {
+    pub block_sizes: Vec<u64>,
+    /// Bytes read by each sequential pass, capped at the file size.
+    pub size: u64,
+    pub random_reads: usize,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            block_sizes: DEFAULT_BLOCK_SIZES.to_vec(),
+            size: DEFAULT_SIZE,
+            random_reads: DEFAULT_RANDOM_READS,
+        }
+    }
+}
+
+/// Access pattern of a pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// Sequential reads of a region not read before.
+    Sequential,
+    /// The same sequential reads again.
+    Warm,
+    /// Reads at random block-aligned offsets.
+    Random,
+}
+
+impl Pattern {
+    fn as_str(self) -> &'static str {
+        match self {
+            Pattern::Sequential => "sequential",
+            Pattern::Warm => "warm",
+            Pattern::Random => "random",
+        }
+    }
+}
+
+/// Measurements of one pass.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchResult {
+    pub pattern: Pattern,
+    pub block_size: u64,
+    pub reads: usize,
+    pub bytes: u64,
+    pub throughput_mib_s: f64,
+    /// Latency of the pass's first read.
+    pub first_byte_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Runs every pass against `path`.
+pub fn run(path: &Path, options: &BenchOptions) -> Result<Vec<BenchResult>> {
+    let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let file_len = file.metadata()?.len();
+    if file_len == 0 {
+        bail!("{} is empty", path.display());
+    }
+
+    let window = options.size.min(file_len);
+    let mut results = Vec::new();
+    for (i, &block_size) in options.block_sizes.iter().enumerate() {
+        if block_size == 0 {
+            bail!("block size must not be zero");
+        }
+        // Separate regions keep earlier passes from warming later ones
+        let start = (i as u64 * window).min(file_len - window);
+        let sequential: Vec<u64> = (start..start + window)
+            .step_by(block_size as usize)
+            .collect();
+
+        drop_cache(&file);
+        results.push(read_pass(
+            &file,
+            Pattern::Sequential,
+            block_size,
+            &sequential,
+        )?);
+        results.push(read_pass(&file, Pattern::Warm, block_size, &sequential)?);
+
+        let random = random_offsets(file_len, block_size, options.random_reads, i as u64 + 1);
+        drop_cache(&file);
+        results.push(read_pass(&file, Pattern::Random, block_size, &random)?);
+    }
+    Ok(results)
+}
+
+/// Asks the kernel to evict the file's cached pages. Best effort; rqbit-fuse
+/// and rqbit may still hold the data.
+fn drop_cache(file: &File) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = file;
+}
+
+fn read_pass(
+    file: &File,
+    pattern: Pattern,
+    block_size: u64,
+    offsets: &[u64],
+) -> Result<BenchResult> {
+    let mut buf = vec![0u8; block_size as usize];
+    let mut latencies = Vec::with_capacity(offsets.len());
+    let mut bytes = 0;
+
+    let started = Instant::now();
+    for &offset in offsets {
+        let read_started = Instant::now();
+        let n = file
+            .read_at(&mut buf, offset)
+            .with_context(|| format!("read of {} bytes at {} failed", block_size, offset))?;
+        latencies.push(read_started.elapsed());
+        bytes += n as u64;
+    }
+    let elapsed = started.elapsed();
+
+    let first_byte = latencies.first().copied().unwrap_or_default();
+    latencies.sort_unstable();
+    Ok(BenchResult {
+        pattern,
+        block_size,
+        reads: offsets.len(),
+        bytes,
+        throughput_mib_s: bytes as f64 / (1 << 20) as f64 / elapsed.as_secs_f64().max(1e-9),
+        first_byte_ms: millis(first_byte),
+        p50_ms: millis(percentile(&latencies, 50.0)),
+        p99_ms: millis(percentile(&latencies, 99.0)),
+    })
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Block-aligned offsets spread over the file, from a fixed seed so runs are
+/// comparable.
+fn random_offsets(file_len: u64, block_size: u64, count: usize, seed: u64) -> Vec<u64> {
+    let blocks = file_len.div_ceil(block_size);
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..count)
+        .map(|_| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % blocks) * block_size
+        })
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 && b % (1 << 20) == 0 => format!("{} MiB", b >> 20),
+        b if b >= 1 << 10 && b % (1 << 10) == 0 => format!("{} KiB", b >> 10),
+        b => format!("{} B", b),
+    }
+}
+
+/// Renders results as a table, one row per pass.
+pub fn render_table(results: &[BenchResult]) -> String {
+    let mut table = format!(
+        "{:>8}  {:<10}  {:>6}  {:>10}  {:>12}  {:>9}  {:>9}\n",
+        "block", "pattern", "reads", "MiB/s", "first byte", "p50", "p99"
+    );
+    for r in results {
+        table.push_str(&format!(
+            "{:>8}  {:<10}  {:>6}  {:>10.1}  {:>9.2} ms  {:>6.2} ms  {:>6.2} ms\n",
+            format_size(r.block_size),
+            r.pattern.as_str(),
+            r.reads,
+            r.throughput_mib_s,
+            r.first_byte_ms,
+            r.p50_ms,
+            r.p99_ms
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_percentile() {
+        let samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        assert_eq!(percentile(&samples, 50.0), Duration::from_millis(50));
+        assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+        assert_eq!(percentile(&samples[..1], 99.0), Duration::from_millis(1));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_random_offsets_are_aligned_and_in_bounds() {
+        let offsets = random_offsets(10 << 20, 64 << 10, 100, 1);
+        assert_eq!(offsets.len(), 100);
+        assert!(offsets.iter().all(|o| o % (64 << 10) == 0 && *o < 10 << 20));
+        assert_eq!(offsets, random_offsets(10 << 20, 64 << 10, 100, 1));
+    }
+
+    #[test]
+    fn test_run_reads_every_pass() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&vec![7u8; 256 << 10]).unwrap();
+
+        let options = BenchOptions {
+            block_sizes: vec![4 << 10, 64 << 10],
+            size: 128 << 10,
+            random_reads: 8,
+        };
+        let results = run(file.path(), &options).unwrap();
+
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[0].pattern, Pattern::Sequential);
+        assert_eq!(results[0].reads, 32);
+        assert_eq!(results[0].bytes, 128 << 10);
+        assert_eq!(results[1].pattern, Pattern::Warm);
+        assert_eq!(results[2].bytes, 8 * (4 << 10));
+        assert_eq!(results[3].block_size, 64 << 10);
+
+        let table = render_table(&results);
+        assert_eq!(table.lines().count(), 7);
+        assert!(table.contains("64 KiB  warm"), "{}", table);
+    }
+
+    #[test]
+    fn test_empty_file_is_rejected() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(run(file.path(), &BenchOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Docker volume plugin exposing rqbit torrents as volumes.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use rqbit_fuse::config::{CliArgs, Config};
+use rqbit_fuse::docker_plugin::{serve, VolumePlugin, DEFAULT_ROOT, DEFAULT_SOCKET};
+use rqbit_fuse::mount::setup_logging;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::signal::unix::{signal, SignalKind};
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[derive(Parser)]
+#[command(name = "rqbit-fuse-docker-plugin")]
+#[command(about = "Docker volume plugin for rqbit-fuse")]
+#[command(version)]
+struct Cli {
+    /// Path to config file; volume options are applied on top of it
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Plugin socket Docker connects to
+    #[arg(long, default_value = DEFAULT_SOCKET)]
+    socket: PathBuf,
+
+    /// Directory holding volume mount points and saved volumes
+    #[arg(long, default_value = DEFAULT_ROOT)]
+    root: PathBuf,
+
+    /// Increase verbosity (can be used multiple times)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Suppress all output except errors
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    setup_logging(cli.verbose, cli.quiet)?;
+
+    let config = Config::load_with_cli(&CliArgs {
+        config_file: cli.config,
+        ..Default::default()
+    })?;
+    let plugin = Arc::new(VolumePlugin::new(config, cli.root)?);
+    let server = serve(Arc::clone(&plugin), &cli.socket)?;
+    tracing::info!("Docker volume plugin listening at {}", cli.socket.display());
+
+    let mut sigint = signal(SignalKind::interrupt()).context("signal handler")?;
+    let mut sigterm = signal(SignalKind::terminate()).context("signal handler")?;
+    tokio::select! {
+        _ = sigint.recv() => {}
+        _ = sigterm.recv() => {}
+    }
+
+    server.abort();
+    plugin.shutdown().await;
+    let _ = std::fs::remove_file(&cli.socket);
+    Ok(())
+}
@@ -0,0 +1,150 @@
+//! Memory limits of the cgroup rqbit-fuse runs in.
+//!
+//! Containers and systemd services cap memory with the cgroup v2
+//! `memory.max` (`memory.limit_in_bytes` on v1 hierarchies). Without an
+//! explicit `max_memory_bytes`, the memory budget defaults to
+//! `cgroup_memory_percent` of that limit, and the cgroup's pressure stall
+//! information (`memory.pressure`) squeezes the budget while the kernel is
+//! reclaiming.
+
+use crate::api::memory::MemoryBudget;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info};
+
+/// Where the cgroup hierarchies are mounted.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+/// How often `memory.pressure` is read.
+const PRESSURE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Share of the last 10 seconds in which some task stalled on memory, in
+/// percent, past which the budget is squeezed.
+const PRESSURE_THRESHOLD: f64 = 10.0;
+
+/// The memory cgroup directory of this process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryCgroup {
+    dir: PathBuf,
+    /// `memory.max`, or `memory.limit_in_bytes` on v1
+    limit_file: &'static str,
+}
+
+impl MemoryCgroup {
+    /// Finds the memory cgroup of this process, preferring the v2
+    /// hierarchy.
+    pub fn detect() -> Option<Self> {
+        let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+        let root = Path::new(CGROUP_ROOT);
+        let unified = unified_path(&cgroups).map(|path| Self {
+            dir: root.join(path.trim_start_matches('/')),
+            limit_file: "memory.max",
+        });
+        let v1 = v1_memory_path(&cgroups).map(|path| Self {
+            dir: root.join("memory").join(path.trim_start_matches('/')),
+            limit_file: "memory.limit_in_bytes",
+        });
+        [unified, v1]
+            .into_iter()
+            .flatten()
+            .find(|cgroup| cgroup.dir.join(cgroup.limit_file).exists())
+    }
+
+    /// The lowest limit of the cgroup and its parents, if any sets one.
+    pub fn limit(&self) -> Option<u64> {
+        self.dir
+            .ancestors()
+            .take_while(|dir| dir.starts_with(CGROUP_ROOT))
+            .filter_map(|dir| std::fs::read_to_string(dir.join(self.limit_file)).ok())
+            .filter_map(|max| parse_memory_max(&max))
+            .min()
+    }
+
+    /// Percentage of the last 10 seconds some task of the cgroup stalled
+    /// waiting for memory.
+    pub fn pressure(&self) -> Option<f64> {
+        parse_pressure(&std::fs::read_to_string(self.dir.join("memory.pressure")).ok()?)
+    }
+
+    /// Squeezes `memory` while the cgroup is under memory pressure, until
+    /// the returned task is aborted. None without pressure information.
+    pub fn spawn_pressure_monitor(&self, memory: Arc<MemoryBudget>) -> Option<JoinHandle<()>> {
+        self.pressure()?;
+        let cgroup = self.clone();
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PRESSURE_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let pressure = cgroup.pressure().unwrap_or(0.0);
+                let squeezed = pressure >= PRESSURE_THRESHOLD;
+                if memory.set_squeezed(squeezed) != squeezed {
+                    if squeezed {
+                        info!(pressure = pressure, "Memory pressure, shrinking caches");
+                    } else {
+                        debug!(pressure = pressure, "Memory pressure ended");
+                    }
+                }
+            }
+        }))
+    }
+}
+
+/// The path of the unified hierarchy in `/proc/self/cgroup`.
+fn unified_path(cgroups: &str) -> Option<&str> {
+    cgroups.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// The path of the v1 memory controller in `/proc/self/cgroup`.
+fn v1_memory_path(cgroups: &str) -> Option<&str> {
+    cgroups.lines().find_map(|line| {
+        let (controllers, path) = line.split_once(':')?.1.split_once(':')?;
+        controllers
+            .split(',')
+            .any(|c| c == "memory")
+            .then_some(path)
+    })
+}
+
+/// A limit file's value; None for `max`, or the near-`i64::MAX` value v1
+/// reports without a limit.
+fn parse_memory_max(max: &str) -> Option<u64> {
+    max.trim().parse().ok().filter(|&max: &u64| max < 1 << 62)
+}
+
+/// The `some avg10` value of a pressure file.
+fn parse_pressure(pressure: &str) -> Option<f64> {
+    pressure
+        .lines()
+        .find_map(|line| line.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgroup_files() {
+        assert_eq!(
+            unified_path("12:cpu:/\n0::/system.slice/rqbit-fuse.service\n"),
+            Some("/system.slice/rqbit-fuse.service")
+        );
+        assert_eq!(unified_path("4:memory:/docker/abc\n"), None);
+        assert_eq!(
+            v1_memory_path("5:cpu,cpuacct:/\n4:memory:/docker/abc\n0::/\n"),
+            Some("/docker/abc")
+        );
+
+        assert_eq!(parse_memory_max("268435456\n"), Some(256 * 1024 * 1024));
+        assert_eq!(parse_memory_max("max\n"), None);
+        assert_eq!(parse_memory_max("9223372036854771712\n"), None);
+
+        let pressure = "some avg10=12.50 avg60=3.00 avg300=0.50 total=123456\n\
+                        full avg10=1.00 avg60=0.00 avg300=0.00 total=456\n";
+        assert_eq!(parse_pressure(pressure), Some(12.5));
+        assert_eq!(parse_pressure(""), None);
+    }
+}
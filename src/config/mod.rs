@@ -1,7 +1,12 @@
 use crate::error::RqbitFuseError;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
+mod units;
+
+pub use units::{parse_bytes, parse_duration_secs};
+
 /// Main configuration for rqbit-fuse.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -12,28 +17,377 @@ pub struct Config {
     pub api_username: Option<String>,
     #[serde(default)]
     pub api_password: Option<String>,
+    /// Login form of a gateway in front of rqbit. When set, `api_username`
+    /// and `api_password` are posted to it for a session cookie instead of
+    /// being sent as Basic auth.
+    #[serde(default)]
+    pub api_login_url: Option<String>,
+    /// File the session cookies are kept in across restarts.
+    #[serde(default)]
+    pub api_cookie_jar: Option<PathBuf>,
+    /// Torrent client behind `api_url`.
+    #[serde(default)]
+    pub api_backend: ApiBackend,
 
     // Cache settings
-    #[serde(default = "default_metadata_ttl")]
+    #[serde(
+        default = "default_metadata_ttl",
+        deserialize_with = "units::duration_secs"
+    )]
     pub metadata_ttl: u64,
     #[serde(default = "default_max_entries")]
     pub max_entries: usize,
+    /// How long the kernel caches entries and attributes.
+    #[serde(
+        default = "default_entry_ttl",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub entry_ttl: u64,
+    /// How long the kernel caches failed lookups (0 = not at all).
+    #[serde(default, deserialize_with = "units::duration_secs")]
+    pub negative_ttl: u64,
 
     // Mount settings
     #[serde(default = "default_mount_point")]
     pub mount_point: PathBuf,
+    /// Permission bits of a mount point rqbit-fuse creates. Defaults to
+    /// what the umask leaves.
+    #[serde(default)]
+    pub mount_point_mode: Option<u32>,
+    /// Owner of a mount point rqbit-fuse creates.
+    #[serde(default)]
+    pub mount_point_uid: Option<u32>,
+    /// Group of a mount point rqbit-fuse creates.
+    #[serde(default)]
+    pub mount_point_gid: Option<u32>,
+    /// Mount over a directory with files in it, hiding them while mounted.
+    #[serde(default)]
+    pub nonempty: bool,
+    /// Remove a mount point rqbit-fuse created after a clean unmount.
+    #[serde(default)]
+    pub remove_mount_point: bool,
+    /// Mount only this torrent (ID or info hash), with its files directly at
+    /// the mount point.
+    #[serde(default)]
+    pub torrent: Option<String>,
+    /// When reads update file access times.
+    #[serde(default)]
+    pub atime: AtimePolicy,
+    /// Owner reported for all entries. Defaults to the user running rqbit-fuse.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// Group reported for all entries. Defaults to the group running rqbit-fuse.
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// Permission bits of files.
+    #[serde(default = "default_file_mode")]
+    pub file_mode: u32,
+    /// Permission bits of directories.
+    #[serde(default = "default_dir_mode")]
+    pub dir_mode: u32,
+    /// Appended to the directory name of torrents rqbit reports as failed.
+    #[serde(default)]
+    pub error_suffix: Option<String>,
+    /// Container mode: mount with the mount syscall instead of fusermount,
+    /// check /dev/fuse up front and act as an init process when PID 1.
+    #[serde(default)]
+    pub container: bool,
+    /// Let root access the mount in addition to the mounting user.
+    #[serde(default)]
+    pub allow_root: bool,
+    /// Let every user access the mount.
+    #[serde(default)]
+    pub allow_other: bool,
+    /// Let the kernel enforce the reported ownership and permission bits.
+    #[serde(default)]
+    pub default_permissions: bool,
+    /// Mount options passed to the kernel verbatim, e.g. an SELinux
+    /// `context=` label.
+    #[serde(default)]
+    pub extra_options: Vec<String>,
+    /// Hide files rqbit has not finished downloading.
+    #[serde(default)]
+    pub hide_incomplete: bool,
 
     // Performance settings
-    #[serde(default = "default_read_timeout")]
+    #[serde(
+        default = "default_read_timeout",
+        deserialize_with = "units::duration_secs"
+    )]
     pub read_timeout: u64,
     #[serde(default = "default_max_concurrent_reads")]
     pub max_concurrent_reads: usize,
-    #[serde(default = "default_readahead_size")]
+    /// Retries of failed API requests available at once, shared by all
+    /// requests to the same rqbit; successful requests earn them back
+    /// (0 = never retry).
+    #[serde(default = "default_retry_budget")]
+    pub retry_budget: u32,
+    #[serde(default = "default_readahead_size", deserialize_with = "units::bytes")]
     pub readahead_size: u64,
+    /// Seconds of playback each stream's readahead is resized to hold, from
+    /// its measured download and read rates, starting at `readahead_size`
+    /// (0 = always `readahead_size`).
+    #[serde(default, deserialize_with = "units::duration_secs")]
+    pub readahead_buffer_secs: u64,
+    /// Largest readahead chosen from measured rates.
+    #[serde(
+        default = "default_readahead_max_size",
+        deserialize_with = "units::bytes"
+    )]
+    pub readahead_max_size: u64,
+    /// Torrents with at least this many files get only their root directory
+    /// at discovery; subdirectories are built on first access (0 = never).
+    #[serde(default = "default_lazy_tree_threshold")]
+    pub lazy_tree_threshold: usize,
+    /// Torrents the list reports with at least this many files are added
+    /// from the list alone; their file list is fetched when their directory
+    /// is first accessed (0 = always fetched at discovery).
+    #[serde(default = "default_lazy_details_threshold")]
+    pub lazy_details_threshold: usize,
+    /// How long the listing of a large directory is reused by readdir until
+    /// the tree changes (0 = never cached).
+    #[serde(
+        default = "default_readdir_cache_ttl",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub readdir_cache_ttl: u64,
+    /// Listing the root fetches a fresh torrent list when the last one is
+    /// older than this, so new torrents show up right away (0 = listings
+    /// only refresh in the background).
+    #[serde(
+        default = "default_root_refresh_interval",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub root_refresh_interval: u64,
+    /// How often the torrent list and status are polled in the background.
+    #[serde(
+        default = "default_status_poll_interval",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub status_poll_interval: u64,
+    /// Poll interval once no FUSE operation has happened for this long,
+    /// until the next one (0 = always poll at `status_poll_interval`).
+    #[serde(
+        default = "default_idle_poll_interval",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub idle_poll_interval: u64,
+    /// Once nothing has been read for this long, close the streams, drop
+    /// cached listings and stop polling until the next FUSE operation
+    /// (0 = never).
+    #[serde(default, deserialize_with = "units::duration_secs")]
+    pub idle_sleep_after: u64,
+    /// Mount right away with an empty root and add torrents as the first
+    /// discovery finds them, instead of discovering them all first.
+    #[serde(default)]
+    pub progressive_mount: bool,
+    /// Directories with at least this many entries have their listing
+    /// cached.
+    #[serde(default = "default_readdir_cache_min_entries")]
+    pub readdir_cache_min_entries: usize,
+    /// Bytes fetched from the start and the end of a file when it is opened,
+    /// where media containers keep their headers and indexes (0 = off).
+    #[serde(default, deserialize_with = "units::bytes")]
+    pub prefetch_head_tail: u64,
+    /// Smallest range fetched from rqbit per read; the rest serves the
+    /// handle's following adjacent reads (0 = fetch exactly what is read).
+    #[serde(default, deserialize_with = "units::bytes")]
+    pub read_aggregate_size: u64,
+    /// Widen reads to whole pieces and check them against the hashes in the
+    /// torrent's metainfo, failing reads of corrupt pieces with EIO.
+    #[serde(default)]
+    pub piece_check_enabled: bool,
+    /// When `read_timeout` expires after part of a read arrived, return that
+    /// part as a short read instead of failing. Files are then opened with
+    /// direct I/O, since the kernel takes a cached short read for EOF.
+    #[serde(default)]
+    pub short_reads: bool,
+    /// Which files keep their kernel page cache when opened again.
+    #[serde(default)]
+    pub page_cache: PageCachePolicy,
+    /// Page cache of files rqbit has finished downloading, overriding
+    /// `page_cache`.
+    #[serde(default)]
+    pub page_cache_complete: Option<PageCacheMode>,
+    /// Page cache of files still downloading, overriding `page_cache`.
+    #[serde(default)]
+    pub page_cache_downloading: Option<PageCacheMode>,
+    /// Cap on the bytes held by stream buffers, read-ahead caches and
+    /// in-flight reads. Near it, caches shrink and new readahead is
+    /// throttled (0 = no cap).
+    #[serde(default, deserialize_with = "units::bytes")]
+    pub max_memory_bytes: u64,
+    /// Without `max_memory_bytes`, the memory cap is this share of the
+    /// cgroup's `memory.max`, in percent (0 = ignore the cgroup).
+    #[serde(default = "default_cgroup_memory_percent")]
+    pub cgroup_memory_percent: u8,
+    /// Readahead per file extension, e.g. `mkv = "64MiB sequential"` or
+    /// `jpg = "whole-file"`, applied when a file is opened.
+    #[serde(default)]
+    pub readahead_profiles: HashMap<String, ReadaheadProfile>,
+
+    // Stream settings
+    /// How long an unused persistent stream is kept open.
+    #[serde(
+        default = "default_stream_idle_timeout",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub stream_idle_timeout: u64,
+    /// Maximum number of persistent streams open at once.
+    #[serde(default = "default_max_streams")]
+    pub max_streams: usize,
+    /// Maximum number of persistent streams per torrent (0 = no limit).
+    #[serde(default = "default_max_streams_per_torrent")]
+    pub max_streams_per_torrent: usize,
+    /// Bytes kept per stream to serve short backward seeks.
+    #[serde(
+        default = "default_stream_seek_back_size",
+        deserialize_with = "units::bytes"
+    )]
+    pub stream_seek_back_size: u64,
+    /// Close a file's stream as soon as its last handle is released instead
+    /// of waiting for the idle timeout.
+    #[serde(default)]
+    pub close_streams_on_release: bool,
+
+    // File handle settings
+    /// Maximum number of open file handles; opens beyond it fail with
+    /// EMFILE (0 = no limit).
+    #[serde(default)]
+    pub max_open_handles: usize,
+    /// Maximum number of open file handles per uid (0 = no limit).
+    #[serde(default)]
+    pub max_open_handles_per_uid: usize,
+    /// Handles neither read nor released for this long are evicted, e.g.
+    /// after their release was lost (0 = never).
+    #[serde(
+        default = "default_handle_ttl",
+        deserialize_with = "units::duration_secs"
+    )]
+    pub handle_ttl: u64,
+    /// Verify handles' cached read-ahead data against checksums taken when
+    /// it was fetched on one hit in this many, refetching it on a mismatch
+    /// (0 = no checksums).
+    #[serde(default)]
+    pub cache_checksums: u64,
+
+    // FUSE session settings, negotiated with the kernel at mount time.
+    // Unset values keep the kernel's defaults.
+    /// Maximum kernel readahead per file, in bytes.
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub fuse_max_readahead: Option<u64>,
+    /// Maximum number of pending background requests (such as readahead).
+    #[serde(default)]
+    pub fuse_max_background: Option<u16>,
+    /// Background requests at which the kernel treats the queue as congested.
+    #[serde(default)]
+    pub fuse_congestion_threshold: Option<u16>,
+    /// Request the kernel writeback cache.
+    #[serde(default)]
+    pub fuse_writeback_cache: bool,
+    /// Allow re-exporting the mount over NFS: advertises export support and
+    /// mounts with `allow_other` so nfsd can reach it.
+    #[serde(default)]
+    pub nfs_export: bool,
 
     // Logging settings
     #[serde(default = "default_log_level")]
     pub log_level: String,
+    /// Appends a JSON line per open and read, with the caller's uid and pid.
+    #[serde(default)]
+    pub audit_log: Option<PathBuf>,
+
+    // Filter settings
+    /// Only torrents matching the filter are shown in the mount.
+    #[serde(default)]
+    pub filter: FilterConfig,
+
+    // Quota settings
+    /// Read limits applied to each user of the mount.
+    #[serde(default)]
+    pub quota: QuotaConfig,
+
+    // Trash settings
+    /// Days a removed torrent stays paused in `/.trash` before rqbit forgets
+    /// it (0 = removing isn't allowed).
+    #[serde(default)]
+    pub trash_days: u64,
+    /// File recording trashed torrents. Defaults to
+    /// `$XDG_STATE_HOME/rqbit-fuse/trash.json`.
+    #[serde(default)]
+    pub trash_state: Option<PathBuf>,
+    /// Add torrents written to `/.rqbit-fuse/add` as magnet links or URLs.
+    #[serde(default)]
+    pub add_torrents: bool,
+    /// Removing a torrent's top-level entry removes the torrent from rqbit,
+    /// unless `trash_days` moves it to the trash.
+    #[serde(default)]
+    pub remove_torrents: TorrentRemoval,
+
+    // Virtual file settings
+    /// Serve the stats of each torrent at
+    /// `/.rqbit-fuse/torrents/<infohash>.json`.
+    #[serde(default)]
+    pub stats_files: bool,
+    /// Give each torrent's directory a `.rqbit-fuse` directory serving the
+    /// torrent's status, peers and progress.
+    #[serde(default)]
+    pub status_dirs: bool,
+    /// Symlinks to this many of the most recently added torrents, newest
+    /// first, in `recent_dir` at the root (0 = no such directory).
+    #[serde(default)]
+    pub recent_count: usize,
+    /// Name of the directory of `recent_count`.
+    #[serde(default = "default_recent_dir")]
+    pub recent_dir: String,
+
+    // Config file settings
+    /// Reject unknown keys in the config file instead of ignoring them.
+    #[serde(default)]
+    pub strict: bool,
+
+    // Profiles
+    /// Built-in settings applied on top of the config file, like a profile.
+    #[serde(default)]
+    pub preset: Option<Preset>,
+    /// Named `[profile.<name>]` overrides selected with `--profile`.
+    #[serde(default)]
+    pub profile: HashMap<String, ConfigSource>,
+
+    // Multi-mount settings
+    /// Additional `[[mounts]]` served by the same process. Each entry
+    /// overrides the top-level settings and must set its own `mount_point`.
+    #[serde(default)]
+    pub mounts: Vec<ConfigSource>,
+    /// Control socket used by `status`. Defaults to
+    /// `$XDG_RUNTIME_DIR/rqbit-fuse.sock`.
+    #[serde(default)]
+    pub control_socket: Option<PathBuf>,
+
+    // Privilege settings
+    /// User (name or uid) to switch to once everything is mounted, when
+    /// started as root.
+    #[serde(default)]
+    pub run_as_user: Option<String>,
+    /// Group (name or gid) to switch to; defaults to the user's primary group.
+    #[serde(default)]
+    pub run_as_group: Option<String>,
+
+    // Sandbox settings
+    /// Restrict the process to the system calls it needs once mounted.
+    /// Requires the `seccomp` build feature.
+    #[serde(default)]
+    pub seccomp: bool,
+
+    // Offline settings
+    /// Serve the tree from the metadata snapshot without contacting rqbit;
+    /// reads fail with ENETDOWN.
+    #[serde(default)]
+    pub offline: bool,
+    /// File keeping the last torrent list seen from rqbit, for `offline`.
+    /// Defaults to `$XDG_STATE_HOME/rqbit-fuse/snapshot.json`.
+    #[serde(default)]
+    pub metadata_snapshot: Option<PathBuf>,
 }
 
 // Default value functions for serde
@@ -45,6 +399,10 @@ fn default_metadata_ttl() -> u64 {
     60
 }
 
+fn default_entry_ttl() -> u64 {
+    1
+}
+
 fn default_max_entries() -> usize {
     1000
 }
@@ -53,6 +411,14 @@ fn default_mount_point() -> PathBuf {
     PathBuf::from("/mnt/torrents")
 }
 
+fn default_file_mode() -> u32 {
+    0o444
+}
+
+fn default_dir_mode() -> u32 {
+    0o555
+}
+
 fn default_read_timeout() -> u64 {
     30
 }
@@ -61,10 +427,74 @@ fn default_max_concurrent_reads() -> usize {
     10
 }
 
+fn default_retry_budget() -> u32 {
+    10
+}
+
+fn default_readahead_max_size() -> u64 {
+    256 * 1024 * 1024
+}
+
 fn default_readahead_size() -> u64 {
     33554432
 }
 
+fn default_lazy_tree_threshold() -> usize {
+    1000
+}
+
+fn default_lazy_details_threshold() -> usize {
+    10000
+}
+
+fn default_root_refresh_interval() -> u64 {
+    5
+}
+
+fn default_recent_dir() -> String {
+    ".recent".to_string()
+}
+
+fn default_status_poll_interval() -> u64 {
+    30
+}
+
+fn default_idle_poll_interval() -> u64 {
+    60
+}
+
+fn default_readdir_cache_ttl() -> u64 {
+    10
+}
+
+fn default_readdir_cache_min_entries() -> usize {
+    256
+}
+
+fn default_stream_idle_timeout() -> u64 {
+    30
+}
+
+fn default_max_streams() -> usize {
+    50
+}
+
+fn default_max_streams_per_torrent() -> usize {
+    4
+}
+
+fn default_cgroup_memory_percent() -> u8 {
+    25
+}
+
+fn default_handle_ttl() -> u64 {
+    3600
+}
+
+fn default_stream_seek_back_size() -> u64 {
+    2 * 1024 * 1024
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -75,13 +505,379 @@ impl Default for Config {
             api_url: default_api_url(),
             api_username: None,
             api_password: None,
+            api_login_url: None,
+            api_cookie_jar: None,
+            api_backend: ApiBackend::default(),
             metadata_ttl: default_metadata_ttl(),
             max_entries: default_max_entries(),
+            entry_ttl: default_entry_ttl(),
+            negative_ttl: 0,
             mount_point: default_mount_point(),
+            mount_point_mode: None,
+            mount_point_uid: None,
+            mount_point_gid: None,
+            nonempty: false,
+            remove_mount_point: false,
+            torrent: None,
+            atime: AtimePolicy::default(),
+            uid: None,
+            gid: None,
+            file_mode: default_file_mode(),
+            dir_mode: default_dir_mode(),
+            error_suffix: None,
+            container: false,
+            allow_root: false,
+            allow_other: false,
+            default_permissions: false,
+            extra_options: Vec::new(),
+            hide_incomplete: false,
             read_timeout: default_read_timeout(),
             max_concurrent_reads: default_max_concurrent_reads(),
+            retry_budget: default_retry_budget(),
             readahead_size: default_readahead_size(),
+            readahead_buffer_secs: 0,
+            readahead_max_size: default_readahead_max_size(),
+            lazy_tree_threshold: default_lazy_tree_threshold(),
+            lazy_details_threshold: default_lazy_details_threshold(),
+            readdir_cache_ttl: default_readdir_cache_ttl(),
+            root_refresh_interval: default_root_refresh_interval(),
+            status_poll_interval: default_status_poll_interval(),
+            idle_poll_interval: default_idle_poll_interval(),
+            idle_sleep_after: 0,
+            progressive_mount: false,
+            readdir_cache_min_entries: default_readdir_cache_min_entries(),
+            prefetch_head_tail: 0,
+            read_aggregate_size: 0,
+            piece_check_enabled: false,
+            short_reads: false,
+            page_cache: PageCachePolicy::default(),
+            page_cache_complete: None,
+            page_cache_downloading: None,
+            max_memory_bytes: 0,
+            cgroup_memory_percent: default_cgroup_memory_percent(),
+            readahead_profiles: HashMap::new(),
+            stream_idle_timeout: default_stream_idle_timeout(),
+            max_streams: default_max_streams(),
+            max_streams_per_torrent: default_max_streams_per_torrent(),
+            stream_seek_back_size: default_stream_seek_back_size(),
+            close_streams_on_release: false,
+            max_open_handles: 0,
+            max_open_handles_per_uid: 0,
+            handle_ttl: default_handle_ttl(),
+            cache_checksums: 0,
+            fuse_max_readahead: None,
+            fuse_max_background: None,
+            fuse_congestion_threshold: None,
+            fuse_writeback_cache: false,
+            nfs_export: false,
             log_level: default_log_level(),
+            audit_log: None,
+            filter: FilterConfig::default(),
+            quota: QuotaConfig::default(),
+            trash_days: 0,
+            add_torrents: false,
+            remove_torrents: TorrentRemoval::default(),
+            stats_files: false,
+            status_dirs: false,
+            recent_count: 0,
+            recent_dir: default_recent_dir(),
+            strict: false,
+            preset: None,
+            profile: HashMap::new(),
+            mounts: Vec::new(),
+            control_socket: None,
+            run_as_user: None,
+            run_as_group: None,
+            seccomp: false,
+            trash_state: None,
+            offline: false,
+            metadata_snapshot: None,
+        }
+    }
+}
+
+/// `[filter]` settings controlling which torrents appear in the mount.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilterConfig {
+    /// Only show torrents whose name matches this regex.
+    #[serde(default)]
+    pub include_name_regex: Option<String>,
+    /// Hide torrents whose name matches this regex.
+    #[serde(default)]
+    pub exclude_name_regex: Option<String>,
+    /// Hide torrents smaller than this (total of all files).
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub min_size: Option<u64>,
+    /// Hide torrents larger than this (total of all files).
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub max_size: Option<u64>,
+    /// Only show torrents carrying at least one of these tags. Needs a
+    /// server that reports tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Group torrents into a top-level directory per tag.
+    #[serde(default)]
+    pub tag_directories: bool,
+    /// Group torrents into directories mirroring their rqbit output folder
+    /// below this path, e.g. `/downloads/tv` becomes `tv/` for
+    /// `/downloads`.
+    #[serde(default)]
+    pub output_folder_root: Option<PathBuf>,
+    /// Leave files matching these glob patterns out of the tree, e.g.
+    /// `*.nfo` or `sample/*`.
+    #[serde(default)]
+    pub hide_files: Vec<String>,
+}
+
+/// `[quota]` settings limiting how much each uid may read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Bytes each uid may read per hour.
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub per_hour: Option<u64>,
+    /// Bytes each uid may read per day.
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub per_day: Option<u64>,
+    /// Users without a quota.
+    #[serde(default)]
+    pub exempt_uids: Vec<u32>,
+}
+
+/// Readahead for files with a given extension, written as a size, a policy
+/// or both: `"64MiB sequential"`, `"whole-file"`, `"128MiB"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ReadaheadProfile {
+    /// Bytes downloaded ahead of the reader; defaults to `readahead_size`.
+    pub size: Option<u64>,
+    pub policy: ReadaheadPolicy,
+}
+
+/// How far ahead of the reader a file is downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadaheadPolicy {
+    /// Download ahead of the reader until reads turn random.
+    #[default]
+    Sequential,
+    /// Download the rest of the file as soon as it is read.
+    WholeFile,
+    /// Only download what is read.
+    None,
+}
+
+impl ReadaheadProfile {
+    /// Bytes to download ahead of the reader of a file of `file_size` bytes,
+    /// given the global `readahead_size`.
+    pub fn prefetch_size(&self, readahead_size: u64, file_size: u64) -> u64 {
+        match self.policy {
+            ReadaheadPolicy::Sequential => self.size.unwrap_or(readahead_size),
+            ReadaheadPolicy::WholeFile => file_size,
+            ReadaheadPolicy::None => 0,
+        }
+    }
+}
+
+impl std::str::FromStr for ReadaheadProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut size = None;
+        let mut policy = None;
+        for word in s.split_whitespace() {
+            let parsed = match word.to_ascii_lowercase().as_str() {
+                "sequential" => Some(ReadaheadPolicy::Sequential),
+                "whole-file" => Some(ReadaheadPolicy::WholeFile),
+                "none" => Some(ReadaheadPolicy::None),
+                _ => None,
+            };
+            match parsed {
+                Some(_) if policy.is_some() => {
+                    return Err(format!("readahead profile '{}' has two policies", s))
+                }
+                Some(parsed) => policy = Some(parsed),
+                None if size.is_some() => {
+                    return Err(format!("readahead profile '{}' has two sizes", s))
+                }
+                None => {
+                    size = Some(units::parse_bytes(word).map_err(|_| {
+                        format!(
+                            "invalid readahead profile '{}' (expected a size, \
+                             sequential, whole-file or none)",
+                            s
+                        )
+                    })?)
+                }
+            }
+        }
+        let policy = match (size, policy) {
+            (None, None) => return Err("readahead profile is empty".to_string()),
+            (Some(_), Some(policy)) if policy != ReadaheadPolicy::Sequential => {
+                return Err(format!(
+                    "readahead profile '{}' sets a size with a policy that doesn't use one",
+                    s
+                ))
+            }
+            (_, policy) => policy.unwrap_or_default(),
+        };
+        Ok(Self { size, policy })
+    }
+}
+
+impl TryFrom<String> for ReadaheadProfile {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ReadaheadProfile> for String {
+    fn from(profile: ReadaheadProfile) -> Self {
+        let policy = match profile.policy {
+            ReadaheadPolicy::Sequential => "sequential",
+            ReadaheadPolicy::WholeFile => "whole-file",
+            ReadaheadPolicy::None => "none",
+        };
+        match profile.size {
+            Some(size) => format!("{} {}", size, policy),
+            None => policy.to_string(),
+        }
+    }
+}
+
+/// How reads update file access times.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AtimePolicy {
+    /// Never record access times.
+    Noatime,
+    /// Record the first access, then at most once a day.
+    #[default]
+    Relatime,
+    /// Record every read.
+    Strict,
+}
+
+/// Which files the kernel may serve repeated reads of from its page cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageCachePolicy {
+    /// Drop a file's cached pages whenever it is opened.
+    Drop,
+    /// Keep the cached pages of files rqbit has finished downloading.
+    #[default]
+    KeepComplete,
+    /// Keep every file's cached pages.
+    Keep,
+    /// Don't cache: every read reaches rqbit-fuse (direct I/O).
+    Bypass,
+}
+
+impl PageCachePolicy {
+    /// What happens to the page cache of a complete and of a downloading
+    /// file when it is opened.
+    pub fn modes(self) -> (PageCacheMode, PageCacheMode) {
+        match self {
+            PageCachePolicy::Drop => (PageCacheMode::Drop, PageCacheMode::Drop),
+            PageCachePolicy::KeepComplete => (PageCacheMode::Keep, PageCacheMode::Drop),
+            PageCachePolicy::Keep => (PageCacheMode::Keep, PageCacheMode::Keep),
+            PageCachePolicy::Bypass => (PageCacheMode::Bypass, PageCacheMode::Bypass),
+        }
+    }
+}
+
+/// What happens to a file's page cache when it is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PageCacheMode {
+    /// Keep the pages cached by earlier opens.
+    Keep,
+    /// Drop the pages cached by earlier opens.
+    Drop,
+    /// Don't cache: every read reaches rqbit-fuse (direct I/O).
+    Bypass,
+}
+
+/// Torrent client the files are served from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiBackend {
+    /// rqbit's HTTP API, streaming file data from it.
+    #[default]
+    Rqbit,
+    /// Transmission's RPC, reading file data from its download directory.
+    Transmission,
+}
+
+/// What removing a torrent's directory with `rmdir` does in rqbit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TorrentRemoval {
+    /// Nothing: the mount is read-only.
+    #[default]
+    Off,
+    /// Forget the torrent, keeping its downloaded files.
+    Forget,
+    /// Forget the torrent and delete its downloaded files.
+    Delete,
+}
+
+/// Built-in settings for common workloads, selected with `--preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Library scanners such as Plex and Jellyfin: long entry caching,
+    /// negative caching, no half-downloaded files, head/tail prefetch for
+    /// media probing and no access time updates.
+    MediaServer,
+}
+
+impl Preset {
+    /// The settings the preset changes.
+    pub fn source(self) -> ConfigSource {
+        match self {
+            Preset::MediaServer => ConfigSource {
+                metadata_ttl: Some(300),
+                entry_ttl: Some(300),
+                negative_ttl: Some(60),
+                hide_incomplete: Some(true),
+                prefetch_head_tail: Some(4 * 1024 * 1024),
+                atime: Some(AtimePolicy::Noatime),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = RqbitFuseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "media-server" => Ok(Preset::MediaServer),
+            _ => Err(RqbitFuseError::InvalidArgument(format!(
+                "unknown preset '{}' (available: media-server)",
+                s
+            ))),
+        }
+    }
+}
+
+impl AtimePolicy {
+    /// Returns true if a read at `now` should replace the recorded `atime`.
+    pub fn should_update(
+        self,
+        atime: Option<std::time::SystemTime>,
+        now: std::time::SystemTime,
+    ) -> bool {
+        const RELATIME_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+        match self {
+            AtimePolicy::Noatime => false,
+            AtimePolicy::Relatime => atime.is_none_or(|atime| {
+                now.duration_since(atime)
+                    .is_ok_and(|elapsed| elapsed >= RELATIME_INTERVAL)
+            }),
+            AtimePolicy::Strict => true,
         }
     }
 }
@@ -100,16 +896,113 @@ macro_rules! merge_if_some {
 }
 
 /// Partial configuration values for merging from various sources.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ConfigSource {
     pub api_url: Option<String>,
     pub mount_point: Option<PathBuf>,
+    pub mount_point_mode: Option<u32>,
+    pub mount_point_uid: Option<u32>,
+    pub mount_point_gid: Option<u32>,
+    pub nonempty: Option<bool>,
+    pub remove_mount_point: Option<bool>,
+    pub torrent: Option<String>,
+    pub atime: Option<AtimePolicy>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub error_suffix: Option<String>,
+    pub container: Option<bool>,
+    pub allow_root: Option<bool>,
+    pub allow_other: Option<bool>,
+    pub default_permissions: Option<bool>,
+    pub extra_options: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
     pub metadata_ttl: Option<u64>,
     pub max_entries: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub entry_ttl: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub negative_ttl: Option<u64>,
+    pub hide_incomplete: Option<bool>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub prefetch_head_tail: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub read_aggregate_size: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub max_memory_bytes: Option<u64>,
+    pub cgroup_memory_percent: Option<u8>,
+    pub piece_check_enabled: Option<bool>,
+    pub readahead_profiles: Option<HashMap<String, ReadaheadProfile>>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
     pub read_timeout: Option<u64>,
+    pub max_concurrent_reads: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub readahead_size: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub readahead_buffer_secs: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub readahead_max_size: Option<u64>,
     pub log_level: Option<String>,
+    pub audit_log: Option<PathBuf>,
     pub api_username: Option<String>,
     pub api_password: Option<String>,
+    pub api_login_url: Option<String>,
+    pub api_cookie_jar: Option<PathBuf>,
+    pub api_backend: Option<ApiBackend>,
+    pub filter: Option<FilterConfig>,
+    pub quota: Option<QuotaConfig>,
+    pub trash_days: Option<u64>,
+    pub add_torrents: Option<bool>,
+    pub remove_torrents: Option<TorrentRemoval>,
+    pub stats_files: Option<bool>,
+    pub status_dirs: Option<bool>,
+    pub recent_count: Option<usize>,
+    pub recent_dir: Option<String>,
+    pub offline: Option<bool>,
+    pub retry_budget: Option<u32>,
+    pub lazy_tree_threshold: Option<usize>,
+    pub lazy_details_threshold: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub readdir_cache_ttl: Option<u64>,
+    pub readdir_cache_min_entries: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub root_refresh_interval: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub status_poll_interval: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub idle_poll_interval: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub idle_sleep_after: Option<u64>,
+    pub progressive_mount: Option<bool>,
+    pub short_reads: Option<bool>,
+    pub page_cache: Option<PageCachePolicy>,
+    pub page_cache_complete: Option<PageCacheMode>,
+    pub page_cache_downloading: Option<PageCacheMode>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub stream_idle_timeout: Option<u64>,
+    pub max_streams: Option<usize>,
+    pub max_streams_per_torrent: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub stream_seek_back_size: Option<u64>,
+    pub close_streams_on_release: Option<bool>,
+    pub max_open_handles: Option<usize>,
+    pub max_open_handles_per_uid: Option<usize>,
+    #[serde(default, deserialize_with = "units::option_duration_secs")]
+    pub handle_ttl: Option<u64>,
+    pub cache_checksums: Option<u64>,
+    #[serde(default, deserialize_with = "units::option_bytes")]
+    pub fuse_max_readahead: Option<u64>,
+    pub fuse_max_background: Option<u16>,
+    pub fuse_congestion_threshold: Option<u16>,
+    pub fuse_writeback_cache: Option<bool>,
+    pub nfs_export: Option<bool>,
+    pub control_socket: Option<PathBuf>,
+    pub run_as_user: Option<String>,
+    pub run_as_group: Option<String>,
+    pub seccomp: Option<bool>,
+    pub trash_state: Option<PathBuf>,
+    pub metadata_snapshot: Option<PathBuf>,
 }
 
 impl ConfigSource {
@@ -123,10 +1016,11 @@ impl ConfigSource {
             source.mount_point = Some(PathBuf::from(val));
         }
         if let Ok(val) = std::env::var("TORRENT_FUSE_METADATA_TTL") {
-            source.metadata_ttl = Some(val.parse().map_err(|_| {
-                RqbitFuseError::InvalidArgument(
-                    "TORRENT_FUSE_METADATA_TTL has invalid format".into(),
-                )
+            source.metadata_ttl = Some(parse_duration_secs(&val).map_err(|e| {
+                RqbitFuseError::InvalidArgument(format!(
+                    "TORRENT_FUSE_METADATA_TTL has invalid format: {}",
+                    e
+                ))
             })?);
         }
         if let Ok(val) = std::env::var("TORRENT_FUSE_MAX_ENTRIES") {
@@ -137,15 +1031,23 @@ impl ConfigSource {
             })?);
         }
         if let Ok(val) = std::env::var("TORRENT_FUSE_READ_TIMEOUT") {
-            source.read_timeout = Some(val.parse().map_err(|_| {
-                RqbitFuseError::InvalidArgument(
-                    "TORRENT_FUSE_READ_TIMEOUT has invalid format".into(),
-                )
+            source.read_timeout = Some(parse_duration_secs(&val).map_err(|e| {
+                RqbitFuseError::InvalidArgument(format!(
+                    "TORRENT_FUSE_READ_TIMEOUT has invalid format: {}",
+                    e
+                ))
             })?);
         }
         if let Ok(val) = std::env::var("TORRENT_FUSE_LOG_LEVEL") {
             source.log_level = Some(val);
         }
+        if let Ok(val) = std::env::var("TORRENT_FUSE_CONTAINER") {
+            source.container = Some(val.parse().map_err(|_| {
+                RqbitFuseError::InvalidArgument(
+                    "TORRENT_FUSE_CONTAINER must be true or false".into(),
+                )
+            })?);
+        }
 
         // Auth credentials - support combined or individual format
         if let Ok(auth_str) = std::env::var("TORRENT_FUSE_AUTH_USERPASS") {
@@ -169,12 +1071,90 @@ impl ConfigSource {
         Self {
             api_url: cli.api_url.clone(),
             mount_point: cli.mount_point.clone(),
+            mount_point_mode: None,
+            mount_point_uid: None,
+            mount_point_gid: None,
+            nonempty: cli.nonempty.then_some(true),
+            remove_mount_point: None,
+            torrent: cli.torrent.clone(),
+            atime: None,
+            uid: None,
+            gid: None,
+            file_mode: None,
+            dir_mode: None,
+            error_suffix: None,
+            container: cli.container.then_some(true),
+            allow_root: cli.allow_root.then_some(true),
+            allow_other: cli.allow_other.then_some(true),
+            default_permissions: cli.default_permissions.then_some(true),
+            extra_options: None,
+            entry_ttl: None,
+            negative_ttl: None,
+            hide_incomplete: None,
+            prefetch_head_tail: None,
+            read_aggregate_size: None,
+            max_memory_bytes: None,
+            cgroup_memory_percent: None,
+            piece_check_enabled: None,
+            readahead_profiles: None,
             metadata_ttl: None,
             max_entries: None,
             read_timeout: None,
+            max_concurrent_reads: None,
+            readahead_size: None,
+            readahead_buffer_secs: None,
+            readahead_max_size: None,
             log_level: None,
+            audit_log: None,
             api_username: cli.username.clone(),
             api_password: cli.password.clone(),
+            api_login_url: None,
+            api_cookie_jar: None,
+            api_backend: None,
+            filter: None,
+            quota: None,
+            trash_days: None,
+            add_torrents: None,
+            remove_torrents: None,
+            stats_files: None,
+            status_dirs: None,
+            recent_count: None,
+            recent_dir: None,
+            offline: cli.offline.then_some(true),
+            retry_budget: None,
+            lazy_tree_threshold: None,
+            lazy_details_threshold: None,
+            readdir_cache_ttl: None,
+            readdir_cache_min_entries: None,
+            root_refresh_interval: None,
+            status_poll_interval: None,
+            idle_poll_interval: None,
+            idle_sleep_after: None,
+            progressive_mount: None,
+            short_reads: None,
+            page_cache: None,
+            page_cache_complete: None,
+            page_cache_downloading: None,
+            stream_idle_timeout: None,
+            max_streams: None,
+            max_streams_per_torrent: None,
+            stream_seek_back_size: None,
+            close_streams_on_release: None,
+            max_open_handles: None,
+            max_open_handles_per_uid: None,
+            handle_ttl: None,
+            cache_checksums: None,
+            fuse_max_readahead: None,
+            fuse_max_background: None,
+            fuse_congestion_threshold: None,
+            fuse_writeback_cache: None,
+            nfs_export: None,
+            control_socket: None,
+            run_as_user: None,
+            run_as_group: None,
+            seccomp: None,
+            trash_state: None,
+            metadata_snapshot: None,
         }
     }
 }
@@ -185,6 +1165,12 @@ impl Config {
     }
 
     pub fn from_file(path: &PathBuf) -> Result<Self, RqbitFuseError> {
+        Self::from_file_strict(path, false)
+    }
+
+    /// Loads a config file, rejecting unknown keys when `strict` is set or
+    /// when the file itself contains `strict = true`.
+    pub fn from_file_strict(path: &PathBuf, strict: bool) -> Result<Self, RqbitFuseError> {
         let content =
             std::fs::read_to_string(path).map_err(|e| RqbitFuseError::IoError(e.to_string()))?;
 
@@ -192,14 +1178,40 @@ impl Config {
             .extension()
             .and_then(|e| e.to_str())
             .map(|e| e.to_lowercase());
-        match ext.as_deref() {
+        let raw: serde_json::Value = match ext.as_deref() {
             Some("json") => serde_json::from_str(&content)
-                .map_err(|e| RqbitFuseError::ParseError(e.to_string())),
-            _ => toml::from_str(&content).map_err(|e| RqbitFuseError::ParseError(e.to_string())),
+                .map_err(|e| RqbitFuseError::ParseError(e.to_string()))?,
+            _ => toml::from_str::<toml::Table>(&content)
+                .map_err(|e| RqbitFuseError::ParseError(e.to_string()))
+                .and_then(|table| {
+                    serde_json::to_value(table)
+                        .map_err(|e| RqbitFuseError::ParseError(e.to_string()))
+                })?,
+        };
+
+        let config: Self = serde_json::from_value(raw.clone())
+            .map_err(|e| RqbitFuseError::ParseError(e.to_string()))?;
+
+        if strict || config.strict {
+            let unknown = unknown_keys(&raw);
+            if !unknown.is_empty() {
+                return Err(RqbitFuseError::ValidationError(
+                    unknown
+                        .into_iter()
+                        .map(|key| format!("{}: unknown configuration key", key))
+                        .collect(),
+                ));
+            }
         }
+
+        Ok(config)
     }
 
     pub fn from_default_locations() -> Result<Self, RqbitFuseError> {
+        Self::from_default_locations_strict(false)
+    }
+
+    pub fn from_default_locations_strict(strict: bool) -> Result<Self, RqbitFuseError> {
         [
             dirs::config_dir().map(|d| d.join("rqbit-fuse/config.toml")),
             Some(PathBuf::from("/etc/rqbit-fuse/config.toml")),
@@ -208,7 +1220,7 @@ impl Config {
         .into_iter()
         .flatten()
         .find(|p| p.exists())
-        .map(|p| Self::from_file(&p))
+        .map(|p| Self::from_file_strict(&p, strict))
         .transpose()
         .map(|opt| opt.unwrap_or_default())
     }
@@ -216,12 +1228,126 @@ impl Config {
     pub fn merge(mut self, source: ConfigSource) -> Self {
         merge_if_some!(self, api_url, source.api_url);
         merge_if_some!(self, mount_point, source.mount_point);
+        merge_if_some!(self, mount_point_mode, source.mount_point_mode, option);
+        merge_if_some!(self, mount_point_uid, source.mount_point_uid, option);
+        merge_if_some!(self, mount_point_gid, source.mount_point_gid, option);
+        merge_if_some!(self, nonempty, source.nonempty);
+        merge_if_some!(self, remove_mount_point, source.remove_mount_point);
+        merge_if_some!(self, torrent, source.torrent, option);
+        merge_if_some!(self, atime, source.atime);
+        merge_if_some!(self, uid, source.uid, option);
+        merge_if_some!(self, gid, source.gid, option);
+        merge_if_some!(self, file_mode, source.file_mode);
+        merge_if_some!(self, dir_mode, source.dir_mode);
+        merge_if_some!(self, error_suffix, source.error_suffix, option);
+        merge_if_some!(self, container, source.container);
+        merge_if_some!(self, allow_root, source.allow_root);
+        merge_if_some!(self, allow_other, source.allow_other);
+        merge_if_some!(self, default_permissions, source.default_permissions);
+        merge_if_some!(self, extra_options, source.extra_options);
+        merge_if_some!(self, entry_ttl, source.entry_ttl);
+        merge_if_some!(self, negative_ttl, source.negative_ttl);
+        merge_if_some!(self, hide_incomplete, source.hide_incomplete);
+        merge_if_some!(self, prefetch_head_tail, source.prefetch_head_tail);
+        merge_if_some!(self, read_aggregate_size, source.read_aggregate_size);
+        merge_if_some!(self, max_memory_bytes, source.max_memory_bytes);
+        merge_if_some!(self, cgroup_memory_percent, source.cgroup_memory_percent);
+        merge_if_some!(self, piece_check_enabled, source.piece_check_enabled);
+        merge_if_some!(self, readahead_profiles, source.readahead_profiles);
         merge_if_some!(self, metadata_ttl, source.metadata_ttl);
         merge_if_some!(self, max_entries, source.max_entries);
         merge_if_some!(self, read_timeout, source.read_timeout);
+        merge_if_some!(self, max_concurrent_reads, source.max_concurrent_reads);
+        merge_if_some!(self, readahead_size, source.readahead_size);
+        merge_if_some!(self, readahead_buffer_secs, source.readahead_buffer_secs);
+        merge_if_some!(self, readahead_max_size, source.readahead_max_size);
         merge_if_some!(self, log_level, source.log_level);
+        merge_if_some!(self, audit_log, source.audit_log, option);
+        merge_if_some!(self, filter, source.filter);
+        merge_if_some!(self, quota, source.quota);
+        merge_if_some!(self, trash_days, source.trash_days);
+        merge_if_some!(self, add_torrents, source.add_torrents);
+        merge_if_some!(self, remove_torrents, source.remove_torrents);
+        merge_if_some!(self, stats_files, source.stats_files);
+        merge_if_some!(self, status_dirs, source.status_dirs);
+        merge_if_some!(self, recent_count, source.recent_count);
+        merge_if_some!(self, recent_dir, source.recent_dir);
+        merge_if_some!(self, offline, source.offline);
+        merge_if_some!(self, retry_budget, source.retry_budget);
+        merge_if_some!(self, lazy_tree_threshold, source.lazy_tree_threshold);
+        merge_if_some!(self, lazy_details_threshold, source.lazy_details_threshold);
+        merge_if_some!(self, readdir_cache_ttl, source.readdir_cache_ttl);
+        merge_if_some!(
+            self,
+            readdir_cache_min_entries,
+            source.readdir_cache_min_entries
+        );
+        merge_if_some!(self, root_refresh_interval, source.root_refresh_interval);
+        merge_if_some!(self, status_poll_interval, source.status_poll_interval);
+        merge_if_some!(self, idle_poll_interval, source.idle_poll_interval);
+        merge_if_some!(self, idle_sleep_after, source.idle_sleep_after);
+        merge_if_some!(self, progressive_mount, source.progressive_mount);
+        merge_if_some!(self, short_reads, source.short_reads);
+        merge_if_some!(self, page_cache, source.page_cache);
+        merge_if_some!(
+            self,
+            page_cache_complete,
+            source.page_cache_complete,
+            option
+        );
+        merge_if_some!(
+            self,
+            page_cache_downloading,
+            source.page_cache_downloading,
+            option
+        );
+        merge_if_some!(self, stream_idle_timeout, source.stream_idle_timeout);
+        merge_if_some!(self, max_streams, source.max_streams);
+        merge_if_some!(
+            self,
+            max_streams_per_torrent,
+            source.max_streams_per_torrent
+        );
+        merge_if_some!(self, stream_seek_back_size, source.stream_seek_back_size);
+        merge_if_some!(
+            self,
+            close_streams_on_release,
+            source.close_streams_on_release
+        );
+        merge_if_some!(self, max_open_handles, source.max_open_handles);
+        merge_if_some!(
+            self,
+            max_open_handles_per_uid,
+            source.max_open_handles_per_uid
+        );
+        merge_if_some!(self, handle_ttl, source.handle_ttl);
+        merge_if_some!(self, cache_checksums, source.cache_checksums);
+        merge_if_some!(self, fuse_max_readahead, source.fuse_max_readahead, option);
+        merge_if_some!(
+            self,
+            fuse_max_background,
+            source.fuse_max_background,
+            option
+        );
+        merge_if_some!(
+            self,
+            fuse_congestion_threshold,
+            source.fuse_congestion_threshold,
+            option
+        );
+        merge_if_some!(self, fuse_writeback_cache, source.fuse_writeback_cache);
+        merge_if_some!(self, nfs_export, source.nfs_export);
+        merge_if_some!(self, control_socket, source.control_socket, option);
+        merge_if_some!(self, run_as_user, source.run_as_user, option);
+        merge_if_some!(self, run_as_group, source.run_as_group, option);
+        merge_if_some!(self, seccomp, source.seccomp);
+        merge_if_some!(self, trash_state, source.trash_state, option);
+        merge_if_some!(self, metadata_snapshot, source.metadata_snapshot, option);
         merge_if_some!(self, api_username, source.api_username, option);
         merge_if_some!(self, api_password, source.api_password, option);
+        merge_if_some!(self, api_login_url, source.api_login_url, option);
+        merge_if_some!(self, api_cookie_jar, source.api_cookie_jar, option);
+        merge_if_some!(self, api_backend, source.api_backend);
         self
     }
 
@@ -230,11 +1356,212 @@ impl Config {
     }
 
     pub fn load_with_cli(cli: &CliArgs) -> Result<Self, RqbitFuseError> {
-        Ok(Self::from_default_locations()?
+        let base = match &cli.config_file {
+            Some(path) => Self::from_file_strict(path, cli.strict_config)?,
+            None => Self::from_default_locations_strict(cli.strict_config)?,
+        };
+        let base = match cli.preset.or(base.preset) {
+            Some(preset) => Self {
+                preset: Some(preset),
+                ..base.merge(preset.source())
+            },
+            None => base,
+        };
+        let base = match &cli.profile {
+            Some(name) => base.with_profile(name)?,
+            None => base,
+        };
+        Ok(base
             .merge(ConfigSource::from_env()?)
             .merge(ConfigSource::from_cli(cli)))
     }
 
+    /// Returns one config per mount point served by this process.
+    ///
+    /// Without `[[mounts]]` this is just the config itself; otherwise each
+    /// entry is merged over the top-level settings.
+    pub fn mount_configs(&self) -> Vec<Config> {
+        let mut base = self.clone();
+        let mounts = std::mem::take(&mut base.mounts);
+        if mounts.is_empty() {
+            return vec![base];
+        }
+        mounts
+            .into_iter()
+            .map(|source| base.clone().merge(source))
+            .collect()
+    }
+
+    /// Persistent stream settings for the API client.
+    pub fn stream_config(&self) -> crate::api::streaming::StreamConfig {
+        crate::api::streaming::StreamConfig {
+            idle_timeout: std::time::Duration::from_secs(self.stream_idle_timeout),
+            max_streams: self.max_streams,
+            max_streams_per_torrent: self.max_streams_per_torrent,
+            seek_back_buffer_size: self.stream_seek_back_size as usize,
+            prefetch_size: self.readahead_size as usize,
+            prefetch_buffer: std::time::Duration::from_secs(self.readahead_buffer_secs),
+            max_prefetch_size: self.readahead_max_size as usize,
+            max_concurrent_reads: self.max_concurrent_reads,
+            max_memory: self.max_memory_bytes,
+        }
+    }
+
+    /// Derives the memory cap from the cgroup's `memory.max`, unless
+    /// `max_memory_bytes` is set, and scales the default readahead windows
+    /// to it so one stream can't take the whole budget.
+    pub fn apply_cgroup_limit(&mut self, limit: u64) {
+        if self.max_memory_bytes != 0 || self.cgroup_memory_percent == 0 {
+            return;
+        }
+        self.max_memory_bytes =
+            (limit as u128 * self.cgroup_memory_percent.min(100) as u128 / 100) as u64;
+        if self.readahead_size == default_readahead_size() {
+            self.readahead_size = self.readahead_size.min(self.max_memory_bytes / 8);
+        }
+        if self.readahead_max_size == default_readahead_max_size() {
+            self.readahead_max_size = self.readahead_max_size.min(self.max_memory_bytes / 4);
+        }
+    }
+
+    /// The readahead profile for a file, chosen by its extension regardless
+    /// of case.
+    pub fn readahead_profile(&self, name: &str) -> Option<&ReadaheadProfile> {
+        let (_, ext) = name.rsplit_once('.')?;
+        self.readahead_profiles
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            .map(|(_, profile)| profile)
+    }
+
+    /// Returns the control socket path, falling back to the per-user default.
+    pub fn control_socket_path(&self) -> PathBuf {
+        self.control_socket
+            .clone()
+            .unwrap_or_else(crate::control::default_socket_path)
+    }
+
+    /// Returns the gateway session of `api_login_url`, if one is set.
+    pub fn session_auth(&self) -> anyhow::Result<Option<crate::api::SessionAuth>> {
+        let (Some(login_url), Some(username), Some(password)) =
+            (&self.api_login_url, &self.api_username, &self.api_password)
+        else {
+            return Ok(None);
+        };
+        crate::api::SessionAuth::new(
+            login_url.clone(),
+            username.clone(),
+            password.clone(),
+            self.api_cookie_jar.clone(),
+        )
+        .map(Some)
+    }
+
+    /// Returns the trash state file, falling back to the per-user default.
+    pub fn trash_state_path(&self) -> PathBuf {
+        self.trash_state.clone().unwrap_or_else(|| {
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .unwrap_or_else(std::env::temp_dir)
+                .join("rqbit-fuse")
+                .join("trash.json")
+        })
+    }
+
+    /// What happens to the page cache of a complete or downloading file when
+    /// it is opened: `page_cache_complete` or `page_cache_downloading` if
+    /// set, otherwise as `page_cache` says.
+    pub fn page_cache_mode(&self, complete: bool) -> PageCacheMode {
+        let (complete_mode, downloading_mode) = self.page_cache.modes();
+        if complete {
+            self.page_cache_complete.unwrap_or(complete_mode)
+        } else {
+            self.page_cache_downloading.unwrap_or(downloading_mode)
+        }
+    }
+
+    /// Returns the metadata snapshot file, falling back to the per-user
+    /// default.
+    pub fn metadata_snapshot_path(&self) -> PathBuf {
+        self.metadata_snapshot.clone().unwrap_or_else(|| {
+            dirs::state_dir()
+                .or_else(dirs::data_local_dir)
+                .unwrap_or_else(std::env::temp_dir)
+                .join("rqbit-fuse")
+                .join("snapshot.json")
+        })
+    }
+
+    /// Applies the `[profile.<name>]` section on top of the base settings.
+    pub fn with_profile(mut self, name: &str) -> Result<Self, RqbitFuseError> {
+        let source = self.profile.get(name).cloned().ok_or_else(|| {
+            let mut available: Vec<_> = self.profile.keys().map(String::as_str).collect();
+            available.sort_unstable();
+            RqbitFuseError::InvalidArgument(format!(
+                "unknown profile '{}' (available: {})",
+                name,
+                if available.is_empty() {
+                    "none".to_string()
+                } else {
+                    available.join(", ")
+                }
+            ))
+        })?;
+        self = self.merge(source);
+        Ok(self)
+    }
+
+    /// Checks that every `[[mounts]]` entry has its own mount point.
+    pub fn validate_mounts(&self) -> Result<(), RqbitFuseError> {
+        let mut mount_points = std::collections::HashSet::new();
+        for (i, mount) in self.mounts.iter().enumerate() {
+            match &mount.mount_point {
+                None => {
+                    return Err(RqbitFuseError::ValidationError(vec![format!(
+                        "mounts[{}].mount_point: Each mount must set a mount point",
+                        i
+                    )]));
+                }
+                Some(path) if !mount_points.insert(path) => {
+                    return Err(RqbitFuseError::ValidationError(vec![format!(
+                        "mounts[{}].mount_point: {} is used by more than one mount",
+                        i,
+                        path.display()
+                    )]));
+                }
+                Some(_) => {}
+            }
+            // Settings of the whole process, not of one of its mounts
+            let process_wide = [
+                ("log_level", mount.log_level.is_some()),
+                ("control_socket", mount.control_socket.is_some()),
+                ("run_as_user", mount.run_as_user.is_some()),
+                ("run_as_group", mount.run_as_group.is_some()),
+                ("seccomp", mount.seccomp.is_some()),
+            ];
+            if let Some((key, _)) = process_wide.iter().find(|(_, set)| *set) {
+                return Err(RqbitFuseError::ValidationError(vec![format!(
+                    "mounts[{}].{}: Can only be set at the top level",
+                    i, key
+                )]));
+            }
+        }
+        if !self.mounts.is_empty() {
+            for (i, mount) in self.mount_configs().iter().enumerate() {
+                mount.validate().map_err(|e| match e {
+                    RqbitFuseError::ValidationError(errors) => RqbitFuseError::ValidationError(
+                        errors
+                            .into_iter()
+                            .map(|error| format!("mounts[{}].{}", i, error))
+                            .collect(),
+                    ),
+                    other => other,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn validate(&self) -> Result<(), RqbitFuseError> {
         if self.api_url.is_empty() {
             return Err(RqbitFuseError::ValidationError(vec![
@@ -255,18 +1582,135 @@ impl Config {
             ]));
         }
 
-        let valid_levels = ["error", "warn", "info", "debug", "trace"];
-        if !valid_levels.contains(&self.log_level.as_str()) {
-            return Err(RqbitFuseError::ValidationError(vec![format!(
-                "log_level: Invalid log level '{}'. Valid levels: {}",
-                self.log_level,
-                valid_levels.join(", ")
-            )]));
+        crate::fs::filter::TorrentFilter::new(&self.filter)?;
+
+        for (key, mode) in [("file_mode", self.file_mode), ("dir_mode", self.dir_mode)] {
+            if mode > 0o777 {
+                return Err(RqbitFuseError::ValidationError(vec![format!(
+                    "{}: Mode {:o} has bits outside 0777",
+                    key, mode
+                )]));
+            }
         }
 
-        Ok(())
-    }
-}
+        if let Some(suffix) = &self.error_suffix {
+            if suffix.contains(['/', '\0']) {
+                return Err(RqbitFuseError::ValidationError(vec![
+                    "error_suffix: Suffix must not contain '/' or NUL".to_string(),
+                ]));
+            }
+        }
+
+        if self.run_as_group.is_some() && self.run_as_user.is_none() {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "run_as_group: Needs run_as_user".to_string(),
+            ]));
+        }
+
+        if self.trash_days > 0 && self.torrent.is_some() {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "trash_days: Not supported when mounting a single torrent".to_string(),
+            ]));
+        }
+
+        if self.add_torrents && self.torrent.is_some() {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "add_torrents: Not supported when mounting a single torrent".to_string(),
+            ]));
+        }
+
+        if self.remove_torrents != TorrentRemoval::Off && self.torrent.is_some() {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "remove_torrents: Not supported when mounting a single torrent".to_string(),
+            ]));
+        }
+
+        if self.stats_files && self.torrent.is_some() {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "stats_files: Not supported when mounting a single torrent".to_string(),
+            ]));
+        }
+
+        if self.recent_count > 0 {
+            if self.torrent.is_some() {
+                return Err(RqbitFuseError::ValidationError(vec![
+                    "recent_count: Not supported when mounting a single torrent".to_string(),
+                ]));
+            }
+            let reserved = [
+                ".",
+                "..",
+                crate::fs::virtual_files::CONTROL_DIR,
+                crate::fs::trash::TRASH_DIR,
+            ];
+            if self.recent_dir.is_empty()
+                || self.recent_dir.contains('/')
+                || reserved.contains(&self.recent_dir.as_str())
+            {
+                return Err(RqbitFuseError::ValidationError(vec![format!(
+                    "recent_dir: {:?} is not a usable directory name",
+                    self.recent_dir
+                )]));
+            }
+        }
+
+        if self.api_login_url.is_some()
+            && (self.api_username.is_none() || self.api_password.is_none())
+        {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "api_login_url: Needs api_username and api_password to log in with".to_string(),
+            ]));
+        }
+
+        if self.api_backend == ApiBackend::Transmission {
+            if self.api_login_url.is_some() {
+                return Err(RqbitFuseError::ValidationError(vec![
+                    "api_login_url: Not supported with the transmission backend".to_string(),
+                ]));
+            }
+            if self.piece_check_enabled {
+                return Err(RqbitFuseError::ValidationError(vec![
+                    "piece_check_enabled: Not supported with the transmission backend".to_string(),
+                ]));
+            }
+        }
+
+        if self.status_poll_interval == 0 {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "status_poll_interval: Must be greater than 0".to_string(),
+            ]));
+        }
+
+        if self.mount_point_mode.is_some_and(|mode| mode > 0o7777) {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "mount_point_mode: Must be a permission mode such as 0o755".to_string(),
+            ]));
+        }
+
+        if self.seccomp && !cfg!(feature = "seccomp") {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "seccomp: rqbit-fuse was built without the `seccomp` feature".to_string(),
+            ]));
+        }
+
+        if self.extra_options.iter().any(|o| o.trim().is_empty()) {
+            return Err(RqbitFuseError::ValidationError(vec![
+                "extra_options: Options must not be empty".to_string(),
+            ]));
+        }
+
+        let valid_levels = ["error", "warn", "info", "debug", "trace"];
+        if !valid_levels.contains(&self.log_level.as_str()) {
+            return Err(RqbitFuseError::ValidationError(vec![format!(
+                "log_level: Invalid log level '{}'. Valid levels: {}",
+                self.log_level,
+                valid_levels.join(", ")
+            )]));
+        }
+
+        self.validate_mounts()
+    }
+}
 
 /// Command-line arguments that override configuration values.
 #[derive(Debug, Clone, Default)]
@@ -276,9 +1720,78 @@ pub struct CliArgs {
     pub config_file: Option<PathBuf>,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub strict_config: bool,
+    pub profile: Option<String>,
+    pub torrent: Option<String>,
+    pub container: bool,
+    pub allow_root: bool,
+    pub allow_other: bool,
+    pub default_permissions: bool,
+    pub preset: Option<Preset>,
+    pub offline: bool,
+    pub nonempty: bool,
+}
+
+/// Returns the dotted paths of keys in `raw` that `Config` does not know about.
+fn unknown_keys(raw: &serde_json::Value) -> Vec<String> {
+    let known = serde_json::to_value(Config::default()).unwrap_or_default();
+    let mut unknown = Vec::new();
+    collect_unknown_keys(raw, &known, "", &mut unknown);
+
+    let mut known_source = serde_json::to_value(ConfigSource::default()).unwrap_or_default();
+    // Sections unset in a source are null; check their keys like the top level
+    if let (Some(source), Some(config)) = (known_source.as_object_mut(), known.as_object()) {
+        for (key, value) in source.iter_mut() {
+            if value.is_null() {
+                if let Some(config_value) = config.get(key) {
+                    *value = config_value.clone();
+                }
+            }
+        }
+    }
+    if let Some(profiles) = raw.get("profile").and_then(|p| p.as_object()) {
+        for (name, profile) in profiles {
+            let prefix = format!("profile.{}", name);
+            collect_unknown_keys(profile, &known_source, &prefix, &mut unknown);
+        }
+    }
+    if let Some(mounts) = raw.get("mounts").and_then(|m| m.as_array()) {
+        for (i, mount) in mounts.iter().enumerate() {
+            let prefix = format!("mounts[{}]", i);
+            collect_unknown_keys(mount, &known_source, &prefix, &mut unknown);
+        }
+    }
+    unknown
+}
+
+fn collect_unknown_keys(
+    raw: &serde_json::Value,
+    known: &serde_json::Value,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    let (Some(raw), Some(known)) = (raw.as_object(), known.as_object()) else {
+        return;
+    };
+    for (key, value) in raw {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match known.get(key) {
+            // Empty objects in the defaults are free-form maps
+            Some(known_value) if known_value.as_object().is_some_and(|o| !o.is_empty()) => {
+                collect_unknown_keys(value, known_value, &path, unknown)
+            }
+            Some(_) => {}
+            None => unknown.push(path),
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
     use std::io::Write;
@@ -366,6 +1879,16 @@ max_concurrent_reads = 20"#,
             config_file: None,
             username: None,
             password: None,
+            strict_config: false,
+            profile: None,
+            torrent: None,
+            container: false,
+            allow_root: false,
+            allow_other: false,
+            default_permissions: false,
+            preset: None,
+            offline: false,
+            nonempty: false,
         };
 
         let merged = config.merge(ConfigSource::from_cli(&cli));
@@ -383,6 +1906,16 @@ max_concurrent_reads = 20"#,
             config_file: None,
             username: Some("testuser".to_string()),
             password: Some("testpass".to_string()),
+            strict_config: false,
+            profile: None,
+            torrent: None,
+            container: false,
+            allow_root: false,
+            allow_other: false,
+            default_permissions: false,
+            preset: None,
+            offline: false,
+            nonempty: false,
         };
 
         let merged = config.merge(ConfigSource::from_cli(&cli));
@@ -468,4 +2001,871 @@ max_concurrent_reads = 20"#,
             assert!(result.is_err(), "Level {} should be invalid", level);
         }
     }
+
+    fn write_config_file(content: &str, ext: &str) -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(format!("config.{}", ext));
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_unknown_keys_ignored_by_default() {
+        let (_dir, path) = write_config_file("readhead_size = 1024\nmetadata_ttl = 5", "toml");
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.metadata_ttl, 5);
+        assert_eq!(config.readahead_size, default_readahead_size());
+    }
+
+    #[rstest::rstest]
+    #[case("readhead_size = 1024", "toml")]
+    #[case(r#"{"readhead_size": 1024}"#, "json")]
+    fn test_strict_rejects_unknown_keys(#[case] content: &str, #[case] ext: &str) {
+        let (_dir, path) = write_config_file(content, ext);
+        let err = Config::from_file_strict(&path, true).unwrap_err();
+        match err {
+            RqbitFuseError::ValidationError(errors) => {
+                assert_eq!(errors, vec!["readhead_size: unknown configuration key"]);
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_strict_key_in_file_enables_strict_mode() {
+        let (_dir, path) = write_config_file("strict = true\nreadhead_size = 1024", "toml");
+        assert!(matches!(
+            Config::from_file(&path),
+            Err(RqbitFuseError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_accepts_known_keys() {
+        let (_dir, path) = write_config_file(
+            "strict = true\napi_url = \"http://localhost:3030\"\nreadahead_size = 1024",
+            "toml",
+        );
+        let config = Config::from_file(&path).unwrap();
+        assert!(config.strict);
+        assert_eq!(config.readahead_size, 1024);
+    }
+
+    #[test]
+    fn test_load_with_cli_strict_config_file() {
+        let (_dir, path) = write_config_file("readhead_size = 1024", "toml");
+        let cli = CliArgs {
+            config_file: Some(path),
+            strict_config: true,
+            ..Default::default()
+        };
+        assert!(Config::load_with_cli(&cli).is_err());
+    }
+
+    #[rstest::rstest]
+    #[case(
+        "toml",
+        "readahead_size = \"32MiB\"\nread_timeout = \"45s\"\nmetadata_ttl = \"5m\""
+    )]
+    #[case(
+        "json",
+        r#"{"readahead_size": "32MiB", "read_timeout": "45s", "metadata_ttl": "5m"}"#
+    )]
+    fn test_human_friendly_units(#[case] ext: &str, #[case] content: &str) {
+        let c = parse_config_content(content, ext);
+        assert_eq!(c.readahead_size, 32 * 1024 * 1024);
+        assert_eq!(c.read_timeout, 45);
+        assert_eq!(c.metadata_ttl, 300);
+    }
+
+    #[test]
+    fn test_read_aggregate_size() {
+        assert_eq!(Config::default().read_aggregate_size, 0);
+        let c = parse_config_content("read_aggregate_size = \"1MiB\"", "toml");
+        assert_eq!(c.read_aggregate_size, 1024 * 1024);
+    }
+
+    #[test]
+    fn test_max_memory_bytes() {
+        assert_eq!(Config::default().stream_config().max_memory, 0);
+        let c = parse_config_content("max_memory_bytes = \"256MiB\"", "toml");
+        assert_eq!(c.stream_config().max_memory, 256 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_apply_cgroup_limit() {
+        let mib = 1024 * 1024;
+        let mut c = Config::default();
+        c.apply_cgroup_limit(256 * mib);
+        assert_eq!(c.max_memory_bytes, 64 * mib);
+        assert_eq!(c.readahead_size, 8 * mib);
+        assert_eq!(c.readahead_max_size, 16 * mib);
+
+        // Large limits leave the defaults alone
+        let mut c = Config::default();
+        c.apply_cgroup_limit(64 * 1024 * mib);
+        assert_eq!(c.max_memory_bytes, 16 * 1024 * mib);
+        assert_eq!(c.readahead_size, default_readahead_size());
+
+        // An explicit cap or percentage of 0 wins over the cgroup
+        let mut c = parse_config_content("max_memory_bytes = \"1GiB\"", "toml");
+        c.apply_cgroup_limit(256 * mib);
+        assert_eq!(c.max_memory_bytes, 1024 * mib);
+        let mut c = parse_config_content("cgroup_memory_percent = 0", "toml");
+        c.apply_cgroup_limit(256 * mib);
+        assert_eq!(c.max_memory_bytes, 0);
+    }
+
+    #[test]
+    fn test_readdir_cache_settings() {
+        let c = Config::default();
+        assert_eq!(c.readdir_cache_ttl, 10);
+        assert_eq!(c.readdir_cache_min_entries, 256);
+        let c = parse_config_content(
+            "readdir_cache_ttl = \"1m\"\nreaddir_cache_min_entries = 50",
+            "toml",
+        );
+        assert_eq!(c.readdir_cache_ttl, 60);
+        assert_eq!(c.readdir_cache_min_entries, 50);
+    }
+
+    #[rstest::rstest]
+    #[case("", PageCachePolicy::KeepComplete)]
+    #[case("page_cache = \"drop\"", PageCachePolicy::Drop)]
+    #[case("page_cache = \"keep\"", PageCachePolicy::Keep)]
+    #[case("page_cache = \"bypass\"", PageCachePolicy::Bypass)]
+    fn test_page_cache_policy(#[case] content: &str, #[case] expected: PageCachePolicy) {
+        assert_eq!(parse_config_content(content, "toml").page_cache, expected);
+    }
+
+    #[test]
+    fn test_page_cache_modes() {
+        let c = Config::default();
+        assert_eq!(c.page_cache_mode(true), PageCacheMode::Keep);
+        assert_eq!(c.page_cache_mode(false), PageCacheMode::Drop);
+
+        let c = parse_config_content(
+            "page_cache = \"keep\"\npage_cache_downloading = \"bypass\"",
+            "toml",
+        );
+        assert_eq!(c.page_cache_mode(true), PageCacheMode::Keep);
+        assert_eq!(c.page_cache_mode(false), PageCacheMode::Bypass);
+
+        let c = parse_config_content("page_cache_complete = \"drop\"", "toml");
+        assert_eq!(c.page_cache_mode(true), PageCacheMode::Drop);
+        assert_eq!(c.page_cache_mode(false), PageCacheMode::Drop);
+    }
+
+    #[test]
+    fn test_short_reads() {
+        assert!(!Config::default().short_reads);
+        let c = parse_config_content("short_reads = true", "toml");
+        assert!(c.short_reads);
+    }
+
+    #[test]
+    fn test_progressive_mount() {
+        assert!(!Config::default().progressive_mount);
+        let c = parse_config_content("progressive_mount = true", "toml");
+        assert!(c.progressive_mount);
+    }
+
+    #[test]
+    fn test_root_refresh_interval() {
+        assert_eq!(Config::default().root_refresh_interval, 5);
+        let c = parse_config_content("root_refresh_interval = \"30s\"", "toml");
+        assert_eq!(c.root_refresh_interval, 30);
+    }
+
+    #[test]
+    fn test_status_poll_intervals() {
+        let c = Config::default();
+        assert_eq!((c.status_poll_interval, c.idle_poll_interval), (30, 60));
+        let c = parse_config_content(
+            "status_poll_interval = \"10s\"\nidle_poll_interval = \"5m\"",
+            "toml",
+        );
+        assert_eq!((c.status_poll_interval, c.idle_poll_interval), (10, 300));
+        assert_eq!(c.idle_sleep_after, 0);
+        let c = parse_config_content("idle_sleep_after = \"15m\"", "toml");
+        assert_eq!(c.idle_sleep_after, 900);
+        let c = Config {
+            status_poll_interval: 0,
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_piece_check_enabled() {
+        assert!(!Config::default().piece_check_enabled);
+        let c = parse_config_content("piece_check_enabled = true", "toml");
+        assert!(c.piece_check_enabled);
+    }
+
+    #[test]
+    fn test_readahead_autoscale() {
+        let c = Config::default();
+        assert_eq!(c.readahead_buffer_secs, 0);
+        assert!(c.stream_config().prefetch_buffer.is_zero());
+        let c = parse_config_content(
+            "readahead_buffer_secs = \"20s\"\nreadahead_max_size = \"1GiB\"",
+            "toml",
+        );
+        let stream = c.stream_config();
+        assert_eq!(stream.prefetch_buffer, std::time::Duration::from_secs(20));
+        assert_eq!(stream.max_prefetch_size, 1024 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_readahead_profiles() {
+        let c = parse_config_content(
+            "[readahead_profiles]\nmkv = \"64MiB sequential\"\n\".jpg\" = \"whole-file\"\niso = \"128MiB\"\ntxt = \"none\"",
+            "toml",
+        );
+        let mkv = c.readahead_profile("Movie.MKV").unwrap();
+        assert_eq!(
+            mkv.prefetch_size(c.readahead_size, 1 << 30),
+            64 * 1024 * 1024
+        );
+        let jpg = c.readahead_profile("cover.jpg").unwrap();
+        assert_eq!(jpg.policy, ReadaheadPolicy::WholeFile);
+        assert_eq!(jpg.prefetch_size(c.readahead_size, 5000), 5000);
+        let iso = c.readahead_profile("disc.iso").unwrap();
+        assert_eq!(iso.policy, ReadaheadPolicy::Sequential);
+        assert_eq!(iso.size, Some(128 * 1024 * 1024));
+        assert_eq!(
+            c.readahead_profile("notes.txt")
+                .unwrap()
+                .prefetch_size(c.readahead_size, 100),
+            0
+        );
+        assert!(c.readahead_profile("README").is_none());
+        assert!(c.readahead_profile("movie.mp4").is_none());
+
+        for invalid in [
+            "",
+            "fast",
+            "1MiB 2MiB",
+            "64MiB whole-file",
+            "none sequential",
+        ] {
+            assert!(
+                invalid.parse::<ReadaheadProfile>().is_err(),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_stream_settings() {
+        let c = parse_config_content(
+            "stream_idle_timeout = \"2m\"\nmax_streams = 8\nmax_streams_per_torrent = 2\nstream_seek_back_size = \"512KiB\"\nclose_streams_on_release = true",
+            "toml",
+        );
+        let stream = c.stream_config();
+        assert_eq!(stream.idle_timeout, std::time::Duration::from_secs(120));
+        assert_eq!(stream.max_streams, 8);
+        assert_eq!(stream.max_streams_per_torrent, 2);
+        assert_eq!(stream.seek_back_buffer_size, 512 * 1024);
+        assert!(c.close_streams_on_release);
+
+        let defaults = Config::default().stream_config();
+        assert_eq!(defaults.max_streams, 50);
+        assert_eq!(defaults.max_concurrent_reads, 10);
+    }
+
+    #[test]
+    fn test_handle_settings() {
+        let c = Config::default();
+        assert_eq!((c.max_open_handles, c.max_open_handles_per_uid), (0, 0));
+        assert_eq!(c.handle_ttl, 3600);
+
+        let c = parse_config_content(
+            "max_open_handles = 4096\nmax_open_handles_per_uid = 256\nhandle_ttl = \"10m\"",
+            "toml",
+        );
+        assert_eq!(c.max_open_handles, 4096);
+        assert_eq!(c.max_open_handles_per_uid, 256);
+        assert_eq!(c.handle_ttl, 600);
+        assert_eq!(c.cache_checksums, 0);
+        let c = parse_config_content("cache_checksums = 8", "toml");
+        assert_eq!(c.cache_checksums, 8);
+    }
+
+    #[rstest::rstest]
+    #[case("", AtimePolicy::Relatime)]
+    #[case("atime = \"noatime\"", AtimePolicy::Noatime)]
+    #[case("atime = \"strict\"", AtimePolicy::Strict)]
+    fn test_atime_policy(#[case] content: &str, #[case] expected: AtimePolicy) {
+        assert_eq!(parse_config_content(content, "toml").atime, expected);
+    }
+
+    #[test]
+    fn test_fuse_session_settings() {
+        let c = parse_config_content(
+            "fuse_max_readahead = \"1MiB\"\nfuse_max_background = 64\nfuse_congestion_threshold = 48\nfuse_writeback_cache = true",
+            "toml",
+        );
+        assert_eq!(c.fuse_max_readahead, Some(1024 * 1024));
+        assert_eq!(c.fuse_max_background, Some(64));
+        assert_eq!(c.fuse_congestion_threshold, Some(48));
+        assert!(c.fuse_writeback_cache);
+
+        let defaults = Config::default();
+        assert_eq!(defaults.fuse_max_readahead, None);
+        assert!(!defaults.fuse_writeback_cache);
+    }
+
+    #[test]
+    fn test_ownership_and_modes() {
+        let c = parse_config_content(
+            "uid = 1000\ngid = 100\nfile_mode = 0o440\ndir_mode = 0o550",
+            "toml",
+        );
+        assert_eq!((c.uid, c.gid), (Some(1000), Some(100)));
+        assert_eq!((c.file_mode, c.dir_mode), (0o440, 0o550));
+
+        let defaults = Config::default();
+        assert_eq!((defaults.file_mode, defaults.dir_mode), (0o444, 0o555));
+
+        let invalid = Config {
+            file_mode: 0o4755,
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_error_suffix() {
+        let c = parse_config_content("error_suffix = \" [failed]\"", "toml");
+        assert_eq!(c.error_suffix.as_deref(), Some(" [failed]"));
+        assert!(c.validate().is_ok());
+
+        let invalid = Config {
+            error_suffix: Some("/failed".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_media_server_preset() {
+        let (_dir, path) = write_config_file(
+            "preset = \"media-server\"\nentry_ttl = 5\nreadahead_size = 1024",
+            "toml",
+        );
+        let c = Config::load_with_cli(&CliArgs {
+            config_file: Some(path.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(c.preset, Some(Preset::MediaServer));
+        // Like a profile, the preset wins over the file's own settings
+        assert_eq!(c.entry_ttl, 300);
+        assert_eq!(c.negative_ttl, 60);
+        assert!(c.hide_incomplete);
+        assert_eq!(c.prefetch_head_tail, 4 * 1024 * 1024);
+        assert_eq!(c.atime, AtimePolicy::Noatime);
+        assert_eq!(c.readahead_size, 1024);
+
+        let (_dir, path) = write_config_file("entry_ttl = 5", "toml");
+        let c = Config::load_with_cli(&CliArgs {
+            config_file: Some(path),
+            preset: Some("media-server".parse().unwrap()),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(c.entry_ttl, 300);
+
+        assert!("plex".parse::<Preset>().is_err());
+    }
+
+    #[test]
+    fn test_atime_policy_should_update() {
+        use std::time::{Duration, SystemTime};
+        let now = SystemTime::now();
+        let hour_ago = now - Duration::from_secs(3600);
+        let two_days_ago = now - Duration::from_secs(2 * 24 * 3600);
+
+        assert!(!AtimePolicy::Noatime.should_update(None, now));
+        assert!(AtimePolicy::Relatime.should_update(None, now));
+        assert!(!AtimePolicy::Relatime.should_update(Some(hour_ago), now));
+        assert!(AtimePolicy::Relatime.should_update(Some(two_days_ago), now));
+        assert!(AtimePolicy::Strict.should_update(Some(hour_ago), now));
+    }
+
+    #[test]
+    fn test_invalid_unit_is_parse_error() {
+        let (_dir, path) = write_config_file("readahead_size = \"32 parsecs\"", "toml");
+        assert!(matches!(
+            Config::from_file(&path),
+            Err(RqbitFuseError::ParseError(_))
+        ));
+    }
+
+    const PROFILES: &str = r#"
+api_url = "http://127.0.0.1:3030"
+mount_point = "/mnt/torrents"
+
+[profile.home]
+api_url = "http://192.168.1.50:3030"
+mount_point = "/media/torrents"
+
+[profile.seedbox]
+api_url = "https://seedbox.example.com:3030"
+api_username = "me"
+read_timeout = "2m"
+readahead_size = "8MiB"
+"#;
+
+    #[test]
+    fn test_with_profile() {
+        let config = parse_config_content(PROFILES, "toml")
+            .with_profile("seedbox")
+            .unwrap();
+        assert_eq!(config.api_url, "https://seedbox.example.com:3030");
+        assert_eq!(config.api_username, Some("me".to_string()));
+        assert_eq!(config.read_timeout, 120);
+        assert_eq!(config.readahead_size, 8 * 1024 * 1024);
+        assert_eq!(config.mount_point, PathBuf::from("/mnt/torrents"));
+    }
+
+    #[test]
+    fn test_profile_overrides_any_setting() {
+        let config = parse_config_content(
+            r#"
+[profile.nas]
+max_streams = 2
+page_cache = "bypass"
+fuse_max_background = 32
+status_poll_interval = "1m"
+run_as_user = "media"
+control_socket = "/run/rqbit-fuse.sock"
+"#,
+            "toml",
+        )
+        .with_profile("nas")
+        .unwrap();
+        assert_eq!(config.max_streams, 2);
+        assert_eq!(config.page_cache, PageCachePolicy::Bypass);
+        assert_eq!(config.fuse_max_background, Some(32));
+        assert_eq!(config.status_poll_interval, 60);
+        assert_eq!(config.run_as_user.as_deref(), Some("media"));
+        assert_eq!(
+            config.control_socket,
+            Some(PathBuf::from("/run/rqbit-fuse.sock"))
+        );
+    }
+
+    #[test]
+    fn test_config_source_covers_every_setting() {
+        let known = serde_json::to_value(Config::default()).unwrap();
+        let source = serde_json::to_value(ConfigSource::default()).unwrap();
+        let missing: Vec<_> = known
+            .as_object()
+            .unwrap()
+            .keys()
+            .filter(|key| !["strict", "preset", "profile", "mounts"].contains(&key.as_str()))
+            .filter(|key| source.get(key.as_str()).is_none())
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "not settable in profiles: {:?}",
+            missing
+        );
+    }
+
+    #[test]
+    fn test_with_unknown_profile() {
+        let err = parse_config_content(PROFILES, "toml")
+            .with_profile("office")
+            .unwrap_err();
+        assert!(err.to_string().contains("available: home, seedbox"));
+    }
+
+    #[test]
+    fn test_strict_checks_profile_keys() {
+        let (_dir, path) = write_config_file(
+            "[profile.home]\napi_url = \"http://a:1\"\nmount_pointt = \"/x\"",
+            "toml",
+        );
+        match Config::from_file_strict(&path, true).unwrap_err() {
+            RqbitFuseError::ValidationError(errors) => {
+                assert_eq!(
+                    errors,
+                    vec!["profile.home.mount_pointt: unknown configuration key"]
+                );
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(
+        "[profile.home]\n[profile.home.filter]\nexclude_trackers = [\"x\"]",
+        "profile.home.filter.exclude_trackers"
+    )]
+    #[case(
+        "[[mounts]]\nmount_point = \"/a\"\n[mounts.filter]\nexclude_trackers = [\"x\"]",
+        "mounts[0].filter.exclude_trackers"
+    )]
+    #[case(
+        "[[mounts]]\nmount_point = \"/a\"\n[mounts.quota]\nper_week = \"1G\"",
+        "mounts[0].quota.per_week"
+    )]
+    fn test_strict_checks_nested_source_keys(#[case] content: &str, #[case] key: &str) {
+        let (_dir, path) = write_config_file(content, "toml");
+        match Config::from_file_strict(&path, true).unwrap_err() {
+            RqbitFuseError::ValidationError(errors) => {
+                assert_eq!(errors, vec![format!("{}: unknown configuration key", key)]);
+            }
+            other => panic!("expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_mount_configs_without_mounts() {
+        let config = Config::default();
+        let mounts = config.mount_configs();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].mount_point, config.mount_point);
+    }
+
+    #[test]
+    fn test_mount_configs_merges_each_mount() {
+        let config = parse_config_content(
+            r#"
+api_url = "http://127.0.0.1:3030"
+read_timeout = 10
+
+[[mounts]]
+mount_point = "/mnt/home"
+
+[[mounts]]
+mount_point = "/mnt/seedbox"
+api_url = "http://seedbox:3030"
+"#,
+            "toml",
+        );
+        assert!(config.validate().is_ok());
+
+        let mounts = config.mount_configs();
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/mnt/home"));
+        assert_eq!(mounts[0].api_url, "http://127.0.0.1:3030");
+        assert_eq!(mounts[1].mount_point, PathBuf::from("/mnt/seedbox"));
+        assert_eq!(mounts[1].api_url, "http://seedbox:3030");
+        assert!(mounts
+            .iter()
+            .all(|m| m.read_timeout == 10 && m.mounts.is_empty()));
+    }
+
+    #[test]
+    fn test_mount_overrides_any_setting() {
+        let config = parse_config_content(
+            r#"
+max_streams = 8
+
+[[mounts]]
+mount_point = "/mnt/home"
+
+[[mounts]]
+mount_point = "/mnt/seedbox"
+max_streams = 2
+page_cache = "bypass"
+fuse_max_background = 32
+"#,
+            "toml",
+        );
+        assert!(config.validate().is_ok());
+
+        let mounts = config.mount_configs();
+        assert_eq!(mounts[0].max_streams, 8);
+        assert_eq!(mounts[0].page_cache, PageCachePolicy::KeepComplete);
+        assert_eq!(mounts[1].max_streams, 2);
+        assert_eq!(mounts[1].page_cache, PageCachePolicy::Bypass);
+        assert_eq!(mounts[1].fuse_max_background, Some(32));
+    }
+
+    #[rstest::rstest]
+    #[case("[[mounts]]\napi_url = \"http://a:1\"")]
+    #[case("[[mounts]]\nmount_point = \"/mnt/a\"\n[[mounts]]\nmount_point = \"/mnt/a\"")]
+    #[case("[[mounts]]\nmount_point = \"/mnt/a\"\nrun_as_user = \"media\"")]
+    #[case("[[mounts]]\nmount_point = \"/mnt/a\"\nstatus_poll_interval = 0")]
+    fn test_validate_invalid_mounts(#[case] content: &str) {
+        let config = parse_config_content(content, "toml");
+        assert!(matches!(
+            config.validate(),
+            Err(RqbitFuseError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_filter_section_parsing() {
+        let c = parse_config_content(
+            r#"
+[filter]
+include_name_regex = "(?i)\\.(mkv|mp4)$"
+min_size = "100MiB"
+"#,
+            "toml",
+        );
+        assert_eq!(
+            c.filter.include_name_regex.as_deref(),
+            Some(r"(?i)\.(mkv|mp4)$")
+        );
+        assert_eq!(c.filter.min_size, Some(100 * 1024 * 1024));
+        assert_eq!(c.filter.max_size, None);
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_run_as_group_needs_user() {
+        let c = Config {
+            run_as_group: Some("media".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+
+        let c = Config {
+            run_as_user: Some("rqbit".to_string()),
+            ..c
+        };
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn test_trash_days() {
+        let config: Config = toml::from_str(
+            r#"
+            trash_days = 7
+            trash_state = "/var/lib/rqbit-fuse/trash.json"
+
+            [[mounts]]
+            mount_point = "/mnt/other"
+            trash_days = 0
+        "#,
+        )
+        .unwrap();
+        let mounts = config.mount_configs();
+        assert_eq!(mounts[0].trash_days, 0);
+        assert_eq!(
+            mounts[0].trash_state_path(),
+            PathBuf::from("/var/lib/rqbit-fuse/trash.json")
+        );
+
+        let c = Config {
+            trash_days: 7,
+            torrent: Some("1".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_stats_files() {
+        let config: Config = toml::from_str(
+            r#"
+            stats_files = true
+
+            [[mounts]]
+            mount_point = "/mnt/other"
+            stats_files = false
+        "#,
+        )
+        .unwrap();
+        assert!(config.stats_files);
+        assert!(!config.mount_configs()[0].stats_files);
+
+        let c = Config {
+            stats_files: true,
+            torrent: Some("1".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_status_dirs() {
+        assert!(!Config::default().status_dirs);
+        let c = parse_config_content("status_dirs = true", "toml");
+        assert!(c.status_dirs);
+        // The root is the torrent's directory then
+        let c = Config {
+            status_dirs: true,
+            torrent: Some("1".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_ok());
+    }
+
+    #[test]
+    fn test_add_torrents() {
+        assert!(!Config::default().add_torrents);
+        let c = parse_config_content("add_torrents = true", "toml");
+        assert!(c.add_torrents);
+        assert!(c.validate().is_ok());
+        let c = Config {
+            add_torrents: true,
+            torrent: Some("1".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_remove_torrents() {
+        assert_eq!(Config::default().remove_torrents, TorrentRemoval::Off);
+        let c = parse_config_content("remove_torrents = \"delete\"", "toml");
+        assert_eq!(c.remove_torrents, TorrentRemoval::Delete);
+        let c = Config {
+            remove_torrents: TorrentRemoval::Forget,
+            torrent: Some("1".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_recent_dir() {
+        let c = Config::default();
+        assert_eq!((c.recent_count, c.recent_dir.as_str()), (0, ".recent"));
+        let c = parse_config_content("recent_count = 20\nrecent_dir = \"Recent\"", "toml");
+        assert_eq!((c.recent_count, c.recent_dir.as_str()), (20, "Recent"));
+        assert!(c.validate().is_ok());
+
+        for name in ["", "a/b", "..", ".trash"] {
+            let c = Config {
+                recent_count: 20,
+                recent_dir: name.to_string(),
+                ..Config::default()
+            };
+            assert!(c.validate().is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_api_login_url_needs_credentials() {
+        let mut c = Config {
+            api_login_url: Some("https://gateway.example.com/login".to_string()),
+            api_username: Some("alice".to_string()),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+        c.api_password = Some("secret".to_string());
+        assert!(c.validate().is_ok());
+        assert!(c.session_auth().unwrap().is_some());
+        assert!(Config::default().session_auth().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_api_backend() {
+        assert_eq!(Config::default().api_backend, ApiBackend::Rqbit);
+        let mut c = parse_config_content("api_backend = \"transmission\"", "toml");
+        assert_eq!(c.api_backend, ApiBackend::Transmission);
+        assert!(c.validate().is_ok());
+        c.piece_check_enabled = true;
+        assert!(c.validate().is_err());
+        c.piece_check_enabled = false;
+        c.api_login_url = Some("https://gateway.example.com/login".to_string());
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_mount_point_preparation() {
+        let config: Config = toml::from_str(
+            r#"
+            mount_point_mode = 0o750
+            mount_point_uid = 1000
+            remove_mount_point = true
+
+            [[mounts]]
+            mount_point = "/mnt/other"
+            nonempty = true
+        "#,
+        )
+        .unwrap();
+        assert_eq!(config.mount_point_mode, Some(0o750));
+        assert_eq!(config.mount_point_gid, None);
+        assert!(!config.nonempty);
+        let mount = &config.mount_configs()[0];
+        assert!(mount.nonempty);
+        assert!(mount.remove_mount_point);
+        assert_eq!(mount.mount_point_uid, Some(1000));
+
+        let c = Config {
+            mount_point_mode: Some(0o10000),
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_seccomp_needs_feature() {
+        let c = Config {
+            seccomp: true,
+            ..Config::default()
+        };
+        assert_eq!(c.validate().is_ok(), cfg!(feature = "seccomp"));
+    }
+
+    #[test]
+    fn test_extra_options() {
+        let c = parse_config_content(
+            r#"extra_options = ["context=system_u:object_r:public_content_t:s0", "max_read=1048576"]"#,
+            "toml",
+        );
+        assert_eq!(
+            c.extra_options,
+            [
+                "context=system_u:object_r:public_content_t:s0",
+                "max_read=1048576"
+            ]
+        );
+        assert!(c.validate().is_ok());
+
+        let c = Config {
+            extra_options: vec![" ".to_string()],
+            ..Config::default()
+        };
+        assert!(c.validate().is_err());
+    }
+
+    #[test]
+    fn test_quota_section_parsing() {
+        assert_eq!(Config::default().quota, QuotaConfig::default());
+        let c = parse_config_content(
+            "[quota]\nper_hour = \"10GiB\"\nper_day = \"50GiB\"\nexempt_uids = [0]",
+            "toml",
+        );
+        assert_eq!(c.quota.per_hour, Some(10 << 30));
+        assert_eq!(c.quota.per_day, Some(50 << 30));
+        assert_eq!(c.quota.exempt_uids, vec![0]);
+    }
+
+    #[test]
+    fn test_validate_invalid_filter_regex() {
+        let config = Config {
+            filter: FilterConfig {
+                exclude_name_regex: Some("[".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(RqbitFuseError::ValidationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_checks_filter_keys() {
+        let (_dir, path) = write_config_file("[filter]\nexclude_trackers = [\"x\"]", "toml");
+        assert!(matches!(
+            Config::from_file_strict(&path, true),
+            Err(RqbitFuseError::ValidationError(_))
+        ));
+    }
 }
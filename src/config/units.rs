@@ -0,0 +1,159 @@
+//! Serde helpers for human-friendly config values.
+//!
+//! Sizes accept bare byte counts or strings such as `"32MiB"`, `"512KB"` or
+//! `"1G"`. Durations accept bare seconds or strings such as `"30s"`, `"5m"` or
+//! `"1h"`. Bare integers remain valid so older config files keep working.
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+/// Parses a size such as `"32MiB"` into bytes.
+///
+/// IEC suffixes (`KiB`, `MiB`, ...) and bare `K`/`M`/`G`/`T` are powers of
+/// 1024; SI suffixes (`KB`, `MB`, ...) are powers of 1000.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let (number, suffix) = split_number(value)?;
+    let multiplier: u64 = match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(format!("unknown size unit '{}' in '{}'", suffix, value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
+
+/// Parses a duration such as `"5m"` into whole seconds.
+pub fn parse_duration_secs(value: &str) -> Result<u64, String> {
+    let (number, suffix) = split_number(value)?;
+    let multiplier: u64 = match suffix.to_ascii_lowercase().as_str() {
+        "" | "s" | "sec" | "secs" => 1,
+        "m" | "min" | "mins" => 60,
+        "h" | "hr" | "hrs" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit '{}' in '{}'", suffix, value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("duration '{}' is too large", value))
+}
+
+fn split_number(value: &str) -> Result<(u64, &str), String> {
+    let value = value.trim();
+    let digits = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..digits]
+        .parse()
+        .map_err(|_| format!("invalid value '{}'", value))?;
+    Ok((number, value[digits..].trim()))
+}
+
+struct UnitVisitor {
+    expecting: &'static str,
+    parse: fn(&str) -> Result<u64, String>,
+}
+
+impl<'de> Visitor<'de> for UnitVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        u64::try_from(v).map_err(|_| E::custom(format!("value must not be negative, got {}", v)))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        (self.parse)(v).map_err(E::custom)
+    }
+}
+
+/// Deserializes a byte count from an integer or a size string.
+pub fn bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_any(UnitVisitor {
+        expecting: "a byte count or a size string like \"32MiB\"",
+        parse: parse_bytes,
+    })
+}
+
+/// Deserializes whole seconds from an integer or a duration string.
+pub fn duration_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_any(UnitVisitor {
+        expecting: "a number of seconds or a duration string like \"30s\"",
+        parse: parse_duration_secs,
+    })
+}
+
+/// Like [`bytes`], for optional fields.
+pub fn option_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    struct Bytes(#[serde(deserialize_with = "bytes")] u64);
+    Ok(Option::<Bytes>::deserialize(deserializer)?.map(|b| b.0))
+}
+
+/// Like [`duration_secs`], for optional fields.
+pub fn option_duration_secs<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    struct Secs(#[serde(deserialize_with = "duration_secs")] u64);
+    Ok(Option::<Secs>::deserialize(deserializer)?.map(|s| s.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest::rstest]
+    #[case("1024", 1024)]
+    #[case("512B", 512)]
+    #[case("32MiB", 32 * 1024 * 1024)]
+    #[case("32M", 32 * 1024 * 1024)]
+    #[case("4 KiB", 4096)]
+    #[case("2KB", 2000)]
+    #[case("1GB", 1_000_000_000)]
+    #[case("1gib", 1 << 30)]
+    fn test_parse_bytes(#[case] input: &str, #[case] expected: u64) {
+        assert_eq!(parse_bytes(input).unwrap(), expected);
+    }
+
+    #[rstest::rstest]
+    #[case("30", 30)]
+    #[case("30s", 30)]
+    #[case("5m", 300)]
+    #[case("2h", 7200)]
+    #[case("1d", 86400)]
+    fn test_parse_duration_secs(#[case] input: &str, #[case] expected: u64) {
+        assert_eq!(parse_duration_secs(input).unwrap(), expected);
+    }
+
+    #[rstest::rstest]
+    #[case("")]
+    #[case("MiB")]
+    #[case("32XB")]
+    #[case("-5")]
+    #[case("1.5GiB")]
+    #[case("99999999999TiB")]
+    fn test_parse_bytes_invalid(#[case] input: &str) {
+        assert!(parse_bytes(input).is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_invalid_unit() {
+        assert!(parse_duration_secs("5 fortnights").is_err());
+    }
+}
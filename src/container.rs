@@ -0,0 +1,138 @@
+//! Container mode: mount without fusermount and behave as PID 1.
+//!
+//! Container images rarely ship the setuid fusermount helper, but a
+//! container started with `--device /dev/fuse --cap-add SYS_ADMIN` can call
+//! the mount syscall itself. Without `AutoUnmount` fuser does exactly that.
+//! The environment is checked before mounting so a missing device or
+//! capability produces an actionable error instead of a fusermount failure.
+//!
+//! As PID 1 the kernel applies no default signal actions and orphaned
+//! processes are reparented to us, so zombies are reaped here.
+
+use anyhow::{bail, Result};
+use std::io::ErrorKind;
+use std::path::Path;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+const FUSE_DEVICE: &str = "/dev/fuse";
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Checks that the container can mount FUSE filesystems.
+pub fn check_environment() -> Result<()> {
+    check_fuse_device(Path::new(FUSE_DEVICE))?;
+
+    match std::fs::read_to_string("/proc/self/status") {
+        Ok(status) if !has_cap_sys_admin(&status) => bail!(
+            "mounting needs CAP_SYS_ADMIN: run the container with --cap-add SYS_ADMIN \
+             (and --security-opt apparmor:unconfined on AppArmor hosts)"
+        ),
+        Ok(_) => Ok(()),
+        // Not Linux, or /proc isn't mounted; let the mount itself decide
+        Err(e) => {
+            debug!("Cannot read capabilities: {}", e);
+            Ok(())
+        }
+    }
+}
+
+/// Checks that the FUSE device exists and can be opened for reading and writing.
+fn check_fuse_device(path: &Path) -> Result<()> {
+    match std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => bail!(
+            "{} not found: run the container with --device /dev/fuse",
+            path.display()
+        ),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => bail!(
+            "cannot open {} ({}): run the container with --device /dev/fuse",
+            path.display(),
+            e
+        ),
+        Err(e) => bail!("cannot open {}: {}", path.display(), e),
+    }
+}
+
+/// Returns true if the `CapEff` line of a `/proc/<pid>/status` file grants
+/// CAP_SYS_ADMIN.
+pub fn has_cap_sys_admin(proc_status: &str) -> bool {
+    proc_status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << CAP_SYS_ADMIN) != 0)
+}
+
+/// Lazily unmounts `path` with the umount syscall.
+pub fn unmount(path: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::umount2(c_path.as_ptr(), libc::MNT_DETACH) };
+    #[cfg(not(target_os = "linux"))]
+    let result = unsafe { libc::unmount(c_path.as_ptr(), libc::MNT_FORCE) };
+
+    if result != 0 {
+        bail!(
+            "failed to unmount {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+/// When running as PID 1, reaps exited orphans on SIGCHLD until dropped.
+pub fn spawn_zombie_reaper() -> Option<JoinHandle<()>> {
+    if std::process::id() != 1 {
+        return None;
+    }
+
+    let mut sigchld = match signal(SignalKind::child()) {
+        Ok(sigchld) => sigchld,
+        Err(e) => {
+            warn!("Cannot reap orphaned processes: {}", e);
+            return None;
+        }
+    };
+    Some(tokio::spawn(async move {
+        while sigchld.recv().await.is_some() {
+            let mut status = 0;
+            while unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) } > 0 {}
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[rstest::rstest]
+    #[case("CapEff:\t000001ffffffffff\n", true)]
+    #[case("CapEff:\t00000000a80425fb\n", false)]
+    #[case("CapPrm:\t000001ffffffffff\nCapEff:\t0000000000200000\n", true)]
+    #[case("CapEff:\t0000000000000000\n", false)]
+    #[case("Name:\trqbit-fuse\n", false)]
+    fn test_has_cap_sys_admin(#[case] status: &str, #[case] expected: bool) {
+        assert_eq!(has_cap_sys_admin(status), expected);
+    }
+
+    #[test]
+    fn test_missing_fuse_device_mentions_docker_flag() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let err = check_fuse_device(&dir.path().join("fuse")).unwrap_err();
+        assert!(err.to_string().contains("--device /dev/fuse"), "{}", err);
+    }
+
+    #[test]
+    fn test_unmount_fails_for_non_mount() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(unmount(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,731 @@
+//! Control socket for querying a running rqbit-fuse process.
+//!
+//! The daemon listens on a Unix socket and answers one JSON request per line
+//! with one JSON response per line, or a stream of them for `logs --follow`.
+//! `rqbit-fuse status`, `top`, `logs` and `export-tree` are clients.
+
+use crate::api::StreamManagerStats;
+use crate::exit_code::ExitCode;
+use crate::fs::export::MountTree;
+use crate::fs::filesystem::TorrentFS;
+use crate::fs::fsck::FsckReport;
+use crate::fs::trash::TrashItem;
+use crate::logs::{LogBuffer, LogLine};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::types::AccessPattern;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Default control socket path: `$XDG_RUNTIME_DIR/rqbit-fuse.sock`, or a
+/// per-user path in `/tmp` when no runtime dir is set.
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("rqbit-fuse.sock"),
+        None => PathBuf::from(format!("/tmp/rqbit-fuse-{}.sock", unsafe {
+            libc::geteuid()
+        })),
+    }
+}
+
+/// Request sent to the control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum ControlRequest {
+    Status,
+    /// Read activity counters, polled by `rqbit-fuse top`.
+    Activity,
+    /// The last `lines` log lines at `level` or more severe. With `follow`,
+    /// later lines keep coming, one response each, until the client
+    /// disconnects.
+    Logs {
+        #[serde(default = "default_log_lines")]
+        lines: usize,
+        #[serde(default)]
+        level: Option<String>,
+        #[serde(default)]
+        follow: bool,
+    },
+    /// Check every mount against rqbit, optionally repairing differences.
+    Fsck {
+        #[serde(default)]
+        repair: bool,
+    },
+    /// List the trash of every mount, after restoring `restore` if given.
+    Trash {
+        #[serde(default)]
+        restore: Option<String>,
+    },
+    /// Every entry of every mount, for `rqbit-fuse export-tree`.
+    ExportTree,
+}
+
+fn default_log_lines() -> usize {
+    100
+}
+
+/// Response returned by the control socket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ControlResponse {
+    Status(StatusReport),
+    Activity(ActivityReport),
+    Logs { lines: Vec<LogLine> },
+    Fsck { reports: Vec<FsckReport> },
+    Trash { mounts: Vec<MountTrash> },
+    Tree { mounts: Vec<MountTree> },
+    Error { message: String },
+}
+
+/// Daemon-wide status, with one entry per mount.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusReport {
+    pub pid: u32,
+    pub uptime_secs: u64,
+    pub metrics: MetricsSnapshot,
+    pub mounts: Vec<MountStatus>,
+}
+
+/// Status of a single mount point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountStatus {
+    pub mount_point: PathBuf,
+    pub api_url: String,
+    pub torrents: usize,
+    pub inodes: usize,
+    pub open_handles: usize,
+    /// Persistent streams of the API client serving this mount. Mounts
+    /// sharing an rqbit instance report the same streams.
+    #[serde(default)]
+    pub streams: StreamManagerStats,
+}
+
+impl MountStatus {
+    async fn from_fs(fs: &TorrentFS) -> Self {
+        Self {
+            mount_point: fs.mount_point().to_path_buf(),
+            api_url: fs.config().api_url.clone(),
+            torrents: fs.torrent_count(),
+            inodes: fs.inode_manager().len(),
+            open_handles: fs.open_handle_count(),
+            streams: fs.api_client().stream_stats().await,
+        }
+    }
+}
+
+/// Cumulative read counters; `rqbit-fuse top` turns the difference between
+/// two reports into rates.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityReport {
+    pub metrics: MetricsSnapshot,
+    pub mounts: Vec<MountActivity>,
+}
+
+/// Read activity of a single mount point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountActivity {
+    pub mount_point: PathBuf,
+    /// FUSE requests waiting for the async worker.
+    pub queue_depth: usize,
+    /// Open file handles.
+    pub handles: Vec<HandleActivity>,
+    /// Torrents read from since they were added.
+    pub torrents: Vec<TorrentActivity>,
+}
+
+/// An open file and how much was read through it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandleActivity {
+    pub fh: u64,
+    pub torrent_id: u64,
+    /// Path inside the mount.
+    pub path: String,
+    /// File offset just past the last read.
+    pub offset: u64,
+    pub bytes_read: u64,
+    #[serde(default)]
+    pub reads: u64,
+    /// Reads not starting where the previous one ended.
+    #[serde(default)]
+    pub seeks: u64,
+    /// Absent before the first read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<AccessPattern>,
+    /// Mean time to answer a read, in microseconds.
+    #[serde(default)]
+    pub avg_latency_us: u64,
+}
+
+/// Bytes read from one torrent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TorrentActivity {
+    pub torrent_id: u64,
+    pub name: String,
+    pub bytes_read: u64,
+}
+
+impl MountActivity {
+    fn from_fs(fs: &TorrentFS) -> Self {
+        let inodes = fs.inode_manager();
+        let name = |inode: u64| inodes.with_entry(inode, |e| e.name().to_string());
+        let mut handles: Vec<HandleActivity> = fs
+            .file_handles()
+            .all()
+            .into_iter()
+            .map(|h| HandleActivity {
+                fh: h.fh,
+                torrent_id: h.torrent_id,
+                path: inodes
+                    .with_entry(h.inode, |e| e.canonical_path().to_string())
+                    .unwrap_or_default(),
+                offset: h.position,
+                bytes_read: h.bytes_read,
+                reads: h.reads,
+                seeks: h.seeks,
+                pattern: h.access_pattern(),
+                avg_latency_us: h
+                    .average_latency()
+                    .map_or(0, |latency| latency.as_micros() as u64),
+            })
+            .collect();
+        handles.sort_by_key(|h| h.fh);
+        let mut torrents: Vec<TorrentActivity> = fs
+            .torrent_reads()
+            .into_iter()
+            .map(|(torrent_id, bytes_read)| TorrentActivity {
+                torrent_id,
+                name: inodes
+                    .lookup_torrent(torrent_id)
+                    .and_then(name)
+                    .unwrap_or_default(),
+                bytes_read,
+            })
+            .collect();
+        torrents.sort_by_key(|t| t.torrent_id);
+        Self {
+            mount_point: fs.mount_point().to_path_buf(),
+            queue_depth: fs.async_worker().queue_depth(),
+            handles,
+            torrents,
+        }
+    }
+}
+
+/// Torrents in the trash of a single mount point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountTrash {
+    pub mount_point: PathBuf,
+    pub items: Vec<TrashItem>,
+}
+
+struct ControlState {
+    filesystems: Vec<Arc<TorrentFS>>,
+    metrics: Arc<Metrics>,
+    started: Instant,
+}
+
+impl ControlState {
+    async fn handle(&self, request: ControlRequest) -> ControlResponse {
+        match request {
+            ControlRequest::Status => {
+                let mut mounts = Vec::with_capacity(self.filesystems.len());
+                for fs in &self.filesystems {
+                    mounts.push(MountStatus::from_fs(fs).await);
+                }
+                ControlResponse::Status(StatusReport {
+                    pid: std::process::id(),
+                    uptime_secs: self.started.elapsed().as_secs(),
+                    metrics: self.metrics.snapshot(),
+                    mounts,
+                })
+            }
+            ControlRequest::Activity => ControlResponse::Activity(ActivityReport {
+                metrics: self.metrics.snapshot(),
+                mounts: self
+                    .filesystems
+                    .iter()
+                    .map(|fs| MountActivity::from_fs(fs))
+                    .collect(),
+            }),
+            ControlRequest::Logs { lines, level, .. } => match parse_level(level.as_deref()) {
+                Ok(level) => ControlResponse::Logs {
+                    lines: LogBuffer::global().recent(level, lines),
+                },
+                Err(message) => ControlResponse::Error { message },
+            },
+            ControlRequest::Fsck { repair } => {
+                let mut reports = Vec::with_capacity(self.filesystems.len());
+                for fs in &self.filesystems {
+                    match fs.fsck(repair).await {
+                        Ok(report) => reports.push(report),
+                        Err(e) => {
+                            return ControlResponse::Error {
+                                message: format!(
+                                    "fsck of {} failed: {:#}",
+                                    fs.mount_point().display(),
+                                    e
+                                ),
+                            }
+                        }
+                    }
+                }
+                ControlResponse::Fsck { reports }
+            }
+            ControlRequest::Trash { restore } => {
+                if let Some(torrent) = restore {
+                    let mut restored = false;
+                    for fs in &self.filesystems {
+                        match fs.restore_from_trash(&torrent).await {
+                            Ok(Some(_)) => {
+                                restored = true;
+                                break;
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                return ControlResponse::Error {
+                                    message: format!("{:#}", e),
+                                }
+                            }
+                        }
+                    }
+                    if !restored {
+                        return ControlResponse::Error {
+                            message: format!("{} is not in the trash", torrent),
+                        };
+                    }
+                }
+                ControlResponse::Trash {
+                    mounts: self
+                        .filesystems
+                        .iter()
+                        .filter(|fs| fs.config().trash_days > 0)
+                        .map(|fs| MountTrash {
+                            mount_point: fs.mount_point().to_path_buf(),
+                            items: fs.trash_items(),
+                        })
+                        .collect(),
+                }
+            }
+            ControlRequest::ExportTree => ControlResponse::Tree {
+                mounts: self.filesystems.iter().map(|fs| fs.export_tree()).collect(),
+            },
+        }
+    }
+}
+
+/// Listens on the control socket until dropped.
+pub struct ControlServer {
+    path: PathBuf,
+    handle: JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Binds the control socket and starts serving requests.
+    ///
+    /// A stale socket left by a crashed process is replaced; a socket owned by
+    /// a live process is an error.
+    pub fn start(
+        path: &Path,
+        filesystems: Vec<Arc<TorrentFS>>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                anyhow::bail!(
+                    "control socket {} is in use by another process",
+                    path.display()
+                );
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind control socket {}", path.display()))?;
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+
+        let state = Arc::new(ControlState {
+            filesystems,
+            metrics,
+            started: Instant::now(),
+        });
+
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let state = Arc::clone(&state);
+                        tokio::spawn(async move {
+                            if let Err(e) = serve_connection(stream, &state).await {
+                                debug!("Control connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        warn!("Control socket accept failed: {}", e);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            handle,
+        })
+    }
+
+    /// Returns the socket path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Parses a log level name; `None` means every level.
+fn parse_level(level: Option<&str>) -> std::result::Result<tracing::Level, String> {
+    match level {
+        None => Ok(tracing::Level::TRACE),
+        Some(level) => level
+            .parse()
+            .map_err(|_| format!("invalid log level '{}'", level)),
+    }
+}
+
+async fn write_response(writer: &mut OwnedWriteHalf, response: &ControlResponse) -> Result<()> {
+    let mut body = serde_json::to_vec(response)?;
+    body.push(b'\n');
+    writer.write_all(&body).await?;
+    Ok(())
+}
+
+async fn serve_connection(stream: UnixStream, state: &ControlState) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(ControlRequest::Logs {
+                lines,
+                level,
+                follow: true,
+            }) => {
+                return match parse_level(level.as_deref()) {
+                    Ok(level) => follow_logs(&mut writer, level, lines).await,
+                    Err(message) => {
+                        write_response(&mut writer, &ControlResponse::Error { message }).await
+                    }
+                };
+            }
+            Ok(request) => state.handle(request).await,
+            Err(e) => ControlResponse::Error {
+                message: format!("invalid request: {}", e),
+            },
+        };
+        write_response(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+/// Sends the recent log lines, then each new one, until the client goes away.
+async fn follow_logs(
+    writer: &mut OwnedWriteHalf,
+    level: tracing::Level,
+    count: usize,
+) -> Result<()> {
+    let (lines, mut follower) = LogBuffer::global().follow(level, count);
+    write_response(writer, &ControlResponse::Logs { lines }).await?;
+    loop {
+        let line = match follower.recv().await {
+            Ok(line) => line,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => LogLine {
+                time: crate::logs::now_millis(),
+                level: tracing::Level::WARN.to_string(),
+                target: "rqbit_fuse::control".to_string(),
+                message: format!("{} log lines skipped", skipped),
+            },
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        if !line.is_at(level) {
+            continue;
+        }
+        // A write error means the client disconnected
+        if write_response(writer, &ControlResponse::Logs { lines: vec![line] })
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Connects to a running daemon and sends `request`. Returns the response
+/// lines and the write half, which keeps the connection open while held.
+async fn open_request(
+    path: &Path,
+    request: &ControlRequest,
+) -> Result<(Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf)> {
+    let stream = UnixStream::connect(path).await.map_err(|e| {
+        ExitCode::NotMounted.error(format!(
+            "could not connect to {} (is rqbit-fuse running?): {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut body = serde_json::to_vec(request)?;
+    body.push(b'\n');
+    writer.write_all(&body).await?;
+
+    Ok((BufReader::new(reader).lines(), writer))
+}
+
+/// Sends a single request to a running daemon and waits for the response.
+pub async fn send_request(path: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let (mut lines, _writer) = open_request(path, request).await?;
+    let line = lines
+        .next_line()
+        .await?
+        .context("control socket closed without a response")?;
+    serde_json::from_str(&line).context("invalid control response")
+}
+
+/// Sends a request answered with a stream of responses, such as
+/// `logs --follow`, and hands each to `on_response` until it returns false
+/// or the daemon closes the connection.
+pub async fn stream_request(
+    path: &Path,
+    request: &ControlRequest,
+    mut on_response: impl FnMut(ControlResponse) -> bool,
+) -> Result<()> {
+    let (mut lines, _writer) = open_request(path, request).await?;
+    while let Some(line) = lines.next_line().await? {
+        let response = serde_json::from_str(&line).context("invalid control response")?;
+        if !on_response(response) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{create_api_client, TorrentClient};
+    use crate::config::Config;
+    use crate::fs::async_bridge::AsyncFuseWorker;
+    use crate::logs::CAPACITY;
+    use tempfile::TempDir;
+
+    fn create_test_fs(mount_point: &str) -> Arc<TorrentFS> {
+        let metrics = Arc::new(Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client("http://127.0.0.1:3030", None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            10,
+        ));
+        let config = Config {
+            mount_point: PathBuf::from(mount_point),
+            ..Default::default()
+        };
+        Arc::new(TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_each_mount() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_read(42);
+
+        let _server = ControlServer::start(
+            &path,
+            vec![create_test_fs("/mnt/a"), create_test_fs("/mnt/b")],
+            Arc::clone(&metrics),
+        )
+        .unwrap();
+
+        match send_request(&path, &ControlRequest::Status).await.unwrap() {
+            ControlResponse::Status(report) => {
+                assert_eq!(report.pid, std::process::id());
+                assert_eq!(report.metrics.bytes_read, 42);
+                let mount_points: Vec<_> = report
+                    .mounts
+                    .iter()
+                    .map(|m| m.mount_point.clone())
+                    .collect();
+                assert_eq!(
+                    mount_points,
+                    vec![PathBuf::from("/mnt/a"), PathBuf::from("/mnt/b")]
+                );
+                assert!(report.mounts.iter().all(|m| m.open_handles == 0));
+                assert!(report.mounts.iter().all(|m| m.streams.max_streams == 50));
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_activity_reports_each_mount() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let _server = ControlServer::start(
+            &path,
+            vec![create_test_fs("/mnt/a"), create_test_fs("/mnt/b")],
+            Arc::new(Metrics::new()),
+        )
+        .unwrap();
+
+        match send_request(&path, &ControlRequest::Activity)
+            .await
+            .unwrap()
+        {
+            ControlResponse::Activity(report) => {
+                assert_eq!(report.mounts.len(), 2);
+                assert!(report
+                    .mounts
+                    .iter()
+                    .all(|m| m.queue_depth == 0 && m.handles.is_empty()));
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logs_recent_and_follow() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let _server = ControlServer::start(&path, vec![], Arc::new(Metrics::new())).unwrap();
+        let log = |level: tracing::Level, message: &str| LogLine {
+            time: 0,
+            level: level.to_string(),
+            target: "test".to_string(),
+            message: message.to_string(),
+        };
+        LogBuffer::global().push(log(tracing::Level::DEBUG, "control test debug"));
+        LogBuffer::global().push(log(tracing::Level::WARN, "control test warn"));
+
+        let request = ControlRequest::Logs {
+            lines: CAPACITY,
+            level: Some("info".to_string()),
+            follow: false,
+        };
+        match send_request(&path, &request).await.unwrap() {
+            ControlResponse::Logs { lines } => {
+                assert!(lines.iter().any(|l| l.message == "control test warn"));
+                assert!(!lines.iter().any(|l| l.message == "control test debug"));
+            }
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        // Lines logged after the first response keep coming
+        let request = ControlRequest::Logs {
+            lines: 0,
+            level: None,
+            follow: true,
+        };
+        let mut responses = 0;
+        stream_request(&path, &request, |response| {
+            responses += 1;
+            match response {
+                ControlResponse::Logs { lines } if responses == 1 => {
+                    assert!(lines.is_empty());
+                    LogBuffer::global().push(log(tracing::Level::ERROR, "control test follow"));
+                    true
+                }
+                ControlResponse::Logs { lines } => {
+                    !lines.iter().any(|l| l.message == "control test follow")
+                }
+                other => panic!("unexpected response: {:?}", other),
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trash_lists_mounts_with_trash() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let _server = ControlServer::start(
+            &path,
+            vec![create_test_fs("/mnt/a")],
+            Arc::new(Metrics::new()),
+        )
+        .unwrap();
+
+        let request = ControlRequest::Trash { restore: None };
+        match send_request(&path, &request).await.unwrap() {
+            ControlResponse::Trash { mounts } => assert!(mounts.is_empty()),
+            other => panic!("unexpected response: {:?}", other),
+        }
+        let request = ControlRequest::Trash {
+            restore: Some("Multi File".to_string()),
+        };
+        assert!(matches!(
+            send_request(&path, &request).await.unwrap(),
+            ControlResponse::Error { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_request_returns_error() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        let _server = ControlServer::start(&path, vec![], Arc::new(Metrics::new())).unwrap();
+
+        let stream = UnixStream::connect(&path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        writer
+            .write_all(b"{\"command\":\"bogus\"}\n")
+            .await
+            .unwrap();
+        let line = BufReader::new(reader)
+            .lines()
+            .next_line()
+            .await
+            .unwrap()
+            .unwrap();
+        let response: ControlResponse = serde_json::from_str(&line).unwrap();
+        assert!(matches!(response, ControlResponse::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_stale_socket_is_replaced_and_removed_on_drop() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("control.sock");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let server = ControlServer::start(&path, vec![], Arc::new(Metrics::new())).unwrap();
+        assert!(ControlServer::start(&path, vec![], Arc::new(Metrics::new())).is_err());
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,562 @@
+//! Docker volume plugin.
+//!
+//! Implements the Docker volume plugin protocol (JSON over HTTP on a Unix
+//! socket in `/run/docker/plugins`) so containers can use rqbit content as a
+//! volume:
+//!
+//! ```text
+//! docker volume create -d rqbit-fuse -o torrent=<infohash> movie
+//! docker run -v movie:/media:ro jellyfin/jellyfin
+//! ```
+//!
+//! A volume is a [`Config`] with the volume's options applied on top of the
+//! plugin's own configuration. It is mounted on the first container mount and
+//! unmounted after the last container using it stops. Volume definitions are
+//! kept in `volumes.json` under the plugin's root directory so they survive
+//! plugin restarts.
+
+use crate::api::create_client;
+use crate::config::Config;
+use crate::fs::async_bridge::AsyncFuseWorker;
+use crate::fs::filesystem::{discover_existing_torrents, TorrentFS};
+use crate::metrics::Metrics;
+use crate::mount::is_mounted;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Default socket path; Docker discovers plugins by socket name.
+pub const DEFAULT_SOCKET: &str = "/run/docker/plugins/rqbit-fuse.sock";
+/// Default directory holding volume mount points and state.
+pub const DEFAULT_ROOT: &str = "/var/lib/rqbit-fuse/volumes";
+
+const CONTENT_TYPE: &str = "application/vnd.docker.plugins.v1.2+json";
+/// How long a mount request waits for the FUSE mount to appear.
+const MOUNT_TIMEOUT: Duration = Duration::from_secs(10);
+const STATE_FILE: &str = "volumes.json";
+
+/// Options accepted by `docker volume create -o`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VolumeOptions {
+    /// Expose a single torrent (ID or info hash); the whole rqbit namespace
+    /// when unset.
+    #[serde(default)]
+    pub torrent: Option<String>,
+    /// rqbit API URL, overriding the plugin configuration.
+    #[serde(default)]
+    pub api_url: Option<String>,
+}
+
+impl VolumeOptions {
+    fn from_opts(opts: Option<HashMap<String, String>>) -> Result<Self> {
+        let opts = opts.unwrap_or_default();
+        serde_json::from_value(serde_json::to_value(opts)?)
+            .map_err(|e| anyhow::anyhow!("invalid volume option: {}", e))
+    }
+}
+
+struct ActiveMount {
+    fs: Arc<TorrentFS>,
+    task: JoinHandle<Result<()>>,
+}
+
+#[derive(Default)]
+struct Volume {
+    options: VolumeOptions,
+    /// IDs of the container mounts using the volume.
+    users: HashSet<String>,
+    mount: Option<ActiveMount>,
+}
+
+/// Volume driver state shared by all plugin connections.
+pub struct VolumePlugin {
+    base: Config,
+    root: PathBuf,
+    metrics: Arc<Metrics>,
+    volumes: Mutex<BTreeMap<String, Volume>>,
+}
+
+impl VolumePlugin {
+    /// Creates the driver, loading volumes saved by a previous run from `root`.
+    pub fn new(base: Config, root: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create {}", root.display()))?;
+
+        let state = root.join(STATE_FILE);
+        let saved: BTreeMap<String, VolumeOptions> = match std::fs::read(&state) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("failed to parse {}", state.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).context(format!("failed to read {}", state.display())),
+        };
+        let volumes = saved
+            .into_iter()
+            .map(|(name, options)| {
+                (
+                    name,
+                    Volume {
+                        options,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            base,
+            root,
+            metrics: Arc::new(Metrics::new()),
+            volumes: Mutex::new(volumes),
+        })
+    }
+
+    /// Handles one plugin API call. Driver errors are reported in the `Err`
+    /// field as the protocol expects.
+    pub async fn handle(&self, endpoint: &str, body: &[u8]) -> Value {
+        let request: Request = if body.is_empty() {
+            Request::default()
+        } else {
+            match serde_json::from_slice(body) {
+                Ok(request) => request,
+                Err(e) => return json!({ "Err": format!("invalid request: {}", e) }),
+            }
+        };
+        match self.dispatch(endpoint, request).await {
+            Ok(response) => response,
+            Err(e) => {
+                warn!("{} failed: {:#}", endpoint, e);
+                json!({ "Err": format!("{:#}", e) })
+            }
+        }
+    }
+
+    async fn dispatch(&self, endpoint: &str, request: Request) -> Result<Value> {
+        match endpoint {
+            "/Plugin.Activate" => Ok(json!({ "Implements": ["VolumeDriver"] })),
+            "/VolumeDriver.Capabilities" => Ok(json!({ "Capabilities": { "Scope": "local" } })),
+            "/VolumeDriver.Create" => {
+                let name = &request.name()?;
+                let options = VolumeOptions::from_opts(request.opts)?;
+                let mut volumes = self.volumes.lock().await;
+                if volumes.contains_key(name) {
+                    bail!("volume {} already exists", name);
+                }
+                volumes.insert(
+                    name.to_string(),
+                    Volume {
+                        options,
+                        ..Default::default()
+                    },
+                );
+                self.save(&volumes)?;
+                Ok(json!({ "Err": "" }))
+            }
+            "/VolumeDriver.Remove" => {
+                let name = &request.name()?;
+                let mut volumes = self.volumes.lock().await;
+                match volumes.get(name) {
+                    None => bail!("no such volume: {}", name),
+                    Some(volume) if volume.mount.is_some() => bail!("volume {} is in use", name),
+                    Some(_) => {}
+                }
+                volumes.remove(name);
+                self.save(&volumes)?;
+                let _ = std::fs::remove_dir(self.mount_point(name));
+                Ok(json!({ "Err": "" }))
+            }
+            "/VolumeDriver.Mount" => {
+                let name = &request.name()?;
+                let mut volumes = self.volumes.lock().await;
+                let volume = volumes
+                    .get_mut(name)
+                    .with_context(|| format!("no such volume: {}", name))?;
+                if volume.mount.is_none() {
+                    volume.mount = Some(self.mount(name, &volume.options).await?);
+                }
+                volume.users.insert(request.id.unwrap_or_default());
+                Ok(json!({ "Mountpoint": self.mount_point(name), "Err": "" }))
+            }
+            "/VolumeDriver.Unmount" => {
+                let name = &request.name()?;
+                let mut volumes = self.volumes.lock().await;
+                let volume = volumes
+                    .get_mut(name)
+                    .with_context(|| format!("no such volume: {}", name))?;
+                volume.users.remove(&request.id.unwrap_or_default());
+                if volume.users.is_empty() {
+                    if let Some(mount) = volume.mount.take() {
+                        self.unmount(name, mount).await;
+                    }
+                }
+                Ok(json!({ "Err": "" }))
+            }
+            "/VolumeDriver.Path" => {
+                let name = &request.name()?;
+                let volumes = self.volumes.lock().await;
+                let volume = volumes
+                    .get(name)
+                    .with_context(|| format!("no such volume: {}", name))?;
+                Ok(json!({ "Mountpoint": self.active_mount_point(name, volume), "Err": "" }))
+            }
+            "/VolumeDriver.Get" => {
+                let name = &request.name()?;
+                let volumes = self.volumes.lock().await;
+                let volume = volumes
+                    .get(name)
+                    .with_context(|| format!("no such volume: {}", name))?;
+                Ok(json!({ "Volume": self.describe(name, volume), "Err": "" }))
+            }
+            "/VolumeDriver.List" => {
+                let volumes = self.volumes.lock().await;
+                let list: Vec<Value> = volumes
+                    .iter()
+                    .map(|(name, volume)| self.describe(name, volume))
+                    .collect();
+                Ok(json!({ "Volumes": list, "Err": "" }))
+            }
+            _ => bail!("unsupported endpoint {}", endpoint),
+        }
+    }
+
+    /// Unmounts every mounted volume.
+    pub async fn shutdown(&self) {
+        let mut volumes = self.volumes.lock().await;
+        for (name, volume) in volumes.iter_mut() {
+            volume.users.clear();
+            if let Some(mount) = volume.mount.take() {
+                self.unmount(name, mount).await;
+            }
+        }
+    }
+
+    fn mount_point(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// The mount point if mounted; Docker expects an empty path otherwise.
+    fn active_mount_point(&self, name: &str, volume: &Volume) -> PathBuf {
+        if volume.mount.is_some() {
+            self.mount_point(name)
+        } else {
+            PathBuf::new()
+        }
+    }
+
+    fn describe(&self, name: &str, volume: &Volume) -> Value {
+        json!({
+            "Name": name,
+            "Mountpoint": self.active_mount_point(name, volume),
+            "Status": {
+                "torrent": volume.options.torrent,
+                "api_url": self.volume_config(name, &volume.options).api_url,
+                "mounts": volume.users.len(),
+            },
+        })
+    }
+
+    fn volume_config(&self, name: &str, options: &VolumeOptions) -> Config {
+        let mut config = self.base.clone();
+        config.mount_point = self.mount_point(name);
+        config.mounts = Vec::new();
+        // Containers seldom run as the plugin's user
+        config.allow_other = true;
+        if options.torrent.is_some() {
+            config.torrent = options.torrent.clone();
+        }
+        if let Some(api_url) = &options.api_url {
+            config.api_url = api_url.clone();
+        }
+        config
+    }
+
+    async fn mount(&self, name: &str, options: &VolumeOptions) -> Result<ActiveMount> {
+        let config = self.volume_config(name, options);
+        config.validate()?;
+        std::fs::create_dir_all(&config.mount_point)
+            .with_context(|| format!("failed to create {}", config.mount_point.display()))?;
+
+        let api_client = create_client(&config, Arc::clone(&self.metrics))?;
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&self.metrics),
+            1000,
+        ));
+        let fs = Arc::new(TorrentFS::with_api_client(
+            config,
+            api_client,
+            Arc::clone(&self.metrics),
+            async_worker,
+        )?);
+        discover_existing_torrents(&fs).await?;
+
+        let mount_point = fs.mount_point().to_path_buf();
+        let task = {
+            let fs = <TorrentFS as Clone>::clone(&fs);
+            tokio::task::spawn_blocking(move || fs.mount())
+        };
+
+        // Docker bind-mounts the path as soon as we answer
+        let deadline = tokio::time::Instant::now() + MOUNT_TIMEOUT;
+        while !is_mounted(&mount_point) {
+            if task.is_finished() {
+                return match task.await? {
+                    Ok(()) => Err(anyhow::anyhow!("filesystem exited before mounting")),
+                    Err(e) => Err(e),
+                };
+            }
+            if tokio::time::Instant::now() >= deadline {
+                fs.shutdown();
+                bail!("timed out waiting for {} to mount", mount_point.display());
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        info!("Mounted volume {} at {}", name, mount_point.display());
+        Ok(ActiveMount { fs, task })
+    }
+
+    async fn unmount(&self, name: &str, mount: ActiveMount) {
+        mount.fs.shutdown();
+        let mount_point = mount.fs.mount_point().to_path_buf();
+        let result = if mount.fs.config().container {
+            crate::container::unmount(&mount_point)
+        } else {
+            crate::mount::unmount_filesystem(&mount_point, false)
+        };
+        if let Err(e) = result {
+            warn!("Failed to unmount volume {}: {:#}", name, e);
+            return;
+        }
+        if let Ok(Err(e)) = mount.task.await {
+            debug!("Volume {} session ended with: {:#}", name, e);
+        }
+        info!("Unmounted volume {}", name);
+    }
+
+    fn save(&self, volumes: &BTreeMap<String, Volume>) -> Result<()> {
+        let saved: BTreeMap<&String, &VolumeOptions> = volumes
+            .iter()
+            .map(|(name, volume)| (name, &volume.options))
+            .collect();
+        let path = self.root.join(STATE_FILE);
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&saved)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Request body shared by the volume driver endpoints.
+#[derive(Debug, Default, Deserialize)]
+struct Request {
+    #[serde(rename = "Name")]
+    name: Option<String>,
+    #[serde(rename = "Opts")]
+    opts: Option<HashMap<String, String>>,
+    #[serde(rename = "ID")]
+    id: Option<String>,
+}
+
+impl Request {
+    fn name(&self) -> Result<String> {
+        match self.name.as_deref() {
+            Some(name) if is_valid_name(name) => Ok(name.to_string()),
+            Some(name) => bail!("invalid volume name: {:?}", name),
+            None => bail!("missing volume name"),
+        }
+    }
+}
+
+/// Volume names become directory names under the plugin root.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\0'])
+}
+
+/// Serves the plugin API on `socket` until the task is aborted.
+pub fn serve(plugin: Arc<VolumePlugin>, socket: &Path) -> Result<JoinHandle<()>> {
+    if let Some(parent) = socket.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    if socket.exists() {
+        std::fs::remove_file(socket)
+            .with_context(|| format!("failed to remove stale socket {}", socket.display()))?;
+    }
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("failed to bind {}", socket.display()))?;
+
+    Ok(tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let plugin = Arc::clone(&plugin);
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream, &plugin).await {
+                            debug!("Plugin connection error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => warn!("Plugin socket accept failed: {}", e),
+            }
+        }
+    }))
+}
+
+/// Answers HTTP/1.1 requests on one connection until the client closes it.
+async fn serve_connection(stream: UnixStream, plugin: &VolumePlugin) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).await? == 0 {
+            return Ok(());
+        }
+        let path = request_line
+            .split_whitespace()
+            .nth(1)
+            .context("malformed request line")?
+            .to_string();
+
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).await? == 0 {
+                bail!("connection closed in headers");
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((key, value)) = header.split_once(':') {
+                if key.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().context("bad Content-Length")?;
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).await?;
+        debug!("Plugin request {}", path);
+
+        let response = serde_json::to_vec(&plugin.handle(&path, &body).await)?;
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            CONTENT_TYPE,
+            response.len()
+        );
+        writer.write_all(head.as_bytes()).await?;
+        writer.write_all(&response).await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn plugin(root: &Path) -> VolumePlugin {
+        VolumePlugin::new(Config::default(), root.to_path_buf()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_volume_lifecycle_without_mounting() {
+        let dir = TempDir::new().unwrap();
+        let driver = plugin(dir.path());
+
+        let response = driver
+            .handle(
+                "/VolumeDriver.Create",
+                br#"{"Name": "movie", "Opts": {"torrent": "3"}}"#,
+            )
+            .await;
+        assert_eq!(response["Err"], "");
+
+        let response = driver
+            .handle("/VolumeDriver.Create", br#"{"Name": "movie"}"#)
+            .await;
+        assert!(response["Err"].as_str().unwrap().contains("already exists"));
+
+        let response = driver
+            .handle("/VolumeDriver.Get", br#"{"Name": "movie"}"#)
+            .await;
+        assert_eq!(response["Volume"]["Name"], "movie");
+        assert_eq!(response["Volume"]["Mountpoint"], "");
+        assert_eq!(response["Volume"]["Status"]["torrent"], "3");
+
+        // Volumes survive a plugin restart
+        let restarted = plugin(dir.path());
+        let response = restarted.handle("/VolumeDriver.List", b"").await;
+        assert_eq!(response["Volumes"].as_array().unwrap().len(), 1);
+
+        let response = restarted
+            .handle("/VolumeDriver.Remove", br#"{"Name": "movie"}"#)
+            .await;
+        assert_eq!(response["Err"], "");
+        let response = restarted.handle("/VolumeDriver.List", b"").await;
+        assert!(response["Volumes"].as_array().unwrap().is_empty());
+    }
+
+    #[rstest::rstest]
+    #[case(r#"{"Name": "x", "Opts": {"bogus": "1"}}"#, "invalid volume option")]
+    #[case(r#"{"Name": "../etc"}"#, "invalid volume name")]
+    #[case(r#"{}"#, "missing volume name")]
+    #[tokio::test]
+    async fn test_create_rejects_bad_requests(#[case] body: &str, #[case] error: &str) {
+        let dir = TempDir::new().unwrap();
+        let response = plugin(dir.path())
+            .handle("/VolumeDriver.Create", body.as_bytes())
+            .await;
+        let err = response["Err"].as_str().unwrap();
+        assert!(err.contains(error), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_serve_answers_http() {
+        let dir = TempDir::new().unwrap();
+        let socket = dir.path().join("plugin.sock");
+        let server = serve(Arc::new(plugin(&dir.path().join("volumes"))), &socket).unwrap();
+
+        let mut stream = UnixStream::connect(&socket).await.unwrap();
+        for _ in 0..2 {
+            stream
+                .write_all(
+                    b"POST /Plugin.Activate HTTP/1.1\r\nHost: plugin\r\nContent-Length: 0\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            let mut reader = BufReader::new(&mut stream);
+            let mut status = String::new();
+            reader.read_line(&mut status).await.unwrap();
+            assert!(status.starts_with("HTTP/1.1 200"));
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await.unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["Implements"][0], "VolumeDriver");
+        }
+        server.abort();
+    }
+}
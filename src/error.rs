@@ -1,5 +1,11 @@
 use thiserror::Error;
 
+// Errno for reads from failed torrents. EREMOTEIO is Linux-only.
+#[cfg(target_os = "linux")]
+const TORRENT_FAILED_ERRNO: i32 = libc::EREMOTEIO;
+#[cfg(not(target_os = "linux"))]
+const TORRENT_FAILED_ERRNO: i32 = libc::EIO;
+
 /// Unified error type for rqbit-fuse with 11 essential variants.
 #[derive(Error, Debug, Clone)]
 pub enum RqbitFuseError {
@@ -42,6 +48,10 @@ pub enum RqbitFuseError {
     /// Parse/serialization error
     #[error("Parse error: {0}")]
     ParseError(String),
+
+    /// rqbit reports the torrent in its error state (EREMOTEIO, EIO on macOS)
+    #[error("Torrent failed: {0}")]
+    TorrentFailed(String),
 }
 
 impl RqbitFuseError {
@@ -67,6 +77,7 @@ impl RqbitFuseError {
             RqbitFuseError::ValidationError(_) => libc::EINVAL,
             RqbitFuseError::NotReady(_) => libc::EAGAIN,
             RqbitFuseError::ParseError(_) => libc::EINVAL,
+            RqbitFuseError::TorrentFailed(_) => TORRENT_FAILED_ERRNO,
         }
     }
 
@@ -195,6 +206,13 @@ mod tests {
             RqbitFuseError::NotReady("test".to_string()).to_errno(),
             libc::EAGAIN
         );
+
+        // Failed torrents
+        assert_eq!(
+            RqbitFuseError::TorrentFailed("test".to_string()).to_errno(),
+            TORRENT_FAILED_ERRNO
+        );
+        assert!(!RqbitFuseError::TorrentFailed("test".to_string()).is_transient());
     }
 
     #[test]
@@ -0,0 +1,189 @@
+//! Exit codes of the command line.
+//!
+//! Wrapper scripts and systemd units branch on why a command failed, so
+//! every failure rqbit-fuse can name gets its own code instead of 1. Errors
+//! are classified by [`ExitCode::of()`], from a [`CodedError`] raised where
+//! the cause is known or from the error types further down the chain.
+//! With `--error-format json` a failure is printed as an [`ErrorReport`].
+
+use crate::error::RqbitFuseError;
+use serde::Serialize;
+
+/// Why a command failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitCode {
+    /// Any failure not listed below.
+    Failure = 1,
+    /// The config file or a setting is invalid.
+    Config = 3,
+    /// rqbit could not be reached.
+    ApiUnreachable = 4,
+    /// The mount point is in use: mounted already, not empty, or busy.
+    MountBusy = 5,
+    /// Nothing is mounted, or no rqbit-fuse process is running.
+    NotMounted = 6,
+    /// The OS or rqbit refused access.
+    PermissionDenied = 7,
+}
+
+/// Exit codes as listed by `--help`.
+pub const HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other failure
+  2  Invalid command line
+  3  Invalid config file or setting
+  4  rqbit API unreachable
+  5  Mount point busy (already mounted, not empty, or in use)
+  6  Not mounted, or no rqbit-fuse process running
+  7  Permission denied";
+
+impl ExitCode {
+    /// An error with this exit code.
+    pub fn error(self, message: impl Into<String>) -> anyhow::Error {
+        CodedError {
+            code: self,
+            message: message.into(),
+        }
+        .into()
+    }
+
+    /// The exit code of an error: the first cause in its chain with a known
+    /// code, from the outermost context in.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain()
+            .find_map(|cause| {
+                if let Some(e) = cause.downcast_ref::<CodedError>() {
+                    return Some(e.code);
+                }
+                if let Some(e) = cause.downcast_ref::<RqbitFuseError>() {
+                    return Self::of_rqbit_error(e);
+                }
+                if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+                    return (e.is_connect() || e.is_timeout()).then_some(Self::ApiUnreachable);
+                }
+                let e = cause.downcast_ref::<std::io::Error>()?;
+                match e.kind() {
+                    std::io::ErrorKind::PermissionDenied => Some(Self::PermissionDenied),
+                    _ if e.raw_os_error() == Some(libc::EBUSY) => Some(Self::MountBusy),
+                    _ => None,
+                }
+            })
+            .unwrap_or(Self::Failure)
+    }
+
+    /// What to try next after a failure with this code.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            Self::Failure => None,
+            Self::Config => Some("check the config file and the command-line settings"),
+            Self::ApiUnreachable => Some("check that rqbit is running and api_url points at it"),
+            Self::MountBusy => {
+                Some("unmount the mount point first, or mount on an empty directory")
+            }
+            Self::NotMounted => Some("start it with `rqbit-fuse mount`"),
+            Self::PermissionDenied => Some(
+                "check the permissions of the mount point and /dev/fuse, and the rqbit credentials",
+            ),
+        }
+    }
+
+    fn of_rqbit_error(err: &RqbitFuseError) -> Option<Self> {
+        match err {
+            RqbitFuseError::ValidationError(_) => Some(Self::Config),
+            RqbitFuseError::PermissionDenied(_) => Some(Self::PermissionDenied),
+            RqbitFuseError::ApiError {
+                status: 401 | 403, ..
+            } => Some(Self::PermissionDenied),
+            e if e.is_server_unavailable() => Some(Self::ApiUnreachable),
+            _ => None,
+        }
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        std::process::ExitCode::from(code as u8)
+    }
+}
+
+/// A failure as printed by `--error-format json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorReport {
+    pub code: ExitCode,
+    /// The error and its causes, outermost first.
+    pub message: String,
+    pub hint: Option<&'static str>,
+    /// Whether the same command may succeed when run again unchanged.
+    pub retriable: bool,
+}
+
+impl ErrorReport {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let code = ExitCode::of(err);
+        let transient = err.chain().any(|cause| {
+            cause
+                .downcast_ref::<RqbitFuseError>()
+                .is_some_and(RqbitFuseError::is_transient)
+        });
+        Self {
+            code,
+            message: format!("{:#}", err),
+            hint: code.hint(),
+            retriable: transient || matches!(code, ExitCode::ApiUnreachable | ExitCode::MountBusy),
+        }
+    }
+}
+
+/// An error whose exit code is known where it is raised.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct CodedError {
+    pub code: ExitCode,
+    pub message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_of_error_chain() {
+        let err = ExitCode::MountBusy.error("busy").context("mounting /mnt");
+        assert_eq!(ExitCode::of(&err), ExitCode::MountBusy);
+
+        let err = anyhow::Error::from(RqbitFuseError::ValidationError(vec!["x".into()]));
+        assert_eq!(ExitCode::of(&err), ExitCode::Config);
+
+        let err = anyhow::Error::from(RqbitFuseError::NetworkError("refused".into()))
+            .context("torrent discovery failed");
+        assert_eq!(ExitCode::of(&err), ExitCode::ApiUnreachable);
+
+        let io = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let err = anyhow::Error::from(io).context("creating /mnt");
+        assert_eq!(ExitCode::of(&err), ExitCode::PermissionDenied);
+
+        let err = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::EBUSY));
+        assert_eq!(ExitCode::of(&err), ExitCode::MountBusy);
+
+        assert_eq!(ExitCode::of(&anyhow::anyhow!("other")), ExitCode::Failure);
+    }
+
+    #[test]
+    fn test_error_report_json() {
+        let err = anyhow::Error::from(RqbitFuseError::NetworkError("refused".into()))
+            .context("torrent discovery failed");
+        let report = serde_json::to_value(ErrorReport::new(&err)).unwrap();
+        assert_eq!(report["code"], "api_unreachable");
+        assert_eq!(
+            report["message"],
+            "torrent discovery failed: Network error: refused"
+        );
+        assert!(report["hint"].as_str().unwrap().contains("rqbit"));
+        assert_eq!(report["retriable"], true);
+
+        let report = ErrorReport::new(&ExitCode::Config.error("bad key"));
+        assert!(!report.retriable);
+    }
+}
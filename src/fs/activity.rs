@@ -0,0 +1,121 @@
+//! When the mount was last used, so background polling can slow down while
+//! nobody is using it.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// The time of the last FUSE operation, and a wakeup for a poller waiting
+/// out an idle period.
+#[derive(Debug)]
+pub struct Activity {
+    start: Instant,
+    /// Milliseconds from `start` to the last operation.
+    last_op_ms: AtomicU64,
+    /// Milliseconds from `start` to the last read.
+    last_read_ms: AtomicU64,
+    /// Whether a poller is waiting in [`Activity::wait()`].
+    waiting: AtomicBool,
+    woken: Notify,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Activity {
+    /// Starts out as used just now.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_op_ms: AtomicU64::new(0),
+            last_read_ms: AtomicU64::new(0),
+            waiting: AtomicBool::new(false),
+            woken: Notify::new(),
+        }
+    }
+
+    /// Records an operation, waking a poller waiting out an idle period.
+    pub fn touch(&self) {
+        let now_ms = self.start.elapsed().as_millis() as u64;
+        self.last_op_ms.fetch_max(now_ms, Ordering::Relaxed);
+        if self.waiting.swap(false, Ordering::AcqRel) {
+            self.woken.notify_one();
+        }
+    }
+
+    /// Records a read, which is also an operation.
+    pub fn touch_read(&self) {
+        let now_ms = self.start.elapsed().as_millis() as u64;
+        self.last_read_ms.fetch_max(now_ms, Ordering::Relaxed);
+        self.touch();
+    }
+
+    /// Time since the last operation.
+    pub fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_op_ms.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+
+    /// Time since the last read.
+    pub fn idle_reads_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_read_ms.load(Ordering::Relaxed));
+        self.start.elapsed().saturating_sub(last)
+    }
+
+    /// Sleeps until the next operation.
+    pub async fn wait_for_use(&self) {
+        self.waiting.store(true, Ordering::Release);
+        self.woken.notified().await;
+        self.waiting.store(false, Ordering::Release);
+    }
+
+    /// Sleeps for `timeout`, or until the next operation. Returns true if an
+    /// operation ended the wait.
+    pub async fn wait(&self, timeout: Duration) -> bool {
+        self.waiting.store(true, Ordering::Release);
+        let woken = tokio::time::timeout(timeout, self.woken.notified())
+            .await
+            .is_ok();
+        self.waiting.store(false, Ordering::Release);
+        woken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_touch_ends_idle_wait() {
+        let activity = Arc::new(Activity::new());
+        tokio::time::sleep(Duration::from_secs(90)).await;
+        assert!(activity.idle_for() >= Duration::from_secs(90));
+        assert!(!activity.wait(Duration::from_secs(60)).await);
+
+        let waiter = tokio::spawn({
+            let activity = Arc::clone(&activity);
+            async move { activity.wait(Duration::from_secs(60)).await }
+        });
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        activity.touch();
+        assert!(waiter.await.unwrap());
+        assert!(activity.idle_for() < Duration::from_secs(1));
+        // An operation other than a read leaves the reads idle
+        assert!(activity.idle_reads_for() >= Duration::from_secs(95));
+
+        let sleeper = tokio::spawn({
+            let activity = Arc::clone(&activity);
+            async move { activity.wait_for_use().await }
+        });
+        tokio::time::sleep(Duration::from_secs(3600)).await;
+        assert!(!sleeper.is_finished());
+        activity.touch_read();
+        sleeper.await.unwrap();
+        assert!(activity.idle_reads_for() < Duration::from_secs(1));
+    }
+}
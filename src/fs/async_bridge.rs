@@ -1,10 +1,14 @@
-use crate::api::client::RqbitClient;
+use crate::api::backend::TorrentClient;
+use crate::api::request_id;
+use crate::api::types::TorrentInfo;
 use crate::error::{anyhow_to_errno, RqbitFuseError, RqbitFuseResult};
+use crate::fs::virtual_files::VirtualFile;
 use crate::metrics::Metrics;
+use bytes::Bytes;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, oneshot};
-use tracing::{info, trace};
+use tracing::{info, trace, Instrument, Span};
 
 /// Request sent from FUSE callback to async worker.
 #[derive(Debug)]
@@ -15,6 +19,7 @@ pub enum FuseRequest {
         offset: u64,
         size: usize,
         timeout: Duration,
+        mode: ReadMode,
         response_tx: std::sync::mpsc::Sender<FuseResponse>,
     },
     CheckPiecesAvailable {
@@ -24,35 +29,90 @@ pub enum FuseRequest {
         timeout: Duration,
         response_tx: std::sync::mpsc::Sender<FuseResponse>,
     },
+    /// Count the pieces from `first` to `last` (inclusive) rqbit has.
+    CountPieces {
+        torrent_id: u64,
+        first: u64,
+        last: u64,
+        timeout: Duration,
+        response_tx: std::sync::mpsc::Sender<FuseResponse>,
+    },
+    /// Forget a torrent, deleting its files with `delete_files`.
     ForgetTorrent {
         torrent_id: u64,
+        delete_files: bool,
         response_tx: std::sync::mpsc::Sender<FuseResponse>,
     },
+    /// Pause (`paused`) or resume a torrent.
+    SetPaused {
+        torrent_id: u64,
+        paused: bool,
+        response_tx: std::sync::mpsc::Sender<FuseResponse>,
+    },
+    /// Generate the contents of a virtual file.
+    RenderVirtual {
+        torrent_id: u64,
+        file: VirtualFile,
+        response_tx: std::sync::mpsc::Sender<FuseResponse>,
+    },
+    /// Add a torrent from a magnet link or a torrent URL.
+    AddTorrent {
+        link: String,
+        response_tx: std::sync::mpsc::Sender<FuseResponse>,
+    },
+    /// Add a torrent from the contents of a `.torrent` file.
+    AddTorrentFile {
+        contents: Bytes,
+        response_tx: std::sync::mpsc::Sender<FuseResponse>,
+    },
+}
+
+/// How a read fetches its data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Wait for the whole range.
+    Full,
+    /// Read whole pieces and check their hashes.
+    Verified,
+    /// Return the data received by the timeout instead of failing.
+    Partial,
+}
+
+/// A request waiting for the worker, with the trace of the FUSE operation
+/// that sent it.
+struct QueuedRequest {
+    request: FuseRequest,
+    trace_id: Option<String>,
+    span: Span,
 }
 
 /// Response from async worker to FUSE callback.
 #[derive(Debug, Clone)]
 pub enum FuseResponse {
-    Success { data: Option<Vec<u8>> },
+    Success { data: Option<Bytes> },
     Error { error_code: i32, message: String },
     PiecesAvailable,
     PiecesNotAvailable { reason: String },
+    /// The number of pieces counted.
+    PieceCount(u64),
+    /// The torrent added, as rqbit lists it right after adding it.
+    Added(Box<TorrentInfo>),
 }
 
 /// Async worker that handles FUSE requests in an async context.
 pub struct AsyncFuseWorker {
-    request_tx: mpsc::Sender<FuseRequest>,
+    request_tx: mpsc::Sender<QueuedRequest>,
     shutdown_tx: Option<oneshot::Sender<()>>,
 }
 
 impl AsyncFuseWorker {
     /// Create a new async worker with the given API client and metrics.
     pub fn new(
-        api_client: Arc<RqbitClient>,
+        api_client: Arc<dyn TorrentClient>,
         metrics: Arc<Metrics>,
         channel_capacity: usize,
     ) -> Self {
-        let (request_tx, mut request_rx) = mpsc::channel::<FuseRequest>(channel_capacity);
+        let (request_tx, mut request_rx) = mpsc::channel::<QueuedRequest>(channel_capacity);
         let (shutdown_tx, mut shutdown_rx) = oneshot::channel();
 
         tokio::spawn(async move {
@@ -69,13 +129,22 @@ impl AsyncFuseWorker {
                     }
 
                     // Handle incoming requests
-                    Some(request) = request_rx.recv() => {
+                    Some(queued) = request_rx.recv() => {
                         let api_client = Arc::clone(&api_client);
                         let metrics = Arc::clone(&metrics);
 
                         // Spawn a task for each request to allow concurrent processing
                         tokio::spawn(async move {
-                            Self::handle_request(&api_client, &metrics, request).await;
+                            let QueuedRequest { request, trace_id, span } = queued;
+                            let handle = async {
+                                trace!("Dequeued request");
+                                Self::handle_request(&api_client, &metrics, request).await;
+                            }
+                            .instrument(span);
+                            match trace_id {
+                                Some(id) => request_id::scope(id, handle).await,
+                                None => handle.await,
+                            }
                         });
                     }
                 }
@@ -92,7 +161,7 @@ impl AsyncFuseWorker {
 
     /// Handle a single FUSE request.
     async fn handle_request(
-        api_client: &Arc<RqbitClient>,
+        api_client: &Arc<dyn TorrentClient>,
         metrics: &Arc<Metrics>,
         request: FuseRequest,
     ) {
@@ -103,24 +172,48 @@ impl AsyncFuseWorker {
                 offset,
                 size,
                 timeout,
+                mode,
                 response_tx,
             } => {
                 trace!("ReadFile: t={} f={} off={} sz={}", torrent_id, file_index, offset, size);
 
                 let start = std::time::Instant::now();
 
-                let result = tokio::time::timeout(
-                    timeout,
-                    api_client.read_file_streaming(torrent_id, file_index as usize, offset, size),
-                )
-                .await;
+                // A short read's deadline is no later than the timeout below,
+                // which polls the read first, so its data is never dropped
+                let read = async {
+                    match mode {
+                        ReadMode::Full => {
+                            api_client
+                                .read_file_streaming(torrent_id, file_index as usize, offset, size)
+                                .await
+                        }
+                        ReadMode::Verified => {
+                            api_client
+                                .read_file_verified(torrent_id, file_index as usize, offset, size)
+                                .await
+                        }
+                        ReadMode::Partial => {
+                            api_client
+                                .read_file_until(
+                                    torrent_id,
+                                    file_index as usize,
+                                    offset,
+                                    size,
+                                    start + timeout,
+                                )
+                                .await
+                        }
+                    }
+                };
+                let result = tokio::time::timeout(timeout, read).await;
 
                 let _latency = start.elapsed();
 
                 let response = match result {
                     Ok(Ok(data)) => {
                         metrics.record_read(data.len() as u64);
-                        FuseResponse::Success { data: Some(data.to_vec()) }
+                        FuseResponse::Success { data: Some(data) }
                     }
                     Ok(Err(e)) => {
                         metrics.record_error();
@@ -166,16 +259,136 @@ impl AsyncFuseWorker {
 
             FuseRequest::ForgetTorrent {
                 torrent_id,
+                delete_files,
                 response_tx,
             } => {
-                trace!("ForgetTorrent: t={}", torrent_id);
-
-                let response = match api_client.forget_torrent(torrent_id).await {
+                trace!(
+                    "ForgetTorrent: t={} delete_files={}",
+                    torrent_id,
+                    delete_files
+                );
+
+                let result = if delete_files {
+                    api_client.delete_torrent(torrent_id).await
+                } else {
+                    api_client.forget_torrent(torrent_id).await
+                };
+                let response = match result {
                     Ok(_) => FuseResponse::Success { data: None },
                     Err(e) => FuseResponse::Error { error_code: anyhow_to_errno(&e), message: e.to_string() },
                 };
                 let _ = response_tx.send(response);
             }
+
+            FuseRequest::SetPaused {
+                torrent_id,
+                paused,
+                response_tx,
+            } => {
+                trace!("SetPaused: t={} paused={}", torrent_id, paused);
+
+                let result = if paused {
+                    api_client.pause_torrent(torrent_id).await
+                } else {
+                    api_client.start_torrent(torrent_id).await
+                };
+                let response = match result {
+                    Ok(_) => FuseResponse::Success { data: None },
+                    Err(e) => FuseResponse::Error {
+                        error_code: anyhow_to_errno(&e),
+                        message: e.to_string(),
+                    },
+                };
+                let _ = response_tx.send(response);
+            }
+
+            FuseRequest::CountPieces {
+                torrent_id,
+                first,
+                last,
+                timeout,
+                response_tx,
+            } => {
+                trace!("CountPieces: t={} pieces={}..={}", torrent_id, first, last);
+
+                let result =
+                    tokio::time::timeout(timeout, api_client.get_piece_bitfield(torrent_id)).await;
+                let response = match result {
+                    Ok(Ok(bitfield)) => {
+                        let count = (first..=last)
+                            .filter(|&piece| bitfield.has_piece(piece as usize))
+                            .count();
+                        FuseResponse::PieceCount(count as u64)
+                    }
+                    Ok(Err(e)) => FuseResponse::Error {
+                        error_code: anyhow_to_errno(&e),
+                        message: e.to_string(),
+                    },
+                    Err(_) => FuseResponse::Error {
+                        error_code: libc::ETIMEDOUT,
+                        message: "Count timed out".to_string(),
+                    },
+                };
+                let _ = response_tx.send(response);
+            }
+
+            FuseRequest::RenderVirtual {
+                torrent_id,
+                file,
+                response_tx,
+            } => {
+                trace!("RenderVirtual: t={} file={:?}", torrent_id, file);
+
+                let response = match file.render(api_client.as_ref(), torrent_id).await {
+                    Ok(data) => FuseResponse::Success { data: Some(data) },
+                    Err(e) => FuseResponse::Error {
+                        error_code: anyhow_to_errno(&e),
+                        message: e.to_string(),
+                    },
+                };
+                let _ = response_tx.send(response);
+            }
+
+            FuseRequest::AddTorrent { link, response_tx } => {
+                trace!("AddTorrent");
+
+                let result = async {
+                    let added = if link.starts_with("magnet:") {
+                        api_client.add_torrent_magnet(&link).await?
+                    } else {
+                        api_client.add_torrent_url(&link).await?
+                    };
+                    api_client.get_torrent(added.id).await
+                };
+                let response = match result.await {
+                    Ok(info) => FuseResponse::Added(Box::new(info)),
+                    Err(e) => FuseResponse::Error {
+                        error_code: anyhow_to_errno(&e),
+                        message: e.to_string(),
+                    },
+                };
+                let _ = response_tx.send(response);
+            }
+
+            FuseRequest::AddTorrentFile {
+                contents,
+                response_tx,
+            } => {
+                trace!("AddTorrentFile: {} bytes", contents.len());
+
+                let result = async {
+                    let added = api_client.add_torrent_file(contents).await?;
+                    api_client.get_torrent(added.id).await
+                };
+                let response = match result.await {
+                    Ok(info) => FuseResponse::Added(Box::new(info)),
+                    Err(e) => FuseResponse::Error {
+                        error_code: anyhow_to_errno(&e),
+                        message: e.to_string(),
+                    },
+                };
+                let _ = response_tx.send(response);
+            }
         }
     }
 
@@ -189,7 +402,11 @@ impl AsyncFuseWorker {
         F: FnOnce(std::sync::mpsc::Sender<FuseResponse>) -> FuseRequest,
     {
         let (tx, rx) = std::sync::mpsc::channel();
-        let request = request_builder(tx);
+        let request = QueuedRequest {
+            request: request_builder(tx),
+            trace_id: request_id::current(),
+            span: Span::current(),
+        };
 
         match self.request_tx.try_send(request) {
             Ok(_) => match rx.recv_timeout(timeout) {
@@ -202,11 +419,37 @@ impl AsyncFuseWorker {
         }
     }
 
-    /// Read a file from a torrent.
-    pub fn read_file(&self, torrent_id: u64, file_index: u64, offset: u64, size: usize, timeout: Duration) -> RqbitFuseResult<Vec<u8>> {
-        match self.send_request(|tx| FuseRequest::ReadFile { torrent_id, file_index, offset, size, timeout, response_tx: tx }, timeout + Duration::from_secs(5))? {
+    /// Read a file from a torrent. With `verify_pieces`, the data returned
+    /// runs to the end of the last whole piece read.
+    pub fn read_file(
+        &self,
+        torrent_id: u64,
+        file_index: u64,
+        offset: u64,
+        size: usize,
+        timeout: Duration,
+        mode: ReadMode,
+    ) -> RqbitFuseResult<Bytes> {
+        match self.send_request(
+            |tx| FuseRequest::ReadFile {
+                torrent_id,
+                file_index,
+                offset,
+                size,
+                timeout,
+                mode,
+                response_tx: tx,
+            },
+            timeout + Duration::from_secs(5),
+        )? {
             FuseResponse::Success { data: Some(data) } => Ok(data),
-            FuseResponse::Error { error_code, message } => Err(RqbitFuseError::IoError(format!("Read failed (code {}): {}", error_code, message))),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Read failed (code {}): {}",
+                error_code, message
+            ))),
             _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
         }
     }
@@ -221,11 +464,162 @@ impl AsyncFuseWorker {
         }
     }
 
-    /// Forget/remove a torrent.
-    pub fn forget_torrent(&self, torrent_id: u64, timeout: Duration) -> RqbitFuseResult<()> {
-        match self.send_request(|tx| FuseRequest::ForgetTorrent { torrent_id, response_tx: tx }, timeout)? {
+    /// Count the pieces from `first` to `last` (inclusive) rqbit has.
+    pub fn count_pieces(
+        &self,
+        torrent_id: u64,
+        first: u64,
+        last: u64,
+        timeout: Duration,
+    ) -> RqbitFuseResult<u64> {
+        match self.send_request(
+            |tx| FuseRequest::CountPieces {
+                torrent_id,
+                first,
+                last,
+                timeout,
+                response_tx: tx,
+            },
+            timeout + Duration::from_secs(5),
+        )? {
+            FuseResponse::PieceCount(count) => Ok(count),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Count failed (code {}): {}",
+                error_code, message
+            ))),
+            _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
+        }
+    }
+
+    /// Forget/remove a torrent, deleting its files with `delete_files`.
+    pub fn forget_torrent(
+        &self,
+        torrent_id: u64,
+        delete_files: bool,
+        timeout: Duration,
+    ) -> RqbitFuseResult<()> {
+        match self.send_request(
+            |tx| FuseRequest::ForgetTorrent {
+                torrent_id,
+                delete_files,
+                response_tx: tx,
+            },
+            timeout,
+        )? {
+            FuseResponse::Success { .. } => Ok(()),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Forget failed (code {}): {}",
+                error_code, message
+            ))),
+            _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
+        }
+    }
+
+    /// Generate the current contents of a virtual file.
+    pub fn render_virtual(
+        &self,
+        torrent_id: u64,
+        file: VirtualFile,
+        timeout: Duration,
+    ) -> RqbitFuseResult<Bytes> {
+        match self.send_request(
+            |tx| FuseRequest::RenderVirtual {
+                torrent_id,
+                file,
+                response_tx: tx,
+            },
+            timeout,
+        )? {
+            FuseResponse::Success { data: Some(data) } => Ok(data),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Render failed (code {}): {}",
+                error_code, message
+            ))),
+            _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
+        }
+    }
+
+    /// Number of requests waiting for the worker.
+    pub fn queue_depth(&self) -> usize {
+        self.request_tx.max_capacity() - self.request_tx.capacity()
+    }
+
+    /// Add a torrent from a magnet link or a torrent URL.
+    pub fn add_torrent(&self, link: String, timeout: Duration) -> RqbitFuseResult<TorrentInfo> {
+        Self::added(self.send_request(
+            |tx| FuseRequest::AddTorrent {
+                link,
+                response_tx: tx,
+            },
+            timeout,
+        )?)
+    }
+
+    /// Add a torrent from the contents of a `.torrent` file.
+    pub fn add_torrent_file(
+        &self,
+        contents: Bytes,
+        timeout: Duration,
+    ) -> RqbitFuseResult<TorrentInfo> {
+        Self::added(self.send_request(
+            |tx| FuseRequest::AddTorrentFile {
+                contents,
+                response_tx: tx,
+            },
+            timeout,
+        )?)
+    }
+
+    fn added(response: FuseResponse) -> RqbitFuseResult<TorrentInfo> {
+        match response {
+            FuseResponse::Added(info) => Ok(*info),
+            FuseResponse::Error {
+                error_code: libc::EINVAL,
+                message,
+            } => Err(RqbitFuseError::InvalidArgument(message)),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Add failed (code {}): {}",
+                error_code, message
+            ))),
+            _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
+        }
+    }
+
+    /// Pause or resume a torrent.
+    pub fn set_paused(
+        &self,
+        torrent_id: u64,
+        paused: bool,
+        timeout: Duration,
+    ) -> RqbitFuseResult<()> {
+        match self.send_request(
+            |tx| FuseRequest::SetPaused {
+                torrent_id,
+                paused,
+                response_tx: tx,
+            },
+            timeout,
+        )? {
             FuseResponse::Success { .. } => Ok(()),
-            FuseResponse::Error { error_code, message } => Err(RqbitFuseError::IoError(format!("Forget failed (code {}): {}", error_code, message))),
+            FuseResponse::Error {
+                error_code,
+                message,
+            } => Err(RqbitFuseError::IoError(format!(
+                "Pause/resume failed (code {}): {}",
+                error_code, message
+            ))),
             _ => Err(RqbitFuseError::IoError("Unexpected response".to_string())),
         }
     }
@@ -252,14 +646,24 @@ mod tests {
     #[test]
     fn test_fuse_request_debug() {
         let (tx, _rx) = std::sync::mpsc::channel();
-        let request = FuseRequest::ReadFile { torrent_id: 1, file_index: 0, offset: 0, size: 1024, timeout: Duration::from_secs(5), response_tx: tx };
+        let request = FuseRequest::ReadFile {
+            torrent_id: 1,
+            file_index: 0,
+            offset: 0,
+            size: 1024,
+            timeout: Duration::from_secs(5),
+            mode: ReadMode::Full,
+            response_tx: tx,
+        };
         let debug_str = format!("{:?}", request);
         assert!(debug_str.contains("ReadFile"));
     }
 
     #[test]
     fn test_fuse_response_debug() {
-        let response = FuseResponse::Success { data: Some(vec![1, 2, 3]) };
+        let response = FuseResponse::Success {
+            data: Some(Bytes::from_static(&[1, 2, 3])),
+        };
         assert!(format!("{:?}", response).contains("Success"));
     }
 }
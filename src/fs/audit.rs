@@ -0,0 +1,150 @@
+//! Access audit log: one JSON line per open and read, recording who read
+//! what from the mount.
+
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Kind of access recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOp {
+    Open,
+    Read,
+}
+
+/// One line of the audit log.
+#[derive(Debug, Serialize)]
+struct AuditRecord<'a> {
+    /// Milliseconds since the Unix epoch.
+    time: u64,
+    op: AuditOp,
+    uid: u32,
+    pid: u32,
+    path: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bytes: Option<u64>,
+}
+
+/// Appends access records to the file set by `audit_log`.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<LineWriter<File>>,
+}
+
+impl AuditLog {
+    /// Opens `path` for appending, creating it readable by its owner only.
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .mode(0o600)
+            .open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    /// Records that `uid`/`pid` opened `path`.
+    pub fn record_open(&self, uid: u32, pid: u32, path: &Path) {
+        self.write(AuditRecord {
+            time: now_millis(),
+            op: AuditOp::Open,
+            uid,
+            pid,
+            path,
+            offset: None,
+            bytes: None,
+        });
+    }
+
+    /// Records that `uid`/`pid` read `bytes` bytes of `path` at `offset`.
+    pub fn record_read(&self, uid: u32, pid: u32, path: &Path, offset: u64, bytes: u64) {
+        self.write(AuditRecord {
+            time: now_millis(),
+            op: AuditOp::Read,
+            uid,
+            pid,
+            path,
+            offset: Some(offset),
+            bytes: Some(bytes),
+        });
+    }
+
+    fn write(&self, record: AuditRecord<'_>) {
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Failed to encode audit record: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        // A whole line per write keeps lines from concurrent mounts intact
+        if let Err(e) = self.file.lock().unwrap().write_all(&line) {
+            warn!("Failed to write audit log {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_are_json_lines() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(&path).unwrap();
+        log.record_open(1000, 42, Path::new("/mnt/torrents/Movie/movie.mkv"));
+        log.record_read(
+            1000,
+            42,
+            Path::new("/mnt/torrents/Movie/movie.mkv"),
+            4096,
+            131072,
+        );
+        drop(log);
+
+        // Reopening appends
+        AuditLog::open(&path)
+            .unwrap()
+            .record_open(0, 1, Path::new("/mnt/torrents/b"));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["op"], "open");
+        assert_eq!(lines[0]["uid"], 1000);
+        assert!(lines[0].get("offset").is_none());
+        assert_eq!(lines[1]["op"], "read");
+        assert_eq!(lines[1]["pid"], 42);
+        assert_eq!(lines[1]["path"], "/mnt/torrents/Movie/movie.mkv");
+        assert_eq!(lines[1]["offset"], 4096);
+        assert_eq!(lines[1]["bytes"], 131072);
+        assert_eq!(lines[2]["uid"], 0);
+
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,295 @@
+//! Export of a mount's inode tree, for backup tooling and external indexing
+//! without walking the mount itself.
+
+use crate::fs::filesystem::TorrentFS;
+use crate::fs::inode::InodeEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// The entries of one mount, in depth-first order sorted by name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountTree {
+    pub mount_point: PathBuf,
+    pub entries: Vec<TreeEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeEntryKind {
+    Directory,
+    File,
+    Symlink,
+}
+
+/// A file, directory or symlink of the mount.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeEntry {
+    /// Path inside the mount.
+    pub path: String,
+    pub kind: TreeEntryKind,
+    /// Size of a file; 0 for directories and symlinks.
+    pub size: u64,
+    /// Torrent of a file, or of a torrent's top-level directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub torrent_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_index: Option<u64>,
+    /// Target of a symlink.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// Absent while unknown, i.e. before the first status poll of a file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub availability: Option<Availability>,
+}
+
+/// How much of an entry rqbit can serve.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum Availability {
+    Complete,
+    Partial {
+        downloaded: u64,
+    },
+    /// The torrent failed in rqbit; reads return an error.
+    Failed {
+        error: String,
+    },
+    /// A magnet whose file list isn't known yet.
+    AwaitingMetadata,
+}
+
+/// A file below a directory whose children haven't been created yet.
+pub(crate) struct PendingFile {
+    /// Path below the directory.
+    pub components: Vec<String>,
+    pub file_index: u64,
+    pub size: u64,
+}
+
+impl TorrentFS {
+    /// Lists every entry of the mount. Directories of large torrents that
+    /// haven't been opened yet are expanded from the torrent's file list,
+    /// without creating their inodes.
+    pub fn export_tree(&self) -> MountTree {
+        let inodes = self.inode_manager();
+        let torrent_dirs: HashMap<u64, u64> = inodes
+            .torrent_to_inode()
+            .iter()
+            .map(|item| (*item.value(), *item.key()))
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut pending = vec![1];
+        while let Some(ino) = pending.pop() {
+            let Some(entry) = inodes.get(ino) else {
+                continue;
+            };
+            let path = entry.canonical_path().to_string();
+            match entry {
+                InodeEntry::Directory { .. } => {
+                    // The root itself is the mount point
+                    if ino != 1 {
+                        let torrent_id = torrent_dirs.get(&ino).copied();
+                        entries.push(TreeEntry {
+                            availability: torrent_id.and_then(|id| self.torrent_availability(id)),
+                            ..TreeEntry::new(path.clone(), TreeEntryKind::Directory, torrent_id)
+                        });
+                    }
+                    match self.pending_dir_files(ino) {
+                        Some((torrent_id, files)) => {
+                            entries.extend(self.pending_entries(&path, torrent_id, files))
+                        }
+                        None => pending.extend(children_by_name(self, ino)),
+                    }
+                }
+                InodeEntry::File {
+                    torrent_id,
+                    file_index,
+                    size,
+                    ..
+                } => entries.push(self.file_entry(path, torrent_id, file_index, size)),
+                InodeEntry::Symlink { target, .. } => entries.push(TreeEntry {
+                    target: Some(target),
+                    ..TreeEntry::new(path, TreeEntryKind::Symlink, None)
+                }),
+                // Generated when read, so no size
+                InodeEntry::Virtual { torrent_id, .. } => {
+                    entries.push(TreeEntry::new(path, TreeEntryKind::File, Some(torrent_id)))
+                }
+            }
+        }
+
+        MountTree {
+            mount_point: self.mount_point().to_path_buf(),
+            entries,
+        }
+    }
+
+    fn torrent_availability(&self, torrent_id: u64) -> Option<Availability> {
+        if let Some(error) = self.torrent_error(torrent_id) {
+            return Some(Availability::Failed { error });
+        }
+        self.is_awaiting_metadata(torrent_id)
+            .then_some(Availability::AwaitingMetadata)
+    }
+
+    fn file_entry(&self, path: String, torrent_id: u64, file_index: u64, size: u64) -> TreeEntry {
+        let availability = match self.torrent_error(torrent_id) {
+            Some(error) => Some(Availability::Failed { error }),
+            None => self
+                .file_progress(torrent_id, file_index)
+                .map(|downloaded| {
+                    if downloaded >= size {
+                        Availability::Complete
+                    } else {
+                        Availability::Partial { downloaded }
+                    }
+                }),
+        };
+        TreeEntry {
+            size,
+            file_index: Some(file_index),
+            availability,
+            ..TreeEntry::new(path, TreeEntryKind::File, Some(torrent_id))
+        }
+    }
+
+    /// Entries below a lazily built directory, from the torrent's file list.
+    fn pending_entries(
+        &self,
+        dir_path: &str,
+        torrent_id: u64,
+        files: Vec<PendingFile>,
+    ) -> Vec<TreeEntry> {
+        let dir_path = dir_path.trim_end_matches('/');
+        let join = |components: &[String]| format!("{}/{}", dir_path, components.join("/"));
+        let mut dirs = BTreeSet::new();
+        let mut entries: Vec<_> = files
+            .into_iter()
+            .map(|file| {
+                for depth in 1..file.components.len() {
+                    dirs.insert(join(&file.components[..depth]));
+                }
+                self.file_entry(
+                    join(&file.components),
+                    torrent_id,
+                    file.file_index,
+                    file.size,
+                )
+            })
+            .collect();
+        entries.extend(
+            dirs.into_iter()
+                .map(|path| TreeEntry::new(path, TreeEntryKind::Directory, None)),
+        );
+        entries.sort_by(|a, b| a.path.split('/').cmp(b.path.split('/')));
+        entries
+    }
+}
+
+impl TreeEntry {
+    fn new(path: String, kind: TreeEntryKind, torrent_id: Option<u64>) -> Self {
+        Self {
+            path,
+            kind,
+            size: 0,
+            torrent_id,
+            file_index: None,
+            target: None,
+            availability: None,
+        }
+    }
+}
+
+/// Children of a directory, ordered so popping them visits names in order.
+fn children_by_name(fs: &TorrentFS, ino: u64) -> Vec<u64> {
+    let mut children = fs.inode_manager().get_children(ino);
+    children.sort_by(|(_, a), (_, b)| b.name().cmp(a.name()));
+    children.into_iter().map(|(child, _)| child).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::{FileInfo, TorrentInfo};
+    use crate::api::TorrentClient;
+    use crate::config::Config;
+    use crate::fs::async_bridge::AsyncFuseWorker;
+    use crate::metrics::Metrics;
+    use std::sync::Arc;
+
+    fn create_test_fs(lazy_tree_threshold: usize) -> TorrentFS {
+        let config = Config {
+            lazy_tree_threshold,
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(crate::api::client::RqbitClient::new(config.api_url.clone()).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            10,
+        ));
+        TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap()
+    }
+
+    fn torrent() -> TorrentInfo {
+        let file = |components: &[&str], length| FileInfo {
+            name: components.last().unwrap().to_string(),
+            length,
+            components: components.iter().map(|c| c.to_string()).collect(),
+        };
+        TorrentInfo {
+            id: 7,
+            info_hash: "abc".to_string(),
+            name: "Album".to_string(),
+            output_folder: "/downloads".to_string(),
+            file_count: Some(3),
+            files: vec![
+                file(&["b.flac"], 30),
+                file(&["a", "sub", "c.flac"], 10),
+                file(&["a.flac"], 20),
+            ],
+            piece_length: Some(16384),
+            tags: None,
+            deferred_size: None,
+        }
+    }
+
+    fn paths(tree: &MountTree) -> Vec<(&str, TreeEntryKind, u64)> {
+        tree.entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.kind, e.size))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_export_lists_eager_and_lazy_trees_alike() {
+        let expected = vec![
+            ("/Album", TreeEntryKind::Directory, 0),
+            ("/Album/a", TreeEntryKind::Directory, 0),
+            ("/Album/a/sub", TreeEntryKind::Directory, 0),
+            ("/Album/a/sub/c.flac", TreeEntryKind::File, 10),
+            ("/Album/a.flac", TreeEntryKind::File, 20),
+            ("/Album/b.flac", TreeEntryKind::File, 30),
+        ];
+
+        let eager = create_test_fs(1000);
+        eager.create_torrent_structure(&torrent()).unwrap();
+        let tree = eager.export_tree();
+        assert_eq!(paths(&tree), expected);
+        assert_eq!(tree.entries[0].torrent_id, Some(7));
+        assert_eq!(tree.entries[3].file_index, Some(1));
+
+        let lazy = create_test_fs(2);
+        lazy.create_torrent_structure(&torrent()).unwrap();
+        let inodes = lazy.inode_manager().len();
+        let tree = lazy.export_tree();
+        assert_eq!(paths(&tree), expected);
+        assert_eq!(tree.entries[3].file_index, Some(1));
+        // Listing doesn't build the pending directories
+        assert_eq!(lazy.inode_manager().len(), inodes);
+    }
+}
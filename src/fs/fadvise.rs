@@ -0,0 +1,144 @@
+//! `posix_fadvise` hints for files of the mount.
+//!
+//! The kernel answers posix_fadvise(2) itself and never passes it on to FUSE
+//! filesystems, so hints reach rqbit-fuse as an ioctl on an open file
+//! instead, sent by `rqbit-fuse advise` or by applications directly.
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// The advice of a [`FADVISE_IOCTL`], laid out as `struct { u64 offset;
+/// u64 len; u32 advice; u32 reserved; }` like the arguments of
+/// posix_fadvise(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FadviseArgs {
+    pub offset: u64,
+    /// 0 = to the end of the file.
+    pub len: u64,
+    pub advice: Advice,
+}
+
+/// Size of [`FadviseArgs`] on the wire.
+const ARGS_SIZE: usize = 24;
+
+/// `_IOW('R', 1, struct fadvise_args)`, in the encoding of x86, arm and
+/// riscv.
+pub const FADVISE_IOCTL: u32 = (1 << 30) | ((ARGS_SIZE as u32) << 16) | ((b'R' as u32) << 8) | 1;
+
+/// The `POSIX_FADV_*` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// Forget earlier advice.
+    Normal,
+    /// Read ahead as far as `readahead_max_size`.
+    Sequential,
+    /// Don't read ahead.
+    Random,
+    /// Fetch the range in the background now.
+    WillNeed,
+    /// Accepted, no effect: rqbit-fuse keeps no cache to drop.
+    DontNeed,
+    /// Accepted, no effect.
+    NoReuse,
+}
+
+impl Advice {
+    pub fn from_raw(advice: u32) -> Option<Self> {
+        match advice as i32 {
+            libc::POSIX_FADV_NORMAL => Some(Advice::Normal),
+            libc::POSIX_FADV_SEQUENTIAL => Some(Advice::Sequential),
+            libc::POSIX_FADV_RANDOM => Some(Advice::Random),
+            libc::POSIX_FADV_WILLNEED => Some(Advice::WillNeed),
+            libc::POSIX_FADV_DONTNEED => Some(Advice::DontNeed),
+            libc::POSIX_FADV_NOREUSE => Some(Advice::NoReuse),
+            _ => None,
+        }
+    }
+
+    pub fn to_raw(self) -> u32 {
+        (match self {
+            Advice::Normal => libc::POSIX_FADV_NORMAL,
+            Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+            Advice::Random => libc::POSIX_FADV_RANDOM,
+            Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+            Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+            Advice::NoReuse => libc::POSIX_FADV_NOREUSE,
+        }) as u32
+    }
+}
+
+impl std::str::FromStr for Advice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "normal" => Ok(Advice::Normal),
+            "sequential" => Ok(Advice::Sequential),
+            "random" => Ok(Advice::Random),
+            "willneed" => Ok(Advice::WillNeed),
+            "dontneed" => Ok(Advice::DontNeed),
+            "noreuse" => Ok(Advice::NoReuse),
+            _ => Err(format!(
+                "unknown advice '{}' (expected normal, sequential, random, willneed, dontneed or noreuse)",
+                s
+            )),
+        }
+    }
+}
+
+impl FadviseArgs {
+    pub fn to_bytes(&self) -> [u8; ARGS_SIZE] {
+        let mut bytes = [0; ARGS_SIZE];
+        bytes[..8].copy_from_slice(&self.offset.to_ne_bytes());
+        bytes[8..16].copy_from_slice(&self.len.to_ne_bytes());
+        bytes[16..20].copy_from_slice(&self.advice.to_raw().to_ne_bytes());
+        bytes
+    }
+
+    /// Parses the ioctl's input; None if it is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ARGS_SIZE {
+            return None;
+        }
+        let u64_at = |at: usize| u64::from_ne_bytes(bytes[at..at + 8].try_into().unwrap());
+        Some(Self {
+            offset: u64_at(0),
+            len: u64_at(8),
+            advice: Advice::from_raw(u32::from_ne_bytes(bytes[16..20].try_into().unwrap()))?,
+        })
+    }
+}
+
+/// Sends `args` for a file of a mount.
+pub fn advise(path: &Path, args: &FadviseArgs) -> std::io::Result<()> {
+    let file = File::open(path)?;
+    let bytes = args.to_bytes();
+    // SAFETY: the ioctl reads ARGS_SIZE bytes, the size encoded in its number
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), FADVISE_IOCTL as _, bytes.as_ptr()) };
+    if result < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args_round_trip() {
+        let args = FadviseArgs {
+            offset: 1 << 20,
+            len: 4096,
+            advice: Advice::WillNeed,
+        };
+        assert_eq!(FadviseArgs::from_bytes(&args.to_bytes()), Some(args));
+        assert_eq!(FadviseArgs::from_bytes(&[0; 8]), None);
+
+        let mut bytes = args.to_bytes();
+        bytes[16] = 42;
+        assert_eq!(FadviseArgs::from_bytes(&bytes), None);
+        assert_eq!(FADVISE_IOCTL, 0x4018_5201);
+    }
+}
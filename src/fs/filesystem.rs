@@ -1,21 +1,36 @@
-use crate::api::client::RqbitClient;
-use crate::api::create_api_client;
-
-use crate::config::Config;
-use crate::fs::async_bridge::AsyncFuseWorker;
+use crate::api::backend::TorrentClient;
+use crate::api::create_client;
+use crate::api::request_id;
+use crate::api::ReadPriority;
+
+use crate::config::{Config, PageCacheMode, TorrentRemoval};
+use crate::fs::activity::Activity;
+use crate::fs::async_bridge::{AsyncFuseWorker, ReadMode};
+use crate::fs::audit::AuditLog;
+use crate::fs::export::PendingFile;
+use crate::fs::fadvise::{Advice, FadviseArgs, FADVISE_IOCTL};
+use crate::fs::filter::TorrentFilter;
 use crate::fs::inode::InodeEntry;
 use crate::fs::inode::InodeManager;
+use crate::fs::quota::ReadQuota;
+use crate::fs::retry::{RetryList, RetryOutcome};
+use crate::fs::snapshot::SnapshotStore;
+use crate::fs::trash::{Trash, TrashItem, TRASH_DIR};
+use crate::fs::virtual_files::{
+    percent, stats_file_name, VirtualFile, ADD_FILE, CONTROL_DIR, STATUS_FILES, TORRENTS_DIR,
+};
 
 use crate::metrics::Metrics;
-use crate::types::handle::FileHandleManager;
+use crate::types::handle::{FileHandle, FileHandleManager};
 use anyhow::{Context, Result};
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use fuser::Filesystem;
+use futures::stream::StreamExt;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{Mutex, Semaphore};
-use tokio::time::interval;
 use tracing::{debug, error, info, instrument, trace, warn};
 
 // Platform-specific error code for "no attribute"
@@ -25,13 +40,154 @@ const ENOATTR: i32 = libc::ENOATTR;
 #[cfg(not(target_os = "macos"))]
 const ENOATTR: i32 = libc::ENODATA;
 
+/// Attributes of torrent files locating them in the torrent: the file's
+/// index in rqbit, and its first and last piece; and how much of the file
+/// was downloaded at the last status poll.
+const FILE_XATTRS: [&str; 4] = [
+    "user.torrent.file_index",
+    "user.torrent.piece_start",
+    "user.torrent.piece_end",
+    "user.torrent.progress",
+];
+
+/// Attribute of torrent files counting their pieces rqbit has, asked from
+/// rqbit on every read.
+const PIECES_XATTR: &str = "user.torrent.pieces_available";
+
+/// Attributes of torrent directories and files describing their torrent.
+const TORRENT_XATTRS: [&str; 3] = [
+    "user.torrent.status",
+    "user.torrent.error",
+    "user.torrent.infohash",
+];
+
+/// Largest `.torrent` file accepted by copying it into the root.
+const MAX_TORRENT_FILE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Returns true for `security.*` and `system.*` (POSIX ACL) attributes,
+/// which this filesystem never has.
+fn is_kernel_xattr(name: &std::ffi::OsStr) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    let name = name.as_bytes();
+    name.starts_with(b"security.") || name.starts_with(b"system.")
+}
+
+/// A directory of a lazily built torrent whose children haven't been
+/// created yet.
+#[derive(Clone)]
+struct PendingDir {
+    torrent_id: u64,
+    files: Arc<[crate::api::types::FileInfo]>,
+    /// Path components of this directory within the torrent.
+    prefix: Vec<String>,
+}
+
+/// The rendered children of a large directory, reused by repeated listings
+/// until it expires or the tree changes.
+struct CachedListing {
+    built: Instant,
+    tree_version: u64,
+    entries: Arc<[(u64, fuser::FileType, String)]>,
+}
+
+/// Inclusive byte ranges covering the first and last `length` bytes of a
+/// file of `size` bytes, merged when they overlap.
+fn head_tail_ranges(size: u64, length: u64) -> Vec<(u64, u64)> {
+    if size <= length.saturating_mul(2) {
+        vec![(0, size - 1)]
+    } else {
+        vec![(0, length - 1), (size - length, size - 1)]
+    }
+}
+
+/// Offset the kernel passes back to continue a listing after `child_ino`.
+///
+/// Cookies come from inode numbers rather than positions, so torrents added
+/// or removed between readdir calls never shift the entries still to come.
+/// Inode numbers start at 2, so cookies don't collide with `.` (1) and `..`
+/// (2).
+fn readdir_cookie(child_ino: u64) -> i64 {
+    child_ino as i64 + 1
+}
+
+/// The children, sorted by inode, still to list after the entry whose
+/// cookie is `offset`.
+fn children_after(children: &[u64], offset: i64) -> &[u64] {
+    let start = children.partition_point(|&ino| readdir_cookie(ino) <= offset);
+    &children[start..]
+}
+
+/// The FUSE file type of an entry.
+fn file_type(entry: &InodeEntry) -> fuser::FileType {
+    if entry.is_directory() {
+        fuser::FileType::Directory
+    } else if entry.is_symlink() {
+        fuser::FileType::Symlink
+    } else {
+        fuser::FileType::RegularFile
+    }
+}
+
+/// Whether an entry takes writes: only the add file does.
+fn is_writable(entry: &InodeEntry) -> bool {
+    matches!(entry, InodeEntry::Virtual { file, .. } if file.is_writable())
+}
+
+/// Where a torrent's files lie in its pieces, for the piece xattrs.
+struct PieceLayout {
+    piece_length: u64,
+    /// Offset in the torrent and length of each file.
+    files: Arc<[(u64, u64)]>,
+}
+
+impl PieceLayout {
+    /// The layout of a torrent whose piece length and files are known.
+    fn of(torrent_info: &crate::api::types::TorrentInfo) -> Option<Self> {
+        let piece_length = torrent_info.piece_length.filter(|&len| len > 0)?;
+        if torrent_info.files.is_empty() {
+            return None;
+        }
+        let mut offset = 0u64;
+        let files = torrent_info
+            .files
+            .iter()
+            .map(|file| {
+                let start = offset;
+                offset = offset.saturating_add(file.length);
+                (start, file.length)
+            })
+            .collect();
+        Some(Self {
+            piece_length,
+            files,
+        })
+    }
+
+    /// First and last piece holding file `file_index`. An empty file gets
+    /// the piece its offset falls in.
+    fn piece_range(&self, file_index: u64) -> Option<(u64, u64)> {
+        let &(offset, length) = self.files.get(usize::try_from(file_index).ok()?)?;
+        let last_byte = offset + length.saturating_sub(1);
+        Some((offset / self.piece_length, last_byte / self.piece_length))
+    }
+}
 
+/// Replies with an xattr value, or its size when the caller passes 0.
+fn reply_xattr(reply: fuser::ReplyXattr, size: u32, data: &[u8]) {
+    if size == 0 {
+        reply.size(data.len() as u32);
+    } else if data.len() <= size as usize {
+        reply.data(data);
+    } else {
+        reply.error(libc::ERANGE);
+    }
+}
 
 /// Main FUSE filesystem implementation for rqbit-fuse. Clone is cheap (Arc-based).
 #[derive(Clone)]
 pub struct TorrentFS {
     config: Config,
-    api_client: Arc<RqbitClient>,
+    api_client: Arc<dyn TorrentClient>,
     inode_manager: Arc<InodeManager>,
     initialized: bool,
     file_handles: Arc<FileHandleManager>,
@@ -41,6 +197,74 @@ pub struct TorrentFS {
     last_discovery: Arc<AtomicU64>,
     async_worker: Arc<AsyncFuseWorker>,
     read_semaphore: Arc<Semaphore>,
+    filter: Arc<TorrentFilter>,
+    pending_dirs: Arc<DashMap<u64, PendingDir>>,
+    /// Directories of torrents listed from their summary alone, to the
+    /// torrent whose file list is fetched on first access.
+    deferred_files: Arc<DashMap<u64, u64>>,
+    /// Torrents shown as placeholder directories until rqbit resolves their
+    /// metadata.
+    awaiting_metadata: Arc<DashSet<u64>>,
+    /// Torrents rqbit reports in its error state, with the error message.
+    failed_torrents: Arc<DashMap<u64, String>>,
+    /// Total file size of each torrent, for statfs.
+    torrent_sizes: Arc<DashMap<u64, u64>>,
+    /// Piece layout of each torrent, for the piece xattrs of its files.
+    piece_layouts: Arc<DashMap<u64, PieceLayout>>,
+    /// Indexes of each torrent's unfinished files, hidden with
+    /// `hide_incomplete`.
+    incomplete_files: Arc<DashMap<u64, HashSet<u64>>>,
+    /// Downloaded bytes of each file as of the last status poll, by
+    /// `(torrent_id, file_index)`; reported as allocated blocks.
+    file_progress: Arc<DashMap<(u64, u64), u64>>,
+    /// Files opened with the page cache on while still downloading, by
+    /// `(torrent_id, file_index)`. Their first open once complete drops
+    /// the pages cached meanwhile.
+    cached_while_downloading: Arc<DashSet<(u64, u64)>>,
+    /// When each directory last gained or lost entries or finished files
+    /// while mounted, so scanners can skip unchanged directories.
+    dir_mtimes: Arc<DashMap<u64, std::time::SystemTime>>,
+    /// Reported as the mtime of directories that haven't changed since.
+    mounted_at: std::time::SystemTime,
+    /// Listings of large directories, by inode.
+    readdir_cache: Arc<DashMap<u64, CachedListing>>,
+    /// Files whose head and tail were already prefetched.
+    prefetched_files: Arc<DashSet<(u64, u64)>>,
+    /// Readahead advice sent for files through [`FADVISE_IOCTL`], by
+    /// `(torrent_id, file_index)`.
+    file_advice: Arc<DashMap<(u64, u64), Advice>>,
+    /// Unmounts the FUSE session serving this filesystem, while mounted.
+    unmounter: Arc<std::sync::Mutex<Option<fuser::SessionUnmounter>>>,
+    /// Invalidates kernel caches of the FUSE session, while mounted.
+    notifier: Arc<std::sync::Mutex<Option<fuser::Notifier>>>,
+    /// Records opens and reads when `audit_log` is set.
+    audit_log: Option<Arc<AuditLog>>,
+    /// Bytes read per uid against the `[quota]` limits.
+    quota: Arc<ReadQuota>,
+    /// Torrents removed into `/.trash`, when `trash_days` is set.
+    trash: Option<Arc<Trash>>,
+    /// Info hash of each torrent, to record it in the trash.
+    info_hashes: Arc<DashMap<u64, String>>,
+    /// Directory each trashed torrent is restored to.
+    trash_homes: Arc<DashMap<u64, u64>>,
+    /// Bytes read from each torrent, for `rqbit-fuse top`.
+    torrent_reads: Arc<DashMap<u64, u64>>,
+    /// Last torrent list seen from rqbit, served when `offline` is set.
+    snapshot: Arc<SnapshotStore>,
+    /// Contents of open generated files, by file handle.
+    virtual_contents: Arc<DashMap<u64, bytes::Bytes>>,
+    /// Data written to `/.rqbit-fuse/add` short of a complete line, by file
+    /// handle.
+    pending_links: Arc<DashMap<u64, Vec<u8>>>,
+    /// Contents of `.torrent` files being copied into the root, by file
+    /// handle.
+    torrent_uploads: Arc<DashMap<u64, Vec<u8>>>,
+    /// Torrents whose structure couldn't be created, attempted again with
+    /// backoff.
+    materialize_retries: Arc<RetryList>,
+    /// When the mount was last used, for `idle_poll_interval` and
+    /// `idle_sleep_after`.
+    activity: Arc<Activity>,
 }
 
 impl TorrentFS {
@@ -65,31 +289,102 @@ impl TorrentFS {
         metrics: Arc<Metrics>,
         async_worker: Arc<AsyncFuseWorker>,
     ) -> Result<Self> {
-        let api_client = Arc::new(
-            create_api_client(
-                &config.api_url,
-                config.api_username.as_deref(),
-                config.api_password.as_deref(),
-                Some(Arc::clone(&metrics)),
-            )
-            .context("API client creation failed")?,
-        );
+        let api_client = create_client(&config, Arc::clone(&metrics))?;
+        Self::with_api_client(config, api_client, metrics, async_worker)
+    }
+
+    /// Create new TorrentFS instance using an existing API client, so several
+    /// mounts can share one connection pool and its caches.
+    pub fn with_api_client(
+        config: Config,
+        api_client: Arc<dyn TorrentClient>,
+        metrics: Arc<Metrics>,
+        async_worker: Arc<AsyncFuseWorker>,
+    ) -> Result<Self> {
         let inode_manager = Arc::new(InodeManager::with_max_inodes(100000));
         let read_semaphore = Arc::new(Semaphore::new(config.max_concurrent_reads));
+        let filter = Arc::new(TorrentFilter::new(&config.filter).context("invalid [filter]")?);
+        let audit_log = match &config.audit_log {
+            Some(path) => {
+                Some(Arc::new(AuditLog::open(path).with_context(|| {
+                    format!("Failed to open audit log {}", path.display())
+                })?))
+            }
+            None => None,
+        };
+
+        let quota = Arc::new(ReadQuota::new(config.quota.clone()));
+        let trash = if config.trash_days > 0 {
+            let path = config.trash_state_path();
+            let retention = Duration::from_secs(config.trash_days * 24 * 60 * 60);
+            Some(Arc::new(Trash::open(&path, retention).with_context(
+                || format!("Failed to open trash state {}", path.display()),
+            )?))
+        } else {
+            None
+        };
+
+        let snapshot = Arc::new(SnapshotStore::new(
+            &config.metadata_snapshot_path(),
+            &config.api_url,
+        ));
+
+        let file_handles = Arc::new(
+            FileHandleManager::with_max_handles(config.max_open_handles)
+                .with_max_handles_per_uid(config.max_open_handles_per_uid)
+                .with_memory(Arc::clone(api_client.memory()))
+                .with_checksums(config.cache_checksums),
+        );
 
-        Ok(Self {
+        let fs = Self {
             config,
             api_client,
             inode_manager,
             initialized: false,
-            file_handles: Arc::new(FileHandleManager::new()),
+            file_handles,
             known_torrents: Arc::new(DashSet::new()),
             discovery_handle: Arc::new(Mutex::new(None)),
             metrics,
             last_discovery: Arc::new(AtomicU64::new(0)),
             async_worker,
             read_semaphore,
-        })
+            filter,
+            pending_dirs: Arc::new(DashMap::new()),
+            deferred_files: Arc::new(DashMap::new()),
+            awaiting_metadata: Arc::new(DashSet::new()),
+            failed_torrents: Arc::new(DashMap::new()),
+            torrent_sizes: Arc::new(DashMap::new()),
+            piece_layouts: Arc::new(DashMap::new()),
+            incomplete_files: Arc::new(DashMap::new()),
+            file_progress: Arc::new(DashMap::new()),
+            cached_while_downloading: Arc::new(DashSet::new()),
+            dir_mtimes: Arc::new(DashMap::new()),
+            mounted_at: std::time::SystemTime::now(),
+            readdir_cache: Arc::new(DashMap::new()),
+            prefetched_files: Arc::new(DashSet::new()),
+            file_advice: Arc::new(DashMap::new()),
+            unmounter: Arc::new(std::sync::Mutex::new(None)),
+            notifier: Arc::new(std::sync::Mutex::new(None)),
+            audit_log,
+            quota,
+            trash,
+            info_hashes: Arc::new(DashMap::new()),
+            trash_homes: Arc::new(DashMap::new()),
+            torrent_reads: Arc::new(DashMap::new()),
+            snapshot,
+            virtual_contents: Arc::new(DashMap::new()),
+            pending_links: Arc::new(DashMap::new()),
+            torrent_uploads: Arc::new(DashMap::new()),
+            materialize_retries: Arc::new(RetryList::new()),
+            activity: Arc::new(Activity::new()),
+        };
+        if fs.trash.is_some() {
+            fs.ensure_root_directory(TRASH_DIR.to_string());
+        }
+        if fs.config.add_torrents && !fs.config.offline {
+            fs.create_add_file();
+        }
+        Ok(fs)
     }
 
     pub fn read_semaphore(&self) -> &Arc<Semaphore> {
@@ -101,82 +396,166 @@ impl TorrentFS {
         (self.config.max_concurrent_reads, self.read_semaphore.available_permits())
     }
 
-    #[cfg(test)]
-    pub fn known_torrents(&self) -> &Arc<DashSet<u64>> {
+    pub(crate) fn known_torrents(&self) -> &Arc<DashSet<u64>> {
         &self.known_torrents
     }
 
+    /// Number of torrents currently materialized in the tree.
+    pub fn torrent_count(&self) -> usize {
+        self.inode_manager.torrent_to_inode().len()
+    }
+
+    /// Number of open file handles.
+    pub fn open_handle_count(&self) -> usize {
+        self.file_handles.len()
+    }
+
+    pub(crate) fn file_handles(&self) -> &Arc<FileHandleManager> {
+        &self.file_handles
+    }
+
+    /// Bytes read from each torrent since it was added.
+    pub(crate) fn torrent_reads(&self) -> Vec<(u64, u64)> {
+        self.torrent_reads
+            .iter()
+            .map(|e| (*e.key(), *e.value()))
+            .collect()
+    }
+
+    /// Bytes of a file rqbit had downloaded at the last status poll.
+    pub(crate) fn file_progress(&self, torrent_id: u64, file_index: u64) -> Option<u64> {
+        self.file_progress
+            .get(&(torrent_id, file_index))
+            .map(|progress| *progress)
+    }
+
+    /// The error rqbit reports for a failed torrent.
+    pub(crate) fn torrent_error(&self, torrent_id: u64) -> Option<String> {
+        self.failed_torrents.get(&torrent_id).map(|m| m.clone())
+    }
+
+    pub(crate) fn is_awaiting_metadata(&self, torrent_id: u64) -> bool {
+        self.awaiting_metadata.contains(&torrent_id)
+    }
+
+    /// Torrent and files below a lazily built directory whose children
+    /// haven't been created yet.
+    pub(crate) fn pending_dir_files(&self, ino: u64) -> Option<(u64, Vec<PendingFile>)> {
+        let pending = self.pending_dirs.get(&ino)?;
+        let depth = pending.prefix.len();
+        let files = pending
+            .files
+            .iter()
+            .enumerate()
+            .filter_map(|(file_idx, file_info)| {
+                let components = if file_info.components.is_empty() {
+                    std::slice::from_ref(&file_info.name)
+                } else {
+                    file_info.components.as_slice()
+                };
+                if components.len() <= depth
+                    || components[..depth] != pending.prefix[..]
+                    || self.filter.hides_file(components)
+                {
+                    return None;
+                }
+                let below = components[depth..]
+                    .iter()
+                    .map(|c| sanitize_filename(c))
+                    .collect();
+                Some(PendingFile {
+                    components: below,
+                    file_index: file_idx as u64,
+                    size: file_info.length,
+                })
+            })
+            .collect();
+        Some((pending.torrent_id, files))
+    }
+
+    /// Records a read through `fh` for `rqbit-fuse top`.
+    fn record_activity(
+        &self,
+        fh: u64,
+        torrent_id: u64,
+        offset: u64,
+        bytes: u64,
+        latency: Duration,
+    ) {
+        self.file_handles.record_read(fh, offset, bytes, latency);
+        *self.torrent_reads.entry(torrent_id).or_insert(0) += bytes;
+    }
+
+    /// Removes handles unused for `handle_ttl`, reporting each as orphaned.
+    /// Their streams are left to [`Self::close_orphaned_streams`].
+    fn evict_idle_handles(&self) -> Vec<FileHandle> {
+        if self.config.handle_ttl == 0 {
+            return Vec::new();
+        }
+        let evicted = self
+            .file_handles
+            .evict_idle(Duration::from_secs(self.config.handle_ttl));
+        self.metrics.record_handles_evicted(evicted.len() as u64);
+        for handle in &evicted {
+            self.virtual_contents.remove(&handle.fh);
+            self.pending_links.remove(&handle.fh);
+            self.torrent_uploads.remove(&handle.fh);
+            let path = self
+                .inode_manager
+                .with_entry(handle.inode, |e| e.canonical_path().to_string())
+                .unwrap_or_default();
+            warn!(
+                handle_op = "orphaned",
+                fh = handle.fh,
+                uid = handle.uid,
+                torrent_id = handle.torrent_id,
+                path = %path,
+                idle_secs = handle.last_used.elapsed().as_secs(),
+                bytes_read = handle.bytes_read,
+                "Evicted file handle whose release never arrived"
+            );
+        }
+        evicted
+    }
+
+    /// Closes the streams of evicted handles' files that no open handle
+    /// reads any more, instead of leaving them to the stream idle timeout.
+    async fn close_orphaned_streams(&self, evicted: &[FileHandle]) {
+        let inodes: HashSet<u64> = evicted.iter().map(|handle| handle.inode).collect();
+        for inode in inodes {
+            if !self.file_handles.get_handles_for_inode(inode).is_empty() {
+                continue;
+            }
+            if let Some(InodeEntry::File {
+                torrent_id,
+                file_index,
+                ..
+            }) = self.inode_manager.get(inode)
+            {
+                if self
+                    .api_client
+                    .close_stream(torrent_id, file_index as usize)
+                    .await
+                {
+                    debug!(
+                        handle_op = "orphan_stream_closed",
+                        torrent_id = torrent_id,
+                        file_index = file_index
+                    );
+                }
+            }
+        }
+    }
+
     fn start_torrent_discovery(&self) {
-        let api_client = Arc::clone(&self.api_client);
-        let inode_manager = Arc::clone(&self.inode_manager);
-        let last_discovery = Arc::clone(&self.last_discovery);
-        let known_torrents = Arc::clone(&self.known_torrents);
-        let file_handles = Arc::clone(&self.file_handles);
-        let poll_interval = Duration::from_secs(30);
+        let fs = self.clone();
 
         let handle = tokio::spawn(async move {
-            let mut ticker = interval(poll_interval);
-
             loop {
-                ticker.tick().await;
-
-                match Self::discover_torrents(&api_client, &inode_manager).await {
-                    Ok(current_torrent_ids) => {
-                        let now_ms = std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .as_millis() as u64;
-                        last_discovery.store(now_ms, Ordering::SeqCst);
-
-                        // Update known_torrents with current torrent IDs
-                        for torrent_id in &current_torrent_ids {
-                            known_torrents.insert(*torrent_id);
-                        }
-
-                        // Detect and remove torrents that were deleted from rqbit
-                        let current: std::collections::HashSet<u64> =
-                            current_torrent_ids.iter().copied().collect();
-                        let known: std::collections::HashSet<u64> =
-                            known_torrents.iter().map(|e| *e).collect();
-                        let removed: Vec<u64> = known.difference(&current).copied().collect();
-
-                        for torrent_id in removed {
-                            info!("Removing torrent {} from filesystem", torrent_id);
-
-                            // Get the torrent's root inode
-                            if let Some(inode) = inode_manager.lookup_torrent(torrent_id) {
-                                // Close all file handles for this torrent
-                                let _removed_handles = file_handles.remove_by_torrent(torrent_id);
-                                // Remove the inode tree for this torrent
-                                if !inode_manager.remove_inode(inode) {
-                                    warn!(
-                                        "Failed to remove inode {} for torrent {}",
-                                        inode, torrent_id
-                                    );
-                                }
-
-                                // Remove from known torrents
-                                known_torrents.remove(&torrent_id);
-
-                                // Record metric
-
-                                info!(
-                                    "Successfully removed torrent {} from filesystem",
-                                    torrent_id
-                                );
-                            } else {
-                                warn!(
-                                    "Torrent {} not found in filesystem, skipping removal",
-                                    torrent_id
-                                );
-                                known_torrents.remove(&torrent_id);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Background torrent discovery failed: {}", e);
-                    }
-                }
+                fs.refresh_torrents(true).await;
+                let evicted = fs.evict_idle_handles();
+                fs.close_orphaned_streams(&evicted).await;
+                fs.wait_for_next_poll().await;
             }
         });
 
@@ -184,7 +563,40 @@ impl TorrentFS {
             *h = Some(handle);
         }
 
-        info!("Started background torrent discovery with 30 second interval");
+        info!(
+            "Started background torrent discovery with {} second interval",
+            self.config.status_poll_interval
+        );
+    }
+
+    /// Waits `status_poll_interval`, or `idle_poll_interval` once the mount
+    /// has been unused that long, cut short by the next FUSE operation.
+    /// Once nothing has been read for `idle_sleep_after`, releases streams
+    /// and caches and waits for the next operation without polling.
+    async fn wait_for_next_poll(&self) {
+        let sleep_after = Duration::from_secs(self.config.idle_sleep_after);
+        if !sleep_after.is_zero() && self.activity.idle_reads_for() >= sleep_after {
+            let streams_closed = self.api_client.release_idle_resources().await;
+            self.readdir_cache.clear();
+            debug!(
+                poll_op = "sleeping",
+                streams_closed = streams_closed,
+                "No reads for {}s, polling paused until the mount is used",
+                sleep_after.as_secs()
+            );
+            self.activity.wait_for_use().await;
+            debug!(poll_op = "resumed", "Mount in use again, polling resumed");
+            return;
+        }
+        let poll_interval = Duration::from_secs(self.config.status_poll_interval);
+        let idle_interval = Duration::from_secs(self.config.idle_poll_interval);
+        if idle_interval <= poll_interval || self.activity.idle_for() < idle_interval {
+            tokio::time::sleep(poll_interval).await;
+            return;
+        }
+        if self.activity.wait(idle_interval).await {
+            debug!(poll_op = "resumed", "Mount in use again, polling resumed");
+        }
     }
 
     fn stop_torrent_discovery(&self) {
@@ -197,11 +609,17 @@ impl TorrentFS {
     }
 
     /// Discover new torrents from rqbit and create filesystem structures.
-    async fn discover_torrents(
-        api_client: &Arc<RqbitClient>,
-        inode_manager: &Arc<InodeManager>,
-    ) -> Result<Vec<u64>> {
-        let result = api_client.list_torrents().await?;
+    /// Returns the IDs of all torrents that belong in this mount.
+    /// With `list_max_age`, the torrent list is fetched again unless the
+    /// cached one is younger.
+    async fn discover_torrents(&self, list_max_age: Option<Duration>) -> Result<Vec<u64>> {
+        let result = match list_max_age {
+            Some(max_age) => self.api_client.list_torrents_newer_than(max_age).await?,
+            None => self.api_client.list_torrents().await?,
+        };
+        if result.errors.is_empty() {
+            self.save_snapshot(&result.torrents);
+        }
 
         // Log any partial failures
         if !result.errors.is_empty() {
@@ -212,2326 +630,6960 @@ impl TorrentFS {
             );
             for (id, name, err) in &result.errors {
                 warn!("Failed to load torrent {} ({}): {}", id, name, err);
+                if self.inode_manager.lookup_torrent(*id).is_none() {
+                    self.record_materialize_failure(*id, name, &err.to_string());
+                }
+            }
+        }
+        let listed: Vec<u64> = result
+            .torrents
+            .iter()
+            .map(|t| t.id)
+            .chain(result.errors.iter().map(|(id, _, _)| *id))
+            .collect();
+        self.materialize_retries.retain(&listed);
+
+        if self.filter.requires_tags()
+            && !result.torrents.is_empty()
+            && result.torrents.iter().all(|t| t.tags.is_none())
+        {
+            warn!(
+                "filter.tags is set but the rqbit server does not report tags; no torrents match"
+            );
+        }
+
+        let torrents: Vec<_> = result
+            .torrents
+            .into_iter()
+            .filter(|t| self.selects_torrent(t))
+            .collect();
+
+        // A duplicate with better availability replaces the mounted copy
+        let (torrents, duplicates) = self.dedup_by_info_hash(torrents).await;
+        for torrent_id in duplicates {
+            if self.inode_manager.lookup_torrent(torrent_id).is_some() {
+                self.remove_torrent_from_fs(torrent_id).await;
             }
         }
 
         // Collect all current torrent IDs
-        let current_torrent_ids: Vec<u64> = result.torrents.iter().map(|t| t.id).collect();
+        let current_torrent_ids: Vec<u64> = torrents.iter().map(|t| t.id).collect();
 
-        for torrent_info in result.torrents {
+        for torrent_info in &torrents {
             // Check if we already have this torrent
-            if inode_manager.lookup_torrent(torrent_info.id).is_none() {
+            if self.inode_manager.lookup_torrent(torrent_info.id).is_none() {
                 // New torrent found - create filesystem structure
-                if let Err(e) = Self::create_torrent_structure_static(inode_manager, &torrent_info)
-                {
-                    warn!(
-                        "Failed to create structure for torrent {}: {}",
-                        torrent_info.id, e
-                    );
-                } else {
+                if self.materialize_torrent(torrent_info).await {
                     info!(
                         "Discovered new torrent {}: {}",
                         torrent_info.id, torrent_info.name
                     );
+                    self.touch_torrent_parent(torrent_info.id);
                 }
+            } else if !torrent_info.files.is_empty()
+                && self.awaiting_metadata.contains(&torrent_info.id)
+            {
+                // Replace the placeholder now that the file list is known
+                info!(
+                    "Metadata resolved for torrent {}: {}",
+                    torrent_info.id, torrent_info.name
+                );
+                self.remove_torrent_from_fs(torrent_info.id).await;
+                self.materialize_torrent(torrent_info).await;
+            } else {
+                self.follow_torrent_rename(torrent_info);
             }
         }
 
-        Ok(current_torrent_ids)
-    }
-
-    /// Detect torrents that have been removed from rqbit.
-    /// Compares current torrent list with known torrents to find removed ones.
-    ///
-    /// # Arguments
-    /// * `current_torrent_ids` - List of torrent IDs currently in rqbit
-    ///
-    /// # Returns
-    /// * `Vec<u64>` - List of torrent IDs that have been removed
-    fn detect_removed_torrents(&self, current_torrent_ids: &[u64]) -> Vec<u64> {
-        let current: std::collections::HashSet<u64> = current_torrent_ids.iter().copied().collect();
-        let known: std::collections::HashSet<u64> =
-            self.known_torrents.iter().map(|e| *e).collect();
+        self.refresh_torrent_stats(&torrents).await;
 
-        // Torrents that were known but not in current list
-        known.difference(&current).copied().collect()
+        Ok(current_torrent_ids)
     }
 
-    /// Remove a torrent and all its associated data from the filesystem.
-    /// Closes streams, removes file handles, and cleans up inodes.
-    ///
-    /// # Arguments
-    /// * `torrent_id` - ID of the torrent to remove
-    async fn remove_torrent_from_fs(&self, torrent_id: u64) {
-        info!("Removing torrent {} from filesystem", torrent_id);
-
-        // Get the torrent's root inode
-        if let Some(inode) = self.inode_manager.lookup_torrent(torrent_id) {
-            // Close all file handles for this torrent
-            let _removed_handles = self.file_handles.remove_by_torrent(torrent_id);
-
-            // Remove the inode tree for this torrent
-            if !self.inode_manager.remove_inode(inode) {
-                warn!(
-                    "Failed to remove inode {} for torrent {}",
-                    inode, torrent_id
-                );
+    /// Creates a torrent's structure unless it is waiting out the backoff of
+    /// earlier failures. A failed attempt is undone, so the next one starts
+    /// afresh. Returns whether the torrent was created.
+    async fn materialize_torrent(&self, torrent_info: &crate::api::types::TorrentInfo) -> bool {
+        let torrent_id = torrent_info.id;
+        if !self.materialize_retries.is_due(torrent_id, Instant::now()) {
+            return false;
+        }
+        match self.create_torrent_structure(torrent_info) {
+            Ok(()) => {
+                let failures = self.materialize_retries.record_success(torrent_id);
+                if failures > 0 {
+                    info!(
+                        torrent_id,
+                        failures,
+                        "Created structure for torrent {} after {} failed attempts",
+                        torrent_info.name,
+                        failures
+                    );
+                }
+                true
             }
+            Err(e) => {
+                if self.inode_manager.lookup_torrent(torrent_id).is_some() {
+                    self.remove_torrent_from_fs(torrent_id).await;
+                } else {
+                    self.forget_torrent_state(torrent_id);
+                }
+                self.record_materialize_failure(torrent_id, &torrent_info.name, &e.to_string());
+                false
+            }
+        }
+    }
 
-            // Remove from known torrents
-            self.known_torrents.remove(&torrent_id);
-
-            // Record metric
+    /// Schedules a torrent that failed to materialize for another attempt,
+    /// unless it is already waiting for one.
+    fn record_materialize_failure(&self, torrent_id: u64, name: &str, error: &str) {
+        let now = Instant::now();
+        if !self.materialize_retries.is_due(torrent_id, now) {
+            return;
+        }
+        match self.materialize_retries.record_failure(torrent_id, now) {
+            RetryOutcome::RetryIn { attempt, delay } => warn!(
+                torrent_id,
+                attempt,
+                retry_in_secs = delay.as_secs(),
+                error,
+                "Failed to create structure for torrent {}, retrying later",
+                name
+            ),
+            RetryOutcome::GaveUp { attempts } => error!(
+                torrent_id,
+                attempts,
+                error,
+                materialize = "gave_up",
+                "Giving up on torrent {} after {} failed attempts; it stays hidden until rqbit-fuse restarts",
+                name,
+                attempts
+            ),
+        }
+    }
 
-            info!(
-                "Successfully removed torrent {} from filesystem",
-                torrent_id
-            );
-        } else {
+    /// Renames a torrent's top-level entry when rqbit reports a new name for
+    /// it. Inode numbers are kept, so open files and working directories stay
+    /// valid; the kernel is told to drop its cached lookup of the old name.
+    fn follow_torrent_rename(&self, torrent_info: &crate::api::types::TorrentInfo) {
+        let Some(inode) = self.inode_manager.lookup_torrent(torrent_info.id) else {
+            return;
+        };
+        if inode == 1 {
+            // Single-torrent mode: the root has no name
+            return;
+        }
+        let Some((old_name, parent)) = self
+            .inode_manager
+            .with_entry(inode, |e| (e.name().to_string(), e.parent()))
+        else {
+            return;
+        };
+        let new_name = self.top_level_name(torrent_info);
+        if old_name == new_name {
+            return;
+        }
+        if !self.inode_manager.rename(inode, new_name.clone()) {
             warn!(
-                "Torrent {} not found in filesystem, skipping removal",
-                torrent_id
+                "Cannot rename torrent {} from {} to {}: name already in use",
+                torrent_info.id, old_name, new_name
             );
-            // Still remove from known_torrents to keep state consistent
-            self.known_torrents.remove(&torrent_id);
+            return;
         }
+        info!(
+            "Torrent {} renamed from {} to {}",
+            torrent_info.id, old_name, new_name
+        );
+        self.invalidate_entry(parent, &old_name);
     }
 
-    /// Gracefully shut down the filesystem.
-    ///
-    /// This stops all background tasks:
-    /// - Status monitoring
-    /// - Torrent discovery
-    ///
-    /// It also shuts down the async worker to complete pending operations.
-    pub fn shutdown(&self) {
-        info!("Initiating graceful shutdown...");
-
-        self.stop_torrent_discovery();
+    /// Name of a torrent's directory, with `error_suffix` appended while it
+    /// is failed.
+    fn torrent_dir_name(&self, torrent_info: &crate::api::types::TorrentInfo) -> String {
+        // Unresolved magnets may not have a name yet
+        let mut torrent_name = if torrent_info.name.is_empty() {
+            sanitize_filename(&torrent_info.info_hash)
+        } else {
+            sanitize_filename(&torrent_info.name)
+        };
+        if let Some(suffix) = &self.config.error_suffix {
+            if self.failed_torrents.contains_key(&torrent_info.id) {
+                torrent_name.push_str(suffix);
+            }
+        }
+        torrent_name
+    }
 
-        info!("Graceful shutdown complete");
+    /// Name of a torrent's top-level entry: its directory, or the file itself
+    /// for single-file torrents.
+    fn top_level_name(&self, torrent_info: &crate::api::types::TorrentInfo) -> String {
+        match torrent_info.files.as_slice() {
+            [file_info] if !file_info.components.is_empty() => {
+                sanitize_filename(file_info.components.last().unwrap())
+            }
+            _ => self.torrent_dir_name(torrent_info),
+        }
     }
 
-    /// Refresh torrent list from rqbit with cooldown protection.
-    /// Returns true if a refresh was performed, false if skipped due to cooldown.
-    ///
-    /// # Arguments
-    /// * `force` - If true, bypass the cooldown check
-    ///
-    /// # Returns
-    /// * `bool` - True if refresh was performed, false if skipped
-    pub async fn refresh_torrents(&self, force: bool) -> bool {
-        const COOLDOWN_MS: u64 = 5000;
+    /// Records which torrents rqbit reports in its error state and, with
+    /// `hide_incomplete`, which files are unfinished. With `error_suffix`
+    /// set, a torrent's directory is rebuilt under its new name whenever it
+    /// fails or recovers.
+    async fn refresh_torrent_stats(&self, torrents: &[crate::api::types::TorrentInfo]) {
+        /// Stats requests in flight at once.
+        const STATS_FETCH_CONCURRENCY: usize = 16;
+
+        let ids: Vec<u64> = torrents.iter().map(|t| t.id).collect();
+        let mut all_stats = futures::stream::iter(ids)
+            .map(|torrent_id| {
+                let api_client = Arc::clone(&self.api_client);
+                async move { api_client.get_torrent_stats(torrent_id).await }
+            })
+            .buffered(STATS_FETCH_CONCURRENCY);
+        for torrent_info in torrents {
+            let Some(stats) = all_stats.next().await else {
+                break;
+            };
+            let torrent_id = torrent_info.id;
+            let stats = match stats {
+                Ok(stats) => stats,
+                Err(e) => {
+                    trace!("No stats for torrent {}: {}", torrent_id, e);
+                    continue;
+                }
+            };
 
-        // Check cooldown unless forced
-        if !force {
-            let now_ms = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as u64;
-            let last_ms = self.last_discovery.load(Ordering::SeqCst);
-
-            if last_ms != 0 && now_ms.saturating_sub(last_ms) < COOLDOWN_MS {
-                let remaining_secs = (COOLDOWN_MS - (now_ms - last_ms)) / 1000;
-                trace!(
-                    "Skipping torrent discovery - cooldown in effect ({}s remaining)",
-                    remaining_secs
-                );
-                return false;
+            let incomplete: HashSet<u64> = torrent_info
+                .files
+                .iter()
+                .enumerate()
+                .filter(|(idx, file)| {
+                    stats.file_progress.get(*idx).copied().unwrap_or(0) < file.length
+                })
+                .map(|(idx, _)| idx as u64)
+                .collect();
+            let previous = if incomplete.is_empty() {
+                self.incomplete_files
+                    .remove(&torrent_id)
+                    .map(|(_, files)| files)
+            } else {
+                self.incomplete_files.insert(torrent_id, incomplete.clone())
+            };
+            if let Some(previous) = previous {
+                let finished: HashSet<u64> = previous.difference(&incomplete).copied().collect();
+                self.touch_finished_files(torrent_id, &finished, incomplete.is_empty());
+            }
+            self.refresh_file_attrs(torrent_info, &stats);
+
+            let error = (stats.state == "error")
+                .then(|| stats.error.unwrap_or_else(|| "unknown error".to_string()));
+            let changed = match &error {
+                Some(message) => self
+                    .failed_torrents
+                    .insert(torrent_id, message.clone())
+                    .is_none(),
+                None => self.failed_torrents.remove(&torrent_id).is_some(),
+            };
+            if !changed {
+                continue;
             }
-        }
 
-        // Perform discovery
-        match Self::discover_torrents(&self.api_client, &self.inode_manager).await {
-            Ok(current_torrent_ids) => {
-                let now_ms = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as u64;
-                self.last_discovery.store(now_ms, Ordering::SeqCst);
+            match &error {
+                Some(message) => warn!(
+                    "Torrent {} ({}) failed: {}",
+                    torrent_id, torrent_info.name, message
+                ),
+                None => info!("Torrent {} ({}) recovered", torrent_id, torrent_info.name),
+            }
 
-                // Update known_torrents with current torrent IDs
-                for torrent_id in &current_torrent_ids {
-                    self.known_torrents.insert(*torrent_id);
+            let has_directory = torrent_info.files.len() != 1 && !self.single_torrent_mode();
+            if self.config.error_suffix.is_some() && has_directory {
+                self.remove_torrent_from_fs(torrent_id).await;
+                if let Some(message) = error {
+                    self.failed_torrents.insert(torrent_id, message);
                 }
-
-                // Detect and remove torrents that were deleted from rqbit
-                let removed = self.detect_removed_torrents(&current_torrent_ids);
-                for torrent_id in removed {
-                    self.remove_torrent_from_fs(torrent_id).await;
+                if let Err(e) = self.create_torrent_structure(torrent_info) {
+                    warn!(
+                        "Failed to create structure for torrent {}: {}",
+                        torrent_id, e
+                    );
                 }
-
-                true
-            }
-            Err(e) => {
-                warn!("Failed to refresh torrents: {}", e);
-                false
             }
         }
     }
 
-    /// Static version of create_torrent_structure for use in background tasks
-    fn create_torrent_structure_static(
-        inode_manager: &Arc<InodeManager>,
+    /// Updates the sizes and downloaded bytes of a torrent's files from a
+    /// status poll, and has the kernel drop the cached attributes of files
+    /// that changed.
+    fn refresh_file_attrs(
+        &self,
         torrent_info: &crate::api::types::TorrentInfo,
-    ) -> Result<()> {
-        use std::collections::HashMap;
-
-        let torrent_name = sanitize_filename(&torrent_info.name);
+        stats: &crate::api::types::TorrentStats,
+    ) {
         let torrent_id = torrent_info.id;
-
-        trace!(
-            "Creating structure for torrent {} ({} files)",
-            torrent_id,
-            torrent_info.files.len()
-        );
-
-        // Create torrent directory for all torrents (both single and multi-file)
-        // This ensures consistent torrent_id -> directory_inode mapping
-        let torrent_dir_inode =
-            inode_manager.allocate_torrent_directory(torrent_id, torrent_name.clone(), 1);
-
-        inode_manager.add_child(1, torrent_dir_inode);
-
-        // Handle single-file torrents - place file directly in torrent directory
-        if torrent_info.files.len() == 1 {
-            let file_info = &torrent_info.files[0];
-            let file_name = if file_info.components.is_empty() {
-                torrent_name.clone()
-            } else {
-                sanitize_filename(file_info.components.last().unwrap())
-            };
-
-            let file_inode = inode_manager.allocate_file(
-                file_name.clone(),
-                torrent_dir_inode,
+        let mut sizes_changed = false;
+        for (ino, file_index) in self.torrent_files(torrent_id) {
+            let idx = file_index as usize;
+            let mut changed = false;
+            if let Some(file) = torrent_info.files.get(idx) {
+                if self.inode_manager.set_size(ino, file.length) {
+                    sizes_changed = true;
+                    changed = true;
+                }
+            }
+            if let Some(&progress) = stats.file_progress.get(idx) {
+                changed |= self
+                    .file_progress
+                    .insert((torrent_id, file_index), progress)
+                    != Some(progress);
+            }
+            if changed {
+                self.invalidate_attr(ino);
+            }
+        }
+        if sizes_changed {
+            debug!("File sizes of torrent {} changed", torrent_id);
+            self.torrent_sizes.insert(
                 torrent_id,
-                0,
-                file_info.length,
+                torrent_info.files.iter().map(|f| f.length).sum(),
             );
+        }
+    }
 
-            inode_manager.add_child(torrent_dir_inode, file_inode);
-
-            trace!(
-                "Created single-file torrent entry {} -> {} (size: {})",
-                file_name,
-                file_inode,
-                file_info.length
-            );
-        } else {
-            // Multi-file torrent - directory already created above
-            let mut created_dirs: HashMap<String, u64> = HashMap::new();
-            created_dirs.insert("".to_string(), torrent_dir_inode);
+    /// Inodes and file indexes of a torrent's files already in the tree.
+    fn torrent_files(&self, torrent_id: u64) -> Vec<(u64, u64)> {
+        let mut files = Vec::new();
+        let mut pending: Vec<u64> = self
+            .inode_manager
+            .lookup_torrent(torrent_id)
+            .into_iter()
+            .collect();
+        while let Some(ino) = pending.pop() {
+            match self.inode_manager.get(ino) {
+                Some(InodeEntry::File { file_index, .. }) => files.push((ino, file_index)),
+                Some(InodeEntry::Directory { .. }) => {
+                    pending.extend(self.inode_manager.child_inodes(ino))
+                }
+                _ => {}
+            }
+        }
+        files
+    }
 
-            for (file_idx, file_info) in torrent_info.files.iter().enumerate() {
-                Self::create_file_entry_static(
-                    inode_manager,
-                    file_info,
-                    file_idx,
-                    torrent_id,
-                    torrent_dir_inode,
-                    &mut created_dirs,
-                )?;
+    /// Tells the kernel to drop a file's cached attributes, keeping its
+    /// cached data.
+    fn invalidate_attr(&self, ino: u64) {
+        if let Some(notifier) = self.notifier.lock().unwrap().as_ref() {
+            if let Err(e) = notifier.inval_inode(ino, -1, 0) {
+                debug!("Failed to invalidate attributes of {}: {}", ino, e);
             }
         }
+    }
 
-        Ok(())
+    /// Returns true if the torrent belongs in this mount.
+    ///
+    /// With `torrent` set (single-torrent mode) only the torrent whose ID or
+    /// info hash matches is shown; otherwise the `[filter]` settings decide.
+    pub fn selects_torrent(&self, torrent_info: &crate::api::types::TorrentInfo) -> bool {
+        match &self.config.torrent {
+            Some(selector) => {
+                torrent_info.id.to_string() == *selector
+                    || torrent_info.info_hash.eq_ignore_ascii_case(selector)
+            }
+            None => self.filter.matches(torrent_info),
+        }
     }
 
-    /// Static version of create_file_entry for use in background tasks
-    fn create_file_entry_static(
-        inode_manager: &Arc<InodeManager>,
-        file_info: &crate::api::types::FileInfo,
-        file_idx: usize,
-        torrent_id: u64,
-        torrent_dir_inode: u64,
-        created_dirs: &mut std::collections::HashMap<String, u64>,
-    ) -> Result<()> {
-        let components = &file_info.components;
+    /// Keeps one torrent per info hash, so a torrent added twice shows up
+    /// once. Among duplicates the one with the most verified data wins; on
+    /// a tie the one already mounted stays. Returns the kept torrents in
+    /// their original order, and the ids of the others.
+    async fn dedup_by_info_hash(
+        &self,
+        torrents: Vec<crate::api::types::TorrentInfo>,
+    ) -> (Vec<crate::api::types::TorrentInfo>, Vec<u64>) {
+        let mut by_hash: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, torrent_info) in torrents.iter().enumerate() {
+            by_hash
+                .entry(torrent_info.info_hash.to_ascii_lowercase())
+                .or_default()
+                .push(idx);
+        }
 
-        if components.is_empty() {
-            return Ok(());
+        let mut dropped = HashSet::new();
+        for indices in by_hash.values().filter(|indices| indices.len() > 1) {
+            let mut best = None;
+            for &idx in indices {
+                let torrent_id = torrents[idx].id;
+                let (finished, progress) = match self.api_client.get_torrent_stats(torrent_id).await
+                {
+                    Ok(stats) => (stats.finished, stats.progress_bytes),
+                    Err(_) => (false, 0),
+                };
+                let mounted = self.inode_manager.lookup_torrent(torrent_id).is_some();
+                let rank = (finished, progress, mounted, std::cmp::Reverse(torrent_id));
+                if best.as_ref().is_none_or(|(best_rank, _)| rank > *best_rank) {
+                    best = Some((rank, idx));
+                }
+            }
+            let Some((_, keep)) = best else { continue };
+            for &idx in indices.iter().filter(|&&idx| idx != keep) {
+                debug!(
+                    "Torrent {} duplicates torrent {} ({}); showing {}",
+                    torrents[idx].id, torrents[keep].id, torrents[idx].info_hash, torrents[keep].id
+                );
+                dropped.insert(idx);
+            }
         }
 
-        let mut current_dir_inode = torrent_dir_inode;
-        let mut current_path = String::new();
+        let mut duplicates = Vec::new();
+        let kept = torrents
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, torrent_info)| {
+                if dropped.contains(&idx) {
+                    duplicates.push(torrent_info.id);
+                    None
+                } else {
+                    Some(torrent_info)
+                }
+            })
+            .collect();
+        (kept, duplicates)
+    }
 
-        // Get the torrent directory's canonical path to build full paths
-        let torrent_dir_path = inode_manager
-            .get(torrent_dir_inode)
-            .map(|e| e.canonical_path().to_string())
-            .unwrap_or_else(|| "/".to_string());
+    /// Returns true when a single torrent is mounted directly at the root.
+    fn single_torrent_mode(&self) -> bool {
+        self.config.torrent.is_some()
+    }
 
-        for dir_component in components.iter().take(components.len().saturating_sub(1)) {
-            if !current_path.is_empty() {
-                current_path.push('/');
-            }
-            current_path.push_str(dir_component);
+    /// Detect torrents that have been removed from rqbit.
+    /// Compares current torrent list with known torrents to find removed ones.
+    ///
+    /// # Arguments
+    /// * `current_torrent_ids` - List of torrent IDs currently in rqbit
+    ///
+    /// # Returns
+    /// * `Vec<u64>` - List of torrent IDs that have been removed
+    fn detect_removed_torrents(&self, current_torrent_ids: &[u64]) -> Vec<u64> {
+        let current: std::collections::HashSet<u64> = current_torrent_ids.iter().copied().collect();
+        let known: std::collections::HashSet<u64> =
+            self.known_torrents.iter().map(|e| *e).collect();
 
-            if let Some(&inode) = created_dirs.get(&current_path) {
-                current_dir_inode = inode;
-            } else {
-                let dir_name = sanitize_filename(dir_component);
-                // Build full canonical path including torrent directory
-                let full_path = format!("{}/{}", torrent_dir_path, current_path);
-                let new_dir_inode = inode_manager.allocate(InodeEntry::Directory {
-                    ino: 0,
-                    name: dir_name.clone(),
-                    parent: current_dir_inode,
-                    children: DashSet::new(),
-                    canonical_path: full_path,
-                });
+        // Torrents that were known but not in current list
+        known.difference(&current).copied().collect()
+    }
 
-                inode_manager.add_child(current_dir_inode, new_dir_inode);
-                created_dirs.insert(current_path.clone(), new_dir_inode);
-                current_dir_inode = new_dir_inode;
+    /// Remove a torrent and all its associated data from the filesystem.
+    /// Closes streams, removes file handles, and cleans up inodes.
+    ///
+    /// # Arguments
+    /// * `torrent_id` - ID of the torrent to remove
+    pub(crate) async fn remove_torrent_from_fs(&self, torrent_id: u64) {
+        info!("Removing torrent {} from filesystem", torrent_id);
 
-                trace!(
-                    "Created directory {} at inode {}",
-                    current_path,
-                    new_dir_inode
-                );
-            }
-        }
+        // Get the torrent's root inode
+        if let Some(inode) = self.inode_manager.lookup_torrent(torrent_id) {
+            self.touch_torrent_parent(torrent_id);
 
-        let file_name = components.last().unwrap();
-        let sanitized_name = sanitize_filename(file_name);
+            // Close all file handles for this torrent
+            let _removed_handles = self.file_handles.remove_by_torrent(torrent_id);
 
-        let file_inode = inode_manager.allocate_file(
-            sanitized_name,
-            current_dir_inode,
-            torrent_id,
-            file_idx as u64,
-            file_info.length,
-        );
+            // Remove the inode tree for this torrent
+            if inode == 1 {
+                // Single-torrent mode: the torrent is the whole tree
+                self.inode_manager.clear_torrents();
+            } else if !self.inode_manager.remove_inode(inode) {
+                warn!(
+                    "Failed to remove inode {} for torrent {}",
+                    inode, torrent_id
+                );
+            }
+            // Torrents inside tag directories aren't unmapped by remove_inode
+            self.inode_manager.torrent_to_inode().remove(&torrent_id);
+            self.forget_torrent_state(torrent_id);
+            self.dir_mtimes
+                .retain(|ino, _| self.inode_manager.contains(*ino));
+            self.readdir_cache
+                .retain(|ino, _| self.inode_manager.contains(*ino));
 
-        inode_manager.add_child(current_dir_inode, file_inode);
+            // Remove from known torrents
+            self.known_torrents.remove(&torrent_id);
 
-        trace!(
-            "Created single-file entry {} (size: {})",
-            file_name,
-            file_info.length
-        );
+            // Record metric
 
-        Ok(())
+            info!(
+                "Successfully removed torrent {} from filesystem",
+                torrent_id
+            );
+        } else {
+            warn!(
+                "Torrent {} not found in filesystem, skipping removal",
+                torrent_id
+            );
+            // Still remove from known_torrents to keep state consistent
+            self.known_torrents.remove(&torrent_id);
+        }
     }
 
-    /// Returns a reference to the API client
-    pub fn api_client(&self) -> &Arc<RqbitClient> {
-        &self.api_client
+    /// Saves the unfiltered torrent list for later `offline` mounts.
+    fn save_snapshot(&self, torrents: &[crate::api::types::TorrentInfo]) {
+        if let Err(e) = self.snapshot.save(torrents) {
+            warn!(
+                "Failed to save metadata snapshot {}: {}",
+                self.snapshot.path().display(),
+                e
+            );
+        }
     }
 
-    /// Returns a reference to the inode manager
-    pub fn inode_manager(&self) -> &Arc<InodeManager> {
-        &self.inode_manager
+    /// Drops the per-torrent state kept alongside the inode tree.
+    fn forget_torrent_state(&self, torrent_id: u64) {
+        self.pending_dirs
+            .retain(|_, dir| dir.torrent_id != torrent_id);
+        self.deferred_files.retain(|_, id| *id != torrent_id);
+        self.awaiting_metadata.remove(&torrent_id);
+        self.failed_torrents.remove(&torrent_id);
+        self.torrent_sizes.remove(&torrent_id);
+        self.piece_layouts.remove(&torrent_id);
+        self.incomplete_files.remove(&torrent_id);
+        self.file_progress.retain(|(id, _), _| *id != torrent_id);
+        self.cached_while_downloading
+            .retain(|(id, _)| *id != torrent_id);
+        self.prefetched_files.retain(|(id, _)| *id != torrent_id);
+        self.file_advice.retain(|(id, _), _| *id != torrent_id);
+        self.info_hashes.remove(&torrent_id);
+        self.trash_homes.remove(&torrent_id);
+        self.torrent_reads.remove(&torrent_id);
+        self.remove_virtual_files(torrent_id);
     }
 
-    /// Returns true if the filesystem has been initialized
-    pub fn is_initialized(&self) -> bool {
-        self.initialized
+    /// How long the kernel may cache entries and attributes.
+    fn entry_ttl(&self) -> Duration {
+        Duration::from_secs(self.config.entry_ttl)
     }
 
-    /// Validates the mount point directory.
-    /// Checks that:
-    /// - The path exists
-    /// - It's a directory
-    /// - We have read/write permissions
-    fn validate_mount_point(&self) -> Result<()> {
-        let mount_point = &self.config.mount_point;
-
-        if !mount_point.exists() {
-            return Err(anyhow::anyhow!(
-                "Mount point does not exist: {}",
-                mount_point.display()
-            ));
+    /// Replies to a failed lookup, as a cacheable negative entry when
+    /// `negative_ttl` is set.
+    fn reply_not_found(&self, reply: fuser::ReplyEntry) {
+        if self.config.negative_ttl == 0 {
+            reply.error(libc::ENOENT);
+            return;
         }
+        // A zero node ID tells the kernel the name does not exist
+        let attr = fuser::FileAttr {
+            ino: 0,
+            size: 0,
+            blocks: 0,
+            atime: std::time::UNIX_EPOCH,
+            mtime: std::time::UNIX_EPOCH,
+            ctime: std::time::UNIX_EPOCH,
+            crtime: std::time::UNIX_EPOCH,
+            kind: fuser::FileType::RegularFile,
+            perm: 0,
+            nlink: 0,
+            uid: 0,
+            gid: 0,
+            rdev: 0,
+            blksize: 0,
+            flags: 0,
+        };
+        reply.entry(&Duration::from_secs(self.config.negative_ttl), &attr, 0);
+    }
 
-        if !mount_point.is_dir() {
-            return Err(anyhow::anyhow!(
-                "Mount point is not a directory: {}",
-                mount_point.display()
-            ));
+    /// Returns true for files hidden because they are still downloading.
+    fn is_hidden(&self, entry: &InodeEntry) -> bool {
+        match entry {
+            InodeEntry::File {
+                torrent_id,
+                file_index,
+                ..
+            } => {
+                self.config.hide_incomplete
+                    && self
+                        .incomplete_files
+                        .get(torrent_id)
+                        .is_some_and(|files| files.contains(file_index))
+            }
+            _ => false,
         }
+    }
 
-        // Check read/write permissions by trying to access the directory
-        if std::fs::read_dir(mount_point).is_err() {
-            return Err(anyhow::anyhow!(
-                "No read permission for mount point: {}",
-                mount_point.display()
-            ));
+    /// Bumps a directory's mtime and tells the kernel to drop its cached
+    /// attributes, so inotify watchers and incremental library scans notice
+    /// the change.
+    fn touch_dir(&self, ino: u64) {
+        self.dir_mtimes.insert(ino, std::time::SystemTime::now());
+        self.readdir_cache.remove(&ino);
+        if let Some(notifier) = self.notifier.lock().unwrap().as_ref() {
+            if let Err(e) = notifier.inval_inode(ino, 0, 0) {
+                debug!("Failed to invalidate attributes of {}: {}", ino, e);
+            }
         }
-
-        info!("Mount point validated: {}", mount_point.display());
-        Ok(())
     }
 
-    /// Establishes connection to the rqbit server and validates it's accessible.
-    async fn connect_to_rqbit(&self) -> Result<()> {
-        info!("Connecting to rqbit server at: {}", self.config.api_url);
-
-        match self.api_client.health_check().await {
-            Ok(true) => {
-                info!("Successfully connected to rqbit server");
-                Ok(())
-            }
-            Ok(false) => Err(anyhow::anyhow!(
-                "rqbit server at {} is not responding or returned an error",
-                self.config.api_url
-            )),
-            Err(e) => Err(anyhow::anyhow!(
-                "Failed to connect to rqbit server at {}: {}",
-                self.config.api_url,
-                e
-            )),
+    /// Bumps the directory holding a torrent's top-level entry, and drops
+    /// any negative lookup of its name the kernel cached before it existed.
+    fn touch_torrent_parent(&self, torrent_id: u64) {
+        if let Some((parent, name)) =
+            self.inode_manager
+                .lookup_torrent(torrent_id)
+                .and_then(|ino| {
+                    self.inode_manager
+                        .with_entry(ino, |e| (e.parent(), e.name().to_string()))
+                })
+        {
+            self.touch_dir(parent);
+            self.invalidate_entry(parent, &name);
         }
     }
 
-    /// Mounts the filesystem at the configured mount point.
-    /// This is the main entry point for mounting the filesystem.
-    pub fn mount(self) -> Result<()>
-    where
-        Self: Sized,
-    {
-        let mount_point = self.config.mount_point.clone();
-        let options = self.build_mount_options();
-
-        info!("Mounting rqbit-fuse at: {}", mount_point.display());
-
-        // Mount the filesystem
-        fuser::mount2(self, &mount_point, &options)
-            .with_context(|| format!("Failed to mount filesystem at: {}", mount_point.display()))
+    /// Bumps the directories of files that just finished downloading, and
+    /// the torrent's parent directory once the whole torrent has finished.
+    /// With `hide_incomplete` the files appear, so cached negative lookups
+    /// of their names are dropped too.
+    fn touch_finished_files(&self, torrent_id: u64, finished: &HashSet<u64>, torrent_done: bool) {
+        if finished.is_empty() {
+            return;
+        }
+        let mut parents = HashSet::new();
+        for item in self.inode_manager.iter_entries() {
+            if let InodeEntry::File {
+                torrent_id: id,
+                file_index,
+                parent,
+                name,
+                ..
+            } = &item.entry
+            {
+                if *id == torrent_id && finished.contains(file_index) {
+                    if self.config.hide_incomplete {
+                        self.invalidate_entry(*parent, name);
+                    }
+                    parents.insert(*parent);
+                }
+            }
+        }
+        for parent in parents {
+            self.touch_dir(parent);
+        }
+        if torrent_done {
+            self.touch_torrent_parent(torrent_id);
+        }
     }
 
-    /// Builds FUSE mount options based on configuration.
-    fn build_mount_options(&self) -> Vec<fuser::MountOption> {
-        let mut options = vec![
-            fuser::MountOption::RO,     // Read-only (torrents are read-only)
-            fuser::MountOption::NoSuid, // No setuid/setgid
-            fuser::MountOption::NoDev,  // No special device files
-            fuser::MountOption::NoAtime, // Don't update access times
-                                        // NOTE: Sync option removed - causes hangs on macOS due to blocking
-                                        // on unmount. Since this is a read-only filesystem, data integrity
-                                        // is not a concern. This fix was needed after macOS system updates
-                                        // broke FUSE mounting with Sync option enabled.
-        ];
-
-        options.push(fuser::MountOption::AutoUnmount);
+    /// The listing of a directory with at least `readdir_cache_min_entries`
+    /// children, from the cache while it is fresh and the tree hasn't
+    /// changed. Smaller directories are listed in place and return None.
+    fn cached_listing(&self, ino: u64) -> Option<Arc<[(u64, fuser::FileType, String)]>> {
+        let ttl = Duration::from_secs(self.config.readdir_cache_ttl);
+        if ttl.is_zero() {
+            return None;
+        }
+        let tree_version = self.inode_manager.tree_version();
+        if let Some(cached) = self.readdir_cache.get(&ino) {
+            if cached.tree_version == tree_version && cached.built.elapsed() < ttl {
+                return Some(Arc::clone(&cached.entries));
+            }
+        }
 
-        options
+        let children = self.inode_manager.child_inodes(ino);
+        if children.len() < self.config.readdir_cache_min_entries {
+            self.readdir_cache.remove(&ino);
+            return None;
+        }
+        let entries: Arc<[_]> = children
+            .into_iter()
+            .filter_map(|child_ino| {
+                self.inode_manager
+                    .with_entry(child_ino, |child| {
+                        (!self.is_hidden(child))
+                            .then(|| (child_ino, file_type(child), child.name().to_string()))
+                    })
+                    .flatten()
+            })
+            .collect();
+        self.readdir_cache.insert(
+            ino,
+            CachedListing {
+                built: Instant::now(),
+                tree_version,
+                entries: Arc::clone(&entries),
+            },
+        );
+        Some(entries)
     }
 
-    /// Build file attributes for a given inode entry.
-    /// Converts internal InodeEntry to FUSE FileAttr.
-    ///
-    /// # Arguments
-    /// * `entry` - The inode entry to build attributes for
-    ///
-    /// # Returns
-    /// * `fuser::FileAttr` - The FUSE file attributes
-    pub fn build_file_attr(&self, entry: &crate::fs::inode::InodeEntry) -> fuser::FileAttr {
-        use crate::fs::inode::InodeEntry;
-        use std::time::{Duration, SystemTime, UNIX_EPOCH};
-
-        let now = SystemTime::now();
-        let creation_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000); // Fixed creation time
-        let uid = unsafe { libc::geteuid() };
-        let gid = unsafe { libc::getegid() };
-
-        match entry {
-            InodeEntry::Directory { ino, .. } => fuser::FileAttr {
-                ino: *ino,
-                size: 0,
-                blocks: 0,
-                atime: now,
-                mtime: now,
-                ctime: now,
-                crtime: creation_time,
-                kind: fuser::FileType::Directory,
-                perm: 0o555, // Read and execute for all, no write (read-only)
-                nlink: 2 + self.inode_manager.get_children(*ino).len() as u32,
-                uid,
-                gid,
-                rdev: 0,
-                flags: 0,
-                blksize: 4096,
-            },
-            InodeEntry::File { ino, size, .. } => fuser::FileAttr {
-                ino: *ino,
-                size: *size,
-                blocks: (*size).div_ceil(4096), // Ceiling division for block count
-                atime: now,
-                mtime: now,
-                ctime: now,
-                crtime: creation_time,
-                kind: fuser::FileType::RegularFile,
-                perm: 0o444, // Read-only for all
-                nlink: 1,
-                uid,
-                gid,
-                rdev: 0,
-                flags: 0,
-                blksize: 4096,
-            },
-            InodeEntry::Symlink { ino, target, .. } => fuser::FileAttr {
-                ino: *ino,
-                size: target.len() as u64,
-                blocks: 1,
-                atime: now,
-                mtime: now,
-                ctime: now,
-                crtime: creation_time,
-                kind: fuser::FileType::Symlink,
-                perm: 0o777, // Symlinks always have 777 permissions
-                nlink: 1,
-                uid,
-                gid,
-                rdev: 0,
-                flags: 0,
-                blksize: 4096,
-            },
+    /// Sets how far ahead the file's stream downloads from the readahead
+    /// profile matching its extension.
+    fn apply_readahead_profile(&self, entry: &InodeEntry) {
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ref name,
+            ..
+        } = *entry
+        else {
+            return;
+        };
+        // Advice sent for the file wins over its extension's profile
+        let prefetch_size = match self.file_advice.get(&(torrent_id, file_index)).as_deref() {
+            Some(Advice::Sequential) => Some(self.config.readahead_max_size as usize),
+            Some(Advice::Random) => Some(0),
+            _ => self
+                .config
+                .readahead_profile(name)
+                .map(|profile| profile.prefetch_size(self.config.readahead_size, size) as usize),
+        };
+        if let Some(prefetch_size) = prefetch_size {
+            debug!(
+                torrent_id,
+                file_index, prefetch_size, "Applying readahead profile to {}", name
+            );
         }
+        self.api_client
+            .set_prefetch_size(torrent_id, file_index as usize, prefetch_size);
     }
-}
 
-impl Filesystem for TorrentFS {
-    /// Read file contents.
-    /// Called when the kernel needs to read data from a file.
-    /// Translates FUSE read requests to HTTP Range requests to rqbit.
-    #[instrument(skip(self, reply), fields(fh))]
-    fn read(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _ino: u64,
+    /// Serves a read of a generated file from the contents generated for its
+    /// handle. Reads from the start generate them again, so rereading an
+    /// open file shows fresh values.
+    fn read_virtual(
+        &self,
         fh: u64,
-        offset: i64,
+        torrent_id: u64,
+        file: VirtualFile,
+        offset: u64,
         size: u32,
-        _flags: i32,
-        _lock_owner: Option<u64>,
         reply: fuser::ReplyData,
     ) {
-        let start_time = Instant::now();
+        let contents = match self.virtual_contents.get(&fh) {
+            Some(contents) if offset > 0 => contents.clone(),
+            _ => {
+                let timeout = Duration::from_secs(self.config.read_timeout);
+                match self.async_worker.render_virtual(torrent_id, file, timeout) {
+                    Ok(contents) => {
+                        self.virtual_contents.insert(fh, contents.clone());
+                        contents
+                    }
+                    Err(e) => {
+                        self.metrics.record_error();
+                        debug!(
+                            fuse_op = "read",
+                            result = "error",
+                            fh = fh,
+                            torrent_id = torrent_id,
+                            error = %e
+                        );
+                        reply.error(e.to_errno());
+                        return;
+                    }
+                }
+            }
+        };
+        let start = (offset as usize).min(contents.len());
+        let end = start + (size as usize).min(contents.len() - start);
+        reply.data(&contents[start..end]);
+    }
 
-        // Clamp read size to FUSE maximum to prevent "Too much data" panic
-        let size = std::cmp::min(size, Self::FUSE_MAX_READ);
+    /// Page cache flags of a file being opened: by whether rqbit had
+    /// finished downloading it at the last status poll, as
+    /// [`Config::page_cache_mode()`] says for that state.
+    fn open_flags(&self, entry: &InodeEntry) -> u32 {
+        // Generated files report no size
+        if entry.is_virtual() {
+            return fuser::consts::FOPEN_DIRECT_IO;
+        }
+        // Without direct I/O the kernel would take a short read for the end
+        // of the file and shrink it
+        if self.config.short_reads {
+            return fuser::consts::FOPEN_DIRECT_IO;
+        }
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ..
+        } = *entry
+        else {
+            return 0;
+        };
+        let complete = self
+            .file_progress(torrent_id, file_index)
+            .is_some_and(|downloaded| downloaded >= size);
+        let key = (torrent_id, file_index);
+        let mode = self.config.page_cache_mode(complete);
+        if mode == PageCacheMode::Bypass {
+            return fuser::consts::FOPEN_DIRECT_IO;
+        }
+        if !complete {
+            self.cached_while_downloading.insert(key);
+        } else if self.cached_while_downloading.remove(&key).is_some() {
+            // Pages cached while the file was downloading may predate the
+            // pieces that arrived since
+            return 0;
+        }
+        match mode {
+            PageCacheMode::Keep => fuser::consts::FOPEN_KEEP_CACHE,
+            _ => 0,
+        }
+    }
 
-        // Validate offset is non-negative
-        if offset < 0 {
-            self.metrics.record_error();
-            tracing::debug!(
-                fuse_op = "read",
-                result = "error",
-                error = "EINVAL",
-                reason = "negative_offset"
-            );
-            reply.error(libc::EINVAL);
+    /// Applies `posix_fadvise` advice to a file. Readahead advice lasts
+    /// until `NORMAL` and replaces the file's stream, so the next read
+    /// opens one with the new readahead.
+    pub(crate) fn advise(&self, entry: &InodeEntry, args: FadviseArgs) {
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ..
+        } = *entry
+        else {
             return;
+        };
+        debug!(torrent_id, file_index, advice = ?args.advice, "File advice");
+        let key = (torrent_id, file_index);
+        match args.advice {
+            Advice::Sequential | Advice::Random => {
+                self.file_advice.insert(key, args.advice);
+            }
+            Advice::Normal => {
+                self.file_advice.remove(&key);
+            }
+            Advice::WillNeed => {
+                self.prefetch_range(torrent_id, file_index, size, args.offset, args.len);
+                return;
+            }
+            Advice::DontNeed | Advice::NoReuse => return,
         }
 
-        let offset = offset as u64;
+        self.apply_readahead_profile(entry);
+        let api_client = Arc::clone(&self.api_client);
+        tokio::spawn(async move {
+            api_client
+                .close_stream(torrent_id, file_index as usize)
+                .await;
+        });
+    }
 
-        // Look up the inode from the file handle
-        let ino = match self.file_handles.get_inode(fh) {
-            Some(inode) => inode,
-            None => {
-                self.metrics.record_error();
-                tracing::debug!(
-                    fuse_op = "read",
-                    result = "error",
-                    error = "EBADF",
-                    fh = fh,
-                    reason = "invalid_file_handle"
+    /// Fetches `len` bytes at `offset` of a file in the background (0 = to
+    /// the end), at most `readahead_max_size` of them.
+    fn prefetch_range(&self, torrent_id: u64, file_index: u64, size: u64, offset: u64, len: u64) {
+        if self.config.offline || offset >= size {
+            return;
+        }
+        let len = match len {
+            0 => size - offset,
+            len => len.min(size - offset),
+        };
+        // Only as much as fits below the memory cap
+        let len = len
+            .min(self.config.readahead_max_size.max(1))
+            .min(self.api_client.memory().headroom());
+        if len == 0 || self.relieve_memory_pressure() {
+            return;
+        }
+        let end = offset + len - 1;
+
+        let api_client = Arc::clone(&self.api_client);
+        tokio::spawn(async move {
+            if let Err(e) = api_client
+                .read_file_with_priority(
+                    torrent_id,
+                    file_index as usize,
+                    Some((offset, end)),
+                    ReadPriority::Background,
+                )
+                .await
+            {
+                debug!(
+                    "Prefetch of torrent {} file {} failed: {}",
+                    torrent_id, file_index, e
                 );
-                reply.error(libc::EBADF);
-                return;
             }
+        });
+    }
+
+    /// Fetches the start and end of a file in the background, once per file,
+    /// so media probes find their headers and indexes already downloaded.
+    fn prefetch_head_tail(&self, entry: &InodeEntry) {
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ..
+        } = *entry
+        else {
+            return;
         };
+        let length = self.config.prefetch_head_tail;
+        if length == 0
+            || self.config.offline
+            || size == 0
+            || self.relieve_memory_pressure()
+            || !self.prefetched_files.insert((torrent_id, file_index))
+        {
+            return;
+        }
 
-        // Get the file entry
-        let (torrent_id, file_index, file_size) = match self.inode_manager.get(ino) {
-            Some(entry) => match entry {
-                crate::types::InodeEntry::File {
-                    torrent_id,
-                    file_index,
-                    size,
-                    ..
-                } => (torrent_id, file_index, size),
-                _ => {
-                    self.metrics.record_error();
-                    tracing::debug!(
-                        fuse_op = "read",
-                        result = "error",
-                        error = "EISDIR",
-                        ino = ino
+        let api_client = Arc::clone(&self.api_client);
+        tokio::spawn(async move {
+            for range in head_tail_ranges(size, length) {
+                if let Err(e) = api_client
+                    .read_file_with_priority(
+                        torrent_id,
+                        file_index as usize,
+                        Some(range),
+                        ReadPriority::Background,
+                    )
+                    .await
+                {
+                    debug!(
+                        "Prefetch of torrent {} file {} failed: {}",
+                        torrent_id, file_index, e
                     );
-                    reply.error(libc::EISDIR);
-                    return;
                 }
-            },
-            None => {
-                self.metrics.record_error();
-                tracing::debug!(
-                    fuse_op = "read",
-                    result = "error",
-                    error = "ENOENT",
-                    ino = ino
-                );
-                reply.error(libc::ENOENT);
-                return;
             }
-        };
+        });
+    }
 
-        // Handle zero-byte reads
-        if size == 0 || offset >= file_size {
-            reply.data(&[]);
-            return;
+    /// Near `max_memory_bytes`, drops the handles' read-ahead data and the
+    /// cached listings. Returns whether memory is short, in which case new
+    /// readahead is skipped.
+    fn relieve_memory_pressure(&self) -> bool {
+        let memory = self.api_client.memory();
+        if !memory.under_pressure() {
+            return false;
         }
+        let freed = self.file_handles.drop_read_ahead();
+        self.readdir_cache.clear();
+        debug!(
+            used = memory.used(),
+            limit = memory.limit(),
+            freed = freed,
+            "Memory near its cap, dropped caches"
+        );
+        true
+    }
 
-        // Calculate actual read range (don't read past EOF)
-        // Use saturating_sub to prevent underflow when offset == file_size
-        let _end = std::cmp::min(offset + size as u64, file_size).saturating_sub(1);
+    /// Returns `(blocks, files, free files)` for statfs: the size of all
+    /// torrents in 4 KiB blocks, none of them free, and the inode count
+    /// against the inode limit.
+    fn statfs_values(&self) -> (u64, u64, u64) {
+        let total_size: u64 = self.torrent_sizes.iter().map(|size| *size).sum();
+        let files = self.inode_manager.len() as u64;
+        let ffree = match self.inode_manager.max_inodes() {
+            0 => u32::MAX as u64,
+            max => (max as u64).saturating_sub(files),
+        };
+        (total_size.div_ceil(4096), files, ffree)
+    }
 
-        // Perform the read using the async worker to avoid blocking async in sync callbacks
-        // This eliminates the deadlock risk from block_in_place + block_on pattern
-        let timeout_duration = Duration::from_secs(self.config.read_timeout);
-        let result = self.async_worker.read_file(
-            torrent_id,
-            file_index,
-            offset,
-            size as usize,
-            timeout_duration,
-        );
+    /// Gracefully shut down the filesystem.
+    ///
+    /// This stops all background tasks:
+    /// - Status monitoring
+    /// - Torrent discovery
+    ///
+    /// It also shuts down the async worker to complete pending operations.
+    pub fn shutdown(&self) {
+        info!("Initiating graceful shutdown...");
 
-        let latency = start_time.elapsed();
+        self.stop_torrent_discovery();
 
-        match result {
-            Ok(data) => {
-                let bytes_read = data.len() as u64;
-                self.metrics.record_read(bytes_read);
+        info!("Graceful shutdown complete");
+    }
 
-                // Log slow reads at debug level only
-                if latency > std::time::Duration::from_secs(1) {
-                    debug!(
-                        fuse_op = "read",
-                        fh = fh,
-                        ino = ino,
-                        torrent_id = torrent_id,
-                        latency_ms = latency.as_millis() as u64,
-                        "Slow read detected"
-                    );
-                }
+    /// Refresh torrent list from rqbit with cooldown protection.
+    /// Returns true if a refresh was performed, false if skipped due to cooldown.
+    ///
+    /// # Arguments
+    /// * `force` - If true, bypass the cooldown check
+    ///
+    /// # Returns
+    /// * `bool` - True if refresh was performed, false if skipped
+    pub async fn refresh_torrents(&self, force: bool) -> bool {
+        self.refresh_torrents_newer_than(force, None).await
+    }
 
-                // Truncate data to requested size to prevent "Too much data" FUSE panic
-                // The API might return more data than requested (e.g., entire piece)
-                let data_slice = if data.len() > size as usize {
-                    warn!(
-                        fuse_op = "read",
-                        fh = fh,
-                        ino = ino,
-                        api_response_bytes = data.len(),
-                        requested_size = size,
-                        "Truncating API response to requested size"
-                    );
-                    &data[..size as usize]
-                } else {
-                    &data[..]
-                };
-                reply.data(data_slice);
-            }
-            Err(e) => {
-                self.metrics.record_error();
+    /// Like `refresh_torrents`, reusing a cached torrent list only if it is
+    /// younger than `list_max_age`.
+    async fn refresh_torrents_newer_than(
+        &self,
+        force: bool,
+        list_max_age: Option<Duration>,
+    ) -> bool {
+        const COOLDOWN_MS: u64 = 5000;
 
-                // Map the error appropriately
-                let error_code = e.to_errno();
-                let error_msg = e.to_string();
+        // The snapshot never changes while offline
+        if self.config.offline {
+            return false;
+        }
 
-                error!(
-                    fuse_op = "read",
-                    fh = fh,
-                    ino = ino,
-                    torrent_id = torrent_id,
-                    file_index = file_index,
-                    error = %error_msg,
-                    "Failed to read file"
-                );
+        // Check cooldown unless forced
+        if !force {
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64;
+            let last_ms = self.last_discovery.load(Ordering::SeqCst);
 
-                reply.error(error_code);
+            if last_ms != 0 && now_ms.saturating_sub(last_ms) < COOLDOWN_MS {
+                let remaining_secs = (COOLDOWN_MS - (now_ms - last_ms)) / 1000;
+                trace!(
+                    "Skipping torrent discovery - cooldown in effect ({}s remaining)",
+                    remaining_secs
+                );
+                return false;
             }
         }
-    }
-
-    /// Release an open file.
-    /// Called when a file is closed. Cleans up file handle state.
-    fn release(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _ino: u64,
-        fh: u64,
-        _flags: i32,
-        _lock_owner: Option<u64>,
-        _flush: bool,
-        reply: fuser::ReplyEmpty,
-    ) {
-        // Clean up the file handle
-        if let Some(_handle) = self.file_handles.remove(fh) {
-            // Handle removed successfully
-        } else {
-            warn!(
-                fuse_op = "release",
-                fh = fh,
-                result = "warning",
-                reason = "handle_not_found"
-            );
-        }
 
-        reply.ok();
-    }
+        // Perform discovery
+        match self.discover_torrents(list_max_age).await {
+            Ok(current_torrent_ids) => {
+                let now_ms = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                self.last_discovery.store(now_ms, Ordering::SeqCst);
 
-    /// Look up a directory entry by name.
-    /// Called when the kernel needs to resolve a path component to an inode.
-    #[instrument(skip(self, reply, name), fields(parent))]
-    fn lookup(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        parent: u64,
-        name: &std::ffi::OsStr,
-        reply: fuser::ReplyEntry,
-    ) {
-        let name_str = name.to_string_lossy();
+                // Update known_torrents with current torrent IDs
+                for torrent_id in &current_torrent_ids {
+                    self.known_torrents.insert(*torrent_id);
+                }
 
-        // Get the parent directory entry
-        let parent_entry = match self.inode_manager.get(parent) {
-            Some(entry) => entry,
-            None => {
-                self.metrics.record_error();
-                tracing::debug!(
-                    fuse_op = "lookup",
-                    result = "error",
-                    error = "ENOENT",
-                    ino = parent
-                );
-                reply.error(libc::ENOENT);
-                return;
-            }
-        };
+                // Detect and remove torrents that were deleted from rqbit
+                let removed = self.detect_removed_torrents(&current_torrent_ids);
+                for torrent_id in removed {
+                    self.remove_torrent_from_fs(torrent_id).await;
+                }
 
-        // Check if parent is a directory
-        if !parent_entry.is_directory() {
-            self.metrics.record_error();
-            tracing::debug!(
-                fuse_op = "lookup",
-                result = "error",
-                error = "ENOTDIR",
-                ino = parent
-            );
-            reply.error(libc::ENOTDIR);
-            return;
-        }
+                self.expire_trash().await;
+                self.update_recent_dir();
 
-        // Handle special entries: "." and ".."
-        let target_ino = match name_str.as_ref() {
-            "." => {
-                // "." refers to the current directory (parent)
-                Some(parent)
+                true
             }
-            ".." => {
-                // ".." refers to the parent directory
-                // Root directory's parent is itself
-                Some(parent_entry.parent())
+            Err(e) => {
+                warn!("Failed to refresh torrents: {}", e);
+                false
             }
-            _ => None,
-        };
+        }
+    }
 
-        if let Some(ino) = target_ino {
-            if let Some(entry) = self.inode_manager.get(ino) {
-                let attr = self.build_file_attr(&entry);
-                reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
-            } else {
-                // This shouldn't happen - special entry maps to non-existent inode
-                error!(
-                    fuse_op = "lookup",
-                    parent = parent,
-                    name = %name_str,
-                    target_ino = ino,
-                    "Special entry maps to missing inode"
-                );
-                reply.error(libc::EIO);
-            }
+    /// Brings the torrent list up to date before the root is listed, so a
+    /// torrent just added in rqbit shows up in this very listing.
+    ///
+    /// Once the last discovery is older than `root_refresh_interval`, a fresh
+    /// list is fetched and waited for up to `ROOT_REFRESH_WAIT`; a slower
+    /// refresh finishes in the background and shows up in the next listing.
+    fn refresh_for_root_listing(&self) {
+        const ROOT_REFRESH_WAIT: Duration = Duration::from_secs(2);
+
+        let interval = Duration::from_secs(self.config.root_refresh_interval);
+        if interval.is_zero() {
+            let fs = self.clone();
+            tokio::spawn(async move {
+                fs.refresh_torrents(false).await;
+            });
             return;
         }
 
-        // Build the full path for this entry
-        let path = if parent == 1 {
-            format!("/{}", name_str)
-        } else {
-            match self.inode_manager.get_path_for_inode(parent) {
-                Some(parent_path) => format!("{}/{}", parent_path, name_str),
-                None => {
-                    error!(
-                        fuse_op = "lookup",
-                        parent = parent,
-                        "Failed to build path for parent inode"
-                    );
-                    reply.error(libc::EIO);
-                    return;
-                }
-            }
-        };
-
-        // Look up the inode by path
-        match self.inode_manager.lookup_by_path(&path) {
-            Some(ino) => {
-                match self.inode_manager.get(ino) {
-                    Some(entry) => {
-                        let attr = self.build_file_attr(&entry);
-                        reply.entry(&std::time::Duration::from_secs(1), &attr, 0);
-                    }
-                    None => {
-                        // This shouldn't happen - path maps to non-existent inode
-                        self.metrics.record_error();
-
-                        error!(
-                            fuse_op = "lookup",
-                            path = %path,
-                            ino = ino,
-                            "Path maps to missing inode"
-                        );
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let last_ms = self.last_discovery.load(Ordering::SeqCst);
+        if now_ms.saturating_sub(last_ms) < interval.as_millis() as u64 {
+            return;
+        }
+        // Claim the refresh so concurrent listings don't start their own
+        if self
+            .last_discovery
+            .compare_exchange(last_ms, now_ms, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
 
-                        reply.error(libc::EIO);
-                    }
-                }
-            }
-            None => {
-                reply.error(libc::ENOENT);
-            }
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let fs = self.clone();
+        tokio::spawn(async move {
+            fs.refresh_torrents_newer_than(true, Some(interval)).await;
+            let _ = done_tx.send(());
+        });
+        if done_rx.recv_timeout(ROOT_REFRESH_WAIT).is_err() {
+            debug!("Torrent list refresh still running, listing the root as is");
         }
     }
 
-    /// Get file attributes.
-    /// Called when the kernel needs to get attributes for a file or directory.
-    /// This is a fundamental operation used by ls, stat, and most file operations.
-    fn getattr(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyAttr) {
-        // Get the inode entry
-        match self.inode_manager.get(ino) {
-            Some(entry) => {
-                let attr = self.build_file_attr(&entry);
-                let ttl = std::time::Duration::from_secs(1);
-                reply.attr(&ttl, &attr);
-            }
-            None => {
-                self.metrics.record_error();
-                tracing::debug!(
-                    fuse_op = "getattr",
-                    result = "error",
-                    error = "ENOENT",
-                    ino = ino
-                );
-                reply.error(libc::ENOENT);
-            }
-        }
+    /// Returns a reference to the API client
+    pub fn api_client(&self) -> &Arc<dyn TorrentClient> {
+        &self.api_client
     }
 
-    /// Open a file.
-    /// Called when the kernel needs to open a file for reading.
-    /// Returns a file handle that will be used in subsequent read operations.
-    fn open(&mut self, _req: &fuser::Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
-        // Check if the inode exists
-        match self.inode_manager.get(ino) {
-            Some(entry) => {
-                // Check if it's a file (not a directory)
-                if entry.is_directory() {
-                    self.metrics.record_error();
-                    reply.error(libc::EISDIR);
-                    return;
-                }
+    /// Returns a reference to the inode manager
+    pub fn inode_manager(&self) -> &Arc<InodeManager> {
+        &self.inode_manager
+    }
 
-                // Check if it's a symlink (symlinks should be resolved before open)
-                if entry.is_symlink() {
-                    self.metrics.record_error();
-                    reply.error(libc::ELOOP);
-                    return;
-                }
+    /// Returns true if the filesystem has been initialized
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
 
-                // Check write access - this is a read-only filesystem
-                let access_mode = flags & libc::O_ACCMODE;
-                if access_mode != libc::O_RDONLY {
-                    self.metrics.record_error();
-                    reply.error(libc::EACCES);
-                    return;
-                }
+    /// Validates the mount point directory.
+    /// Checks that:
+    /// - The path exists
+    /// - It's a directory
+    /// - We have read/write permissions
+    fn validate_mount_point(&self) -> Result<()> {
+        let mount_point = &self.config.mount_point;
 
-                // Get torrent_id from the entry
-                let torrent_id = entry.torrent_id().unwrap_or(0);
+        if !mount_point.exists() {
+            return Err(anyhow::anyhow!(
+                "Mount point does not exist: {}",
+                mount_point.display()
+            ));
+        }
 
-                // Allocate a unique file handle
-                let fh = self.file_handles.allocate(ino, torrent_id, flags);
+        if !mount_point.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Mount point is not a directory: {}",
+                mount_point.display()
+            ));
+        }
 
-                // Check if handle allocation failed (limit reached)
-                if fh == 0 {
-                    self.metrics.record_error();
-                    reply.error(libc::EMFILE);
-                    return;
-                }
+        // Check read/write permissions by trying to access the directory
+        if std::fs::read_dir(mount_point).is_err() {
+            return Err(anyhow::anyhow!(
+                "No read permission for mount point: {}",
+                mount_point.display()
+            ));
+        }
 
-                reply.opened(fh, 0);
-            }
-            None => {
-                self.metrics.record_error();
-                reply.error(libc::ENOENT);
+        info!("Mount point validated: {}", mount_point.display());
+        Ok(())
+    }
+
+    /// Establishes connection to the rqbit server and validates it's accessible.
+    async fn connect_to_rqbit(&self) -> Result<()> {
+        info!("Connecting to rqbit server at: {}", self.config.api_url);
+
+        match self.api_client.health_check().await {
+            Ok(true) => {
+                info!("Successfully connected to rqbit server");
+                Ok(())
             }
+            Ok(false) => Err(anyhow::anyhow!(
+                "rqbit server at {} is not responding or returned an error",
+                self.config.api_url
+            )),
+            Err(e) => Err(anyhow::anyhow!(
+                "Failed to connect to rqbit server at {}: {}",
+                self.config.api_url,
+                e
+            )),
         }
     }
 
-    /// Read the target of a symbolic link.
-    /// Called when the kernel needs to resolve a symlink target.
-    fn readlink(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyData) {
-        match self.inode_manager.get(ino) {
-            Some(entry) => {
-                if let crate::types::InodeEntry::Symlink { target, .. } = entry {
-                    reply.data(target.as_bytes());
-                } else {
-                    reply.error(libc::EINVAL);
-                }
+    /// Mounts the filesystem at the configured mount point.
+    /// This is the main entry point for mounting the filesystem.
+    /// Returns once the filesystem is unmounted, either externally or with
+    /// [`TorrentFS::unmount()`] from a clone.
+    pub fn mount(self) -> Result<()>
+    where
+        Self: Sized,
+    {
+        let fs = self.clone();
+        let session = self.new_session()?;
+        fs.serve(session)
+    }
+
+    /// Stops background tasks and unmounts the filesystem mounted by
+    /// [`TorrentFS::mount()`] or [`TorrentFS::spawn_mount()`]. May be called
+    /// on any clone; does nothing if the filesystem isn't mounted.
+    pub fn unmount(&self) -> Result<()> {
+        self.shutdown();
+        let unmounter = self.unmounter.lock().unwrap().take();
+        if let Some(mut unmounter) = unmounter {
+            info!("Unmounting {}", self.config.mount_point.display());
+            unmounter.unmount().with_context(|| {
+                format!("Failed to unmount {}", self.config.mount_point.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// The value of one of [`FILE_XATTRS`] for a torrent file; None for
+    /// other entries, and for piece ranges of torrents whose piece length
+    /// rqbit didn't report.
+    fn file_xattr(&self, entry: &InodeEntry, name: &str) -> Option<String> {
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ..
+        } = entry
+        else {
+            return None;
+        };
+        match name {
+            "user.torrent.file_index" => Some(file_index.to_string()),
+            "user.torrent.progress" => {
+                let downloaded = self.file_progress(*torrent_id, *file_index)?;
+                Some(percent(downloaded, *size))
             }
-            None => {
-                self.metrics.record_error();
-                reply.error(libc::ENOENT);
+            "user.torrent.piece_start" | "user.torrent.piece_end" => {
+                let layout = self.piece_layouts.get(torrent_id)?;
+                let (start, end) = layout.piece_range(*file_index)?;
+                let piece = if name == "user.torrent.piece_start" {
+                    start
+                } else {
+                    end
+                };
+                Some(piece.to_string())
             }
+            _ => None,
         }
     }
 
-    /// Read directory entries.
-    /// Called when the kernel needs to list the contents of a directory.
-    /// For the root directory, this will also trigger a torrent discovery check.
-    #[instrument(skip(self, reply), fields(ino))]
-    fn readdir(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        ino: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: fuser::ReplyDirectory,
-    ) {
-        // Trigger torrent discovery when listing root directory (with cooldown)
-        if ino == 1 {
-            let api_client = Arc::clone(&self.api_client);
-            let inode_manager = Arc::clone(&self.inode_manager);
-            let last_discovery = Arc::clone(&self.last_discovery);
-            let known_torrents = Arc::clone(&self.known_torrents);
-            let file_handles = Arc::clone(&self.file_handles);
-
-            tokio::spawn(async move {
-                const COOLDOWN_MS: u64 = 5000;
-
-                // Atomically check and claim discovery slot
-                let now_ms = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as u64;
-                let last_ms = last_discovery.load(Ordering::SeqCst);
-
-                let should_run = last_ms == 0 || now_ms.saturating_sub(last_ms) >= COOLDOWN_MS;
-
-                if should_run {
-                    match Self::discover_torrents(&api_client, &inode_manager).await {
-                        Ok(current_torrent_ids) => {
-                            last_discovery.store(now_ms, Ordering::SeqCst);
-
-                            // Update known_torrents with current torrent IDs
-                            for torrent_id in &current_torrent_ids {
-                                known_torrents.insert(*torrent_id);
-                            }
-
-                            // Detect and remove torrents that were deleted from rqbit
-                            let current: std::collections::HashSet<u64> =
-                                current_torrent_ids.iter().copied().collect();
-                            let known: std::collections::HashSet<u64> =
-                                known_torrents.iter().map(|e| *e).collect();
-                            let removed: Vec<u64> = known.difference(&current).copied().collect();
-
-                            for torrent_id in removed {
-                                info!("Removing torrent {} from filesystem", torrent_id);
-
-                                // Get the torrent's root inode
-                                if let Some(inode) = inode_manager.lookup_torrent(torrent_id) {
-                                    // Close all file handles for this torrent
-                                    let removed_handles =
-                                        file_handles.remove_by_torrent(torrent_id);
-                                    if removed_handles > 0 {
-                                        debug!(
-                                            "Closed {} file handles for torrent {}",
-                                            removed_handles, torrent_id
-                                        );
-                                    }
-
-                                    // Remove the inode tree for this torrent
-                                    if !inode_manager.remove_inode(inode) {
-                                        warn!(
-                                            "Failed to remove inode {} for torrent {}",
-                                            inode, torrent_id
-                                        );
-                                    }
-
-                                    // Remove from known torrents
-                                    known_torrents.remove(&torrent_id);
-
-                                    // Record metric
-
-                                    info!(
-                                        "Successfully removed torrent {} from filesystem",
-                                        torrent_id
-                                    );
-                                } else {
-                                    warn!(
-                                        "Torrent {} not found in filesystem, skipping removal",
-                                        torrent_id
-                                    );
-                                    known_torrents.remove(&torrent_id);
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            warn!("On-demand torrent discovery failed: {}", e);
-                        }
-                    }
-                }
-            });
+    /// How many of a file's pieces rqbit has, for [`PIECES_XATTR`]; None for
+    /// entries other than files whose pieces are known, and while offline.
+    fn pieces_available(&self, entry: &InodeEntry) -> Result<Option<u64>, i32> {
+        if self.config.offline {
+            return Ok(None);
         }
+        let InodeEntry::File {
+            torrent_id,
+            file_index,
+            ..
+        } = entry
+        else {
+            return Ok(None);
+        };
+        let Some((start, end)) = self
+            .piece_layouts
+            .get(torrent_id)
+            .and_then(|layout| layout.piece_range(*file_index))
+        else {
+            return Ok(None);
+        };
+        let timeout = Duration::from_secs(self.config.read_timeout);
+        self.async_worker
+            .count_pieces(*torrent_id, start, end, timeout)
+            .map(Some)
+            .map_err(|e| e.to_errno())
+    }
 
-        // Get the directory entry
-        let entry = match self.inode_manager.get(ino) {
-            Some(e) => e,
-            None => {
-                self.metrics.record_error();
-                reply.error(libc::ENOENT);
-                return;
+    /// Tells the kernel to forget its cached lookup of `name` in `parent`.
+    /// Does nothing when not mounted.
+    fn invalidate_entry(&self, parent: u64, name: &str) {
+        if let Some(notifier) = self.notifier.lock().unwrap().as_ref() {
+            if let Err(e) = notifier.inval_entry(parent, std::ffi::OsStr::new(name)) {
+                // ENOENT just means the kernel had nothing cached
+                debug!("Failed to invalidate {} in {}: {}", name, parent, e);
             }
-        };
+        }
+    }
 
-        // Check if it's a directory
-        if !entry.is_directory() {
-            self.metrics.record_error();
-            reply.error(libc::ENOTDIR);
+    /// Records an open, or a read of `(offset, bytes)`, in the audit log.
+    fn audit(&self, req: &fuser::Request<'_>, ino: u64, read: Option<(u64, u64)>) {
+        let Some(audit_log) = &self.audit_log else {
+            return;
+        };
+        let Some(path) = self.inode_manager.with_entry(ino, |e| {
+            self.config
+                .mount_point
+                .join(e.canonical_path().trim_start_matches('/'))
+        }) else {
             return;
+        };
+        match read {
+            Some((offset, bytes)) => {
+                audit_log.record_read(req.uid(), req.pid(), &path, offset, bytes)
+            }
+            None => audit_log.record_open(req.uid(), req.pid(), &path),
         }
+    }
 
-        // If offset is 0, start from beginning; otherwise continue from offset
-        let mut current_offset = offset;
+    /// Mounts the filesystem and registers the session for `unmount()`.
+    pub(crate) fn new_session(self) -> Result<fuser::Session<Self>> {
+        let mount_point = self.config.mount_point.clone();
+        let options = self.build_mount_options();
+        let unmounter = Arc::clone(&self.unmounter);
+        let notifier = Arc::clone(&self.notifier);
 
-        // Always include . and .. entries
-        if current_offset == 0 {
-            if reply.add(ino, 1, fuser::FileType::Directory, ".") {
-                reply.ok();
-                return;
-            }
-            current_offset = 1;
+        info!("Mounting rqbit-fuse at: {}", mount_point.display());
+
+        let mut session = fuser::Session::new(self, &mount_point, &options)
+            .with_context(|| format!("Failed to mount filesystem at: {}", mount_point.display()))?;
+        *unmounter.lock().unwrap() = Some(session.unmount_callable());
+        *notifier.lock().unwrap() = Some(session.notifier());
+        Ok(session)
+    }
+
+    /// Serves FUSE requests until the session ends.
+    pub(crate) fn serve(&self, mut session: fuser::Session<Self>) -> Result<()> {
+        let result = session.run();
+        self.unmounter.lock().unwrap().take();
+        self.notifier.lock().unwrap().take();
+        result.with_context(|| {
+            format!(
+                "FUSE session at {} failed",
+                self.config.mount_point.display()
+            )
+        })
+    }
+
+    /// Builds FUSE mount options based on configuration.
+    pub(crate) fn build_mount_options(&self) -> Vec<fuser::MountOption> {
+        let mut options = vec![
+            fuser::MountOption::NoSuid, // No setuid/setgid
+            fuser::MountOption::NoDev,  // No special device files
+                                        // NOTE: Sync option removed - causes hangs on macOS due to blocking
+                                        // on unmount. Since this is a read-only filesystem, data integrity
+                                        // is not a concern. This fix was needed after macOS system updates
+                                        // broke FUSE mounting with Sync option enabled.
+        ];
+
+        // Read-only, unless torrents can be moved to the trash, added or
+        // removed
+        let writable = self.config.trash_days > 0
+            || self.config.add_torrents
+            || self.config.remove_torrents != TorrentRemoval::Off;
+        if !writable || self.config.offline {
+            options.push(fuser::MountOption::RO);
         }
 
-        if current_offset == 1 {
-            let parent_ino = entry.parent();
-            if reply.add(parent_ino, 2, fuser::FileType::Directory, "..") {
-                reply.ok();
-                return;
-            }
-            current_offset = 2;
+        // Access times are tracked by the filesystem according to the atime policy
+        if self.config.atime == crate::config::AtimePolicy::Noatime {
+            options.push(fuser::MountOption::NoAtime);
         }
 
-        // Get children of this directory
-        let children = self.inode_manager.get_children(ino);
-        let child_offset_start = 2; // . and .. take offsets 0 and 1
+        // nfsd accesses the mount as root
+        if self.config.nfs_export || self.config.allow_other {
+            options.push(fuser::MountOption::AllowOther);
+        } else if self.config.allow_root {
+            options.push(fuser::MountOption::AllowRoot);
+        }
 
-        for (idx, (child_ino, child_entry)) in children.iter().enumerate() {
-            let entry_offset = child_offset_start + idx as i64;
+        // The kernel checks file_mode/dir_mode and uid/gid itself instead of
+        // asking access(), which also covers open() by other users
+        if self.config.default_permissions {
+            options.push(fuser::MountOption::DefaultPermissions);
+        }
 
-            // Skip entries before the requested offset
-            if entry_offset < current_offset {
-                continue;
-            }
+        // Kernel-side options without a setting of their own
+        options.extend(
+            self.config
+                .extra_options
+                .iter()
+                .map(|option| fuser::MountOption::CUSTOM(option.clone())),
+        );
 
-            let file_type = if child_entry.is_directory() {
-                fuser::FileType::Directory
-            } else if child_entry.is_symlink() {
-                fuser::FileType::Symlink
-            } else {
-                fuser::FileType::RegularFile
-            };
+        // auto_unmount is implemented by fusermount; without it fuser calls
+        // the mount syscall directly, which is what container mode relies on
+        if !self.config.container {
+            options.push(fuser::MountOption::AutoUnmount);
+        }
 
-            if reply.add(*child_ino, entry_offset + 1, file_type, child_entry.name()) {
-                reply.ok();
-                return;
+        options
+    }
+
+    /// Applies the configured FUSE session parameters. Values the kernel
+    /// rejects are logged and replaced with the closest accepted value.
+    fn configure_kernel(&self, kernel: &mut fuser::KernelConfig) {
+        if let Some(readahead) = self.config.fuse_max_readahead {
+            let readahead = readahead.min(u32::MAX as u64) as u32;
+            if let Err(nearest) = kernel.set_max_readahead(readahead) {
+                warn!(
+                    "Kernel rejected fuse_max_readahead={}, using {}",
+                    readahead, nearest
+                );
+                let _ = kernel.set_max_readahead(nearest);
             }
         }
+        if let Some(max_background) = self.config.fuse_max_background {
+            if let Err(nearest) = kernel.set_max_background(max_background) {
+                warn!(
+                    "Kernel rejected fuse_max_background={}, using {}",
+                    max_background, nearest
+                );
+                let _ = kernel.set_max_background(nearest);
+            }
+        }
+        if let Some(threshold) = self.config.fuse_congestion_threshold {
+            if let Err(nearest) = kernel.set_congestion_threshold(threshold) {
+                warn!(
+                    "Kernel rejected fuse_congestion_threshold={}, using {}",
+                    threshold, nearest
+                );
+                let _ = kernel.set_congestion_threshold(nearest);
+            }
+        }
+        if self.config.fuse_writeback_cache
+            && kernel
+                .add_capabilities(fuser::consts::FUSE_WRITEBACK_CACHE)
+                .is_err()
+        {
+            warn!("Kernel does not support fuse_writeback_cache, leaving it disabled");
+        }
+        // Lets the kernel look up "." and ".." by inode, so NFS file
+        // handles can be decoded after the dentry cache is dropped
+        if self.config.nfs_export
+            && kernel
+                .add_capabilities(fuser::consts::FUSE_EXPORT_SUPPORT)
+                .is_err()
+        {
+            warn!("Kernel does not support FUSE export, NFS re-export will not work");
+        }
+    }
 
-        reply.ok();
+    /// Returns the uid and gid reported for every entry.
+    fn owner(&self) -> (u32, u32) {
+        (
+            self.config
+                .uid
+                .unwrap_or_else(|| unsafe { libc::geteuid() }),
+            self.config
+                .gid
+                .unwrap_or_else(|| unsafe { libc::getegid() }),
+        )
     }
 
-    /// Create a directory.
-    /// This filesystem is read-only, so it always returns EROFS (read-only filesystem).
-    fn mkdir(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _parent: u64,
-        _name: &std::ffi::OsStr,
-        _mode: u32,
-        _umask: u32,
-        reply: fuser::ReplyEntry,
-    ) {
-        reply.error(libc::EROFS);
+    /// Checks an access() mask for the caller against an entry's attributes,
+    /// using the owner/group/other permission bits. Root may read anything
+    /// and execute anything with at least one execute bit.
+    fn check_access(attr: &fuser::FileAttr, uid: u32, gid: u32, mask: i32) -> bool {
+        let perm = u32::from(attr.perm);
+        if uid == 0 {
+            return mask & libc::X_OK == 0 || perm & 0o111 != 0;
+        }
+        let granted = if uid == attr.uid {
+            perm >> 6
+        } else if gid == attr.gid {
+            perm >> 3
+        } else {
+            perm
+        } & 0o7;
+        let wanted = (mask & (libc::R_OK | libc::W_OK | libc::X_OK)) as u32;
+        granted & wanted == wanted
     }
 
-    /// Remove a directory.
-    /// This filesystem is read-only, so it always returns EROFS (read-only filesystem).
-    fn rmdir(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _parent: u64,
-        _name: &std::ffi::OsStr,
-        reply: fuser::ReplyEmpty,
-    ) {
-        reply.error(libc::EROFS);
+    /// Build file attributes for a given inode entry.
+    /// Converts internal InodeEntry to FUSE FileAttr.
+    ///
+    /// # Arguments
+    /// * `entry` - The inode entry to build attributes for
+    ///
+    /// # Returns
+    /// * `fuser::FileAttr` - The FUSE file attributes
+    pub fn build_file_attr(&self, entry: &crate::fs::inode::InodeEntry) -> fuser::FileAttr {
+        use crate::fs::inode::InodeEntry;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let now = SystemTime::now();
+        let creation_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000); // Fixed creation time
+        let (uid, gid) = self.owner();
+
+        match entry {
+            InodeEntry::Directory { ino, .. } => {
+                let mtime = self
+                    .dir_mtimes
+                    .get(ino)
+                    .map_or(self.mounted_at, |time| *time);
+                fuser::FileAttr {
+                    ino: *ino,
+                    size: 0,
+                    blocks: 0,
+                    atime: now,
+                    mtime,
+                    ctime: mtime,
+                    crtime: creation_time,
+                    kind: fuser::FileType::Directory,
+                    perm: self.config.dir_mode as u16,
+                    nlink: 2 + self.inode_manager.subdir_count(*ino),
+                    uid,
+                    gid,
+                    rdev: 0,
+                    flags: 0,
+                    blksize: 4096,
+                }
+            }
+            InodeEntry::File {
+                ino,
+                torrent_id,
+                file_index,
+                size,
+                atime,
+                ..
+            } => fuser::FileAttr {
+                ino: *ino,
+                size: *size,
+                // Only what rqbit has downloaded is allocated, once known
+                blocks: self
+                    .file_progress
+                    .get(&(*torrent_id, *file_index))
+                    .map_or(*size, |progress| (*progress).min(*size))
+                    .div_ceil(4096),
+                atime: atime.unwrap_or(creation_time),
+                mtime: now,
+                ctime: now,
+                crtime: creation_time,
+                kind: fuser::FileType::RegularFile,
+                perm: self.config.file_mode as u16,
+                nlink: 1,
+                uid,
+                gid,
+                rdev: 0,
+                flags: 0,
+                blksize: 4096,
+            },
+            InodeEntry::Symlink { ino, target, .. } => fuser::FileAttr {
+                ino: *ino,
+                size: target.len() as u64,
+                blocks: 1,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                crtime: creation_time,
+                kind: fuser::FileType::Symlink,
+                perm: 0o777, // Symlinks always have 777 permissions
+                nlink: 1,
+                uid,
+                gid,
+                rdev: 0,
+                flags: 0,
+                blksize: 4096,
+            },
+            // Sized 0 like procfs files: the contents are generated when
+            // read, and opened with direct I/O so the kernel reads them anyway
+            // The add file is writable by whoever may read it
+            InodeEntry::Virtual { ino, file, .. } => fuser::FileAttr {
+                ino: *ino,
+                size: 0,
+                blocks: 0,
+                atime: now,
+                mtime: now,
+                ctime: now,
+                crtime: creation_time,
+                kind: fuser::FileType::RegularFile,
+                perm: if file.is_writable() {
+                    (self.config.file_mode | (self.config.file_mode & 0o444) >> 1) as u16
+                } else {
+                    self.config.file_mode as u16
+                },
+                nlink: 1,
+                uid,
+                gid,
+                rdev: 0,
+                flags: 0,
+                blksize: 4096,
+            },
+        }
     }
+}
 
-    /// Remove a file (or torrent directory).
-    /// This allows removing torrents by unlinking their root directory from the mount point.
-    /// Individual files cannot be removed (read-only).
-    fn unlink(
+impl Filesystem for TorrentFS {
+    /// Read file contents.
+    /// Called when the kernel needs to read data from a file.
+    /// Translates FUSE read requests to HTTP Range requests to rqbit.
+    #[instrument(skip(self, reply), fields(fh))]
+    fn read(
         &mut self,
-        _req: &fuser::Request<'_>,
-        parent: u64,
-        name: &std::ffi::OsStr,
-        reply: fuser::ReplyEmpty,
+        req: &fuser::Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
     ) {
-        let name_str = name.to_string_lossy();
-        trace!("unlink: parent={}, name={}", parent, name_str);
+        let _trace = request_id::begin("read");
+        let start_time = Instant::now();
+        self.activity.touch_read();
 
-        // Only allow unlinking torrent directories from root
-        if parent != 1 {
-            reply.error(libc::EROFS);
+        // Clamp read size to FUSE maximum to prevent "Too much data" panic
+        let size = std::cmp::min(size, Self::FUSE_MAX_READ);
+
+        // Validate offset is non-negative
+        if offset < 0 {
+            self.metrics.record_error();
+            tracing::debug!(
+                fuse_op = "read",
+                result = "error",
+                error = "EINVAL",
+                reason = "negative_offset"
+            );
+            reply.error(libc::EINVAL);
+            return;
+        }
+
+        let offset = offset as u64;
+
+        // File data lives on the unreachable server
+        if self.config.offline {
+            self.metrics.record_error();
+            tracing::debug!(
+                fuse_op = "read",
+                result = "error",
+                error = "ENETDOWN",
+                fh = fh
+            );
+            reply.error(libc::ENETDOWN);
             return;
         }
 
-        // Look up the torrent directory by name
-        let path = format!("/{}", name_str);
-        let ino = match self.inode_manager.lookup_by_path(&path) {
-            Some(ino) => ino,
+        // Look up the inode from the file handle
+        let ino = match self.file_handles.get_inode(fh) {
+            Some(inode) => inode,
             None => {
-                reply.error(libc::ENOENT);
+                self.metrics.record_error();
+                tracing::debug!(
+                    fuse_op = "read",
+                    result = "error",
+                    error = "EBADF",
+                    fh = fh,
+                    reason = "invalid_file_handle"
+                );
+                reply.error(libc::EBADF);
                 return;
             }
         };
 
-        // Verify this is a torrent directory
-        let torrent_id = match self.inode_manager.get(ino) {
-            Some(entry) => {
-                if !entry.is_directory() {
-                    reply.error(libc::ENOTDIR);
+        // Get the file entry
+        let (torrent_id, file_index, file_size, atime) = match self.inode_manager.get(ino) {
+            Some(entry) => match entry {
+                crate::types::InodeEntry::File {
+                    torrent_id,
+                    file_index,
+                    size,
+                    atime,
+                    ..
+                } => (torrent_id, file_index, size, atime),
+                crate::types::InodeEntry::Virtual {
+                    torrent_id, file, ..
+                } => {
+                    self.read_virtual(fh, torrent_id, file, offset, size, reply);
                     return;
                 }
-                // Find the torrent ID
-                match self
-                    .inode_manager
-                    .torrent_to_inode()
-                    .iter()
-                    .find(|item| *item.value() == ino)
-                    .map(|item| *item.key())
-                {
-                    Some(id) => id,
-                    None => {
-                        warn!("unlink: no torrent ID found for inode {}", ino);
-                        reply.error(libc::EIO);
-                        return;
-                    }
+                _ => {
+                    self.metrics.record_error();
+                    tracing::debug!(
+                        fuse_op = "read",
+                        result = "error",
+                        error = "EISDIR",
+                        ino = ino
+                    );
+                    reply.error(libc::EISDIR);
+                    return;
                 }
-            }
+            },
             None => {
+                self.metrics.record_error();
+                tracing::debug!(
+                    fuse_op = "read",
+                    result = "error",
+                    error = "ENOENT",
+                    ino = ino
+                );
                 reply.error(libc::ENOENT);
                 return;
             }
         };
 
-        // Check for open file handles in this torrent
-        let has_open_handles = {
-            // Get all file inodes in this torrent directory
-            let file_inodes: Vec<u64> = self
-                .inode_manager
-                .get_children(ino)
-                .iter()
-                .filter(|(_, entry)| entry.is_file())
-                .map(|(inode, _)| *inode)
-                .collect();
-
-            // Check if any file handle points to these inodes
-            file_inodes.iter().any(|file_inode| {
-                !self
-                    .file_handles
-                    .get_handles_for_inode(*file_inode)
-                    .is_empty()
-            })
-        };
-
-        if has_open_handles {
-            warn!(
-                "unlink: torrent {} has open file handles, cannot remove",
-                torrent_id
+        // Fail fast instead of waiting for data a failed torrent won't deliver
+        if let Some(message) = self.failed_torrents.get(&torrent_id) {
+            let err = crate::error::RqbitFuseError::TorrentFailed(message.clone());
+            self.metrics.record_error();
+            tracing::debug!(
+                fuse_op = "read",
+                result = "error",
+                torrent_id = torrent_id,
+                error = %err
             );
-            reply.error(libc::EBUSY);
+            reply.error(err.to_errno());
             return;
         }
 
-        // Perform the removal
-        if let Err(e) = self.remove_torrent(torrent_id, ino) {
-            error!("unlink: failed to remove torrent {}: {}", torrent_id, e);
-
-            // Map error appropriately
-            let error_code = if let Some(api_err) = e.downcast_ref::<crate::error::RqbitFuseError>()
-            {
-                api_err.to_errno()
-            } else {
-                libc::EIO
-            };
-
-            reply.error(error_code);
+        // Handle zero-byte reads
+        if size == 0 || offset >= file_size {
+            reply.data(&[]);
             return;
         }
 
-        info!("Successfully removed torrent {} ({})", torrent_id, name_str);
-        reply.ok();
-    }
-
-    /// Get extended attribute value.
-    /// Exposes torrent status information via extended attributes.
-    fn getxattr(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        ino: u64,
-        name: &std::ffi::OsStr,
-        _size: u32,
-        reply: fuser::ReplyXattr,
-    ) {
-        let name_str = name.to_string_lossy();
-        trace!("getxattr: ino={}, name={}", ino, name_str);
-
-        // Only support the "user.torrent.status" attribute
-        if name_str != "user.torrent.status" {
-            reply.error(ENOATTR);
+        if !self.quota.allows(req.uid(), std::time::SystemTime::now()) {
+            self.metrics.record_error();
+            tracing::debug!(
+                fuse_op = "read",
+                result = "error",
+                error = "EDQUOT",
+                uid = req.uid()
+            );
+            reply.error(libc::EDQUOT);
             return;
         }
 
-        // Get the torrent ID for this inode
-        let torrent_id = match self.inode_manager.get(ino) {
-            Some(entry) => match entry {
-                InodeEntry::File { torrent_id, .. } => torrent_id,
-                InodeEntry::Directory { .. } => {
-                    // For directories, try to find torrent_id by looking up which torrent maps to this inode
-                    self.inode_manager
-                        .torrent_to_inode()
-                        .iter()
-                        .find(|item| *item.value() == ino)
-                        .map(|item| *item.key())
-                        .unwrap_or(0)
-                }
-                InodeEntry::Symlink { .. } => {
-                    // Symlinks don't have torrent status
-                    reply.error(ENOATTR);
-                    return;
-                }
-            },
-            None => {
-                reply.error(libc::ENOENT);
-                return;
-            }
-        };
+        // Don't read past EOF
+        let available = (file_size - offset).min(size as u64) as usize;
 
-        if torrent_id == 0 {
-            // This directory is not associated with a torrent (e.g., subdirectory)
-            reply.error(ENOATTR);
+        // Serve adjacent reads from data fetched by an earlier, larger read
+        if let Some(data) = self.file_handles.take_read_ahead(fh, offset, available) {
+            self.metrics.record_read(data.len() as u64);
+            self.record_activity(
+                fh,
+                torrent_id,
+                offset,
+                data.len() as u64,
+                start_time.elapsed(),
+            );
+            self.audit(req, ino, Some((offset, data.len() as u64)));
+            let now = std::time::SystemTime::now();
+            self.quota.record(req.uid(), data.len() as u64, now);
+            if self.config.atime.should_update(atime, now) {
+                self.inode_manager.set_atime(ino, now);
+            }
+            reply.data(&data);
             return;
         }
 
-        // Status monitoring has been removed, return attribute not found
-        reply.error(ENOATTR);
-    }
+        // Fetch at least read_aggregate_size bytes, so the kernel's small
+        // reads cost one upstream round trip between them, unless memory is
+        // short
+        let fetch_size = if self.relieve_memory_pressure() {
+            size as usize
+        } else {
+            (self.config.read_aggregate_size.min(file_size - offset) as usize).max(size as usize)
+        };
 
-    /// List extended attributes.
-    fn listxattr(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        ino: u64,
-        size: u32,
-        reply: fuser::ReplyXattr,
-    ) {
-        // Check if inode exists
-        if self.inode_manager.get(ino).is_none() {
-            reply.error(libc::ENOENT);
-            return;
-        }
+        // Perform the read using the async worker to avoid blocking async in sync callbacks
+        // This eliminates the deadlock risk from block_in_place + block_on pattern
+        let timeout_duration = Duration::from_secs(self.config.read_timeout);
+        let mode = if self.config.piece_check_enabled {
+            ReadMode::Verified
+        } else if self.config.short_reads {
+            ReadMode::Partial
+        } else {
+            ReadMode::Full
+        };
+        let result = self.async_worker.read_file(
+            torrent_id,
+            file_index,
+            offset,
+            fetch_size,
+            timeout_duration,
+            mode,
+        );
+
+        let latency = start_time.elapsed();
+
+        match result {
+            Ok(data) => {
+                // Keep what was fetched beyond this read for the next ones,
+                // including the rest of verified pieces
+                let aggregated = if self.config.piece_check_enabled {
+                    data.len()
+                } else {
+                    data.len().min(fetch_size)
+                };
+                let data = if aggregated > size as usize {
+                    self.file_handles.set_read_ahead(
+                        fh,
+                        offset + size as u64,
+                        data.slice(size as usize..aggregated),
+                    );
+                    data.slice(..size as usize)
+                } else {
+                    data
+                };
 
-        // The only attribute we support
-        let attr_list = "user.torrent.status\0";
-        let data = attr_list.as_bytes();
+                let bytes_read = data.len() as u64;
+                self.metrics.record_read(bytes_read);
+                self.record_activity(fh, torrent_id, offset, bytes_read, latency);
+                self.audit(req, ino, Some((offset, bytes_read)));
 
-        if size == 0 {
-            reply.size(data.len() as u32);
-        } else if data.len() <= size as usize {
-            reply.data(data);
-        } else {
-            reply.error(libc::ERANGE);
+                let now = std::time::SystemTime::now();
+                self.quota.record(req.uid(), bytes_read, now);
+                if self.config.atime.should_update(atime, now) {
+                    self.inode_manager.set_atime(ino, now);
+                }
+
+                // Log slow reads at debug level only
+                if latency > std::time::Duration::from_secs(1) {
+                    debug!(
+                        fuse_op = "read",
+                        fh = fh,
+                        ino = ino,
+                        torrent_id = torrent_id,
+                        latency_ms = latency.as_millis() as u64,
+                        "Slow read detected"
+                    );
+                }
+
+                // Truncate data to requested size to prevent "Too much data" FUSE panic
+                // The API might return more data than requested (e.g., entire piece)
+                let data_slice = if data.len() > size as usize {
+                    warn!(
+                        fuse_op = "read",
+                        fh = fh,
+                        ino = ino,
+                        api_response_bytes = data.len(),
+                        requested_size = size,
+                        "Truncating API response to requested size"
+                    );
+                    &data[..size as usize]
+                } else {
+                    &data[..]
+                };
+                reply.data(data_slice);
+            }
+            Err(e) => {
+                self.metrics.record_error();
+
+                // Map the error appropriately
+                let error_code = e.to_errno();
+                let error_msg = e.to_string();
+
+                error!(
+                    fuse_op = "read",
+                    fh = fh,
+                    ino = ino,
+                    torrent_id = torrent_id,
+                    file_index = file_index,
+                    error = %error_msg,
+                    "Failed to read file"
+                );
+
+                reply.error(error_code);
+            }
         }
     }
 
-    /// Initialize the filesystem.
-    /// Called when the filesystem is mounted. Sets up the connection to rqbit,
-    /// validates the mount point, and initializes the root inode.
-    fn init(
+    /// Release an open file.
+    /// Called when a file is closed. Cleans up file handle state.
+    fn release(
         &mut self,
         _req: &fuser::Request<'_>,
-        _config: &mut fuser::KernelConfig,
-    ) -> Result<(), libc::c_int> {
-        info!("Initializing rqbit-fuse filesystem");
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        // Clean up the file handle
+        self.virtual_contents.remove(&fh);
+        self.pending_links.remove(&fh);
+        self.torrent_uploads.remove(&fh);
+        if let Some(handle) = self.file_handles.remove(fh) {
+            // Close the stream early once no other handle reads the file
+            if self.config.close_streams_on_release
+                && self
+                    .file_handles
+                    .get_handles_for_inode(handle.inode)
+                    .is_empty()
+            {
+                if let Some(crate::types::InodeEntry::File { file_index, .. }) =
+                    self.inode_manager.get(handle.inode)
+                {
+                    let api_client = Arc::clone(&self.api_client);
+                    let torrent_id = handle.torrent_id;
+                    tokio::spawn(async move {
+                        api_client
+                            .close_stream(torrent_id, file_index as usize)
+                            .await;
+                    });
+                }
+            }
+        } else {
+            warn!(
+                fuse_op = "release",
+                fh = fh,
+                result = "warning",
+                reason = "handle_not_found"
+            );
+        }
+        self.remove_torrent_upload(ino);
 
-        // Validate mount point
-        if let Err(e) = self.validate_mount_point() {
-            error!("Mount point validation failed: {}", e);
-            return Err(libc::EIO);
+        reply.ok();
+    }
+
+    /// Write to a file.
+    /// Only the add file takes writes, each line written being a torrent to
+    /// add, and `.torrent` files being copied into the root.
+    fn write(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        let _trace = request_id::begin("write");
+        self.activity.touch();
+        let result = match self.inode_manager.get(ino) {
+            Some(InodeEntry::Virtual {
+                file: VirtualFile::AddTorrent,
+                ..
+            }) => self.add_written_links(fh, data, false),
+            Some(InodeEntry::Virtual {
+                file: VirtualFile::TorrentUpload,
+                ..
+            }) => self.write_torrent_upload(fh, offset, data),
+            _ => Err(libc::EBADF),
+        };
+        match result {
+            Ok(()) => reply.written(data.len() as u32),
+            Err(errno) => reply.error(errno),
         }
+    }
 
-        // Check that root inode (inode 1) exists and is a directory
-        match self.inode_manager.get(1) {
-            Some(entry) => {
-                if !entry.is_directory() {
-                    error!("Root inode (1) is not a directory");
-                    return Err(libc::EIO);
+    /// Copy a range between two files.
+    /// Copies torrent file data straight into the files `write` takes, so
+    /// `cp` of a `.torrent` file inside a torrent into the root costs no
+    /// round trip through user space. Other copies fall back to the
+    /// kernel's read and write.
+    fn copy_file_range(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino_in: u64,
+        fh_in: u64,
+        offset_in: i64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+        _flags: u32,
+        reply: fuser::ReplyWrite,
+    ) {
+        let _trace = request_id::begin("copy_file_range");
+        self.activity.touch_read();
+        let result = u64::try_from(offset_in)
+            .map_err(|_| libc::EINVAL)
+            .and_then(|offset_in| {
+                self.copy_into_writable(
+                    req.uid(),
+                    fh_in,
+                    offset_in,
+                    ino_out,
+                    fh_out,
+                    offset_out,
+                    len,
+                )
+            });
+        match result {
+            Ok(copied) => {
+                if copied > 0 {
+                    self.audit(req, ino_in, Some((offset_in as u64, copied as u64)));
                 }
+                reply.written(copied)
             }
-            None => {
-                error!("Root inode (1) not found - inode manager not properly initialized");
-                return Err(libc::EIO);
+            Err(errno) => {
+                if errno != libc::EOPNOTSUPP {
+                    self.metrics.record_error();
+                }
+                tracing::debug!(fuse_op = "copy_file_range", result = "error", errno);
+                reply.error(errno)
             }
         }
-
-        // Start the background torrent discovery task
-        self.start_torrent_discovery();
-
-        self.initialized = true;
-        info!("rqbit-fuse filesystem initialized successfully");
-
-        Ok(())
     }
 
-    /// Clean up filesystem.
-    /// Called on unmount.
-    fn destroy(&mut self) {
-        info!("Shutting down rqbit-fuse filesystem");
-        self.initialized = false;
-        // Stop the torrent discovery task
-        self.stop_torrent_discovery();
-        // Clean up any resources
+    /// Flush an open file on close.
+    /// Adds the torrent of a last line written to the add file without a
+    /// newline, as `printf` leaves it, and of a `.torrent` file copied in.
+    fn flush(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        _lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let result = if self.pending_links.contains_key(&fh) {
+            let _trace = request_id::begin("flush");
+            trace!("flush: ino={}, fh={}", ino, fh);
+            self.add_written_links(fh, &[], true)
+        } else if self.torrent_uploads.contains_key(&fh) {
+            let _trace = request_id::begin("flush");
+            trace!("flush: ino={}, fh={}", ino, fh);
+            self.add_torrent_upload(fh)
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
     }
 
-    /// Get filesystem statistics.
-    /// Returns information about the filesystem such as total space, free space, etc.
-    fn statfs(&mut self, _req: &fuser::Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
-        let inode_count = self.inode_manager.len() as u64;
-
-        reply.statfs(0, 0, 0, inode_count, inode_count, 4096, 255, 4096);
+    /// Set file attributes.
+    /// Only truncating to empty is accepted, and only for the add file,
+    /// which `>` redirections truncate before writing, and for `.torrent`
+    /// files being copied in.
+    fn setattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<fuser::TimeOrNow>,
+        _mtime: Option<fuser::TimeOrNow>,
+        _ctime: Option<std::time::SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<std::time::SystemTime>,
+        _chgtime: Option<std::time::SystemTime>,
+        _bkuptime: Option<std::time::SystemTime>,
+        _flags: Option<u32>,
+        reply: fuser::ReplyAttr,
+    ) {
+        let Some(entry) = self.inode_manager.get(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+        if !is_writable(&entry) || mode.is_some() || uid.is_some() || gid.is_some() {
+            reply.error(libc::EROFS);
+            return;
+        }
+        if size.is_some_and(|size| size > 0) {
+            reply.error(libc::EINVAL);
+            return;
+        }
+        if let (Some(_), Some(fh)) = (size, fh) {
+            if let Some(mut contents) = self.torrent_uploads.get_mut(&fh) {
+                contents.clear();
+            }
+        }
+        reply.attr(&self.entry_ttl(), &self.build_file_attr(&entry));
     }
 
-    /// Check file access permissions.
-    /// This is called for the access() system call.
-    /// Since this is a read-only filesystem:
-    /// - R_OK (read) is allowed if the inode exists
-    /// - W_OK (write) is always denied (read-only filesystem)
-    /// - X_OK (execute) is allowed for directories (to traverse), denied for files
-    fn access(&mut self, _req: &fuser::Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
-        const W_OK: i32 = 2;
-        const X_OK: i32 = 1;
-        const F_OK: i32 = 0;
-
-        if mask == F_OK {
-            if self.inode_manager.contains(ino) {
-                reply.ok();
-            } else {
-                reply.error(libc::ENOENT);
+    /// Create and open a file.
+    /// Only `.torrent` files can be created, at the root with
+    /// `add_torrents` set. They exist until closed, when their torrent is
+    /// added.
+    fn create(
+        &mut self,
+        req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        let _trace = request_id::begin("create");
+        self.activity.touch();
+        let name = name.to_string_lossy();
+        match self.create_torrent_upload(req.uid(), parent, &name, flags) {
+            Ok((entry, fh)) => reply.created(
+                &self.entry_ttl(),
+                &self.build_file_attr(&entry),
+                0,
+                fh,
+                fuser::consts::FOPEN_DIRECT_IO,
+            ),
+            Err(errno) => {
+                self.metrics.record_error();
+                reply.error(errno);
             }
-            return;
         }
+    }
 
-        if mask & W_OK != 0 {
-            reply.error(libc::EACCES);
+    /// Readahead advice for an open file. posix_fadvise(2) never reaches
+    /// FUSE, so it is sent as [`FADVISE_IOCTL`].
+    fn ioctl(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: u32,
+        cmd: u32,
+        in_data: &[u8],
+        _out_size: u32,
+        reply: fuser::ReplyIoctl,
+    ) {
+        if cmd != FADVISE_IOCTL {
+            reply.error(libc::ENOTTY);
             return;
         }
-
-        match self.inode_manager.get(ino) {
+        let Some(args) = FadviseArgs::from_bytes(in_data) else {
+            reply.error(libc::EINVAL);
+            return;
+        };
+        match self
+            .file_handles
+            .get_inode(fh)
+            .and_then(|ino| self.inode_manager.get(ino))
+        {
             Some(entry) => {
-                if entry.is_directory() {
-                    reply.ok();
-                } else if mask & X_OK != 0 {
-                    reply.error(libc::EACCES);
-                } else {
-                    reply.ok();
-                }
-            }
-            None => {
-                reply.error(libc::ENOENT);
+                self.advise(&entry, args);
+                reply.ioctl(0, &[]);
             }
+            None => reply.error(libc::EBADF),
         }
     }
-}
-
-impl TorrentFS {
-    /// Maximum read size for FUSE responses (64KB).
-    /// Matches rqbit's internal buffer size for optimal performance.
-    /// Benchmarks show 64KB provides best throughput without "Too much data" errors.
-    const FUSE_MAX_READ: u32 = 64 * 1024; // 64KB
-}
-
-/// Async initialization helper that can be called from the async runtime
-/// to perform the full initialization including the rqbit connection check.
-pub async fn initialize_filesystem(fs: &mut TorrentFS) -> Result<()> {
-    // Check connection to rqbit
-    fs.connect_to_rqbit().await?;
-    Ok(())
-}
 
-/// Discover and populate existing torrents from rqbit.
-/// This should be called before mounting to ensure all existing torrents
-/// appear in the filesystem.
-pub async fn discover_existing_torrents(fs: &TorrentFS) -> Result<()> {
-    info!("Discovering existing torrents from rqbit...");
+    /// Look up a directory entry by name.
+    /// Called when the kernel needs to resolve a path component to an inode.
+    #[instrument(skip(self, reply, name), fields(parent))]
+    fn lookup(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEntry,
+    ) {
+        self.activity.touch();
+        let name_str = name.to_string_lossy();
 
-    // Get list of all torrents from rqbit
-    let result = fs
-        .api_client
-        .list_torrents()
-        .await
-        .context("list torrents failed")?;
+        // Get the parent directory entry
+        let parent_entry = match self.inode_manager.get(parent) {
+            Some(entry) => entry,
+            None => {
+                self.metrics.record_error();
+                tracing::debug!(
+                    fuse_op = "lookup",
+                    result = "error",
+                    error = "ENOENT",
+                    ino = parent
+                );
+                reply.error(libc::ENOENT);
+                return;
+            }
+        };
 
-    // Log any partial failures
-    if !result.errors.is_empty() {
-        warn!(
-            "Partial torrent discovery: {} succeeded, {} failed",
-            result.torrents.len(),
-            result.errors.len()
-        );
-        for (id, name, err) in &result.errors {
-            warn!("Failed to load torrent {} ({}): {}", id, name, err);
+        // Check if parent is a directory
+        if !parent_entry.is_directory() {
+            self.metrics.record_error();
+            tracing::debug!(
+                fuse_op = "lookup",
+                result = "error",
+                error = "ENOTDIR",
+                ino = parent
+            );
+            reply.error(libc::ENOTDIR);
+            return;
         }
-    }
+        self.materialize_dir(parent);
 
-    if result.torrents.is_empty() {
-        info!("No existing torrents found in rqbit");
-        return Ok(());
-    }
+        // Handle special entries: "." and ".."
+        let target_ino = match name_str.as_ref() {
+            "." => {
+                // "." refers to the current directory (parent)
+                Some(parent)
+            }
+            ".." => {
+                // ".." refers to the parent directory
+                // Root directory's parent is itself
+                Some(parent_entry.parent())
+            }
+            _ => None,
+        };
 
-    info!(
-        "Found {} existing torrents, populating filesystem...",
-        result.torrents.len()
-    );
+        if let Some(ino) = target_ino {
+            if let Some(entry) = self.inode_manager.get(ino) {
+                let attr = self.build_file_attr(&entry);
+                self.inode_manager.add_lookup(attr.ino);
+                reply.entry(&self.entry_ttl(), &attr, self.inode_manager.generation());
+            } else {
+                // This shouldn't happen - special entry maps to non-existent inode
+                error!(
+                    fuse_op = "lookup",
+                    parent = parent,
+                    name = %name_str,
+                    target_ino = ino,
+                    "Special entry maps to missing inode"
+                );
+                reply.error(libc::EIO);
+            }
+            return;
+        }
 
-    let mut success_count = 0;
-    let mut error_count = 0;
+        // Build the full path for this entry
+        let path = if parent == 1 {
+            format!("/{}", name_str)
+        } else {
+            match self.inode_manager.get_path_for_inode(parent) {
+                Some(parent_path) => format!("{}/{}", parent_path, name_str),
+                None => {
+                    error!(
+                        fuse_op = "lookup",
+                        parent = parent,
+                        "Failed to build path for parent inode"
+                    );
+                    reply.error(libc::EIO);
+                    return;
+                }
+            }
+        };
 
-    for torrent_info in result.torrents {
-        // Check if we already have this torrent (avoid duplicates)
-        if fs.inode_manager.lookup_torrent(torrent_info.id).is_some() {
-            continue;
+        // Look up the inode by path
+        match self.inode_manager.lookup_by_path(&path) {
+            Some(ino) => {
+                match self.inode_manager.get(ino) {
+                    Some(entry) if self.is_hidden(&entry) => self.reply_not_found(reply),
+                    Some(entry) => {
+                        let attr = self.build_file_attr(&entry);
+                        self.inode_manager.add_lookup(attr.ino);
+                        reply.entry(&self.entry_ttl(), &attr, self.inode_manager.generation());
+                    }
+                    None => {
+                        // This shouldn't happen - path maps to non-existent inode
+                        self.metrics.record_error();
+
+                        error!(
+                            fuse_op = "lookup",
+                            path = %path,
+                            ino = ino,
+                            "Path maps to missing inode"
+                        );
+
+                        reply.error(libc::EIO);
+                    }
+                }
+            }
+            None => self.reply_not_found(reply),
         }
+    }
 
-        // Create filesystem structure for this torrent
-        match fs.create_torrent_structure(&torrent_info) {
-            Ok(()) => {
-                success_count += 1;
+    /// Get file attributes.
+    /// Called when the kernel needs to get attributes for a file or directory.
+    /// This is a fundamental operation used by ls, stat, and most file operations.
+    fn getattr(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyAttr) {
+        self.activity.touch();
+        // Get the inode entry
+        match self.inode_manager.get(ino) {
+            Some(entry) => {
+                let attr = self.build_file_attr(&entry);
+                reply.attr(&self.entry_ttl(), &attr);
             }
-            Err(e) => {
-                error_count += 1;
-                warn!(
-                    "Failed to create filesystem structure for torrent {} ({}): {}",
-                    torrent_info.id, torrent_info.name, e
+            None => {
+                self.metrics.record_error();
+                tracing::debug!(
+                    fuse_op = "getattr",
+                    result = "error",
+                    error = "ENOENT",
+                    ino = ino
                 );
+                reply.error(libc::ENOENT);
             }
         }
     }
 
-    info!(
-        "Finished discovering torrents: {} successful, {} failed, {} total",
-        success_count,
-        error_count,
-        success_count + error_count
-    );
-
-    Ok(())
-}
+    /// Open a file.
+    /// Called when the kernel needs to open a file for reading.
+    /// Returns a file handle that will be used in subsequent read operations.
+    fn open(&mut self, req: &fuser::Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        self.activity.touch();
+        // Check if the inode exists
+        match self.inode_manager.get(ino) {
+            Some(entry) => {
+                // Check if it's a file (not a directory)
+                if entry.is_directory() {
+                    self.metrics.record_error();
+                    reply.error(libc::EISDIR);
+                    return;
+                }
 
-/// Torrent addition flow implementation
-impl TorrentFS {
-    /// Adds a torrent from a magnet link and creates the filesystem structure.
-    /// Returns the torrent ID if successful.
-    pub async fn add_torrent_magnet(&self, magnet_link: &str) -> Result<u64> {
-        // First, add the torrent to rqbit
-        let response = self
-            .api_client
-            .add_torrent_magnet(magnet_link)
-            .await
-            .context("add magnet failed")?;
+                // Check if it's a symlink (symlinks should be resolved before open)
+                if entry.is_symlink() {
+                    self.metrics.record_error();
+                    reply.error(libc::ELOOP);
+                    return;
+                }
 
-        info!(
-            "Added torrent {} with hash {}",
-            response.id, response.info_hash
-        );
+                // Check write access - this is a read-only filesystem, but
+                // for the add file
+                let access_mode = flags & libc::O_ACCMODE;
+                if access_mode != libc::O_RDONLY && !is_writable(&entry) {
+                    self.metrics.record_error();
+                    reply.error(libc::EACCES);
+                    return;
+                }
 
-        // Check for duplicate torrent
-        if self.inode_manager.lookup_torrent(response.id).is_some() {
-            warn!(
-                "Torrent {} already exists in filesystem, skipping structure creation",
-                response.id
-            );
-            return Ok(response.id);
-        }
+                // Get torrent_id from the entry
+                let torrent_id = entry.torrent_id().unwrap_or(0);
 
-        // Get torrent details to build the file structure
-        let torrent_info = self
-            .api_client
-            .get_torrent(response.id)
-            .await
-            .context("get torrent failed")?;
+                // Allocate a unique file handle, making room by evicting
+                // idle handles once a limit is reached
+                let mut fh = self
+                    .file_handles
+                    .allocate_for(req.uid(), ino, torrent_id, flags);
+                if fh == 0 {
+                    let evicted = self.evict_idle_handles();
+                    if !evicted.is_empty() {
+                        fh = self
+                            .file_handles
+                            .allocate_for(req.uid(), ino, torrent_id, flags);
+                        let fs = self.clone();
+                        tokio::spawn(async move { fs.close_orphaned_streams(&evicted).await });
+                    }
+                }
 
-        // Create the filesystem structure
-        self.create_torrent_structure(&torrent_info)
-            .context("create structure failed")?;
+                // Check if handle allocation failed (limit reached)
+                if fh == 0 {
+                    self.metrics.record_error();
+                    debug!(
+                        fuse_op = "open",
+                        result = "error",
+                        error = "EMFILE",
+                        uid = req.uid()
+                    );
+                    reply.error(libc::EMFILE);
+                    return;
+                }
+                self.metrics.record_handle_opened();
 
-        Ok(response.id)
+                self.audit(req, ino, None);
+                self.apply_readahead_profile(&entry);
+                self.prefetch_head_tail(&entry);
+                reply.opened(fh, self.open_flags(&entry));
+            }
+            None => {
+                self.metrics.record_error();
+                reply.error(libc::ENOENT);
+            }
+        }
     }
 
-    /// Adds a torrent from a torrent file URL and creates the filesystem structure.
-    /// Returns the torrent ID if successful.
-    pub async fn add_torrent_url(&self, torrent_url: &str) -> Result<u64> {
-        // First, add the torrent to rqbit
-        let response = self
-            .api_client
-            .add_torrent_url(torrent_url)
-            .await
-            .context("add URL failed")?;
-
-        info!(
-            "Added torrent {} with hash {}",
-            response.id, response.info_hash
-        );
+    /// Read the target of a symbolic link.
+    /// Called when the kernel needs to resolve a symlink target.
+    fn readlink(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyData) {
+        self.activity.touch();
+        match self.inode_manager.get(ino) {
+            Some(entry) => {
+                if let crate::types::InodeEntry::Symlink { target, .. } = entry {
+                    reply.data(target.as_bytes());
+                } else {
+                    reply.error(libc::EINVAL);
+                }
+            }
+            None => {
+                self.metrics.record_error();
+                reply.error(libc::ENOENT);
+            }
+        }
+    }
 
-        // Check for duplicate torrent
-        if self.inode_manager.lookup_torrent(response.id).is_some() {
-            warn!(
-                "Torrent {} already exists in filesystem, skipping structure creation",
-                response.id
-            );
-            return Ok(response.id);
+    /// Read directory entries.
+    /// Called when the kernel needs to list the contents of a directory.
+    /// For the root directory, this will also trigger a torrent discovery check.
+    #[instrument(skip(self, reply), fields(ino))]
+    fn readdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: fuser::ReplyDirectory,
+    ) {
+        self.activity.touch();
+        // Pick up torrents added since the last discovery
+        if ino == 1 && offset == 0 {
+            self.refresh_for_root_listing();
         }
 
-        // Get torrent details to build the file structure
-        let torrent_info = self
-            .api_client
-            .get_torrent(response.id)
-            .await
-            .context("get torrent failed")?;
-
-        // Create the filesystem structure
-        self.create_torrent_structure(&torrent_info)
-            .context("create structure failed")?;
-
-        Ok(response.id)
-    }
-
-    /// Creates the filesystem directory structure for a torrent.
-    /// For single-file torrents, the file is added directly to root.
-    /// For multi-file torrents, a directory is created with the torrent name.
-    ///
-    /// # Arguments
-    /// * `torrent_info` - The torrent metadata from rqbit API
-    ///
-    /// # Returns
-    /// * `Result<()>` - Ok if structure was created successfully
-    ///
-    /// # Errors
-    /// Returns an error if inode allocation fails
-    pub fn create_torrent_structure(
-        &self,
-        torrent_info: &crate::api::types::TorrentInfo,
-    ) -> Result<()> {
-        use std::collections::HashMap;
+        // Get the directory entry
+        let entry = match self.inode_manager.get(ino) {
+            Some(e) => e,
+            None => {
+                self.metrics.record_error();
+                reply.error(libc::ENOENT);
+                return;
+            }
+        };
 
-        let torrent_name = sanitize_filename(&torrent_info.name);
-        let torrent_id = torrent_info.id;
+        // Check if it's a directory
+        if !entry.is_directory() {
+            self.metrics.record_error();
+            reply.error(libc::ENOTDIR);
+            return;
+        }
+        self.materialize_dir(ino);
 
-        trace!(
-            "Creating structure for torrent {} ({} files)",
-            torrent_id,
-            torrent_info.files.len()
-        );
+        // If offset is 0, start from beginning; otherwise continue from offset
+        let mut current_offset = offset;
 
-        // Handle single-file torrents differently - add file directly to root
-        if torrent_info.files.len() == 1 {
-            let file_info = &torrent_info.files[0];
-            let file_name = if file_info.components.is_empty() {
-                // Use torrent name as filename if no components provided
-                torrent_name.clone()
-            } else {
-                sanitize_filename(file_info.components.last().unwrap())
-            };
+        // Always include . and .. entries
+        if current_offset == 0 {
+            if reply.add(ino, 1, fuser::FileType::Directory, ".") {
+                reply.ok();
+                return;
+            }
+            current_offset = 1;
+        }
 
-            // Create file entry directly under root
-            let file_inode = self.inode_manager.allocate_file(
-                file_name.clone(),
-                1, // parent is root
-                torrent_id,
-                0, // single file has index 0
-                file_info.length,
-            );
+        if current_offset == 1 {
+            let parent_ino = entry.parent();
+            if reply.add(parent_ino, 2, fuser::FileType::Directory, "..") {
+                reply.ok();
+                return;
+            }
+            current_offset = 2;
+        }
 
-            // Add to root's children
-            self.inode_manager.add_child(1, file_inode);
+        // File managers list the same large directories over and over
+        if let Some(listing) = self.cached_listing(ino) {
+            let start = listing
+                .partition_point(|(child_ino, ..)| readdir_cookie(*child_ino) <= current_offset);
+            for (child_ino, kind, name) in &listing[start..] {
+                if reply.add(*child_ino, readdir_cookie(*child_ino), *kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+            return;
+        }
 
-            // Track torrent mapping
-            self.inode_manager
-                .torrent_to_inode()
-                .insert(torrent_id, file_inode);
+        // Only the inode numbers are copied; entries are visited in place
+        // until the reply buffer is full, so large directories are listed
+        // without cloning every child on each call
+        let children = self.inode_manager.child_inodes(ino);
 
-            trace!(
-                "Created single-file entry {} (size: {})",
-                file_name,
-                file_info.length
-            );
-        } else {
-            // Multi-file torrent: create directory structure
-            let torrent_dir_inode =
-                self.inode_manager
-                    .allocate_torrent_directory(torrent_id, torrent_name.clone(), 1);
+        for &child_ino in children_after(&children, current_offset) {
+            let full = self.inode_manager.with_entry(child_ino, |child_entry| {
+                if self.is_hidden(child_entry) {
+                    return false;
+                }
+                reply.add(
+                    child_ino,
+                    readdir_cookie(child_ino),
+                    file_type(child_entry),
+                    child_entry.name(),
+                )
+            });
+            if full == Some(true) {
+                reply.ok();
+                return;
+            }
+        }
 
-            // Add torrent directory to root's children
-            self.inode_manager.add_child(1, torrent_dir_inode);
+        reply.ok();
+    }
 
-            // Track created directories to avoid duplicates
-            let mut created_dirs: HashMap<String, u64> = HashMap::new();
-            created_dirs.insert("".to_string(), torrent_dir_inode);
+    /// Create a directory.
+    /// This filesystem is read-only, so it always returns EROFS (read-only filesystem).
+    fn mkdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        _parent: u64,
+        _name: &std::ffi::OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: fuser::ReplyEntry,
+    ) {
+        reply.error(libc::EROFS);
+    }
 
-            // Process each file in the torrent
-            info!(
-                torrent_id = torrent_id,
-                file_count = torrent_info.files.len(),
-                "About to process files"
-            );
-            for (file_idx, file_info) in torrent_info.files.iter().enumerate() {
-                info!(torrent_id = torrent_id, file_idx = file_idx, file_name = %file_info.name, "Processing file");
-                self.create_file_entry(
-                    file_info,
-                    file_idx,
-                    torrent_id,
-                    torrent_dir_inode,
-                    &mut created_dirs,
-                    &torrent_name,
-                )?;
-            }
-            info!(torrent_id = torrent_id, "Finished processing all files");
+    /// Remove a directory.
+    /// Moves torrents to the trash when `trash_days` is set, or removes them
+    /// from rqbit with `remove_torrents`; otherwise the filesystem is
+    /// read-only and this returns EROFS.
+    fn rmdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _trace = request_id::begin("rmdir");
+        match self.remove_entry(parent, &name.to_string_lossy()) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
         }
+    }
 
-        info!(
-            "Created filesystem structure for torrent {} with {} files",
-            torrent_id,
-            torrent_info.files.len()
-        );
-
-        Ok(())
+    /// Rename an entry.
+    /// Only moves torrents into the trash and back to where they came from,
+    /// when `trash_days` is set.
+    fn rename(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        newparent: u64,
+        newname: &std::ffi::OsStr,
+        _flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _trace = request_id::begin("rename");
+        let Some(trash) = &self.trash else {
+            reply.error(libc::EROFS);
+            return;
+        };
+        if name != newname {
+            reply.error(libc::EPERM);
+            return;
+        }
+        match self.trash_rename(trash, parent, &name.to_string_lossy(), newparent) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
     }
 
-    /// Creates a file entry (and any necessary parent directories) for a torrent file.
-    fn create_file_entry(
-        &self,
-        file_info: &crate::api::types::FileInfo,
-        file_idx: usize,
-        torrent_id: u64,
-        torrent_dir_inode: u64,
-        created_dirs: &mut std::collections::HashMap<String, u64>,
-        _torrent_name: &str,
-    ) -> Result<()> {
-        let components = &file_info.components;
+    /// Remove a file.
+    /// Like `rmdir`, for single-file torrents.
+    fn unlink(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let _trace = request_id::begin("unlink");
+        let name_str = name.to_string_lossy();
+        trace!("unlink: parent={}, name={}", parent, name_str);
+        match self.remove_entry(parent, &name_str) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
 
-        if components.is_empty() {
-            debug!(
-                torrent_id = torrent_id,
-                file_idx = file_idx,
-                file_name = %file_info.name,
-                "create_file_entry: empty components, using file name as fallback"
-            );
-            // Use file_info.name as fallback when components is empty
-            let file_name = sanitize_filename(&file_info.name);
-            let file_inode = self.inode_manager.allocate_file(
-                file_name.clone(),
-                torrent_dir_inode,
-                torrent_id,
-                file_idx as u64,
-                file_info.length,
-            );
-            self.inode_manager.add_child(torrent_dir_inode, file_inode);
-            return Ok(());
+    /// Forget about an inode.
+    /// The kernel drops `nlookup` references obtained through lookup; inodes
+    /// of removed torrents are freed once no references remain.
+    fn forget(&mut self, _req: &fuser::Request<'_>, ino: u64, nlookup: u64) {
+        if self.inode_manager.forget(ino, nlookup) {
+            trace!(fuse_op = "forget", ino = ino, "Released removed inode");
         }
+    }
 
-        // Get torrent directory's canonical path for building full paths
-        let torrent_dir_path = self
-            .inode_manager
-            .get_path_for_inode(torrent_dir_inode)
-            .unwrap_or_else(|| "/".to_string());
+    /// Get extended attribute value.
+    /// Exposes torrent status information via extended attributes.
+    fn getxattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        name: &std::ffi::OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        // ls and cp probe these on every file; answer without any lookup
+        if is_kernel_xattr(name) {
+            reply.error(ENOATTR);
+            return;
+        }
 
-        // Build parent directories
-        let mut current_dir_inode = torrent_dir_inode;
-        let mut current_path = String::new();
+        let name_str = name.to_string_lossy();
+        trace!("getxattr: ino={}, name={}", ino, name_str);
 
-        // Process all components except the last one (which is the filename)
-        for dir_component in components.iter().take(components.len().saturating_sub(1)) {
-            if !current_path.is_empty() {
-                current_path.push('/');
+        if FILE_XATTRS.contains(&name_str.as_ref()) {
+            match self.inode_manager.get(ino) {
+                Some(entry) => match self.file_xattr(&entry, &name_str) {
+                    Some(value) => reply_xattr(reply, size, value.as_bytes()),
+                    None => reply.error(ENOATTR),
+                },
+                None => reply.error(libc::ENOENT),
             }
-            current_path.push_str(dir_component);
+            return;
+        }
 
-            // Check if this directory already exists
-            if let Some(&inode) = created_dirs.get(&current_path) {
-                current_dir_inode = inode;
-            } else {
-                // Create new directory with full canonical path
-                let dir_name = sanitize_filename(dir_component);
-                let full_canonical_path = if torrent_dir_path == "/" {
-                    format!("/{}", current_path)
-                } else {
-                    format!("{}/{}", torrent_dir_path, current_path)
-                };
-                let new_dir_inode = self.inode_manager.allocate(InodeEntry::Directory {
-                    ino: 0,
-                    name: dir_name.clone(),
-                    parent: current_dir_inode,
-                    children: DashSet::new(),
-                    canonical_path: full_canonical_path,
-                });
+        if name_str == PIECES_XATTR {
+            let Some(entry) = self.inode_manager.get(ino) else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+            match self.pieces_available(&entry) {
+                Ok(Some(count)) => reply_xattr(reply, size, count.to_string().as_bytes()),
+                Ok(None) => reply.error(ENOATTR),
+                Err(errno) => reply.error(errno),
+            }
+            return;
+        }
 
-                // Add to parent
-                self.inode_manager
-                    .add_child(current_dir_inode, new_dir_inode);
+        if !TORRENT_XATTRS.contains(&name_str.as_ref()) {
+            reply.error(ENOATTR);
+            return;
+        }
 
-                created_dirs.insert(current_path.clone(), new_dir_inode);
-                current_dir_inode = new_dir_inode;
+        let torrent_id = match self.inode_manager.get(ino) {
+            Some(InodeEntry::Symlink { .. }) => {
+                // Symlinks don't have torrent status
+                reply.error(ENOATTR);
+                return;
+            }
+            Some(_) => match self.torrent_id_for_inode(ino) {
+                Some(torrent_id) => torrent_id,
+                None => {
+                    // This directory is not associated with a torrent (e.g., subdirectory)
+                    reply.error(ENOATTR);
+                    return;
+                }
+            },
+            None => {
+                reply.error(libc::ENOENT);
+                return;
+            }
+        };
 
-                debug!(
-                    "Created directory {} at inode {}",
-                    current_path, new_dir_inode
-                );
+        let failure = self.failed_torrents.get(&torrent_id).map(|m| m.clone());
+        let value = match (name_str.as_ref(), failure) {
+            ("user.torrent.infohash", _) => match self.info_hashes.get(&torrent_id) {
+                Some(info_hash) => info_hash.clone(),
+                None => {
+                    reply.error(ENOATTR);
+                    return;
+                }
+            },
+            ("user.torrent.error", Some(message)) => message,
+            ("user.torrent.status", Some(_)) => "error".to_string(),
+            ("user.torrent.status", None) if self.awaiting_metadata.contains(&torrent_id) => {
+                "awaiting_metadata".to_string()
             }
-        }
+            // Status monitoring has been removed, return attribute not found
+            _ => {
+                reply.error(ENOATTR);
+                return;
+            }
+        };
+        reply_xattr(reply, size, value.as_bytes());
+    }
 
-        // Build parent directories
-        let mut current_dir_inode = torrent_dir_inode;
-        let mut current_path = String::new();
+    /// List extended attributes.
+    fn listxattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        // Check if inode exists
+        let Some(entry) = self.inode_manager.get(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
 
-        // Process all components except the last one (which is the filename)
-        for dir_component in components.iter().take(components.len().saturating_sub(1)) {
-            if !current_path.is_empty() {
-                current_path.push('/');
+        let mut attr_list = String::from("user.torrent.status\0");
+        let failed = self
+            .torrent_id_for_inode(ino)
+            .is_some_and(|torrent_id| self.failed_torrents.contains_key(&torrent_id));
+        if failed {
+            attr_list.push_str("user.torrent.error\0");
+        }
+        let has_info_hash = self
+            .torrent_id_for_inode(ino)
+            .is_some_and(|torrent_id| self.info_hashes.contains_key(&torrent_id));
+        if has_info_hash {
+            attr_list.push_str("user.torrent.infohash\0");
+        }
+        for name in FILE_XATTRS {
+            if self.file_xattr(&entry, name).is_some() {
+                attr_list.push_str(name);
+                attr_list.push('\0');
             }
-            current_path.push_str(dir_component);
+        }
+        // Listed without asking rqbit, whenever the file's pieces are known
+        let pieces_known = self
+            .file_xattr(&entry, "user.torrent.piece_start")
+            .is_some();
+        if pieces_known && !self.config.offline {
+            attr_list.push_str(PIECES_XATTR);
+            attr_list.push('\0');
+        }
+        reply_xattr(reply, size, attr_list.as_bytes());
+    }
 
-            // Check if this directory already exists
-            if let Some(&inode) = created_dirs.get(&current_path) {
-                current_dir_inode = inode;
-            } else {
-                // Create new directory
-                let dir_name = sanitize_filename(dir_component);
-                let new_dir_inode = self.inode_manager.allocate(InodeEntry::Directory {
-                    ino: 0,
-                    name: dir_name.clone(),
-                    parent: current_dir_inode,
-                    children: DashSet::new(),
-                    canonical_path: format!("/{}", current_path),
-                });
+    /// Initialize the filesystem.
+    /// Called when the filesystem is mounted. Sets up the connection to rqbit,
+    /// validates the mount point, and initializes the root inode.
+    fn init(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        config: &mut fuser::KernelConfig,
+    ) -> Result<(), libc::c_int> {
+        info!("Initializing rqbit-fuse filesystem");
 
-                // Add to parent
-                self.inode_manager
-                    .add_child(current_dir_inode, new_dir_inode);
+        self.configure_kernel(config);
 
-                created_dirs.insert(current_path.clone(), new_dir_inode);
-                current_dir_inode = new_dir_inode;
+        // Validate mount point
+        if let Err(e) = self.validate_mount_point() {
+            error!("Mount point validation failed: {}", e);
+            return Err(libc::EIO);
+        }
 
-                debug!(
-                    "Created directory {} at inode {}",
-                    current_path, new_dir_inode
-                );
+        // Check that root inode (inode 1) exists and is a directory
+        match self.inode_manager.get(1) {
+            Some(entry) => {
+                if !entry.is_directory() {
+                    error!("Root inode (1) is not a directory");
+                    return Err(libc::EIO);
+                }
+            }
+            None => {
+                error!("Root inode (1) not found - inode manager not properly initialized");
+                return Err(libc::EIO);
             }
         }
 
-        // Create the file entry
-        let file_name = components.last().unwrap();
-        let sanitized_name = sanitize_filename(file_name);
+        // Start the background torrent discovery task
+        if !self.config.offline {
+            self.start_torrent_discovery();
+        }
 
-        let file_inode = self.inode_manager.allocate_file(
-            sanitized_name,
-            current_dir_inode,
-            torrent_id,
-            file_idx as u64,
-            file_info.length,
+        self.initialized = true;
+        info!("rqbit-fuse filesystem initialized successfully");
+
+        Ok(())
+    }
+
+    /// Clean up filesystem.
+    /// Called on unmount.
+    fn destroy(&mut self) {
+        info!("Shutting down rqbit-fuse filesystem");
+        self.initialized = false;
+        // Stop the torrent discovery task
+        self.stop_torrent_discovery();
+        // Clean up any resources
+    }
+
+    /// Get filesystem statistics.
+    /// Returns information about the filesystem such as total space, free space, etc.
+    fn statfs(&mut self, _req: &fuser::Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
+        let (blocks, files, ffree) = self.statfs_values();
+
+        // Read-only: no free blocks. Names are limited to 255 bytes, which
+        // NFS clients take from here.
+        reply.statfs(blocks, 0, 0, files, ffree, 4096, 255, 4096);
+    }
+
+    /// Check file access permissions.
+    /// This is called for the access() system call.
+    /// Since this is a read-only filesystem, W_OK is always denied. Other
+    /// bits are checked against the configured uid/gid and modes for the
+    /// caller's uid and gid.
+    fn access(&mut self, req: &fuser::Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+        let Some(entry) = self.inode_manager.get(ino) else {
+            reply.error(libc::ENOENT);
+            return;
+        };
+
+        if mask == libc::F_OK {
+            reply.ok();
+            return;
+        }
+
+        if mask & libc::W_OK != 0 && !is_writable(&entry) {
+            reply.error(libc::EACCES);
+            return;
+        }
+
+        let attr = self.build_file_attr(&entry);
+        if Self::check_access(&attr, req.uid(), req.gid(), mask) {
+            reply.ok();
+        } else {
+            reply.error(libc::EACCES);
+        }
+    }
+}
+
+impl TorrentFS {
+    /// Maximum read size for FUSE responses (64KB).
+    /// Matches rqbit's internal buffer size for optimal performance.
+    /// Benchmarks show 64KB provides best throughput without "Too much data" errors.
+    const FUSE_MAX_READ: u32 = 64 * 1024; // 64KB
+}
+
+/// Async initialization helper that can be called from the async runtime
+/// to perform the full initialization including the rqbit connection check.
+pub async fn initialize_filesystem(fs: &mut TorrentFS) -> Result<()> {
+    // Check connection to rqbit
+    fs.connect_to_rqbit().await?;
+    Ok(())
+}
+
+/// Populates the filesystem from the metadata snapshot, for `offline`.
+fn load_snapshot(fs: &TorrentFS) -> Result<()> {
+    let path = fs.snapshot.path();
+    let snapshot = fs
+        .snapshot
+        .load()
+        .with_context(|| format!("Failed to read metadata snapshot {}", path.display()))?
+        .with_context(|| {
+            format!(
+                "No metadata snapshot of {} in {}; mount once while rqbit is reachable",
+                fs.config.api_url,
+                path.display()
+            )
+        })?;
+    info!(
+        "Offline: serving {} torrents from the snapshot saved at {} (Unix time)",
+        snapshot.torrents.len(),
+        snapshot.saved_at
+    );
+
+    for torrent_info in snapshot.torrents.iter().filter(|t| fs.selects_torrent(t)) {
+        if fs.inode_manager.lookup_torrent(torrent_info.id).is_some() {
+            continue;
+        }
+        if let Err(e) = fs.create_torrent_structure(torrent_info) {
+            warn!(
+                "Failed to create filesystem structure for torrent {} ({}): {}",
+                torrent_info.id, torrent_info.name, e
+            );
+        }
+    }
+    fs.update_recent_dir();
+    Ok(())
+}
+
+/// Discover and populate existing torrents from rqbit.
+/// This should be called before mounting to ensure all existing torrents
+/// appear in the filesystem.
+pub async fn discover_existing_torrents(fs: &TorrentFS) -> Result<()> {
+    if fs.config.offline {
+        return load_snapshot(fs);
+    }
+
+    info!("Discovering existing torrents from rqbit...");
+
+    // Get list of all torrents from rqbit
+    let result = fs
+        .api_client
+        .list_torrents()
+        .await
+        .context("list torrents failed")?;
+    if result.errors.is_empty() {
+        fs.save_snapshot(&result.torrents);
+    }
+
+    // Log any partial failures
+    if !result.errors.is_empty() {
+        warn!(
+            "Partial torrent discovery: {} succeeded, {} failed",
+            result.torrents.len(),
+            result.errors.len()
         );
+        for (id, name, err) in &result.errors {
+            warn!("Failed to load torrent {} ({}): {}", id, name, err);
+        }
+    }
+
+    if result.torrents.is_empty() {
+        info!("No existing torrents found in rqbit");
+        return Ok(());
+    }
+
+    info!(
+        "Found {} existing torrents, populating filesystem...",
+        result.torrents.len()
+    );
+
+    let mut success_count = 0;
+    let mut error_count = 0;
+    let mut selected = Vec::new();
+
+    // Skip torrents that don't belong in this mount, and duplicates
+    let torrents = result
+        .torrents
+        .into_iter()
+        .filter(|t| fs.selects_torrent(t))
+        .collect();
+    let (torrents, _) = fs.dedup_by_info_hash(torrents).await;
+
+    for torrent_info in torrents {
+        // Check if we already have this torrent (avoid duplicates)
+        if fs.inode_manager.lookup_torrent(torrent_info.id).is_some() {
+            continue;
+        }
+
+        // Create filesystem structure for this torrent, or retry at a
+        // later discovery
+        if fs.materialize_torrent(&torrent_info).await {
+            success_count += 1;
+        } else {
+            error_count += 1;
+        }
+        selected.push(torrent_info);
+    }
+
+    fs.update_recent_dir();
+
+    // Unfinished files must be hidden before the first lookup
+    if fs.config.hide_incomplete {
+        fs.refresh_torrent_stats(&selected).await;
+    }
+
+    info!(
+        "Finished discovering torrents: {} successful, {} failed, {} total",
+        success_count,
+        error_count,
+        success_count + error_count
+    );
+
+    Ok(())
+}
+
+/// Torrent addition flow implementation
+impl TorrentFS {
+    /// Adds a torrent from a magnet link and creates the filesystem structure.
+    /// Returns the torrent ID if successful.
+    pub async fn add_torrent_magnet(&self, magnet_link: &str) -> Result<u64> {
+        // First, add the torrent to rqbit
+        let response = self
+            .api_client
+            .add_torrent_magnet(magnet_link)
+            .await
+            .context("add magnet failed")?;
+
+        info!(
+            "Added torrent {} with hash {}",
+            response.id, response.info_hash
+        );
+
+        // Check for duplicate torrent
+        if self.inode_manager.lookup_torrent(response.id).is_some() {
+            warn!(
+                "Torrent {} already exists in filesystem, skipping structure creation",
+                response.id
+            );
+            return Ok(response.id);
+        }
+
+        // Get torrent details to build the file structure
+        let torrent_info = self
+            .api_client
+            .get_torrent(response.id)
+            .await
+            .context("get torrent failed")?;
+
+        // Create the filesystem structure
+        self.create_torrent_structure(&torrent_info)
+            .context("create structure failed")?;
+
+        Ok(response.id)
+    }
+
+    /// Adds a torrent from a torrent file URL and creates the filesystem structure.
+    /// Returns the torrent ID if successful.
+    pub async fn add_torrent_url(&self, torrent_url: &str) -> Result<u64> {
+        // First, add the torrent to rqbit
+        let response = self
+            .api_client
+            .add_torrent_url(torrent_url)
+            .await
+            .context("add URL failed")?;
+
+        info!(
+            "Added torrent {} with hash {}",
+            response.id, response.info_hash
+        );
+
+        // Check for duplicate torrent
+        if self.inode_manager.lookup_torrent(response.id).is_some() {
+            warn!(
+                "Torrent {} already exists in filesystem, skipping structure creation",
+                response.id
+            );
+            return Ok(response.id);
+        }
+
+        // Get torrent details to build the file structure
+        let torrent_info = self
+            .api_client
+            .get_torrent(response.id)
+            .await
+            .context("get torrent failed")?;
+
+        // Create the filesystem structure
+        self.create_torrent_structure(&torrent_info)
+            .context("create structure failed")?;
+
+        Ok(response.id)
+    }
+
+    /// Creates the filesystem directory structure for a torrent.
+    /// For single-file torrents, the file is added directly to root.
+    /// For multi-file torrents, a directory is created with the torrent name.
+    ///
+    /// # Arguments
+    /// * `torrent_info` - The torrent metadata from rqbit API
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if structure was created successfully
+    ///
+    /// # Errors
+    /// Returns an error if inode allocation fails
+    pub fn create_torrent_structure(
+        &self,
+        torrent_info: &crate::api::types::TorrentInfo,
+    ) -> Result<()> {
+        use std::collections::HashMap;
+
+        let torrent_id = torrent_info.id;
+        let torrent_name = self.torrent_dir_name(torrent_info);
+
+        let size = match torrent_info.deferred_size {
+            Some(size) => size,
+            None => torrent_info.files.iter().map(|f| f.length).sum(),
+        };
+        self.torrent_sizes.insert(torrent_id, size);
+        if let Some(layout) = PieceLayout::of(torrent_info) {
+            self.piece_layouts.insert(torrent_id, layout);
+        }
+
+        if torrent_info.files.is_empty() && torrent_info.deferred_size.is_none() {
+            // Shown as an empty directory until discovery sees the metadata
+            debug!("Torrent {} is waiting for metadata", torrent_id);
+            self.awaiting_metadata.insert(torrent_id);
+        }
+
+        trace!(
+            "Creating structure for torrent {} ({} files)",
+            torrent_id,
+            torrent_info.files.len()
+        );
+
+        // Torrents sit at the root, or under their tag directory, unless
+        // they are in the trash
+        let mut parent_inode = self.torrent_parent_inode(torrent_info);
+        self.info_hashes
+            .insert(torrent_id, torrent_info.info_hash.clone());
+        self.create_stats_file(torrent_id, &torrent_info.info_hash);
+        if let Some(trash) = &self.trash {
+            if trash.contains(&torrent_info.info_hash) {
+                self.trash_homes.insert(torrent_id, parent_inode);
+                parent_inode = self.ensure_root_directory(TRASH_DIR.to_string());
+            }
+        }
+
+        // Handle single-file torrents differently - add file directly to root
+        if torrent_info.files.len() == 1 {
+            let file_info = &torrent_info.files[0];
+            // The torrent name is used if no components are provided
+            let file_name = self.top_level_name(torrent_info);
+
+            // Create file entry directly under root
+            let file_inode = self.inode_manager.allocate_file(
+                file_name.clone(),
+                parent_inode,
+                torrent_id,
+                0, // single file has index 0
+                file_info.length,
+            );
+
+            if file_inode == 0 {
+                anyhow::bail!("inode limit reached creating {}", file_name);
+            }
+
+            // Add to root's children
+            self.inode_manager.add_child(parent_inode, file_inode);
+
+            // Track torrent mapping
+            self.inode_manager
+                .torrent_to_inode()
+                .insert(torrent_id, file_inode);
+
+            trace!(
+                "Created single-file entry {} (size: {})",
+                file_name,
+                file_info.length
+            );
+        } else {
+            // Multi-file torrent: create directory structure, or use the root
+            // itself when mounting a single torrent
+            let torrent_dir_inode = if self.single_torrent_mode() {
+                self.inode_manager.torrent_to_inode().insert(torrent_id, 1);
+                1
+            } else {
+                let torrent_dir_inode = self.inode_manager.allocate_torrent_directory(
+                    torrent_id,
+                    torrent_name.clone(),
+                    parent_inode,
+                );
+                if torrent_dir_inode == 0 {
+                    anyhow::bail!("inode limit reached creating {}", torrent_name);
+                }
+
+                // Add torrent directory to root's children
+                self.inode_manager
+                    .add_child(parent_inode, torrent_dir_inode);
+                torrent_dir_inode
+            };
+            self.create_status_dir(torrent_id, torrent_dir_inode);
+
+            if torrent_info.deferred_size.is_some() {
+                self.deferred_files.insert(torrent_dir_inode, torrent_id);
+                debug!(
+                    "Deferred file list of torrent {} ({:?} files) until first access",
+                    torrent_id, torrent_info.file_count
+                );
+                return Ok(());
+            }
+
+            let threshold = self.config.lazy_tree_threshold;
+            if threshold > 0 && torrent_info.files.len() >= threshold {
+                self.pending_dirs.insert(
+                    torrent_dir_inode,
+                    PendingDir {
+                        torrent_id,
+                        files: torrent_info.files.clone().into(),
+                        prefix: Vec::new(),
+                    },
+                );
+                debug!(
+                    "Deferred structure of torrent {} ({} files) until first access",
+                    torrent_id,
+                    torrent_info.files.len()
+                );
+                return Ok(());
+            }
+
+            // Track created directories to avoid duplicates
+            let mut created_dirs: HashMap<String, u64> = HashMap::new();
+            created_dirs.insert("".to_string(), torrent_dir_inode);
+
+            // Process each file in the torrent
+            info!(
+                torrent_id = torrent_id,
+                file_count = torrent_info.files.len(),
+                "About to process files"
+            );
+            for (file_idx, file_info) in torrent_info.files.iter().enumerate() {
+                info!(torrent_id = torrent_id, file_idx = file_idx, file_name = %file_info.name, "Processing file");
+                let path = if file_info.components.is_empty() {
+                    std::slice::from_ref(&file_info.name)
+                } else {
+                    file_info.components.as_slice()
+                };
+                if self.filter.hides_file(path) {
+                    trace!(torrent_id, file_idx, "Hidden by filter.hide_files");
+                    continue;
+                }
+                self.create_file_entry(
+                    file_info,
+                    file_idx,
+                    torrent_id,
+                    torrent_dir_inode,
+                    &mut created_dirs,
+                    &torrent_name,
+                )?;
+            }
+            info!(torrent_id = torrent_id, "Finished processing all files");
+        }
+
+        info!(
+            "Created filesystem structure for torrent {} with {} files",
+            torrent_id,
+            torrent_info.files.len()
+        );
+
+        Ok(())
+    }
+
+    /// Returns the torrent a file belongs to, or the torrent whose top-level
+    /// directory `ino` is.
+    fn torrent_id_for_inode(&self, ino: u64) -> Option<u64> {
+        match self.inode_manager.get(ino)? {
+            InodeEntry::File { torrent_id, .. } => Some(torrent_id),
+            InodeEntry::Directory { .. } => self
+                .inode_manager
+                .torrent_to_inode()
+                .iter()
+                .find(|item| *item.value() == ino)
+                .map(|item| *item.key()),
+            InodeEntry::Virtual { .. } | InodeEntry::Symlink { .. } => None,
+        }
+    }
+
+    /// Creates the children of a lazily built directory the first time it is
+    /// looked up or listed. Subdirectories are themselves left pending.
+    fn materialize_dir(&self, ino: u64) {
+        self.fetch_deferred_files(ino);
+        let Some((_, pending)) = self.pending_dirs.remove(&ino) else {
+            return;
+        };
+        let depth = pending.prefix.len();
+        let parent_path = self
+            .inode_manager
+            .get_path_for_inode(ino)
+            .unwrap_or_else(|| "/".to_string());
+        let mut subdirs = std::collections::HashSet::new();
+
+        for (file_idx, file_info) in pending.files.iter().enumerate() {
+            let components = if file_info.components.is_empty() {
+                std::slice::from_ref(&file_info.name)
+            } else {
+                file_info.components.as_slice()
+            };
+            if components.len() <= depth
+                || components[..depth] != pending.prefix[..]
+                || self.filter.hides_file(components)
+            {
+                continue;
+            }
+
+            let name = &components[depth];
+            if components.len() == depth + 1 {
+                let file_inode = self.inode_manager.allocate_file(
+                    sanitize_filename(name),
+                    ino,
+                    pending.torrent_id,
+                    file_idx as u64,
+                    file_info.length,
+                );
+                self.inode_manager.add_child(ino, file_inode);
+            } else if subdirs.insert(name.as_str()) {
+                let dir_name = sanitize_filename(name);
+                let canonical_path = if parent_path == "/" {
+                    format!("/{}", dir_name)
+                } else {
+                    format!("{}/{}", parent_path, dir_name)
+                };
+                let dir_inode = self.inode_manager.allocate(InodeEntry::Directory {
+                    ino: 0,
+                    name: dir_name,
+                    parent: ino,
+                    children: DashSet::new(),
+                    canonical_path,
+                });
+                self.inode_manager.add_child(ino, dir_inode);
+
+                let mut prefix = pending.prefix.clone();
+                prefix.push(name.clone());
+                self.pending_dirs.insert(
+                    dir_inode,
+                    PendingDir {
+                        torrent_id: pending.torrent_id,
+                        files: Arc::clone(&pending.files),
+                        prefix,
+                    },
+                );
+            }
+        }
+
+        trace!(
+            "Materialized directory {} of torrent {}",
+            ino,
+            pending.torrent_id
+        );
+    }
+
+    /// Fetches the file list of a torrent listed from its summary alone the
+    /// first time its directory is accessed, leaving the directory to be
+    /// built lazily. On failure the directory stays empty until the next
+    /// access.
+    fn fetch_deferred_files(&self, ino: u64) {
+        let Some(torrent_id) = self.deferred_files.get(&ino).map(|id| *id) else {
+            return;
+        };
+        let api_client = Arc::clone(&self.api_client);
+        let (tx, rx) = std::sync::mpsc::channel();
+        tokio::spawn(async move {
+            let _ = tx.send(api_client.get_torrent(torrent_id).await);
+        });
+        let torrent_info = match rx.recv_timeout(Duration::from_secs(self.config.read_timeout)) {
+            Ok(Ok(torrent_info)) => torrent_info,
+            Ok(Err(e)) => {
+                warn!("Failed to fetch files of torrent {}: {}", torrent_id, e);
+                return;
+            }
+            Err(_) => {
+                warn!("Timed out fetching files of torrent {}", torrent_id);
+                return;
+            }
+        };
+
+        // Another lookup may have fetched them meanwhile
+        if self.deferred_files.remove(&ino).is_none() {
+            return;
+        }
+        debug!(
+            "Fetched {} files of torrent {}",
+            torrent_info.files.len(),
+            torrent_id
+        );
+        if let Some(layout) = PieceLayout::of(&torrent_info) {
+            self.piece_layouts.insert(torrent_id, layout);
+        }
+        self.pending_dirs.insert(
+            ino,
+            PendingDir {
+                torrent_id,
+                files: torrent_info.files.into(),
+                prefix: Vec::new(),
+            },
+        );
+    }
+
+    /// Returns the directory a torrent's top-level entry goes in: its tag
+    /// directory when `filter.tag_directories` is set, otherwise the root,
+    /// and below that its output folder's directories when
+    /// `filter.output_folder_root` is set.
+    fn torrent_parent_inode(&self, torrent_info: &crate::api::types::TorrentInfo) -> u64 {
+        if self.single_torrent_mode() {
+            return 1;
+        }
+        let parent = match self.filter.directory_tag(torrent_info) {
+            Some(tag) => self.ensure_tag_directory(tag),
+            None => 1,
+        };
+        self.filter
+            .folder_directories(torrent_info)
+            .into_iter()
+            .fold(parent, |parent, name| {
+                self.ensure_directory(parent, sanitize_filename(name))
+            })
+    }
+
+    /// Returns the inode of the top-level directory for `tag`, creating it if needed.
+    fn ensure_tag_directory(&self, tag: &str) -> u64 {
+        self.ensure_root_directory(sanitize_filename(tag))
+    }
+
+    /// Returns the inode of the top-level directory `name`, creating it if needed.
+    fn ensure_root_directory(&self, name: String) -> u64 {
+        self.ensure_directory(1, name)
+    }
+
+    /// Returns the inode of directory `name` in `parent`, creating it if needed.
+    fn ensure_directory(&self, parent: u64, name: String) -> u64 {
+        let path = match self.inode_manager.get_path_for_inode(parent) {
+            Some(parent_path) if parent_path != "/" => format!("{}/{}", parent_path, name),
+            _ => format!("/{}", name),
+        };
+        if let Some(inode) = self.inode_manager.lookup_by_path(&path) {
+            return inode;
+        }
+
+        let inode = self.inode_manager.allocate(InodeEntry::Directory {
+            ino: 0,
+            name,
+            parent,
+            children: DashSet::new(),
+            canonical_path: path.clone(),
+        });
+        self.inode_manager.add_child(parent, inode);
+        debug!("Created directory {} at inode {}", path, inode);
+        inode
+    }
+
+    /// Points the symlinks in `recent_dir` at the `recent_count` most
+    /// recently added torrents, numbered newest first so a listing sorted by
+    /// name shows them in that order. rqbit numbers torrents in the order
+    /// they were added, so the highest ids are the newest. Trashed torrents
+    /// are left out.
+    fn update_recent_dir(&self) {
+        let count = self.config.recent_count;
+        if count == 0 || self.single_torrent_mode() {
+            return;
+        }
+        let trash_dir = self
+            .inode_manager
+            .lookup_by_path(&format!("/{}", TRASH_DIR));
+        let mut torrents: Vec<(u64, u64)> = self
+            .inode_manager
+            .torrent_to_inode()
+            .iter()
+            .map(|item| (*item.key(), *item.value()))
+            .collect();
+        torrents.sort_unstable_by_key(|&(torrent_id, _)| std::cmp::Reverse(torrent_id));
+
+        let width = count.to_string().len();
+        let mut links = HashMap::new();
+        for (_, ino) in torrents {
+            if links.len() == count {
+                break;
+            }
+            let Some(entry) = self.inode_manager.get(ino) else {
+                continue;
+            };
+            if trash_dir.is_some_and(|trash| entry.parent() == trash) {
+                continue;
+            }
+            let Some(path) = self.inode_manager.get_path_for_inode(ino) else {
+                continue;
+            };
+            let rank = links.len() + 1;
+            links.insert(
+                format!("{:0width$} {}", rank, entry.name(), width = width),
+                format!("..{}", path),
+            );
+        }
+
+        // Unchanged links keep their inodes; the others are replaced
+        let dir = self.ensure_root_directory(self.config.recent_dir.clone());
+        for (ino, entry) in self.inode_manager.get_children(dir) {
+            if let InodeEntry::Symlink { name, target, .. } = &entry {
+                if links.get(name) == Some(target) {
+                    links.remove(name);
+                    continue;
+                }
+            }
+            self.inode_manager.remove_inode(ino);
+            self.invalidate_entry(dir, entry.name());
+        }
+        for (name, target) in links {
+            let ino = self.inode_manager.allocate_symlink(name, dir, target);
+            self.inode_manager.add_child(dir, ino);
+        }
+    }
+
+    /// Adds a torrent's stats file to `/.rqbit-fuse/torrents` when
+    /// `stats_files` is set.
+    fn create_stats_file(&self, torrent_id: u64, info_hash: &str) {
+        if !self.config.stats_files {
+            return;
+        }
+        let control_dir = self.ensure_root_directory(CONTROL_DIR.to_string());
+        let torrents_dir = self.ensure_directory(control_dir, TORRENTS_DIR.to_string());
+        let name = stats_file_name(info_hash);
+        if self.child_inode(torrents_dir, &name).is_some() {
+            return;
+        }
+        let inode = self.inode_manager.allocate_virtual_file(
+            name,
+            torrents_dir,
+            torrent_id,
+            VirtualFile::TorrentStats,
+        );
+        if inode != 0 {
+            self.inode_manager.add_child(torrents_dir, inode);
+        }
+    }
+
+    /// Adds the `.rqbit-fuse` directory of [`STATUS_FILES`] to a torrent's
+    /// directory when `status_dirs` is set.
+    fn create_status_dir(&self, torrent_id: u64, torrent_dir: u64) {
+        if !self.config.status_dirs {
+            return;
+        }
+        let status_dir = self.ensure_directory(torrent_dir, CONTROL_DIR.to_string());
+        for (name, file) in STATUS_FILES {
+            if self.child_inode(status_dir, name).is_some() {
+                continue;
+            }
+            let inode = self.inode_manager.allocate_virtual_file(
+                name.to_string(),
+                status_dir,
+                torrent_id,
+                file,
+            );
+            if inode != 0 {
+                self.inode_manager.add_child(status_dir, inode);
+            }
+        }
+    }
+
+    /// Adds `/.rqbit-fuse/add` for `add_torrents`.
+    fn create_add_file(&self) {
+        let control_dir = self.ensure_root_directory(CONTROL_DIR.to_string());
+        if self.child_inode(control_dir, ADD_FILE).is_some() {
+            return;
+        }
+        let inode = self.inode_manager.allocate_virtual_file(
+            ADD_FILE.to_string(),
+            control_dir,
+            0,
+            VirtualFile::AddTorrent,
+        );
+        if inode != 0 {
+            self.inode_manager.add_child(control_dir, inode);
+        }
+    }
+
+    /// Adds the torrents of the complete lines written to the add file
+    /// through `fh`, keeping the rest for the next write; with `flush`, of
+    /// the rest as well. Stops at the first link that can't be added.
+    fn add_written_links(&self, fh: u64, data: &[u8], flush: bool) -> Result<(), i32> {
+        let written = {
+            let mut pending = self.pending_links.entry(fh).or_default();
+            pending.extend_from_slice(data);
+            let end = if flush {
+                pending.len()
+            } else {
+                pending
+                    .iter()
+                    .rposition(|&b| b == b'\n')
+                    .map_or(0, |newline| newline + 1)
+            };
+            pending.drain(..end).collect::<Vec<u8>>()
+        };
+        let written = String::from_utf8(written).map_err(|_| libc::EINVAL)?;
+        written
+            .lines()
+            .map(str::trim)
+            .filter(|link| !link.is_empty())
+            .try_for_each(|link| self.add_link(link))
+    }
+
+    /// Adds a torrent from a magnet link or torrent URL, and its entry
+    /// unless the filter leaves it out. Waits for rqbit to accept it, so a
+    /// rejected link fails the write.
+    fn add_link(&self, link: &str) -> Result<(), i32> {
+        if !["magnet:", "http://", "https://"]
+            .iter()
+            .any(|scheme| link.starts_with(scheme))
+        {
+            warn!("Not adding {:?}: not a magnet link or torrent URL", link);
+            return Err(libc::EINVAL);
+        }
+        let timeout = Duration::from_secs(30);
+        let torrent_info = self
+            .async_worker
+            .add_torrent(link.to_string(), timeout)
+            .map_err(|e| {
+                warn!("Failed to add torrent {:?}: {}", link, e);
+                e.to_errno()
+            })?;
+        info!("Added torrent {}: {}", torrent_info.id, torrent_info.name);
+        self.show_added_torrent(&torrent_info);
+        Ok(())
+    }
+
+    /// Creates a `.torrent` file being copied into the root and opens it for
+    /// `uid`, returning its entry and file handle.
+    fn create_torrent_upload(
+        &self,
+        uid: u32,
+        parent: u64,
+        name: &str,
+        flags: i32,
+    ) -> Result<(InodeEntry, u64), i32> {
+        if !self.config.add_torrents
+            || self.config.offline
+            || parent != 1
+            || !name.to_ascii_lowercase().ends_with(".torrent")
+        {
+            return Err(libc::EROFS);
+        }
+        if self.child_inode(parent, name).is_some() {
+            return Err(libc::EEXIST);
+        }
+
+        let ino = self.inode_manager.allocate_virtual_file(
+            name.to_string(),
+            parent,
+            0,
+            VirtualFile::TorrentUpload,
+        );
+        if ino == 0 {
+            return Err(libc::ENOSPC);
+        }
+        self.inode_manager.add_child(parent, ino);
+        let fh = self.file_handles.allocate_for(uid, ino, 0, flags);
+        if fh == 0 {
+            self.inode_manager.remove_inode(ino);
+            return Err(libc::EMFILE);
+        }
+        self.metrics.record_handle_opened();
+        self.torrent_uploads.insert(fh, Vec::new());
+        debug!(fuse_op = "create", ino, fh, "Copying in {}", name);
+        let entry = self.inode_manager.get(ino).ok_or(libc::ENOENT)?;
+        Ok((entry, fh))
+    }
+
+    /// Places data written to a `.torrent` file being copied in.
+    fn write_torrent_upload(&self, fh: u64, offset: i64, data: &[u8]) -> Result<(), i32> {
+        let start = usize::try_from(offset).map_err(|_| libc::EINVAL)?;
+        let end = start + data.len();
+        if end > MAX_TORRENT_FILE_SIZE {
+            return Err(libc::EFBIG);
+        }
+        let mut contents = self.torrent_uploads.entry(fh).or_default();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[start..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Copies up to `len` bytes of the torrent file open as `fh_in` into the
+    /// add file or a `.torrent` file being copied in, returning how many
+    /// were copied. Copies into any other file are left to the kernel
+    /// (EOPNOTSUPP).
+    #[allow(clippy::too_many_arguments)]
+    fn copy_into_writable(
+        &self,
+        uid: u32,
+        fh_in: u64,
+        offset_in: u64,
+        ino_out: u64,
+        fh_out: u64,
+        offset_out: i64,
+        len: u64,
+    ) -> Result<u32, i32> {
+        let file = match self.inode_manager.get(ino_out) {
+            Some(InodeEntry::Virtual { file, .. })
+                if matches!(file, VirtualFile::AddTorrent | VirtualFile::TorrentUpload) =>
+            {
+                file
+            }
+            _ => return Err(libc::EOPNOTSUPP),
+        };
+        let ino_in = self.file_handles.get_inode(fh_in).ok_or(libc::EBADF)?;
+        let Some(InodeEntry::File {
+            torrent_id,
+            file_index,
+            size,
+            ..
+        }) = self.inode_manager.get(ino_in)
+        else {
+            return Err(libc::EOPNOTSUPP);
+        };
+        if self.config.offline {
+            return Err(libc::ENETDOWN);
+        }
+        if let Some(message) = self.failed_torrents.get(&torrent_id) {
+            return Err(crate::error::RqbitFuseError::TorrentFailed(message.clone()).to_errno());
+        }
+        if len == 0 || offset_in >= size {
+            return Ok(0);
+        }
+        let now = std::time::SystemTime::now();
+        if !self.quota.allows(uid, now) {
+            return Err(libc::EDQUOT);
+        }
+
+        let len = len.min(size - offset_in).min(Self::FUSE_MAX_READ as u64) as usize;
+        let mode = if self.config.piece_check_enabled {
+            ReadMode::Verified
+        } else {
+            ReadMode::Full
+        };
+        let data = self
+            .async_worker
+            .read_file(
+                torrent_id,
+                file_index,
+                offset_in,
+                len,
+                Duration::from_secs(self.config.read_timeout),
+                mode,
+            )
+            .map_err(|e| e.to_errno())?;
+        let data = &data[..data.len().min(len)];
+        self.metrics.record_read(data.len() as u64);
+        self.quota.record(uid, data.len() as u64, now);
+
+        match file {
+            VirtualFile::AddTorrent => self.add_written_links(fh_out, data, false)?,
+            _ => self.write_torrent_upload(fh_out, offset_out, data)?,
+        }
+        debug!(
+            fuse_op = "copy_file_range",
+            fh_in,
+            fh_out,
+            torrent_id,
+            "Copied {} bytes",
+            data.len()
+        );
+        Ok(data.len() as u32)
+    }
+
+    /// Adds the torrent of the `.torrent` file written through `fh`, and
+    /// its entry unless the filter leaves it out. Nothing written adds
+    /// nothing, so `touch` is harmless.
+    fn add_torrent_upload(&self, fh: u64) -> Result<(), i32> {
+        let Some((_, contents)) = self.torrent_uploads.remove(&fh) else {
+            return Ok(());
+        };
+        if contents.is_empty() {
+            return Ok(());
+        }
+        if let Err(e) = crate::api::metainfo::PieceHashes::parse(&contents) {
+            warn!("Not adding a torrent file rqbit couldn't read: {}", e);
+            return Err(libc::EINVAL);
+        }
+        let timeout = Duration::from_secs(30);
+        let torrent_info = self
+            .async_worker
+            .add_torrent_file(bytes::Bytes::from(contents), timeout)
+            .map_err(|e| {
+                warn!("Failed to add torrent file: {}", e);
+                e.to_errno()
+            })?;
+        info!("Added torrent {}: {}", torrent_info.id, torrent_info.name);
+        self.show_added_torrent(&torrent_info);
+        Ok(())
+    }
+
+    /// Removes a `.torrent` file copied in once its last handle is closed.
+    fn remove_torrent_upload(&self, ino: u64) {
+        let Some(InodeEntry::Virtual {
+            file: VirtualFile::TorrentUpload,
+            parent,
+            name,
+            ..
+        }) = self.inode_manager.get(ino)
+        else {
+            return;
+        };
+        if !self.file_handles.get_handles_for_inode(ino).is_empty() {
+            return;
+        }
+        self.inode_manager.remove_inode(ino);
+        let fs = self.clone();
+        tokio::spawn(async move { fs.invalidate_entry(parent, &name) });
+    }
+
+    /// Creates the entry of a torrent just added through the mount, unless
+    /// the filter leaves it out or discovery got there first.
+    fn show_added_torrent(&self, torrent_info: &crate::api::types::TorrentInfo) {
+        let torrent_id = torrent_info.id;
+        if !self.selects_torrent(torrent_info)
+            || self.inode_manager.lookup_torrent(torrent_id).is_some()
+        {
+            return;
+        }
+        // Discovery tries again if this fails
+        if let Err(e) = self.create_torrent_structure(torrent_info) {
+            warn!(
+                "Failed to create filesystem structure for torrent {}: {}",
+                torrent_id, e
+            );
+            return;
+        }
+        self.update_recent_dir();
+        // Not from within the write: invalidating an entry waits for
+        // lookups of its directory, which may be queued behind it
+        let fs = self.clone();
+        tokio::spawn(async move { fs.touch_torrent_parent(torrent_id) });
+    }
+
+    /// Removes the generated files of a torrent.
+    fn remove_virtual_files(&self, torrent_id: u64) {
+        let path = format!("/{}/{}", CONTROL_DIR, TORRENTS_DIR);
+        let Some(torrents_dir) = self.inode_manager.lookup_by_path(&path) else {
+            return;
+        };
+        for (ino, entry) in self.inode_manager.get_children(torrents_dir) {
+            if matches!(entry, InodeEntry::Virtual { torrent_id: id, .. } if id == torrent_id) {
+                self.inode_manager.remove_inode(ino);
+            }
+        }
+    }
+
+    /// Creates a file entry (and any necessary parent directories) for a torrent file.
+    fn create_file_entry(
+        &self,
+        file_info: &crate::api::types::FileInfo,
+        file_idx: usize,
+        torrent_id: u64,
+        torrent_dir_inode: u64,
+        created_dirs: &mut std::collections::HashMap<String, u64>,
+        _torrent_name: &str,
+    ) -> Result<()> {
+        let components = &file_info.components;
+
+        if components.is_empty() {
+            debug!(
+                torrent_id = torrent_id,
+                file_idx = file_idx,
+                file_name = %file_info.name,
+                "create_file_entry: empty components, using file name as fallback"
+            );
+            // Use file_info.name as fallback when components is empty
+            let file_name = sanitize_filename(&file_info.name);
+            let file_inode = self.inode_manager.allocate_file(
+                file_name.clone(),
+                torrent_dir_inode,
+                torrent_id,
+                file_idx as u64,
+                file_info.length,
+            );
+            if file_inode == 0 {
+                anyhow::bail!("inode limit reached creating {}", file_name);
+            }
+            self.inode_manager.add_child(torrent_dir_inode, file_inode);
+            return Ok(());
+        }
+
+        // Get torrent directory's canonical path for building full paths
+        let torrent_dir_path = self
+            .inode_manager
+            .get_path_for_inode(torrent_dir_inode)
+            .unwrap_or_else(|| "/".to_string());
+
+        // Build parent directories
+        let mut current_dir_inode = torrent_dir_inode;
+        let mut current_path = String::new();
+
+        // Process all components except the last one (which is the filename)
+        for dir_component in components.iter().take(components.len().saturating_sub(1)) {
+            if !current_path.is_empty() {
+                current_path.push('/');
+            }
+            current_path.push_str(dir_component);
+
+            // Check if this directory already exists
+            if let Some(&inode) = created_dirs.get(&current_path) {
+                current_dir_inode = inode;
+            } else {
+                // Create new directory with full canonical path
+                let dir_name = sanitize_filename(dir_component);
+                let full_canonical_path = if torrent_dir_path == "/" {
+                    format!("/{}", current_path)
+                } else {
+                    format!("{}/{}", torrent_dir_path, current_path)
+                };
+                let new_dir_inode = self.inode_manager.allocate(InodeEntry::Directory {
+                    ino: 0,
+                    name: dir_name.clone(),
+                    parent: current_dir_inode,
+                    children: DashSet::new(),
+                    canonical_path: full_canonical_path,
+                });
+                if new_dir_inode == 0 {
+                    anyhow::bail!("inode limit reached creating {}", current_path);
+                }
+
+                // Add to parent
+                self.inode_manager
+                    .add_child(current_dir_inode, new_dir_inode);
+
+                created_dirs.insert(current_path.clone(), new_dir_inode);
+                current_dir_inode = new_dir_inode;
+
+                debug!(
+                    "Created directory {} at inode {}",
+                    current_path, new_dir_inode
+                );
+            }
+        }
+
+        // Build parent directories
+        let mut current_dir_inode = torrent_dir_inode;
+        let mut current_path = String::new();
+
+        // Process all components except the last one (which is the filename)
+        for dir_component in components.iter().take(components.len().saturating_sub(1)) {
+            if !current_path.is_empty() {
+                current_path.push('/');
+            }
+            current_path.push_str(dir_component);
+
+            // Check if this directory already exists
+            if let Some(&inode) = created_dirs.get(&current_path) {
+                current_dir_inode = inode;
+            } else {
+                // Create new directory
+                let dir_name = sanitize_filename(dir_component);
+                let new_dir_inode = self.inode_manager.allocate(InodeEntry::Directory {
+                    ino: 0,
+                    name: dir_name.clone(),
+                    parent: current_dir_inode,
+                    children: DashSet::new(),
+                    canonical_path: format!("/{}", current_path),
+                });
+
+                // Add to parent
+                self.inode_manager
+                    .add_child(current_dir_inode, new_dir_inode);
+
+                created_dirs.insert(current_path.clone(), new_dir_inode);
+                current_dir_inode = new_dir_inode;
+
+                debug!(
+                    "Created directory {} at inode {}",
+                    current_path, new_dir_inode
+                );
+            }
+        }
+
+        // Create the file entry
+        let file_name = components.last().unwrap();
+        let sanitized_name = sanitize_filename(file_name);
+
+        let file_inode = self.inode_manager.allocate_file(
+            sanitized_name,
+            current_dir_inode,
+            torrent_id,
+            file_idx as u64,
+            file_info.length,
+        );
+        if file_inode == 0 {
+            anyhow::bail!("inode limit reached creating {}", file_name);
+        }
+
+        // Add to parent directory
+        self.inode_manager.add_child(current_dir_inode, file_inode);
+
+        info!(
+            torrent_id = torrent_id,
+            file_idx = file_idx,
+            file_name = %file_name,
+            inode = file_inode,
+            parent_inode = current_dir_inode,
+            size = file_info.length,
+            "Created file entry"
+        );
+
+        Ok(())
+    }
+
+    /// Checks if a torrent is already in the filesystem.
+    pub fn has_torrent(&self, torrent_id: u64) -> bool {
+        self.inode_manager.lookup_torrent(torrent_id).is_some()
+    }
+
+    /// Gets the list of torrent IDs currently in the filesystem.
+    pub fn list_torrents(&self) -> Vec<u64> {
+        self.inode_manager.get_all_torrent_ids()
+    }
+
+    /// Remove a torrent from the filesystem and rqbit.
+    ///
+    /// This method:
+    /// 1. Removes the torrent from rqbit (forget - keeps files)
+    /// 2. Removes all inodes associated with the torrent
+    /// 3. Removes the torrent directory from root's children
+    fn remove_torrent(
+        &self,
+        torrent_id: u64,
+        torrent_inode: u64,
+        delete_files: bool,
+    ) -> Result<()> {
+        debug!("Removing torrent {} (inode {})", torrent_id, torrent_inode);
+
+        // Remove from rqbit (forget, keeping the downloaded files unless
+        // `delete_files`) using async worker
+        // This avoids the dangerous block_in_place + block_on pattern
+        let timeout = Duration::from_secs(30);
+        if let Err(e) = self
+            .async_worker
+            .forget_torrent(torrent_id, delete_files, timeout)
+        {
+            return Err(anyhow::anyhow!(
+                "Failed to remove torrent {} from rqbit: {}",
+                torrent_id,
+                e
+            ));
+        }
+
+        // Remove torrent directory from root's children list
+        self.inode_manager.remove_child(1, torrent_inode);
+
+        // Remove all inodes associated with this torrent (recursively)
+        self.inode_manager.remove_inode(torrent_inode);
+        // Torrents inside tag directories and the trash aren't unmapped by remove_inode
+        self.inode_manager.torrent_to_inode().remove(&torrent_id);
+        self.forget_torrent_state(torrent_id);
+
+        info!(
+            "Successfully removed torrent {} from filesystem",
+            torrent_id
+        );
+        Ok(())
+    }
+
+    /// Removes a torrent by its ID.
+    /// Convenience method that finds the inode and calls remove_torrent.
+    pub fn remove_torrent_by_id(&self, torrent_id: u64) -> Result<()> {
+        let torrent_inode = self
+            .inode_manager
+            .lookup_torrent(torrent_id)
+            .ok_or_else(|| anyhow::anyhow!("Torrent {} not found in filesystem", torrent_id))?;
+
+        self.remove_torrent(torrent_id, torrent_inode, false)
+    }
+
+    /// Removes a torrent by its ID like [`Self::remove_torrent_by_id`], and
+    /// deletes its downloaded files too.
+    pub fn delete_torrent_by_id(&self, torrent_id: u64) -> Result<()> {
+        let torrent_inode = self
+            .inode_manager
+            .lookup_torrent(torrent_id)
+            .ok_or_else(|| anyhow::anyhow!("Torrent {} not found in filesystem", torrent_id))?;
+
+        self.remove_torrent(torrent_id, torrent_inode, true)
+    }
+
+    /// Returns the torrent whose top-level entry is `ino`.
+    fn torrent_at(&self, ino: u64) -> Option<u64> {
+        self.inode_manager
+            .torrent_to_inode()
+            .iter()
+            .find(|item| *item.value() == ino)
+            .map(|item| *item.key())
+    }
+
+    /// Returns the torrent whose tree contains `ino`, with the inode of its
+    /// top-level entry.
+    fn owning_torrent(&self, mut ino: u64) -> Option<(u64, u64)> {
+        while ino != 1 {
+            if let Some(torrent_id) = self.torrent_at(ino) {
+                return Some((torrent_id, ino));
+            }
+            ino = self.inode_manager.get(ino)?.parent();
+        }
+        None
+    }
+
+    fn child_inode(&self, parent: u64, name: &str) -> Option<u64> {
+        self.inode_manager
+            .get_children(parent)
+            .into_iter()
+            .find(|(_, entry)| entry.name() == name)
+            .map(|(ino, _)| ino)
+    }
+
+    /// Handles `rmdir` and `unlink`: by the trash with `trash_days` set,
+    /// removing the torrent with `remove_torrents`, and otherwise not at
+    /// all, the filesystem being read-only. Entries inside a torrent can't
+    /// be removed (EPERM), only the torrent's top-level entry.
+    fn remove_entry(&self, parent: u64, name: &str) -> Result<(), i32> {
+        if let Some(trash) = &self.trash {
+            return self.trash_remove(trash, parent, name);
+        }
+        if self.config.remove_torrents == TorrentRemoval::Off {
+            return Err(libc::EROFS);
+        }
+        let ino = self.child_inode(parent, name).ok_or(libc::ENOENT)?;
+        match self.owning_torrent(ino) {
+            Some((torrent_id, top)) if top == ino => {
+                self.remove_torrent_entry(torrent_id, ino, name)
+            }
+            _ => Err(libc::EPERM),
+        }
+    }
+
+    /// Removes a torrent from rqbit as `remove_torrents` says, and its
+    /// top-level entry `ino`. Torrents with open files are busy.
+    fn remove_torrent_entry(&self, torrent_id: u64, ino: u64, name: &str) -> Result<(), i32> {
+        if self
+            .file_handles
+            .all()
+            .iter()
+            .any(|handle| handle.torrent_id == torrent_id)
+        {
+            warn!(
+                "Not removing torrent {} ({}): it has open files",
+                torrent_id, name
+            );
+            return Err(libc::EBUSY);
+        }
+
+        let delete_files = self.config.remove_torrents == TorrentRemoval::Delete;
+        if let Err(e) = self.remove_torrent(torrent_id, ino, delete_files) {
+            error!("Failed to remove torrent {}: {}", torrent_id, e);
+            return Err(e
+                .downcast_ref::<crate::error::RqbitFuseError>()
+                .map_or(libc::EIO, |e| e.to_errno()));
+        }
+        self.update_recent_dir();
+        info!(
+            "Removed torrent {} ({}){}",
+            torrent_id,
+            name,
+            if delete_files { " and its files" } else { "" }
+        );
+        Ok(())
+    }
+
+    /// Handles `rmdir` and `unlink` with `trash_days` set: a torrent's
+    /// top-level entry moves to the trash, and one in the trash is forgotten
+    /// right away. Entries inside a torrent can't be removed (EPERM).
+    fn trash_remove(&self, trash: &Trash, parent: u64, name: &str) -> Result<(), i32> {
+        let ino = self.child_inode(parent, name).ok_or(libc::ENOENT)?;
+        let trash_dir = self.ensure_root_directory(TRASH_DIR.to_string());
+        match self.owning_torrent(ino) {
+            Some((torrent_id, top)) if top == ino && parent == trash_dir => {
+                let hash = self.info_hashes.get(&torrent_id).map(|h| h.clone());
+                if let Err(e) = self.remove_torrent(torrent_id, ino, false) {
+                    error!("Failed to forget trashed torrent {}: {}", torrent_id, e);
+                    return Err(libc::EIO);
+                }
+                if let Some(hash) = hash {
+                    if let Err(e) = trash.remove(&hash) {
+                        warn!("Failed to update trash state: {}", e);
+                    }
+                }
+                info!("Forgot torrent {} ({}) from the trash", torrent_id, name);
+                Ok(())
+            }
+            Some((torrent_id, top)) if top == ino => {
+                self.trash_torrent(trash, torrent_id, ino, trash_dir)
+            }
+            _ => Err(libc::EPERM),
+        }
+    }
+
+    /// Handles `rename` with `trash_days` set: torrents may only move into
+    /// the trash, or out of it back to the directory they came from.
+    fn trash_rename(
+        &self,
+        trash: &Trash,
+        parent: u64,
+        name: &str,
+        newparent: u64,
+    ) -> Result<(), i32> {
+        let ino = self.child_inode(parent, name).ok_or(libc::ENOENT)?;
+        let Some(torrent_id) = self.torrent_at(ino) else {
+            return Err(libc::EPERM);
+        };
+        let trash_dir = self.ensure_root_directory(TRASH_DIR.to_string());
+        if parent == newparent {
+            return Ok(());
+        }
+        if newparent == trash_dir {
+            return self.trash_torrent(trash, torrent_id, ino, trash_dir);
+        }
+        if parent != trash_dir || self.trash_homes.get(&torrent_id).map(|h| *h) != Some(newparent) {
+            return Err(libc::EPERM);
+        }
+        self.untrash(trash, torrent_id, ino)?;
+        if let Err(e) = self
+            .async_worker
+            .set_paused(torrent_id, false, Duration::from_secs(30))
+        {
+            warn!(
+                "Restored torrent {} but failed to resume it: {}",
+                torrent_id, e
+            );
+        }
+        Ok(())
+    }
+
+    /// Pauses a torrent and moves its top-level entry `ino` to the trash.
+    fn trash_torrent(
+        &self,
+        trash: &Trash,
+        torrent_id: u64,
+        ino: u64,
+        trash_dir: u64,
+    ) -> Result<(), i32> {
+        let hash = self
+            .info_hashes
+            .get(&torrent_id)
+            .map(|h| h.clone())
+            .ok_or(libc::EIO)?;
+        let home = self
+            .inode_manager
+            .get(ino)
+            .map(|e| e.parent())
+            .ok_or(libc::ENOENT)?;
+        if !self.inode_manager.move_entry(ino, trash_dir) {
+            return Err(libc::EEXIST);
+        }
+
+        let timeout = Duration::from_secs(30);
+        if let Err(e) = self.async_worker.set_paused(torrent_id, true, timeout) {
+            error!("Failed to pause torrent {}: {}", torrent_id, e);
+            self.inode_manager.move_entry(ino, home);
+            return Err(e.to_errno());
+        }
+        if let Err(e) = trash.put(&hash, std::time::SystemTime::now()) {
+            error!("Failed to update trash state: {}", e);
+            self.inode_manager.move_entry(ino, home);
+            let _ = self.async_worker.set_paused(torrent_id, false, timeout);
+            return Err(libc::EIO);
+        }
+        self.trash_homes.insert(torrent_id, home);
+        info!("Moved torrent {} to the trash", torrent_id);
+        Ok(())
+    }
+
+    /// Moves a trashed torrent back to its directory and takes it out of the
+    /// state file. The caller resumes it.
+    fn untrash(&self, trash: &Trash, torrent_id: u64, ino: u64) -> Result<(), i32> {
+        let home = self
+            .trash_homes
+            .get(&torrent_id)
+            .map(|h| *h)
+            .ok_or(libc::ENOENT)?;
+        let hash = self
+            .info_hashes
+            .get(&torrent_id)
+            .map(|h| h.clone())
+            .ok_or(libc::EIO)?;
+        let trash_dir = self.ensure_root_directory(TRASH_DIR.to_string());
+        if !self.inode_manager.move_entry(ino, home) {
+            return Err(libc::EEXIST);
+        }
+        if let Err(e) = trash.remove(&hash) {
+            error!("Failed to update trash state: {}", e);
+            self.inode_manager.move_entry(ino, trash_dir);
+            return Err(libc::EIO);
+        }
+        self.trash_homes.remove(&torrent_id);
+        info!("Restored torrent {} from the trash", torrent_id);
+        Ok(())
+    }
+
+    /// Lists the torrents in the trash, oldest first.
+    pub fn trash_items(&self) -> Vec<TrashItem> {
+        let Some(trash) = &self.trash else {
+            return Vec::new();
+        };
+        let mut items: Vec<TrashItem> = self
+            .trash_homes
+            .iter()
+            .filter_map(|home| {
+                let torrent_id = *home.key();
+                let info_hash = self.info_hashes.get(&torrent_id)?.clone();
+                let ino = self.inode_manager.lookup_torrent(torrent_id)?;
+                Some(TrashItem {
+                    torrent_id,
+                    name: self.inode_manager.get(ino)?.name().to_string(),
+                    trashed_at: trash.trashed_at(&info_hash)?,
+                    expires_at: trash.expires_at(&info_hash)?,
+                    info_hash,
+                })
+            })
+            .collect();
+        items.sort_by_key(|item| (item.trashed_at, item.torrent_id));
+        items
+    }
+
+    /// Restores a trashed torrent selected by name, ID or info hash and
+    /// resumes it. Returns `None` if no such torrent is in the trash.
+    pub async fn restore_from_trash(&self, torrent: &str) -> Result<Option<TrashItem>> {
+        let Some(trash) = &self.trash else {
+            return Ok(None);
+        };
+        let Some(item) = self.trash_items().into_iter().find(|item| {
+            item.name == torrent
+                || item.torrent_id.to_string() == torrent
+                || item.info_hash.eq_ignore_ascii_case(torrent)
+        }) else {
+            return Ok(None);
+        };
+        let ino = self
+            .inode_manager
+            .lookup_torrent(item.torrent_id)
+            .context("torrent is no longer in the trash")?;
+        let home = self.trash_homes.get(&item.torrent_id).map(|h| *h);
+        let trash_dir = self.ensure_root_directory(TRASH_DIR.to_string());
+        self.untrash(trash, item.torrent_id, ino).map_err(|errno| {
+            anyhow::anyhow!(
+                "failed to restore {}: {}",
+                item.name,
+                std::io::Error::from_raw_os_error(errno)
+            )
+        })?;
+        self.invalidate_entry(trash_dir, &item.name);
+        if let Some(home) = home {
+            self.invalidate_entry(home, &item.name);
+        }
+        self.api_client
+            .start_torrent(item.torrent_id)
+            .await
+            .with_context(|| format!("restored {} but failed to resume it", item.name))?;
+        Ok(Some(item))
+    }
+
+    /// Forgets torrents that have been in the trash for `trash_days`.
+    async fn expire_trash(&self) {
+        let Some(trash) = &self.trash else {
+            return;
+        };
+        let now = std::time::SystemTime::now();
+        let trash_dir = self.ensure_root_directory(TRASH_DIR.to_string());
+        for item in self.trash_items() {
+            if !trash.is_expired(&item.info_hash, now) {
+                continue;
+            }
+            if let Err(e) = self.api_client.forget_torrent(item.torrent_id).await {
+                warn!(
+                    "Failed to forget expired torrent {}: {}",
+                    item.torrent_id, e
+                );
+                continue;
+            }
+            if let Err(e) = trash.remove(&item.info_hash) {
+                warn!("Failed to update trash state: {}", e);
+            }
+            info!(
+                "Forgot torrent {} ({}) after its time in the trash",
+                item.torrent_id, item.name
+            );
+            self.remove_torrent_from_fs(item.torrent_id).await;
+            self.invalidate_entry(trash_dir, &item.name);
+        }
+    }
+}
+
+/// Sanitizes a filename for use in the filesystem.
+/// Removes or replaces characters that are problematic in filenames.
+/// Also prevents path traversal attacks by removing ".." components.
+fn sanitize_filename(name: &str) -> String {
+    // Replace path traversal sequences first
+    let name = name.replace("..", "_");
+
+    // Remove leading/trailing whitespace and dots
+    let trimmed = name.trim().trim_start_matches('.').trim_end_matches('.');
+
+    if trimmed.is_empty() {
+        return "unnamed".to_string();
+    }
+
+    trimmed
+        .chars()
+        .map(|c| match c {
+            // Null character
+            '\0' => '_',
+            // Path separators
+            '/' | '\\' => '_',
+            // Control characters
+            c if c.is_control() => '_',
+            // Other problematic characters
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            _ => c,
+        })
+        .collect()
+}
+
+/// Validates that a path component doesn't contain path traversal sequences.
+/// Returns true if the component is safe to use.
+#[allow(dead_code)]
+pub(crate) fn is_safe_path_component(component: &str) -> bool {
+    // Reject empty components, current dir, parent dir references
+    if component.is_empty() || component == "." || component == ".." || component.contains("..") {
+        return false;
+    }
+
+    // Reject components with path separators
+    if component.contains('/') || component.contains('\\') {
+        return false;
+    }
+
+    // Reject components starting with null bytes or control characters
+    if component.starts_with('\0')
+        || component
+            .chars()
+            .next()
+            .map(|c| c.is_control())
+            .unwrap_or(false)
+    {
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::create_api_client;
+    use crate::config::PageCachePolicy;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    /// Helper function to create a test AsyncFuseWorker
+    fn create_test_async_worker() -> Arc<AsyncFuseWorker> {
+        let config = Config::default();
+        let api_client = Arc::new(
+            create_api_client(
+                &config.api_url,
+                config.api_username.as_deref(),
+                config.api_password.as_deref(),
+                None,
+            )
+            .expect("Failed to create API client"),
+        );
+        Arc::new(AsyncFuseWorker::new(
+            api_client,
+            Arc::new(crate::metrics::Metrics::new()),
+            100,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_torrent_fs_creation() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        assert!(!fs.is_initialized());
+        assert_eq!(fs.inode_manager().get(1).unwrap().ino(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unmount_when_not_mounted() {
+        let fs = TorrentFS::new(
+            Config::default(),
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        assert!(fs.unmount().is_ok());
+        assert!(fs.clone().unmount().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_must_be_writable() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            audit_log: Some(dir.path().join("missing/audit.log")),
+            ..Default::default()
+        };
+        let err = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .err()
+        .expect("audit log directory does not exist");
+        assert!(err.to_string().contains("audit log"), "{}", err);
+
+        let config = Config {
+            audit_log: Some(dir.path().join("audit.log")),
+            ..Default::default()
+        };
+        TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        assert!(dir.path().join("audit.log").exists());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::field_reassign_with_default)]
+    async fn test_validate_mount_point_success() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut config = Config::default();
+        config.mount_point = temp_dir.path().to_path_buf();
+
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+        assert!(fs.validate_mount_point().is_ok());
+    }
+
+    #[tokio::test]
+    #[allow(clippy::field_reassign_with_default)]
+    async fn test_validate_mount_point_nonexistent() {
+        let mut config = Config::default();
+        config.mount_point = PathBuf::from("/nonexistent/path/that/does/not/exist");
+
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+        let result = fs.validate_mount_point();
+
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("does not exist"));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::field_reassign_with_default)]
+    async fn test_validate_mount_point_is_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("not_a_directory.txt");
+        std::fs::write(&file_path, "This is a file, not a directory").unwrap();
+
+        let mut config = Config::default();
+        config.mount_point = file_path;
+
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+        let result = fs.validate_mount_point();
+
+        assert!(result.is_err());
+        let error_msg = result.unwrap_err().to_string();
+        assert!(
+            error_msg.contains("is not a directory") || error_msg.contains("Not a directory"),
+            "Expected error message about mount point not being a directory, got: {}",
+            error_msg
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_mount_options() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        let options = fs.build_mount_options();
+
+        // Check that required options are present
+        assert!(options.contains(&fuser::MountOption::RO));
+        assert!(options.contains(&fuser::MountOption::NoSuid));
+        assert!(options.contains(&fuser::MountOption::AutoUnmount));
+        assert!(!options.contains(&fuser::MountOption::NoAtime));
+        assert!(!options.contains(&fuser::MountOption::DefaultPermissions));
+    }
+
+    #[tokio::test]
+    async fn test_build_mount_options_default_permissions() {
+        let config = Config {
+            allow_other: true,
+            default_permissions: true,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        let options = fs.build_mount_options();
+        assert!(options.contains(&fuser::MountOption::DefaultPermissions));
+        assert!(options.contains(&fuser::MountOption::AllowOther));
+    }
+
+    #[tokio::test]
+    async fn test_build_mount_options_extra_options() {
+        let config = Config {
+            extra_options: vec!["max_read=1048576".to_string()],
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        let options = fs.build_mount_options();
+        assert!(options.contains(&fuser::MountOption::CUSTOM("max_read=1048576".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_build_mount_options_container() {
+        let config = Config {
+            container: true,
+            allow_root: true,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        let options = fs.build_mount_options();
+        assert!(!options.contains(&fuser::MountOption::AutoUnmount));
+        assert!(options.contains(&fuser::MountOption::AllowRoot));
+        assert!(!options.contains(&fuser::MountOption::AllowOther));
+    }
+
+    #[rstest::rstest]
+    #[case("security.selinux", true)]
+    #[case("security.capability", true)]
+    #[case("system.posix_acl_access", true)]
+    #[case("system.posix_acl_default", true)]
+    #[case("user.torrent.status", false)]
+    #[case("securityish", false)]
+    fn test_is_kernel_xattr(#[case] name: &str, #[case] expected: bool) {
+        assert_eq!(is_kernel_xattr(std::ffi::OsStr::new(name)), expected);
+    }
+
+    #[tokio::test]
+    async fn test_file_xattrs() {
+        let fs = TorrentFS::new(
+            Config::default(),
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        let mut torrent = multi_file_torrent_info(1, "abc");
+        torrent.piece_length = Some(1024);
+        torrent.files.push(crate::api::types::FileInfo {
+            name: "empty".to_string(),
+            length: 0,
+            components: vec!["empty".to_string()],
+        });
+        fs.create_torrent_structure(&torrent).unwrap();
+        fs.file_progress.insert((1, 1), 512);
+        fs.file_progress.insert((1, 2), 0);
+
+        let xattrs = |path: &str| {
+            let entry = fs
+                .inode_manager
+                .get(fs.inode_manager.lookup_by_path(path).unwrap())
+                .unwrap();
+            FILE_XATTRS.map(|name| fs.file_xattr(&entry, name))
+        };
+        let some = |index: u64, start: u64, end: u64, progress: Option<&str>| {
+            let [index, start, end] = [index, start, end].map(|value| Some(value.to_string()));
+            [index, start, end, progress.map(str::to_string)]
+        };
+        assert_eq!(xattrs("/Multi File/file1.txt"), some(0, 0, 0, None));
+        assert_eq!(
+            xattrs("/Multi File/subdir/file2.txt"),
+            some(1, 1, 2, Some("25.0"))
+        );
+        assert_eq!(xattrs("/Multi File/empty"), some(2, 3, 3, Some("100.0")));
+        assert_eq!(xattrs("/Multi File/subdir"), [None, None, None, None]);
+
+        // Without a piece length only the index is known
+        fs.remove_torrent_from_fs(1).await;
+        torrent.piece_length = None;
+        fs.create_torrent_structure(&torrent).unwrap();
+        assert_eq!(
+            xattrs("/Multi File/file1.txt"),
+            [Some("0".to_string()), None, None, None]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pieces_available_xattr() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        // Pieces 1 and 3 of 4
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/haves"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(vec![0b00001010u8])
+                    .append_header("x-bitfield-len", "4"),
+            )
+            .mount(&server)
+            .await;
+
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+        let mut torrent = multi_file_torrent_info(1, "abc123");
+        torrent.piece_length = Some(1024);
+        fs.create_torrent_structure(&torrent).unwrap();
+        assert_eq!(
+            fs.info_hashes.get(&1).as_deref().map(String::as_str),
+            Some("abc123")
+        );
+
+        tokio::task::spawn_blocking(move || {
+            let pieces = |path: &str| {
+                let entry = fs
+                    .inode_manager
+                    .get(fs.inode_manager.lookup_by_path(path).unwrap())
+                    .unwrap();
+                fs.pieces_available(&entry)
+            };
+            assert_eq!(pieces("/Multi File/file1.txt"), Ok(Some(0)));
+            assert_eq!(pieces("/Multi File/subdir/file2.txt"), Ok(Some(1)));
+            assert_eq!(pieces("/Multi File/subdir"), Ok(None));
+        })
+        .await
+        .unwrap();
+    }
+
+    #[rstest::rstest]
+    #[case(0o440, 1000, 100, libc::R_OK, true)]
+    #[case(0o440, 2000, 100, libc::R_OK, true)]
+    #[case(0o440, 2000, 200, libc::R_OK, false)]
+    #[case(0o444, 2000, 200, libc::R_OK, true)]
+    #[case(0o444, 1000, 100, libc::X_OK, false)]
+    #[case(0o550, 1000, 100, libc::R_OK | libc::X_OK, true)]
+    #[case(0o500, 2000, 100, libc::X_OK, false)]
+    #[case(0o400, 0, 0, libc::R_OK, true)]
+    #[case(0o444, 0, 0, libc::X_OK, false)]
+    #[case(0o500, 0, 0, libc::X_OK, true)]
+    fn test_check_access(
+        #[case] perm: u16,
+        #[case] uid: u32,
+        #[case] gid: u32,
+        #[case] mask: i32,
+        #[case] expected: bool,
+    ) {
+        let attr = fuser::FileAttr {
+            perm,
+            uid: 1000,
+            gid: 100,
+            ..crate::types::attr::default_file_attr(2, 0)
+        };
+        assert_eq!(TorrentFS::check_access(&attr, uid, gid, mask), expected);
+    }
+
+    #[tokio::test]
+    async fn test_configured_ownership_and_modes() {
+        let config = Config {
+            uid: Some(1234),
+            gid: Some(5678),
+            file_mode: 0o440,
+            dir_mode: 0o550,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        let root = fs.build_file_attr(&fs.inode_manager.get(1).unwrap());
+        assert_eq!((root.uid, root.gid, root.perm), (1234, 5678, 0o550));
+
+        let ino = fs
+            .inode_manager
+            .allocate_file("a.mkv".to_string(), 1, 1, 0, 100);
+        let file = fs.build_file_attr(&fs.inode_manager.get(ino).unwrap());
+        assert_eq!((file.uid, file.gid, file.perm), (1234, 5678, 0o440));
+    }
+
+    #[tokio::test]
+    async fn test_atime_policy() {
+        use crate::config::AtimePolicy;
+
+        for (policy, records) in [(AtimePolicy::Noatime, false), (AtimePolicy::Strict, true)] {
+            let config = Config {
+                atime: policy,
+                ..Default::default()
+            };
+            let fs = TorrentFS::new(
+                config,
+                Arc::new(crate::metrics::Metrics::new()),
+                create_test_async_worker(),
+            )
+            .unwrap();
+            assert_eq!(
+                fs.build_mount_options()
+                    .contains(&fuser::MountOption::NoAtime),
+                !records
+            );
+
+            let ino = fs
+                .inode_manager
+                .allocate_file("a.mkv".to_string(), 1, 1, 0, 100);
+            let before = fs
+                .build_file_attr(&fs.inode_manager.get(ino).unwrap())
+                .atime;
+
+            let now = std::time::SystemTime::now();
+            if fs.config.atime.should_update(None, now) {
+                assert!(fs.inode_manager.set_atime(ino, now));
+            }
+            let after = fs
+                .build_file_attr(&fs.inode_manager.get(ino).unwrap())
+                .atime;
+            assert_eq!(after == now, records);
+            assert_eq!(after == before, !records);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove_torrent_cleans_up_inodes() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Create a torrent structure manually
+        let torrent_id = 123u64;
+        let torrent_inode =
+            fs.inode_manager
+                .allocate_torrent_directory(torrent_id, "test_torrent".to_string(), 1);
+        fs.inode_manager.add_child(1, torrent_inode);
+
+        // Add a file to the torrent
+        let file_inode = fs.inode_manager.allocate_file(
+            "test.txt".to_string(),
+            torrent_inode,
+            torrent_id,
+            0,
+            1024,
+        );
+        fs.inode_manager.add_child(torrent_inode, file_inode);
+
+        // Verify structures exist
+        assert!(fs.inode_manager.get(torrent_inode).is_some());
+        assert!(fs.inode_manager.get(file_inode).is_some());
+        assert!(fs.inode_manager.lookup_torrent(torrent_id).is_some());
+
+        // Remove the torrent (this would normally call rqbit API)
+        // Since we can't call the API in tests, we manually clean up
+        fs.inode_manager.remove_child(1, torrent_inode);
+        fs.inode_manager.remove_inode(torrent_inode);
+
+        // Verify structures are cleaned up
+        assert!(fs.inode_manager.get(torrent_inode).is_none());
+        assert!(fs.inode_manager.get(file_inode).is_none());
+        assert!(fs.inode_manager.lookup_torrent(torrent_id).is_none());
+
+        // Verify torrent is no longer in root's children
+        let root_children = fs.inode_manager.get_children(1);
+        assert!(!root_children.iter().any(|(ino, _)| *ino == torrent_inode));
+    }
+
+    // Edge case tests
+    #[test]
+    fn test_sanitize_filename_path_traversal() {
+        // Path traversal attempts should be neutralized - all separators become _
+        assert_eq!(sanitize_filename("../../../etc/passwd"), "______etc_passwd");
+        assert_eq!(sanitize_filename(".."), "_");
+        // "../secret" -> "_/secret" -> "__secret"
+        assert_eq!(sanitize_filename("../secret"), "__secret");
+    }
+
+    #[test]
+    fn test_sanitize_filename_special_chars() {
+        // Special characters should be replaced
+        assert_eq!(sanitize_filename("file:name.txt"), "file_name.txt");
+        assert_eq!(sanitize_filename("file*name?.txt"), "file_name_.txt");
+        // Both < and > are replaced, resulting in double underscore between script tags
+        assert_eq!(
+            sanitize_filename("<script>alert(1)</script>"),
+            "_script_alert(1)__script_"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_filename_control_chars() {
+        // Control characters should be replaced
+        assert_eq!(sanitize_filename("file\x00name"), "file_name");
+        assert_eq!(sanitize_filename("file\nname"), "file_name");
+        assert_eq!(sanitize_filename("file\tname"), "file_name");
+    }
+
+    #[test]
+    fn test_sanitize_filename_leading_dots() {
+        // Leading/trailing dots should be removed (prevents hidden files)
+        assert_eq!(sanitize_filename(".hidden"), "hidden");
+        assert_eq!(sanitize_filename("file."), "file");
+        assert_eq!(sanitize_filename("..double"), "_double");
+    }
+
+    #[test]
+    fn test_sanitize_filename_empty() {
+        // Empty names should be replaced with "unnamed"
+        assert_eq!(sanitize_filename(""), "unnamed");
+        assert_eq!(sanitize_filename("   "), "unnamed");
+        // "..." becomes "_." (".." replaced with "_", leaving "."), then trimmed to "_"
+        assert_eq!(sanitize_filename("..."), "_");
+    }
+
+    #[test]
+    fn test_is_safe_path_component() {
+        // Safe components
+        assert!(is_safe_path_component("normal_file"));
+        assert!(is_safe_path_component("file.txt"));
+        assert!(is_safe_path_component("my-directory"));
+
+        // Unsafe components
+        assert!(!is_safe_path_component(""));
+        assert!(!is_safe_path_component("."));
+        assert!(!is_safe_path_component(".."));
+        assert!(!is_safe_path_component("../.."));
+        assert!(!is_safe_path_component("dir/file"));
+        assert!(!is_safe_path_component("dir\\file"));
+    }
+
+    #[tokio::test]
+    async fn test_symlink_creation() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Create a symlink
+        let symlink_inode =
+            fs.inode_manager
+                .allocate_symlink("link".to_string(), 1, "/target/path".to_string());
+
+        // Verify symlink exists
+        let entry = fs.inode_manager.get(symlink_inode).unwrap();
+        assert!(entry.is_symlink());
+        assert_eq!(entry.name(), "link");
+
+        // Verify attributes
+        let attr = fs.build_file_attr(&entry);
+        assert_eq!(attr.kind, fuser::FileType::Symlink);
+        assert_eq!(attr.size, "/target/path".len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_zero_byte_file() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Create a zero-byte file
+        let file_inode = fs.inode_manager.allocate_file(
+            "empty.txt".to_string(),
+            1,
+            1,
+            0,
+            0, // Zero size
+        );
+
+        // Verify file exists
+        let entry = fs.inode_manager.get(file_inode).unwrap();
+        assert!(entry.is_file());
+
+        // Verify attributes
+        let attr = fs.build_file_attr(&entry);
+        assert_eq!(attr.size, 0);
+        assert_eq!(attr.blocks, 0);
+    }
+
+    #[tokio::test]
+    async fn test_large_file() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Create a large file (>4GB)
+        let large_size = 5u64 * 1024 * 1024 * 1024; // 5 GB
+        let file_inode =
+            fs.inode_manager
+                .allocate_file("large.iso".to_string(), 1, 1, 0, large_size);
+
+        // Verify attributes
+        let entry = fs.inode_manager.get(file_inode).unwrap();
+        let attr = fs.build_file_attr(&entry);
+        assert_eq!(attr.size, large_size);
+        assert!(attr.blocks > 0);
+    }
+
+    #[tokio::test]
+    async fn test_unicode_filename() {
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Test various Unicode filenames
+        let unicode_names = vec![
+            "文件.txt",       // Chinese
+            "ファイル.txt",   // Japanese
+            "файл.txt",       // Russian
+            "αρχείο.txt",     // Greek
+            "📄document.txt", // Emoji
+            "naïve.txt",      // Accented
+        ];
+
+        for name in unicode_names {
+            let inode = fs
+                .inode_manager
+                .allocate_file(name.to_string(), 1, 1, 0, 100);
+            let entry = fs.inode_manager.get(inode).unwrap();
+            assert_eq!(entry.name(), name);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_single_file_torrent_structure() {
+        use crate::api::types::{FileInfo, TorrentInfo};
+
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
+
+        // Create a single-file torrent info
+        let torrent_info = TorrentInfo {
+            id: 1,
+            info_hash: "abc123".to_string(),
+            name: "Single File".to_string(),
+            output_folder: "/tmp".to_string(),
+            file_count: Some(1),
+            files: vec![FileInfo {
+                name: "file.txt".to_string(),
+                length: 1024,
+                components: vec!["file.txt".to_string()],
+            }],
+            piece_length: Some(262144),
+            tags: None,
+            deferred_size: None,
+        };
+
+        // Create structure
+        fs.create_torrent_structure(&torrent_info).unwrap();
+
+        // Verify file was added directly to root (no directory)
+        let root_children = fs.inode_manager.get_children(1);
+        assert_eq!(root_children.len(), 1);
+
+        let (inode, entry) = &root_children[0];
+        assert!(entry.is_file());
+        assert_eq!(entry.name(), "file.txt");
+
+        // Verify torrent mapping points to file
+        let torrent_inode = fs.inode_manager.lookup_torrent(1).unwrap();
+        assert_eq!(torrent_inode, *inode);
+    }
+
+    #[tokio::test]
+    async fn test_multi_file_torrent_structure() {
+        use crate::api::types::{FileInfo, TorrentInfo};
 
-        // Add to parent directory
-        self.inode_manager.add_child(current_dir_inode, file_inode);
+        let config = Config::default();
+        let async_worker = create_test_async_worker();
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            async_worker,
+        )
+        .unwrap();
 
-        info!(
-            torrent_id = torrent_id,
-            file_idx = file_idx,
-            file_name = %file_name,
-            inode = file_inode,
-            parent_inode = current_dir_inode,
-            size = file_info.length,
-            "Created file entry"
-        );
+        // Create a multi-file torrent info
+        let torrent_info = TorrentInfo {
+            id: 2,
+            info_hash: "def456".to_string(),
+            name: "Multi File".to_string(),
+            output_folder: "/tmp".to_string(),
+            file_count: Some(2),
+            files: vec![
+                FileInfo {
+                    name: "file1.txt".to_string(),
+                    length: 1024,
+                    components: vec!["file1.txt".to_string()],
+                },
+                FileInfo {
+                    name: "file2.txt".to_string(),
+                    length: 2048,
+                    components: vec!["subdir".to_string(), "file2.txt".to_string()],
+                },
+            ],
+            piece_length: Some(262144),
+            tags: None,
+            deferred_size: None,
+        };
 
-        Ok(())
-    }
+        // Create structure
+        fs.create_torrent_structure(&torrent_info).unwrap();
 
-    /// Checks if a torrent is already in the filesystem.
-    pub fn has_torrent(&self, torrent_id: u64) -> bool {
-        self.inode_manager.lookup_torrent(torrent_id).is_some()
+        // Verify directory was created
+        let root_children = fs.inode_manager.get_children(1);
+        assert_eq!(root_children.len(), 1);
+
+        let (_dir_inode, entry) = &root_children[0];
+        assert!(entry.is_directory());
+        assert_eq!(entry.name(), "Multi File");
     }
 
-    /// Gets the list of torrent IDs currently in the filesystem.
-    pub fn list_torrents(&self) -> Vec<u64> {
-        self.inode_manager.get_all_torrent_ids()
+    fn create_single_torrent_fs(selector: &str) -> TorrentFS {
+        let config = Config {
+            torrent: Some(selector.to_string()),
+            ..Default::default()
+        };
+        TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap()
     }
 
-    /// Remove a torrent from the filesystem and rqbit.
-    ///
-    /// This method:
-    /// 1. Removes the torrent from rqbit (forget - keeps files)
-    /// 2. Removes all inodes associated with the torrent
-    /// 3. Removes the torrent directory from root's children
-    fn remove_torrent(&self, torrent_id: u64, torrent_inode: u64) -> Result<()> {
-        debug!("Removing torrent {} (inode {})", torrent_id, torrent_inode);
+    fn multi_file_torrent_info(id: u64, info_hash: &str) -> crate::api::types::TorrentInfo {
+        use crate::api::types::{FileInfo, TorrentInfo};
 
-        // Remove from rqbit (forget - keeps downloaded files) using async worker
-        // This avoids the dangerous block_in_place + block_on pattern
-        let timeout = Duration::from_secs(30);
-        if let Err(e) = self.async_worker.forget_torrent(torrent_id, timeout) {
-            return Err(anyhow::anyhow!(
-                "Failed to remove torrent {} from rqbit: {}",
-                torrent_id,
-                e
-            ));
+        TorrentInfo {
+            id,
+            info_hash: info_hash.to_string(),
+            name: "Multi File".to_string(),
+            output_folder: "/tmp".to_string(),
+            file_count: Some(2),
+            files: vec![
+                FileInfo {
+                    name: "file1.txt".to_string(),
+                    length: 1024,
+                    components: vec!["file1.txt".to_string()],
+                },
+                FileInfo {
+                    name: "file2.txt".to_string(),
+                    length: 2048,
+                    components: vec!["subdir".to_string(), "file2.txt".to_string()],
+                },
+            ],
+            piece_length: Some(262144),
+            tags: None,
+            deferred_size: None,
         }
+    }
 
-        // Remove torrent directory from root's children list
-        self.inode_manager.remove_child(1, torrent_inode);
+    #[tokio::test]
+    async fn test_selects_torrent_by_id_or_info_hash() {
+        let all = TorrentFS::new(
+            Config::default(),
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        assert!(all.selects_torrent(&multi_file_torrent_info(7, "abc123")));
 
-        // Remove all inodes associated with this torrent (recursively)
-        self.inode_manager.remove_inode(torrent_inode);
+        let by_id = create_single_torrent_fs("7");
+        assert!(by_id.selects_torrent(&multi_file_torrent_info(7, "abc123")));
+        assert!(!by_id.selects_torrent(&multi_file_torrent_info(8, "def456")));
 
-        info!(
-            "Successfully removed torrent {} from filesystem",
-            torrent_id
-        );
-        Ok(())
+        let by_hash = create_single_torrent_fs("ABC123");
+        assert!(by_hash.selects_torrent(&multi_file_torrent_info(7, "abc123")));
+        assert!(!by_hash.selects_torrent(&multi_file_torrent_info(7, "abc124")));
     }
 
-    /// Removes a torrent by its ID.
-    /// Convenience method that finds the inode and calls remove_torrent.
-    pub fn remove_torrent_by_id(&self, torrent_id: u64) -> Result<()> {
-        let torrent_inode = self
-            .inode_manager
-            .lookup_torrent(torrent_id)
-            .ok_or_else(|| anyhow::anyhow!("Torrent {} not found in filesystem", torrent_id))?;
+    #[tokio::test]
+    async fn test_single_torrent_mode_structure() {
+        let fs = create_single_torrent_fs("2");
+        fs.create_torrent_structure(&multi_file_torrent_info(2, "def456"))
+            .unwrap();
 
-        self.remove_torrent(torrent_id, torrent_inode)
+        // Files and directories sit directly at the mount point
+        let mut names: Vec<_> = fs
+            .inode_manager
+            .get_children(1)
+            .iter()
+            .map(|(_, e)| e.name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["file1.txt", "subdir"]);
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/subdir/file2.txt")
+            .is_some());
+        assert_eq!(fs.inode_manager.lookup_torrent(2), Some(1));
+        assert_eq!(fs.torrent_count(), 1);
+
+        // Removing the torrent empties the mount but keeps the root
+        fs.remove_torrent_from_fs(2).await;
+        assert!(fs.inode_manager.get_children(1).is_empty());
+        assert!(fs.inode_manager.get(1).is_some());
+        assert_eq!(fs.torrent_count(), 0);
     }
-}
 
-/// Sanitizes a filename for use in the filesystem.
-/// Removes or replaces characters that are problematic in filenames.
-/// Also prevents path traversal attacks by removing ".." components.
-fn sanitize_filename(name: &str) -> String {
-    // Replace path traversal sequences first
-    let name = name.replace("..", "_");
+    #[tokio::test]
+    async fn test_selects_torrent_applies_filter() {
+        let config = Config {
+            filter: crate::config::FilterConfig {
+                exclude_name_regex: Some("^Multi".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        assert!(!fs.selects_torrent(&multi_file_torrent_info(1, "abc")));
+    }
 
-    // Remove leading/trailing whitespace and dots
-    let trimmed = name.trim().trim_start_matches('.').trim_end_matches('.');
+    #[tokio::test]
+    async fn test_tag_directories() {
+        let config = Config {
+            filter: crate::config::FilterConfig {
+                tag_directories: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
 
-    if trimmed.is_empty() {
-        return "unnamed".to_string();
+        let mut movie = multi_file_torrent_info(1, "aaa");
+        movie.tags = Some(vec!["media".to_string()]);
+        let mut show = multi_file_torrent_info(2, "bbb");
+        show.name = "Show".to_string();
+        show.tags = Some(vec!["media".to_string()]);
+        fs.create_torrent_structure(&movie).unwrap();
+        fs.create_torrent_structure(&show).unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(3, "ccc"))
+            .unwrap();
+
+        let media = fs.inode_manager.lookup_by_path("/media").unwrap();
+        assert_eq!(fs.inode_manager.get_children(media).len(), 2);
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/media/Show/subdir/file2.txt")
+            .is_some());
+        // Untagged torrents stay at the root
+        assert_eq!(fs.inode_manager.get_children(1).len(), 2);
+
+        fs.remove_torrent_from_fs(2).await;
+        assert_eq!(fs.inode_manager.get_children(media).len(), 1);
+        assert_eq!(fs.inode_manager.lookup_torrent(2), None);
     }
 
-    trimmed
-        .chars()
-        .map(|c| match c {
-            // Null character
-            '\0' => '_',
-            // Path separators
-            '/' | '\\' => '_',
-            // Control characters
-            c if c.is_control() => '_',
-            // Other problematic characters
-            ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            _ => c,
-        })
-        .collect()
-}
+    #[rstest::rstest]
+    #[case::eager(0)]
+    #[case::lazy(2)]
+    #[tokio::test]
+    async fn test_hide_files(#[case] lazy_tree_threshold: usize) {
+        let config = Config {
+            filter: crate::config::FilterConfig {
+                hide_files: vec!["*.nfo".to_string(), "sample/*".to_string()],
+                ..Default::default()
+            },
+            lazy_tree_threshold,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
 
-/// Validates that a path component doesn't contain path traversal sequences.
-/// Returns true if the component is safe to use.
-#[allow(dead_code)]
-pub(crate) fn is_safe_path_component(component: &str) -> bool {
-    // Reject empty components, current dir, parent dir references
-    if component.is_empty() || component == "." || component == ".." || component.contains("..") {
-        return false;
-    }
+        let mut torrent = multi_file_torrent_info(1, "abc");
+        for components in [&["Movie.nfo"][..], &["sample", "clip.mkv"]] {
+            torrent.files.push(crate::api::types::FileInfo {
+                name: components.last().unwrap().to_string(),
+                length: 10,
+                components: components.iter().map(|c| c.to_string()).collect(),
+            });
+        }
+        fs.create_torrent_structure(&torrent).unwrap();
+        let torrent_dir = fs.inode_manager.lookup_torrent(1).unwrap();
+        fs.materialize_dir(torrent_dir);
 
-    // Reject components with path separators
-    if component.contains('/') || component.contains('\\') {
-        return false;
-    }
+        let names: Vec<String> = fs
+            .inode_manager
+            .get_children(torrent_dir)
+            .into_iter()
+            .map(|(_, entry)| entry.name().to_string())
+            .collect();
+        assert_eq!(names.len(), 2, "{:?}", names);
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .is_some());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/Movie.nfo")
+            .is_none());
+        // A directory whose files are all hidden is left out too
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/sample")
+            .is_none());
 
-    // Reject components starting with null bytes or control characters
-    if component.starts_with('\0')
-        || component
-            .chars()
-            .next()
-            .map(|c| c.is_control())
-            .unwrap_or(false)
-    {
-        return false;
+        // Only subdirectories count towards a directory's links
+        let attr = fs.build_file_attr(&fs.inode_manager.get(torrent_dir).unwrap());
+        assert_eq!(attr.nlink, 3);
     }
 
-    true
-}
+    #[tokio::test]
+    async fn test_recent_dir() {
+        let config = Config {
+            recent_count: 2,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        for id in 1..=3 {
+            let mut torrent = multi_file_torrent_info(id, &format!("{:040x}", id));
+            torrent.name = format!("Torrent {}", id);
+            fs.create_torrent_structure(&torrent).unwrap();
+        }
+        fs.update_recent_dir();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::PathBuf;
-    use tempfile::TempDir;
+        let recent = fs.inode_manager.lookup_by_path("/.recent").unwrap();
+        let links = |fs: &TorrentFS| {
+            let mut links: Vec<(String, String)> = fs
+                .inode_manager
+                .get_children(recent)
+                .into_iter()
+                .filter_map(|(_, entry)| match entry {
+                    InodeEntry::Symlink { name, target, .. } => Some((name, target)),
+                    _ => None,
+                })
+                .collect();
+            links.sort();
+            links
+        };
+        assert_eq!(
+            links(&fs),
+            vec![
+                ("1 Torrent 3".to_string(), "../Torrent 3".to_string()),
+                ("2 Torrent 2".to_string(), "../Torrent 2".to_string()),
+            ]
+        );
 
-    /// Helper function to create a test AsyncFuseWorker
-    fn create_test_async_worker() -> Arc<AsyncFuseWorker> {
-        let config = Config::default();
-        let api_client = Arc::new(
-            create_api_client(
-                &config.api_url,
-                config.api_username.as_deref(),
-                config.api_password.as_deref(),
-                None,
-            )
-            .expect("Failed to create API client"),
+        fs.remove_torrent_from_fs(3).await;
+        fs.update_recent_dir();
+        assert_eq!(
+            links(&fs),
+            vec![
+                ("1 Torrent 2".to_string(), "../Torrent 2".to_string()),
+                ("2 Torrent 1".to_string(), "../Torrent 1".to_string()),
+            ]
         );
-        Arc::new(AsyncFuseWorker::new(
-            api_client,
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/.recent/2 Torrent 2")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_output_folder_directories() {
+        let config = Config {
+            filter: crate::config::FilterConfig {
+                output_folder_root: Some(PathBuf::from("/downloads")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
             Arc::new(crate::metrics::Metrics::new()),
-            100,
-        ))
+            create_test_async_worker(),
+        )
+        .unwrap();
+
+        let in_folder = |id, name: &str, output_folder: &str| {
+            let mut torrent = multi_file_torrent_info(id, &format!("{:040x}", id));
+            torrent.name = name.to_string();
+            torrent.output_folder = output_folder.to_string();
+            torrent
+        };
+        fs.create_torrent_structure(&in_folder(1, "Show S01", "/downloads/tv/Show"))
+            .unwrap();
+        fs.create_torrent_structure(&in_folder(2, "Show S02", "/downloads/tv/Show"))
+            .unwrap();
+        fs.create_torrent_structure(&in_folder(3, "Movie", "/downloads/movies"))
+            .unwrap();
+        fs.create_torrent_structure(&in_folder(4, "Other", "/srv/other"))
+            .unwrap();
+
+        let show = fs.inode_manager.lookup_by_path("/tv/Show").unwrap();
+        assert_eq!(fs.inode_manager.get_children(show).len(), 2);
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/tv/Show/Show S02/subdir/file2.txt")
+            .is_some());
+        assert!(fs.inode_manager.lookup_by_path("/movies/Movie").is_some());
+        // Torrents outside the root stay at the root
+        assert!(fs.inode_manager.lookup_by_path("/Other").is_some());
+        assert_eq!(fs.inode_manager.get_children(1).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_lazy_tree_materializes_on_access() {
+        let config = Config {
+            lazy_tree_threshold: 2,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc"))
+            .unwrap();
+
+        // Only the torrent directory exists until it is accessed
+        let torrent_dir = fs.inode_manager.lookup_torrent(1).unwrap();
+        assert!(fs.inode_manager.get_children(torrent_dir).is_empty());
+        assert_eq!(fs.inode_manager.len(), 2);
+
+        fs.materialize_dir(torrent_dir);
+        let mut names: Vec<_> = fs
+            .inode_manager
+            .get_children(torrent_dir)
+            .iter()
+            .map(|(_, e)| e.name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["file1.txt", "subdir"]);
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir/file2.txt")
+            .is_none());
+
+        let subdir = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir")
+            .unwrap();
+        fs.materialize_dir(subdir);
+        let file2 = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir/file2.txt")
+            .unwrap();
+        match fs.inode_manager.get(file2).unwrap() {
+            InodeEntry::File {
+                file_index, size, ..
+            } => assert_eq!((file_index, size), (1, 2048)),
+            other => panic!("expected a file, got {:?}", other),
+        }
+
+        // Materializing again is a no-op
+        fs.materialize_dir(torrent_dir);
+        assert_eq!(fs.inode_manager.get_children(torrent_dir).len(), 2);
     }
 
     #[tokio::test]
-    async fn test_torrent_fs_creation() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_lazy_tree_pending_dirs_dropped_with_torrent() {
+        let config = Config {
+            lazy_tree_threshold: 2,
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc"))
+            .unwrap();
+        fs.materialize_dir(fs.inode_manager.lookup_torrent(1).unwrap());
+        assert_eq!(fs.pending_dirs.len(), 1);
 
-        assert!(!fs.is_initialized());
-        assert_eq!(fs.inode_manager().get(1).unwrap().ino(), 1);
+        fs.remove_torrent_from_fs(1).await;
+        assert!(fs.pending_dirs.is_empty());
     }
 
     #[tokio::test]
-    async fn test_validate_mount_point_success() {
-        let temp_dir = TempDir::new().unwrap();
-        let mut config = Config::default();
-        config.mount_point = temp_dir.path().to_path_buf();
-
-        let async_worker = create_test_async_worker();
+    async fn test_orphaned_handles_close_their_streams() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 1024]))
+            .mount(&server)
+            .await;
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
-        assert!(fs.validate_mount_point().is_ok());
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+        let ino = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .unwrap();
+
+        let first = fs.file_handles.allocate(ino, 1, libc::O_RDONLY);
+        let second = fs.file_handles.allocate(ino, 1, libc::O_RDONLY);
+        fs.api_client
+            .read_file_streaming(1, 0, 0, 100)
+            .await
+            .unwrap();
+        assert_eq!(fs.api_client.stream_stats().await.active_streams, 1);
+
+        // The stream stays while another handle reads the file
+        let evicted = vec![fs.file_handles.remove(first).unwrap()];
+        fs.close_orphaned_streams(&evicted).await;
+        assert_eq!(fs.api_client.stream_stats().await.active_streams, 1);
+
+        let evicted = vec![fs.file_handles.remove(second).unwrap()];
+        fs.close_orphaned_streams(&evicted).await;
+        assert_eq!(fs.api_client.stream_stats().await.active_streams, 0);
     }
 
     #[tokio::test]
-    async fn test_validate_mount_point_nonexistent() {
-        let mut config = Config::default();
-        config.mount_point = PathBuf::from("/nonexistent/path/that/does/not/exist");
+    async fn test_failed_structure_is_undone_and_retried_later() {
+        let mut fs = TorrentFS::new(
+            Config::default(),
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        // Room for the torrent's directory, not for its files
+        fs.inode_manager = Arc::new(InodeManager::with_max_inodes(2));
+        let torrent = multi_file_torrent_info(1, "abc123");
 
-        let async_worker = create_test_async_worker();
+        assert!(!fs.materialize_torrent(&torrent).await);
+        assert!(fs.inode_manager.lookup_torrent(1).is_none());
+        assert!(fs.inode_manager.get_children(1).is_empty());
+
+        // Not attempted again before the backoff passes
+        fs.inode_manager = Arc::new(InodeManager::new());
+        assert!(!fs.materialize_torrent(&torrent).await);
+        assert!(fs.inode_manager.lookup_torrent(1).is_none());
+
+        assert!(
+            fs.materialize_torrent(&multi_file_torrent_info(2, "def456"))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_files() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "state": "live",
+                "progress_bytes": 512,
+                "total_bytes": 1024,
+                "finished": false,
+                "live": {"snapshot": {"peer_stats": {"live": 3}}, "download_speed": {"mbps": 1.5}}
+            })))
+            .mount(&server)
+            .await;
+        let config = Config {
+            api_url: server.uri(),
+            stats_files: true,
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
-        let result = fs.validate_mount_point();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "ABC123"))
+            .unwrap();
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().to_string().contains("does not exist"));
+        let ino = fs
+            .inode_manager
+            .lookup_by_path("/.rqbit-fuse/torrents/abc123.json")
+            .unwrap();
+        let Some(InodeEntry::Virtual {
+            torrent_id, file, ..
+        }) = fs.inode_manager.get(ino)
+        else {
+            panic!("Expected a virtual file");
+        };
+        assert_eq!(torrent_id, 1);
+        assert_eq!(
+            fs.build_file_attr(&fs.inode_manager.get(ino).unwrap()).size,
+            0
+        );
+
+        // Fields rqbit-fuse doesn't model are passed through
+        let contents = file.render(fs.api_client.as_ref(), 1).await.unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+        assert_eq!(stats["live"]["snapshot"]["peer_stats"]["live"], 3);
+
+        fs.remove_torrent_from_fs(1).await;
+        assert!(fs.inode_manager.get(ino).is_none());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/.rqbit-fuse/torrents")
+            .is_some());
     }
 
     #[tokio::test]
-    async fn test_validate_mount_point_is_file() {
-        let temp_dir = TempDir::new().unwrap();
-        let file_path = temp_dir.path().join("not_a_directory.txt");
-        std::fs::write(&file_path, "This is a file, not a directory").unwrap();
+    async fn test_status_dirs() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "state": "live",
+                "file_progress": [1024, 1000],
+                "progress_bytes": 2024,
+                "uploaded_bytes": 0,
+                "total_bytes": 3072,
+                "finished": false,
+                "live": null
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/peer_stats"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "peers": {"10.0.0.2:6881": {"state": "live"}}
+            })))
+            .mount(&server)
+            .await;
+        let config = Config {
+            api_url: server.uri(),
+            status_dirs: true,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+        // Single-file torrents have no directory to put it in
+        let mut single = multi_file_torrent_info(2, "def456");
+        single.name = "Single".to_string();
+        single.files.truncate(1);
+        fs.create_torrent_structure(&single).unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/.rqbit-fuse").is_none());
+
+        let render = |name: &str| {
+            let ino = fs
+                .inode_manager
+                .lookup_by_path(&format!("/Multi File/.rqbit-fuse/{}", name))
+                .unwrap();
+            let Some(InodeEntry::Virtual {
+                torrent_id, file, ..
+            }) = fs.inode_manager.get(ino)
+            else {
+                panic!("Expected a virtual file");
+            };
+            assert_eq!(torrent_id, 1);
+            file.render(fs.api_client.as_ref(), torrent_id)
+        };
+        let status: serde_json::Value =
+            serde_json::from_slice(&render("status.json").await.unwrap()).unwrap();
+        assert_eq!(status["progress_bytes"], 2024);
+        let peers: serde_json::Value =
+            serde_json::from_slice(&render("peers.json").await.unwrap()).unwrap();
+        assert_eq!(peers["peers"]["10.0.0.2:6881"]["state"], "live");
+        assert_eq!(&render("progress").await.unwrap()[..], b"65.8\n");
+
+        fs.remove_torrent_from_fs(1).await;
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/.rqbit-fuse")
+            .is_none());
+    }
 
-        let mut config = Config::default();
-        config.mount_point = file_path;
+    #[test]
+    fn test_percent_rounds_down() {
+        assert_eq!(percent(2024, 3072), "65.8");
+        assert_eq!(percent(9999, 10000), "99.9");
+        assert_eq!(percent(10000, 10000), "100.0");
+        assert_eq!(percent(0, 0), "100.0");
+    }
 
-        let async_worker = create_test_async_worker();
+    #[tokio::test]
+    async fn test_magnet_placeholder_populated_once_resolved() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            metadata_snapshot: Some(dir.path().join("snapshot.json")),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
-        let result = fs.validate_mount_point();
 
-        assert!(result.is_err());
-        let error_msg = result.unwrap_err().to_string();
-        assert!(
-            error_msg.contains("is not a directory") || error_msg.contains("Not a directory"),
-            "Expected error message about mount point not being a directory, got: {}",
-            error_msg
-        );
+        let mut magnet = multi_file_torrent_info(1, "abc123");
+        magnet.name = String::new();
+        magnet.files.clear();
+        fs.create_torrent_structure(&magnet).unwrap();
+
+        let placeholder = fs.inode_manager.lookup_by_path("/abc123").unwrap();
+        assert!(fs.inode_manager.get_children(placeholder).is_empty());
+        assert!(fs.awaiting_metadata.contains(&1));
+
+        let resolved = serde_json::to_value(multi_file_torrent_info(1, "abc123")).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "torrents": [resolved.clone()] })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(resolved))
+            .mount(&server)
+            .await;
+
+        fs.discover_torrents(None).await.unwrap();
+        assert!(!fs.awaiting_metadata.contains(&1));
+        assert!(fs.inode_manager.lookup_by_path("/abc123").is_none());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir/file2.txt")
+            .is_some());
     }
 
-    #[tokio::test]
-    async fn test_build_mount_options() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_root_listing_fetches_stale_torrent_list() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            root_refresh_interval: 1,
+            metadata_snapshot: Some(dir.path().join("snapshot.json")),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
 
-        let options = fs.build_mount_options();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "torrents": [] })),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        assert!(fs.refresh_torrents(true).await);
+        assert_eq!(fs.torrent_count(), 0);
+
+        // Added in rqbit while the empty list is still cached
+        let added = serde_json::to_value(multi_file_torrent_info(1, "abc123")).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "torrents": [added.clone()] })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(added))
+            .mount(&server)
+            .await;
+
+        // Within the interval the listing doesn't wait on rqbit
+        let listing = fs.clone();
+        tokio::task::spawn_blocking(move || listing.refresh_for_root_listing())
+            .await
+            .unwrap();
+        assert_eq!(fs.torrent_count(), 0);
 
-        // Check that required options are present
-        assert!(options.contains(&fuser::MountOption::RO));
-        assert!(options.contains(&fuser::MountOption::NoSuid));
-        assert!(options.contains(&fuser::MountOption::AutoUnmount));
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let listing = fs.clone();
+        tokio::task::spawn_blocking(move || listing.refresh_for_root_listing())
+            .await
+            .unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
     }
 
-    #[tokio::test]
-    async fn test_remove_torrent_cleans_up_inodes() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_deferred_file_list_fetched_on_first_access() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
 
-        // Create a torrent structure manually
-        let torrent_id = 123u64;
-        let torrent_inode =
-            fs.inode_manager
-                .allocate_torrent_directory(torrent_id, "test_torrent".to_string(), 1);
-        fs.inode_manager.add_child(1, torrent_inode);
+        let full = multi_file_torrent_info(1, "abc123");
+        let summary = crate::api::types::TorrentInfo {
+            files: Vec::new(),
+            deferred_size: Some(3072),
+            ..full.clone()
+        };
+        fs.create_torrent_structure(&summary).unwrap();
+        let torrent_dir = fs.inode_manager.lookup_by_path("/Multi File").unwrap();
+        assert!(fs.inode_manager.get_children(torrent_dir).is_empty());
+        assert!(!fs.awaiting_metadata.contains(&1));
+        assert_eq!(fs.statfs_values().0, 1);
+
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(full))
+            .expect(1)
+            .mount(&server)
+            .await;
+        for _ in 0..2 {
+            let accessed = fs.clone();
+            tokio::task::spawn_blocking(move || accessed.materialize_dir(torrent_dir))
+                .await
+                .unwrap();
+        }
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .is_some());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir")
+            .is_some());
+    }
 
-        // Add a file to the torrent
-        let file_inode = fs.inode_manager.allocate_file(
-            "test.txt".to_string(),
-            torrent_inode,
-            torrent_id,
-            0,
-            1024,
-        );
-        fs.inode_manager.add_child(torrent_inode, file_inode);
+    #[tokio::test]
+    async fn test_page_cache_open_flags() {
+        use fuser::consts::{FOPEN_DIRECT_IO, FOPEN_KEEP_CACHE};
+
+        let open_flags = |config: Config, downloaded: Option<u64>| {
+            let fs = TorrentFS::new(
+                config,
+                Arc::new(crate::metrics::Metrics::new()),
+                create_test_async_worker(),
+            )
+            .unwrap();
+            fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+                .unwrap();
+            if let Some(downloaded) = downloaded {
+                fs.file_progress.insert((1, 0), downloaded);
+            }
+            let ino = fs
+                .inode_manager
+                .lookup_by_path("/Multi File/file1.txt")
+                .unwrap();
+            fs.open_flags(&fs.inode_manager.get(ino).unwrap())
+        };
+        let policy = |page_cache| Config {
+            page_cache,
+            ..Default::default()
+        };
 
-        // Verify structures exist
-        assert!(fs.inode_manager.get(torrent_inode).is_some());
-        assert!(fs.inode_manager.get(file_inode).is_some());
-        assert!(fs.inode_manager.lookup_torrent(torrent_id).is_some());
+        assert_eq!(open_flags(policy(PageCachePolicy::Drop), Some(1024)), 0);
+        assert_eq!(
+            open_flags(policy(PageCachePolicy::Keep), None),
+            FOPEN_KEEP_CACHE
+        );
+        assert_eq!(
+            open_flags(policy(PageCachePolicy::Bypass), None),
+            FOPEN_DIRECT_IO
+        );
+        let keep_complete = PageCachePolicy::KeepComplete;
+        assert_eq!(open_flags(policy(keep_complete), None), 0);
+        assert_eq!(open_flags(policy(keep_complete), Some(1000)), 0);
+        assert_eq!(
+            open_flags(policy(keep_complete), Some(1024)),
+            FOPEN_KEEP_CACHE
+        );
+        assert_eq!(open_flags(Config::default(), Some(1024)), FOPEN_KEEP_CACHE);
+        let config = Config {
+            page_cache_downloading: Some(PageCacheMode::Bypass),
+            ..Default::default()
+        };
+        assert_eq!(open_flags(config.clone(), Some(1000)), FOPEN_DIRECT_IO);
+        assert_eq!(open_flags(config, Some(1024)), FOPEN_KEEP_CACHE);
 
-        // Remove the torrent (this would normally call rqbit API)
-        // Since we can't call the API in tests, we manually clean up
-        fs.inode_manager.remove_child(1, torrent_inode);
-        fs.inode_manager.remove_inode(torrent_inode);
+        // Short reads need direct I/O whatever the policy
+        let config = Config {
+            short_reads: true,
+            ..policy(PageCachePolicy::Keep)
+        };
+        assert_eq!(open_flags(config, Some(1024)), FOPEN_DIRECT_IO);
+    }
 
-        // Verify structures are cleaned up
-        assert!(fs.inode_manager.get(torrent_inode).is_none());
-        assert!(fs.inode_manager.get(file_inode).is_none());
-        assert!(fs.inode_manager.lookup_torrent(torrent_id).is_none());
+    #[rstest::rstest]
+    #[case(PageCachePolicy::KeepComplete, 0)]
+    #[case(PageCachePolicy::Keep, fuser::consts::FOPEN_KEEP_CACHE)]
+    #[tokio::test]
+    async fn test_page_cache_dropped_once_complete(
+        #[case] page_cache: PageCachePolicy,
+        #[case] downloading_flags: u32,
+    ) {
+        use fuser::consts::FOPEN_KEEP_CACHE;
 
-        // Verify torrent is no longer in root's children
-        let root_children = fs.inode_manager.get_children(1);
-        assert!(!root_children.iter().any(|(ino, _)| *ino == torrent_inode));
+        let config = Config {
+            page_cache,
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+        let ino = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .unwrap();
+        let entry = fs.inode_manager.get(ino).unwrap();
+
+        fs.file_progress.insert((1, 0), 512);
+        assert_eq!(fs.open_flags(&entry), downloading_flags);
+        // The first open once complete drops what was cached before
+        fs.file_progress.insert((1, 0), 1024);
+        assert_eq!(fs.open_flags(&entry), 0);
+        assert_eq!(fs.open_flags(&entry), FOPEN_KEEP_CACHE);
     }
 
-    // Edge case tests
-    #[test]
-    fn test_sanitize_filename_path_traversal() {
-        // Path traversal attempts should be neutralized - all separators become _
-        assert_eq!(sanitize_filename("../../../etc/passwd"), "______etc_passwd");
-        assert_eq!(sanitize_filename(".."), "_");
-        // "../secret" -> "_/secret" -> "__secret"
-        assert_eq!(sanitize_filename("../secret"), "__secret");
-    }
+    #[tokio::test]
+    async fn test_file_advice() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+        let ino = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .unwrap();
+        let entry = fs.inode_manager.get(ino).unwrap();
+        let advise = |advice, offset, len| {
+            fs.advise(
+                &entry,
+                FadviseArgs {
+                    offset,
+                    len,
+                    advice,
+                },
+            )
+        };
 
-    #[test]
-    fn test_sanitize_filename_special_chars() {
-        // Special characters should be replaced
-        assert_eq!(sanitize_filename("file:name.txt"), "file_name.txt");
-        assert_eq!(sanitize_filename("file*name?.txt"), "file_name_.txt");
-        // Both < and > are replaced, resulting in double underscore between script tags
+        advise(Advice::Random, 0, 0);
         assert_eq!(
-            sanitize_filename("<script>alert(1)</script>"),
-            "_script_alert(1)__script_"
+            fs.file_advice.get(&(1, 0)).as_deref(),
+            Some(&Advice::Random)
+        );
+        advise(Advice::DontNeed, 0, 0);
+        assert_eq!(
+            fs.file_advice.get(&(1, 0)).as_deref(),
+            Some(&Advice::Random)
         );
+        advise(Advice::Normal, 0, 0);
+        assert!(fs.file_advice.is_empty());
+
+        // WILLNEED fetches the range, clipped to the 1024-byte file
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stream/0"))
+            .and(header("Range", "bytes=1000-1023"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(vec![0u8; 24]))
+            .expect(1)
+            .mount(&server)
+            .await;
+        advise(Advice::WillNeed, 1000, 4096);
+        for _ in 0..50 {
+            if !server.received_requests().await.unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        server.verify().await;
     }
 
-    #[test]
-    fn test_sanitize_filename_control_chars() {
-        // Control characters should be replaced
-        assert_eq!(sanitize_filename("file\x00name"), "file_name");
-        assert_eq!(sanitize_filename("file\nname"), "file_name");
-        assert_eq!(sanitize_filename("file\tname"), "file_name");
+    #[tokio::test]
+    async fn test_renamed_torrent_keeps_its_inodes() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            metadata_snapshot: Some(dir.path().join("snapshot.json")),
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+        let dir = fs.inode_manager.lookup_by_path("/Multi File").unwrap();
+        let file = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir/file2.txt")
+            .unwrap();
+
+        let mut renamed = multi_file_torrent_info(1, "abc123");
+        renamed.name = "Renamed".to_string();
+        let renamed = serde_json::to_value(renamed).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "torrents": [renamed.clone()] })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(renamed))
+            .mount(&server)
+            .await;
+
+        fs.discover_torrents(None).await.unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_none());
+        assert_eq!(fs.inode_manager.lookup_by_path("/Renamed"), Some(dir));
+        assert_eq!(
+            fs.inode_manager.lookup_by_path("/Renamed/subdir/file2.txt"),
+            Some(file)
+        );
+        assert_eq!(fs.inode_manager.lookup_torrent(1), Some(dir));
     }
 
-    #[test]
-    fn test_sanitize_filename_leading_dots() {
-        // Leading/trailing dots should be removed (prevents hidden files)
-        assert_eq!(sanitize_filename(".hidden"), "hidden");
-        assert_eq!(sanitize_filename("file."), "file");
-        assert_eq!(sanitize_filename("..double"), "_double");
+    #[tokio::test]
+    async fn test_duplicate_info_hash_shows_best_copy() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            metadata_snapshot: Some(dir.path().join("snapshot.json")),
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+
+        let first = serde_json::to_value(multi_file_torrent_info(1, "abc123")).unwrap();
+        let second = serde_json::to_value(multi_file_torrent_info(2, "ABC123")).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(
+                    serde_json::json!({ "torrents": [first.clone(), second.clone()] }),
+                ),
+            )
+            .mount(&server)
+            .await;
+        for (id, torrent, progress) in [(1, first, 1024), (2, second, 3072)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/torrents/{}", id)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(torrent))
+                .mount(&server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/torrents/{}/stats/v1", id)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "state": "live",
+                    "file_progress": [0, 0],
+                    "progress_bytes": progress,
+                    "uploaded_bytes": 0,
+                    "total_bytes": 3072,
+                    "finished": progress == 3072,
+                    "live": null
+                })))
+                .mount(&server)
+                .await;
+        }
+
+        // The complete copy replaces the mounted one under the same name
+        assert_eq!(fs.discover_torrents(None).await.unwrap(), [2]);
+        assert!(fs.inode_manager.lookup_torrent(1).is_none());
+        let dir = fs.inode_manager.lookup_by_path("/Multi File").unwrap();
+        assert_eq!(fs.inode_manager.lookup_torrent(2), Some(dir));
+        assert_eq!(fs.list_torrents(), [2]);
     }
 
-    #[test]
-    fn test_sanitize_filename_empty() {
-        // Empty names should be replaced with "unnamed"
-        assert_eq!(sanitize_filename(""), "unnamed");
-        assert_eq!(sanitize_filename("   "), "unnamed");
-        // "..." becomes "_." (".." replaced with "_", leaving "."), then trimmed to "_"
-        assert_eq!(sanitize_filename("..."), "_");
+    #[tokio::test]
+    async fn test_failed_torrent_gets_suffix_until_recovered() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            error_suffix: Some(" [failed]".to_string()),
+            ..Default::default()
+        };
+        let fs = TorrentFS::new(
+            config,
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        let torrent = multi_file_torrent_info(1, "abc123");
+        fs.create_torrent_structure(&torrent).unwrap();
+
+        let stats = |state: &str, error: Option<&str>| {
+            serde_json::json!({
+                "state": state,
+                "file_progress": [0, 0],
+                "error": error,
+                "progress_bytes": 0,
+                "uploaded_bytes": 0,
+                "total_bytes": 3072,
+                "finished": false,
+                "live": null
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(stats("error", Some("No space left on device"))),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats("live", None)))
+            .mount(&server)
+            .await;
+
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert_eq!(
+            fs.failed_torrents.get(&1).map(|m| m.clone()).as_deref(),
+            Some("No space left on device")
+        );
+        let failed_dir = fs.inode_manager.lookup_by_path("/Multi File [failed]");
+        assert_eq!(fs.inode_manager.lookup_torrent(1), failed_dir);
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_none());
+        let file = fs
+            .inode_manager
+            .lookup_by_path("/Multi File [failed]/file1.txt")
+            .unwrap();
+        assert_eq!(fs.torrent_id_for_inode(file), Some(1));
+
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert!(fs.failed_torrents.is_empty());
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File [failed]")
+            .is_none());
     }
 
-    #[test]
-    fn test_is_safe_path_component() {
-        // Safe components
-        assert!(is_safe_path_component("normal_file"));
-        assert!(is_safe_path_component("file.txt"));
-        assert!(is_safe_path_component("my-directory"));
+    #[tokio::test]
+    async fn test_offline_mount_serves_saved_snapshot() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let server = MockServer::start().await;
+        let torrent = serde_json::to_value(multi_file_torrent_info(1, "abc123")).unwrap();
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "torrents": [torrent.clone()] })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(torrent))
+            .mount(&server)
+            .await;
+
+        let config = Config {
+            api_url: server.uri(),
+            metadata_snapshot: Some(dir.path().join("snapshot.json")),
+            ..Default::default()
+        };
+        let new_fs = |config: Config| {
+            TorrentFS::new(
+                config,
+                Arc::new(crate::metrics::Metrics::new()),
+                create_test_async_worker(),
+            )
+            .unwrap()
+        };
+
+        // No snapshot of this server yet
+        let offline = Config {
+            offline: true,
+            ..config.clone()
+        };
+        assert!(discover_existing_torrents(&new_fs(offline.clone()))
+            .await
+            .is_err());
 
-        // Unsafe components
-        assert!(!is_safe_path_component(""));
-        assert!(!is_safe_path_component("."));
-        assert!(!is_safe_path_component(".."));
-        assert!(!is_safe_path_component("../.."));
-        assert!(!is_safe_path_component("dir/file"));
-        assert!(!is_safe_path_component("dir\\file"));
+        discover_existing_torrents(&new_fs(config)).await.unwrap();
+        drop(server);
+
+        let fs = new_fs(offline);
+        discover_existing_torrents(&fs).await.unwrap();
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/Multi File/subdir/file2.txt")
+            .is_some());
+        assert!(!fs.refresh_torrents(true).await);
     }
 
     #[tokio::test]
-    async fn test_symlink_creation() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_status_poll_refreshes_file_attrs() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
+        let mut torrent = multi_file_torrent_info(1, "abc123");
+        fs.create_torrent_structure(&torrent).unwrap();
+
+        let stats = |file_progress: [u64; 2]| {
+            serde_json::json!({
+                "state": "live",
+                "file_progress": file_progress,
+                "progress_bytes": 0,
+                "uploaded_bytes": 0,
+                "total_bytes": 3072,
+                "finished": false,
+                "live": null
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 0])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 8192])))
+            .mount(&server)
+            .await;
+
+        let attr = |path: &str| {
+            let ino = fs.inode_manager.lookup_by_path(path).unwrap();
+            fs.build_file_attr(&fs.inode_manager.get(ino).unwrap())
+        };
 
-        // Create a symlink
-        let symlink_inode =
-            fs.inode_manager
-                .allocate_symlink("link".to_string(), 1, "/target/path".to_string());
-
-        // Verify symlink exists
-        let entry = fs.inode_manager.get(symlink_inode).unwrap();
-        assert!(entry.is_symlink());
-        assert_eq!(entry.name(), "link");
-
-        // Verify attributes
-        let attr = fs.build_file_attr(&entry);
-        assert_eq!(attr.kind, fuser::FileType::Symlink);
-        assert_eq!(attr.size, "/target/path".len() as u64);
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert_eq!(attr("/Multi File/file1.txt").blocks, 1);
+        assert_eq!(attr("/Multi File/subdir/file2.txt").blocks, 0);
+
+        torrent.files[1].length = 8192;
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        let file2 = attr("/Multi File/subdir/file2.txt");
+        assert_eq!(file2.size, 8192);
+        assert_eq!(file2.blocks, 2);
+        assert_eq!(fs.statfs_values().0, 3);
     }
 
     #[tokio::test]
-    async fn test_zero_byte_file() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_hide_incomplete_files() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            hide_incomplete: true,
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
+        let torrent = multi_file_torrent_info(1, "abc123");
+        fs.create_torrent_structure(&torrent).unwrap();
+
+        let stats = |file_progress: [u64; 2]| {
+            serde_json::json!({
+                "state": "live",
+                "file_progress": file_progress,
+                "progress_bytes": 0,
+                "uploaded_bytes": 0,
+                "total_bytes": 3072,
+                "finished": false,
+                "live": null
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 100])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 2048])))
+            .mount(&server)
+            .await;
+
+        let entry = |path: &str| {
+            let ino = fs.inode_manager.lookup_by_path(path).unwrap();
+            fs.inode_manager.get(ino).unwrap()
+        };
 
-        // Create a zero-byte file
-        let file_inode = fs.inode_manager.allocate_file(
-            "empty.txt".to_string(),
-            1,
-            1,
-            0,
-            0, // Zero size
-        );
-
-        // Verify file exists
-        let entry = fs.inode_manager.get(file_inode).unwrap();
-        assert!(entry.is_file());
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert!(!fs.is_hidden(&entry("/Multi File/file1.txt")));
+        assert!(fs.is_hidden(&entry("/Multi File/subdir/file2.txt")));
+        assert!(!fs.is_hidden(&entry("/Multi File/subdir")));
 
-        // Verify attributes
-        let attr = fs.build_file_attr(&entry);
-        assert_eq!(attr.size, 0);
-        assert_eq!(attr.blocks, 0);
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert!(!fs.is_hidden(&entry("/Multi File/subdir/file2.txt")));
+        assert!(fs.incomplete_files.is_empty());
     }
 
     #[tokio::test]
-    async fn test_large_file() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_finished_files_bump_directory_mtime() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let config = Config {
+            api_url: server.uri(),
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
-
-        // Create a large file (>4GB)
-        let large_size = 5u64 * 1024 * 1024 * 1024; // 5 GB
-        let file_inode =
-            fs.inode_manager
-                .allocate_file("large.iso".to_string(), 1, 1, 0, large_size);
-
-        // Verify attributes
-        let entry = fs.inode_manager.get(file_inode).unwrap();
-        let attr = fs.build_file_attr(&entry);
-        assert_eq!(attr.size, large_size);
-        assert!(attr.blocks > 0);
+        let torrent = multi_file_torrent_info(1, "abc123");
+        fs.create_torrent_structure(&torrent).unwrap();
+
+        let stats = |file_progress: [u64; 2]| {
+            serde_json::json!({
+                "state": "live",
+                "file_progress": file_progress,
+                "progress_bytes": 0,
+                "uploaded_bytes": 0,
+                "total_bytes": 3072,
+                "finished": false,
+                "live": null
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 100])))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1/stats/v1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(stats([1024, 2048])))
+            .mount(&server)
+            .await;
+
+        let entry = |path: &str| {
+            let ino = fs.inode_manager.lookup_by_path(path).unwrap();
+            fs.inode_manager.get(ino).unwrap()
+        };
+        let mtime = |path: &str| fs.build_file_attr(&entry(path)).mtime;
+
+        // Without hide_incomplete, unfinished files are tracked but shown
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert!(!fs.is_hidden(&entry("/Multi File/subdir/file2.txt")));
+        assert_eq!(mtime("/Multi File/subdir"), fs.mounted_at);
+        assert_eq!(mtime("/"), fs.mounted_at);
+
+        fs.refresh_torrent_stats(std::slice::from_ref(&torrent))
+            .await;
+        assert!(mtime("/Multi File/subdir") > fs.mounted_at);
+        assert_eq!(mtime("/Multi File"), fs.mounted_at);
+        assert!(mtime("/") > fs.mounted_at);
     }
 
     #[tokio::test]
-    async fn test_unicode_filename() {
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_readdir_cache_follows_tree_changes() {
+        let config = Config {
+            readdir_cache_min_entries: 2,
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+
+        // Too small to cache
+        assert!(fs.cached_listing(1).is_none());
+
+        let torrent_dir = fs.inode_manager.lookup_by_path("/Multi File").unwrap();
+        let listing = fs.cached_listing(torrent_dir).unwrap();
+        let names: Vec<&str> = listing.iter().map(|(_, _, name)| name.as_str()).collect();
+        assert_eq!(names, ["file1.txt", "subdir"]);
+        assert!(Arc::ptr_eq(
+            &listing,
+            &fs.cached_listing(torrent_dir).unwrap()
+        ));
+
+        let file1 = fs
+            .inode_manager
+            .lookup_by_path("/Multi File/file1.txt")
+            .unwrap();
+        assert!(fs.inode_manager.rename(file1, "renamed.txt".to_string()));
+        let listing = fs.cached_listing(torrent_dir).unwrap();
+        assert!(listing.iter().any(|(_, _, name)| name == "renamed.txt"));
+    }
 
-        // Test various Unicode filenames
-        let unicode_names = vec![
-            "文件.txt",       // Chinese
-            "ファイル.txt",   // Japanese
-            "файл.txt",       // Russian
-            "αρχείο.txt",     // Greek
-            "📄document.txt", // Emoji
-            "naïve.txt",      // Accented
-        ];
+    #[rstest::rstest]
+    #[case(100, 10, vec![(0, 9), (90, 99)])]
+    #[case(20, 10, vec![(0, 19)])]
+    #[case(5, 10, vec![(0, 4)])]
+    fn test_head_tail_ranges(
+        #[case] size: u64,
+        #[case] length: u64,
+        #[case] expected: Vec<(u64, u64)>,
+    ) {
+        assert_eq!(head_tail_ranges(size, length), expected);
+    }
 
-        for name in unicode_names {
-            let inode = fs
-                .inode_manager
-                .allocate_file(name.to_string(), 1, 1, 0, 100);
-            let entry = fs.inode_manager.get(inode).unwrap();
-            assert_eq!(entry.name(), name);
-        }
+    #[test]
+    fn test_readdir_cookies_survive_tree_changes() {
+        // The first call lists . and .. and two children
+        let before = [3, 5, 8, 9];
+        let first: Vec<u64> = children_after(&before, 2).iter().take(2).copied().collect();
+        assert_eq!(first, [3, 5]);
+        let offset = readdir_cookie(5);
+
+        // Meanwhile 3 and 8 are removed and 12 is added
+        let after = [5, 9, 12];
+        assert_eq!(children_after(&after, offset), [9, 12]);
+
+        // Resuming after a removed entry continues with the next one
+        assert_eq!(children_after(&after, readdir_cookie(8)), [9, 12]);
+        assert!(children_after(&after, readdir_cookie(12)).is_empty());
     }
 
     #[tokio::test]
-    async fn test_single_file_torrent_structure() {
-        use crate::api::types::{FileInfo, TorrentInfo};
-
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
+    async fn test_nfs_export_options_and_statfs() {
+        let config = Config {
+            nfs_export: true,
+            ..Default::default()
+        };
         let fs = TorrentFS::new(
             config,
             Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
+            create_test_async_worker(),
         )
         .unwrap();
+        assert!(fs
+            .build_mount_options()
+            .contains(&fuser::MountOption::AllowOther));
+        assert_eq!(fs.statfs_values(), (0, 1, 99_999));
+
+        // 1024 + 2048 bytes round up to one block
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc"))
+            .unwrap();
+        let (blocks, files, ffree) = fs.statfs_values();
+        assert_eq!(blocks, 1);
+        assert_eq!(files + ffree, 100_000);
+
+        fs.remove_torrent_from_fs(1).await;
+        assert_eq!(fs.statfs_values().0, 0);
+    }
 
-        // Create a single-file torrent info
-        let torrent_info = TorrentInfo {
-            id: 1,
-            info_hash: "abc123".to_string(),
-            name: "Single File".to_string(),
-            output_folder: "/tmp".to_string(),
-            file_count: Some(1),
-            files: vec![FileInfo {
-                name: "file.txt".to_string(),
-                length: 1024,
-                components: vec!["file.txt".to_string()],
-            }],
-            piece_length: Some(262144),
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_trash_and_restore_torrent() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        for action in ["pause", "start"] {
+            Mock::given(method("POST"))
+                .and(path(format!("/torrents/1/{}", action)))
+                .respond_with(ResponseTemplate::new(200))
+                .expect(2)
+                .mount(&server)
+                .await;
+        }
+        let state = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            api_url: server.uri(),
+            trash_days: 7,
+            trash_state: Some(state.path().join("trash.json")),
+            ..Default::default()
         };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(
+            config.clone(),
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            Arc::clone(&async_worker),
+        )
+        .unwrap();
+        assert!(!fs.build_mount_options().contains(&fuser::MountOption::RO));
+        let torrent = multi_file_torrent_info(1, "abc123");
+        fs.create_torrent_structure(&torrent).unwrap();
+        let trash_dir = fs.inode_manager.lookup_by_path("/.trash").unwrap();
+
+        // Files inside a torrent can't be removed, only the whole torrent
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            let trash = blocking.trash.clone().unwrap();
+            let dir = blocking
+                .inode_manager
+                .lookup_by_path("/Multi File")
+                .unwrap();
+            assert_eq!(
+                blocking.trash_remove(&trash, dir, "file1.txt"),
+                Err(libc::EPERM)
+            );
+            assert_eq!(blocking.trash_remove(&trash, 1, "Multi File"), Ok(()));
+            assert_eq!(blocking.trash_remove(&trash, 1, ".trash"), Err(libc::EPERM));
+        })
+        .await
+        .unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_none());
+        assert!(fs
+            .inode_manager
+            .lookup_by_path("/.trash/Multi File/subdir/file2.txt")
+            .is_some());
+        let items = fs.trash_items();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].expires_at, items[0].trashed_at + 7 * 24 * 60 * 60);
+
+        // The trash survives a restart
+        let restarted =
+            TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+        restarted.create_torrent_structure(&torrent).unwrap();
+        assert!(restarted
+            .inode_manager
+            .lookup_by_path("/.trash/Multi File")
+            .is_some());
 
-        // Create structure
-        fs.create_torrent_structure(&torrent_info).unwrap();
+        assert_eq!(
+            fs.restore_from_trash("ABC123").await.unwrap(),
+            items.into_iter().next()
+        );
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
+        assert!(fs.trash_items().is_empty());
+        assert_eq!(fs.restore_from_trash("Multi File").await.unwrap(), None);
+
+        // Moving into the trash and back to where it came from
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            let trash = blocking.trash.clone().unwrap();
+            assert_eq!(
+                blocking.trash_rename(&trash, 1, "Multi File", trash_dir),
+                Ok(())
+            );
+            assert_eq!(
+                blocking.trash_rename(&trash, trash_dir, "Multi File", 1),
+                Ok(())
+            );
+        })
+        .await
+        .unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
+        assert!(
+            !Trash::open(&state.path().join("trash.json"), Duration::ZERO)
+                .unwrap()
+                .contains("abc123")
+        );
+    }
 
-        // Verify file was added directly to root (no directory)
-        let root_children = fs.inode_manager.get_children(1);
-        assert_eq!(root_children.len(), 1);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_add_torrents_written_to_add_file() {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let magnet = "magnet:?xt=urn:btih:abc123";
+        let url = "https://example.com/b.torrent";
+        Mock::given(method("POST"))
+            .and(path("/torrents"))
+            .and(body_json(serde_json::json!({ "magnet_link": magnet })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "id": 1, "info_hash": "abc123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/torrents"))
+            .and(body_json(serde_json::json!({ "torrent_link": url })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "id": 1, "info_hash": "abc123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(multi_file_torrent_info(1, "abc123")),
+            )
+            .mount(&server)
+            .await;
 
-        let (inode, entry) = &root_children[0];
-        assert!(entry.is_file());
-        assert_eq!(entry.name(), "file.txt");
+        let config = Config {
+            api_url: server.uri(),
+            add_torrents: true,
+            ..Default::default()
+        };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+        assert!(!fs.build_mount_options().contains(&fuser::MountOption::RO));
+        let add = fs.inode_manager.lookup_by_path("/.rqbit-fuse/add").unwrap();
+        let entry = fs.inode_manager.get(add).unwrap();
+        assert!(is_writable(&entry));
+        assert_eq!(fs.build_file_attr(&entry).perm, 0o666);
+
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            // The URL's line is only complete once flushed
+            let written = format!("{}\n{}", magnet, &url[..10]);
+            assert_eq!(
+                blocking.add_written_links(7, written.as_bytes(), false),
+                Ok(())
+            );
+            assert!(blocking
+                .inode_manager
+                .lookup_by_path("/Multi File/file1.txt")
+                .is_some());
+            assert_eq!(
+                blocking.add_written_links(7, &url.as_bytes()[10..], false),
+                Ok(())
+            );
+            assert_eq!(blocking.add_written_links(7, &[], true), Ok(()));
 
-        // Verify torrent mapping points to file
-        let torrent_inode = fs.inode_manager.lookup_torrent(1).unwrap();
-        assert_eq!(torrent_inode, *inode);
+            assert_eq!(
+                blocking.add_written_links(8, b"not a link\n", false),
+                Err(libc::EINVAL)
+            );
+        })
+        .await
+        .unwrap();
+        assert_eq!(fs.torrent_count(), 1);
     }
 
-    #[tokio::test]
-    async fn test_multi_file_torrent_structure() {
-        use crate::api::types::{FileInfo, TorrentInfo};
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_torrent_file_copied_into_root() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/torrents"))
+            .and(header("content-type", "application/x-bittorrent"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "id": 1, "info_hash": "abc123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(multi_file_torrent_info(1, "abc123")),
+            )
+            .mount(&server)
+            .await;
 
-        let config = Config::default();
-        let async_worker = create_test_async_worker();
-        let fs = TorrentFS::new(
-            config,
-            Arc::new(crate::metrics::Metrics::new()),
-            async_worker,
-        )
+        let config = Config {
+            api_url: server.uri(),
+            add_torrents: true,
+            ..Default::default()
+        };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+
+        let mut torrent = b"d4:infod6:lengthi4e4:name1:t12:piece lengthi4e6:pieces20:".to_vec();
+        torrent.extend_from_slice(&[0; 20]);
+        torrent.extend_from_slice(b"ee");
+
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            let fs = blocking;
+            let control_dir = fs.inode_manager.lookup_by_path("/.rqbit-fuse").unwrap();
+            for (parent, name) in [(control_dir, "a.torrent"), (1, "notes.txt")] {
+                assert_eq!(
+                    fs.create_torrent_upload(1000, parent, name, libc::O_WRONLY)
+                        .err(),
+                    Some(libc::EROFS)
+                );
+            }
+
+            let (entry, fh) = fs
+                .create_torrent_upload(1000, 1, "Multi.torrent", libc::O_WRONLY)
+                .unwrap();
+            assert!(is_writable(&entry));
+            assert_eq!(
+                fs.create_torrent_upload(1000, 1, "Multi.torrent", libc::O_WRONLY)
+                    .err(),
+                Some(libc::EEXIST)
+            );
+            // Written in two parts, the second first
+            assert_eq!(fs.write_torrent_upload(fh, 10, &torrent[10..]), Ok(()));
+            assert_eq!(fs.write_torrent_upload(fh, 0, &torrent[..10]), Ok(()));
+            assert_eq!(fs.add_torrent_upload(fh), Ok(()));
+            assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
+            // Flushed again by a duplicate descriptor
+            assert_eq!(fs.add_torrent_upload(fh), Ok(()));
+
+            fs.file_handles.remove(fh);
+            fs.remove_torrent_upload(entry.ino());
+            assert!(fs.inode_manager.lookup_by_path("/Multi.torrent").is_none());
+
+            let (_, fh) = fs
+                .create_torrent_upload(1000, 1, "bad.torrent", libc::O_WRONLY)
+                .unwrap();
+            assert_eq!(
+                fs.write_torrent_upload(fh, MAX_TORRENT_FILE_SIZE as i64, b"x"),
+                Err(libc::EFBIG)
+            );
+            assert_eq!(fs.write_torrent_upload(fh, 0, b"not a torrent"), Ok(()));
+            assert_eq!(fs.add_torrent_upload(fh), Err(libc::EINVAL));
+        })
+        .await
         .unwrap();
+        assert_eq!(fs.torrent_count(), 1);
+    }
 
-        // Create a multi-file torrent info
-        let torrent_info = TorrentInfo {
-            id: 2,
-            info_hash: "def456".to_string(),
-            name: "Multi File".to_string(),
-            output_folder: "/tmp".to_string(),
-            file_count: Some(2),
-            files: vec![
-                FileInfo {
-                    name: "file1.txt".to_string(),
-                    length: 1024,
-                    components: vec!["file1.txt".to_string()],
-                },
-                FileInfo {
-                    name: "file2.txt".to_string(),
-                    length: 2048,
-                    components: vec!["subdir".to_string(), "file2.txt".to_string()],
-                },
-            ],
-            piece_length: Some(262144),
-        };
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_torrent_file_copied_from_a_torrent() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mut torrent = b"d4:infod6:lengthi4e4:name1:t12:piece lengthi4e6:pieces20:".to_vec();
+        torrent.extend_from_slice(&[0; 20]);
+        torrent.extend_from_slice(b"ee");
+        let mut stream = torrent.clone();
+        stream.resize(1024, 0);
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/2/stream/0"))
+            .respond_with(ResponseTemplate::new(206).set_body_bytes(stream))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "id": 1, "info_hash": "abc123" })),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/torrents/1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(multi_file_torrent_info(1, "abc123")),
+            )
+            .mount(&server)
+            .await;
 
-        // Create structure
-        fs.create_torrent_structure(&torrent_info).unwrap();
+        let config = Config {
+            api_url: server.uri(),
+            add_torrents: true,
+            ..Default::default()
+        };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+        let mut source = multi_file_torrent_info(2, "def456");
+        source.name = "Source".to_string();
+        fs.create_torrent_structure(&source).unwrap();
+
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            let fs = blocking;
+            let ino_in = fs
+                .inode_manager
+                .lookup_by_path("/Source/file1.txt")
+                .unwrap();
+            let fh_in = fs.file_handles.allocate(ino_in, 2, libc::O_RDONLY);
+            let (entry, fh_out) = fs
+                .create_torrent_upload(1000, 1, "Multi.torrent", libc::O_WRONLY)
+                .unwrap();
+            let len = torrent.len() as u64;
+
+            // Not into the torrent's own files
+            assert_eq!(
+                fs.copy_into_writable(1000, fh_in, 0, ino_in, fh_in, 0, len),
+                Err(libc::EOPNOTSUPP)
+            );
+            // Past the end of the source there is nothing to copy
+            assert_eq!(
+                fs.copy_into_writable(1000, fh_in, 1024, entry.ino(), fh_out, 0, len),
+                Ok(0)
+            );
+            assert_eq!(
+                fs.copy_into_writable(1000, fh_in, 0, entry.ino(), fh_out, 0, len),
+                Ok(torrent.len() as u32)
+            );
+            assert_eq!(fs.add_torrent_upload(fh_out), Ok(()));
+            assert!(fs.inode_manager.lookup_by_path("/Multi File").is_some());
+        })
+        .await
+        .unwrap();
+    }
 
-        // Verify directory was created
-        let root_children = fs.inode_manager.get_children(1);
-        assert_eq!(root_children.len(), 1);
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_remove_torrents() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/torrents/1/delete"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = Config {
+            api_url: server.uri(),
+            remove_torrents: TorrentRemoval::Delete,
+            stats_files: true,
+            ..Default::default()
+        };
+        let metrics = Arc::new(crate::metrics::Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(create_api_client(&server.uri(), None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            100,
+        ));
+        let fs = TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap();
+        assert!(!fs.build_mount_options().contains(&fuser::MountOption::RO));
+        fs.create_torrent_structure(&multi_file_torrent_info(1, "abc123"))
+            .unwrap();
+
+        let blocking = fs.clone();
+        tokio::task::spawn_blocking(move || {
+            let fs = blocking;
+            assert_eq!(fs.remove_entry(1, ".rqbit-fuse"), Err(libc::EPERM));
+
+            let file = fs
+                .inode_manager
+                .lookup_by_path("/Multi File/subdir/file2.txt")
+                .unwrap();
+            let fh = fs.file_handles.allocate(file, 1, libc::O_RDONLY);
+            assert_eq!(fs.remove_entry(1, "Multi File"), Err(libc::EBUSY));
+            fs.file_handles.remove(fh);
+
+            // Files inside a torrent can't be removed, only the whole torrent
+            let dir = fs.inode_manager.lookup_by_path("/Multi File").unwrap();
+            assert_eq!(fs.remove_entry(dir, "file1.txt"), Err(libc::EPERM));
+            assert_eq!(fs.remove_entry(dir, "subdir"), Err(libc::EPERM));
+            assert!(fs
+                .inode_manager
+                .lookup_by_path("/Multi File/file1.txt")
+                .is_some());
+            assert_eq!(fs.remove_entry(1, "Multi File"), Ok(()));
+            assert_eq!(fs.remove_entry(1, "Multi File"), Err(libc::ENOENT));
+        })
+        .await
+        .unwrap();
+        assert!(fs.inode_manager.lookup_by_path("/Multi File").is_none());
+        assert_eq!(fs.torrent_count(), 0);
 
-        let (_dir_inode, entry) = &root_children[0];
-        assert!(entry.is_directory());
-        assert_eq!(entry.name(), "Multi File");
+        let read_only = TorrentFS::new(
+            Config::default(),
+            Arc::new(crate::metrics::Metrics::new()),
+            create_test_async_worker(),
+        )
+        .unwrap();
+        assert!(read_only
+            .build_mount_options()
+            .contains(&fuser::MountOption::RO));
+        assert_eq!(read_only.remove_entry(1, "Multi File"), Err(libc::EROFS));
     }
 }
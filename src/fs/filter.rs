@@ -0,0 +1,347 @@
+//! Torrent selection filters applied during discovery.
+
+use crate::api::types::TorrentInfo;
+use crate::config::FilterConfig;
+use crate::error::RqbitFuseError;
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+
+/// Compiled form of [`FilterConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct TorrentFilter {
+    include_name: Option<Regex>,
+    exclude_name: Option<Regex>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    tags: Vec<String>,
+    tag_directories: bool,
+    output_folder_root: Option<PathBuf>,
+    hide_files: Vec<Regex>,
+}
+
+impl TorrentFilter {
+    pub fn new(config: &FilterConfig) -> Result<Self, RqbitFuseError> {
+        let compile = |key: &str, pattern: &Option<String>| {
+            pattern.as_deref().map(Regex::new).transpose().map_err(|e| {
+                RqbitFuseError::ValidationError(vec![format!("filter.{}: {}", key, e)])
+            })
+        };
+
+        Ok(Self {
+            include_name: compile("include_name_regex", &config.include_name_regex)?,
+            exclude_name: compile("exclude_name_regex", &config.exclude_name_regex)?,
+            min_size: config.min_size,
+            max_size: config.max_size,
+            tags: config.tags.clone(),
+            tag_directories: config.tag_directories,
+            output_folder_root: config.output_folder_root.clone(),
+            hide_files: config
+                .hide_files
+                .iter()
+                .map(|pattern| {
+                    Regex::new(&glob_to_regex(pattern)).map_err(|e| {
+                        RqbitFuseError::ValidationError(vec![format!(
+                            "filter.hide_files: Invalid pattern {:?}: {}",
+                            pattern, e
+                        )])
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Returns true if the torrent should appear in the mount.
+    pub fn matches(&self, torrent: &TorrentInfo) -> bool {
+        if let Some(include) = &self.include_name {
+            if !include.is_match(&torrent.name) {
+                return false;
+            }
+        }
+        if let Some(exclude) = &self.exclude_name {
+            if exclude.is_match(&torrent.name) {
+                return false;
+            }
+        }
+
+        let total_size: u64 = torrent.files.iter().map(|f| f.length).sum();
+        if self.min_size.is_some_and(|min| total_size < min) {
+            return false;
+        }
+        if self.max_size.is_some_and(|max| total_size > max) {
+            return false;
+        }
+
+        if !self.tags.is_empty() {
+            let torrent_tags = torrent.tags.as_deref().unwrap_or_default();
+            if !torrent_tags.iter().any(|t| self.tags.contains(t)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Returns true if a file of a torrent, given by its path components
+    /// within the torrent, is left out of the tree by `hide_files`.
+    pub fn hides_file(&self, components: &[String]) -> bool {
+        if self.hide_files.is_empty() {
+            return false;
+        }
+        let path = components.join("/");
+        self.hide_files.iter().any(|glob| glob.is_match(&path))
+    }
+
+    /// Returns true if the filter can only match torrents that report tags.
+    pub fn requires_tags(&self) -> bool {
+        !self.tags.is_empty()
+    }
+
+    /// Returns the tag whose directory the torrent is grouped under, if tag
+    /// directories are enabled: the first of its tags the filter selects, or
+    /// its first tag when no tags are configured.
+    pub fn directory_tag<'a>(&self, torrent: &'a TorrentInfo) -> Option<&'a str> {
+        if !self.tag_directories {
+            return None;
+        }
+        let torrent_tags = torrent.tags.as_deref()?;
+        torrent_tags
+            .iter()
+            .find(|t| self.tags.is_empty() || self.tags.contains(t))
+            .map(String::as_str)
+    }
+
+    /// Returns the directories the torrent is grouped under by output
+    /// folder, outermost first: the folder's path below
+    /// `output_folder_root`. Empty when the option is unset or the folder
+    /// isn't below it.
+    pub fn folder_directories<'a>(&self, torrent: &'a TorrentInfo) -> Vec<&'a str> {
+        let Some(root) = &self.output_folder_root else {
+            return Vec::new();
+        };
+        match Path::new(&torrent.output_folder).strip_prefix(root) {
+            Ok(relative) => relative
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => name.to_str(),
+                    _ => None,
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Translates a `hide_files` glob into a regex over a file's path within
+/// its torrent. `*` and `?` stay within one path component and `**` spans
+/// any number; the pattern matches the whole path or any trailing part of
+/// it starting at a component, so `*.nfo` matches in every directory.
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("(?:^|/)");
+    let mut chars = glob.trim_start_matches('/').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::FileInfo;
+
+    fn tagged(tags: &[&str]) -> TorrentInfo {
+        TorrentInfo {
+            tags: Some(tags.iter().map(|t| t.to_string()).collect()),
+            ..torrent("tagged", 1)
+        }
+    }
+
+    fn torrent(name: &str, size: u64) -> TorrentInfo {
+        TorrentInfo {
+            id: 1,
+            info_hash: "abc".to_string(),
+            name: name.to_string(),
+            output_folder: "/tmp".to_string(),
+            file_count: Some(1),
+            files: vec![FileInfo {
+                name: name.to_string(),
+                length: size,
+                components: vec![name.to_string()],
+            }],
+            piece_length: None,
+            tags: None,
+            deferred_size: None,
+        }
+    }
+
+    #[test]
+    fn test_default_filter_matches_everything() {
+        let filter = TorrentFilter::default();
+        assert!(filter.matches(&torrent("anything", 0)));
+    }
+
+    #[rstest::rstest]
+    #[case("Movie.2024.1080p.mkv", 4 << 30, true)]
+    #[case("Movie.2024.1080p.sample.mkv", 4 << 30, false)]
+    #[case("linux.iso", 4 << 30, false)]
+    #[case("Tiny.mkv", 1024, false)]
+    #[case("Huge.mkv", 100 << 30, false)]
+    fn test_filter_matches(#[case] name: &str, #[case] size: u64, #[case] expected: bool) {
+        let filter = TorrentFilter::new(&FilterConfig {
+            include_name_regex: Some(r"(?i)\.(mkv|mp4)$".to_string()),
+            exclude_name_regex: Some(r"(?i)sample".to_string()),
+            min_size: Some(100 << 20),
+            max_size: Some(50 << 30),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(filter.matches(&torrent(name, size)), expected);
+    }
+
+    #[test]
+    fn test_invalid_regex_is_validation_error() {
+        let err = TorrentFilter::new(&FilterConfig {
+            include_name_regex: Some("(".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("filter.include_name_regex"));
+    }
+
+    #[test]
+    fn test_tag_filter() {
+        let filter = TorrentFilter::new(&FilterConfig {
+            tags: vec!["media".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filter.requires_tags());
+        assert!(filter.matches(&tagged(&["linux", "media"])));
+        assert!(!filter.matches(&tagged(&["linux"])));
+        // Servers without tag support report no tags at all
+        assert!(!filter.matches(&torrent("untagged", 1)));
+    }
+
+    #[test]
+    fn test_directory_tag() {
+        let plain = TorrentFilter::default();
+        assert_eq!(plain.directory_tag(&tagged(&["media"])), None);
+
+        let grouped = TorrentFilter::new(&FilterConfig {
+            tag_directories: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            grouped.directory_tag(&tagged(&["movies", "media"])),
+            Some("movies")
+        );
+        assert_eq!(grouped.directory_tag(&torrent("untagged", 1)), None);
+
+        let selected = TorrentFilter::new(&FilterConfig {
+            tags: vec!["media".to_string()],
+            tag_directories: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            selected.directory_tag(&tagged(&["movies", "media"])),
+            Some("media")
+        );
+    }
+
+    #[rstest::rstest]
+    #[case("/downloads/tv/Some Show", vec!["tv", "Some Show"])]
+    #[case("/downloads/", vec![])]
+    #[case("/downloads2/tv", vec![])]
+    #[case("/elsewhere/tv", vec![])]
+    fn test_folder_directories(#[case] output_folder: &str, #[case] expected: Vec<&str>) {
+        let in_folder = TorrentInfo {
+            output_folder: output_folder.to_string(),
+            ..torrent("t", 1)
+        };
+        assert!(TorrentFilter::default()
+            .folder_directories(&in_folder)
+            .is_empty());
+
+        let grouped = TorrentFilter::new(&FilterConfig {
+            output_folder_root: Some(PathBuf::from("/downloads")),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(grouped.folder_directories(&in_folder), expected);
+    }
+
+    #[rstest::rstest]
+    #[case(&["Movie.nfo"], true)]
+    #[case(&["Extras", "Movie.nfo"], true)]
+    #[case(&["Movie.nfo.mkv"], false)]
+    #[case(&["Movie.sfv"], true)]
+    #[case(&["sample", "clip.mkv"], true)]
+    #[case(&["Movie", "sample", "clip.mkv"], true)]
+    #[case(&["sample", "deep", "clip.mkv"], false)]
+    #[case(&["samples", "clip.mkv"], false)]
+    #[case(&["Movie.mkv"], false)]
+    #[case(&["Proof", "a.jpg"], true)]
+    #[case(&["Proof", "b.png"], false)]
+    fn test_hides_file(#[case] components: &[&str], #[case] expected: bool) {
+        let filter = TorrentFilter::new(&FilterConfig {
+            hide_files: vec![
+                "*.nfo".to_string(),
+                "*.sfv".to_string(),
+                "sample/*".to_string(),
+                "Proof/[!b]*.jpg".to_string(),
+            ],
+            ..Default::default()
+        })
+        .unwrap();
+        let components: Vec<String> = components.iter().map(|c| c.to_string()).collect();
+        assert_eq!(filter.hides_file(&components), expected);
+        assert!(!TorrentFilter::default().hides_file(&components));
+    }
+
+    #[test]
+    fn test_hide_files_double_star() {
+        let filter = TorrentFilter::new(&FilterConfig {
+            hide_files: vec!["Extras/**".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        let path = |p: &str| p.split('/').map(str::to_string).collect::<Vec<_>>();
+        assert!(filter.hides_file(&path("Extras/a/b.mkv")));
+        assert!(!filter.hides_file(&path("Movie.mkv")));
+
+        let err = TorrentFilter::new(&FilterConfig {
+            hide_files: vec!["[z-a]".to_string()],
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("filter.hide_files"), "{}", err);
+    }
+}
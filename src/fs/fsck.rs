@@ -0,0 +1,254 @@
+//! Consistency check of a mount's inode tree against the rqbit torrent list.
+
+use crate::fs::filesystem::TorrentFS;
+use crate::fs::inode::InodeEntry;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tracing::info;
+
+/// A difference between the mounted tree and rqbit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FsckIssue {
+    /// A torrent directory whose torrent rqbit no longer has (or that the
+    /// mount no longer selects).
+    Orphan { torrent_id: u64, path: String },
+    /// A torrent rqbit has that is missing from the mount.
+    Missing { torrent_id: u64, name: String },
+    /// A file whose size differs from rqbit's.
+    SizeMismatch {
+        torrent_id: u64,
+        path: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+/// Result of checking one mount.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FsckReport {
+    pub mount_point: PathBuf,
+    pub issues: Vec<FsckIssue>,
+    /// Whether the issues were repaired in place.
+    pub repaired: bool,
+}
+
+impl TorrentFS {
+    /// Compares the inode tree with the torrents rqbit reports and, with
+    /// `repair`, removes orphans, adds missing torrents and rebuilds
+    /// torrents with mismatched file sizes.
+    pub async fn fsck(&self, repair: bool) -> Result<FsckReport> {
+        let result = self.api_client().list_torrents().await?;
+        // Torrents rqbit failed to describe are neither orphans nor missing
+        let unknown: Vec<u64> = result.errors.iter().map(|(id, _, _)| *id).collect();
+        let torrents: HashMap<u64, _> = result
+            .torrents
+            .into_iter()
+            .filter(|t| self.selects_torrent(t))
+            .map(|t| (t.id, t))
+            .collect();
+
+        let inodes = self.inode_manager();
+        let mut mapped: Vec<(u64, u64)> = inodes
+            .torrent_to_inode()
+            .iter()
+            .map(|item| (*item.key(), *item.value()))
+            .collect();
+        mapped.sort_unstable();
+
+        let mut issues = Vec::new();
+        for &(torrent_id, inode) in &mapped {
+            if !torrents.contains_key(&torrent_id) && !unknown.contains(&torrent_id) {
+                issues.push(FsckIssue::Orphan {
+                    torrent_id,
+                    path: inodes
+                        .get_path_for_inode(inode)
+                        .unwrap_or_else(|| "/".to_string()),
+                });
+            }
+        }
+
+        let mut torrent_ids: Vec<_> = torrents.keys().copied().collect();
+        torrent_ids.sort_unstable();
+        for torrent_id in torrent_ids {
+            let torrent = &torrents[&torrent_id];
+            if inodes.lookup_torrent(torrent_id).is_none() {
+                issues.push(FsckIssue::Missing {
+                    torrent_id,
+                    name: torrent.name.clone(),
+                });
+                continue;
+            }
+            let mut files: Vec<_> = inodes
+                .iter_entries()
+                .filter_map(|e| match e.entry {
+                    InodeEntry::File {
+                        torrent_id: id,
+                        file_index,
+                        size,
+                        ..
+                    } if id == torrent_id => Some((e.inode, file_index, size)),
+                    _ => None,
+                })
+                .collect();
+            files.sort_unstable();
+            for (inode, file_index, actual) in files {
+                let Some(file) = torrent.files.get(file_index as usize) else {
+                    continue;
+                };
+                if file.length != actual {
+                    issues.push(FsckIssue::SizeMismatch {
+                        torrent_id,
+                        path: inodes.get_path_for_inode(inode).unwrap_or_default(),
+                        expected: file.length,
+                        actual,
+                    });
+                }
+            }
+        }
+
+        let repaired = repair && !issues.is_empty();
+        if repaired {
+            for issue in &issues {
+                match issue {
+                    FsckIssue::Orphan { torrent_id, .. } => {
+                        self.remove_torrent_from_fs(*torrent_id).await;
+                    }
+                    FsckIssue::Missing { torrent_id, .. } => {
+                        self.create_torrent_structure(&torrents[torrent_id])?;
+                        self.known_torrents().insert(*torrent_id);
+                    }
+                    FsckIssue::SizeMismatch { torrent_id, .. } => {
+                        // Rebuild once per torrent
+                        if inodes.lookup_torrent(*torrent_id).is_some() {
+                            self.remove_torrent_from_fs(*torrent_id).await;
+                            self.create_torrent_structure(&torrents[torrent_id])?;
+                            self.known_torrents().insert(*torrent_id);
+                        }
+                    }
+                }
+            }
+            info!(
+                "fsck repaired {} issue(s) in {}",
+                issues.len(),
+                self.mount_point().display()
+            );
+        }
+
+        Ok(FsckReport {
+            mount_point: self.mount_point().to_path_buf(),
+            issues,
+            repaired,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::TorrentInfo;
+    use crate::api::TorrentClient;
+    use crate::config::Config;
+    use crate::fs::async_bridge::AsyncFuseWorker;
+    use crate::metrics::Metrics;
+    use std::sync::Arc;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn torrent(id: u64, name: &str, length: u64) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "info_hash": format!("hash{}", id),
+            "name": name,
+            "output_folder": "/downloads",
+            "file_count": 2,
+            "files": [
+                {"name": "a.bin", "length": length, "components": ["a.bin"]},
+                {"name": "b.bin", "length": 10, "components": ["b.bin"]}
+            ],
+            "piece_length": 1048576
+        })
+    }
+
+    async fn mock_rqbit(torrents: &[serde_json::Value]) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/torrents"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "torrents": torrents })),
+            )
+            .mount(&server)
+            .await;
+        for t in torrents {
+            Mock::given(method("GET"))
+                .and(path(format!("/torrents/{}", t["id"])))
+                .respond_with(ResponseTemplate::new(200).set_body_json(t))
+                .mount(&server)
+                .await;
+        }
+        server
+    }
+
+    fn create_test_fs(api_url: String) -> TorrentFS {
+        let config = Config {
+            api_url,
+            ..Default::default()
+        };
+        let metrics = Arc::new(Metrics::new());
+        let api_client: Arc<dyn TorrentClient> =
+            Arc::new(crate::api::client::RqbitClient::new(config.api_url.clone()).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(
+            Arc::clone(&api_client),
+            Arc::clone(&metrics),
+            10,
+        ));
+        TorrentFS::with_api_client(config, api_client, metrics, async_worker).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fsck_reports_and_repairs() {
+        let server = mock_rqbit(&[torrent(1, "resized", 2048), torrent(3, "new", 10)]).await;
+        let fs = create_test_fs(server.uri());
+
+        let stale: TorrentInfo = serde_json::from_value(torrent(1, "resized", 1024)).unwrap();
+        let gone: TorrentInfo = serde_json::from_value(torrent(2, "gone", 10)).unwrap();
+        fs.create_torrent_structure(&stale).unwrap();
+        fs.create_torrent_structure(&gone).unwrap();
+
+        let report = fs.fsck(false).await.unwrap();
+        assert!(!report.repaired);
+        assert_eq!(
+            report.issues,
+            vec![
+                FsckIssue::Orphan {
+                    torrent_id: 2,
+                    path: "/gone".to_string()
+                },
+                FsckIssue::SizeMismatch {
+                    torrent_id: 1,
+                    path: "/resized/a.bin".to_string(),
+                    expected: 2048,
+                    actual: 1024
+                },
+                FsckIssue::Missing {
+                    torrent_id: 3,
+                    name: "new".to_string()
+                },
+            ]
+        );
+        // A dry run leaves the tree alone
+        assert!(fs.inode_manager().lookup_torrent(2).is_some());
+
+        let report = fs.fsck(true).await.unwrap();
+        assert!(report.repaired);
+        assert_eq!(report.issues.len(), 3);
+
+        let report = fs.fsck(false).await.unwrap();
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert!(fs.inode_manager().lookup_torrent(2).is_none());
+        assert!(fs.inode_manager().lookup_torrent(3).is_some());
+    }
+}
@@ -1,5 +1,8 @@
 use dashmap::DashSet;
 use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+use super::virtual_files::VirtualFile;
 
 #[derive(Debug, Clone)]
 pub enum InodeEntry {
@@ -18,6 +21,8 @@ pub enum InodeEntry {
         file_index: u64,
         size: u64,
         canonical_path: String,
+        /// Last access time recorded under the configured atime policy
+        atime: Option<SystemTime>,
     },
     Symlink {
         ino: u64,
@@ -26,6 +31,15 @@ pub enum InodeEntry {
         target: String,
         canonical_path: String,
     },
+    /// A file rqbit-fuse generates for a torrent, like its stats
+    Virtual {
+        ino: u64,
+        name: String,
+        parent: u64,
+        torrent_id: u64,
+        file: VirtualFile,
+        canonical_path: String,
+    },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -46,6 +60,8 @@ enum InodeEntryData {
         file_index: u64,
         size: u64,
         canonical_path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        atime: Option<SystemTime>,
     },
     Symlink {
         ino: u64,
@@ -54,6 +70,14 @@ enum InodeEntryData {
         target: String,
         canonical_path: String,
     },
+    Virtual {
+        ino: u64,
+        name: String,
+        parent: u64,
+        torrent_id: u64,
+        file: VirtualFile,
+        canonical_path: String,
+    },
 }
 
 impl From<&InodeEntry> for InodeEntryData {
@@ -80,6 +104,7 @@ impl From<&InodeEntry> for InodeEntryData {
                 file_index,
                 size,
                 canonical_path,
+                atime,
             } => InodeEntryData::File {
                 ino: *ino,
                 name: name.clone(),
@@ -88,6 +113,7 @@ impl From<&InodeEntry> for InodeEntryData {
                 file_index: *file_index,
                 size: *size,
                 canonical_path: canonical_path.clone(),
+                atime: *atime,
             },
             InodeEntry::Symlink {
                 ino,
@@ -102,6 +128,21 @@ impl From<&InodeEntry> for InodeEntryData {
                 target: target.clone(),
                 canonical_path: canonical_path.clone(),
             },
+            InodeEntry::Virtual {
+                ino,
+                name,
+                parent,
+                torrent_id,
+                file,
+                canonical_path,
+            } => InodeEntryData::Virtual {
+                ino: *ino,
+                name: name.clone(),
+                parent: *parent,
+                torrent_id: *torrent_id,
+                file: *file,
+                canonical_path: canonical_path.clone(),
+            },
         }
     }
 }
@@ -130,6 +171,7 @@ impl From<InodeEntryData> for InodeEntry {
                 file_index,
                 size,
                 canonical_path,
+                atime,
             } => InodeEntry::File {
                 ino,
                 name,
@@ -138,6 +180,7 @@ impl From<InodeEntryData> for InodeEntry {
                 file_index,
                 size,
                 canonical_path,
+                atime,
             },
             InodeEntryData::Symlink {
                 ino,
@@ -152,6 +195,21 @@ impl From<InodeEntryData> for InodeEntry {
                 target,
                 canonical_path,
             },
+            InodeEntryData::Virtual {
+                ino,
+                name,
+                parent,
+                torrent_id,
+                file,
+                canonical_path,
+            } => InodeEntry::Virtual {
+                ino,
+                name,
+                parent,
+                torrent_id,
+                file,
+                canonical_path,
+            },
         }
     }
 }
@@ -184,20 +242,20 @@ macro_rules! match_fields {
 
 impl InodeEntry {
     pub fn ino(&self) -> u64 {
-        *match_fields!(self, Directory => ino, File => ino, Symlink => ino)
+        *match_fields!(self, Directory => ino, File => ino, Symlink => ino, Virtual => ino)
     }
 
     pub fn name(&self) -> &str {
-        match_fields!(self, Directory => name, File => name, Symlink => name)
+        match_fields!(self, Directory => name, File => name, Symlink => name, Virtual => name)
     }
 
     pub fn parent(&self) -> u64 {
-        *match_fields!(self, Directory => parent, File => parent, Symlink => parent)
+        *match_fields!(self, Directory => parent, File => parent, Symlink => parent, Virtual => parent)
     }
 
     /// Returns the stored canonical path
     pub fn canonical_path(&self) -> &str {
-        match_fields!(self, Directory => canonical_path, File => canonical_path, Symlink => canonical_path)
+        match_fields!(self, Directory => canonical_path, File => canonical_path, Symlink => canonical_path, Virtual => canonical_path)
     }
 
     /// Returns the torrent_id if this is a file
@@ -220,6 +278,24 @@ impl InodeEntry {
         matches!(self, InodeEntry::Symlink { .. })
     }
 
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, InodeEntry::Virtual { .. })
+    }
+
+    /// Moves the entry to another directory; see `set_path` for its path.
+    pub(crate) fn set_parent(&mut self, parent: u64) {
+        *match_fields!(self, Directory => parent, File => parent, Symlink => parent, Virtual => parent) =
+            parent;
+    }
+
+    /// Gives the entry a new name and canonical path, keeping its inode.
+    pub(crate) fn set_path(&mut self, name: String, canonical_path: String) {
+        *match_fields!(self, Directory => name, File => name, Symlink => name, Virtual => name) =
+            name;
+        *match_fields!(self, Directory => canonical_path, File => canonical_path, Symlink => canonical_path, Virtual => canonical_path) =
+            canonical_path;
+    }
+
     /// Returns a new InodeEntry with the specified inode number
     pub fn with_ino(&self, ino: u64) -> Self {
         match self {
@@ -243,6 +319,7 @@ impl InodeEntry {
                 file_index,
                 size,
                 canonical_path,
+                atime,
                 ..
             } => InodeEntry::File {
                 ino,
@@ -252,6 +329,7 @@ impl InodeEntry {
                 file_index: *file_index,
                 size: *size,
                 canonical_path: canonical_path.clone(),
+                atime: *atime,
             },
             InodeEntry::Symlink {
                 name,
@@ -266,6 +344,21 @@ impl InodeEntry {
                 target: target.clone(),
                 canonical_path: canonical_path.clone(),
             },
+            InodeEntry::Virtual {
+                name,
+                parent,
+                torrent_id,
+                file,
+                canonical_path,
+                ..
+            } => InodeEntry::Virtual {
+                ino,
+                name: name.clone(),
+                parent: *parent,
+                torrent_id: *torrent_id,
+                file: *file,
+                canonical_path: canonical_path.clone(),
+            },
         }
     }
 }
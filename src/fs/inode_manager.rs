@@ -3,6 +3,7 @@ use dashmap::DashSet;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use super::inode_entry::InodeEntry;
+use super::virtual_files::VirtualFile;
 
 /// Manages inode allocation and mapping between inodes and filesystem entries.
 pub struct InodeManager {
@@ -11,6 +12,16 @@ pub struct InodeManager {
     path_to_inode: DashMap<String, u64>,
     torrent_to_inode: DashMap<u64, u64>,
     max_inodes: usize,
+    /// Outstanding kernel lookups per inode (see `forget`)
+    lookup_counts: DashMap<u64, u64>,
+    /// Removed entries the kernel still references, kept until forgotten
+    orphans: DashMap<u64, InodeEntry>,
+    /// Generation reported with every inode number (see `generation`)
+    generation: AtomicU64,
+    /// Bumped whenever a directory's children or an entry's name change
+    tree_version: AtomicU64,
+    /// Number of child directories per directory, for its link count
+    subdirs: DashMap<u64, u32>,
 }
 
 #[derive(Debug)]
@@ -49,9 +60,37 @@ impl InodeManager {
             path_to_inode,
             torrent_to_inode,
             max_inodes,
+            lookup_counts: DashMap::new(),
+            orphans: DashMap::new(),
+            generation: AtomicU64::new(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(1),
+            ),
+            tree_version: AtomicU64::new(0),
+            subdirs: DashMap::new(),
         }
     }
 
+    /// Generation number paired with inode numbers in lookup replies. It
+    /// starts at the creation time, so NFS file handles from an earlier mount
+    /// go stale instead of resolving to a different file, and changes
+    /// whenever inode numbers are handed out again.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Changes whenever an entry is added, removed, renamed or moved, so
+    /// views derived from the tree can tell they are stale.
+    pub fn tree_version(&self) -> u64 {
+        self.tree_version.load(Ordering::SeqCst)
+    }
+
+    fn tree_changed(&self) {
+        self.tree_version.fetch_add(1, Ordering::SeqCst);
+    }
+
     /// Check if a new inode can be allocated.
     pub fn can_allocate(&self) -> bool {
         if self.max_inodes > 0 {
@@ -99,6 +138,7 @@ impl InodeManager {
         if let Some(id) = torrent_id {
             self.torrent_to_inode.insert(id, inode);
         }
+        self.tree_changed();
 
         inode
     }
@@ -139,6 +179,7 @@ impl InodeManager {
             file_index,
             size,
             canonical_path,
+            atime: None,
         };
         self.allocate_entry(entry, None)
     }
@@ -156,6 +197,27 @@ impl InodeManager {
         self.allocate_entry(entry, None)
     }
 
+    /// Allocates a file whose contents rqbit-fuse generates for a torrent.
+    pub fn allocate_virtual_file(
+        &self,
+        name: String,
+        parent: u64,
+        torrent_id: u64,
+        file: VirtualFile,
+    ) -> u64 {
+        let canonical_path = self.build_canonical_path(parent, &name);
+
+        let entry = InodeEntry::Virtual {
+            ino: 0,
+            name,
+            parent,
+            torrent_id,
+            file,
+            canonical_path,
+        };
+        self.allocate_entry(entry, None)
+    }
+
     fn build_canonical_path(&self, parent: u64, name: &str) -> String {
         if let Some(parent_entry) = self.entries.get(&parent) {
             let parent_path = parent_entry.canonical_path();
@@ -171,7 +233,10 @@ impl InodeManager {
 
     /// Looks up an inode by its number.
     pub fn get(&self, inode: u64) -> Option<InodeEntry> {
-        self.entries.get(&inode).map(|e| e.clone())
+        self.entries
+            .get(&inode)
+            .or_else(|| self.orphans.get(&inode))
+            .map(|e| e.clone())
     }
 
     /// Looks up an inode by its path.
@@ -193,7 +258,40 @@ impl InodeManager {
 
     /// Check if an inode exists in the manager.
     pub fn contains(&self, inode: u64) -> bool {
-        self.entries.contains_key(&inode)
+        self.entries.contains_key(&inode) || self.orphans.contains_key(&inode)
+    }
+
+    /// Records that the kernel was handed a reference to `inode` (a lookup
+    /// reply). Each one is released by a later `forget`.
+    pub fn add_lookup(&self, inode: u64) {
+        *self.lookup_counts.entry(inode).or_insert(0) += 1;
+    }
+
+    /// Returns the number of outstanding kernel lookups for `inode`.
+    pub fn lookup_count(&self, inode: u64) -> u64 {
+        self.lookup_counts.get(&inode).map_or(0, |c| *c)
+    }
+
+    /// Releases `nlookup` kernel references to `inode`. Once none remain, a
+    /// removed inode is dropped for good. Returns true if it was dropped.
+    pub fn forget(&self, inode: u64, nlookup: u64) -> bool {
+        let remaining = match self.lookup_counts.get_mut(&inode) {
+            Some(mut count) => {
+                *count = count.saturating_sub(nlookup);
+                *count
+            }
+            None => 0,
+        };
+        if remaining > 0 {
+            return false;
+        }
+        self.lookup_counts.remove(&inode);
+        self.orphans.remove(&inode).is_some()
+    }
+
+    /// Number of removed inodes still referenced by the kernel.
+    pub fn orphan_count(&self) -> usize {
+        self.orphans.len()
     }
 
     /// Iterate over all entries (read-only).
@@ -250,11 +348,39 @@ impl InodeManager {
 
         self.entries
             .iter()
-            .filter(|entry| entry.parent() == parent_inode)
+            .filter(|entry| entry.parent() == parent_inode && entry.ino() != parent_inode)
             .map(|entry| (entry.ino(), entry.clone()))
             .collect()
     }
 
+    /// Inode numbers of a directory's children in ascending order, without
+    /// cloning their entries.
+    pub fn child_inodes(&self, parent_inode: u64) -> Vec<u64> {
+        let mut inodes: Vec<u64> = match self.entries.get(&parent_inode).as_deref() {
+            Some(InodeEntry::Directory { children, .. }) if !children.is_empty() => {
+                children.iter().map(|child| *child).collect()
+            }
+            Some(InodeEntry::Directory { .. }) => self
+                .entries
+                .iter()
+                .filter(|entry| entry.parent() == parent_inode && entry.ino() != parent_inode)
+                .map(|entry| entry.ino())
+                .collect(),
+            _ => Vec::new(),
+        };
+        inodes.sort_unstable();
+        inodes
+    }
+
+    /// Calls `f` with the entry of `inode` without cloning it. The entry is
+    /// locked while `f` runs, so `f` must not modify the inode manager.
+    pub fn with_entry<R>(&self, inode: u64, f: impl FnOnce(&InodeEntry) -> R) -> Option<R> {
+        match self.entries.get(&inode) {
+            Some(entry) => Some(f(&entry)),
+            None => self.orphans.get(&inode).map(|entry| f(&entry)),
+        }
+    }
+
     /// Gets the next inode number without allocating it.
     /// Useful for getting the current state.
     pub fn next_inode(&self) -> u64 {
@@ -292,21 +418,23 @@ impl InodeManager {
         }
 
         // Step 2: Remove from parent's children list atomically
-        if entry.parent() != 1 {
-            if let Some(mut parent_entry) = self.entries.get_mut(&entry.parent()) {
-                if let InodeEntry::Directory { children, .. } = &mut *parent_entry {
-                    children.retain(|&c| c != inode);
+        if let Some(mut parent_entry) = self.entries.get_mut(&entry.parent()) {
+            if let InodeEntry::Directory { children, .. } = &mut *parent_entry {
+                if children.remove(&inode).is_some() && entry.is_directory() {
+                    self.subdir_removed(entry.parent());
                 }
             }
         }
+        self.subdirs.remove(&inode);
 
         // Step 3: Remove from indices using the path we built before
         // Build path once and use it for both lookups
         let path = self.build_path(&entry);
         self.path_to_inode.remove(&path);
 
-        // Remove from torrent mapping if it's a torrent directory
-        if entry.is_directory() && entry.parent() == 1 {
+        // Remove from torrent mapping if it's a torrent's top-level entry
+        // (a directory, or the file itself for single-file torrents)
+        if entry.parent() == 1 {
             // Find and remove all torrent_id mappings to this inode
             let torrent_ids: Vec<u64> = self
                 .torrent_to_inode
@@ -319,9 +447,21 @@ impl InodeManager {
             }
         }
 
+        self.tree_changed();
+
         // Step 4: Finally remove from primary entries map
-        // This is the authoritative removal - after this the inode is truly gone
-        self.entries.remove(&inode).is_some()
+        // If the kernel still holds lookups (e.g. open files), keep the entry
+        // reachable by inode number until it forgets them, so it sees the
+        // file go away instead of ESTALE on an inode it still uses
+        match self.entries.remove(&inode) {
+            Some((_, entry)) => {
+                if self.lookup_count(inode) > 0 {
+                    self.orphans.insert(inode, entry);
+                }
+                true
+            }
+            None => false,
+        }
     }
 
     /// Clears all torrent entries atomically but keeps the root inode.
@@ -349,8 +489,12 @@ impl InodeManager {
         self.path_to_inode.insert("/".to_string(), 1);
         self.torrent_to_inode.clear();
 
-        // Reset next inode counter
-        self.next_inode.store(2, Ordering::SeqCst);
+        // Reset next inode counter, unless removed inodes are still in use
+        // and their numbers must not be handed out again
+        if self.orphans.is_empty() {
+            self.next_inode.store(2, Ordering::SeqCst);
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        }
     }
 
     /// Builds the full path for an inode.
@@ -369,9 +513,14 @@ impl InodeManager {
 
     /// Adds a child to a directory's children list.
     pub fn add_child(&self, parent: u64, child: u64) {
+        self.tree_changed();
+        let is_dir = self.entries.get(&child).is_some_and(|e| e.is_directory());
         if let Some(mut entry) = self.entries.get_mut(&parent) {
             if let InodeEntry::Directory { children, .. } = &mut *entry {
                 if children.insert(child) {
+                    if is_dir {
+                        *self.subdirs.entry(parent).or_insert(0) += 1;
+                    }
                     tracing::info!(
                         "Added child {} to directory {} (total: {})",
                         child,
@@ -389,14 +538,120 @@ impl InodeManager {
         }
     }
 
+    /// Records the last access time of a file. Returns false for non-files.
+    pub fn set_atime(&self, inode: u64, time: std::time::SystemTime) -> bool {
+        match self.entries.get_mut(&inode).as_deref_mut() {
+            Some(InodeEntry::File { atime, .. }) => {
+                *atime = Some(time);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Updates the size of a file. Returns true if it changed.
+    pub fn set_size(&self, inode: u64, new_size: u64) -> bool {
+        match self.entries.get_mut(&inode).as_deref_mut() {
+            Some(InodeEntry::File { size, .. }) if *size != new_size => {
+                *size = new_size;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Renames an entry in place and rewrites the paths of everything below
+    /// it; inode numbers don't change, so open files keep working. Returns
+    /// false if the entry doesn't exist or its new name is already taken.
+    pub fn rename(&self, inode: u64, name: String) -> bool {
+        if inode == 1 {
+            return false;
+        }
+        let Some(parent) = self.entries.get(&inode).map(|e| e.parent()) else {
+            return false;
+        };
+        let path = self.build_canonical_path(parent, &name);
+        if self.path_to_inode.contains_key(&path) {
+            return false;
+        }
+        self.set_path(inode, name, path);
+        true
+    }
+
+    /// Moves an entry into another directory under the same name, keeping
+    /// its inode like `rename`. Returns false if the entry or directory
+    /// doesn't exist or the name is taken there.
+    pub fn move_entry(&self, inode: u64, new_parent: u64) -> bool {
+        if inode == 1 || !self.get(new_parent).is_some_and(|e| e.is_directory()) {
+            return false;
+        }
+        let Some((parent, name)) = self
+            .entries
+            .get(&inode)
+            .map(|e| (e.parent(), e.name().to_string()))
+        else {
+            return false;
+        };
+        let path = self.build_canonical_path(new_parent, &name);
+        if self.path_to_inode.contains_key(&path) {
+            return false;
+        }
+        self.remove_child(parent, inode);
+        if let Some(mut entry) = self.entries.get_mut(&inode) {
+            entry.set_parent(new_parent);
+        }
+        self.add_child(new_parent, inode);
+        self.set_path(inode, name, path);
+        true
+    }
+
+    fn set_path(&self, inode: u64, name: String, path: String) {
+        self.tree_changed();
+        let children: Vec<u64> = {
+            let Some(mut entry) = self.entries.get_mut(&inode) else {
+                return;
+            };
+            self.path_to_inode
+                .remove_if(entry.canonical_path(), |_, i| *i == inode);
+            entry.set_path(name, path.clone());
+            match &*entry {
+                InodeEntry::Directory { children, .. } => children.iter().map(|c| *c).collect(),
+                _ => Vec::new(),
+            }
+        };
+        for child in children {
+            let Some(child_name) = self.entries.get(&child).map(|e| e.name().to_string()) else {
+                continue;
+            };
+            let child_path = format!("{}/{}", path, child_name);
+            self.set_path(child, child_name, child_path);
+        }
+        self.path_to_inode.insert(path, inode);
+    }
+
     /// Removes a child from a directory's children list.
     pub fn remove_child(&self, parent: u64, child: u64) {
+        self.tree_changed();
+        let is_dir = self.entries.get(&child).is_some_and(|e| e.is_directory());
         if let Some(mut entry) = self.entries.get_mut(&parent) {
             if let InodeEntry::Directory { children, .. } = &mut *entry {
-                children.remove(&child);
+                if children.remove(&child).is_some() && is_dir {
+                    self.subdir_removed(parent);
+                }
             }
         }
     }
+
+    /// Number of directories in a directory, without visiting its children.
+    pub fn subdir_count(&self, inode: u64) -> u32 {
+        self.subdirs.get(&inode).map_or(0, |count| *count)
+    }
+
+    fn subdir_removed(&self, parent: u64) {
+        if let Some(mut count) = self.subdirs.get_mut(&parent) {
+            *count = count.saturating_sub(1);
+        }
+    }
 }
 
 impl Default for InodeManager {
@@ -503,6 +758,147 @@ mod tests {
         assert_eq!(manager.lookup_by_path("/test_torrent/file.txt"), Some(3));
     }
 
+    #[test]
+    fn test_child_inodes_sorted() {
+        let manager = InodeManager::new();
+        let torrent_inode = manager.allocate_torrent_directory(1, "t".to_string(), 1);
+        manager.add_child(1, torrent_inode);
+        let mut files = Vec::new();
+        for i in 0..50 {
+            let file = manager.allocate_file(format!("f{}", i), torrent_inode, 1, i, 10);
+            manager.add_child(torrent_inode, file);
+            files.push(file);
+        }
+
+        assert_eq!(manager.child_inodes(torrent_inode), files);
+        assert_eq!(manager.child_inodes(1), [torrent_inode]);
+        assert!(manager.child_inodes(files[0]).is_empty());
+        assert_eq!(
+            manager.with_entry(files[3], |e| e.name().to_string()),
+            Some("f3".to_string())
+        );
+        assert_eq!(manager.with_entry(9999, |_| ()), None);
+    }
+
+    #[test]
+    fn test_tree_version_changes_with_the_tree() {
+        let manager = create_test_manager();
+        let mut version = manager.tree_version();
+        let mut changed = |manager: &InodeManager| {
+            let current = manager.tree_version();
+            let changed = current != version;
+            version = current;
+            changed
+        };
+
+        let dir = manager.allocate_torrent_directory(1, "dir".to_string(), 1);
+        manager.add_child(1, dir);
+        assert!(changed(&manager));
+        manager.set_atime(dir, std::time::SystemTime::now());
+        assert!(!changed(&manager));
+        assert!(manager.rename(dir, "renamed".to_string()));
+        assert!(changed(&manager));
+        assert!(manager.remove_inode(dir));
+        assert!(changed(&manager));
+    }
+
+    #[test]
+    fn test_rename_moves_descendant_paths() {
+        let manager = create_test_manager();
+        let dir = manager.allocate_torrent_directory(1, "Old".to_string(), 1);
+        manager.add_child(1, dir);
+        let sub = manager.allocate(InodeEntry::Directory {
+            ino: 0,
+            name: "sub".to_string(),
+            parent: dir,
+            children: DashSet::new(),
+            canonical_path: "/Old/sub".to_string(),
+        });
+        manager.add_child(dir, sub);
+        let file = manager.allocate_file("a.txt".to_string(), sub, 1, 0, 10);
+        manager.add_child(sub, file);
+        let other = manager.allocate_torrent_directory(2, "Other".to_string(), 1);
+        manager.add_child(1, other);
+
+        assert!(manager.rename(dir, "New".to_string()));
+        assert_eq!(manager.lookup_by_path("/New"), Some(dir));
+        assert_eq!(manager.lookup_by_path("/New/sub/a.txt"), Some(file));
+        assert!(manager.lookup_by_path("/Old").is_none());
+        assert!(manager.lookup_by_path("/Old/sub/a.txt").is_none());
+        assert_eq!(
+            manager.get(file).unwrap().canonical_path(),
+            "/New/sub/a.txt"
+        );
+        assert_eq!(
+            manager.get_path_for_inode(file).as_deref(),
+            Some("/New/sub/a.txt")
+        );
+        assert_eq!(manager.lookup_torrent(1), Some(dir));
+
+        // Taken names and the root are left alone
+        assert!(!manager.rename(dir, "Other".to_string()));
+        assert_eq!(manager.lookup_by_path("/Other"), Some(other));
+        assert!(!manager.rename(1, "root".to_string()));
+    }
+
+    #[test]
+    fn test_move_entry() {
+        let manager = create_test_manager();
+        let dir = manager.allocate_torrent_directory(1, "Movie".to_string(), 1);
+        manager.add_child(1, dir);
+        let file = manager.allocate_file("a.mkv".to_string(), dir, 1, 0, 10);
+        manager.add_child(dir, file);
+        let trash = manager.allocate(InodeEntry::Directory {
+            ino: 0,
+            name: ".trash".to_string(),
+            parent: 1,
+            children: DashSet::new(),
+            canonical_path: "/.trash".to_string(),
+        });
+        manager.add_child(1, trash);
+
+        assert!(manager.move_entry(dir, trash));
+        assert_eq!(manager.lookup_by_path("/.trash/Movie/a.mkv"), Some(file));
+        assert!(manager.lookup_by_path("/Movie").is_none());
+        assert_eq!(manager.get(dir).unwrap().parent(), trash);
+        let children: Vec<u64> = manager.get_children(1).iter().map(|(i, _)| *i).collect();
+        assert_eq!(children, [trash]);
+        assert_eq!(manager.lookup_torrent(1), Some(dir));
+
+        // Not into files, nor onto a taken name
+        assert!(!manager.move_entry(dir, file));
+        let other = manager.allocate_torrent_directory(2, "Movie".to_string(), 1);
+        manager.add_child(1, other);
+        assert!(!manager.move_entry(other, trash));
+        assert!(!manager.move_entry(dir, 1));
+    }
+
+    #[test]
+    fn test_subdir_count() {
+        let manager = InodeManager::new();
+        let torrent_inode = manager.allocate_torrent_directory(1, "t".to_string(), 1);
+        manager.add_child(1, torrent_inode);
+        let file = manager.allocate_file("f".to_string(), torrent_inode, 1, 0, 10);
+        manager.add_child(torrent_inode, file);
+        let dirs: Vec<u64> = (0..3)
+            .map(|i| {
+                let dir = manager.allocate_torrent_directory(1, format!("d{}", i), torrent_inode);
+                manager.add_child(torrent_inode, dir);
+                dir
+            })
+            .collect();
+        assert_eq!(manager.subdir_count(1), 1);
+        assert_eq!(manager.subdir_count(torrent_inode), 3);
+
+        manager.remove_inode(dirs[0]);
+        manager.remove_child(torrent_inode, dirs[1]);
+        manager.remove_child(torrent_inode, file);
+        assert_eq!(manager.subdir_count(torrent_inode), 1);
+        manager.remove_inode(torrent_inode);
+        assert_eq!(manager.subdir_count(1), 0);
+        assert_eq!(manager.subdir_count(torrent_inode), 0);
+    }
+
     #[test]
     fn test_get_children() {
         let manager = create_test_manager();
@@ -565,6 +961,20 @@ mod tests {
         assert_eq!(manager.next_inode(), 2);
     }
 
+    #[test]
+    fn test_generation_changes_when_inode_numbers_are_reused() {
+        let manager = create_test_manager();
+        let generation = manager.generation();
+        assert!(generation > 0);
+
+        manager.allocate_torrent_directory(1, "torrent1".to_string(), 1);
+        assert_eq!(manager.generation(), generation);
+
+        manager.clear_torrents();
+        assert_eq!(manager.next_inode(), 2);
+        assert_ne!(manager.generation(), generation);
+    }
+
     #[test]
     fn test_allocate_symlink() {
         let manager = create_test_manager();
@@ -622,6 +1032,7 @@ mod tests {
             file_index: 0,
             size: 100,
             canonical_path: "/invalid.txt".to_string(),
+            atime: None,
         };
         if let dashmap::mapref::entry::Entry::Vacant(e) = manager.entries.entry(0) {
             e.insert(entry);
@@ -797,4 +1208,43 @@ mod tests {
         assert!(manager.get(cycle2_torrent).is_some());
         assert!(manager.lookup_torrent(20).is_some());
     }
+
+    #[test]
+    fn test_removed_inode_kept_until_forgotten() {
+        let manager = create_test_manager();
+        let dir = manager.allocate_torrent_directory(1, "Torrent".to_string(), 1);
+        manager.add_child(1, dir);
+        let file = manager.allocate_file("a.mkv".to_string(), dir, 1, 0, 100);
+        manager.add_child(dir, file);
+        let unused = manager.allocate_file("b.mkv".to_string(), dir, 1, 1, 100);
+        manager.add_child(dir, unused);
+
+        manager.add_lookup(file);
+        manager.add_lookup(file);
+        assert!(manager.remove_inode(dir));
+
+        // Gone from the tree, but still resolvable by inode number
+        assert!(manager.lookup_by_path("/Torrent/a.mkv").is_none());
+        assert!(manager.get_children(1).is_empty());
+        assert!(manager.get(file).is_some());
+        assert!(manager.get(unused).is_none());
+        assert_eq!(manager.orphan_count(), 1);
+
+        assert!(!manager.forget(file, 1));
+        assert!(manager.get(file).is_some());
+        assert!(manager.forget(file, 1));
+        assert!(manager.get(file).is_none());
+        assert_eq!(manager.orphan_count(), 0);
+    }
+
+    #[test]
+    fn test_clear_torrents_keeps_inode_numbers_of_orphans() {
+        let manager = create_test_manager();
+        let file = manager.allocate_file("a.mkv".to_string(), 1, 1, 0, 100);
+        manager.add_lookup(file);
+
+        manager.clear_torrents();
+        assert!(manager.get(file).is_some());
+        assert!(manager.allocate_file("b.mkv".to_string(), 1, 2, 0, 100) > file);
+    }
 }
@@ -1,10 +1,22 @@
 //! FUSE filesystem implementation for torrent access.
 
+pub mod activity;
 pub mod async_bridge;
+pub mod audit;
+pub mod export;
+pub mod fadvise;
 pub mod filesystem;
+pub mod filter;
+pub mod fsck;
 pub mod inode;
 pub mod inode_entry;
 pub mod inode_manager;
+pub mod quota;
+pub mod retry;
+pub mod session;
+pub mod snapshot;
+pub mod trash;
+pub mod virtual_files;
 
 pub use crate::error::{RqbitFuseError, RqbitFuseResult};
 pub use async_bridge::AsyncFuseWorker;
@@ -12,3 +24,4 @@ pub use filesystem::TorrentFS;
 // Re-exports from split modules for backward compatibility
 pub use inode_entry::InodeEntry;
 pub use inode_manager::{InodeEntryRef, InodeManager};
+pub use session::MountHandle;
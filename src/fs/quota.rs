@@ -0,0 +1,141 @@
+//! Per-user read quotas, so one user of a shared mount can't take all of
+//! rqbit's bandwidth.
+
+use crate::config::QuotaConfig;
+use dashmap::DashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// Bytes read by one uid in the current hour and day.
+#[derive(Debug, Default)]
+struct Usage {
+    hour: u64,
+    hour_bytes: u64,
+    day: u64,
+    day_bytes: u64,
+}
+
+impl Usage {
+    /// Starts new windows once the hour or day has rolled over.
+    fn roll(&mut self, secs: u64) {
+        if self.hour != secs / HOUR {
+            self.hour = secs / HOUR;
+            self.hour_bytes = 0;
+        }
+        if self.day != secs / DAY {
+            self.day = secs / DAY;
+            self.day_bytes = 0;
+        }
+    }
+}
+
+/// Tracks bytes read per uid against the `[quota]` limits. Windows are
+/// fixed UTC hours and days.
+#[derive(Debug)]
+pub struct ReadQuota {
+    config: QuotaConfig,
+    usage: DashMap<u32, Usage>,
+}
+
+impl ReadQuota {
+    pub fn new(config: QuotaConfig) -> Self {
+        Self {
+            config,
+            usage: DashMap::new(),
+        }
+    }
+
+    /// Returns true if either limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.config.per_hour.is_some() || self.config.per_day.is_some()
+    }
+
+    fn applies_to(&self, uid: u32) -> bool {
+        self.is_enabled() && !self.config.exempt_uids.contains(&uid)
+    }
+
+    /// Returns false once `uid` has used up its hourly or daily quota.
+    pub fn allows(&self, uid: u32, now: SystemTime) -> bool {
+        if !self.applies_to(uid) {
+            return true;
+        }
+        let Some(mut usage) = self.usage.get_mut(&uid) else {
+            return true;
+        };
+        usage.roll(unix_secs(now));
+        self.config
+            .per_hour
+            .is_none_or(|limit| usage.hour_bytes < limit)
+            && self
+                .config
+                .per_day
+                .is_none_or(|limit| usage.day_bytes < limit)
+    }
+
+    /// Counts `bytes` read by `uid` against its quota.
+    pub fn record(&self, uid: u32, bytes: u64, now: SystemTime) {
+        if !self.applies_to(uid) {
+            return;
+        }
+        let mut usage = self.usage.entry(uid).or_default();
+        usage.roll(unix_secs(now));
+        usage.hour_bytes += bytes;
+        usage.day_bytes += bytes;
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_quota_per_uid_and_window() {
+        let quota = ReadQuota::new(QuotaConfig {
+            per_hour: Some(100),
+            per_day: Some(250),
+            exempt_uids: vec![0],
+        });
+        let start = 10 * DAY;
+
+        quota.record(1000, 60, at(start));
+        assert!(quota.allows(1000, at(start)));
+        quota.record(1000, 60, at(start + 10));
+        assert!(!quota.allows(1000, at(start + 20)));
+        // Other users and exempt users are unaffected
+        assert!(quota.allows(1001, at(start + 20)));
+        quota.record(0, 1000, at(start));
+        assert!(quota.allows(0, at(start)));
+
+        // The next hour starts a new hourly window, but the day adds up
+        assert!(quota.allows(1000, at(start + HOUR)));
+        quota.record(1000, 99, at(start + HOUR));
+        assert!(quota.allows(1000, at(start + HOUR)));
+        quota.record(1000, 50, at(start + HOUR));
+        assert!(!quota.allows(1000, at(start + 2 * HOUR)));
+
+        // A new day resets both
+        assert!(quota.allows(1000, at(start + DAY)));
+    }
+
+    #[test]
+    fn test_quota_disabled() {
+        let quota = ReadQuota::new(QuotaConfig::default());
+        assert!(!quota.is_enabled());
+        quota.record(1000, u64::MAX, at(0));
+        assert!(quota.allows(1000, at(0)));
+        assert!(quota.usage.is_empty());
+    }
+}
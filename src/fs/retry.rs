@@ -0,0 +1,132 @@
+//! Retries of torrents whose structure couldn't be created.
+//!
+//! A torrent that fails to materialize at discovery (an API error while
+//! fetching its file list, the inode limit) is attempted again at later
+//! discoveries, each time waiting twice as long, until it succeeds or
+//! [`MAX_ATTEMPTS`] is reached.
+
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+/// Attempts after which a torrent is given up on, until rqbit drops it or
+/// the mount restarts.
+pub const MAX_ATTEMPTS: u32 = 8;
+/// Wait before the first retry, doubled after every further failure.
+const FIRST_DELAY: Duration = Duration::from_secs(10);
+/// Longest wait between two attempts.
+const MAX_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// Failures of one torrent.
+#[derive(Debug, Clone)]
+struct RetryState {
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+/// What to do after a failed attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOutcome {
+    /// Attempted again after `delay`.
+    RetryIn { attempt: u32, delay: Duration },
+    /// Reached [`MAX_ATTEMPTS`]; not attempted again.
+    GaveUp { attempts: u32 },
+}
+
+/// Torrents waiting for another attempt, by ID.
+#[derive(Debug, Default)]
+pub struct RetryList {
+    torrents: DashMap<u64, RetryState>,
+}
+
+impl RetryList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a torrent may be attempted at `now`: it never failed, or its
+    /// backoff has passed and it wasn't given up on.
+    pub fn is_due(&self, torrent_id: u64, now: Instant) -> bool {
+        self.torrents
+            .get(&torrent_id)
+            .is_none_or(|state| state.attempts < MAX_ATTEMPTS && now >= state.next_attempt)
+    }
+
+    /// Records a failed attempt at `now`.
+    pub fn record_failure(&self, torrent_id: u64, now: Instant) -> RetryOutcome {
+        let mut state = self.torrents.entry(torrent_id).or_insert(RetryState {
+            attempts: 0,
+            next_attempt: now,
+        });
+        state.attempts += 1;
+        if state.attempts >= MAX_ATTEMPTS {
+            return RetryOutcome::GaveUp {
+                attempts: state.attempts,
+            };
+        }
+        let delay = FIRST_DELAY
+            .saturating_mul(1 << (state.attempts - 1).min(16))
+            .min(MAX_DELAY);
+        state.next_attempt = now + delay;
+        RetryOutcome::RetryIn {
+            attempt: state.attempts,
+            delay,
+        }
+    }
+
+    /// Forgets the failures of a torrent, returning how many there were.
+    pub fn record_success(&self, torrent_id: u64) -> u32 {
+        self.torrents
+            .remove(&torrent_id)
+            .map_or(0, |(_, state)| state.attempts)
+    }
+
+    /// Drops torrents rqbit no longer lists.
+    pub fn retain(&self, current: &[u64]) {
+        self.torrents.retain(|id, _| current.contains(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_until_given_up() {
+        let retries = RetryList::new();
+        let start = Instant::now();
+        assert!(retries.is_due(1, start));
+
+        assert_eq!(
+            retries.record_failure(1, start),
+            RetryOutcome::RetryIn {
+                attempt: 1,
+                delay: FIRST_DELAY
+            }
+        );
+        assert!(!retries.is_due(1, start));
+        assert!(retries.is_due(1, start + FIRST_DELAY));
+        assert!(retries.is_due(2, start));
+
+        let mut now = start;
+        for attempt in 2..MAX_ATTEMPTS {
+            let RetryOutcome::RetryIn { delay, .. } = retries.record_failure(1, now) else {
+                panic!("gave up after {} attempts", attempt);
+            };
+            assert_eq!(delay, (FIRST_DELAY * 2u32.pow(attempt - 1)).min(MAX_DELAY));
+            now += delay;
+        }
+        assert_eq!(
+            retries.record_failure(1, now),
+            RetryOutcome::GaveUp {
+                attempts: MAX_ATTEMPTS
+            }
+        );
+        assert!(!retries.is_due(1, now + MAX_DELAY));
+
+        retries.retain(&[2]);
+        assert!(retries.is_due(1, now));
+        retries.record_failure(2, now);
+        assert_eq!(retries.record_success(2), 1);
+        assert!(retries.is_due(2, now));
+    }
+}
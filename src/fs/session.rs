@@ -0,0 +1,112 @@
+//! Background mounts for applications that already run a tokio runtime.
+
+use crate::fs::filesystem::TorrentFS;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::task::JoinHandle;
+
+/// A filesystem mounted by [`TorrentFS::spawn_mount()`].
+///
+/// The FUSE session runs on a blocking task of the runtime that spawned it.
+/// Dropping the handle unmounts the filesystem without waiting for the
+/// session to finish; use [`MountHandle::unmount()`] to wait.
+pub struct MountHandle {
+    mount_point: PathBuf,
+    fs: TorrentFS,
+    session: Option<JoinHandle<Result<()>>>,
+}
+
+impl TorrentFS {
+    /// Mounts the filesystem in the background and returns once the kernel
+    /// has accepted the mount.
+    ///
+    /// Must be called from within a tokio runtime; FUSE callbacks are served
+    /// on one of its blocking threads.
+    pub fn spawn_mount(self) -> Result<MountHandle> {
+        let mount_point = self.config().mount_point.clone();
+        let fs = self.clone();
+        let session = self.new_session()?;
+        let serving = fs.clone();
+        let session = tokio::task::spawn_blocking(move || serving.serve(session));
+
+        Ok(MountHandle {
+            mount_point,
+            fs,
+            session: Some(session),
+        })
+    }
+}
+
+impl MountHandle {
+    /// Returns the path the filesystem is mounted at.
+    pub fn mount_point(&self) -> &Path {
+        &self.mount_point
+    }
+
+    /// Returns the mounted filesystem.
+    pub fn filesystem(&self) -> &TorrentFS {
+        &self.fs
+    }
+
+    /// Returns true once the FUSE session has ended, e.g. after an external
+    /// `fusermount -u`.
+    pub fn is_finished(&self) -> bool {
+        self.session.as_ref().is_none_or(|s| s.is_finished())
+    }
+
+    /// Stops background tasks, unmounts the filesystem and waits for the
+    /// FUSE session to end.
+    pub async fn unmount(mut self) -> Result<()> {
+        self.fs.unmount()?;
+        self.wait().await
+    }
+
+    /// Waits until the filesystem is unmounted by someone else, then stops
+    /// background tasks.
+    pub async fn join(mut self) -> Result<()> {
+        let result = self.wait().await;
+        self.fs.shutdown();
+        result
+    }
+
+    async fn wait(&mut self) -> Result<()> {
+        let Some(session) = self.session.take() else {
+            return Ok(());
+        };
+        session.await.context("FUSE session panicked")?
+    }
+}
+
+impl Drop for MountHandle {
+    fn drop(&mut self) {
+        if self.session.is_some() {
+            let _ = self.fs.unmount();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::create_api_client;
+    use crate::config::Config;
+    use crate::fs::async_bridge::AsyncFuseWorker;
+    use crate::fs::filesystem::TorrentFS;
+    use crate::metrics::Metrics;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_spawn_mount_missing_mount_point() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            mount_point: dir.path().join("missing"),
+            ..Config::default()
+        };
+        let metrics = Arc::new(Metrics::new());
+        let api_client = Arc::new(create_api_client(&config.api_url, None, None, None).unwrap());
+        let async_worker = Arc::new(AsyncFuseWorker::new(api_client, Arc::clone(&metrics), 100));
+        let fs = TorrentFS::new(config, metrics, async_worker).unwrap();
+
+        let err = fs.spawn_mount().err().expect("mount should fail");
+        assert!(err.to_string().contains("missing"), "{}", err);
+    }
+}
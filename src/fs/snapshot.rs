@@ -0,0 +1,162 @@
+//! Snapshot of the torrent list for offline mounts.
+//!
+//! Every successful discovery saves the torrent list rqbit returned, keyed
+//! by API URL, so `--offline` can serve the same tree while rqbit is
+//! unreachable. Mounts of different servers share one state file.
+
+use crate::api::types::TorrentInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Serializes updates of the state file between mounts of one process.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Contents of the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SnapshotState {
+    /// rqbit API URL to its last torrent list.
+    servers: BTreeMap<String, Snapshot>,
+}
+
+/// The torrent list of one server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Unix seconds.
+    pub saved_at: u64,
+    pub torrents: Vec<TorrentInfo>,
+}
+
+/// Snapshot of one server in the state file.
+#[derive(Debug)]
+pub struct SnapshotStore {
+    path: PathBuf,
+    api_url: String,
+    /// Serialized list last written, to skip rewriting an unchanged list.
+    last_saved: Mutex<Option<String>>,
+}
+
+impl SnapshotStore {
+    pub fn new(path: &Path, api_url: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            api_url: api_url.to_string(),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the saved torrent list of this server, if any.
+    pub fn load(&self) -> std::io::Result<Option<Snapshot>> {
+        let _lock = STATE_LOCK.lock().unwrap();
+        Ok(read_state(&self.path)?.servers.remove(&self.api_url))
+    }
+
+    /// Saves `torrents` as this server's list, unless it is the list saved
+    /// last time.
+    pub fn save(&self, torrents: &[TorrentInfo]) -> std::io::Result<()> {
+        let serialized = serde_json::to_string(torrents)?;
+        let mut last_saved = self.last_saved.lock().unwrap();
+        if last_saved.as_deref() == Some(serialized.as_str()) {
+            return Ok(());
+        }
+
+        let _lock = STATE_LOCK.lock().unwrap();
+        let mut state = read_state(&self.path)?;
+        state.servers.insert(
+            self.api_url.clone(),
+            Snapshot {
+                saved_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                torrents: torrents.to_vec(),
+            },
+        );
+        write_state(&self.path, &state)?;
+        *last_saved = Some(serialized);
+        Ok(())
+    }
+}
+
+fn read_state(path: &Path) -> std::io::Result<SnapshotState> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(SnapshotState::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the state file atomically, so a crash never leaves it truncated.
+fn write_state(path: &Path, state: &SnapshotState) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::types::FileInfo;
+
+    fn torrent(id: u64, name: &str) -> TorrentInfo {
+        TorrentInfo {
+            id,
+            info_hash: format!("{:040x}", id),
+            name: name.to_string(),
+            output_folder: "/downloads".to_string(),
+            file_count: Some(1),
+            files: vec![FileInfo {
+                name: "a.bin".to_string(),
+                length: 10,
+                components: vec!["a.bin".to_string()],
+            }],
+            piece_length: Some(16384),
+            tags: None,
+            deferred_size: None,
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip_per_server() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state/snapshot.json");
+        let a = SnapshotStore::new(&path, "http://a:3030");
+        let b = SnapshotStore::new(&path, "http://b:3030");
+        assert!(a.load().unwrap().is_none());
+
+        a.save(&[torrent(1, "one")]).unwrap();
+        b.save(&[torrent(2, "two"), torrent(3, "three")]).unwrap();
+
+        let loaded = a.load().unwrap().unwrap();
+        assert_eq!(loaded.torrents.len(), 1);
+        assert_eq!(loaded.torrents[0].name, "one");
+        assert_eq!(loaded.torrents[0].files[0].length, 10);
+        assert_eq!(b.load().unwrap().unwrap().torrents.len(), 2);
+    }
+
+    #[test]
+    fn test_unchanged_list_is_not_rewritten() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("snapshot.json");
+        let store = SnapshotStore::new(&path, "http://a:3030");
+        store.save(&[torrent(1, "one")]).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        store.save(&[torrent(1, "one")]).unwrap();
+        assert!(!path.exists());
+
+        store.save(&[torrent(1, "renamed")]).unwrap();
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,180 @@
+//! Trash for removed torrents.
+//!
+//! With `trash_days` set, removing a torrent's directory pauses the torrent
+//! and moves it to `/.trash` instead of removing it from rqbit. Moving it
+//! back, or `rqbit-fuse trash --restore`, resumes it; after `trash_days` it
+//! is forgotten. Trashed torrents are recorded by info hash in a state file,
+//! so they stay in the trash across restarts.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the trash directory at the root of the mount.
+pub const TRASH_DIR: &str = ".trash";
+
+/// Mounts of one process may share the state file; updates re-read it under
+/// this lock so they don't drop each other's entries.
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Contents of the state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TrashState {
+    /// Lowercase info hash to the time it was trashed, in Unix seconds.
+    torrents: BTreeMap<String, u64>,
+}
+
+/// A torrent in the trash, as reported by `rqbit-fuse trash`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashItem {
+    pub torrent_id: u64,
+    pub name: String,
+    pub info_hash: String,
+    /// Unix seconds.
+    pub trashed_at: u64,
+    /// Unix seconds after which the torrent is forgotten.
+    pub expires_at: u64,
+}
+
+/// Trashed torrents of a mount and their state file.
+#[derive(Debug)]
+pub struct Trash {
+    path: PathBuf,
+    retention: Duration,
+    torrents: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Trash {
+    /// Loads the state file at `path`; a missing file is an empty trash.
+    pub fn open(path: &Path, retention: Duration) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            retention,
+            torrents: Mutex::new(read_state(path)?.torrents),
+        })
+    }
+
+    /// Returns when the torrent was trashed, in Unix seconds.
+    pub fn trashed_at(&self, info_hash: &str) -> Option<u64> {
+        self.torrents
+            .lock()
+            .unwrap()
+            .get(&info_hash.to_ascii_lowercase())
+            .copied()
+    }
+
+    pub fn contains(&self, info_hash: &str) -> bool {
+        self.trashed_at(info_hash).is_some()
+    }
+
+    /// Returns when a trashed torrent is due to be forgotten, in Unix seconds.
+    pub fn expires_at(&self, info_hash: &str) -> Option<u64> {
+        self.trashed_at(info_hash)
+            .map(|trashed_at| trashed_at + self.retention.as_secs())
+    }
+
+    /// Returns true once a trashed torrent has been in the trash for the
+    /// retention period.
+    pub fn is_expired(&self, info_hash: &str, now: SystemTime) -> bool {
+        self.expires_at(info_hash)
+            .is_some_and(|expires_at| unix_secs(now) >= expires_at)
+    }
+
+    /// Records a torrent as trashed at `now`.
+    pub fn put(&self, info_hash: &str, now: SystemTime) -> std::io::Result<()> {
+        let info_hash = info_hash.to_ascii_lowercase();
+        let trashed_at = unix_secs(now);
+        self.update(|torrents| {
+            torrents.insert(info_hash.clone(), trashed_at);
+        })
+    }
+
+    /// Takes a torrent out of the trash, after it is restored or forgotten.
+    pub fn remove(&self, info_hash: &str) -> std::io::Result<()> {
+        let info_hash = info_hash.to_ascii_lowercase();
+        self.update(|torrents| {
+            torrents.remove(&info_hash);
+        })
+    }
+
+    fn update(&self, change: impl Fn(&mut BTreeMap<String, u64>)) -> std::io::Result<()> {
+        let _lock = STATE_LOCK.lock().unwrap();
+        let mut state = read_state(&self.path)?;
+        change(&mut state.torrents);
+        write_state(&self.path, &state)?;
+        change(&mut self.torrents.lock().unwrap());
+        Ok(())
+    }
+}
+
+fn read_state(path: &Path) -> std::io::Result<TrashState> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(TrashState::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Replaces the state file atomically, so a crash never leaves it truncated.
+fn write_state(path: &Path, state: &TrashState) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(state)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_trash_expiry_and_persistence() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state/trash.json");
+        let trash = Trash::open(&path, Duration::from_secs(7 * DAY)).unwrap();
+        assert!(!trash.contains("abc"));
+
+        trash.put("ABC", at(100 * DAY)).unwrap();
+        assert!(trash.contains("abc"));
+        assert_eq!(trash.expires_at("abc"), Some(107 * DAY));
+        assert!(!trash.is_expired("abc", at(107 * DAY - 1)));
+        assert!(trash.is_expired("abc", at(107 * DAY)));
+
+        // Another mount sharing the file keeps both entries
+        let other = Trash::open(&path, Duration::from_secs(DAY)).unwrap();
+        assert!(other.contains("abc"));
+        other.put("def", at(101 * DAY)).unwrap();
+        trash.remove("abc").unwrap();
+
+        let reopened = Trash::open(&path, Duration::from_secs(DAY)).unwrap();
+        assert!(!reopened.contains("abc"));
+        assert_eq!(reopened.trashed_at("def"), Some(101 * DAY));
+    }
+
+    #[test]
+    fn test_corrupt_state_file_is_an_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("trash.json");
+        std::fs::write(&path, "not json").unwrap();
+        let err = Trash::open(&path, Duration::ZERO).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
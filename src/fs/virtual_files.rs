@@ -0,0 +1,101 @@
+//! Files generated by rqbit-fuse instead of read from a torrent.
+//!
+//! `/.rqbit-fuse/torrents/<infohash>.json` holds the stats rqbit reports
+//! for a torrent (peers, rates, piece counts), fetched again whenever a
+//! reader starts at offset 0, so dashboards can poll them with plain reads.
+//! With `status_dirs`, each torrent's directory has its own `.rqbit-fuse`
+//! directory with the same stats as `status.json`, its peers as
+//! `peers.json`, and its progress as a plain percentage in `progress`.
+//!
+//! `/.rqbit-fuse/add` takes magnet links and torrent URLs instead, one per
+//! line, and adds each to rqbit as it is written. A `.torrent` file created
+//! at the root exists only while it is written, and adds its torrent once
+//! it is closed.
+
+use crate::api::backend::TorrentClient;
+use anyhow::Result;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Name of the directory of generated files at the root of the mount.
+pub const CONTROL_DIR: &str = ".rqbit-fuse";
+/// Directory of [`CONTROL_DIR`] holding a stats file per torrent.
+pub const TORRENTS_DIR: &str = "torrents";
+/// File of [`CONTROL_DIR`] that adds the torrents written to it.
+pub const ADD_FILE: &str = "add";
+/// Files of the [`CONTROL_DIR`] in a torrent's directory.
+pub const STATUS_FILES: [(&str, VirtualFile); 3] = [
+    ("status.json", VirtualFile::TorrentStats),
+    ("peers.json", VirtualFile::TorrentPeers),
+    ("progress", VirtualFile::TorrentProgress),
+];
+
+/// What a generated file holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualFile {
+    /// The torrent's stats, as rqbit returned them.
+    TorrentStats,
+    /// The stats of the torrent's live peers, as rqbit returned them.
+    TorrentPeers,
+    /// The percentage of the torrent downloaded, e.g. `56.8`.
+    TorrentProgress,
+    /// Adds the magnet links and URLs written to it; reads as empty.
+    AddTorrent,
+    /// A `.torrent` file being copied in, added once it is closed; reads
+    /// as empty.
+    TorrentUpload,
+}
+
+impl VirtualFile {
+    /// Whether the file may be opened for writing.
+    pub fn is_writable(self) -> bool {
+        matches!(self, VirtualFile::AddTorrent | VirtualFile::TorrentUpload)
+    }
+
+    /// Generates the current contents of the file for a torrent.
+    pub async fn render(self, api_client: &dyn TorrentClient, torrent_id: u64) -> Result<Bytes> {
+        match self {
+            VirtualFile::TorrentStats => {
+                let stats = api_client.get_torrent_stats_raw(torrent_id).await?;
+                pretty_json(&stats)
+            }
+            VirtualFile::TorrentPeers => {
+                let peers = api_client.get_peer_stats_raw(torrent_id).await?;
+                pretty_json(&peers)
+            }
+            VirtualFile::TorrentProgress => {
+                let stats = api_client.get_torrent_stats(torrent_id).await?;
+                // Nothing to report while rqbit waits for the metadata
+                let percent = match stats.total_bytes {
+                    0 => "0.0".to_string(),
+                    total => percent(stats.progress_bytes, total),
+                };
+                Ok(Bytes::from(percent + "\n"))
+            }
+            VirtualFile::AddTorrent | VirtualFile::TorrentUpload => Ok(Bytes::new()),
+        }
+    }
+}
+
+/// `done` bytes of `total` as a percentage with one decimal, rounded down
+/// so that only a finished download reads `100.0`. Nothing to download is
+/// finished.
+pub fn percent(done: u64, total: u64) -> String {
+    if total == 0 {
+        return "100.0".to_string();
+    }
+    let permille = u128::from(done.min(total)) * 1000 / u128::from(total);
+    format!("{}.{}", permille / 10, permille % 10)
+}
+
+fn pretty_json(value: &serde_json::Value) -> Result<Bytes> {
+    let mut json = serde_json::to_vec_pretty(value)?;
+    json.push(b'\n');
+    Ok(Bytes::from(json))
+}
+
+/// Name of a torrent's stats file.
+pub fn stats_file_name(info_hash: &str) -> String {
+    format!("{}.json", info_hash.to_ascii_lowercase())
+}
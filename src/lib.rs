@@ -7,12 +7,31 @@
 // The primary types and functions intended for public use.
 
 pub mod api;
+#[cfg(feature = "cli")]
+pub mod autofs;
+#[cfg(feature = "cli")]
+pub mod bench;
+pub mod cgroup;
 pub mod config;
+pub mod container;
+pub mod control;
+#[cfg(feature = "docker-plugin")]
+pub mod docker_plugin;
 pub mod error;
+pub mod exit_code;
 pub mod fs;
+pub mod logs;
 pub mod metrics;
 pub mod mount;
+pub mod mount_lock;
+pub mod privileges;
+#[cfg(feature = "seccomp")]
+pub mod sandbox;
+#[cfg(feature = "cli")]
+pub mod supervisor;
+pub mod top;
 pub mod types;
+pub mod userns;
 
 /// Configuration module re-exports.
 ///
@@ -28,17 +47,27 @@ pub use fs::async_bridge::AsyncFuseWorker;
 /// The main filesystem implementation.
 ///
 /// This is the core type that handles all FUSE operations. Use [`TorrentFS::new()`]
-/// to create an instance, then call [`TorrentFS::mount()`] to mount it.
+/// to create an instance, then call [`TorrentFS::mount()`] to mount it, or
+/// [`TorrentFS::spawn_mount()`] to mount it in the background.
 pub use fs::filesystem::TorrentFS;
 
+/// Handle to a filesystem mounted in the background.
+pub use fs::session::MountHandle;
+
 /// Metrics collection for monitoring performance.
 ///
 /// Tracks API call latency, cache hits/misses, FUSE operation counts, and other
 /// useful metrics for debugging and optimization.
 pub use metrics::Metrics;
 
-use crate::api::create_api_client;
+use crate::api::{create_client, TorrentClient};
+use crate::control::ControlServer;
+use crate::mount_lock::MountLock;
+use crate::privileges::{drop_privileges, Credentials};
+use crate::userns::UserNamespace;
 use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -46,7 +75,9 @@ use std::time::Duration;
 ///
 /// This is the main entry point for using rqbit-fuse as a library.
 /// It sets up the metrics collection, API client, async worker, and filesystem,
-/// then mounts the FUSE filesystem at the configured mount point.
+/// then mounts the FUSE filesystem at the configured mount point, or at every
+/// `[[mounts]]` entry when several are configured. The filesystems are served
+/// in the background; the returned [`MountController`] unmounts them.
 ///
 /// # Arguments
 ///
@@ -54,11 +85,12 @@ use std::time::Duration;
 ///
 /// # Returns
 ///
-/// Returns `Ok(())` on successful unmount, or an error if:
+/// Returns a [`MountController`] once every filesystem is mounted, or an
+/// error if:
 /// - API client creation fails
 /// - Filesystem creation fails
+/// - Torrent discovery fails
 /// - Mounting fails
-/// - An error occurs during operation
 ///
 /// # Example
 ///
@@ -68,176 +100,316 @@ use std::time::Duration;
 /// #[tokio::main]
 /// async fn main() -> anyhow::Result<()> {
 ///     let config = Config::from_args()?;
-///     run(config).await?;
-///     Ok(())
+///     let mounts = run(config).await?;
+///     // ... until the application shuts down
+///     mounts.unmount().await
 /// }
 /// ```
-///
-/// # Note
-///
-/// This function blocks until the filesystem is unmounted. It handles SIGINT and
-/// SIGTERM gracefully, cleaning up resources on shutdown.
-pub async fn run(config: Config) -> Result<()> {
+pub async fn run(config: Config) -> Result<MountController> {
     tracing::info!(operation = "startup", message = "rqbit-fuse starting");
     tracing::debug!(config = ?config, "Configuration loaded");
 
+    // Resolve the user now, so a typo fails before anything is mounted
+    let credentials = config
+        .run_as_user
+        .as_deref()
+        .map(|user| Credentials::resolve(user, config.run_as_group.as_deref()))
+        .transpose()
+        .context("run_as_user")?;
+
     // Create metrics
     let metrics = Arc::new(Metrics::new());
 
-    // Create API client for the async worker
-    let api_client = Arc::new(
-        create_api_client(
-            &config.api_url,
-            config.api_username.as_deref(),
-            config.api_password.as_deref(),
-            Some(Arc::clone(&metrics)),
-        )
-        .context("API client creation failed")?,
-    );
+    // Mounts backed by the same torrent client share one API client and async
+    // worker, and with them the HTTP connection pool and caches.
+    let mut backends: HashMap<_, (Arc<dyn TorrentClient>, Arc<AsyncFuseWorker>)> = HashMap::new();
+    let mut filesystems = Vec::new();
+    let mut pressure_monitors = Vec::new();
+    let mut locks = Vec::new();
+    let mut created_mount_points = Vec::new();
+
+    // Without an explicit memory cap, the budget follows the cgroup limit
+    let cgroup = crate::cgroup::MemoryCgroup::detect();
+    let cgroup_limit = cgroup.as_ref().and_then(|cgroup| cgroup.limit());
+    if let Some(limit) = cgroup_limit {
+        tracing::info!("cgroup memory limit: {} bytes", limit);
+    }
+
+    // fusermount can't mount in a user namespace, but the mount syscall can
+    let user_namespace = UserNamespace::current().map(|userns| {
+        let can_mount = crate::userns::can_mount();
+        (userns, can_mount)
+    });
 
-    // Create async worker for FUSE callbacks
-    // Channel capacity of 1000 allows for good concurrency without excessive memory use
-    let async_worker = Arc::new(AsyncFuseWorker::new(api_client, Arc::clone(&metrics), 1000));
+    for mut mount_config in config.mount_configs() {
+        if let Some((userns, can_mount)) = &user_namespace {
+            userns
+                .prepare(&mut mount_config, *can_mount)
+                .context("user namespace")?;
+        }
+        if let Some(limit) = cgroup_limit {
+            mount_config.apply_cgroup_limit(limit);
+        }
+        // Fail before anything is mounted if the path is already served
+        locks.push(MountLock::acquire(&mount_config.mount_point)?);
+        if crate::mount::prepare_mount_point(&mount_config)? && mount_config.remove_mount_point {
+            created_mount_points.push(mount_config.mount_point.clone());
+        }
+        let key = (
+            mount_config.api_url.clone(),
+            mount_config.api_username.clone(),
+            mount_config.api_password.clone(),
+            mount_config.api_login_url.clone(),
+            mount_config.api_backend,
+        );
+        let (api_client, async_worker) = match backends.get(&key) {
+            Some((api_client, async_worker)) => (Arc::clone(api_client), Arc::clone(async_worker)),
+            None => {
+                let api_client = create_client(&mount_config, Arc::clone(&metrics))?;
+
+                // Create async worker for FUSE callbacks
+                // Channel capacity of 1000 allows for good concurrency without excessive memory use
+                let async_worker = Arc::new(AsyncFuseWorker::new(
+                    Arc::clone(&api_client),
+                    Arc::clone(&metrics),
+                    1000,
+                ));
+                if mount_config.cgroup_memory_percent > 0 {
+                    pressure_monitors.extend(cgroup.as_ref().and_then(|cgroup| {
+                        cgroup.spawn_pressure_monitor(Arc::clone(api_client.memory()))
+                    }));
+                }
+                backends.insert(key, (Arc::clone(&api_client), Arc::clone(&async_worker)));
+                (api_client, async_worker)
+            }
+        };
 
-    // Create the filesystem with async worker
-    let fs = TorrentFS::new(config, Arc::clone(&metrics), async_worker)
+        let fs = TorrentFS::with_api_client(
+            mount_config,
+            api_client,
+            Arc::clone(&metrics),
+            async_worker,
+        )
         .context("filesystem creation failed")?;
+        filesystems.push(Arc::new(fs));
+    }
+
+    // Serve `status` and other control requests; mounting works without it
+    let control_server = match ControlServer::start(
+        &config.control_socket_path(),
+        filesystems.clone(),
+        Arc::clone(&metrics),
+    ) {
+        Ok(server) => {
+            tracing::info!("Control socket listening at {}", server.path().display());
+            Some(server)
+        }
+        Err(e) => {
+            tracing::warn!("Control socket disabled: {:#}", e);
+            None
+        }
+    };
 
-    // Wrap in Arc for sharing between signal handler and main flow
-    let fs_arc = Arc::new(fs);
-    let mount_point = fs_arc.mount_point().to_path_buf();
-    let mount_point_cleanup = mount_point.clone();
+    // Fail early with a useful hint instead of an opaque mount error
+    let container = filesystems.iter().any(|fs| fs.config().container);
+    if container {
+        crate::container::check_environment().context("container mode")?;
+    }
+    let zombie_reaper = if container {
+        crate::container::spawn_zombie_reaper()
+    } else {
+        None
+    };
 
-    // Clone for signal handler
-    let fs_for_signal = Arc::clone(&fs_arc);
-    let fs_for_mount = Arc::clone(&fs_arc);
+    let mut controller = MountController {
+        mounts: Vec::new(),
+        metrics,
+        control_server,
+        zombie_reaper,
+        pressure_monitors,
+        _locks: locks,
+        created_mount_points,
+    };
 
-    // Channel to signal shutdown from signal handler to mount task
-    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    // Discover existing torrents before mounting. Progressive mounts start
+    // empty and are filled by the discovery started when they are mounted.
+    for fs in &filesystems {
+        if fs.config().progressive_mount && !fs.config().offline {
+            tracing::info!(
+                "Mounting {} before discovery; torrents appear as they are found",
+                fs.mount_point().display()
+            );
+            continue;
+        }
+        crate::fs::filesystem::discover_existing_torrents(fs)
+            .await
+            .with_context(|| {
+                format!(
+                    "torrent discovery failed for {}",
+                    fs.mount_point().display()
+                )
+            })?;
+    }
 
-    // Spawn signal handler task
-    let signal_handler = tokio::spawn(async move {
-        use tokio::signal::unix::{signal, SignalKind};
+    // Mounts already made are unmounted when the controller is dropped
+    for fs in &filesystems {
+        let fs = <TorrentFS as Clone>::clone(fs);
+        controller.mounts.push(fs.spawn_mount()?);
+    }
 
-        let mut sigint = signal(SignalKind::interrupt()).unwrap();
-        let mut sigterm = signal(SignalKind::terminate()).unwrap();
+    // Every FUSE session is established; the rest needs no root
+    if let Some(credentials) = &credentials {
+        drop_privileges(credentials).context("failed to drop privileges")?;
+        tracing::info!(
+            "Dropped privileges to uid {} gid {}",
+            credentials.uid,
+            credentials.gid
+        );
+    }
 
-        tokio::select! {
-            _ = sigint.recv() => {
-                tracing::info!("Received SIGINT, initiating graceful shutdown...");
-            }
-            _ = sigterm.recv() => {
-                tracing::info!("Received SIGTERM, initiating graceful shutdown...");
-            }
-        }
+    #[cfg(feature = "seccomp")]
+    if config.seccomp {
+        crate::sandbox::apply()?;
+        tracing::info!("seccomp sandbox enabled");
+    }
 
-        // Signal the mount task to shut down
-        let _ = shutdown_tx.send(());
+    Ok(controller)
+}
 
-        // Initiate graceful shutdown with timeout
-        let shutdown_timeout = Duration::from_secs(10);
-        let mount_point_force = mount_point.clone();
+/// The filesystems mounted by [`run()`].
+///
+/// Embedding applications call [`MountController::unmount()`] on their own
+/// shutdown path; [`MountController::wait()`] instead serves until SIGINT or
+/// SIGTERM. Dropping the controller unmounts without waiting.
+pub struct MountController {
+    mounts: Vec<MountHandle>,
+    metrics: Arc<Metrics>,
+    control_server: Option<ControlServer>,
+    zombie_reaper: Option<tokio::task::JoinHandle<()>>,
+    pressure_monitors: Vec<tokio::task::JoinHandle<()>>,
+    /// Held until the filesystems are unmounted.
+    _locks: Vec<MountLock>,
+    /// Mount points created by [`run()`] with `remove_mount_point` set.
+    created_mount_points: Vec<PathBuf>,
+}
 
-        let shutdown_result = tokio::time::timeout(shutdown_timeout, async {
-            fs_for_signal.shutdown();
+impl MountController {
+    /// Maximum time [`MountController::unmount()`] waits for the FUSE
+    /// sessions to end.
+    pub const UNMOUNT_TIMEOUT: Duration = Duration::from_secs(10);
 
-            // Try to unmount the filesystem gracefully
-            tokio::task::spawn_blocking(move || {
-                std::process::Command::new("fusermount")
-                    .arg("-u")
-                    .arg(&mount_point)
-                    .output()
-            })
-            .await
-        })
-        .await;
+    /// Returns the mounted filesystems.
+    pub fn filesystems(&self) -> impl Iterator<Item = &TorrentFS> {
+        self.mounts.iter().map(MountHandle::filesystem)
+    }
 
-        match shutdown_result {
-            Ok(Ok(Ok(_))) => {
-                tracing::info!("Graceful shutdown completed successfully");
-            }
-            Ok(Ok(Err(e))) => {
-                tracing::warn!("Unmount failed, trying force unmount: {}", e);
-                // Try force unmount
-                if let Err(force_err) = tokio::task::spawn_blocking(move || {
-                    std::process::Command::new("fusermount")
-                        .arg("-uz")
-                        .arg(&mount_point_force)
-                        .output()
-                })
-                .await
-                {
-                    tracing::error!("Force unmount also failed: {}", force_err);
+    /// Returns the paths the filesystems are mounted at.
+    pub fn mount_points(&self) -> impl Iterator<Item = &Path> {
+        self.mounts.iter().map(MountHandle::mount_point)
+    }
+
+    /// Unmounts every filesystem and waits for its FUSE session to end.
+    pub async fn unmount(mut self) -> Result<()> {
+        let mounts = std::mem::take(&mut self.mounts);
+        let unmount = async {
+            let mut result = Ok(());
+            for mount in mounts {
+                let mount_point = mount.mount_point().to_path_buf();
+                if let Err(e) = mount.unmount().await {
+                    tracing::error!("{:#}", e);
+                    if result.is_ok() {
+                        result = Err(e.context(format!("unmounting {}", mount_point.display())));
+                    }
                 }
             }
-            Ok(Err(e)) => {
-                tracing::error!("Shutdown task failed: {}", e);
-            }
-            Err(_) => {
-                tracing::warn!(
-                    "Shutdown timed out after {:?}, forcing exit",
-                    shutdown_timeout
-                );
+            result
+        };
+
+        match tokio::time::timeout(Self::UNMOUNT_TIMEOUT, unmount).await {
+            Ok(result) => {
+                tracing::info!("Graceful shutdown completed");
+                if result.is_ok() {
+                    self.remove_created_mount_points();
+                }
+                result
             }
+            Err(_) => anyhow::bail!("unmount timed out after {:?}", Self::UNMOUNT_TIMEOUT),
         }
-    });
-
-    // Discover existing torrents before mounting
-    crate::fs::filesystem::discover_existing_torrents(&fs_arc)
-        .await
-        .context("torrent discovery failed")?;
-
-    // Mount the filesystem in a blocking task so signals can be processed
-    // This will return when the filesystem is unmounted (either via signal or externally)
-    let mount_result =
-        tokio::task::spawn_blocking(move || <TorrentFS as Clone>::clone(&fs_for_mount).mount())
-            .await;
-
-    // Race between mount completing and receiving shutdown signal
-    // If we received a signal, shutdown_rx will be Some(Err(Canceled))
-    tokio::select! {
-        _ = shutdown_rx => {
-            tracing::info!("Shutdown signal received, mount task is completing...");
-        }
-        _ = async {} => {}
     }
 
-    if let Err(e) = mount_result {
-        // If mount fails, we still need to clean up
-        fs_arc.shutdown();
-        metrics.log_summary();
-        return Err(anyhow::anyhow!("Mount task failed: {}", e));
+    /// Waits until every filesystem has been unmounted externally, e.g. with
+    /// `fusermount -u`.
+    pub async fn join(mut self) -> Result<()> {
+        let mut result = Ok(());
+        for mount in std::mem::take(&mut self.mounts) {
+            let mount_point = mount.mount_point().to_path_buf();
+            if let Err(e) = mount.join().await {
+                tracing::info!("Mount at {} returned: {:#}", mount_point.display(), e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        if result.is_ok() {
+            self.remove_created_mount_points();
+        }
+        result
     }
 
-    // Check if mount returned due to shutdown signal
-    if mount_result.as_ref().is_ok_and(|r| r.is_err()) {
-        tracing::info!("Mount returned due to unmount signal");
+    /// Removes the mount points [`run()`] created, once nothing is mounted
+    /// on them. A directory something was put in since is left alone.
+    fn remove_created_mount_points(&mut self) {
+        for mount_point in std::mem::take(&mut self.created_mount_points) {
+            match std::fs::remove_dir(&mount_point) {
+                Ok(()) => tracing::info!("Removed mount point {}", mount_point.display()),
+                Err(e) => tracing::warn!(
+                    "Failed to remove mount point {}: {}",
+                    mount_point.display(),
+                    e
+                ),
+            }
+        }
     }
 
-    // The filesystem has been unmounted, clean up
-    // Use timeout to ensure we don't hang on shutdown
-    let cleanup_timeout = Duration::from_secs(5);
-    let cleanup = async {
-        fs_arc.shutdown();
-
-        // Try to unmount if still mounted
-        tokio::task::spawn_blocking(move || {
-            std::process::Command::new("fusermount")
-                .arg("-u")
-                .arg(mount_point_cleanup)
-                .output()
-        })
-        .await
-        .ok();
-    };
+    /// Serves until SIGINT or SIGTERM, then unmounts every filesystem.
+    /// Returns early once every filesystem has been unmounted externally.
+    pub async fn wait(self) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
 
-    let _ = tokio::time::timeout(cleanup_timeout, cleanup).await;
+        let mut sigint = signal(SignalKind::interrupt()).context("signal handler")?;
+        let mut sigterm = signal(SignalKind::terminate()).context("signal handler")?;
+        let unmounted = async {
+            while !self.mounts.iter().all(MountHandle::is_finished) {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+        };
 
-    // Wait for signal handler to complete (it will timeout if already done)
-    let _ = tokio::time::timeout(Duration::from_secs(5), signal_handler).await;
+        tokio::select! {
+            _ = sigint.recv() => {
+                tracing::info!("Received SIGINT, initiating graceful shutdown...");
+            }
+            _ = sigterm.recv() => {
+                tracing::info!("Received SIGTERM, initiating graceful shutdown...");
+            }
+            _ = unmounted => return self.join().await,
+        }
+        self.unmount().await
+    }
+}
 
-    // Log final metrics on shutdown
-    metrics.log_summary();
+impl Drop for MountController {
+    fn drop(&mut self) {
+        // Unmount before the control socket goes away
+        self.mounts.clear();
+        self.control_server = None;
+        if let Some(reaper) = self.zombie_reaper.take() {
+            reaper.abort();
+        }
+        for monitor in self.pressure_monitors.drain(..) {
+            monitor.abort();
+        }
 
-    Ok(())
+        // Log final metrics on shutdown
+        self.metrics.log_summary();
+    }
 }
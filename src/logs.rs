@@ -0,0 +1,245 @@
+//! Recent log lines kept in memory for `rqbit-fuse logs`.
+//!
+//! The daemon's log goes to stderr, which is easy to lose once it runs under
+//! a supervisor or in the background. The logging setup also records every
+//! event in a ring buffer, and the control socket serves it, so the log can
+//! be read and followed without knowing where stderr ended up.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tracing::Level;
+
+/// Lines kept by the process-wide buffer.
+pub const CAPACITY: usize = 2000;
+
+/// One log event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// Milliseconds since the Unix epoch.
+    pub time: u64,
+    pub level: String,
+    pub target: String,
+    /// The message followed by the event's other fields.
+    pub message: String,
+}
+
+impl LogLine {
+    /// Returns true if the line is at `max` or a more severe level.
+    pub fn is_at(&self, max: Level) -> bool {
+        self.level.parse::<Level>().is_ok_and(|level| level <= max)
+    }
+}
+
+impl std::fmt::Display for LogLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            format_time(self.time),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// Ring buffer of recent log lines that also feeds followers.
+#[derive(Debug)]
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Mutex<VecDeque<LogLine>>,
+    followers: broadcast::Sender<LogLine>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Mutex::new(VecDeque::with_capacity(capacity)),
+            followers: broadcast::channel(capacity.max(1)).0,
+        }
+    }
+
+    /// The buffer filled by the logging setup of the `rqbit-fuse` binary.
+    /// Stays empty when the library is used with another subscriber.
+    pub fn global() -> &'static LogBuffer {
+        static GLOBAL: OnceLock<LogBuffer> = OnceLock::new();
+        GLOBAL.get_or_init(|| LogBuffer::new(CAPACITY))
+    }
+
+    pub fn push(&self, line: LogLine) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        // Sent under the lock, so a new follower sees each line exactly once
+        let _ = self.followers.send(line.clone());
+        lines.push_back(line);
+    }
+
+    /// Returns the last `count` lines at `max` or a more severe level.
+    pub fn recent(&self, max: Level, count: usize) -> Vec<LogLine> {
+        Self::last(&self.lines.lock().unwrap(), max, count)
+    }
+
+    /// Like [`LogBuffer::recent()`], and also subscribes to the lines
+    /// pushed afterwards.
+    pub fn follow(&self, max: Level, count: usize) -> (Vec<LogLine>, broadcast::Receiver<LogLine>) {
+        let lines = self.lines.lock().unwrap();
+        (Self::last(&lines, max, count), self.followers.subscribe())
+    }
+
+    fn last(lines: &VecDeque<LogLine>, max: Level, count: usize) -> Vec<LogLine> {
+        let mut last: Vec<LogLine> = lines
+            .iter()
+            .rev()
+            .filter(|line| line.is_at(max))
+            .take(count)
+            .cloned()
+            .collect();
+        last.reverse();
+        last
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Formats Unix milliseconds as an RFC 3339 UTC timestamp.
+pub fn format_time(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, day_secs) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs / 60 % 60,
+        day_secs % 60,
+        millis % 1000
+    )
+}
+
+/// Tracing layer recording events in [`LogBuffer::global()`].
+#[cfg(feature = "cli")]
+pub struct BufferLayer;
+
+#[cfg(feature = "cli")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for BufferLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        LogBuffer::global().push(LogLine {
+            time: now_millis(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+/// Formats an event's fields like the stderr log: the message, then
+/// `key=value` for the others.
+#[cfg(feature = "cli")]
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+#[cfg(feature = "cli")]
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        use std::fmt::Write;
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.message, "{}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == "message" {
+            if !self.message.is_empty() {
+                self.message.push(' ');
+            }
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(time: u64, level: Level) -> LogLine {
+        LogLine {
+            time,
+            level: level.to_string(),
+            target: "rqbit_fuse".to_string(),
+            message: format!("line {}", time),
+        }
+    }
+
+    #[test]
+    fn test_buffer_keeps_recent_lines() {
+        let buffer = LogBuffer::new(3);
+        for (time, level) in [
+            (1, Level::INFO),
+            (2, Level::DEBUG),
+            (3, Level::WARN),
+            (4, Level::INFO),
+        ] {
+            buffer.push(line(time, level));
+        }
+
+        let times = |lines: Vec<LogLine>| lines.iter().map(|l| l.time).collect::<Vec<_>>();
+        assert_eq!(times(buffer.recent(Level::TRACE, 10)), [2, 3, 4]);
+        assert_eq!(times(buffer.recent(Level::INFO, 10)), [3, 4]);
+        assert_eq!(times(buffer.recent(Level::TRACE, 1)), [4]);
+        assert_eq!(times(buffer.recent(Level::ERROR, 10)), Vec::<u64>::new());
+
+        let (recent, mut follower) = buffer.follow(Level::TRACE, 1);
+        assert_eq!(times(recent), [4]);
+        buffer.push(line(5, Level::ERROR));
+        assert_eq!(follower.try_recv().unwrap().time, 5);
+    }
+
+    #[rstest::rstest]
+    #[case(0, "1970-01-01T00:00:00.000Z")]
+    #[case(951_782_400_123, "2000-02-29T00:00:00.123Z")]
+    #[case(1_791_936_245_007, "2026-10-14T00:04:05.007Z")]
+    fn test_format_time(#[case] millis: u64, #[case] expected: &str) {
+        assert_eq!(format_time(millis), expected);
+    }
+}
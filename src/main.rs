@@ -1,21 +1,45 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use rqbit_fuse::config::{CliArgs, Config, ConfigSource};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use rqbit_fuse::api::create_api_client;
+use rqbit_fuse::autofs;
+use rqbit_fuse::bench::{self, BenchOptions};
+use rqbit_fuse::config::{parse_bytes, parse_duration_secs, CliArgs, Config, Preset};
+use rqbit_fuse::control::{send_request, stream_request, ControlRequest, ControlResponse};
+use rqbit_fuse::exit_code::{self, ErrorReport, ExitCode};
+use rqbit_fuse::fs::fadvise::{self, Advice, FadviseArgs};
+use rqbit_fuse::fs::fsck::FsckIssue;
 use rqbit_fuse::mount::{is_mount_point, setup_logging, unmount_filesystem};
+use rqbit_fuse::top;
 use std::path::PathBuf;
+use std::time::Duration;
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+#[cfg(all(feature = "mimalloc", not(feature = "jemalloc")))]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 #[derive(Parser)]
 #[command(name = "rqbit-fuse")]
 #[command(about = "A FUSE filesystem for accessing torrents via rqbit")]
 #[command(version)]
+#[command(after_help = exit_code::HELP)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Format of failures on stderr; json prints one object with the
+    /// code, message, hint and whether retrying may help
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    error_format: OutputFormat,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Mount the torrent filesystem
+    #[command(after_help = exit_code::HELP)]
     Mount {
         /// Path to mount point (overrides config)
         #[arg(short, long, env = "TORRENT_FUSE_MOUNT_POINT")]
@@ -44,9 +68,222 @@ enum Commands {
         /// Suppress all output except errors
         #[arg(short, long)]
         quiet: bool,
+
+        /// Reject unknown keys in the config file
+        #[arg(long)]
+        strict_config: bool,
+
+        /// Apply a named [profile.<name>] section from the config file
+        #[arg(short, long, env = "TORRENT_FUSE_PROFILE")]
+        profile: Option<String>,
+
+        /// Mount only this torrent (ID or info hash) directly at the mount point
+        #[arg(short, long, value_name = "INFOHASH|ID")]
+        torrent: Option<String>,
+
+        /// Apply a built-in set of settings (media-server)
+        #[arg(long, value_name = "NAME", env = "TORRENT_FUSE_PRESET")]
+        preset: Option<Preset>,
+
+        /// Run the mount in a child process and remount it after crashes
+        #[arg(long)]
+        supervise: bool,
+
+        /// Mount without fusermount and act as a well-behaved PID 1
+        #[arg(long)]
+        container: bool,
+
+        /// Allow root, in addition to the mounting user, to access the mount
+        #[arg(long)]
+        allow_root: bool,
+
+        /// Allow all users to access the mount
+        #[arg(long)]
+        allow_other: bool,
+
+        /// Have the kernel enforce the reported ownership and modes
+        #[arg(long)]
+        default_permissions: bool,
+
+        /// Serve the last saved torrent list without contacting rqbit;
+        /// reads fail until mounted online again
+        #[arg(long)]
+        offline: bool,
+
+        /// Mount over a directory that has files in it, hiding them
+        #[arg(long)]
+        nonempty: bool,
+
+        /// Output format of the result, printed once mounted
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Show the status of a running rqbit-fuse process
+    #[command(after_help = exit_code::HELP)]
+    Status {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Show reads through a running process's mounts, refreshing live
+    Top {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Time between refreshes
+        #[arg(short = 'd', long, value_name = "DURATION", value_parser = parse_duration_secs, default_value = "1s")]
+        delay: u64,
+
+        /// Exit after this many refreshes
+        #[arg(short = 'n', long, value_name = "COUNT")]
+        iterations: Option<u64>,
+    },
+
+    /// Show recent log lines of a running process
+    Logs {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Keep printing new lines as they are logged
+        #[arg(short, long)]
+        follow: bool,
+
+        /// Only show lines at this level or more severe (error, warn, info, debug, trace)
+        #[arg(short, long, value_name = "LEVEL")]
+        level: Option<String>,
+
+        /// Number of recent lines to show
+        #[arg(short = 'n', long, value_name = "COUNT", default_value_t = 100)]
+        lines: usize,
+    },
+
+    /// Check a running mount against rqbit's torrent list
+    Fsck {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Repair differences in place
+        #[arg(long)]
+        repair: bool,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// List torrents in the trash of a running mount, or restore one
+    Trash {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Restore and resume a torrent, by name, ID or info hash
+        #[arg(long, value_name = "TORRENT")]
+        restore: Option<String>,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Dump every entry of a running mount (paths, sizes, torrent ids,
+    /// availability) as JSON
+    ExportTree {
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
+
+        /// Control socket of the running process (overrides config)
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Write the JSON to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Measure read throughput and latency of a file in a mount
+    Bench {
+        /// File inside the mount to read
+        file: PathBuf,
+
+        /// Block size to measure; repeat for several (default: 4KiB, 64KiB, 1MiB)
+        #[arg(short, long = "block-size", value_name = "SIZE", value_parser = parse_bytes)]
+        block_sizes: Vec<u64>,
+
+        /// Bytes read sequentially per block size
+        #[arg(short, long, value_name = "SIZE", value_parser = parse_bytes, default_value = "64MiB")]
+        size: u64,
+
+        /// Random reads per block size
+        #[arg(short, long, default_value_t = bench::DEFAULT_RANDOM_READS)]
+        random_reads: usize,
+
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Send posix_fadvise(2) advice for a file in a mount, which the kernel
+    /// otherwise never passes on
+    Advise {
+        /// File inside the mount
+        file: PathBuf,
+
+        /// normal, sequential, random, willneed, dontneed or noreuse
+        advice: Advice,
+
+        /// Start of the range fetched by willneed
+        #[arg(long, value_name = "SIZE", value_parser = parse_bytes, default_value = "0")]
+        offset: u64,
+
+        /// Length of the range fetched by willneed (0 = to the end of the file)
+        #[arg(long, value_name = "SIZE", value_parser = parse_bytes, default_value = "0")]
+        len: u64,
+    },
+
+    /// Print an automount(8) map, or the entry for KEY when run as an
+    /// executable map
+    AutofsMap {
+        /// Map key to look up (torrent name, ID or info hash)
+        key: Option<String>,
+
+        /// Path to config file
+        #[arg(short, long, value_name = "FILE")]
+        config: Option<PathBuf>,
     },
 
     /// Unmount the torrent filesystem
+    #[command(after_help = exit_code::HELP)]
     Umount {
         /// Path to mount point (overrides config)
         #[arg(short, long, env = "TORRENT_FUSE_MOUNT_POINT")]
@@ -59,14 +296,52 @@ enum Commands {
         /// Force unmount even if filesystem is busy
         #[arg(short, long)]
         force: bool,
+
+        /// Use the mount point from a named [profile.<name>] section
+        #[arg(short, long, env = "TORRENT_FUSE_PROFILE")]
+        profile: Option<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Generate roff man pages for rqbit-fuse and each subcommand
+    Man {
+        /// Write one page per command to this directory instead of printing
+        /// the main page
+        #[arg(short, long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
     },
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() -> std::process::ExitCode {
+    let (result, error_format) = if autofs::invoked_as_mount_helper() {
+        (run_mount_helper().await, OutputFormat::Text)
+    } else {
+        let cli = Cli::parse();
+        (run(cli.command).await, cli.error_format)
+    };
+
+    let Err(e) = result else {
+        return std::process::ExitCode::SUCCESS;
+    };
+    match error_format {
+        OutputFormat::Text => eprintln!("Error: {:?}", e),
+        OutputFormat::Json => eprintln!("{}", serde_json::json!(ErrorReport::new(&e))),
+    }
+    ExitCode::of(&e).into()
+}
 
-    match cli.command {
+async fn run(command: Commands) -> Result<()> {
+    match command {
         Commands::Mount {
             mount_point,
             api_url,
@@ -75,89 +350,601 @@ async fn main() -> Result<()> {
             password,
             verbose,
             quiet,
+            strict_config,
+            profile,
+            torrent,
+            preset,
+            supervise,
+            container,
+            allow_root,
+            allow_other,
+            default_permissions,
+            offline,
+            nonempty,
+            format,
         } => {
             setup_logging(verbose, quiet)?;
-            run_mount(mount_point, api_url, config, username, password).await
+            let cli_args = CliArgs {
+                api_url,
+                mount_point,
+                config_file: config,
+                username,
+                password,
+                strict_config,
+                profile,
+                torrent,
+                container,
+                allow_root,
+                allow_other,
+                default_permissions,
+                preset,
+                offline,
+                nonempty,
+            };
+            if supervise {
+                run_supervised(cli_args).await
+            } else {
+                run_mount(cli_args, format).await
+            }
         }
+        Commands::Status {
+            config,
+            socket,
+            format,
+        } => run_status(config, socket, format).await,
+        Commands::Top {
+            config,
+            socket,
+            delay,
+            iterations,
+        } => run_top(config, socket, delay, iterations).await,
+        Commands::Logs {
+            config,
+            socket,
+            follow,
+            level,
+            lines,
+        } => run_logs(config, socket, follow, level, lines).await,
+        Commands::Fsck {
+            config,
+            socket,
+            repair,
+            format,
+        } => run_fsck(config, socket, repair, format).await,
+        Commands::Trash {
+            config,
+            socket,
+            restore,
+            format,
+        } => run_trash(config, socket, restore, format).await,
+        Commands::ExportTree {
+            config,
+            socket,
+            output,
+        } => run_export_tree(config, socket, output).await,
+        Commands::Bench {
+            file,
+            block_sizes,
+            size,
+            random_reads,
+            format,
+        } => run_bench(file, block_sizes, size, random_reads, format).await,
+        Commands::Advise {
+            file,
+            advice,
+            offset,
+            len,
+        } => run_advise(file, advice, offset, len),
+        Commands::AutofsMap { key, config } => run_autofs_map(key, config).await,
         Commands::Umount {
             mount_point,
             config,
             force,
-        } => run_umount(mount_point, config, force).await,
+            profile,
+            format,
+        } => run_umount(mount_point, config, force, profile, format).await,
+        Commands::Man { out_dir } => run_man(out_dir),
     }
 }
 
-fn load_config(
+fn load_config(cli_args: &CliArgs) -> Result<Config> {
+    Config::load_with_cli(cli_args).map_err(|e| ExitCode::Config.error(e.to_string()))
+}
+
+async fn run_mount(cli_args: CliArgs, format: OutputFormat) -> Result<()> {
+    let config = load_config(&cli_args)?;
+    config
+        .validate_mounts()
+        .map_err(|e| ExitCode::Config.error(e.to_string()))?;
+
+    tracing::info!("rqbit-fuse starting");
+    for mount_config in config.mount_configs() {
+        tracing::info!(
+            "Mount point: {} (rqbit API at {})",
+            mount_config.mount_point.display(),
+            mount_config.api_url
+        );
+    }
+
+    let mount_configs = config.mount_configs();
+    let controller = rqbit_fuse::run(config).await?;
+    if format == OutputFormat::Json {
+        let mounts: Vec<_> = mount_configs
+            .into_iter()
+            .map(|mut config| {
+                if config.api_password.is_some() {
+                    config.api_password = Some("********".to_string());
+                }
+                serde_json::json!({
+                    "mount_point": config.mount_point,
+                    "config": config,
+                })
+            })
+            .collect();
+        let result = serde_json::json!({
+            "pid": std::process::id(),
+            "mounts": mounts,
+        });
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    }
+    controller.wait().await
+}
+
+async fn run_supervised(cli_args: CliArgs) -> Result<()> {
+    let config = load_config(&cli_args)?;
+    config
+        .validate_mounts()
+        .map_err(|e| ExitCode::Config.error(e.to_string()))?;
+    let mount_points = config
+        .mount_configs()
+        .into_iter()
+        .map(|c| c.mount_point)
+        .collect();
+
+    // The child runs the same command line without --supervise
+    let program = std::env::current_exe().context("Failed to locate rqbit-fuse executable")?;
+    let args = std::env::args_os()
+        .skip(1)
+        .filter(|arg| arg != "--supervise")
+        .collect();
+
+    tracing::info!("rqbit-fuse supervisor starting");
+    rqbit_fuse::supervisor::supervise(program, args, mount_points).await
+}
+
+/// Returns the control socket given on the command line or from the config.
+fn resolve_socket(config_file: Option<PathBuf>, socket: Option<PathBuf>) -> Result<PathBuf> {
+    match socket {
+        Some(socket) => Ok(socket),
+        None => Ok(load_config(&CliArgs {
+            config_file,
+            ..Default::default()
+        })?
+        .control_socket_path()),
+    }
+}
+
+fn run_man(out_dir: Option<PathBuf>) -> Result<()> {
+    match out_dir {
+        Some(out_dir) => {
+            std::fs::create_dir_all(&out_dir)?;
+            clap_mangen::generate_to(Cli::command(), &out_dir)?;
+            println!("Wrote man pages to {}", out_dir.display());
+        }
+        None => clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())?,
+    }
+    Ok(())
+}
+
+async fn run_autofs_map(key: Option<String>, config_file: Option<PathBuf>) -> Result<()> {
+    let config = load_config(&CliArgs {
+        config_file,
+        ..Default::default()
+    })?;
+    let mut client = create_api_client(
+        &config.api_url,
+        config.api_username.as_deref(),
+        config.api_password.as_deref(),
+        None,
+    )?;
+    if let Some(session) = config.session_auth()? {
+        client = client.with_session(session);
+    }
+    let torrents = client.list_torrents().await?.torrents;
+
+    match key {
+        None => print!("{}", autofs::render_map(&torrents)),
+        // automount treats an empty answer as "no such key"
+        Some(key) => match autofs::lookup(&torrents, &key) {
+            Some(entry) => println!("{}", entry),
+            None => std::process::exit(1),
+        },
+    }
+    Ok(())
+}
+
+/// Runs as `mount.rqbit-fuse SOURCE DIR -o OPTIONS`: starts a detached
+/// `rqbit-fuse mount` and returns once the mount is up.
+async fn run_mount_helper() -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let args = autofs::HelperArgs::parse(std::env::args_os().skip(1))?;
+    let program = std::env::current_exe().context("Failed to locate rqbit-fuse executable")?;
+    let program = program.with_file_name("rqbit-fuse");
+    let mut child = std::process::Command::new(&program)
+        .args(args.mount_args()?)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("Failed to start {}", program.display()))?;
+
+    autofs::wait_for_mount(&args.mount_point, Duration::from_secs(30), || {
+        Ok(child.try_wait()?.is_some())
+    })
+    .await
+}
+
+async fn run_status(
     config_file: Option<PathBuf>,
-    mount_point: Option<PathBuf>,
-    api_url: Option<String>,
-    username: Option<String>,
-    password: Option<String>,
-) -> Result<Config> {
-    let cli_args = CliArgs {
-        api_url,
-        mount_point,
-        config_file: config_file.clone(),
-        username,
-        password,
+    socket: Option<PathBuf>,
+    format: OutputFormat,
+) -> Result<()> {
+    let socket = resolve_socket(config_file, socket)?;
+
+    let report = match send_request(&socket, &ControlRequest::Status).await? {
+        ControlResponse::Status(report) => report,
+        ControlResponse::Error { message } => anyhow::bail!("status failed: {}", message),
+        other => anyhow::bail!("unexpected response: {:?}", other),
+    };
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "rqbit-fuse running (pid {}, up {}s)",
+        report.pid, report.uptime_secs
+    );
+    println!(
+        "  bytes read: {}, errors: {}, cache hits/misses: {}/{}",
+        report.metrics.bytes_read,
+        report.metrics.error_count,
+        report.metrics.cache_hits,
+        report.metrics.cache_misses
+    );
+    println!(
+        "  streams opened/reused/expired: {}/{}/{}",
+        report.metrics.streams_opened,
+        report.metrics.streams_reused,
+        report.metrics.streams_expired
+    );
+    println!(
+        "  handles opened/evicted: {}/{}",
+        report.metrics.handles_opened, report.metrics.handles_evicted
+    );
+    for mount in &report.mounts {
+        println!();
+        println!("{}", mount.mount_point.display());
+        println!("  api: {}", mount.api_url);
+        println!(
+            "  torrents: {}, inodes: {}, open handles: {}",
+            mount.torrents, mount.inodes, mount.open_handles
+        );
+        println!(
+            "  streams: {}/{} active, {} bytes streamed, {:.0}% reused",
+            mount.streams.active_streams,
+            mount.streams.max_streams,
+            mount.streams.bytes_streamed,
+            mount.streams.reuse_ratio * 100.0
+        );
+        if mount.streams.memory_limit > 0 {
+            println!(
+                "  memory: {}/{} bytes",
+                mount.streams.memory_used, mount.streams.memory_limit
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn run_top(
+    config_file: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    delay: u64,
+    iterations: Option<u64>,
+) -> Result<()> {
+    let socket = resolve_socket(config_file, socket)?;
+    let delay = Duration::from_secs(delay.max(1));
+
+    let mut previous = None;
+    let mut last_poll = std::time::Instant::now();
+    let mut refreshes = 0;
+    loop {
+        let report = match send_request(&socket, &ControlRequest::Activity).await? {
+            ControlResponse::Activity(report) => report,
+            ControlResponse::Error { message } => anyhow::bail!("top failed: {}", message),
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        };
+        let elapsed = last_poll.elapsed();
+        last_poll = std::time::Instant::now();
+        print!(
+            "{}{}",
+            top::CLEAR_SCREEN,
+            top::render(previous.as_ref(), &report, elapsed)
+        );
+        previous = Some(report);
+
+        refreshes += 1;
+        if iterations.is_some_and(|n| refreshes >= n) {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+async fn run_logs(
+    config_file: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    follow: bool,
+    level: Option<String>,
+    lines: usize,
+) -> Result<()> {
+    let socket = resolve_socket(config_file, socket)?;
+    let request = ControlRequest::Logs {
+        lines,
+        level,
+        follow,
     };
 
-    if let Some(ref config_path) = config_file {
-        Ok(Config::from_file(config_path)?
-            .merge(ConfigSource::from_env()?)
-            .merge(ConfigSource::from_cli(&cli_args)))
+    let mut error = None;
+    let print = |response| match response {
+        ControlResponse::Logs { lines } => {
+            for line in lines {
+                println!("{}", line);
+            }
+            true
+        }
+        ControlResponse::Error { message } => {
+            error = Some(message);
+            false
+        }
+        other => {
+            error = Some(format!("unexpected response: {:?}", other));
+            false
+        }
+    };
+    if follow {
+        tokio::select! {
+            result = stream_request(&socket, &request, print) => result?,
+            _ = tokio::signal::ctrl_c() => {}
+        }
     } else {
-        Ok(Config::load_with_cli(&cli_args)?)
+        let mut print = print;
+        print(send_request(&socket, &request).await?);
+    }
+    match error {
+        Some(message) => anyhow::bail!("logs failed: {}", message),
+        None => Ok(()),
     }
 }
 
-async fn run_mount(
-    mount_point: Option<PathBuf>,
-    api_url: Option<String>,
+async fn run_fsck(
     config_file: Option<PathBuf>,
-    username: Option<String>,
-    password: Option<String>,
+    socket: Option<PathBuf>,
+    repair: bool,
+    format: OutputFormat,
 ) -> Result<()> {
-    let config = load_config(config_file, mount_point, api_url, username, password)?;
+    let socket = resolve_socket(config_file, socket)?;
 
-    if !config.mount_point.exists() {
-        tracing::info!(
-            "Creating mount point: {}",
-            config.mount_point.display()
+    let reports = match send_request(&socket, &ControlRequest::Fsck { repair }).await? {
+        ControlResponse::Fsck { reports } => reports,
+        ControlResponse::Error { message } => anyhow::bail!("fsck failed: {}", message),
+        other => anyhow::bail!("unexpected response: {:?}", other),
+    };
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for report in &reports {
+            println!("{}", report.mount_point.display());
+            if report.issues.is_empty() {
+                println!("  clean");
+            }
+            for issue in &report.issues {
+                match issue {
+                    FsckIssue::Orphan { torrent_id, path } => {
+                        println!("  orphan: torrent {} at {}", torrent_id, path)
+                    }
+                    FsckIssue::Missing { torrent_id, name } => {
+                        println!("  missing: torrent {} ({})", torrent_id, name)
+                    }
+                    FsckIssue::SizeMismatch {
+                        torrent_id,
+                        path,
+                        expected,
+                        actual,
+                    } => println!(
+                        "  size mismatch: torrent {} {} is {} bytes, rqbit reports {}",
+                        torrent_id, path, actual, expected
+                    ),
+                }
+            }
+            if report.repaired {
+                println!("  repaired {} issue(s)", report.issues.len());
+            }
+        }
+    }
+
+    let unrepaired: usize = reports
+        .iter()
+        .filter(|r| !r.repaired)
+        .map(|r| r.issues.len())
+        .sum();
+    if unrepaired > 0 {
+        anyhow::bail!(
+            "{} issue(s) found, run with --repair to fix them",
+            unrepaired
         );
-        std::fs::create_dir_all(&config.mount_point).with_context(|| {
-            format!(
-                "Failed to create mount point: {}",
-                config.mount_point.display()
-            )
-        })?;
     }
+    Ok(())
+}
 
-    tracing::info!("rqbit-fuse starting");
-    tracing::info!("Using rqbit API at: {}", config.api_url);
-    tracing::info!("Mount point: {}", config.mount_point.display());
+async fn run_export_tree(
+    config_file: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let socket = resolve_socket(config_file, socket)?;
 
-    rqbit_fuse::run(config).await
+    let mounts = match send_request(&socket, &ControlRequest::ExportTree).await? {
+        ControlResponse::Tree { mounts } => mounts,
+        ControlResponse::Error { message } => anyhow::bail!("export-tree failed: {}", message),
+        other => anyhow::bail!("unexpected response: {:?}", other),
+    };
+
+    let json = serde_json::to_string_pretty(&mounts)?;
+    match output {
+        Some(path) => {
+            std::fs::write(&path, json + "\n")
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            let entries: usize = mounts.iter().map(|m| m.entries.len()).sum();
+            eprintln!("wrote {} entries to {}", entries, path.display());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+async fn run_trash(
+    config_file: Option<PathBuf>,
+    socket: Option<PathBuf>,
+    restore: Option<String>,
+    format: OutputFormat,
+) -> Result<()> {
+    let socket = resolve_socket(config_file, socket)?;
+
+    let request = ControlRequest::Trash {
+        restore: restore.clone(),
+    };
+    let mounts = match send_request(&socket, &request).await? {
+        ControlResponse::Trash { mounts } => mounts,
+        ControlResponse::Error { message } => anyhow::bail!("trash failed: {}", message),
+        other => anyhow::bail!("unexpected response: {:?}", other),
+    };
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&mounts)?);
+        return Ok(());
+    }
+    if let Some(torrent) = restore {
+        println!("restored {}", torrent);
+    }
+    if mounts.is_empty() {
+        println!("no mount has trash_days set");
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    for mount in &mounts {
+        println!("{}", mount.mount_point.display());
+        if mount.items.is_empty() {
+            println!("  empty");
+        }
+        for item in &mount.items {
+            let days_left = item.expires_at.saturating_sub(now).div_ceil(24 * 60 * 60);
+            println!(
+                "  {} (torrent {}, {}): forgotten in {} day(s)",
+                item.name, item.torrent_id, item.info_hash, days_left
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn run_bench(
+    file: PathBuf,
+    block_sizes: Vec<u64>,
+    size: u64,
+    random_reads: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    let mut options = BenchOptions {
+        size,
+        random_reads,
+        ..Default::default()
+    };
+    if !block_sizes.is_empty() {
+        options.block_sizes = block_sizes;
+    }
+
+    let results = tokio::task::spawn_blocking(move || bench::run(&file, &options)).await??;
+
+    if format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        print!("{}", bench::render_table(&results));
+    }
+    Ok(())
+}
+
+fn run_advise(file: PathBuf, advice: Advice, offset: u64, len: u64) -> Result<()> {
+    let args = FadviseArgs {
+        offset,
+        len,
+        advice,
+    };
+    fadvise::advise(&file, &args).with_context(|| format!("failed to advise {}", file.display()))
 }
 
 async fn run_umount(
     mount_point: Option<PathBuf>,
     config_file: Option<PathBuf>,
     force: bool,
+    profile: Option<String>,
+    format: OutputFormat,
 ) -> Result<()> {
-    let config = load_config(config_file, mount_point.clone(), None, None, None)?;
+    let config = load_config(&CliArgs {
+        mount_point: mount_point.clone(),
+        config_file,
+        profile,
+        ..Default::default()
+    })?;
 
     let mount_point = mount_point.unwrap_or_else(|| config.mount_point.clone());
 
     tracing::info!("Unmounting: {}", mount_point.display());
 
     if !is_mount_point(&mount_point)? {
-        anyhow::bail!("{} is not a mount point", mount_point.display());
+        return Err(
+            ExitCode::NotMounted.error(format!("{} is not a mount point", mount_point.display()))
+        );
     }
 
-    unmount_filesystem(&mount_point, force)?;
+    // fusermount only reports why it failed on stderr
+    unmount_filesystem(&mount_point, force).map_err(|e| {
+        if e.to_string().contains("busy") {
+            ExitCode::MountBusy.error(format!("{:#}", e))
+        } else {
+            e
+        }
+    })?;
 
     tracing::info!("Successfully unmounted {}", mount_point.display());
+    if format == OutputFormat::Json {
+        let result = serde_json::json!({
+            "mount_point": mount_point,
+            "unmounted": true,
+            "forced": force,
+        });
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    }
     Ok(())
 }
 
@@ -1,5 +1,6 @@
 //! Minimal performance metrics collection.
 
+use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicU64, Ordering};
 use tracing::info;
 
@@ -14,6 +15,16 @@ pub struct Metrics {
     pub cache_hits: AtomicU64,
     /// Total number of cache misses
     pub cache_misses: AtomicU64,
+    /// Persistent streams opened
+    pub streams_opened: AtomicU64,
+    /// Reads served by an already-open stream
+    pub streams_reused: AtomicU64,
+    /// Streams closed after sitting idle
+    pub streams_expired: AtomicU64,
+    /// File handles opened
+    pub handles_opened: AtomicU64,
+    /// File handles removed by the handle TTL instead of a release
+    pub handles_evicted: AtomicU64,
 }
 
 impl Metrics {
@@ -41,6 +52,46 @@ impl Metrics {
         self.cache_misses.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Record a new persistent stream
+    pub fn record_stream_opened(&self) {
+        self.streams_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a read served by an existing stream
+    pub fn record_stream_reused(&self) {
+        self.streams_reused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record streams closed for being idle
+    pub fn record_streams_expired(&self, count: u64) {
+        self.streams_expired.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Record an opened file handle
+    pub fn record_handle_opened(&self) {
+        self.handles_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record file handles evicted by the handle TTL
+    pub fn record_handles_evicted(&self, count: u64) {
+        self.handles_evicted.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Point-in-time copy of the counters, e.g. for `status`.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            error_count: self.error_count.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            streams_opened: self.streams_opened.load(Ordering::Relaxed),
+            streams_reused: self.streams_reused.load(Ordering::Relaxed),
+            streams_expired: self.streams_expired.load(Ordering::Relaxed),
+            handles_opened: self.handles_opened.load(Ordering::Relaxed),
+            handles_evicted: self.handles_evicted.load(Ordering::Relaxed),
+        }
+    }
+
     /// Log summary on shutdown
     pub fn log_summary(&self) {
         let bytes = self.bytes_read.load(Ordering::Relaxed);
@@ -54,6 +105,15 @@ impl Metrics {
             0.0
         };
 
+        let opened = self.streams_opened.load(Ordering::Relaxed);
+        let reused = self.streams_reused.load(Ordering::Relaxed);
+        let stream_reads = opened + reused;
+        let reuse_rate = if stream_reads > 0 {
+            (reused as f64 / stream_reads as f64) * 100.0
+        } else {
+            0.0
+        };
+
         info!(
             operation = "metrics_summary",
             bytes_read = bytes,
@@ -61,10 +121,35 @@ impl Metrics {
             cache_hits = hits,
             cache_misses = misses,
             cache_hit_rate_pct = hit_rate,
+            streams_opened = opened,
+            streams_reused = reused,
+            stream_reuse_rate_pct = reuse_rate,
+            streams_expired = self.streams_expired.load(Ordering::Relaxed),
+            handles_opened = self.handles_opened.load(Ordering::Relaxed),
+            handles_evicted = self.handles_evicted.load(Ordering::Relaxed),
         );
     }
 }
 
+/// Serializable copy of [`Metrics`] counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub bytes_read: u64,
+    pub error_count: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    #[serde(default)]
+    pub streams_opened: u64,
+    #[serde(default)]
+    pub streams_reused: u64,
+    #[serde(default)]
+    pub streams_expired: u64,
+    #[serde(default)]
+    pub handles_opened: u64,
+    #[serde(default)]
+    pub handles_evicted: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,4 +186,16 @@ mod tests {
 
         assert!((hit_rate - 66.67).abs() < 0.01);
     }
+
+    #[test]
+    fn test_snapshot() {
+        let metrics = Metrics::new();
+        metrics.record_read(10);
+        metrics.record_cache_miss();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.bytes_read, 10);
+        assert_eq!(snapshot.cache_misses, 1);
+        assert_eq!(snapshot.error_count, 0);
+    }
 }
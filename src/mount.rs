@@ -3,26 +3,28 @@
 use anyhow::{Context, Result};
 use std::path::PathBuf;
 
+#[cfg(feature = "cli")]
 pub fn setup_logging(verbose: u8, quiet: bool) -> Result<()> {
-    use tracing_subscriber::fmt;
-
-    if quiet {
-        let subscriber = fmt()
-            .with_max_level(tracing::Level::ERROR)
-            .without_time()
-            .finish();
-        tracing::subscriber::set_global_default(subscriber)?;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::{fmt, prelude::*};
+
+    let (level, stderr) = if quiet {
+        (tracing::Level::ERROR, fmt::layer().without_time().boxed())
     } else {
         let level = match verbose {
             0 => tracing::Level::INFO,
             1 => tracing::Level::DEBUG,
             _ => tracing::Level::TRACE,
         };
+        (level, fmt::layer().with_target(true).boxed())
+    };
 
-        let subscriber = fmt().with_max_level(level).with_target(true).finish();
-
-        tracing::subscriber::set_global_default(subscriber)?;
-    }
+    // `rqbit-fuse logs` can show debug lines without restarting with -v
+    let buffered = level.max(tracing::Level::DEBUG);
+    let subscriber = tracing_subscriber::registry()
+        .with(stderr.with_filter(LevelFilter::from_level(level)))
+        .with(crate::logs::BufferLayer.with_filter(LevelFilter::from_level(buffered)));
+    tracing::subscriber::set_global_default(subscriber)?;
 
     Ok(())
 }
@@ -101,6 +103,82 @@ pub fn is_mount_point(path: &PathBuf) -> Result<bool> {
     Ok(false)
 }
 
+/// Returns true if `path` is on a different device than its parent, without
+/// shelling out to `mount`.
+pub fn is_mounted(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let (Some(parent), Ok(meta)) = (path.parent(), std::fs::metadata(path)) else {
+        return false;
+    };
+    std::fs::metadata(parent).is_ok_and(|parent| parent.dev() != meta.dev())
+}
+
 pub fn unmount_filesystem(path: &std::path::Path, force: bool) -> Result<()> {
     try_unmount(path, force)
 }
+
+/// Gets `config.mount_point` ready to mount on: creates it if missing, with
+/// the configured mode and owner, and refuses a directory with files in it
+/// unless `nonempty` is set. Returns whether the directory was created.
+pub fn prepare_mount_point(config: &crate::config::Config) -> Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mount_point = &config.mount_point;
+    if !mount_point.exists() {
+        tracing::info!("Creating mount point: {}", mount_point.display());
+        std::fs::create_dir_all(mount_point)
+            .with_context(|| format!("Failed to create mount point: {}", mount_point.display()))?;
+        if let Some(mode) = config.mount_point_mode {
+            std::fs::set_permissions(mount_point, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to set mode of {}", mount_point.display()))?;
+        }
+        if config.mount_point_uid.is_some() || config.mount_point_gid.is_some() {
+            std::os::unix::fs::chown(mount_point, config.mount_point_uid, config.mount_point_gid)
+                .with_context(|| format!("Failed to set owner of {}", mount_point.display()))?;
+        }
+        return Ok(true);
+    }
+
+    // A FUSE mount left behind by a crashed process can't be listed; the
+    // mount itself reports it
+    let has_entries = std::fs::read_dir(mount_point)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if has_entries && !config.nonempty {
+        return Err(crate::exit_code::ExitCode::MountBusy.error(format!(
+            "{} is not empty; mount with --nonempty (or `nonempty = true`) to hide its files while mounted",
+            mount_point.display()
+        )));
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_prepare_mount_point() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            mount_point: dir.path().join("a/mnt"),
+            mount_point_mode: Some(0o700),
+            ..Config::default()
+        };
+        assert!(prepare_mount_point(&config).unwrap());
+        let meta = std::fs::metadata(&config.mount_point).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o700);
+        // An empty directory is used as it is
+        assert!(!prepare_mount_point(&config).unwrap());
+
+        std::fs::write(config.mount_point.join("file"), b"").unwrap();
+        let err = prepare_mount_point(&config).unwrap_err();
+        assert!(err.to_string().contains("not empty"), "{}", err);
+        config.nonempty = true;
+        assert!(!prepare_mount_point(&config).unwrap());
+    }
+}
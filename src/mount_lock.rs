@@ -0,0 +1,131 @@
+//! Lock against mounting the same path twice.
+//!
+//! A second `mount` on a path rqbit-fuse already serves would stack a new
+//! FUSE mount on top of the first, shadowing it until one is unmounted.
+//! Each mount point is locked with flock(2) on a file in the runtime
+//! directory, keyed by the mount point's path, and the lock is held until
+//! the process exits.
+
+use crate::exit_code::ExitCode;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Directory of the lock files: `$XDG_RUNTIME_DIR/rqbit-fuse`, or a
+/// per-user directory in `/tmp` when no runtime dir is set.
+pub fn default_lock_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("rqbit-fuse"),
+        None => PathBuf::from(format!("/tmp/rqbit-fuse-{}", unsafe { libc::geteuid() })),
+    }
+}
+
+/// The lock of one mount point; released when dropped.
+#[derive(Debug)]
+pub struct MountLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl MountLock {
+    /// Locks `mount_point` in [`default_lock_dir()`].
+    pub fn acquire(mount_point: &Path) -> Result<Self> {
+        Self::acquire_in(&default_lock_dir(), mount_point)
+    }
+
+    /// Locks `mount_point` with a lock file in `dir`, failing right away if
+    /// another process, or another mount of this one, holds it.
+    pub fn acquire_in(dir: &Path, mount_point: &Path) -> Result<Self> {
+        let mount_point = normalize(mount_point);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create lock directory {}", dir.display()))?;
+        let path = dir.join(lock_file_name(&mount_point));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("failed to open lock file {}", path.display()))?;
+
+        // SAFETY: flock only takes the descriptor of the open file
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EWOULDBLOCK) {
+                return Err(err).with_context(|| format!("failed to lock {}", path.display()));
+            }
+            let mut owner = String::new();
+            let _ = file.read_to_string(&mut owner);
+            let pid = owner.lines().next().unwrap_or("?");
+            return Err(ExitCode::MountBusy.error(format!(
+                "{} is already mounted by rqbit-fuse (pid {}); unmount it first with `rqbit-fuse umount -m {}`",
+                mount_point.display(),
+                pid,
+                mount_point.display()
+            )));
+        }
+
+        // Record the owner for the error above
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}\n{}", std::process::id(), mount_point.display())?;
+        Ok(Self { path, _file: file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The absolute, symlink-free path, as far as it can be resolved. A mount
+/// point whose FUSE server died can't be resolved; it is made absolute.
+fn normalize(mount_point: &Path) -> PathBuf {
+    mount_point
+        .canonicalize()
+        .or_else(|_| std::path::absolute(mount_point))
+        .unwrap_or_else(|_| mount_point.to_path_buf())
+}
+
+/// Lock file of a mount point: a hash of its path, so any path fits.
+fn lock_file_name(mount_point: &Path) -> String {
+    let digest = Sha256::digest(mount_point.as_os_str().as_encoded_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("mount-{}.lock", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_on_mount_point_fails() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mount_point = dir.path().join("mnt");
+        std::fs::create_dir(&mount_point).unwrap();
+
+        let lock = MountLock::acquire_in(dir.path(), &mount_point).unwrap();
+        // The same directory by another path
+        let err = MountLock::acquire_in(dir.path(), &mount_point.join("../mnt")).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("already mounted"), "{}", message);
+        assert!(
+            message.contains(&std::process::id().to_string()),
+            "{}",
+            message
+        );
+
+        // Other mount points are independent
+        let other = dir.path().join("other");
+        std::fs::create_dir(&other).unwrap();
+        let other_lock = MountLock::acquire_in(dir.path(), &other).unwrap();
+        assert_ne!(other_lock.path(), lock.path());
+
+        drop(lock);
+        MountLock::acquire_in(dir.path(), &mount_point).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+//! Dropping root privileges once the mounts are established.
+//!
+//! Mounting in a system location may need root, but the long-running
+//! process talks to the network and serves every FUSE request. With
+//! `run_as_user` it switches to an unprivileged user as soon as every FUSE
+//! session is up. Descriptors opened before the switch, such as `/dev/fuse`,
+//! the control socket and the audit log, stay usable.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::{CStr, CString};
+
+/// The user and group the process switches to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+    /// Login name, used to set the user's supplementary groups.
+    name: Option<CString>,
+}
+
+impl Credentials {
+    /// Resolves `user`, a name or numeric uid, and `group`, a name or
+    /// numeric gid. Without a group the user's primary group is used.
+    pub fn resolve(user: &str, group: Option<&str>) -> Result<Self> {
+        let (uid, primary_gid, name) = lookup_user(user)?;
+        let gid = match group {
+            Some(group) => lookup_group(group)?,
+            None => primary_gid
+                .with_context(|| format!("user {} has no passwd entry; set run_as_group", user))?,
+        };
+        Ok(Self { uid, gid, name })
+    }
+}
+
+// getpwnam() and friends return static storage, so each entry is copied out
+// before the next lookup
+fn lookup_user(user: &str) -> Result<(u32, Option<u32>, Option<CString>)> {
+    let name = CString::new(user).context("user name contains NUL")?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if !entry.is_null() {
+        let entry = unsafe { &*entry };
+        return Ok((entry.pw_uid, Some(entry.pw_gid), Some(name)));
+    }
+
+    let Ok(uid) = user.parse::<u32>() else {
+        bail!("unknown user {}", user);
+    };
+    let entry = unsafe { libc::getpwuid(uid) };
+    if entry.is_null() {
+        return Ok((uid, None, None));
+    }
+    let entry = unsafe { &*entry };
+    let name = unsafe { CStr::from_ptr(entry.pw_name) }.to_owned();
+    Ok((uid, Some(entry.pw_gid), Some(name)))
+}
+
+fn lookup_group(group: &str) -> Result<u32> {
+    let name = CString::new(group).context("group name contains NUL")?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if !entry.is_null() {
+        return Ok(unsafe { (*entry).gr_gid });
+    }
+    group
+        .parse()
+        .map_err(|_| anyhow::anyhow!("unknown group {}", group))
+}
+
+/// Switches every thread of the process to `credentials` for good.
+pub fn drop_privileges(credentials: &Credentials) -> Result<()> {
+    let Credentials { uid, gid, name } = credentials;
+    // Groups first: changing them needs the privileges dropped below
+    let rc = match name {
+        Some(name) => unsafe { libc::initgroups(name.as_ptr(), *gid as _) },
+        None => unsafe { libc::setgroups(1, gid) },
+    };
+    check(rc, "setgroups")?;
+    check(unsafe { libc::setgid(*gid) }, "setgid")?;
+    check(unsafe { libc::setuid(*uid) }, "setuid")?;
+
+    if *uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        bail!("root privileges could be regained after setuid({})", uid);
+    }
+    Ok(())
+}
+
+fn check(rc: libc::c_int, call: &str) -> Result<()> {
+    if rc != 0 {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("{} failed", call));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_root() {
+        let by_name = Credentials::resolve("root", None).unwrap();
+        assert_eq!((by_name.uid, by_name.gid), (0, 0));
+        assert_eq!(Credentials::resolve("0", None).unwrap(), by_name);
+        assert_eq!(Credentials::resolve("root", Some("0")).unwrap().gid, 0);
+    }
+
+    #[test]
+    fn test_resolve_unknown_users() {
+        assert!(Credentials::resolve("no-such-user-rqbit-fuse", None).is_err());
+        assert!(Credentials::resolve("root", Some("no-such-group-rqbit-fuse")).is_err());
+
+        // Numeric ids without a passwd entry need an explicit group
+        assert!(Credentials::resolve("4000000000", None).is_err());
+        let creds = Credentials::resolve("4000000000", Some("4000000001")).unwrap();
+        assert_eq!((creds.uid, creds.gid), (4_000_000_000, 4_000_000_001));
+    }
+}
@@ -0,0 +1,323 @@
+//! Optional seccomp sandbox.
+//!
+//! rqbit-fuse parses torrent metadata and network responses it doesn't
+//! control. With `seccomp = true` (and the `seccomp` feature) every thread is
+//! restricted to the system calls needed to serve FUSE requests, talk to
+//! rqbit and write logs, once the mounts are up. Anything else, such as
+//! `execve`, `ptrace` or `mount`, fails with `EPERM`.
+
+use anyhow::{bail, Result};
+use libc::{sock_filter, sock_fprog, BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+compile_error!("the `seccomp` feature supports Linux on x86_64 and aarch64 only");
+
+// Offsets into `struct seccomp_data`
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+
+/// System calls available in both supported architectures.
+const ALLOWED: &[libc::c_long] = &[
+    // Files, FUSE device, pipes
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_preadv,
+    libc::SYS_pwritev,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_splice,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_unlinkat,
+    // State files (trash, snapshot) are written next to their path and renamed over it
+    libc::SYS_mkdirat,
+    libc::SYS_renameat2,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_umount2,
+    // Memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    // Threads and scheduling
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_prctl,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_restart_syscall,
+    // Event loop and timers
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    // Network: rqbit API, DNS and the control socket
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept4,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_setsockopt,
+    libc::SYS_getsockopt,
+    libc::SYS_sendto,
+    libc::SYS_recvfrom,
+    libc::SYS_sendmsg,
+    libc::SYS_recvmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_shutdown,
+    // Signals and child processes (container mode reaps zombies)
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+    libc::SYS_wait4,
+    libc::SYS_waitid,
+    // Process information
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getppid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getrandom,
+    libc::SYS_prlimit64,
+    libc::SYS_uname,
+];
+
+/// Older variants only x86_64 has; its libc may still use them.
+#[cfg(target_arch = "x86_64")]
+const ALLOWED_ARCH: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_pipe,
+    libc::SYS_poll,
+    libc::SYS_epoll_wait,
+    libc::SYS_dup2,
+    libc::SYS_readlink,
+    libc::SYS_unlink,
+    libc::SYS_rmdir,
+    libc::SYS_mkdir,
+    libc::SYS_rename,
+    libc::SYS_renameat,
+    libc::SYS_accept,
+    libc::SYS_arch_prctl,
+];
+#[cfg(target_arch = "aarch64")]
+const ALLOWED_ARCH: &[libc::c_long] = &[];
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Builds the filter: kill on a foreign architecture, allow the listed
+/// system calls, fail everything else with `EPERM`.
+fn filter() -> Vec<sock_filter> {
+    let mut program = vec![
+        stmt(BPF_LD | BPF_W | BPF_ABS, ARCH_OFFSET),
+        jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+        stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD | BPF_W | BPF_ABS, NR_OFFSET),
+    ];
+    for &nr in ALLOWED.iter().chain(ALLOWED_ARCH) {
+        program.push(jump(BPF_JMP | BPF_JEQ | BPF_K, nr as u32, 0, 1));
+        program.push(stmt(BPF_RET | BPF_K, libc::SECCOMP_RET_ALLOW));
+    }
+    program.push(stmt(
+        BPF_RET | BPF_K,
+        libc::SECCOMP_RET_ERRNO | libc::EPERM as u32,
+    ));
+    program
+}
+
+/// Installs `program` on every thread of the process. Doesn't allocate, so
+/// it is safe to call in a forked child.
+fn install(program: &[sock_filter]) -> std::io::Result<()> {
+    let prog = sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_ptr() as *mut sock_filter,
+    };
+    // Required to install a filter without CAP_SYS_ADMIN
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &prog as *const sock_fprog,
+        )
+    };
+    match rc {
+        0 => Ok(()),
+        // TSYNC reports the thread it couldn't synchronize
+        tid if tid > 0 => Err(std::io::Error::other(format!(
+            "thread {} could not be synchronized",
+            tid
+        ))),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Restricts every thread of the process to the allowed system calls, for
+/// the rest of its life.
+pub fn apply() -> Result<()> {
+    if let Err(e) = install(&filter()) {
+        bail!("seccomp: {}", e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_fits() {
+        let program = filter();
+        assert!(program.len() < libc::BPF_MAXINSNS as usize);
+        assert_eq!(
+            program.len(),
+            4 + 2 * (ALLOWED.len() + ALLOWED_ARCH.len()) + 1
+        );
+    }
+
+    #[test]
+    fn test_filter_blocks_exec() {
+        // Install in a forked child, which has only the forking thread
+        let program = filter();
+        let true_path = c"/bin/true";
+        let argv = [true_path.as_ptr(), std::ptr::null()];
+        let envp = [std::ptr::null()];
+
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            let code = match install(&program) {
+                Err(_) => 1,
+                Ok(()) => {
+                    unsafe { libc::execve(true_path.as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+                    if std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM) {
+                        // Allowed calls keep working
+                        if unsafe { libc::getpid() } > 0 {
+                            0
+                        } else {
+                            2
+                        }
+                    } else {
+                        3
+                    }
+                }
+            };
+            unsafe { libc::_exit(code) };
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+    }
+
+    /// Runs `check` in a forked child with the filter installed; true if it
+    /// returned true there.
+    fn passes_in_sandbox(check: impl FnOnce() -> bool) -> bool {
+        let program = filter();
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0);
+        if pid == 0 {
+            let code = match install(&program) {
+                Err(_) => 1,
+                Ok(()) if check() => 0,
+                Ok(()) => 2,
+            };
+            unsafe { libc::_exit(code) };
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+    }
+
+    #[test]
+    fn test_filter_allows_state_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let trash_path = dir.path().join("state/trash.json");
+        assert!(passes_in_sandbox(|| {
+            crate::fs::trash::Trash::open(&trash_path, std::time::Duration::ZERO)
+                .and_then(|trash| trash.put("abc123", std::time::SystemTime::now()))
+                .is_ok()
+        }));
+        assert!(
+            crate::fs::trash::Trash::open(&trash_path, std::time::Duration::ZERO)
+                .unwrap()
+                .contains("abc123")
+        );
+
+        let snapshot_path = dir.path().join("snapshot/snapshot.json");
+        let snapshot = crate::fs::snapshot::SnapshotStore::new(&snapshot_path, "http://rqbit");
+        assert!(passes_in_sandbox(|| snapshot.save(&[]).is_ok()));
+        assert!(snapshot.load().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_filter_allows_removing_mount_points() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mount_point = dir.path().join("mnt");
+        std::fs::create_dir(&mount_point).unwrap();
+        assert!(passes_in_sandbox(
+            || std::fs::remove_dir(&mount_point).is_ok()
+        ));
+        assert!(!mount_point.exists());
+    }
+}
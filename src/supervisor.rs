@@ -0,0 +1,229 @@
+//! Supervisor mode: run the mount in a child process and remount it after
+//! crashes.
+//!
+//! The supervisor restarts the child when it exits with an error or is
+//! killed, and when a mount point reports "Transport endpoint is not
+//! connected" (the FUSE daemon is gone or hung). Stale mounts are lazily
+//! unmounted before each restart. A clean exit of the child, e.g. after
+//! `rqbit-fuse umount`, ends supervision.
+
+use crate::mount::unmount_filesystem;
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+/// How often mount points are checked for a disconnected transport.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// A child that stays up this long resets the restart backoff.
+const STABLE_RUNTIME: Duration = Duration::from_secs(60);
+/// Time the child gets to unmount after SIGTERM before it is killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(15);
+
+/// Exponential restart delay.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            current: initial,
+        }
+    }
+
+    /// Returns the delay before the next restart and doubles it.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    /// Starts over from the initial delay.
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+/// Returns true if `path` is a FUSE mount whose daemon is gone
+/// ("Transport endpoint is not connected").
+pub fn is_disconnected(path: &Path) -> bool {
+    matches!(
+        std::fs::metadata(path),
+        Err(e) if e.raw_os_error() == Some(libc::ENOTCONN)
+    )
+}
+
+/// Lazily unmounts every mount point whose transport is disconnected.
+fn cleanup_stale_mounts(mount_points: &[PathBuf]) {
+    for mount_point in mount_points {
+        if is_disconnected(mount_point) {
+            info!("Unmounting stale mount {}", mount_point.display());
+            if let Err(e) = unmount_filesystem(mount_point, true) {
+                warn!("Failed to unmount {}: {:#}", mount_point.display(), e);
+            }
+        }
+    }
+}
+
+enum ChildOutcome {
+    Exited(ExitStatus),
+    Disconnected(PathBuf),
+    Shutdown,
+}
+
+/// Runs `program args` and restarts it with backoff until it exits cleanly
+/// or the supervisor receives SIGINT/SIGTERM.
+pub async fn supervise(
+    program: PathBuf,
+    args: Vec<OsString>,
+    mount_points: Vec<PathBuf>,
+) -> Result<()> {
+    let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut backoff = Backoff::default();
+
+    loop {
+        cleanup_stale_mounts(&mount_points);
+
+        let started = Instant::now();
+        let mut child = Command::new(&program)
+            .args(&args)
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start {}", program.display()))?;
+        info!(pid = child.id(), "Supervisor started mount process");
+
+        let mut health = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        health.tick().await;
+        let outcome = loop {
+            tokio::select! {
+                status = child.wait() => break ChildOutcome::Exited(status?),
+                _ = sigint.recv() => break ChildOutcome::Shutdown,
+                _ = sigterm.recv() => break ChildOutcome::Shutdown,
+                _ = health.tick() => {
+                    if let Some(path) = mount_points.iter().find(|p| is_disconnected(p)) {
+                        break ChildOutcome::Disconnected(path.clone());
+                    }
+                }
+            }
+        };
+
+        match outcome {
+            ChildOutcome::Shutdown => {
+                info!("Supervisor stopping mount process");
+                stop_child(&mut child).await;
+                return Ok(());
+            }
+            ChildOutcome::Exited(status) if status.success() => {
+                info!("Mount process exited cleanly, supervisor done");
+                return Ok(());
+            }
+            ChildOutcome::Exited(status) => {
+                error!("Mount process exited with {}", status);
+            }
+            ChildOutcome::Disconnected(path) => {
+                error!(
+                    "Mount at {} is disconnected, restarting mount process",
+                    path.display()
+                );
+                stop_child(&mut child).await;
+            }
+        }
+
+        if started.elapsed() >= STABLE_RUNTIME {
+            backoff.reset();
+        }
+        let delay = backoff.next_delay();
+        info!("Remounting in {:?}", delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = sigint.recv() => return Ok(()),
+            _ = sigterm.recv() => return Ok(()),
+        }
+    }
+}
+
+/// Asks the child to shut down with SIGTERM, killing it if it doesn't exit
+/// in time.
+async fn stop_child(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+    }
+    if tokio::time::timeout(SHUTDOWN_GRACE, child.wait())
+        .await
+        .is_err()
+    {
+        warn!("Mount process did not exit after SIGTERM, killing it");
+        let _ = child.kill().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(5));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_is_disconnected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(!is_disconnected(dir.path()));
+        assert!(!is_disconnected(&dir.path().join("missing")));
+    }
+
+    #[tokio::test]
+    async fn test_supervise_stops_after_clean_exit() {
+        supervise(PathBuf::from("true"), vec![], vec![])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_supervise_restarts_failed_child() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let marker = dir.path().join("ran");
+        // Fails on the first run, succeeds on the second
+        let script = format!(
+            "if [ -e {0} ]; then exit 0; else touch {0}; exit 1; fi",
+            marker.display()
+        );
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            supervise(
+                PathBuf::from("sh"),
+                vec!["-c".into(), script.into()],
+                vec![],
+            ),
+        )
+        .await
+        .expect("supervisor should stop after the successful restart")
+        .unwrap();
+        assert!(marker.exists());
+    }
+}
@@ -0,0 +1,238 @@
+//! `rqbit-fuse top`: live view of reads through a running process's mounts.
+//!
+//! The control socket reports cumulative counters. Each refresh compares the
+//! latest report with the previous one, so rates cover the last interval,
+//! like `iotop`.
+
+use crate::control::ActivityReport;
+use crate::types::AccessPattern;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Clears the terminal and moves the cursor home.
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+fn format_rate(bytes_per_sec: f64) -> String {
+    const MIB: f64 = (1 << 20) as f64;
+    const KIB: f64 = (1 << 10) as f64;
+    if bytes_per_sec >= MIB {
+        format!("{:.1} MiB/s", bytes_per_sec / MIB)
+    } else if bytes_per_sec >= KIB {
+        format!("{:.1} KiB/s", bytes_per_sec / KIB)
+    } else {
+        format!("{:.0} B/s", bytes_per_sec)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GiB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+fn format_latency(micros: u64) -> String {
+    match micros {
+        0 => "-".to_string(),
+        us if us >= 1_000_000 => format!("{:.1} s", us as f64 / 1e6),
+        us if us >= 1000 => format!("{:.1} ms", us as f64 / 1e3),
+        us => format!("{} us", us),
+    }
+}
+
+/// Bytes per second between two cumulative counters.
+fn rate(previous: Option<u64>, current: u64, elapsed: Duration) -> f64 {
+    match previous {
+        Some(previous) if !elapsed.is_zero() => {
+            current.saturating_sub(previous) as f64 / elapsed.as_secs_f64()
+        }
+        _ => 0.0,
+    }
+}
+
+/// Renders one screen from the latest report and the one taken `elapsed`
+/// earlier. Without a previous report every rate is zero.
+pub fn render(
+    previous: Option<&ActivityReport>,
+    current: &ActivityReport,
+    elapsed: Duration,
+) -> String {
+    let metrics = &current.metrics;
+    let lookups = metrics.cache_hits + metrics.cache_misses;
+    let hit_ratio = if lookups > 0 {
+        format!("{:.0}%", metrics.cache_hits as f64 * 100.0 / lookups as f64)
+    } else {
+        "-".to_string()
+    };
+    let total_rate = rate(
+        previous.map(|p| p.metrics.bytes_read),
+        metrics.bytes_read,
+        elapsed,
+    );
+    let mut screen = format!(
+        "read {}, cache hit ratio {}, {} errors\n",
+        format_rate(total_rate),
+        hit_ratio,
+        metrics.error_count
+    );
+
+    for mount in &current.mounts {
+        let before =
+            previous.and_then(|p| p.mounts.iter().find(|m| m.mount_point == mount.mount_point));
+        let handle_bytes: HashMap<u64, u64> = before
+            .map(|m| m.handles.iter().map(|h| (h.fh, h.bytes_read)).collect())
+            .unwrap_or_default();
+        let torrent_bytes: HashMap<u64, u64> = before
+            .map(|m| {
+                m.torrents
+                    .iter()
+                    .map(|t| (t.torrent_id, t.bytes_read))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        screen.push_str(&format!(
+            "\n{}  (worker queue {})\n",
+            mount.mount_point.display(),
+            mount.queue_depth
+        ));
+
+        // Most active first; handles opened since the last report count
+        // from zero
+        let mut handles: Vec<_> = mount
+            .handles
+            .iter()
+            .map(|h| {
+                let previous = before.map(|_| handle_bytes.get(&h.fh).copied().unwrap_or(0));
+                (rate(previous, h.bytes_read, elapsed), h)
+            })
+            .collect();
+        handles.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.path.cmp(&b.1.path)));
+        screen.push_str(&format!(
+            "  {:>12}  {:>10}  {:>8}  {:>6}  {:<10}  {}\n",
+            "RATE", "OFFSET", "LATENCY", "SEEKS", "PATTERN", "OPEN FILE"
+        ));
+        for (rate, h) in &handles {
+            let pattern = match h.pattern {
+                Some(AccessPattern::Sequential) => "sequential",
+                Some(AccessPattern::Mixed) => "mixed",
+                Some(AccessPattern::Random) => "random",
+                None => "-",
+            };
+            screen.push_str(&format!(
+                "  {:>12}  {:>10}  {:>8}  {:>6}  {:<10}  {}\n",
+                format_rate(*rate),
+                format_bytes(h.offset),
+                format_latency(h.avg_latency_us),
+                h.seeks,
+                pattern,
+                h.path
+            ));
+        }
+
+        let mut torrents: Vec<_> = mount
+            .torrents
+            .iter()
+            .map(|t| {
+                let previous =
+                    before.map(|_| torrent_bytes.get(&t.torrent_id).copied().unwrap_or(0));
+                (rate(previous, t.bytes_read, elapsed), t)
+            })
+            .collect();
+        torrents.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then(a.1.torrent_id.cmp(&b.1.torrent_id))
+        });
+        screen.push_str(&format!(
+            "  {:>12}  {:>10}  {}\n",
+            "RATE", "TOTAL", "TORRENT"
+        ));
+        for (rate, t) in &torrents {
+            screen.push_str(&format!(
+                "  {:>12}  {:>10}  {} ({})\n",
+                format_rate(*rate),
+                format_bytes(t.bytes_read),
+                t.name,
+                t.torrent_id
+            ));
+        }
+    }
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{HandleActivity, MountActivity, TorrentActivity};
+    use crate::metrics::MetricsSnapshot;
+    use std::path::PathBuf;
+
+    fn report(bytes_read: u64, handles: &[(u64, &str, u64)]) -> ActivityReport {
+        ActivityReport {
+            metrics: MetricsSnapshot {
+                bytes_read,
+                cache_hits: 3,
+                cache_misses: 1,
+                ..Default::default()
+            },
+            mounts: vec![MountActivity {
+                mount_point: PathBuf::from("/mnt/torrents"),
+                queue_depth: 2,
+                handles: handles
+                    .iter()
+                    .map(|&(fh, path, bytes_read)| HandleActivity {
+                        fh,
+                        torrent_id: 1,
+                        path: path.to_string(),
+                        offset: bytes_read,
+                        bytes_read,
+                        reads: 4,
+                        seeks: 1,
+                        pattern: Some(AccessPattern::Mixed),
+                        avg_latency_us: 2500,
+                    })
+                    .collect(),
+                torrents: vec![TorrentActivity {
+                    torrent_id: 1,
+                    name: "Movie".to_string(),
+                    bytes_read,
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_render_rates_between_reports() {
+        let before = report(1 << 20, &[(1, "/Movie/a.mkv", 1 << 20)]);
+        let after = report(
+            5 << 20,
+            &[(1, "/Movie/a.mkv", 2 << 20), (2, "/Movie/b.mkv", 3 << 20)],
+        );
+        let screen = render(Some(&before), &after, Duration::from_secs(2));
+
+        assert!(screen.starts_with("read 2.0 MiB/s, cache hit ratio 75%, 0 errors\n"));
+        assert!(screen.contains("/mnt/torrents  (worker queue 2)"));
+        // The handle opened since the last report is the busiest
+        let b = screen
+            .find("1.5 MiB/s     3.0 MiB    2.5 ms       1  mixed       /Movie/b.mkv")
+            .unwrap();
+        let a = screen
+            .find("512.0 KiB/s     2.0 MiB    2.5 ms       1  mixed       /Movie/a.mkv")
+            .unwrap();
+        assert!(b < a);
+        assert!(screen.contains("2.0 MiB/s     5.0 MiB  Movie (1)"));
+    }
+
+    #[test]
+    fn test_render_first_report_has_no_rates() {
+        let screen = render(
+            None,
+            &report(1 << 20, &[(1, "/a", 1 << 20)]),
+            Duration::ZERO,
+        );
+        assert!(screen.starts_with("read 0 B/s"));
+        assert!(screen.contains("0 B/s     1.0 MiB    2.5 ms       1  mixed       /a"));
+    }
+}
@@ -1,6 +1,51 @@
+use crate::api::memory::MemoryBudget;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::hash::Hasher;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Bytes of read-ahead data covered by one checksum.
+const CHECKSUM_BLOCK: usize = 64 * 1024;
+
+/// Checksums of read-ahead data, one per [`CHECKSUM_BLOCK`], taken when it
+/// was stored so data corrupted while held in memory isn't served.
+#[derive(Debug, Clone)]
+pub struct ReadAheadChecksums {
+    /// File offset of `data`.
+    offset: u64,
+    /// All the data stored; the read-ahead data is what is left of it.
+    data: Bytes,
+    sums: Arc<[u64]>,
+}
+
+impl ReadAheadChecksums {
+    fn new(offset: u64, data: Bytes) -> Self {
+        let sums = data.chunks(CHECKSUM_BLOCK).map(checksum).collect();
+        Self { offset, data, sums }
+    }
+
+    /// Returns true if the blocks holding `offset..offset + len` still
+    /// match their checksums.
+    fn verify(&self, offset: u64, len: usize) -> bool {
+        let start = (offset - self.offset) as usize;
+        let blocks = start / CHECKSUM_BLOCK..(start + len).div_ceil(CHECKSUM_BLOCK);
+        blocks.into_iter().all(|block| {
+            let from = block * CHECKSUM_BLOCK;
+            let to = (from + CHECKSUM_BLOCK).min(self.data.len());
+            checksum(&self.data[from..to]) == self.sums[block]
+        })
+    }
+}
+
+fn checksum(data: &[u8]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
 
 /// Information stored for each open file handle.
 #[derive(Debug, Clone)]
@@ -13,6 +58,36 @@ pub struct FileHandle {
     pub torrent_id: u64,
     /// Open flags used when opening the file
     pub flags: i32,
+    /// User that opened the file
+    pub uid: u32,
+    /// When the handle was opened or last read through
+    pub last_used: Instant,
+    /// Data fetched beyond the last read, and the file offset it starts at
+    pub read_ahead: Option<(u64, Bytes)>,
+    /// Checksums of the read-ahead data, with `cache_checksums` set
+    pub read_ahead_checksums: Option<ReadAheadChecksums>,
+    /// Bytes returned by reads through this handle
+    pub bytes_read: u64,
+    /// File offset just past the last read
+    pub position: u64,
+    /// Reads through this handle
+    pub reads: u64,
+    /// Reads not starting where the previous one ended
+    pub seeks: u64,
+    /// Time spent answering the reads
+    pub read_latency: Duration,
+}
+
+/// How an application moves through a file, from the share of its reads
+/// that seek.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessPattern {
+    /// At most one read in eight seeks.
+    Sequential,
+    Mixed,
+    /// At least every other read seeks.
+    Random,
 }
 
 impl FileHandle {
@@ -23,8 +98,37 @@ impl FileHandle {
             inode,
             torrent_id,
             flags,
+            uid: 0,
+            last_used: Instant::now(),
+            read_ahead: None,
+            read_ahead_checksums: None,
+            bytes_read: 0,
+            position: 0,
+            reads: 0,
+            seeks: 0,
+            read_latency: Duration::ZERO,
         }
     }
+
+    /// The access pattern of the reads so far, if there were any.
+    pub fn access_pattern(&self) -> Option<AccessPattern> {
+        match (self.reads, self.seeks) {
+            (0, _) => None,
+            (reads, seeks) if seeks * 8 <= reads => Some(AccessPattern::Sequential),
+            (reads, seeks) if seeks * 2 >= reads => Some(AccessPattern::Random),
+            _ => Some(AccessPattern::Mixed),
+        }
+    }
+
+    /// Bytes of read-ahead data held.
+    fn read_ahead_len(&self) -> usize {
+        self.read_ahead.as_ref().map_or(0, |(_, data)| data.len())
+    }
+
+    /// Mean time taken by a read, if there were any.
+    pub fn average_latency(&self) -> Option<Duration> {
+        (self.reads > 0).then(|| self.read_latency / self.reads as u32)
+    }
 }
 
 /// Manager for file handles.
@@ -37,6 +141,17 @@ pub struct FileHandleManager {
     handles: Arc<Mutex<HashMap<u64, FileHandle>>>,
     /// Maximum number of file handles allowed (0 = unlimited)
     max_handles: usize,
+    /// Maximum number of file handles one uid may hold (0 = unlimited)
+    max_handles_per_uid: usize,
+    /// Budget the handles' read-ahead data is charged to
+    memory: Option<Arc<MemoryBudget>>,
+    /// Read-ahead hits to verify the checksums of: one in this many
+    /// (0 = no checksums)
+    checksum_every: u64,
+    /// Read-ahead hits so far, for `checksum_every`
+    hits: AtomicU64,
+    /// Read-ahead data dropped for failing its checksums
+    checksum_failures: AtomicU64,
 }
 
 impl FileHandleManager {
@@ -54,19 +169,66 @@ impl FileHandleManager {
             next_handle: AtomicU64::new(1), // Start at 1, 0 is reserved/invalid
             handles: Arc::new(Mutex::new(HashMap::new())),
             max_handles,
+            max_handles_per_uid: 0,
+            memory: None,
+            checksum_every: 0,
+            hits: AtomicU64::new(0),
+            checksum_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Limit the handles one uid may hold (0 = unlimited)
+    pub fn with_max_handles_per_uid(mut self, max_handles_per_uid: usize) -> Self {
+        self.max_handles_per_uid = max_handles_per_uid;
+        self
+    }
+
+    /// Charge the handles' read-ahead data to `memory`
+    pub fn with_memory(mut self, memory: Arc<MemoryBudget>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    /// Checksum read-ahead data and verify one hit in `every` against its
+    /// checksums (0 = no checksums)
+    pub fn with_checksums(mut self, every: u64) -> Self {
+        self.checksum_every = every;
+        self
+    }
+
+    /// Read-ahead data dropped so far for failing its checksums
+    pub fn checksum_failures(&self) -> u64 {
+        self.checksum_failures.load(Ordering::Relaxed)
+    }
+
+    /// Updates the memory charge after read-ahead data changed size
+    fn charge_read_ahead(&self, added: usize, removed: usize) {
+        if let Some(memory) = &self.memory {
+            memory.reserve(added as u64);
+            memory.release(removed as u64);
         }
     }
 
     /// Allocate a new file handle for an open file.
     /// Returns a unique handle ID, or 0 if handle limit is reached.
     pub fn allocate(&self, inode: u64, torrent_id: u64, flags: i32) -> u64 {
+        self.allocate_for(0, inode, torrent_id, flags)
+    }
+
+    /// Allocate a file handle for a file opened by `uid`.
+    /// Returns 0 if the global or the uid's handle limit is reached.
+    pub fn allocate_for(&self, uid: u32, inode: u64, torrent_id: u64, flags: i32) -> u64 {
+        let mut handles = self.handles.lock().unwrap();
+
         // Check if we're at the handle limit
-        if self.max_handles > 0 {
-            let handles = self.handles.lock().unwrap();
-            if handles.len() >= self.max_handles {
-                // At limit - return 0 to indicate failure
-                return 0;
-            }
+        if self.max_handles > 0 && handles.len() >= self.max_handles {
+            // At limit - return 0 to indicate failure
+            return 0;
+        }
+        if self.max_handles_per_uid > 0
+            && handles.values().filter(|h| h.uid == uid).count() >= self.max_handles_per_uid
+        {
+            return 0;
         }
 
         let fh = self.next_handle.fetch_add(1, Ordering::SeqCst);
@@ -78,9 +240,10 @@ impl FileHandleManager {
             fh
         };
 
-        let handle = FileHandle::new(fh, inode, torrent_id, flags);
-
-        let mut handles = self.handles.lock().unwrap();
+        let handle = FileHandle {
+            uid,
+            ..FileHandle::new(fh, inode, torrent_id, flags)
+        };
         handles.insert(fh, handle);
 
         fh
@@ -96,7 +259,116 @@ impl FileHandleManager {
     /// Returns the removed handle information if it existed.
     pub fn remove(&self, fh: u64) -> Option<FileHandle> {
         let mut handles = self.handles.lock().unwrap();
-        handles.remove(&fh)
+        let handle = handles.remove(&fh)?;
+        self.charge_read_ahead(0, handle.read_ahead_len());
+        Some(handle)
+    }
+
+    /// Takes `size` bytes at `offset` from the handle's read-ahead data if
+    /// it holds all of them. The data before them is dropped. Data failing
+    /// its checksums is dropped entirely, to be fetched again.
+    pub fn take_read_ahead(&self, fh: u64, offset: u64, size: usize) -> Option<Bytes> {
+        let mut handles = self.handles.lock().unwrap();
+        let handle = handles.get_mut(&fh)?;
+        let (start, data) = handle.read_ahead.as_mut()?;
+        if offset < *start || offset + size as u64 > *start + data.len() as u64 {
+            return None;
+        }
+
+        if let Some(checksums) = &handle.read_ahead_checksums {
+            let sampled = self
+                .hits
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.checksum_every);
+            if sampled && !checksums.verify(offset, size) {
+                warn!(
+                    fh,
+                    inode = handle.inode,
+                    offset,
+                    "Cached read-ahead data failed its checksum; fetching it again"
+                );
+                self.checksum_failures.fetch_add(1, Ordering::Relaxed);
+                self.charge_read_ahead(0, handle.read_ahead_len());
+                handle.read_ahead = None;
+                handle.read_ahead_checksums = None;
+                return None;
+            }
+        }
+
+        let skip = (offset - *start) as usize;
+        let taken = data.slice(skip..skip + size);
+        self.charge_read_ahead(0, skip + size);
+        if skip + size == data.len() {
+            handle.read_ahead = None;
+            handle.read_ahead_checksums = None;
+        } else {
+            *data = data.slice(skip + size..);
+            *start = offset + size as u64;
+        }
+        Some(taken)
+    }
+
+    /// Keeps `data`, read from `offset`, for the handle's following reads.
+    pub fn set_read_ahead(&self, fh: u64, offset: u64, data: Bytes) {
+        let mut handles = self.handles.lock().unwrap();
+        if let Some(handle) = handles.get_mut(&fh) {
+            self.charge_read_ahead(data.len(), handle.read_ahead_len());
+            handle.read_ahead_checksums = (self.checksum_every > 0 && !data.is_empty())
+                .then(|| ReadAheadChecksums::new(offset, data.clone()));
+            handle.read_ahead = (!data.is_empty()).then_some((offset, data));
+        }
+    }
+
+    /// Drops the read-ahead data of every handle, returning the bytes freed.
+    pub fn drop_read_ahead(&self) -> usize {
+        let mut handles = self.handles.lock().unwrap();
+        let freed = handles
+            .values_mut()
+            .filter_map(|handle| {
+                handle.read_ahead_checksums = None;
+                handle.read_ahead.take()
+            })
+            .map(|(_, data)| data.len())
+            .sum();
+        self.charge_read_ahead(0, freed);
+        freed
+    }
+
+    /// Records a read of `bytes` at `offset` through the handle, answered
+    /// in `latency`.
+    pub fn record_read(&self, fh: u64, offset: u64, bytes: u64, latency: Duration) {
+        let mut handles = self.handles.lock().unwrap();
+        if let Some(handle) = handles.get_mut(&fh) {
+            if offset != handle.position {
+                handle.seeks += 1;
+            }
+            handle.reads += 1;
+            handle.read_latency += latency;
+            handle.bytes_read += bytes;
+            handle.position = offset + bytes;
+            handle.last_used = Instant::now();
+        }
+    }
+
+    /// Removes the handles unused for `ttl`, such as ones whose release
+    /// never arrived, and returns them.
+    pub fn evict_idle(&self, ttl: Duration) -> Vec<FileHandle> {
+        let mut handles = self.handles.lock().unwrap();
+        let idle: Vec<u64> = handles
+            .iter()
+            .filter(|(_, h)| h.last_used.elapsed() >= ttl)
+            .map(|(fh, _)| *fh)
+            .collect();
+        idle.into_iter()
+            .filter_map(|fh| handles.remove(&fh))
+            .inspect(|handle| self.charge_read_ahead(0, handle.read_ahead_len()))
+            .collect()
+    }
+
+    /// Returns a copy of every open handle.
+    pub fn all(&self) -> Vec<FileHandle> {
+        let handles = self.handles.lock().unwrap();
+        handles.values().cloned().collect()
     }
 
     /// Get the inode associated with a handle.
@@ -138,7 +410,9 @@ impl FileHandleManager {
 
         let count = handles_to_remove.len();
         for fh in handles_to_remove {
-            handles.remove(&fh);
+            if let Some(handle) = handles.remove(&fh) {
+                self.charge_read_ahead(0, handle.read_ahead_len());
+            }
         }
 
         count
@@ -210,6 +484,121 @@ mod tests {
         assert!(handles_for_200.contains(&fh3));
     }
 
+    #[test]
+    fn test_read_ahead() {
+        let manager = create_manager();
+        let fh = manager.allocate(100, 1, libc::O_RDONLY);
+        assert!(manager.take_read_ahead(fh, 0, 4).is_none());
+
+        manager.set_read_ahead(fh, 100, Bytes::from_static(b"abcdefghij"));
+        assert!(manager.take_read_ahead(fh, 96, 4).is_none());
+        assert!(manager.take_read_ahead(fh, 108, 4).is_none());
+        assert_eq!(manager.take_read_ahead(fh, 100, 4).unwrap(), &b"abcd"[..]);
+        assert_eq!(manager.take_read_ahead(fh, 106, 2).unwrap(), &b"gh"[..]);
+        // Data before the last read is gone
+        assert!(manager.take_read_ahead(fh, 104, 2).is_none());
+        assert_eq!(manager.take_read_ahead(fh, 108, 2).unwrap(), &b"ij"[..]);
+        assert!(manager.get(fh).unwrap().read_ahead.is_none());
+    }
+
+    #[test]
+    fn test_read_ahead_checksums() {
+        let manager = FileHandleManager::new().with_checksums(1);
+        let fh = manager.allocate(100, 1, libc::O_RDONLY);
+        let data = Bytes::from(vec![7u8; 3 * CHECKSUM_BLOCK]);
+        manager.set_read_ahead(fh, 1000, data);
+        assert_eq!(
+            manager.take_read_ahead(fh, 1000, 10).unwrap(),
+            &[7u8; 10][..]
+        );
+
+        // The second block changes while held
+        let mut corrupted = vec![7u8; 3 * CHECKSUM_BLOCK];
+        corrupted[CHECKSUM_BLOCK + 5] = 0;
+        {
+            let mut handles = manager.handles.lock().unwrap();
+            let checksums = handles.get_mut(&fh).unwrap().read_ahead_checksums.as_mut();
+            checksums.unwrap().data = Bytes::from(corrupted);
+        }
+        // Reads within the first block still match
+        assert!(manager.take_read_ahead(fh, 1010, 10).is_some());
+        assert!(manager.take_read_ahead(fh, 1020, CHECKSUM_BLOCK).is_none());
+        assert_eq!(manager.checksum_failures(), 1);
+        let handle = manager.get(fh).unwrap();
+        assert!(handle.read_ahead.is_none() && handle.read_ahead_checksums.is_none());
+
+        // Without checksums nothing is kept alongside the data
+        let plain = create_manager();
+        let fh = plain.allocate(100, 1, libc::O_RDONLY);
+        plain.set_read_ahead(fh, 0, Bytes::from_static(b"abcd"));
+        assert!(plain.get(fh).unwrap().read_ahead_checksums.is_none());
+    }
+
+    #[test]
+    fn test_read_ahead_is_charged() {
+        let memory = MemoryBudget::new(0);
+        let manager = FileHandleManager::new().with_memory(Arc::clone(&memory));
+        let fh = manager.allocate(1, 1, 0);
+        let other = manager.allocate(2, 1, 0);
+
+        manager.set_read_ahead(fh, 0, Bytes::from_static(b"abcdefghij"));
+        manager.set_read_ahead(other, 0, Bytes::from_static(b"abcd"));
+        assert_eq!(memory.used(), 14);
+        manager.take_read_ahead(fh, 2, 2).unwrap();
+        assert_eq!(memory.used(), 10);
+        manager.remove(other);
+        assert_eq!(memory.used(), 6);
+        assert_eq!(manager.drop_read_ahead(), 6);
+        assert_eq!(memory.used(), 0);
+    }
+
+    #[test]
+    fn test_record_read() {
+        let manager = create_manager();
+        let fh = manager.allocate(100, 1, libc::O_RDONLY);
+        let ms = Duration::from_millis;
+        manager.record_read(fh, 0, 4096, ms(10));
+        manager.record_read(fh, 8192, 4096, ms(30));
+        manager.record_read(999, 0, 1, ms(1));
+
+        let handle = manager.get(fh).unwrap();
+        assert_eq!((handle.bytes_read, handle.position), (8192, 12288));
+        assert_eq!((handle.reads, handle.seeks), (2, 1));
+        assert_eq!(handle.average_latency(), Some(ms(20)));
+        assert_eq!(manager.all().len(), 1);
+    }
+
+    #[test]
+    fn test_access_pattern() {
+        let manager = create_manager();
+        let fh = manager.allocate(100, 1, libc::O_RDONLY);
+        assert_eq!(manager.get(fh).unwrap().access_pattern(), None);
+
+        for i in 0..8 {
+            manager.record_read(fh, i * 10, 10, Duration::ZERO);
+        }
+        manager.record_read(fh, 1000, 10, Duration::ZERO);
+        assert_eq!(
+            manager.get(fh).unwrap().access_pattern(),
+            Some(AccessPattern::Sequential)
+        );
+
+        manager.record_read(fh, 0, 10, Duration::ZERO);
+        manager.record_read(fh, 500, 10, Duration::ZERO);
+        assert_eq!(
+            manager.get(fh).unwrap().access_pattern(),
+            Some(AccessPattern::Mixed)
+        );
+
+        for i in 0..10 {
+            manager.record_read(fh, i * 1000 + 7, 10, Duration::ZERO);
+        }
+        assert_eq!(
+            manager.get(fh).unwrap().access_pattern(),
+            Some(AccessPattern::Random)
+        );
+    }
+
     #[test]
     fn test_handle_exhaustion() {
         let manager = FileHandleManager::with_max_handles(5);
@@ -232,6 +621,37 @@ mod tests {
         assert_eq!(manager.len(), 5);
     }
 
+    #[test]
+    fn test_per_uid_limit() {
+        let manager = FileHandleManager::with_max_handles(3).with_max_handles_per_uid(2);
+        assert!(manager.allocate_for(1000, 100, 1, libc::O_RDONLY) > 0);
+        let fh = manager.allocate_for(1000, 101, 1, libc::O_RDONLY);
+        assert!(fh > 0);
+        assert_eq!(manager.allocate_for(1000, 102, 1, libc::O_RDONLY), 0);
+
+        // Other users still get handles, up to the global limit
+        assert!(manager.allocate_for(1001, 102, 1, libc::O_RDONLY) > 0);
+        assert_eq!(manager.allocate_for(1002, 103, 1, libc::O_RDONLY), 0);
+
+        manager.remove(fh);
+        assert!(manager.allocate_for(1000, 102, 1, libc::O_RDONLY) > 0);
+    }
+
+    #[test]
+    fn test_evict_idle() {
+        let manager = create_manager();
+        let idle = manager.allocate(100, 1, libc::O_RDONLY);
+        let busy = manager.allocate(101, 1, libc::O_RDONLY);
+        std::thread::sleep(Duration::from_millis(20));
+        manager.record_read(busy, 0, 10, Duration::ZERO);
+
+        let evicted = manager.evict_idle(Duration::from_millis(10));
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].fh, idle);
+        assert!(manager.get(idle).is_none());
+        assert!(manager.get(busy).is_some());
+    }
+
     #[test]
     fn test_unlimited_handles() {
         let manager = FileHandleManager::with_max_handles(0);
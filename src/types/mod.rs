@@ -5,4 +5,4 @@ pub mod handle;
 
 pub use crate::fs::inode::InodeEntry;
 pub use fuser::FileAttr;
-pub use handle::FileHandle;
+pub use handle::{AccessPattern, FileHandle};
@@ -0,0 +1,220 @@
+//! Mounting inside unprivileged user namespaces.
+//!
+//! Rootless containers, `unshare -rm` and systemd user services with
+//! `PrivateUsers=` run rqbit-fuse in a user namespace. The setuid fusermount
+//! helper can't work there, but since Linux 4.18 a process holding
+//! `CAP_SYS_ADMIN` in the namespace may mount FUSE with the mount syscall, as
+//! container mode does. Owners reported to the kernel must be mapped in the
+//! namespace, so configured ids are translated.
+
+use crate::config::Config;
+use anyhow::{bail, Result};
+use tracing::{info, warn};
+
+/// One line of `/proc/<pid>/uid_map` or `gid_map`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IdRange {
+    inside: u32,
+    outside: u32,
+    count: u32,
+}
+
+/// Id mapping between a user namespace and its parent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdMap {
+    ranges: Vec<IdRange>,
+}
+
+impl IdMap {
+    /// Parses the contents of a `uid_map` or `gid_map` file.
+    pub fn parse(map: &str) -> Option<Self> {
+        let ranges = map
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut fields = line.split_whitespace().map(|f| f.parse::<u32>().ok());
+                Some(IdRange {
+                    inside: fields.next()??,
+                    outside: fields.next()??,
+                    count: fields.next()??,
+                })
+            })
+            .collect::<Option<_>>()?;
+        Some(Self { ranges })
+    }
+
+    /// Returns true for the initial namespace, which maps every id to itself.
+    pub fn is_identity(&self) -> bool {
+        self.ranges
+            == [IdRange {
+                inside: 0,
+                outside: 0,
+                count: u32::MAX,
+            }]
+    }
+
+    /// Returns true if `id` exists inside the namespace.
+    pub fn contains(&self, id: u32) -> bool {
+        self.ranges
+            .iter()
+            .any(|r| id >= r.inside && id - r.inside < r.count)
+    }
+
+    /// Returns the id inside the namespace for the parent's `id`.
+    pub fn from_outside(&self, id: u32) -> Option<u32> {
+        self.ranges
+            .iter()
+            .find(|r| id >= r.outside && id - r.outside < r.count)
+            .map(|r| r.inside + (id - r.outside))
+    }
+
+    /// Translates a configured id: ids that exist in the namespace are used
+    /// as they are, others are taken as ids of the parent namespace.
+    pub fn translate(&self, id: u32) -> Option<u32> {
+        if self.contains(id) {
+            Some(id)
+        } else {
+            self.from_outside(id)
+        }
+    }
+}
+
+/// The user namespace rqbit-fuse runs in, unless it is the initial one.
+#[derive(Debug, Clone)]
+pub struct UserNamespace {
+    pub uid_map: IdMap,
+    pub gid_map: IdMap,
+}
+
+impl UserNamespace {
+    /// Returns the current namespace, or `None` in the initial namespace or
+    /// where the maps can't be read.
+    pub fn current() -> Option<Self> {
+        let read = |name| {
+            let map = std::fs::read_to_string(format!("/proc/self/{}", name)).ok()?;
+            IdMap::parse(&map)
+        };
+        let uid_map = read("uid_map")?;
+        if uid_map.is_identity() {
+            return None;
+        }
+        Some(Self {
+            uid_map,
+            gid_map: read("gid_map")?,
+        })
+    }
+
+    /// Adjusts a mount's settings for the namespace: mounts directly when
+    /// allowed, and translates the configured owner.
+    pub fn prepare(&self, config: &mut Config, can_mount: bool) -> Result<()> {
+        if let Some(uid) = config.uid {
+            let Some(mapped) = self.uid_map.translate(uid) else {
+                bail!("uid {} is not mapped in this user namespace", uid);
+            };
+            config.uid = Some(mapped);
+        }
+        if let Some(gid) = config.gid {
+            let Some(mapped) = self.gid_map.translate(gid) else {
+                bail!("gid {} is not mapped in this user namespace", gid);
+            };
+            config.gid = Some(mapped);
+        }
+
+        if can_mount && !config.container {
+            info!(
+                "Running in a user namespace; mounting {} without fusermount",
+                config.mount_point.display()
+            );
+            config.container = true;
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if this process may mount FUSE inside its user namespace:
+/// it holds `CAP_SYS_ADMIN` there and the kernel is 4.18 or newer.
+pub fn can_mount() -> bool {
+    let has_cap = std::fs::read_to_string("/proc/self/status")
+        .is_ok_and(|status| crate::container::has_cap_sys_admin(&status));
+    if !has_cap {
+        return false;
+    }
+    let release = std::fs::read_to_string("/proc/sys/kernel/osrelease").unwrap_or_default();
+    if !kernel_supports_userns_fuse(&release) {
+        warn!(
+            "Kernel {} can't mount FUSE in a user namespace (needs 4.18); using fusermount",
+            release.trim()
+        );
+        return false;
+    }
+    true
+}
+
+/// Returns true if a kernel release string is 4.18 or newer.
+fn kernel_supports_userns_fuse(release: &str) -> bool {
+    let mut parts = release
+        .trim()
+        .split(['.', '-'])
+        .map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= (4, 18)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_map() {
+        let initial = IdMap::parse("         0          0 4294967295\n").unwrap();
+        assert!(initial.is_identity());
+
+        // `unshare -r` as uid 1000, or a rootless container with subuids
+        let map = IdMap::parse("0 1000 1\n1 100000 65536\n").unwrap();
+        assert!(!map.is_identity());
+        assert!(map.contains(0) && map.contains(65536) && !map.contains(65537));
+        assert_eq!(map.from_outside(1000), Some(0));
+        assert_eq!(map.from_outside(100999), Some(1000));
+        assert_eq!(map.from_outside(5), None);
+
+        // Ids of the namespace win over ids of the parent
+        assert_eq!(map.translate(1000), Some(1000));
+        assert_eq!(map.translate(100000), Some(1));
+        assert_eq!(map.translate(99999), None);
+
+        assert!(IdMap::parse("0 1000\n").is_none());
+        assert!(!IdMap::parse("").unwrap().contains(0));
+    }
+
+    #[test]
+    fn test_prepare() {
+        let userns = UserNamespace {
+            uid_map: IdMap::parse("0 1000 1\n").unwrap(),
+            gid_map: IdMap::parse("0 1000 1\n").unwrap(),
+        };
+        let mut config = Config {
+            uid: Some(1000),
+            ..Config::default()
+        };
+        userns.prepare(&mut config, true).unwrap();
+        assert_eq!((config.uid, config.gid), (Some(0), None));
+        assert!(config.container);
+
+        let mut config = Config {
+            gid: Some(100),
+            ..Config::default()
+        };
+        assert!(userns.prepare(&mut config, false).is_err());
+    }
+
+    #[rstest::rstest]
+    #[case("6.8.0-45-generic", true)]
+    #[case("4.18.0-553.el8_10.x86_64", true)]
+    #[case("4.15.0-213-generic", false)]
+    #[case("3.10.0", false)]
+    #[case("", false)]
+    fn test_kernel_supports_userns_fuse(#[case] release: &str, #[case] expected: bool) {
+        assert_eq!(kernel_supports_userns_fuse(release), expected);
+    }
+}
@@ -1,3 +1,5 @@
+#![allow(clippy::field_reassign_with_default)]
+
 use rqbit_fuse::config::{Config, ConfigSource};
 
 mod common;
@@ -82,7 +84,7 @@ fn test_edge_056_invalid_timeout_from_env_handling() {
         ("30.5", "decimal"),
         ("", "empty string"),
         (" ", "whitespace"),
-        ("30s", "with unit suffix"),
+        ("30x", "unknown unit suffix"),
     ];
 
     for (value, description) in test_cases {
@@ -103,6 +105,21 @@ fn test_edge_056_invalid_timeout_from_env_handling() {
     }
 }
 
+#[test]
+fn test_duration_units_from_env() {
+    let _guard = lock_env_vars();
+
+    std::env::set_var("TORRENT_FUSE_METADATA_TTL", "5m");
+    std::env::set_var("TORRENT_FUSE_READ_TIMEOUT", "30s");
+    let source = ConfigSource::from_env();
+    std::env::remove_var("TORRENT_FUSE_METADATA_TTL");
+    std::env::remove_var("TORRENT_FUSE_READ_TIMEOUT");
+
+    let merged = Config::default().merge(source.unwrap());
+    assert_eq!(merged.metadata_ttl, 300);
+    assert_eq!(merged.read_timeout, 30);
+}
+
 #[test]
 fn test_edge_057_missing_required_env_vars() {
     let _guard = lock_env_vars();
@@ -0,0 +1,68 @@
+//! NFS re-export tests.
+//!
+//! These need root, a running rqbit with at least one torrent, the kernel NFS
+//! server and a mount made with `nfs_export = true`. They are ignored by
+//! default; see doc/usage/nfs.md for the loopback setup, then run:
+//!
+//! ```bash
+//! RQBIT_FUSE_NFS_SOURCE=/mnt/torrents RQBIT_FUSE_NFS_CLIENT=/mnt/nfs \
+//!     cargo test --test nfs_tests -- --ignored
+//! ```
+
+use std::collections::BTreeMap;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+fn env_path(name: &str) -> PathBuf {
+    PathBuf::from(std::env::var_os(name).unwrap_or_else(|| panic!("{} is not set", name)))
+}
+
+/// Maps each path below `root` to (inode, size).
+fn tree(root: &Path) -> BTreeMap<PathBuf, (u64, u64)> {
+    let mut entries = BTreeMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let entry = entry.unwrap();
+            let meta = entry.metadata().unwrap();
+            if meta.is_dir() {
+                pending.push(entry.path());
+            }
+            let relative = entry.path().strip_prefix(root).unwrap().to_path_buf();
+            entries.insert(relative, (meta.ino(), meta.len()));
+        }
+    }
+    entries
+}
+
+#[test]
+#[ignore = "needs a loopback NFS export of a running mount"]
+fn test_nfs_export_matches_fuse_mount() {
+    let source = env_path("RQBIT_FUSE_NFS_SOURCE");
+    let client = env_path("RQBIT_FUSE_NFS_CLIENT");
+
+    let fuse_tree = tree(&source);
+    assert!(!fuse_tree.is_empty(), "the mount has no torrents");
+    // NFS reports the FUSE inode numbers, so handles map back to the same files
+    assert_eq!(tree(&client), fuse_tree);
+}
+
+#[test]
+#[ignore = "needs a loopback NFS export of a running mount"]
+fn test_nfs_reads_survive_dropped_caches() {
+    let source = env_path("RQBIT_FUSE_NFS_SOURCE");
+    let client = env_path("RQBIT_FUSE_NFS_CLIENT");
+
+    let (path, _) = tree(&source)
+        .into_iter()
+        .find(|(_, (_, size))| *size > 0)
+        .expect("the mount has no non-empty files");
+    let file = std::fs::File::open(client.join(&path)).unwrap();
+
+    // Forces nfsd to decode its file handle through the FUSE export ops
+    std::fs::write("/proc/sys/vm/drop_caches", "2").unwrap();
+
+    let mut buf = [0u8; 4096];
+    let read = std::os::unix::fs::FileExt::read_at(&file, &mut buf, 0).unwrap();
+    assert!(read > 0);
+}
@@ -1,5 +1,7 @@
 //! Resource limit edge case tests
 
+#![allow(clippy::field_reassign_with_default)]
+
 use std::sync::Arc;
 use std::time::Duration;
 use tempfile::TempDir;